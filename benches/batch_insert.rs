@@ -0,0 +1,54 @@
+//! Compares the batch modes of `StorageManager::store_entries_with` on
+//! synthetic corpora. Run with `cargo bench --bench batch_insert`.
+
+use chrono::{Duration, NaiveDate};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use rusty_diary::storage::{BatchMode, DiaryEntry, StorageManager};
+use rusty_diary::NoProgress;
+use tempfile::TempDir;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+/// One entry per day, a few versions per day, with a link now and then
+fn corpus(size: usize) -> Vec<DiaryEntry> {
+    let start = NaiveDate::from_ymd_opt(1900, 1, 1).unwrap();
+    (0..size)
+        .map(|i| {
+            let date = start + Duration::days((i / 3) as i64);
+            let mut content = format!("Entry {} written on {}. ", i, date).repeat(8);
+            if i % 10 == 0 {
+                content.push_str(&format!("See [[{}]].", start));
+            }
+            DiaryEntry::new((i % 3) as i64 + 1, date, content, date.and_hms_opt(21, 0, 0).unwrap())
+        })
+        .collect()
+}
+
+fn batch_insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch_insert");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let entries = corpus(size);
+        group.throughput(Throughput::Elements(size as u64));
+
+        for mode in [BatchMode::Replace, BatchMode::Upsert, BatchMode::DeferredMetadata] {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", mode), size), &entries, |b, entries| {
+                b.iter_batched(
+                    || {
+                        let dir = TempDir::new().unwrap();
+                        let storage = StorageManager::new(dir.path().join("bench.db")).unwrap();
+                        (dir, storage, entries.clone())
+                    },
+                    |(_dir, storage, entries)| storage.store_entries_with(entries, mode, &NoProgress).unwrap(),
+                    BatchSize::PerIteration,
+                );
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, batch_insert);
+criterion_main!(benches);
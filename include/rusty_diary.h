@@ -0,0 +1,60 @@
+/*
+ * C bindings to rusty_diary, built with `--features ffi`; see src/ffi.rs.
+ *
+ * Every function returning int returns 0 on success or one of the error
+ * codes below. Strings are NUL-terminated UTF-8, dates YYYY-MM-DD, results
+ * JSON. Strings handed out must be released with rusty_diary_free_string.
+ */
+
+#ifndef RUSTY_DIARY_H
+#define RUSTY_DIARY_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The error codes the bindings return; the same as ErrorKind::code() */
+#define RUSTY_DIARY_OK 0
+#define RUSTY_DIARY_E_IO 1
+#define RUSTY_DIARY_E_DATABASE 2
+#define RUSTY_DIARY_E_DATE_PARSE 3
+#define RUSTY_DIARY_E_CONTENT_INTEGRITY 8
+#define RUSTY_DIARY_E_SERIALIZATION 9
+#define RUSTY_DIARY_E_SCHEMA_TOO_NEW 10
+#define RUSTY_DIARY_E_INCOMPATIBLE_SCHEMA 11
+#define RUSTY_DIARY_E_INVALID_CONFIG 13
+/* A bug inside the library; the handle should not be used again */
+#define RUSTY_DIARY_E_PANIC (-1)
+
+/* An open diary database */
+typedef struct DiaryHandle DiaryHandle;
+
+/* Opens, creating and migrating as needed, the database at db_path */
+int32_t rusty_diary_open(const char *db_path, DiaryHandle **out);
+
+/* Closes a handle; NULL is ignored */
+void rusty_diary_close(DiaryHandle *handle);
+
+/* Stores content as the entry for date. exec_version, if not NULL, receives
+ * the version it was stored under, or 0 when the day already had this text. */
+int32_t rusty_diary_capture(DiaryHandle *handle, const char *date, const char *content, int64_t *exec_version);
+
+/* The latest version of each day from start to end, newest first, as a JSON array */
+int32_t rusty_diary_query(DiaryHandle *handle, const char *start, const char *end, char **json);
+
+/* Statistics over every stored entry, as a JSON object */
+int32_t rusty_diary_stats(DiaryHandle *handle, char **json);
+
+/* The last failure on the calling thread, or NULL */
+char *rusty_diary_last_error(void);
+
+/* Releases a string returned by these functions; NULL is ignored */
+void rusty_diary_free_string(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RUSTY_DIARY_H */
@@ -0,0 +1,1224 @@
+//! Snapshot backups. `create` packs the database with its year shards and
+//! cold archives, the attachments folder and a fingerprint of the config
+//! into a single zstd-compressed `.rdbk` file, encrypted for age recipients
+//! or a passphrase by the `age` command line tool. `verify` unpacks a
+//! backup into a scratch folder and checks its hashes, the integrity of its
+//! databases and their row counts; `restore` does the same before moving
+//! the files into place.
+//!
+//! Every backup taken is recorded in the database. `create_incremental`
+//! builds on the latest one, carrying only the entry versions stored by
+//! later runs and the attachments whose hash changed, so a full backup and
+//! the incrementals after it form a chain. Chains are verified and
+//! restored as a whole, and `compact` folds one into a fresh full backup.
+//!
+//! `restore_as_of` needs no backup: it rebuilds the diary as it stood on a
+//! past day from the entry versions the live database keeps, into a
+//! separate folder.
+//!
+//! Inside the compression a backup is its manifest, as JSON prefixed with
+//! its length, followed by the contents of each file it lists, in order.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use rusqlite::{params, Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{info, warn};
+use walkdir::WalkDir;
+
+use crate::config::Config;
+use crate::diary::photos::ATTACHMENTS_DIR;
+use crate::diary::portable::slash_path;
+use crate::diary::{JournalReport, RustyDiary};
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::hooks::{self, Hooks};
+use crate::storage::models::content_hash;
+use crate::storage::{migrations, shard, BackupRecord, StorageManager};
+
+/// Extension of backup files
+pub const EXTENSION: &str = "rdbk";
+
+/// Start of an unencrypted backup, ahead of the compressed stream
+const MAGIC: &[u8] = b"RDBK\n";
+
+/// Starts of age's binary and armored output
+const AGE_HEADERS: [&[u8]; 2] = [b"age-encryption.org/", b"-----BEGIN AGE ENCRYPTED FILE-----"];
+
+/// Layout version written; backups in a newer layout are refused
+const FORMAT: u32 = 1;
+
+/// zstd level for backups; they are written rarely and kept long
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// Manifests larger than this are taken for corruption
+const MAX_MANIFEST_BYTES: u64 = 64 << 20;
+
+/// What a file in a backup is, which decides where it is restored to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileKind {
+    /// The main database, restored to the configured database path
+    Database,
+    /// A year shard, renamed after the database it is restored next to
+    Shard,
+    /// A cold archive, restored next to the database
+    Archive,
+    /// A file from the attachments folder
+    Attachment,
+    /// The entry versions an incremental backup carries, as JSON lines
+    Entries,
+}
+
+impl FileKind {
+    /// Folder the file is unpacked under in a scratch folder
+    fn folder(self) -> &'static str {
+        match self {
+            FileKind::Attachment => ATTACHMENTS_DIR,
+            _ => "db",
+        }
+    }
+}
+
+/// A file held in a backup
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupFile {
+    pub kind: FileKind,
+    /// Name next to the database, or `/`-separated path inside the
+    /// attachments folder
+    pub path: String,
+    pub size: u64,
+    /// Hex SHA-256 of the contents
+    pub sha256: String,
+    /// Year a shard holds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+}
+
+/// What a backup holds, stored at its start
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub format: u32,
+    /// Unique id, which the incremental backup after this one names
+    #[serde(default)]
+    pub id: String,
+    /// The backup an incremental one builds on; unset for a full backup
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    /// Latest run whose entries the backup, with its chain, covers
+    #[serde(default)]
+    pub exec_version: i64,
+    pub crate_version: String,
+    pub created_at: NaiveDateTime,
+    pub schema_version: i32,
+    /// `Config::fingerprint` of the config the backup was taken with
+    pub config_fingerprint: String,
+    /// Rows in each table of the main database; only `diary_entries` for
+    /// an incremental backup
+    pub tables: BTreeMap<String, i64>,
+    pub files: Vec<BackupFile>,
+    /// Attachments deleted since the parent backup
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+impl BackupManifest {
+    pub fn is_full(&self) -> bool {
+        self.parent.is_none()
+    }
+
+    /// Total size of the files, uncompressed
+    pub fn size(&self) -> u64 {
+        self.files.iter().map(|file| file.size).sum()
+    }
+}
+
+/// The outcome of checking a backup chain
+#[derive(Debug, Clone, Serialize)]
+pub struct Verification {
+    /// The manifest of the last backup in the chain
+    pub manifest: BackupManifest,
+    /// Backups in the chain, the full one included
+    pub backups: usize,
+    /// Whether the backup was taken with the current config's fingerprint
+    pub config_matches: bool,
+    /// Files, databases and tables that did not check out
+    pub problems: Vec<String>,
+}
+
+impl Verification {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// What `restore_as_of` rebuilt
+#[derive(Debug, Clone, Serialize)]
+pub struct PointInTime {
+    pub as_of: NaiveDate,
+    /// The last sync run started by then; later versions are left out
+    pub exec_version: i64,
+    /// Entry versions stored, every revision up to `exec_version`
+    pub entries: usize,
+    pub days: usize,
+    pub db_path: PathBuf,
+    pub journal: JournalReport,
+}
+
+/// Encryption by the `age` command line tool: the recipients or
+/// passphrase backups are encrypted for, and the identities they are
+/// decrypted with. Without recipients or a passphrase backups are written
+/// in the clear; encrypted ones are recognized when read.
+#[derive(Debug, Clone)]
+pub struct Age {
+    command: String,
+    recipients: Vec<String>,
+    recipients_files: Vec<PathBuf>,
+    passphrase: bool,
+    identities: Vec<PathBuf>,
+}
+
+impl Default for Age {
+    fn default() -> Self {
+        Self {
+            command: "age".to_string(),
+            recipients: Vec::new(),
+            recipients_files: Vec::new(),
+            passphrase: false,
+            identities: Vec::new(),
+        }
+    }
+}
+
+impl Age {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Shell command run in place of `age`, given age's arguments
+    pub fn with_command(mut self, command: &str) -> Self {
+        self.command = command.to_string();
+        self
+    }
+
+    /// Encrypts for a public key such as `age1...` or an SSH key
+    pub fn with_recipient(mut self, recipient: &str) -> Self {
+        self.recipients.push(recipient.to_string());
+        self
+    }
+
+    /// Encrypts for the public keys listed in a file, one per line
+    pub fn with_recipients_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.recipients_files.push(path.into());
+        self
+    }
+
+    /// Encrypts with a passphrase, which age prompts for on the terminal
+    pub fn with_passphrase(mut self, enabled: bool) -> Self {
+        self.passphrase = enabled;
+        self
+    }
+
+    /// Decrypts with the private keys in a file; without one, age prompts
+    /// for the passphrase of passphrase-encrypted backups
+    pub fn with_identity<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.identities.push(path.into());
+        self
+    }
+
+    /// Whether backups get encrypted
+    pub fn encrypts(&self) -> bool {
+        self.passphrase || !self.recipients.is_empty() || !self.recipients_files.is_empty()
+    }
+
+    fn encrypt(&self, input: &Path, output: &Path) -> Result<()> {
+        let mut args: Vec<&OsStr> = Vec::new();
+        for recipient in &self.recipients {
+            args.extend([OsStr::new("-r"), OsStr::new(recipient)]);
+        }
+        for file in &self.recipients_files {
+            args.extend([OsStr::new("-R"), file.as_os_str()]);
+        }
+        if self.passphrase {
+            args.push(OsStr::new("-p"));
+        }
+        args.extend([OsStr::new("-o"), output.as_os_str(), input.as_os_str()]);
+        self.run(&args)
+    }
+
+    fn decrypt(&self, input: &Path, output: &Path) -> Result<()> {
+        let mut args = vec![OsStr::new("-d")];
+        for identity in &self.identities {
+            args.extend([OsStr::new("-i"), identity.as_os_str()]);
+        }
+        args.extend([OsStr::new("-o"), output.as_os_str(), input.as_os_str()]);
+        self.run(&args)
+    }
+
+    fn run(&self, args: &[&OsStr]) -> Result<()> {
+        // The arguments reach the command as "$@", so paths need no quoting
+        let mut process = if cfg!(windows) {
+            hooks::shell(&self.command)
+        } else {
+            let mut process = hooks::shell(&format!("{} \"$@\"", self.command));
+            process.arg(&self.command);
+            process
+        };
+        let output = process
+            .args(args)
+            .output()
+            .map_err(|e| RustyDiaryError::Encryption(format!("Cannot run `{}`: {}", self.command, e)))?;
+
+        if !output.status.success() {
+            return Err(RustyDiaryError::Encryption(format!(
+                "`{}` {}: {}",
+                self.command,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// What goes into a backup before it is written
+struct Contents {
+    manifest: BackupManifest,
+    /// Where each file of the manifest is read from
+    sources: Vec<PathBuf>,
+    /// Every attachment as of the backup, by path, with its hash
+    attachments: BTreeMap<String, String>,
+}
+
+/// Writes a full backup of the configured database and attachments to
+/// `destination`, encrypted as `age` says, and records it. The databases
+/// are copied with `VACUUM INTO`, so a sync running meanwhile does not
+/// tear the copy.
+pub fn create(config: &Config, destination: &Path, age: &Age) -> Result<BackupManifest> {
+    let storage = open(config)?;
+    let scratch = Scratch::new(&parent_dir(destination), "backup")?;
+    let contents = snapshot(config, scratch.path())?;
+    pack(&contents, scratch.path(), destination, age)?;
+    record(&storage, &contents, destination)?;
+    Ok(contents.manifest)
+}
+
+/// Writes an incremental backup building on the latest recorded one: the
+/// entry versions stored since, and the attachments added or changed,
+/// told apart by their hashes. Fails when there is no backup to build on,
+/// or when entries were sharded, archived or pruned since, which only a
+/// full backup captures.
+pub fn create_incremental(config: &Config, destination: &Path, age: &Age) -> Result<BackupManifest> {
+    let storage = open(config)?;
+    let base = storage.backups()?.into_iter().next().ok_or_else(|| {
+        RustyDiaryError::InvalidConfig("No backup recorded to build on; create a full backup first".to_string())
+    })?;
+    if storage.count_entries_through(base.exec_version)? != base.entries {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "Entries were sharded, archived or pruned since backup {}; create a full backup",
+            base.id
+        )));
+    }
+    let scratch = Scratch::new(&parent_dir(destination), "backup")?;
+
+    // A run still storing its entries is left for the next backup
+    let entries = storage.entries_between_runs(base.exec_version, i64::MAX)?;
+    let exec_version = entries.iter().map(|entry| entry.exec_version).max().unwrap_or(base.exec_version);
+    let entries_path = scratch.path().join("entries.jsonl");
+    {
+        let mut out = BufWriter::new(File::create(&entries_path).at_path(&entries_path)?);
+        for entry in &entries {
+            serde_json::to_writer(&mut out, entry)?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()?;
+    }
+    let mut contents = Contents::new(manifest(config, Some(base.id.clone()), exec_version));
+    contents.add(FileKind::Entries, "entries.jsonl".to_string(), None, entries_path)?;
+
+    let previous = storage.backup_attachments(&base.id)?;
+    for (path, source) in attachment_files(&config.directory.join(ATTACHMENTS_DIR))? {
+        let (size, sha256) = hash_file(&source)?;
+        if previous.get(&path) != Some(&sha256) {
+            let file = BackupFile { kind: FileKind::Attachment, path: path.clone(), size, sha256: sha256.clone(), year: None };
+            contents.manifest.files.push(file);
+            contents.sources.push(source);
+        }
+        contents.attachments.insert(path, sha256);
+    }
+    contents.manifest.removed = previous.into_keys().filter(|path| !contents.attachments.contains_key(path)).collect();
+    contents.manifest.tables.insert("diary_entries".to_string(), storage.count_entries_through(exec_version)?);
+
+    pack(&contents, scratch.path(), destination, age)?;
+    record(&storage, &contents, destination)?;
+    Ok(contents.manifest)
+}
+
+/// Restores a chain, a full backup followed by the incrementals built on
+/// it in order, into a scratch folder and checks it, leaving the live
+/// diary untouched
+pub fn verify(config: &Config, backups: &[PathBuf], age: &Age) -> Result<Verification> {
+    let scratch = Scratch::new(&std::env::temp_dir(), "verify")?;
+    let (manifests, problems) = assemble(backups, age, scratch.path())?;
+    Ok(verification(config, manifests, problems))
+}
+
+/// Restores a chain to the configured database path and attachments
+/// folder. An existing database is only replaced with `force`, and
+/// nothing is touched unless the whole chain checks out.
+pub fn restore(config: &Config, backups: &[PathBuf], age: &Age, force: bool) -> Result<Verification> {
+    if config.db_path.exists() && !force {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "{} already exists; restore with --force to replace it",
+            config.db_path.display()
+        )));
+    }
+    let db_dir = parent_dir(&config.db_path);
+    fs::create_dir_all(&db_dir).at_path(&db_dir)?;
+
+    // Unpacking next to the database lets its files be renamed into place
+    let scratch = Scratch::new(&db_dir, "restore")?;
+    let (manifests, problems) = assemble(backups, age, scratch.path())?;
+    if !problems.is_empty() {
+        return Err(RustyDiaryError::ContentIntegrity(format!(
+            "Not restoring a damaged backup: {}",
+            problems.join("; ")
+        )));
+    }
+
+    let attachments = config.directory.join(ATTACHMENTS_DIR);
+    for (path, source) in attachment_files(&scratch.path().join(ATTACHMENTS_DIR))? {
+        let target = attachments.join(&path);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        fs::copy(&source, &target).at_path(&target)?;
+    }
+
+    // The main database goes last, so a failure midway leaves it as it was
+    let (database, others): (Vec<_>, Vec<_>) = manifests[0]
+        .files
+        .iter()
+        .filter(|file| file.kind != FileKind::Attachment)
+        .partition(|file| file.kind == FileKind::Database);
+    for file in others.into_iter().chain(database) {
+        let source = scratch.path().join(file.kind.folder()).join(&file.path);
+        let target = match file.kind {
+            FileKind::Database => config.db_path.clone(),
+            FileKind::Shard => shard::path(&config.db_path, file.year.unwrap_or_default()),
+            _ => db_dir.join(&file.path),
+        };
+        if file.kind != FileKind::Database {
+            fs::rename(&source, &target).at_path(&target)?;
+            continue;
+        }
+
+        // A write-ahead log left by the old database would be replayed into
+        // the restored one. It is set aside right before the swap, and put
+        // back if the database cannot be replaced.
+        let aside = set_aside_wal(&target, scratch.path())?;
+        if let Err(e) = fs::rename(&source, &target) {
+            put_back(&aside);
+            return Err(e).at_path(&target);
+        }
+    }
+
+    let verification = verification(config, manifests, problems);
+    info!(backups = verification.backups, "Restored backup taken {}", verification.manifest.created_at);
+    Ok(verification)
+}
+
+/// Moves the write-ahead log and shared memory of the database at
+/// `db_path` into `folder`, returning where each went
+fn set_aside_wal(db_path: &Path, folder: &Path) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut aside = Vec::new();
+    for suffix in ["-wal", "-shm"] {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(suffix);
+        let path = PathBuf::from(path);
+        if !path.exists() {
+            continue;
+        }
+        let moved = folder.join(path.file_name().unwrap_or_default());
+        if let Err(e) = fs::rename(&path, &moved) {
+            put_back(&aside);
+            return Err(e).at_path(&path);
+        }
+        aside.push((path, moved));
+    }
+    Ok(aside)
+}
+
+/// Returns files moved by `set_aside_wal` to where they were
+fn put_back(aside: &[(PathBuf, PathBuf)]) {
+    for (path, moved) in aside {
+        if let Err(e) = fs::rename(moved, path) {
+            warn!("Could not put {} back: {}", path.display(), e);
+        }
+    }
+}
+
+/// Folds a chain into a single full backup written to `destination`,
+/// which later incremental backups build on
+pub fn compact(config: &Config, backups: &[PathBuf], destination: &Path, age: &Age) -> Result<BackupManifest> {
+    let storage = open(config)?;
+    let unpacked = Scratch::new(&std::env::temp_dir(), "compact")?;
+    let (manifests, problems) = assemble(backups, age, unpacked.path())?;
+    if !problems.is_empty() {
+        return Err(RustyDiaryError::ContentIntegrity(format!(
+            "Not compacting a damaged backup: {}",
+            problems.join("; ")
+        )));
+    }
+
+    let chain = Config {
+        directory: unpacked.path().to_path_buf(),
+        db_path: database_path(unpacked.path(), &manifests[0]),
+        ..config.clone()
+    };
+    let scratch = Scratch::new(&parent_dir(destination), "backup")?;
+    let mut contents = snapshot(&chain, scratch.path())?;
+    contents.manifest.config_fingerprint = manifests[manifests.len() - 1].config_fingerprint.clone();
+    pack(&contents, scratch.path(), destination, age)?;
+    record(&storage, &contents, destination)?;
+    Ok(contents.manifest)
+}
+
+/// The recorded backups, newest first
+pub fn history(config: &Config) -> Result<Vec<BackupRecord>> {
+    StorageManager::open_read_only_with_settings(&config.db_path, &config.sqlite)?.backups()
+}
+
+/// Rebuilds the diary as it stood at the end of `as_of` into a new
+/// database in `into`, and writes its journal there with the configured
+/// layout. The versions kept are those stored by the sync runs started by
+/// then, shards and cold archives included. Stars, metadata and tags keep
+/// no history and are not carried over. The live diary is only read.
+pub fn restore_as_of(config: &Config, as_of: NaiveDate, into: &Path) -> Result<PointInTime> {
+    if !config.db_path.is_file() {
+        return Err(RustyDiaryError::InvalidConfig(format!("No database at {}", config.db_path.display())));
+    }
+    let live = StorageManager::open_read_only_with_settings(&config.db_path, &config.sqlite)?;
+    let Some(exec_version) = live
+        .sync_runs()?
+        .iter()
+        .filter(|run| run.started_at.date() <= as_of)
+        .map(|run| run.id)
+        .max()
+    else {
+        return Err(RustyDiaryError::InvalidConfig(format!("No sync run had started by {}", as_of)));
+    };
+
+    let mut entries = live.entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+    entries.extend(live.archived_entries()?);
+    entries.retain(|entry| entry.exec_version <= exec_version);
+    entries.sort_by_key(|entry| (entry.exec_version, entry.date));
+    let (Some(start_date), Some(end_date)) =
+        (entries.iter().map(|entry| entry.date).min(), entries.iter().map(|entry| entry.date).max())
+    else {
+        return Err(RustyDiaryError::InvalidConfig(format!("No entries were stored by {}", as_of)));
+    };
+
+    fs::create_dir_all(into).at_path(into)?;
+    let into = into.canonicalize().at_path(into)?;
+    if config.directory.canonicalize().is_ok_and(|directory| directory == into) {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "{} is the diary directory; restore into another folder",
+            into.display()
+        )));
+    }
+    let db_path = into.join(file_name(&config.db_path));
+    if db_path.exists() {
+        return Err(RustyDiaryError::InvalidConfig(format!("{} already exists", db_path.display())));
+    }
+
+    // The rebuilt diary runs no hooks, so nothing hears of its journal as if it were the live one
+    let target = Config { directory: into, db_path: db_path.clone(), read_only: false, hooks: Hooks::default(), ..config.clone() };
+    let diary = RustyDiary::new(target)?;
+    let count = entries.len();
+    let days = entries.iter().map(|entry| entry.date).collect::<BTreeSet<_>>().len();
+    diary.storage().store_entries(entries)?;
+    let journal = diary.write_journal(start_date, end_date)?;
+
+    info!(exec_version, entries = count, "Restored the diary as of {}", as_of);
+    Ok(PointInTime { as_of, exec_version, entries: count, days, db_path, journal })
+}
+
+impl Contents {
+    fn new(manifest: BackupManifest) -> Self {
+        Self { manifest, sources: Vec::new(), attachments: BTreeMap::new() }
+    }
+
+    /// Adds a file, hashing it as it is now
+    fn add(&mut self, kind: FileKind, path: String, year: Option<i32>, source: PathBuf) -> Result<()> {
+        let (size, sha256) = hash_file(&source)?;
+        if kind == FileKind::Attachment {
+            self.attachments.insert(path.clone(), sha256.clone());
+        }
+        self.manifest.files.push(BackupFile { kind, path, size, sha256, year });
+        self.sources.push(source);
+        Ok(())
+    }
+}
+
+/// A manifest with no files yet
+fn manifest(config: &Config, parent: Option<String>, exec_version: i64) -> BackupManifest {
+    let created_at = config.clock.now();
+    BackupManifest {
+        format: FORMAT,
+        id: content_hash(&format!("{} {} {}", created_at, std::process::id(), config.db_path.display()))[..16].to_string(),
+        parent,
+        exec_version,
+        crate_version: crate::VERSION.to_string(),
+        created_at,
+        schema_version: migrations::latest_version(),
+        config_fingerprint: config.fingerprint(),
+        tables: BTreeMap::new(),
+        files: Vec::new(),
+        removed: Vec::new(),
+    }
+}
+
+/// Opens the database, which brings its schema up to date, and fails
+/// early when there is none
+fn open(config: &Config) -> Result<StorageManager> {
+    if !config.db_path.is_file() {
+        return Err(RustyDiaryError::InvalidConfig(format!("No database at {}", config.db_path.display())));
+    }
+    StorageManager::with_settings(&config.db_path, &config.sqlite, config.clock.clone())
+}
+
+/// Everything a full backup holds, with the databases copied into `scratch`
+fn snapshot(config: &Config, scratch: &Path) -> Result<Contents> {
+    let db_dir = parent_dir(&config.db_path);
+    let db_name = file_name(&config.db_path);
+    let copy = scratch.join(&db_name);
+    Connection::open_with_flags(&config.db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        .execute("VACUUM INTO ?1", params![copy.to_string_lossy()])?;
+
+    let conn = Connection::open_with_flags(&copy, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let exec_version = conn.query_row("SELECT COALESCE(MAX(exec_version), 0) FROM diary_entries", [], |row| row.get(0))?;
+    let mut contents = Contents::new(manifest(config, None, exec_version));
+    contents.manifest.schema_version = migrations::inspect(&conn)?.version;
+    contents.manifest.tables = row_counts(&conn)?;
+    contents.add(FileKind::Database, db_name, None, copy)?;
+
+    let mut stmt = conn.prepare("SELECT year FROM shards ORDER BY year")?;
+    let years = stmt.query_map([], |row| row.get::<_, i32>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    for year in years {
+        let path = shard::path(&config.db_path, year);
+        if path.is_file() {
+            let name = file_name(&path);
+            let copy = scratch.join(&name);
+            Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+                .execute("VACUUM INTO ?1", params![copy.to_string_lossy()])?;
+            contents.add(FileKind::Shard, name, Some(year), copy)?;
+        }
+    }
+
+    // Cold archives are never changed once written, so they are read in place
+    let mut stmt = conn.prepare("SELECT file_name FROM cold_archives ORDER BY id")?;
+    let names = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+    for name in names {
+        let path = db_dir.join(&name);
+        contents.add(FileKind::Archive, name, None, path)?;
+    }
+
+    for (path, source) in attachment_files(&config.directory.join(ATTACHMENTS_DIR))? {
+        contents.add(FileKind::Attachment, path, None, source)?;
+    }
+    Ok(contents)
+}
+
+/// Writes the backup and moves it to `destination`, encrypting it first
+/// if `age` has recipients
+fn pack(contents: &Contents, scratch: &Path, destination: &Path, age: &Age) -> Result<()> {
+    let plain = scratch.join("backup");
+    write_archive(&plain, &contents.manifest, &contents.sources)?;
+    let written = if age.encrypts() {
+        let encrypted = scratch.join("backup.age");
+        age.encrypt(&plain, &encrypted)?;
+        encrypted
+    } else {
+        plain
+    };
+    fs::rename(&written, destination).at_path(destination)?;
+
+    let manifest = &contents.manifest;
+    info!(files = manifest.files.len(), bytes = manifest.size(), "Wrote backup {} to {}", manifest.id, destination.display());
+    Ok(())
+}
+
+fn record(storage: &StorageManager, contents: &Contents, destination: &Path) -> Result<()> {
+    let manifest = &contents.manifest;
+    let backup = BackupRecord {
+        id: manifest.id.clone(),
+        parent: manifest.parent.clone(),
+        exec_version: manifest.exec_version,
+        entries: manifest.tables.get("diary_entries").copied().unwrap_or_default(),
+        created_at: manifest.created_at,
+        path: std::path::absolute(destination).unwrap_or_else(|_| destination.to_path_buf()).display().to_string(),
+    };
+    storage.record_backup(&backup, &contents.attachments)
+}
+
+/// Unpacks a chain into `folder` and checks it, applying each incremental
+/// backup to what the ones before it restored. Returns the manifests, and
+/// the problems found.
+fn assemble(backups: &[PathBuf], age: &Age, folder: &Path) -> Result<(Vec<BackupManifest>, Vec<String>)> {
+    let (first, rest) = backups
+        .split_first()
+        .ok_or_else(|| RustyDiaryError::InvalidConfig("No backup to restore".to_string()))?;
+    let (manifest, mut problems) = unpack(first, age, folder)?;
+    if !manifest.is_full() {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "{} is an incremental backup; start with the full backup its chain builds on",
+            first.display()
+        )));
+    }
+    check(folder, &manifest, &mut problems);
+    let db_path = database_path(folder, &manifest);
+
+    let mut manifests = vec![manifest];
+    for backup in rest {
+        let step = Scratch::new(folder, "step")?;
+        let (manifest, found) = unpack(backup, age, step.path())?;
+        let previous = &manifests[manifests.len() - 1];
+        if manifest.parent.as_deref() != Some(previous.id.as_str()) {
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "{} does not build on backup {}; give the chain in the order it was taken",
+                backup.display(),
+                previous.id
+            )));
+        }
+        problems.extend(found);
+        if problems.is_empty() {
+            apply(folder, &db_path, step.path(), &manifest, &mut problems)?;
+        }
+        manifests.push(manifest);
+    }
+    Ok((manifests, problems))
+}
+
+/// Stores an unpacked incremental backup's entries in the restored
+/// database and brings the restored attachments up to date
+fn apply(folder: &Path, db_path: &Path, step: &Path, manifest: &BackupManifest, problems: &mut Vec<String>) -> Result<()> {
+    let attachments = folder.join(ATTACHMENTS_DIR);
+    for file in &manifest.files {
+        let source = step.join(file.kind.folder()).join(&file.path);
+        if file.kind == FileKind::Entries {
+            let mut entries = Vec::new();
+            for line in BufReader::new(File::open(&source).at_path(&source)?).lines() {
+                let line = line.at_path(&source)?;
+                if !line.trim().is_empty() {
+                    entries.push(serde_json::from_str(&line)?);
+                }
+            }
+            StorageManager::new(db_path)?.store_entries(entries)?;
+            // Fold the write-ahead log back in, since only the database file is moved into place
+            Connection::open(db_path)?.execute_batch("PRAGMA wal_checkpoint(TRUNCATE); PRAGMA journal_mode = DELETE;")?;
+        } else {
+            let target = attachments.join(&file.path);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).at_path(parent)?;
+            }
+            fs::rename(&source, &target).at_path(&target)?;
+        }
+    }
+    for path in &manifest.removed {
+        let target = attachments.join(relative_path(path)?);
+        if target.exists() {
+            fs::remove_file(&target).at_path(&target)?;
+        }
+    }
+
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    compare_counts(&conn, &manifest.tables, problems)
+}
+
+fn verification(config: &Config, mut manifests: Vec<BackupManifest>, problems: Vec<String>) -> Verification {
+    let backups = manifests.len();
+    let manifest = manifests.pop().expect("a chain holds at least its full backup");
+    Verification { config_matches: manifest.config_fingerprint == config.fingerprint(), manifest, backups, problems }
+}
+
+/// Where a full backup's main database is unpacked in `folder`
+fn database_path(folder: &Path, manifest: &BackupManifest) -> PathBuf {
+    let name = manifest.files.iter().find(|file| file.kind == FileKind::Database).map_or("", |file| &file.path);
+    folder.join(FileKind::Database.folder()).join(name)
+}
+
+/// The files of an attachments folder, by `/`-separated path inside it
+fn attachment_files(attachments: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    if attachments.is_dir() {
+        for entry in WalkDir::new(attachments).sort_by_file_name() {
+            let entry = entry.map_err(io::Error::from).at_path(attachments)?;
+            if entry.file_type().is_file() {
+                let relative = entry.path().strip_prefix(attachments).unwrap_or(entry.path());
+                files.push((slash_path(relative), entry.path().to_path_buf()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn write_archive(path: &Path, manifest: &BackupManifest, sources: &[PathBuf]) -> Result<()> {
+    let mut file = BufWriter::new(File::create(path).at_path(path)?);
+    file.write_all(MAGIC)?;
+
+    let mut encoder = zstd::Encoder::new(file, COMPRESSION_LEVEL)?;
+    let json = serde_json::to_vec(manifest)?;
+    encoder.write_all(&(json.len() as u64).to_le_bytes())?;
+    encoder.write_all(&json)?;
+
+    for (file, source) in manifest.files.iter().zip(sources) {
+        let mut reader = Hashing::new(File::open(source).at_path(source)?);
+        let size = io::copy(&mut (&mut reader).take(file.size), &mut encoder)?;
+        if size != file.size || reader.finish() != file.sha256 {
+            return Err(RustyDiaryError::ContentIntegrity(format!(
+                "{} changed while it was being backed up",
+                source.display()
+            )));
+        }
+    }
+
+    encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(())
+}
+
+/// Decrypts `backup` if needed and unpacks its files into `folder`, the
+/// database files under `db/` and attachments under `attachments/`.
+/// Returns the manifest and the files that do not match their hash.
+fn unpack(backup: &Path, age: &Age, folder: &Path) -> Result<(BackupManifest, Vec<String>)> {
+    let mut head = Vec::new();
+    File::open(backup).at_path(backup)?.take(64).read_to_end(&mut head).at_path(backup)?;
+    let plain = if head.starts_with(MAGIC) {
+        backup.to_path_buf()
+    } else if AGE_HEADERS.iter().any(|header| head.starts_with(header)) {
+        let plain = folder.join("backup");
+        age.decrypt(backup, &plain)?;
+        plain
+    } else {
+        return Err(RustyDiaryError::ContentIntegrity(format!("{} is not a backup", backup.display())));
+    };
+
+    let mut reader = BufReader::new(File::open(&plain).at_path(&plain)?);
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic).at_path(&plain)?;
+    if magic != MAGIC {
+        return Err(RustyDiaryError::ContentIntegrity(format!("{} does not decrypt to a backup", backup.display())));
+    }
+
+    let mut decoder = zstd::Decoder::with_buffer(reader)?;
+    let mut length = [0; 8];
+    decoder.read_exact(&mut length).at_path(backup)?;
+    let length = u64::from_le_bytes(length);
+    if length > MAX_MANIFEST_BYTES {
+        return Err(RustyDiaryError::ContentIntegrity(format!("{} has a corrupt manifest", backup.display())));
+    }
+    let mut json = vec![0; length as usize];
+    decoder.read_exact(&mut json).at_path(backup)?;
+    let manifest: BackupManifest = serde_json::from_slice(&json)?;
+    if manifest.format > FORMAT {
+        return Err(RustyDiaryError::IncompatibleSchema(format!(
+            "backup format {} is newer than this build reads ({}); upgrade rusty_diary to restore it",
+            manifest.format, FORMAT
+        )));
+    }
+
+    let mut problems = Vec::new();
+    for file in &manifest.files {
+        let target = folder.join(file.kind.folder()).join(relative_path(&file.path)?);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        let mut out = File::create(&target).at_path(&target)?;
+        let mut reader = Hashing::new((&mut decoder).take(file.size));
+        let size = io::copy(&mut reader, &mut out).at_path(backup)?;
+        if size != file.size {
+            return Err(RustyDiaryError::ContentIntegrity(format!("{} ends inside {}", backup.display(), file.path)));
+        }
+        if reader.finish() != file.sha256 {
+            problems.push(format!("{} does not match its hash", file.path));
+        }
+    }
+
+    if plain != backup {
+        fs::remove_file(&plain).at_path(&plain)?;
+    }
+    Ok((manifest, problems))
+}
+
+/// Opens the unpacked databases, checking their integrity and that the
+/// main one has the rows the manifest lists
+fn check(folder: &Path, manifest: &BackupManifest, problems: &mut Vec<String>) {
+    if !manifest.files.iter().any(|file| file.kind == FileKind::Database) {
+        problems.push("the backup holds no database".to_string());
+    }
+
+    for file in &manifest.files {
+        if file.kind == FileKind::Shard && file.year.is_none() {
+            problems.push(format!("shard {} does not say which year it holds", file.path));
+        }
+        if !matches!(file.kind, FileKind::Database | FileKind::Shard) {
+            continue;
+        }
+        let path = folder.join(file.kind.folder()).join(&file.path);
+        let checked = (|| -> Result<()> {
+            let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+            if integrity != "ok" {
+                problems.push(format!("{} failed its integrity check: {}", file.path, integrity));
+            }
+            if file.kind == FileKind::Database {
+                compare_counts(&conn, &manifest.tables, problems)?;
+            }
+            Ok(())
+        })();
+        if let Err(e) = checked {
+            problems.push(format!("{} cannot be read: {}", file.path, e));
+        }
+    }
+}
+
+/// Notes each table whose row count differs from `expected`
+fn compare_counts(conn: &Connection, expected: &BTreeMap<String, i64>, problems: &mut Vec<String>) -> Result<()> {
+    let counts = row_counts(conn)?;
+    for (table, expected) in expected {
+        match counts.get(table) {
+            Some(found) if found == expected => {}
+            Some(found) => problems.push(format!("table {} has {} rows, expected {}", table, found, expected)),
+            None => problems.push(format!("table {} is missing", table)),
+        }
+    }
+    Ok(())
+}
+
+/// Rows in each table of a database, SQLite's own tables aside
+fn row_counts(conn: &Connection) -> Result<BTreeMap<String, i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%' ORDER BY name",
+    )?;
+    let tables = stmt.query_map([], |row| row.get::<_, String>(0))?.collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut counts = BTreeMap::new();
+    for table in tables {
+        let sql = format!("SELECT COUNT(*) FROM \"{}\"", table.replace('"', "\"\""));
+        counts.insert(table, conn.query_row(&sql, [], |row| row.get(0))?);
+    }
+    Ok(counts)
+}
+
+/// A path from a manifest as a relative path, refusing any that would
+/// leave the folder it is unpacked into
+fn relative_path(path: &str) -> Result<PathBuf> {
+    let relative = PathBuf::from(path);
+    if path.is_empty() || !relative.components().all(|part| matches!(part, Component::Normal(_))) {
+        return Err(RustyDiaryError::ContentIntegrity(format!("Unsafe path {:?} in backup", path)));
+    }
+    Ok(relative)
+}
+
+fn hash_file(path: &Path) -> Result<(u64, String)> {
+    let mut reader = Hashing::new(File::open(path).at_path(path)?);
+    let size = io::copy(&mut reader, &mut io::sink()).at_path(path)?;
+    Ok((size, reader.finish()))
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    path.parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| "rusty-diary.db".to_string(), |name| name.to_string_lossy().into_owned())
+}
+
+/// A reader hashing what passes through it
+struct Hashing<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Hashing<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, hasher: Sha256::new() }
+    }
+
+    /// Hex SHA-256 of everything read
+    fn finish(self) -> String {
+        self.hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+impl<R: Read> Read for Hashing<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// A hidden folder, removed with everything in it when dropped
+pub(crate) struct Scratch(PathBuf);
+
+impl Scratch {
+    /// A new folder in `parent`, told apart from others by the process id
+    /// and a counter
+    pub(crate) fn new(parent: &Path, purpose: &str) -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        fs::create_dir_all(parent).at_path(parent)?;
+        loop {
+            let path = parent.join(format!(
+                ".rusty-diary-{}-{}-{}",
+                purpose,
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Self(path)),
+                // Left behind by an earlier process with the same id
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e).at_path(&path),
+            }
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    use crate::storage::models::DiaryEntry;
+
+    fn diary(root: &Path) -> Result<Config> {
+        let now = SystemClock.now();
+        let config = Config::new().with_directory(root.join("diary")).with_db(root.join("diary.db"));
+        let storage = StorageManager::new(&config.db_path)?;
+        storage.store_entries(vec![
+            DiaryEntry::new(1, NaiveDate::from_ymd_opt(2020, 5, 1).unwrap(), "An old day".to_string(), now),
+            DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(), "![beach](attachments/2024/a.jpg)".to_string(), now),
+        ])?;
+        storage.archive_year(2020)?;
+
+        let attachments = config.directory.join(ATTACHMENTS_DIR).join("2024");
+        fs::create_dir_all(&attachments)?;
+        fs::write(attachments.join("a.jpg"), b"not really a photo")?;
+        Ok(config)
+    }
+
+    #[test]
+    fn test_create_verify_restore() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = diary(temp_dir.path())?;
+        let backup = temp_dir.path().join("diary.rdbk");
+
+        let manifest = create(&config, &backup, &Age::new())?;
+        let kinds: Vec<FileKind> = manifest.files.iter().map(|file| file.kind).collect();
+        assert_eq!(kinds, [FileKind::Database, FileKind::Shard, FileKind::Attachment]);
+        assert_eq!(manifest.files[2].path, "2024/a.jpg");
+        assert_eq!(manifest.tables["shards"], 1);
+
+        let verification = verify(&config, std::slice::from_ref(&backup), &Age::new())?;
+        assert!(verification.is_ok(), "{:?}", verification.problems);
+        assert!(verification.config_matches);
+        assert!(!verify(&config.clone().with_habit("run"), std::slice::from_ref(&backup), &Age::new())?.config_matches);
+
+        // Restoring under another name renames the shards to match
+        let elsewhere = Config::new()
+            .with_directory(temp_dir.path().join("restored"))
+            .with_db(temp_dir.path().join("restored").join("other.db"));
+        restore(&elsewhere, std::slice::from_ref(&backup), &Age::new(), false)?;
+        assert!(temp_dir.path().join("restored").join("other-2020.db").is_file());
+        assert_eq!(fs::read(temp_dir.path().join("restored/attachments/2024/a.jpg"))?, b"not really a photo");
+        let storage = StorageManager::new(&elsewhere.db_path)?;
+        assert_eq!(storage.entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?.len(), 2);
+        assert!(matches!(
+            restore(&elsewhere, std::slice::from_ref(&backup), &Age::new(), false),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+
+        // Forcing replaces the database and drops its old log
+        drop(storage);
+        let wal = temp_dir.path().join("restored/other.db-wal");
+        fs::write(&wal, b"old log")?;
+        restore(&elsewhere, std::slice::from_ref(&backup), &Age::new(), true)?;
+        assert!(!wal.exists());
+
+        // The old log is kept while the database cannot be replaced
+        let blocked = Config::new()
+            .with_directory(temp_dir.path().join("blocked"))
+            .with_db(temp_dir.path().join("blocked").join("diary.db"));
+        fs::create_dir_all(blocked.db_path.join("in-the-way"))?;
+        let wal = temp_dir.path().join("blocked/diary.db-wal");
+        fs::write(&wal, b"old log")?;
+        assert!(restore(&blocked, std::slice::from_ref(&backup), &Age::new(), true).is_err());
+        assert_eq!(fs::read(&wal)?, b"old log");
+
+        // Damage is caught by the hashes or by decompression itself
+        let mut bytes = fs::read(&backup)?;
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+        fs::write(&backup, bytes)?;
+        assert!(verify(&config, &[backup], &Age::new()).map_or(true, |verification| !verification.is_ok()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_chain() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let config = diary(temp_dir.path())?;
+        let backup = |name: &str| temp_dir.path().join(name);
+        assert!(matches!(
+            create_incremental(&config, &backup("early.rdbk"), &Age::new()),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+
+        let full = create(&config, &backup("full.rdbk"), &Age::new())?;
+        let storage = StorageManager::new(&config.db_path)?;
+        storage.store_entries(vec![DiaryEntry::new(2, NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), "New day".to_string(), now)])?;
+        let attachments = config.directory.join(ATTACHMENTS_DIR).join("2024");
+        fs::remove_file(attachments.join("a.jpg"))?;
+        fs::write(attachments.join("b.jpg"), b"another photo")?;
+
+        let incremental = create_incremental(&config, &backup("1.rdbk"), &Age::new())?;
+        assert_eq!(incremental.parent.as_deref(), Some(full.id.as_str()));
+        assert_eq!(incremental.exec_version, 2);
+        let files: Vec<(FileKind, &str)> = incremental.files.iter().map(|file| (file.kind, file.path.as_str())).collect();
+        assert_eq!(files, [(FileKind::Entries, "entries.jsonl"), (FileKind::Attachment, "2024/b.jpg")]);
+        assert_eq!(incremental.removed, ["2024/a.jpg"]);
+
+        let chain = [backup("full.rdbk"), backup("1.rdbk")];
+        let verification = verify(&config, &chain, &Age::new())?;
+        assert!(verification.is_ok(), "{:?}", verification.problems);
+        assert_eq!(verification.backups, 2);
+        assert!(matches!(verify(&config, &chain[1..], &Age::new()), Err(RustyDiaryError::InvalidConfig(_))));
+        assert!(verify(&config, &[backup("1.rdbk"), backup("1.rdbk")], &Age::new()).is_err());
+
+        let elsewhere = Config::new()
+            .with_directory(temp_dir.path().join("restored"))
+            .with_db(temp_dir.path().join("restored").join("diary.db"));
+        restore(&elsewhere, &chain, &Age::new(), false)?;
+        let restored = StorageManager::new(&elsewhere.db_path)?;
+        assert_eq!(restored.entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?.len(), 3);
+        assert!(elsewhere.directory.join("attachments/2024/b.jpg").is_file());
+        assert!(!elsewhere.directory.join("attachments/2024/a.jpg").exists());
+
+        // Later incrementals build on the compacted backup
+        let compacted = compact(&config, &chain, &backup("compacted.rdbk"), &Age::new())?;
+        assert!(compacted.is_full());
+        assert_eq!(compacted.tables["diary_entries"], 2);
+        let next = create_incremental(&config, &backup("2.rdbk"), &Age::new())?;
+        assert_eq!(next.parent.as_deref(), Some(compacted.id.as_str()));
+        assert_eq!(next.files.len(), 1);
+        assert!(verify(&config, &[backup("compacted.rdbk"), backup("2.rdbk")], &Age::new())?.is_ok());
+        assert_eq!(history(&config)?.len(), 4);
+
+        storage.delete_entries(NaiveDate::from_ymd_opt(2024, 1, 3).unwrap())?;
+        assert!(matches!(
+            create_incremental(&config, &backup("pruned.rdbk"), &Age::new()),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_encrypted_with_age() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = diary(temp_dir.path())?;
+        let backup = temp_dir.path().join("diary.rdbk");
+
+        // Stands in for age, tagging the file with age's header and logging its arguments
+        let script = temp_dir.path().join("fake-age.sh");
+        let log = temp_dir.path().join("age.log");
+        fs::write(
+            &script,
+            format!(
+                "echo \"$@\" >> {log}\n\
+                 while [ $# -gt 1 ]; do case \"$1\" in -d) decrypt=1;; -o) out=\"$2\"; shift;; -r|-i) shift;; esac; shift; done\n\
+                 if [ -n \"$decrypt\" ]; then tail -c +23 \"$1\" > \"$out\"; \
+                 else {{ printf 'age-encryption.org/v1\\n'; cat \"$1\"; }} > \"$out\"; fi\n",
+                log = log.display()
+            ),
+        )?;
+        let command = format!("sh {}", script.display());
+
+        create(&config, &backup, &Age::new().with_command(&command).with_recipient("age1example"))?;
+        assert!(fs::read(&backup)?.starts_with(b"age-encryption.org/v1\n"));
+
+        let identity = temp_dir.path().join("key.txt");
+        let verification = verify(&config, std::slice::from_ref(&backup), &Age::new().with_command(&command).with_identity(&identity))?;
+        assert!(verification.is_ok(), "{:?}", verification.problems);
+
+        let log = fs::read_to_string(&log)?;
+        let calls: Vec<&str> = log.lines().collect();
+        assert!(calls[0].starts_with("-r age1example -o "));
+        assert!(calls[1].starts_with(&format!("-d -i {} -o ", identity.display())));
+
+        let failing = Age::new().with_command("false").with_recipient("age1example");
+        assert!(matches!(create(&config, &backup, &failing), Err(RustyDiaryError::Encryption(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_as_of() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config::new().with_directory(temp_dir.path().join("diary")).with_db(temp_dir.path().join("diary.db"));
+        fs::create_dir_all(&config.directory)?;
+        let entry = |exec_version, stored: (i32, u32, u32), day: (i32, u32, u32), content: &str| {
+            let stored = NaiveDate::from_ymd_opt(stored.0, stored.1, stored.2).unwrap().and_hms_opt(21, 0, 0).unwrap();
+            let day = NaiveDate::from_ymd_opt(day.0, day.1, day.2).unwrap();
+            DiaryEntry::new(exec_version, day, content.to_string(), stored)
+        };
+        let storage = StorageManager::new(&config.db_path)?;
+        storage.store_entries(vec![
+            entry(1, (2024, 1, 1), (2020, 5, 1), "An old day"),
+            entry(1, (2024, 1, 1), (2024, 1, 1), "First draft"),
+        ])?;
+        storage.store_entries(vec![
+            entry(2, (2024, 3, 1), (2024, 1, 1), "Second draft"),
+            entry(2, (2024, 3, 1), (2024, 2, 10), "February"),
+        ])?;
+        storage.store_entries(vec![entry(3, (2024, 6, 2), (2024, 6, 2), "June")])?;
+        storage.archive_year(2020)?;
+
+        let into = temp_dir.path().join("then");
+        let restored = restore_as_of(&config, NaiveDate::from_ymd_opt(2024, 3, 15).unwrap(), &into)?;
+        assert_eq!((restored.exec_version, restored.entries, restored.days), (2, 4, 3));
+        let storage = StorageManager::new(&restored.db_path)?;
+        let latest = storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        let contents: Vec<&str> = latest.iter().map(|entry| entry.content.as_str()).collect();
+        assert_eq!(contents, ["February", "Second draft", "An old day"]);
+        let journal = fs::read_to_string(&restored.journal.written[0])?;
+        assert!(journal.contains("Second draft") && !journal.contains("June"));
+
+        // The live diary is left as it was
+        assert_eq!(StorageManager::new(&config.db_path)?.latest_exec_version()?, 3);
+        assert!(restore_as_of(&config, NaiveDate::from_ymd_opt(2024, 3, 15).unwrap(), &into).is_err());
+        assert!(restore_as_of(&config, NaiveDate::from_ymd_opt(2023, 12, 31).unwrap(), &temp_dir.path().join("before")).is_err());
+        assert!(restore_as_of(&config, NaiveDate::from_ymd_opt(2024, 7, 1).unwrap(), &config.directory).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,57 @@
+//! Cancelling long-running operations, e.g. when the user presses Ctrl-C
+//! during a sync of a huge archive. Operations check their token between
+//! units of work and stop with `RustyDiaryError::Cancelled`, rolling back
+//! the open transaction and leaving diary files untouched.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::error::{Result, RustyDiaryError};
+
+/// A flag shared between the operations that check it and whoever cancels
+/// them. Clones share the flag; the default token is never cancelled.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Asks every operation holding a clone of the token to stop
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Fails with `RustyDiaryError::Cancelled` once the token is cancelled
+    pub fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            return Err(RustyDiaryError::Cancelled);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(token.check().is_ok());
+
+        clone.cancel();
+        assert!(token.is_cancelled());
+        assert_eq!(token.check().unwrap_err().kind(), ErrorKind::Cancelled);
+        assert!(!CancellationToken::default().is_cancelled());
+    }
+}
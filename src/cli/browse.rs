@@ -0,0 +1,465 @@
+use anyhow::Context;
+use chrono::{Datelike, NaiveDate};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use rusty_diary::diary::flavor::MarkdownFlavor;
+use rusty_diary::diary::highlight::Highlighter;
+use rusty_diary::diary::related::{RelatedEntry, RelatedFinder};
+use rusty_diary::storage::{DiaryEntry, StorageManager};
+
+use super::{markdown, show};
+
+/// Related days listed in the sidebar
+const RELATED_LIMIT: usize = 5;
+
+/// Runs the journal browser, opening on `today`'s year, until the user quits
+pub fn run(storage: &StorageManager, today: NaiveDate, highlighter: Option<Highlighter>, flavor: MarkdownFlavor) -> anyhow::Result<()> {
+    let entries = storage
+        .latest_entries(NaiveDate::MIN, NaiveDate::MAX)
+        .context("Failed to load entries")?;
+    let mut app = App::new(entries, today);
+    app.highlighter = highlighter;
+    app.flavor = flavor;
+
+    let mut terminal = ratatui::try_init().context("Failed to initialize the terminal")?;
+    let result = (|| -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| draw(frame, &app))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !app.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    })();
+    ratatui::restore();
+
+    result
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Months,
+    Entries,
+}
+
+/// Browser state, kept free of terminal I/O
+struct App {
+    /// Latest version of each day, newest first
+    entries: Vec<DiaryEntry>,
+    finder: RelatedFinder,
+    /// (year, month, entry count), newest first
+    months: Vec<(i32, u32, usize)>,
+    /// 0 shows every month, otherwise `months[month - 1]`
+    month: usize,
+    /// Indices into `entries` that pass the month and search filters
+    visible: Vec<usize>,
+    selected: usize,
+    focus: Focus,
+    query: String,
+    searching: bool,
+    scroll: u16,
+    status: String,
+    today: NaiveDate,
+    /// Colors code blocks in the preview
+    highlighter: Option<Highlighter>,
+    /// Markdown dialect of the entries
+    flavor: MarkdownFlavor,
+}
+
+impl App {
+    /// `entries` holds one version per day, newest first
+    fn new(entries: Vec<DiaryEntry>, today: NaiveDate) -> Self {
+        let mut months: Vec<(i32, u32, usize)> = Vec::new();
+        for entry in &entries {
+            match months.last_mut() {
+                Some((year, month, count)) if (*year, *month) == (entry.date.year(), entry.date.month()) => {
+                    *count += 1;
+                }
+                _ => months.push((entry.date.year(), entry.date.month(), 1)),
+            }
+        }
+
+        let mut app = Self {
+            finder: RelatedFinder::new(&entries),
+            entries,
+            months,
+            month: 0,
+            visible: Vec::new(),
+            selected: 0,
+            focus: Focus::Entries,
+            query: String::new(),
+            searching: false,
+            scroll: 0,
+            status: String::new(),
+            today,
+            highlighter: None,
+            flavor: MarkdownFlavor::CommonMark,
+        };
+        app.refilter();
+        app
+    }
+
+    fn selected_entry(&self) -> Option<&DiaryEntry> {
+        self.visible.get(self.selected).map(|&i| &self.entries[i])
+    }
+
+    /// Days related to the selected entry, most related first
+    fn related(&self) -> Vec<RelatedEntry> {
+        self.selected_entry().map(|entry| self.finder.find(entry, RELATED_LIMIT)).unwrap_or_default()
+    }
+
+    fn refilter(&mut self) {
+        let current = self.selected_entry().map(|entry| entry.date);
+        let month = self.month.checked_sub(1).map(|i| (self.months[i].0, self.months[i].1));
+        let pattern = DiaryEntry::query_pattern(&self.query);
+
+        self.visible = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| month.is_none_or(|m| m == (entry.date.year(), entry.date.month())))
+            .filter(|(_, entry)| {
+                self.query.is_empty() || pattern.as_ref().is_some_and(|p| entry.search_pattern(p, 0).is_some())
+            })
+            .map(|(i, _)| i)
+            .collect();
+
+        // Keep the cursor on the same day when it is still visible
+        self.selected = current
+            .and_then(|date| self.visible.iter().position(|&i| self.entries[i].date == date))
+            .unwrap_or(0);
+        self.scroll = 0;
+    }
+
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.visible.len().saturating_sub(1));
+        self.scroll = 0;
+    }
+
+    fn move_cursor(&mut self, down: bool) {
+        match self.focus {
+            Focus::Entries => {
+                let index = if down { self.selected + 1 } else { self.selected.saturating_sub(1) };
+                self.select(index);
+            }
+            Focus::Months => {
+                self.month = if down {
+                    (self.month + 1).min(self.months.len())
+                } else {
+                    self.month.saturating_sub(1)
+                };
+                self.refilter();
+            }
+        }
+    }
+
+    /// Jumps to the next earlier year's entry for today's month and day,
+    /// wrapping around to the most recent one
+    fn on_this_day(&mut self) {
+        let today = self.today;
+        let matches = move |entry: &DiaryEntry| {
+            (entry.date.month(), entry.date.day()) == (today.month(), today.day())
+                && entry.date.year() < today.year()
+        };
+        if !self.entries.iter().any(matches) {
+            self.status = format!("No entries on {} in earlier years", self.today.format("%B %-d"));
+            return;
+        }
+
+        if self.month != 0 || !self.query.is_empty() {
+            self.month = 0;
+            self.query.clear();
+            self.refilter();
+        }
+
+        // Cycle onwards from a matching selection, otherwise start at the most recent
+        let on_match = self.selected_entry().is_some_and(matches);
+        let start = if on_match { self.selected + 1 } else { 0 };
+        let next = (start..self.visible.len())
+            .chain(0..start)
+            .find(|&i| matches(&self.entries[self.visible[i]]))
+            .expect("a matching entry is visible without filters");
+        self.select(next);
+
+        let years = self.today.year() - self.entries[self.visible[next]].date.year();
+        self.status = format!("On this day, {} year{} ago", years, if years == 1 { "" } else { "s" });
+    }
+
+    /// Jumps to the day most related to the selected one, clearing the
+    /// filters when it is hidden by them
+    fn most_related(&mut self) {
+        let Some(related) = self.related().into_iter().next() else {
+            self.status = "No related entries".to_string();
+            return;
+        };
+
+        let is_visible = |app: &Self| app.visible.iter().any(|&i| app.entries[i].date == related.date);
+        if !is_visible(self) {
+            self.month = 0;
+            self.query.clear();
+            self.refilter();
+        }
+        if let Some(index) = self.visible.iter().position(|&i| self.entries[i].date == related.date) {
+            self.select(index);
+        }
+        self.status = format!("Related: {}", show::related_reasons(&related));
+    }
+
+    /// Applies a key press; returns false when the browser should close
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        self.status.clear();
+
+        if self.searching {
+            match key.code {
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.query.clear();
+                    self.refilter();
+                }
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refilter();
+                }
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.refilter();
+                }
+                _ => {}
+            }
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.refilter();
+            }
+            KeyCode::Char('/') => {
+                self.searching = true;
+                self.focus = Focus::Entries;
+            }
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right | KeyCode::Char('h') | KeyCode::Char('l') => {
+                self.focus = match self.focus {
+                    Focus::Months => Focus::Entries,
+                    Focus::Entries => Focus::Months,
+                };
+            }
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(true),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(false),
+            KeyCode::Char('g') | KeyCode::Home => self.select(0),
+            KeyCode::Char('G') | KeyCode::End => self.select(usize::MAX),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.scroll = self.scroll.saturating_add(10),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            KeyCode::Char('t') => self.on_this_day(),
+            KeyCode::Char('r') => self.most_related(),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn pane(title: String, focused: bool) -> Block<'static> {
+    let style = if focused { Style::default().fg(Color::Cyan) } else { Style::default() };
+    Block::default().borders(Borders::ALL).border_style(style).title(title)
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [main, footer] = Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+    let [sidebar, entries, preview] = Layout::horizontal([
+        Constraint::Length(18),
+        Constraint::Length(26),
+        Constraint::Min(20),
+    ])
+    .areas(main);
+    let [months, related] =
+        Layout::vertical([Constraint::Min(3), Constraint::Length(RELATED_LIMIT as u16 + 2)]).areas(sidebar);
+
+    draw_months(frame, app, months);
+    draw_related(frame, app, related);
+    draw_entries(frame, app, entries);
+    draw_preview(frame, app, preview);
+
+    let footer_text = if app.searching {
+        format!("/{}", app.query)
+    } else if !app.status.is_empty() {
+        app.status.clone()
+    } else {
+        "q quit  / search  t on this day  r most related  tab switch pane  j/k move  PgUp/PgDn scroll".to_string()
+    };
+    frame.render_widget(Paragraph::new(footer_text).style(Style::default().fg(Color::DarkGray)), footer);
+}
+
+fn draw_months(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = std::iter::once(format!("All {:>8}", app.entries.len()))
+        .chain(app.months.iter().map(|(year, month, count)| format!("{}-{:02} {:>6}", year, month, count)))
+        .map(ListItem::new)
+        .collect();
+
+    let list = List::new(items)
+        .block(pane("Months".to_string(), app.focus == Focus::Months))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, area, &mut ListState::default().with_selected(Some(app.month)));
+}
+
+fn draw_related(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .related()
+        .iter()
+        .map(|related| ListItem::new(format!("{} {:>4.1}", related.date, related.score)))
+        .collect();
+    frame.render_widget(List::new(items).block(pane("Related".to_string(), false)), area);
+}
+
+fn draw_entries(frame: &mut Frame, app: &App, area: Rect) {
+    let items: Vec<ListItem> = app
+        .visible
+        .iter()
+        .map(|&i| {
+            let entry = &app.entries[i];
+            ListItem::new(format!("{} {} {:>5}w", entry.date, entry.date.format("%a"), entry.word_count()))
+        })
+        .collect();
+
+    let title = if app.query.is_empty() {
+        format!("Entries ({})", app.visible.len())
+    } else {
+        format!("\"{}\" ({})", app.query, app.visible.len())
+    };
+    let list = List::new(items)
+        .block(pane(title, app.focus == Focus::Entries))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let selected = (!app.visible.is_empty()).then_some(app.selected);
+    frame.render_stateful_widget(list, area, &mut ListState::default().with_selected(selected));
+}
+
+fn draw_preview(frame: &mut Frame, app: &App, area: Rect) {
+    let Some(entry) = app.selected_entry() else {
+        frame.render_widget(Paragraph::new(Line::from("No entries")).block(pane(String::new(), false)), area);
+        return;
+    };
+
+    let title = format!(
+        "{} · v{} · {} words",
+        entry.date.format("%A, %B %-d %Y"),
+        entry.exec_version,
+        entry.word_count()
+    );
+    let preview = Paragraph::new(markdown::to_text(&app.flavor.to_commonmark(&entry.content), app.highlighter.as_ref()))
+        .block(pane(title, false))
+        .wrap(Wrap { trim: false })
+        .scroll((app.scroll, 0));
+    frame.render_widget(preview, area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_diary::clock::{Clock, SystemClock};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn app() -> App {
+        // Newest first, as `latest_entries` returns them
+        let entries = vec![
+            DiaryEntry::new(2, date(2024, 3, 2), "Coffee with Ana".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, date(2024, 3, 1), "# Rainy\nStayed in".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, date(2023, 3, 2), "Rain again".to_string(), SystemClock.now()),
+        ];
+        App::new(entries, date(2025, 3, 2))
+    }
+
+    #[test]
+    fn test_months_and_search_filter_entries() {
+        let mut app = app();
+        assert_eq!(app.entries.len(), 3);
+        assert_eq!(app.months, vec![(2024, 3, 2), (2023, 3, 1)]);
+
+        app.handle_key(key(KeyCode::Tab));
+        app.handle_key(key(KeyCode::Char('j')));
+        assert_eq!(app.visible.len(), 2);
+
+        app.handle_key(key(KeyCode::Char('/')));
+        for c in "RAIN".chars() {
+            app.handle_key(key(KeyCode::Char(c)));
+        }
+        app.handle_key(key(KeyCode::Enter));
+        assert_eq!(app.selected_entry().unwrap().date, date(2024, 3, 1));
+
+        app.handle_key(key(KeyCode::Esc));
+        assert_eq!(app.visible.len(), 2);
+        assert!(app.handle_key(key(KeyCode::Char('x'))));
+        assert!(!app.handle_key(key(KeyCode::Char('q'))));
+    }
+
+    #[test]
+    fn test_on_this_day_cycles_through_years() {
+        let mut app = app();
+        app.handle_key(key(KeyCode::Char('j')));
+        app.handle_key(key(KeyCode::Char('t')));
+        assert_eq!(app.selected_entry().unwrap().date, date(2024, 3, 2));
+        assert_eq!(app.status, "On this day, 1 year ago");
+
+        app.handle_key(key(KeyCode::Char('t')));
+        assert_eq!(app.selected_entry().unwrap().date, date(2023, 3, 2));
+        app.handle_key(key(KeyCode::Char('t')));
+        assert_eq!(app.selected_entry().unwrap().date, date(2024, 3, 2));
+
+        let mut empty = App::new(Vec::new(), date(2025, 3, 2));
+        empty.handle_key(key(KeyCode::Char('t')));
+        assert!(empty.status.starts_with("No entries"));
+    }
+
+    #[test]
+    fn test_jump_to_most_related() {
+        let entries = vec![
+            DiaryEntry::new(1, date(2024, 3, 3), "Climbing session #climbing".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, date(2024, 3, 2), "Coffee with Ana".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, date(2024, 2, 1), "First climbing day #climbing".to_string(), SystemClock.now()),
+        ];
+        let mut app = App::new(entries, date(2025, 3, 2));
+        assert_eq!(app.related()[0].date, date(2024, 2, 1));
+
+        app.handle_key(key(KeyCode::Tab));
+        app.handle_key(key(KeyCode::Char('j')));
+        app.handle_key(key(KeyCode::Tab));
+        assert_eq!(app.visible.len(), 2);
+        app.handle_key(key(KeyCode::Char('r')));
+        assert_eq!(app.selected_entry().unwrap().date, date(2024, 2, 1));
+        assert!(app.status.starts_with("Related: #climbing"));
+
+        app.handle_key(key(KeyCode::Char('k')));
+        app.handle_key(key(KeyCode::Char('r')));
+        assert_eq!(app.status, "No related entries");
+    }
+
+    #[test]
+    fn test_draw_renders_preview() {
+        let mut app = app();
+        app.handle_key(key(KeyCode::Char('j')));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 12)).unwrap();
+        terminal.draw(|frame| draw(frame, &app)).unwrap();
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+
+        assert!(screen.contains("2024-03 "));
+        assert!(screen.contains("Friday, March 1 2024"));
+        assert!(screen.contains("Rainy"));
+        assert!(screen.contains("Stayed in"));
+    }
+}
@@ -0,0 +1,20 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+
+/// Opens `path` in `$VISUAL`, `$EDITOR` or `vi`, and waits for it to close
+pub fn edit(path: &Path) -> anyhow::Result<()> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$1\"", editor))
+        .arg("sh")
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to start editor '{}'", editor))?;
+    anyhow::ensure!(status.success(), "Editor '{}' exited with {}", editor, status);
+    Ok(())
+}
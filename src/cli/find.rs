@@ -0,0 +1,282 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+use chrono::NaiveDate;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use nucleo_matcher::pattern::{CaseMatching, Normalization, Pattern};
+use nucleo_matcher::{Config, Matcher};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, TerminalOptions, Viewport};
+use rusty_diary::diary::flavor::MarkdownFlavor;
+use rusty_diary::diary::highlight::Highlighter;
+use rusty_diary::storage::{DiaryEntry, StorageManager};
+
+/// Rows taken by the inline picker, prompt included
+const PICKER_HEIGHT: u16 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Page,
+    Edit,
+}
+
+/// Lets the user fuzzy-pick an entry, then shows it in `$PAGER` or opens
+/// it in `$EDITOR`. Editing writes `<date>.md` into the diary directory so
+/// the next sync stores the changes as a new version.
+pub fn run(
+    storage: &StorageManager,
+    directory: &Path,
+    query: &str,
+    edit: bool,
+    highlighter: Option<&Highlighter>,
+    flavor: MarkdownFlavor,
+) -> anyhow::Result<()> {
+    let entries = storage
+        .latest_entries(NaiveDate::MIN, NaiveDate::MAX)
+        .context("Failed to load entries")?;
+    anyhow::ensure!(!entries.is_empty(), "No stored entries to search");
+
+    let mut picker = Picker::new(entries.iter().map(candidate).collect(), query);
+    let default_action = if edit { Action::Edit } else { Action::Page };
+
+    let mut terminal = ratatui::try_init_with_options(TerminalOptions {
+        viewport: Viewport::Inline(PICKER_HEIGHT),
+    })
+    .context("Failed to initialize the terminal")?;
+    let choice = (|| -> anyhow::Result<Option<(usize, Action)>> {
+        loop {
+            terminal.draw(|frame| draw(frame, &picker))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                match picker.handle_key(key, default_action) {
+                    KeyOutcome::Continue => {}
+                    KeyOutcome::Cancel => return Ok(None),
+                    KeyOutcome::Pick(index, action) => return Ok(Some((index, action))),
+                }
+            }
+        }
+    })();
+    terminal.clear()?;
+    ratatui::restore();
+
+    match choice? {
+        Some((index, Action::Page)) => {
+            let entry = std::slice::from_ref(&entries[index]);
+            page(&super::show::format_entries(entry, false, true, highlighter, flavor))
+        }
+        Some((index, Action::Edit)) => edit_entry(directory, &entries[index]),
+        None => Ok(()),
+    }
+}
+
+/// One picker line: date, then the first two non-empty lines with heading markers dropped
+fn candidate(entry: &DiaryEntry) -> String {
+    let lines = entry
+        .content
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .filter(|line| !line.is_empty());
+
+    let mut text = format!("{} {}", entry.date, entry.date.format("%a"));
+    for line in lines.take(2) {
+        text.push_str("  ");
+        text.push_str(line);
+    }
+    text
+}
+
+fn page(content: &str) -> anyhow::Result<()> {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start pager '{}'", pager))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager may quit before reading everything
+        let _ = stdin.write_all(content.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+fn edit_entry(directory: &Path, entry: &DiaryEntry) -> anyhow::Result<()> {
+    let path = directory.join(format!("{}.md", entry.date));
+    if !path.exists() {
+        fs::write(&path, &entry.content)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    super::editor::edit(&path)?;
+    eprintln!("Saved to {}; run a sync to store the changes", path.display());
+    Ok(())
+}
+
+enum KeyOutcome {
+    Continue,
+    Cancel,
+    Pick(usize, Action),
+}
+
+/// Picker state, kept free of terminal I/O
+struct Picker {
+    candidates: Vec<String>,
+    query: String,
+    /// Indices into `candidates`, best match first
+    matches: Vec<usize>,
+    selected: usize,
+    matcher: Matcher,
+}
+
+impl Picker {
+    fn new(candidates: Vec<String>, query: &str) -> Self {
+        let mut picker = Self {
+            candidates,
+            query: query.to_string(),
+            matches: Vec::new(),
+            selected: 0,
+            matcher: Matcher::new(Config::DEFAULT),
+        };
+        picker.update();
+        picker
+    }
+
+    fn update(&mut self) {
+        let pattern = Pattern::parse(&self.query, CaseMatching::Smart, Normalization::Smart);
+        let indexed = self.candidates.iter().enumerate().map(|(index, text)| Candidate { index, text });
+
+        self.matches = pattern
+            .match_list(indexed, &mut self.matcher)
+            .into_iter()
+            .map(|(candidate, _)| candidate.index)
+            .collect();
+        self.selected = 0;
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, default_action: Action) -> KeyOutcome {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+        let action = match (key.code, ctrl) {
+            (KeyCode::Esc, _) | (KeyCode::Char('c'), true) => return KeyOutcome::Cancel,
+            (KeyCode::Enter, _) => default_action,
+            (KeyCode::Char('e'), true) => Action::Edit,
+            (KeyCode::Down, _) | (KeyCode::Char('n'), true) => {
+                self.selected = (self.selected + 1).min(self.matches.len().saturating_sub(1));
+                return KeyOutcome::Continue;
+            }
+            (KeyCode::Up, _) | (KeyCode::Char('p'), true) => {
+                self.selected = self.selected.saturating_sub(1);
+                return KeyOutcome::Continue;
+            }
+            (KeyCode::Backspace, _) => {
+                self.query.pop();
+                self.update();
+                return KeyOutcome::Continue;
+            }
+            (KeyCode::Char(c), false) => {
+                self.query.push(c);
+                self.update();
+                return KeyOutcome::Continue;
+            }
+            _ => return KeyOutcome::Continue,
+        };
+
+        match self.matches.get(self.selected) {
+            Some(&index) => KeyOutcome::Pick(index, action),
+            None => KeyOutcome::Continue,
+        }
+    }
+}
+
+/// A candidate line that remembers its position through `match_list`
+struct Candidate<'a> {
+    index: usize,
+    text: &'a str,
+}
+
+impl AsRef<str> for Candidate<'_> {
+    fn as_ref(&self) -> &str {
+        self.text
+    }
+}
+
+fn draw(frame: &mut Frame, picker: &Picker) {
+    let [prompt, list] = Layout::vertical([Constraint::Length(1), Constraint::Min(1)]).areas(frame.area());
+
+    let counter = format!("  {}/{}", picker.matches.len(), picker.candidates.len());
+    frame.render_widget(
+        Paragraph::new(Line::from(vec![
+            Span::styled("> ", Style::default().fg(Color::Cyan)),
+            Span::raw(picker.query.clone()),
+            Span::styled(counter, Style::default().fg(Color::DarkGray)),
+        ])),
+        prompt,
+    );
+
+    let items: Vec<ListItem> = picker
+        .matches
+        .iter()
+        .map(|&index| ListItem::new(picker.candidates[index].clone()))
+        .collect();
+    let list_widget = List::new(items)
+        .highlight_symbol("▌ ")
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD).fg(Color::Cyan));
+    let selected = (!picker.matches.is_empty()).then_some(picker.selected);
+    frame.render_stateful_widget(list_widget, list, &mut ListState::default().with_selected(selected));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_diary::clock::{Clock, SystemClock};
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn test_candidate_uses_first_lines() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let entry = DiaryEntry::new(1, date, "# Rainy day\n\nStayed in\nRead a book".to_string(), SystemClock.now());
+
+        assert_eq!(candidate(&entry), "2024-03-01 Fri  Rainy day  Stayed in");
+    }
+
+    #[test]
+    fn test_picker_filters_and_picks() {
+        let mut picker = Picker::new(
+            vec![
+                "2024-03-02 Sat  Coffee with Ana".to_string(),
+                "2024-03-01 Fri  Rainy day".to_string(),
+            ],
+            "",
+        );
+        assert_eq!(picker.matches, vec![0, 1]);
+
+        for c in "rainy".chars() {
+            picker.handle_key(key(KeyCode::Char(c)), Action::Page);
+        }
+        assert_eq!(picker.matches, vec![1]);
+        assert!(matches!(picker.handle_key(key(KeyCode::Enter), Action::Page), KeyOutcome::Pick(1, Action::Page)));
+        assert!(matches!(picker.handle_key(ctrl('e'), Action::Page), KeyOutcome::Pick(1, Action::Edit)));
+
+        picker.handle_key(key(KeyCode::Char('x')), Action::Page);
+        assert!(picker.matches.is_empty());
+        assert!(matches!(picker.handle_key(key(KeyCode::Enter), Action::Page), KeyOutcome::Continue));
+        assert!(matches!(picker.handle_key(key(KeyCode::Esc), Action::Page), KeyOutcome::Cancel));
+    }
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+
+use rusty_diary::storage::EntryLink;
+use serde::Serialize;
+
+/// Formats `graph export` understands
+pub const FORMATS: &[&str] = &["dot", "json"];
+
+/// Entries and notes connected by wiki links
+#[derive(Debug, Serialize)]
+pub struct LinkGraph<'a> {
+    /// Linking days and link targets, sorted
+    pub nodes: Vec<String>,
+    pub edges: &'a [EntryLink],
+}
+
+impl<'a> LinkGraph<'a> {
+    pub fn new(links: &'a [EntryLink]) -> Self {
+        let nodes: BTreeSet<String> = links
+            .iter()
+            .flat_map(|link| [link.source.to_string(), link.target.clone()])
+            .collect();
+        Self { nodes: nodes.into_iter().collect(), edges: links }
+    }
+
+    /// Graphviz source; days are boxes, named notes are note shapes
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph diary {\n    node [shape=box];\n");
+        for note in self.edges.iter().filter(|link| link.target_date.is_none()) {
+            let line = format!("    {} [shape=note];\n", quote(&note.target));
+            if !out.contains(&line) {
+                out.push_str(&line);
+            }
+        }
+        for link in self.edges {
+            out.push_str(&format!("    {} -> {};\n", quote(&link.source.to_string()), quote(&link.target)));
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+fn quote(id: &str) -> String {
+    format!("\"{}\"", id.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_to_dot() {
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let links = vec![
+            EntryLink { source: ymd(2), target: "2024-01-01".to_string(), target_date: Some(ymd(1)) },
+            EntryLink { source: ymd(2), target: "The \"Plan\"".to_string(), target_date: None },
+            EntryLink { source: ymd(3), target: "The \"Plan\"".to_string(), target_date: None },
+        ];
+
+        let graph = LinkGraph::new(&links);
+        assert_eq!(graph.nodes, vec!["2024-01-01", "2024-01-02", "2024-01-03", "The \"Plan\""]);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph diary {\n    node [shape=box];\n    \"The \\\"Plan\\\"\" [shape=note];\n    \
+             \"2024-01-02\" -> \"2024-01-01\";\n    \"2024-01-02\" -> \"The \\\"Plan\\\"\";\n    \
+             \"2024-01-03\" -> \"The \\\"Plan\\\"\";\n}\n"
+        );
+    }
+}
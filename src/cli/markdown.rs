@@ -0,0 +1,488 @@
+//! Terminal rendering of diary markdown. Covers what diaries typically use:
+//! headings, lists, task items, quotes, rules, tables, footnotes, fenced
+//! code, links, inline emphasis/code and emoji shortcodes. Anything else
+//! passes through unchanged.
+
+use rusty_diary::diary::highlight::Highlighter;
+use rusty_diary::diary::processor::resolve_shortcodes;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tint {
+    Cyan,
+    Yellow,
+    Magenta,
+    Gray,
+    DarkGray,
+    /// A color picked by syntax highlighting
+    Rgb(u8, u8, u8),
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SpanStyle {
+    pub tint: Option<Tint>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+}
+
+impl SpanStyle {
+    fn tint(tint: Tint) -> Self {
+        Self { tint: Some(tint), ..Self::default() }
+    }
+
+    /// Layers `other` on top of this style
+    fn patch(self, other: SpanStyle) -> Self {
+        Self {
+            tint: other.tint.or(self.tint),
+            bold: self.bold || other.bold,
+            italic: self.italic || other.italic,
+            underline: self.underline || other.underline,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StyledSpan {
+    pub text: String,
+    pub style: SpanStyle,
+}
+
+pub type StyledLine = Vec<StyledSpan>;
+
+fn span(text: impl Into<String>, style: SpanStyle) -> StyledSpan {
+    StyledSpan { text: text.into(), style }
+}
+
+/// The styled lines of `content`. Fenced code is colored by `highlighter`
+/// when given and the fence names a language it knows.
+pub fn render(content: &str, highlighter: Option<&Highlighter>) -> Vec<StyledLine> {
+    let mut lines = Vec::new();
+    // Language and lines of the fenced code block being read
+    let mut code: Option<(&str, Vec<&str>)> = None;
+    // Rows of the table being read
+    let mut table: Vec<&str> = Vec::new();
+
+    for raw in content.lines() {
+        let trimmed = raw.trim_start();
+
+        if code.is_none() && trimmed.starts_with('|') {
+            table.push(trimmed);
+            continue;
+        }
+        if !table.is_empty() {
+            lines.extend(table_lines(&std::mem::take(&mut table)));
+        }
+
+        if let Some(fence) = trimmed.strip_prefix("```") {
+            match code.take() {
+                Some((language, block)) => lines.extend(code_lines(&block, language, highlighter)),
+                None => code = Some((fence.split_whitespace().next().unwrap_or_default(), Vec::new())),
+            }
+            continue;
+        }
+        if let Some((_, block)) = &mut code {
+            block.push(raw);
+            continue;
+        }
+
+        lines.push(render_line(trimmed, raw.len() - trimmed.len()));
+    }
+    if let Some((language, block)) = code {
+        lines.extend(code_lines(&block, language, highlighter));
+    }
+    lines.extend(table_lines(&table));
+
+    lines
+}
+
+/// A pipe table with aligned columns. Rows without the `|---|` line under
+/// the first are not a table and render as ordinary lines.
+fn table_lines(rows: &[&str]) -> Vec<StyledLine> {
+    let is_delimiter = |row: &str| row.chars().all(|c| matches!(c, '|' | '-' | ':' | ' '));
+    if rows.len() < 2 || !is_delimiter(rows[1]) {
+        return rows.iter().map(|row| render_line(row, 0)).collect();
+    }
+
+    let cells: Vec<Vec<Vec<StyledSpan>>> = rows
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| *i != 1)
+        .map(|(_, row)| {
+            let row = row.trim().trim_start_matches('|');
+            let row = row.strip_suffix('|').unwrap_or(row);
+            row.split('|').map(|cell| inline(cell.trim())).collect()
+        })
+        .collect();
+    let width = |cell: &Vec<StyledSpan>| cell.iter().map(|s| s.text.width()).sum::<usize>();
+    let columns = cells.iter().map(Vec::len).max().unwrap_or_default();
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| cells.iter().filter_map(|row| row.get(column)).map(width).max().unwrap_or_default())
+        .collect();
+
+    let border = SpanStyle::tint(Tint::DarkGray);
+    let mut lines = Vec::new();
+    for (i, row) in cells.iter().enumerate() {
+        let mut line = Vec::new();
+        for (column, cell_width) in widths.iter().enumerate() {
+            line.push(span(if column == 0 { "│ " } else { " │ " }, border));
+            let cell = row.get(column).cloned().unwrap_or_default();
+            let padding = cell_width - width(&cell);
+            let header = SpanStyle { bold: i == 0, ..SpanStyle::default() };
+            line.extend(cell.into_iter().map(|s| patched(s, header)));
+            line.push(span(" ".repeat(padding), SpanStyle::default()));
+        }
+        line.push(span(" │", border));
+        lines.push(line);
+        if i == 0 {
+            let rule: Vec<String> = widths.iter().map(|width| "─".repeat(width + 2)).collect();
+            lines.push(vec![span(format!("├{}┤", rule.join("┼")), border)]);
+        }
+    }
+    lines
+}
+
+/// Indented lines of a code block, highlighted or all in one color
+fn code_lines(block: &[&str], language: &str, highlighter: Option<&Highlighter>) -> Vec<StyledLine> {
+    let highlighted = highlighter.and_then(|highlighter| highlighter.lines(&block.join("\n"), language));
+    match highlighted {
+        Some(highlighted) => highlighted
+            .into_iter()
+            .map(|pieces| {
+                let mut line = vec![span("  ", SpanStyle::default())];
+                line.extend(pieces.into_iter().map(|((r, g, b), text)| span(text, SpanStyle::tint(Tint::Rgb(r, g, b)))));
+                line
+            })
+            .collect(),
+        None => block.iter().map(|raw| vec![span(format!("  {}", raw), SpanStyle::tint(Tint::Yellow))]).collect(),
+    }
+}
+
+fn render_line(line: &str, indent: usize) -> StyledLine {
+    let padding = span(" ".repeat(indent), SpanStyle::default());
+
+    if let Some(level) = heading_level(line) {
+        let style = SpanStyle { tint: Some(Tint::Cyan), bold: true, underline: level == 1, ..SpanStyle::default() };
+        return inline(line[level..].trim()).into_iter().map(|s| patched(s, style)).collect();
+    }
+
+    if let Some((id, note)) = footnote_definition(line) {
+        let style = SpanStyle::tint(Tint::Gray);
+        let mut spans = vec![padding, span(format!("[{}] ", id), SpanStyle::tint(Tint::Magenta))];
+        spans.extend(inline(note).into_iter().map(|s| patched(s, style)));
+        return spans;
+    }
+
+    if matches!(line, "***" | "---" | "___") {
+        return vec![span("─".repeat(40), SpanStyle::tint(Tint::DarkGray))];
+    }
+
+    if let Some(quote) = line.strip_prefix('>') {
+        let style = SpanStyle { tint: Some(Tint::Gray), italic: true, ..SpanStyle::default() };
+        let mut spans = vec![span("│ ", SpanStyle::tint(Tint::DarkGray))];
+        spans.extend(inline(quote.trim_start()).into_iter().map(|s| patched(s, style)));
+        return spans;
+    }
+
+    for bullet in ["- ", "* ", "+ "] {
+        if let Some(item) = line.strip_prefix(bullet) {
+            let (marker, item) = if let Some(rest) = item.strip_prefix("[ ] ") {
+                ("☐ ", rest)
+            } else if let Some(rest) = item.strip_prefix("[x] ").or_else(|| item.strip_prefix("[X] ")) {
+                ("☑ ", rest)
+            } else {
+                ("• ", item)
+            };
+
+            let mut spans = vec![padding, span(marker, SpanStyle::tint(Tint::Magenta))];
+            spans.extend(inline(item));
+            return spans;
+        }
+    }
+
+    let mut spans = vec![padding];
+    spans.extend(inline(line));
+    spans
+}
+
+/// Applies a block style underneath the span's own inline style
+fn patched(s: StyledSpan, block: SpanStyle) -> StyledSpan {
+    StyledSpan { text: s.text, style: block.patch(s.style) }
+}
+
+/// The id and text of a footnote definition such as `[^1]: text`
+fn footnote_definition(line: &str) -> Option<(&str, &str)> {
+    let (id, note) = line.strip_prefix("[^")?.split_once("]:")?;
+    footnote_id(id).then(|| (id, note.trim_start()))
+}
+
+fn footnote_id(id: &str) -> bool {
+    !id.is_empty() && !id.contains(char::is_whitespace) && !id.contains(['[', ']'])
+}
+
+fn heading_level(line: &str) -> Option<usize> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    ((1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' '))).then_some(level)
+}
+
+/// Splits a line on `**bold**`, `*italic*`/`_italic_` and `` `code` `` markers,
+/// `[text](links)` and `[^note]` footnote references, resolving emoji
+/// shortcodes outside code. Unterminated markers are kept as literal text,
+/// and images as written.
+fn inline(text: &str) -> Vec<StyledSpan> {
+    let mut spans = Vec::new();
+    let mut plain = String::new();
+    let mut rest = text;
+    let resolved = |text: &str| resolve_shortcodes(text).into_owned();
+
+    while let Some(c) = rest.chars().next() {
+        if let Some(id) = rest.strip_prefix("[^").and_then(|after| after.split_once(']')).map(|(id, _)| id) {
+            if footnote_id(id) {
+                if !plain.is_empty() {
+                    spans.push(span(resolved(&std::mem::take(&mut plain)), SpanStyle::default()));
+                }
+                spans.push(span(format!("[{}]", id), SpanStyle::tint(Tint::Magenta)));
+                rest = &rest[id.len() + 3..];
+                continue;
+            }
+        }
+        if let Some((label, len)) = link(rest).filter(|_| !plain.ends_with('!')) {
+            if !plain.is_empty() {
+                spans.push(span(resolved(&std::mem::take(&mut plain)), SpanStyle::default()));
+            }
+            let style = SpanStyle { underline: true, ..SpanStyle::default() };
+            spans.extend(inline(label).into_iter().map(|s| patched(s, style)));
+            rest = &rest[len..];
+            continue;
+        }
+        let (marker, style) = match c {
+            '`' => ("`", SpanStyle::tint(Tint::Yellow)),
+            '*' if rest.starts_with("**") => ("**", SpanStyle { bold: true, ..SpanStyle::default() }),
+            '_' if plain.ends_with(char::is_alphanumeric) => {
+                // Underscores inside words like snake_case are not emphasis
+                plain.push(c);
+                rest = &rest[1..];
+                continue;
+            }
+            '*' | '_' => (&rest[..1], SpanStyle { italic: true, ..SpanStyle::default() }),
+            _ => {
+                plain.push(c);
+                rest = &rest[c.len_utf8()..];
+                continue;
+            }
+        };
+
+        match rest[marker.len()..].find(marker) {
+            Some(end) if end > 0 => {
+                if !plain.is_empty() {
+                    spans.push(span(resolved(&std::mem::take(&mut plain)), SpanStyle::default()));
+                }
+                let text = &rest[marker.len()..marker.len() + end];
+                spans.push(span(if marker == "`" { text.to_string() } else { resolved(text) }, style));
+                rest = &rest[2 * marker.len() + end..];
+            }
+            _ => {
+                plain.push_str(marker);
+                rest = &rest[marker.len()..];
+            }
+        }
+    }
+
+    if !plain.is_empty() {
+        spans.push(span(resolved(&plain), SpanStyle::default()));
+    }
+    spans
+}
+
+/// The label of a `[label](target)` link `rest` starts with, and the
+/// link's length
+fn link(rest: &str) -> Option<(&str, usize)> {
+    let close = rest.strip_prefix('[')?.find("](")? + 1;
+    let label = &rest[1..close];
+    if label.is_empty() || label.contains('[') {
+        return None;
+    }
+    let end = rest[close + 2..].find(')')?;
+    Some((label, close + 3 + end))
+}
+
+/// Renders to terminal text, with ANSI escape codes (suitable for
+/// `less -R`) when `color` is set; code is only highlighted in color
+pub fn to_terminal(content: &str, color: bool, highlighter: Option<&Highlighter>) -> String {
+    let mut out = String::new();
+    for line in render(content, highlighter.filter(|_| color)) {
+        for s in line {
+            if color {
+                out.push_str(&ansi(&s));
+            } else {
+                out.push_str(&s.text);
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn ansi(s: &StyledSpan) -> String {
+    let mut codes = Vec::new();
+    if s.style.bold {
+        codes.push("1");
+    }
+    if s.style.italic {
+        codes.push("3");
+    }
+    if s.style.underline {
+        codes.push("4");
+    }
+    let rgb;
+    if let Some(tint) = s.style.tint {
+        codes.push(match tint {
+            Tint::Cyan => "36",
+            Tint::Yellow => "33",
+            Tint::Magenta => "35",
+            Tint::Gray => "37",
+            Tint::DarkGray => "90",
+            Tint::Rgb(r, g, b) => {
+                rgb = format!("38;2;{};{};{}", r, g, b);
+                &rgb
+            }
+        });
+    }
+
+    if codes.is_empty() {
+        s.text.clone()
+    } else {
+        format!("\x1b[{}m{}\x1b[0m", codes.join(";"), s.text)
+    }
+}
+
+/// Renders to styled ratatui text for the terminal UIs
+#[cfg(feature = "tui")]
+pub fn to_text(content: &str, highlighter: Option<&Highlighter>) -> ratatui::text::Text<'static> {
+    use ratatui::style::{Color, Modifier, Style};
+    use ratatui::text::{Line, Span};
+
+    let style = |s: SpanStyle| {
+        let mut style = Style::default();
+        if let Some(tint) = s.tint {
+            style = style.fg(match tint {
+                Tint::Cyan => Color::Cyan,
+                Tint::Yellow => Color::Yellow,
+                Tint::Magenta => Color::Magenta,
+                Tint::Gray => Color::Gray,
+                Tint::DarkGray => Color::DarkGray,
+                Tint::Rgb(r, g, b) => Color::Rgb(r, g, b),
+            });
+        }
+        for (enabled, modifier) in [
+            (s.bold, Modifier::BOLD),
+            (s.italic, Modifier::ITALIC),
+            (s.underline, Modifier::UNDERLINED),
+        ] {
+            if enabled {
+                style = style.add_modifier(modifier);
+            }
+        }
+        style
+    };
+
+    render(content, highlighter)
+        .into_iter()
+        .map(|line| Line::from(line.into_iter().map(|s| Span::styled(s.text, style(s.style))).collect::<Vec<_>>()))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(line: &StyledLine) -> String {
+        line.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_renders_block_elements() {
+        let lines = render("# Monday\n- [x] run\n- tea\n> quoted\n***\n```\nfn main() {}\n```\n#hashtag", None);
+
+        assert_eq!(lines.iter().map(plain).collect::<Vec<_>>(), vec![
+            "Monday",
+            "☑ run",
+            "• tea",
+            "│ quoted",
+            &"─".repeat(40),
+            "  fn main() {}",
+            "#hashtag",
+        ]);
+        assert!(lines[0][0].style.bold && lines[0][0].style.underline);
+    }
+
+    #[test]
+    fn test_inline_emphasis() {
+        let spans = inline("a **bold** and *it* with `code`, 2 * 3 snake_case_name");
+        let contents: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+
+        assert_eq!(contents, vec!["a ", "bold", " and ", "it", " with ", "code", ", 2 * 3 snake_case_name"]);
+        assert!(spans[1].style.bold);
+        assert!(spans[3].style.italic);
+    }
+
+    #[test]
+    fn test_shortcodes_and_footnotes() {
+        let spans = inline("tea :coffee: **:tada:** `:coffee:`[^1] :nope:");
+        let contents: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+
+        assert_eq!(contents, vec!["tea ☕ ", "🎉", " ", ":coffee:", "[1]", " :nope:"]);
+        assert_eq!(spans[4].style.tint, Some(Tint::Magenta));
+
+        let lines = render("[^1]: brewed *twice*", None);
+        assert_eq!(plain(&lines[0]), "[1] brewed twice");
+        assert_eq!(lines[0].last().unwrap().style, SpanStyle { tint: Some(Tint::Gray), italic: true, ..SpanStyle::default() });
+    }
+
+    #[test]
+    fn test_links() {
+        let spans = inline("see [**books**](Reading%20List.md), ![cat](cat.png) and [x] [y]");
+        let contents: Vec<&str> = spans.iter().map(|s| s.text.as_str()).collect();
+
+        assert_eq!(contents, vec!["see ", "books", ", ![cat](cat.png) and [x] [y]"]);
+        assert!(spans[1].style.underline && spans[1].style.bold);
+    }
+
+    #[test]
+    fn test_tables() {
+        let lines = render("| Day | Mood |\n|-----|:----:|\n| Mon | :smile: |\n| Tuesday | ok |\nafter\n| lone |", None);
+
+        assert_eq!(lines.iter().map(plain).collect::<Vec<_>>(), vec![
+            "│ Day     │ Mood │",
+            "├─────────┼──────┤",
+            "│ Mon     │ 😄   │",
+            "│ Tuesday │ ok   │",
+            "after",
+            "| lone |",
+        ]);
+        assert!(lines[0][1].style.bold);
+        assert!(!lines[2][1].style.bold);
+    }
+
+    #[cfg(feature = "highlight")]
+    #[test]
+    fn test_highlighted_code() {
+        let highlighter = Highlighter::new(rusty_diary::diary::highlight::DEFAULT_TERMINAL_THEME).unwrap();
+        let lines = render("```rust\nlet x = 1;\n```\n```\nplain\n```", Some(&highlighter));
+
+        assert_eq!(lines.iter().map(plain).collect::<Vec<_>>(), vec!["  let x = 1;", "  plain"]);
+        assert!(lines[0].iter().skip(1).all(|s| matches!(s.style.tint, Some(Tint::Rgb(..)))));
+        assert_eq!(lines[1][0].style.tint, Some(Tint::Yellow));
+        assert!(to_terminal("```rust\nlet x = 1;\n```", true, Some(&highlighter)).contains("\x1b[38;2;"));
+    }
+
+    #[test]
+    fn test_terminal_output() {
+        let content = "## Plan\nplain *now*";
+
+        assert_eq!(to_terminal(content, true, None), "\x1b[1;36mPlan\x1b[0m\nplain \x1b[3mnow\x1b[0m\n");
+        assert_eq!(to_terminal(content, false, None), "Plan\nplain now\n");
+    }
+}
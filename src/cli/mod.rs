@@ -0,0 +1,11 @@
+//! Terminal front-ends used by the `rusty_diary` binary
+
+#[cfg(feature = "tui")]
+pub mod browse;
+pub mod editor;
+#[cfg(feature = "tui")]
+pub mod find;
+pub mod graph;
+pub mod markdown;
+pub mod search;
+pub mod show;
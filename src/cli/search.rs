@@ -0,0 +1,58 @@
+use rusty_diary::storage::SearchHit;
+
+/// Formats search hits for the terminal: a line per entry, then its
+/// snippets with the match highlighted. Hits without snippets quote their
+/// first line instead, and ranked hits show their score.
+pub fn format_hits(hits: &[SearchHit], color: bool) -> String {
+    let mut out = String::new();
+
+    for hit in hits {
+        let header = match hit.score {
+            Some(score) => format!("{} (v{}, {} matching lines, score {:.2})", hit.date, hit.exec_version, hit.matches, score),
+            None => format!("{} (v{}, {} matching lines)", hit.date, hit.exec_version, hit.matches),
+        };
+        if color {
+            out.push_str(&format!("\x1b[1;35m{}\x1b[0m\n", header));
+        } else {
+            out.push_str(&format!("{}\n", header));
+        }
+
+        if hit.snippets.is_empty() {
+            out.push_str(&format!("    {}\n", hit.line));
+        }
+        for snippet in &hit.snippets {
+            let matched = if color {
+                format!("\x1b[1;33m{}\x1b[0m", snippet.matched)
+            } else {
+                format!("[{}]", snippet.matched)
+            };
+            out.push_str(&format!("    …{}{}{}…\n", snippet.before, matched, snippet.after));
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_diary::clock::{Clock, SystemClock};
+    use chrono::NaiveDate;
+    use rusty_diary::storage::DiaryEntry;
+
+    #[test]
+    fn test_format_hits() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let entry = DiaryEntry::new(2, date, "Cold.\nWalked in the rain to work".to_string(), SystemClock.now());
+        let hit = entry.search("rain", 2).unwrap();
+
+        assert_eq!(
+            format_hits(std::slice::from_ref(&hit), false),
+            "2024-03-01 (v2, 1 matching lines)\n    …in the [rain] to work…\n"
+        );
+        assert!(format_hits(std::slice::from_ref(&hit), true).contains("the \x1b[1;33mrain\x1b[0m to"));
+
+        let ranked = SearchHit { score: Some(1.5), ..hit };
+        assert!(format_hits(&[ranked], false).starts_with("2024-03-01 (v2, 1 matching lines, score 1.50)\n"));
+    }
+}
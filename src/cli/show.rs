@@ -0,0 +1,116 @@
+use rusty_diary::diary::flavor::MarkdownFlavor;
+use rusty_diary::diary::highlight::Highlighter;
+use rusty_diary::diary::related::RelatedEntry;
+use rusty_diary::storage::DiaryEntry;
+
+use super::markdown;
+
+/// Formats stored versions of an entry for the terminal, in the given order.
+/// `raw` keeps the markdown unchanged; versions are then separated by HTML
+/// comments so the output is still valid markdown. Code blocks are colored
+/// by `highlighter` along with the rest when `color` is set. Other content
+/// is read as written in `flavor`.
+pub fn format_entries(
+    entries: &[DiaryEntry],
+    raw: bool,
+    color: bool,
+    highlighter: Option<&Highlighter>,
+    flavor: MarkdownFlavor,
+) -> String {
+    let mut out = String::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        if raw {
+            if entries.len() > 1 {
+                out.push_str(&format!("<!-- {} v{} -->\n", entry.date, entry.exec_version));
+            }
+            out.push_str(&entry.content);
+            out.push('\n');
+            continue;
+        }
+
+        if i > 0 {
+            out.push('\n');
+        }
+        let header = format!(
+            "{} · v{} · {} words",
+            entry.date.format("%A, %B %-d %Y"),
+            entry.exec_version,
+            entry.word_count()
+        );
+        if color {
+            out.push_str(&format!("\x1b[1;35m{}\x1b[0m\n\n", header));
+        } else {
+            out.push_str(&format!("{}\n\n", header));
+        }
+        out.push_str(&markdown::to_terminal(&flavor.to_commonmark(&entry.content), color, highlighter));
+    }
+
+    out
+}
+
+/// Lists related days under a heading, each with why it is related.
+/// Empty when there are none.
+pub fn format_related(related: &[RelatedEntry], color: bool) -> String {
+    if related.is_empty() {
+        return String::new();
+    }
+
+    let mut out = if color { "\n\x1b[1;35mRelated\x1b[0m\n".to_string() } else { "\nRelated\n".to_string() };
+    for entry in related {
+        out.push_str(&format!("  {}  {}\n", entry.date, related_reasons(entry)));
+    }
+    out
+}
+
+/// Why a day is related, e.g. `#rain, linked, 10% similar`
+pub fn related_reasons(entry: &RelatedEntry) -> String {
+    let mut reasons: Vec<String> = entry.shared_tags.iter().map(|tag| format!("#{}", tag)).collect();
+    if entry.linked {
+        reasons.push("linked".to_string());
+    }
+    if entry.similarity > 0.0 {
+        reasons.push(format!("{:.0}% similar", entry.similarity * 100.0));
+    }
+    reasons.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusty_diary::clock::{Clock, SystemClock};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_format_entries() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let entries = vec![
+            DiaryEntry::new(2, date, "# Rain\n**wet**".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, date, "Draft".to_string(), SystemClock.now()),
+        ];
+
+        assert_eq!(
+            format_entries(&entries[..1], false, false, None, MarkdownFlavor::CommonMark),
+            "Friday, March 1 2024 · v2 · 3 words\n\nRain\nwet\n"
+        );
+        assert_eq!(format_entries(&entries[..1], true, false, None, MarkdownFlavor::CommonMark), "# Rain\n**wet**\n");
+        assert_eq!(
+            format_entries(&entries, true, false, None, MarkdownFlavor::CommonMark),
+            "<!-- 2024-03-01 v2 -->\n# Rain\n**wet**\n<!-- 2024-03-01 v1 -->\nDraft\n"
+        );
+    }
+
+    #[test]
+    fn test_format_related() {
+        let related = vec![RelatedEntry {
+            date: NaiveDate::from_ymd_opt(2024, 2, 9).unwrap(),
+            score: 1.6,
+            shared_tags: vec!["rain".to_string()],
+            linked: true,
+            similarity: 0.1,
+        }];
+
+        assert_eq!(format_related(&related, false), "\nRelated\n  2024-02-09  #rain, linked, 10% similar\n");
+        assert_eq!(format_related(&[], false), "");
+    }
+}
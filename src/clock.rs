@@ -0,0 +1,37 @@
+//! Where the current time comes from. Entries, sync runs, backups and
+//! journal names are stamped with the time of a `Clock`; with a
+//! `FixedClock` the same input gives the same output every time, for tests
+//! and for replaying a run that went wrong.
+
+use std::fmt::Debug;
+
+use chrono::{NaiveDate, NaiveDateTime};
+
+/// The current local time
+pub trait Clock: Send + Sync + Debug {
+    fn now(&self) -> NaiveDateTime;
+
+    fn today(&self) -> NaiveDate {
+        self.now().date()
+    }
+}
+
+/// This machine's clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        chrono::Local::now().naive_local()
+    }
+}
+
+/// A clock stopped at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub NaiveDateTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> NaiveDateTime {
+        self.0
+    }
+}
@@ -1,11 +1,93 @@
-use std::path::PathBuf;
+use std::path::{self, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::clock::{Clock, SystemClock};
+use crate::diary::file::PathDates;
+use crate::diary::filename::{FilenameTemplate, FilenameValues, DEFAULT_FILENAME};
+use crate::diary::flavor::MarkdownFlavor;
+use crate::diary::portable::file_name_problem;
+use crate::diary::processor::Normalization;
+use crate::diary::split::JournalSplit;
+use crate::diary::template::JournalTemplate;
+use crate::error::{Result, RustyDiaryError};
+use crate::hooks::{Hook, HookEvent, Hooks};
+use crate::storage::models::content_hash;
+use crate::storage::{SearchSettings, SqliteSettings};
 
 #[derive(Clone, Debug)]
 pub struct Config {
     pub directory: PathBuf,
     pub date_pattern: String,
+    /// Folder levels searched for diary files; 1 is `directory` only
+    pub max_depth: usize,
+    /// How year/month folders relate to file dates in nested layouts
+    pub path_dates: PathDates,
+    /// Files modified more recently are left for a later run
+    pub min_file_age: Duration,
+    /// Gitignore-style patterns skipped during collection, on top of `.diaryignore`
+    pub ignore_patterns: Vec<String>,
+    /// Text normalization applied to entries as they are read
+    pub normalization: Normalization,
+    /// Read files with invalid UTF-8 by replacing the bad bytes, keeping
+    /// the files, instead of skipping them
+    pub lossy_utf8: bool,
+    /// Split files into one entry per date heading (`## 2024-03-04`)
+    pub split_date_headings: bool,
+    /// Attach images dropped in the diary directory to their day's entry
+    pub photo_journal: bool,
+    /// Store suggested tags at least this confident (0 to 1) for new entries
+    pub auto_tag: Option<f64>,
+    /// Author credited with synced entries whose frontmatter names none
+    pub author: Option<String>,
+    /// Place new entries are stamped with (needs the `stamp` feature)
+    pub location: Option<String>,
+    /// Repositories whose commits of the day are stored with new entries
+    pub git_repos: Vec<PathBuf>,
+    /// Habits tracked from checklist items such as `- [x] meditate`
+    pub habits: Vec<String>,
+    /// Named queries as (name, query), on top of `.diarysearches`
+    pub saved_searches: Vec<(String, String)>,
     pub output_file_prefix: String,
+    /// Journal file name template; see `FilenameTemplate`
+    pub output_filename: String,
     pub db_path: PathBuf,
+    /// Number of query results kept in the in-process read cache (0 disables it)
+    pub read_cache_size: usize,
+    /// Store entry content zstd-compressed
+    pub compress_content: bool,
+    /// Open the database read-only; see `StorageManager::open_read_only`
+    pub read_only: bool,
+    /// Use an empty database in memory instead of the one at `db_path`,
+    /// e.g. to preview a sync before any database exists
+    pub in_memory: bool,
+    /// SQLite PRAGMA tuning; see `SqliteSettings::profile`
+    pub sqlite: SqliteSettings,
+    /// Stemming, diacritic folding and stopwords for text search
+    pub search: SearchSettings,
+    /// Theme for code blocks in HTML exports and `show` (needs the
+    /// `highlight` feature); each has its own default
+    pub highlight_theme: Option<String>,
+    /// Markdown dialect entries are written in, turned into CommonMark
+    /// when they are shown or exported
+    pub markdown_flavor: MarkdownFlavor,
+    /// Commands and callbacks fired around syncing and journal writing
+    pub hooks: Hooks,
+    /// Directory scanned for parser/exporter plugins (needs the `plugins` feature)
+    pub plugin_dir: Option<PathBuf>,
+    /// Layout of written journal files
+    pub journal_template: JournalTemplate,
+    /// Write one journal per month or year instead of a new file per run
+    pub journal_split: JournalSplit,
+    /// Keep this one journal, relative to `directory`, updated in place
+    /// instead of writing a new file per run
+    pub journal_file: Option<PathBuf>,
+    /// Time entries, runs and backups are stamped with; the system clock
+    /// unless a run is replayed at a fixed moment
+    pub clock: Arc<dyn Clock>,
 }
 
 impl Default for Config {
@@ -13,8 +95,37 @@ impl Default for Config {
         Self {
             directory: PathBuf::from("."),
             date_pattern: String::from(r"^(\d{4}-\d{2}-\d{2})(\.md)?$"),
+            max_depth: 1,
+            path_dates: PathDates::Ignore,
+            min_file_age: Duration::ZERO,
+            ignore_patterns: Vec::new(),
+            normalization: Normalization::Lf,
+            lossy_utf8: false,
+            split_date_headings: false,
+            photo_journal: false,
+            auto_tag: None,
+            author: None,
+            location: None,
+            git_repos: Vec::new(),
+            habits: Vec::new(),
+            saved_searches: Vec::new(),
             db_path: PathBuf::from("rusty-diary.db"),
             output_file_prefix: String::from("rusty-diary-log"),
+            output_filename: String::from(DEFAULT_FILENAME),
+            read_cache_size: 0,
+            compress_content: true,
+            read_only: false,
+            in_memory: false,
+            sqlite: SqliteSettings::default(),
+            search: SearchSettings::default(),
+            highlight_theme: None,
+            markdown_flavor: MarkdownFlavor::CommonMark,
+            hooks: Hooks::default(),
+            plugin_dir: None,
+            journal_template: JournalTemplate::default(),
+            journal_split: JournalSplit::None,
+            journal_file: None,
+            clock: Arc::new(SystemClock),
         }
     }
 }
@@ -24,6 +135,107 @@ impl Config {
         Self::default()
     }
 
+    /// Checks the settings that otherwise only fail deep inside a sync:
+    /// the date pattern, the paths and the journal prefix. Every problem
+    /// found is reported together, each with what to change.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+
+        let date_pattern = match Regex::new(&self.date_pattern) {
+            Err(e) => {
+                problems.push(format!(
+                    "date pattern '{}' is not a valid regular expression: {}",
+                    self.date_pattern,
+                    e.to_string().lines().last().unwrap_or_default().trim()
+                ));
+                None
+            }
+            Ok(regex) if regex.captures_len() < 2 => {
+                problems.push(format!(
+                    "date pattern '{}' has no capture group (wrap the date in parentheses, e.g. ^(\\d{{4}}-\\d{{2}}-\\d{{2}})\\.md$)",
+                    self.date_pattern
+                ));
+                None
+            }
+            Ok(regex) => Some(regex),
+        };
+
+        match FilenameTemplate::parse(&self.output_filename) {
+            Err(e) => problems.push(e.to_string()),
+            Ok(template) => {
+                if let Some(date_pattern) = &date_pattern {
+                    if self.names_journals_like_diary_files(&template, date_pattern) {
+                        problems.push(format!(
+                            "output filename '{}' names journals like diary files, which the next sync \
+                             would read back (start the file name with {{prefix}}, e.g. {{prefix}}_{{date}}.md)",
+                            self.output_filename
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut paths = vec![("directory", self.directory.as_path()), ("database path", self.db_path.as_path())];
+        paths.extend(self.plugin_dir.as_deref().map(|dir| ("plugin directory", dir)));
+        paths.extend(self.journal_file.as_deref().map(|file| ("journal file", file)));
+        for (name, path) in paths {
+            if let Err(e) = path::absolute(path) {
+                problems.push(format!("{} '{}' cannot be resolved: {}", name, path.display(), e));
+            }
+        }
+
+        if let Some(problem) = file_name_problem(&self.output_file_prefix) {
+            problems.push(format!(
+                "output prefix '{}' {} (pick a plain file name such as rusty-diary-log)",
+                self.output_file_prefix, problem
+            ));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(RustyDiaryError::InvalidConfig(problems.join("; ")))
+        }
+    }
+
+    /// Whether journals named after `template` could be taken for diary
+    /// files. Journals starting with the output prefix are never collected,
+    /// so only names without it are checked against the date pattern.
+    fn names_journals_like_diary_files(&self, template: &FilenameTemplate, date_pattern: &Regex) -> bool {
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let sample = template.render(&FilenameValues {
+            prefix: &self.output_file_prefix,
+            today: day,
+            exec_version: 1,
+            start: day,
+            end: day,
+        });
+        let name = |part: Option<&std::ffi::OsStr>| part.map(|part| part.to_string_lossy().into_owned());
+
+        let Some(file_name) = name(sample.file_name()) else { return false };
+        if !self.output_file_prefix.is_empty() && file_name.starts_with(&self.output_file_prefix) {
+            return false;
+        }
+        date_pattern.is_match(&file_name) || name(sample.file_stem()).is_some_and(|stem| date_pattern.is_match(&stem))
+    }
+
+    /// Hex SHA-256 of the settings that decide what a sync stores: the
+    /// date pattern, layout, normalization and the like, but not where the
+    /// diary or database live. Backups record it to tell whether they are
+    /// restored under the setup they were taken with.
+    pub fn fingerprint(&self) -> String {
+        let settings = format!(
+            "{:?}",
+            (
+                (&self.date_pattern, self.max_depth, self.path_dates, &self.ignore_patterns),
+                (self.normalization, self.lossy_utf8, self.split_date_headings, self.photo_journal),
+                (&self.author, &self.habits, self.compress_content, self.markdown_flavor),
+                (&self.output_file_prefix, &self.output_filename, self.journal_split, &self.journal_file),
+            )
+        );
+        content_hash(&settings)
+    }
+
     pub fn with_directory<P: Into<PathBuf>>(mut self, path: P) -> Self {
         self.directory = path.into();
         self
@@ -39,8 +251,193 @@ impl Config {
         self
     }
 
+    pub fn with_layout(mut self, max_depth: usize, path_dates: PathDates) -> Self {
+        self.max_depth = max_depth;
+        self.path_dates = path_dates;
+        self
+    }
+
+    pub fn with_min_file_age(mut self, age: Duration) -> Self {
+        self.min_file_age = age;
+        self
+    }
+
+    pub fn with_ignore_pattern(mut self, pattern: &str) -> Self {
+        self.ignore_patterns.push(pattern.to_string());
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn with_lossy_utf8(mut self, enabled: bool) -> Self {
+        self.lossy_utf8 = enabled;
+        self
+    }
+
+    pub fn with_date_heading_split(mut self, enabled: bool) -> Self {
+        self.split_date_headings = enabled;
+        self
+    }
+
+    pub fn with_photo_journal(mut self, enabled: bool) -> Self {
+        self.photo_journal = enabled;
+        self
+    }
+
+    pub fn with_auto_tag(mut self, threshold: f64) -> Self {
+        self.auto_tag = Some(threshold);
+        self
+    }
+
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    pub fn with_location(mut self, location: &str) -> Self {
+        self.location = Some(location.to_string());
+        self
+    }
+
+    pub fn with_git_repo<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.git_repos.push(path.into());
+        self
+    }
+
+    pub fn with_habit(mut self, habit: &str) -> Self {
+        self.habits.push(habit.to_string());
+        self
+    }
+
+    pub fn with_saved_search(mut self, name: &str, query: &str) -> Self {
+        self.saved_searches.push((name.to_string(), query.to_string()));
+        self
+    }
+
     pub fn with_output_file_prefix(mut self, name: &str) -> Self {
         self.output_file_prefix = name.to_string();
         self
     }
+
+    pub fn with_output_filename(mut self, template: &str) -> Self {
+        self.output_filename = template.to_string();
+        self
+    }
+
+    pub fn with_read_cache(mut self, size: usize) -> Self {
+        self.read_cache_size = size;
+        self
+    }
+
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.compress_content = enabled;
+        self
+    }
+
+    pub fn with_read_only(mut self, enabled: bool) -> Self {
+        self.read_only = enabled;
+        self
+    }
+
+    pub fn with_in_memory(mut self, enabled: bool) -> Self {
+        self.in_memory = enabled;
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_sqlite_settings(mut self, settings: SqliteSettings) -> Self {
+        self.sqlite = settings;
+        self
+    }
+
+    pub fn with_search_settings(mut self, settings: SearchSettings) -> Self {
+        self.search = settings;
+        self
+    }
+
+    pub fn with_highlight_theme(mut self, theme: &str) -> Self {
+        self.highlight_theme = Some(theme.to_string());
+        self
+    }
+
+    pub fn with_markdown_flavor(mut self, flavor: MarkdownFlavor) -> Self {
+        self.markdown_flavor = flavor;
+        self
+    }
+
+    pub fn with_plugin_dir<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.plugin_dir = Some(path.into());
+        self
+    }
+
+    pub fn with_journal_template(mut self, template: JournalTemplate) -> Self {
+        self.journal_template = template;
+        self
+    }
+
+    pub fn with_journal_split(mut self, split: JournalSplit) -> Self {
+        self.journal_split = split;
+        self
+    }
+
+    pub fn with_journal_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.journal_file = Some(path.into());
+        self
+    }
+
+    pub fn with_hook(mut self, event: HookEvent, hook: Hook) -> Self {
+        self.hooks.register(event, hook);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(config: &Config) -> String {
+        match config.validate() {
+            Err(RustyDiaryError::InvalidConfig(problems)) => problems,
+            other => panic!("expected an invalid configuration, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(Config::default().validate().is_ok());
+
+        let config = Config::new().with_date_pattern(r"^\d{4}-\d{2}-\d{2}\.md$");
+        assert!(problems(&config).contains("has no capture group"));
+
+        let config = Config::new().with_date_pattern(r"^(\d{4}");
+        assert!(problems(&config).contains("is not a valid regular expression"));
+
+        let config = Config::new().with_db("");
+        assert!(problems(&config).contains("database path '' cannot be resolved"));
+
+        for prefix in ["", "..", "logs/diary", "diary:log", "diary\tlog", "nul"] {
+            let config = Config { output_file_prefix: prefix.to_string(), ..Config::default() };
+            assert!(problems(&config).starts_with("output prefix"), "{:?}", prefix);
+        }
+
+        // Journals named like diary files would be read back on the next sync
+        for template in ["{date}.md", "{year}/{start}.md", "journal-{date}"] {
+            let config = Config::new().with_date_pattern(r"(\d{4}-\d{2}-\d{2})").with_output_filename(template);
+            assert!(problems(&config).contains("names journals like diary files"), "{}", template);
+        }
+        let loose = Config::new().with_date_pattern(r"(\d{4}-\d{2}-\d{2})");
+        assert!(loose.validate().is_ok());
+        assert!(problems(&Config::new().with_output_filename("{week}.md")).contains("unknown placeholder"));
+
+        // Every problem is reported at once
+        let config = Config { output_file_prefix: String::new(), ..Config::new().with_date_pattern("(").with_directory("") };
+        assert_eq!(problems(&config).split("; ").count(), 3);
+    }
 }
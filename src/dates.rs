@@ -0,0 +1,287 @@
+//! Human date expressions, accepted wherever a date is taken:
+//!
+//! - `2024-03-01`, `today`, `yesterday`, `tomorrow`
+//! - `3 days ago`, `2 weeks ago`, `1 month ago`, `1 year ago`
+//! - `monday` (the latest one, today included), `last monday`, `next friday`,
+//!   `this monday` (in the current week)
+//! - `last week`, `this month`, `next quarter`, `last year`
+//! - `march` (the latest one), `march 2023`, `2023`, `2023-03`, `2023-Q2`,
+//!   `2024-W12` (ISO week)
+//!
+//! Expressions name a period; a range starts at the first day of its `from`
+//! and ends at the last day of its `to`, so `--from "last month"` covers the
+//! whole of last month.
+//!
+//! `DateRange` gives the boundaries of calendar periods to API callers.
+
+use chrono::{Datelike, Duration, Month, Months, NaiveDate, Weekday};
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Result, RustyDiaryError};
+
+/// An inclusive range of days
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+pub struct DateRange {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl DateRange {
+    pub fn new(start: NaiveDate, end: NaiveDate) -> Self {
+        Self { start, end }
+    }
+
+    /// Every representable day
+    pub fn all() -> Self {
+        Self::new(NaiveDate::MIN, NaiveDate::MAX)
+    }
+
+    pub fn day(date: NaiveDate) -> Self {
+        Self::new(date, date)
+    }
+
+    /// Monday to Sunday of an ISO 8601 week, e.g. `iso_week(2024, 12)`.
+    /// `None` when the year has no such week.
+    pub fn iso_week(year: i32, week: u32) -> Option<Self> {
+        let start = NaiveDate::from_isoywd_opt(year, week, Weekday::Mon)?;
+        Some(Self::new(start, start + Duration::days(6)))
+    }
+
+    /// A calendar month, 1 to 12
+    pub fn month(year: i32, month: u32) -> Option<Self> {
+        let start = NaiveDate::from_ymd_opt(year, month, 1)?;
+        let end = match start.checked_add_months(Months::new(1)) {
+            Some(next) => next.pred_opt()?,
+            None => NaiveDate::MAX,
+        };
+        Some(Self::new(start, end))
+    }
+
+    /// A calendar quarter, 1 to 4
+    pub fn quarter(year: i32, quarter: u32) -> Option<Self> {
+        if !(1..=4).contains(&quarter) {
+            return None;
+        }
+        Some(Self::new(Self::month(year, quarter * 3 - 2)?.start, Self::month(year, quarter * 3)?.end))
+    }
+
+    pub fn year(year: i32) -> Option<Self> {
+        Some(Self::new(NaiveDate::from_ymd_opt(year, 1, 1)?, NaiveDate::from_ymd_opt(year, 12, 31)?))
+    }
+
+    /// The ISO week containing `date`
+    pub fn week_of(date: NaiveDate) -> Self {
+        let start = date - Duration::days(date.weekday().num_days_from_monday().into());
+        Self::new(start, start + Duration::days(6))
+    }
+
+    /// The month containing `date`
+    pub fn month_of(date: NaiveDate) -> Self {
+        Self::month(date.year(), date.month()).expect("a date's own month is valid")
+    }
+
+    /// The year containing `date`
+    pub fn year_of(date: NaiveDate) -> Self {
+        Self::year(date.year()).expect("a date's own year is valid")
+    }
+
+    pub fn contains(&self, date: NaiveDate) -> bool {
+        self.start <= date && date <= self.end
+    }
+
+    /// Number of days in the range, both ends included
+    pub fn days(&self) -> i64 {
+        (self.end - self.start).num_days() + 1
+    }
+}
+
+/// First day of the period the expression names, relative to `today`
+pub fn parse_start(expression: &str, today: NaiveDate) -> Result<NaiveDate> {
+    resolve(expression, today).map(|range| range.start)
+}
+
+/// Last day of the period the expression names, relative to `today`
+pub fn parse_end(expression: &str, today: NaiveDate) -> Result<NaiveDate> {
+    resolve(expression, today).map(|range| range.end)
+}
+
+/// The period the expression names, relative to `today`
+pub fn resolve(expression: &str, today: NaiveDate) -> Result<DateRange> {
+    let text = expression.trim().to_lowercase();
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let day = DateRange::day;
+
+    if let Ok(date) = NaiveDate::parse_from_str(&text, "%Y-%m-%d") {
+        return Ok(day(date));
+    }
+
+    let resolved = match words.as_slice() {
+        ["today"] => Some(day(today)),
+        ["yesterday"] => today.pred_opt().map(day),
+        ["tomorrow"] => today.succ_opt().map(day),
+        [count, unit, "ago"] => count.parse().ok().and_then(|count| ago(today, count, unit)).map(day),
+        [which @ ("last" | "this" | "next"), unit] => {
+            let shift = match *which {
+                "last" => -1,
+                "this" => 0,
+                _ => 1,
+            };
+            relative(today, shift, unit)
+        }
+        [name] => match (name.parse::<Weekday>(), name.parse::<Month>()) {
+            (Ok(weekday), _) => Some(day(latest_weekday(today, weekday))),
+            (_, Ok(month)) => {
+                let month = month.number_from_month();
+                let year = if month <= today.month() { today.year() } else { today.year() - 1 };
+                DateRange::month(year, month)
+            }
+            _ => numeric_period(name),
+        },
+        [name, year] => {
+            let month = name.parse::<Month>().ok();
+            month.zip(year.parse().ok()).and_then(|(month, year)| DateRange::month(year, month.number_from_month()))
+        }
+        _ => None,
+    };
+
+    resolved.ok_or_else(|| {
+        RustyDiaryError::InvalidConfig(format!(
+            "Unrecognized date '{}'; use YYYY-MM-DD or an expression such as 'last monday', \
+             '2 weeks ago', 'march' or '2023-Q2'",
+            expression
+        ))
+    })
+}
+
+/// `count` days, weeks, months or years before `today`
+fn ago(today: NaiveDate, count: u32, unit: &str) -> Option<NaiveDate> {
+    match unit.strip_suffix('s').unwrap_or(unit) {
+        "day" => today.checked_sub_signed(Duration::days(count.into())),
+        "week" => today.checked_sub_signed(Duration::weeks(count.into())),
+        "month" => today.checked_sub_months(Months::new(count)),
+        "year" => today.checked_sub_months(Months::new(count.checked_mul(12)?)),
+        _ => None,
+    }
+}
+
+/// `last`, `this` or `next` (`shift` of -1, 0 or 1) weekday, week, month,
+/// quarter or year
+fn relative(today: NaiveDate, shift: i32, unit: &str) -> Option<DateRange> {
+    let this_week = DateRange::week_of(today);
+
+    if let Ok(weekday) = unit.parse::<Weekday>() {
+        let this = this_week.start + Duration::days(weekday.num_days_from_monday().into());
+        let date = match shift {
+            -1 if this < today => this,
+            -1 => this - Duration::weeks(1),
+            1 if this > today => this,
+            1 => this + Duration::weeks(1),
+            _ => this,
+        };
+        return Some(DateRange::day(date));
+    }
+
+    match unit {
+        "week" => Some(DateRange::week_of(this_week.start + Duration::weeks(shift.into()))),
+        "month" => {
+            let months = today.year() * 12 + today.month0() as i32 + shift;
+            DateRange::month(months.div_euclid(12), months.rem_euclid(12) as u32 + 1)
+        }
+        "quarter" => {
+            let quarters = today.year() * 4 + (today.month0() / 3) as i32 + shift;
+            DateRange::quarter(quarters.div_euclid(4), quarters.rem_euclid(4) as u32 + 1)
+        }
+        "year" => DateRange::year(today.year() + shift),
+        _ => None,
+    }
+}
+
+/// `2023`, `2023-03`, `2023-q2` or `2024-w12`
+fn numeric_period(text: &str) -> Option<DateRange> {
+    let period = Regex::new(r"^(\d{4})(?:-(\d{2})|-q([1-4])|-w(\d{2}))?$").unwrap();
+    let caps = period.captures(text)?;
+    let year = caps[1].parse().ok()?;
+
+    if let Some(month) = caps.get(2) {
+        DateRange::month(year, month.as_str().parse().ok()?)
+    } else if let Some(quarter) = caps.get(3) {
+        DateRange::quarter(year, quarter.as_str().parse().ok()?)
+    } else if let Some(week) = caps.get(4) {
+        DateRange::iso_week(year, week.as_str().parse().ok()?)
+    } else {
+        DateRange::year(year)
+    }
+}
+
+/// The latest `weekday`, today included
+fn latest_weekday(today: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let back = (7 + today.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    today - Duration::days(back.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_expressions() -> Result<()> {
+        // A Wednesday
+        let today = ymd(2024, 3, 13);
+        let day = DateRange::day;
+        let range = |start, end| DateRange::new(start, end);
+
+        assert_eq!(resolve("2024-01-05", today)?, day(ymd(2024, 1, 5)));
+        assert_eq!(resolve(" Yesterday ", today)?, day(ymd(2024, 3, 12)));
+        assert_eq!(resolve("2 weeks ago", today)?, day(ymd(2024, 2, 28)));
+        assert_eq!(resolve("1 month ago", today)?, day(ymd(2024, 2, 13)));
+        assert_eq!(resolve("wednesday", today)?, day(today));
+        assert_eq!(resolve("last monday", today)?, day(ymd(2024, 3, 11)));
+        assert_eq!(resolve("last wed", today)?, day(ymd(2024, 3, 6)));
+        assert_eq!(resolve("next monday", today)?, day(ymd(2024, 3, 18)));
+        assert_eq!(resolve("this friday", today)?, day(ymd(2024, 3, 15)));
+
+        assert_eq!(resolve("last week", today)?, range(ymd(2024, 3, 4), ymd(2024, 3, 10)));
+        assert_eq!(resolve("last month", today)?, range(ymd(2024, 2, 1), ymd(2024, 2, 29)));
+        assert_eq!(resolve("last quarter", today)?, range(ymd(2023, 10, 1), ymd(2023, 12, 31)));
+        assert_eq!(resolve("next year", today)?, range(ymd(2025, 1, 1), ymd(2025, 12, 31)));
+
+        assert_eq!(resolve("march", today)?, range(ymd(2024, 3, 1), ymd(2024, 3, 31)));
+        assert_eq!(resolve("dec", today)?, range(ymd(2023, 12, 1), ymd(2023, 12, 31)));
+        assert_eq!(resolve("March 2023", today)?, range(ymd(2023, 3, 1), ymd(2023, 3, 31)));
+        assert_eq!(resolve("2023", today)?, range(ymd(2023, 1, 1), ymd(2023, 12, 31)));
+        assert_eq!(resolve("2023-02", today)?, range(ymd(2023, 2, 1), ymd(2023, 2, 28)));
+        assert_eq!(resolve("2023-Q2", today)?, range(ymd(2023, 4, 1), ymd(2023, 6, 30)));
+        assert_eq!(resolve("2024-W12", today)?, range(ymd(2024, 3, 18), ymd(2024, 3, 24)));
+
+        for invalid in ["", "someday", "2 fortnights ago", "last decade", "2023-Q5", "2023-13", "2024-02-30"] {
+            assert!(resolve(invalid, today).is_err(), "{} should be rejected", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_date_range_periods() {
+        let range = |start, end| Some(DateRange::new(start, end));
+
+        assert_eq!(DateRange::iso_week(2024, 12), range(ymd(2024, 3, 18), ymd(2024, 3, 24)));
+        // ISO week 1 of 2025 starts in December 2024
+        assert_eq!(DateRange::iso_week(2025, 1), range(ymd(2024, 12, 30), ymd(2025, 1, 5)));
+        assert_eq!(DateRange::iso_week(2024, 53), None);
+        assert_eq!(DateRange::month(2023, 2), range(ymd(2023, 2, 1), ymd(2023, 2, 28)));
+        assert_eq!(DateRange::month(2023, 13), None);
+        assert_eq!(DateRange::quarter(2024, 4), range(ymd(2024, 10, 1), ymd(2024, 12, 31)));
+        assert_eq!(DateRange::quarter(2024, 0), None);
+        assert_eq!(DateRange::year(2024).map(|year| year.days()), Some(366));
+
+        let week = DateRange::week_of(ymd(2024, 3, 13));
+        assert_eq!(week, DateRange::iso_week(2024, 11).unwrap());
+        assert!(week.contains(ymd(2024, 3, 17)) && !week.contains(ymd(2024, 3, 18)));
+        assert_eq!(DateRange::month_of(ymd(2024, 2, 10)).end, ymd(2024, 2, 29));
+        assert_eq!(DateRange::month(NaiveDate::MAX.year(), NaiveDate::MAX.month()).map(|m| m.end), Some(NaiveDate::MAX));
+    }
+}
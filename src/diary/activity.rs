@@ -0,0 +1,150 @@
+//! Work journals that write part of themselves: the commits made on an
+//! entry's day in configured git repositories are stored with the entry as
+//! the `git.commits` and `git.shipped` metadata fields.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use chrono::{Duration, NaiveDate};
+
+use crate::error::{IoContext, Result};
+use crate::storage::models::DiaryEntry;
+
+use super::processor::Enricher;
+
+/// Metadata field holding the number of commits made on the day
+pub const COMMITS_FIELD: &str = "git.commits";
+/// Metadata field listing them, e.g. `rusty-diary: Fix the parser; notes: Add March`
+pub const SHIPPED_FIELD: &str = "git.shipped";
+
+/// Enricher counting the commits authored on an entry's day, by running
+/// `git log` in each repository
+pub struct GitActivity {
+    repos: Vec<PathBuf>,
+    author: Option<String>,
+}
+
+impl GitActivity {
+    pub fn new(repos: Vec<PathBuf>) -> Self {
+        Self { repos, author: None }
+    }
+
+    /// Counts only commits whose author name or email contains `author`.
+    /// By default this is the `user.email` each repository is configured
+    /// with, or everyone when it has none.
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_string());
+        self
+    }
+
+    /// Subjects of the commits authored in `repo` on `date`, oldest first
+    pub fn commits(&self, repo: &Path, date: NaiveDate) -> Result<Vec<String>> {
+        let author = match &self.author {
+            Some(author) => Some(author.clone()),
+            None => Some(git(repo, &["config", "user.email"]).unwrap_or_default()).filter(|email| !email.is_empty()),
+        };
+
+        // Commits are committed no earlier than they are authored, so only
+        // recent ones are read, then matched on their author date
+        let since = format!("--since={}", date - Duration::days(1));
+        let mut args = vec!["log", "--no-merges", "--reverse", "--date=short", "--format=%ad%x09%s", &since];
+        let author_arg = author.map(|author| format!("--author={}", author));
+        if let Some(author_arg) = &author_arg {
+            args.extend(["--fixed-strings", author_arg]);
+        }
+
+        let day = date.to_string();
+        Ok(git(repo, &args)?
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .filter(|(authored, _)| *authored == day)
+            .map(|(_, subject)| subject.to_string())
+            .collect())
+    }
+}
+
+impl Enricher for GitActivity {
+    fn name(&self) -> &str {
+        "git"
+    }
+
+    fn enrich(&self, entry: &DiaryEntry) -> Result<Vec<(String, String)>> {
+        let mut shipped = Vec::new();
+        for repo in &self.repos {
+            let name = repo.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+            shipped.extend(self.commits(repo, entry.date)?.into_iter().map(|subject| format!("{}: {}", name, subject)));
+        }
+
+        if shipped.is_empty() {
+            return Ok(Vec::new());
+        }
+        Ok(vec![
+            (COMMITS_FIELD.to_string(), shipped.len().to_string()),
+            (SHIPPED_FIELD.to_string(), shipped.join("; ")),
+        ])
+    }
+}
+
+/// The trimmed output of a git command run in `repo`
+fn git(repo: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").arg("-C").arg(repo).args(args).output().at_path(repo)?;
+    if !output.status.success() {
+        let reason = format!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+        return Err(std::io::Error::other(reason)).at_path(repo);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    fn commit(repo: &Path, email: &str, date: &str, subject: &str) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo)
+            .args(["-c", &format!("user.email={}", email), "-c", "user.name=Dev", "commit", "-q", "--allow-empty"])
+            .args(["-m", subject])
+            .env("GIT_AUTHOR_DATE", format!("{}T12:00:00+00:00", date))
+            .env("GIT_COMMITTER_DATE", format!("{}T12:00:00+00:00", date))
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    #[test]
+    fn test_commits_of_the_day() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let repo = temp_dir.path().join("website");
+        std::fs::create_dir(&repo)?;
+        git(&repo, &["init", "-q"])?;
+        git(&repo, &["config", "user.email", "me@example.com"])?;
+        commit(&repo, "me@example.com", "2024-03-04", "Start the site");
+        commit(&repo, "me@example.com", "2024-03-05", "Add the about page");
+        commit(&repo, "other@example.com", "2024-03-05", "Fix a typo");
+        commit(&repo, "me@example.com", "2024-03-05", "Publish");
+
+        let activity = GitActivity::new(vec![repo.clone()]);
+        let entry = DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(), "Shipped".to_string(), now);
+        assert_eq!(
+            activity.enrich(&entry)?,
+            vec![
+                ("git.commits".to_string(), "2".to_string()),
+                ("git.shipped".to_string(), "website: Add the about page; website: Publish".to_string()),
+            ]
+        );
+        assert_eq!(activity.with_author("other@").commits(&repo, entry.date)?, vec!["Fix a typo"]);
+
+        let quiet = DiaryEntry { date: NaiveDate::from_ymd_opt(2024, 3, 6).unwrap(), ..entry.clone() };
+        assert!(GitActivity::new(vec![repo]).enrich(&quiet)?.is_empty());
+        assert!(GitActivity::new(vec![temp_dir.path().join("missing")]).enrich(&entry).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,598 @@
+//! Archives for moving a diary to another database without losing
+//! anything; not to be confused with the cold archives of old entries.
+//! `export archive` writes a folder holding every exported version of the
+//! entries as stored, with its author and timestamps, what their days keep
+//! apart from the content (star, metadata fields and stored tags), and the
+//! attachment files they link to. `import archive` restores it into an
+//! empty database.
+//!
+//! The manifest records a digest over the canonical form of all of it, so
+//! an import can check that what it stored is what was exported, and
+//! `verify_roundtrip` exports and imports through a scratch folder to show
+//! what, if anything, does not survive the trip.
+//!
+//! ```text
+//! manifest.json   format, counts, attachment hashes and the digest
+//! entries.jsonl   one entry version per line, oldest day first
+//! days.jsonl      one day per line
+//! files/          the attachments, by their path in the diary folder
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::backup::Scratch;
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::storage::models::{DiaryEntry, StoredTag};
+use crate::storage::{EntryQuery, StorageManager};
+
+use super::export::ExportSink;
+use super::portable::slash_path;
+
+/// Name of the export target and importer
+pub const NAME: &str = "archive";
+
+/// Layout version written; archives in a newer layout are refused
+const FORMAT: u32 = 1;
+
+const MANIFEST: &str = "manifest.json";
+const ENTRIES: &str = "entries.jsonl";
+const DAYS: &str = "days.jsonl";
+const FILES: &str = "files";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    pub format: u32,
+    pub created_at: NaiveDateTime,
+    pub entries: usize,
+    pub days: usize,
+    /// Hash of each attachment's contents, by its path in the diary folder
+    pub attachments: BTreeMap<String, String>,
+    /// Hash over the canonical form of the entries, days and attachments
+    pub digest: String,
+}
+
+/// What a day keeps apart from its entries' content
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedDay {
+    pub date: NaiveDate,
+    pub starred: bool,
+    pub meta: BTreeMap<String, String>,
+    /// Ordered by tag
+    pub tags: Vec<StoredTag>,
+}
+
+/// A line of `entries.jsonl`
+#[derive(Serialize, Deserialize)]
+struct ArchivedEntry {
+    #[serde(flatten)]
+    entry: DiaryEntry,
+    /// Hash of the content, checked when the archive is read
+    hash: String,
+}
+
+/// An entry version as the digest sees it: everything stored with it,
+/// the content by its hash
+#[derive(Serialize)]
+struct CanonicalEntry<'a> {
+    author: Option<&'a str>,
+    created_at: NaiveDateTime,
+    updated_at: Option<NaiveDateTime>,
+    hash: String,
+}
+
+/// An archive as read, checked against its manifest
+#[derive(Debug)]
+pub struct Archive {
+    pub manifest: Manifest,
+    pub entries: Vec<DiaryEntry>,
+    pub days: Vec<ArchivedDay>,
+    root: PathBuf,
+}
+
+/// What an archive holds, after it was written or restored
+#[derive(Debug, Clone, Serialize)]
+pub struct ArchiveReport {
+    pub entries: usize,
+    pub days: usize,
+    pub attachments: usize,
+    pub digest: String,
+}
+
+/// Outcome of exporting entries to an archive and importing it into a
+/// fresh database
+#[derive(Debug, Clone, Serialize)]
+pub struct RoundTrip {
+    pub entries: usize,
+    pub days: usize,
+    pub attachments: usize,
+    pub digest: String,
+    /// Digest of what the import stored
+    pub restored_digest: String,
+    /// What differs after the trip, such as `2024-01-02 v3`, `day
+    /// 2024-01-02` or `attachment attachments/a.png`
+    pub differences: Vec<String>,
+}
+
+impl RoundTrip {
+    pub fn is_identical(&self) -> bool {
+        self.differences.is_empty() && self.digest == self.restored_digest
+    }
+}
+
+/// Whether `path` is an archive folder
+pub fn is_archive(path: &Path) -> bool {
+    path.join(MANIFEST).is_file()
+}
+
+/// The days of the entries, with what they keep apart from the content
+pub fn days(storage: &StorageManager, entries: &[DiaryEntry]) -> Result<Vec<ArchivedDay>> {
+    let dates: BTreeSet<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+    let (Some(first), Some(last)) = (dates.first(), dates.last()) else { return Ok(Vec::new()) };
+    let starred: BTreeSet<NaiveDate> = storage.starred(*first, *last)?.into_iter().collect();
+    dates
+        .into_iter()
+        .map(|date| {
+            let mut tags = storage.stored_tags(date)?;
+            tags.sort_by(|a, b| a.tag.cmp(&b.tag));
+            Ok(ArchivedDay { date, starred: starred.contains(&date), meta: storage.meta(date)?, tags })
+        })
+        .collect()
+}
+
+/// The local files the entries link to that exist, by their path in the
+/// diary folder. Files outside the folder are left out.
+fn attachments(directory: &Path, entries: &[DiaryEntry]) -> BTreeMap<String, PathBuf> {
+    let sink = ExportSink::new(directory).with_directory(directory);
+    entries
+        .iter()
+        .flat_map(|entry| sink.attachments(entry))
+        .filter_map(|path| {
+            let relative = path.strip_prefix(directory).ok()?;
+            relative
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+                .then(|| (slash_path(relative), path.clone()))
+        })
+        .collect()
+}
+
+fn file_hash(path: &Path) -> Result<String> {
+    let bytes = fs::read(path).at_path(path)?;
+    Ok(Sha256::digest(&bytes).iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Each entry version, day and attachment in canonical form, by a name
+/// such as `2024-01-02 v3`, `day 2024-01-02` or `attachment a.png`
+fn canonical(
+    entries: &[DiaryEntry],
+    days: &[ArchivedDay],
+    attachments: &BTreeMap<String, String>,
+) -> Result<BTreeMap<String, String>> {
+    let mut records = BTreeMap::new();
+    for entry in entries {
+        let canonical = CanonicalEntry {
+            author: entry.author.as_deref(),
+            created_at: entry.created_at,
+            updated_at: entry.updated_at,
+            hash: entry.content_hash(),
+        };
+        records.insert(format!("{} v{}", entry.date, entry.exec_version), serde_json::to_string(&canonical)?);
+    }
+    for day in days {
+        records.insert(format!("day {}", day.date), serde_json::to_string(day)?);
+    }
+    for (path, hash) in attachments {
+        records.insert(format!("attachment {}", path), hash.clone());
+    }
+    Ok(records)
+}
+
+fn digest(records: &BTreeMap<String, String>) -> String {
+    let mut hasher = Sha256::new();
+    for (name, record) in records {
+        hasher.update(format!("{}\t{}\n", name, record));
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Writes the entries, their days and attachments into a new archive
+/// folder at `destination`, created at `created_at`. Attachment links are
+/// resolved against `directory`.
+pub fn write(
+    storage: &StorageManager,
+    directory: &Path,
+    entries: &[DiaryEntry],
+    destination: &Path,
+    created_at: NaiveDateTime,
+) -> Result<ArchiveReport> {
+    if destination.is_file() || fs::read_dir(destination).is_ok_and(|mut files| files.next().is_some()) {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "{} already exists; archives are written to a new folder",
+            destination.display()
+        )));
+    }
+    let mut entries = entries.to_vec();
+    entries.sort_by_key(|entry| (entry.date, entry.exec_version));
+    let days = days(storage, &entries)?;
+
+    let mut hashes = BTreeMap::new();
+    for (path, source) in attachments(directory, &entries) {
+        let copy = destination.join(FILES).join(&path);
+        if let Some(parent) = copy.parent() {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        fs::copy(&source, &copy).at_path(&copy)?;
+        hashes.insert(path, file_hash(&copy)?);
+    }
+
+    fs::create_dir_all(destination).at_path(destination)?;
+    write_lines(&destination.join(ENTRIES), entries.iter().map(|entry| ArchivedEntry { entry: entry.clone(), hash: entry.content_hash() }))?;
+    write_lines(&destination.join(DAYS), days.iter())?;
+
+    let digest = digest(&canonical(&entries, &days, &hashes)?);
+    let manifest = Manifest {
+        format: FORMAT,
+        created_at,
+        entries: entries.len(),
+        days: days.len(),
+        attachments: hashes,
+        digest: digest.clone(),
+    };
+    // Written last, so a folder without one is an unfinished archive
+    let path = destination.join(MANIFEST);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?).at_path(&path)?;
+    Ok(ArchiveReport { entries: entries.len(), days: days.len(), attachments: manifest.attachments.len(), digest })
+}
+
+fn write_lines<T: Serialize>(path: &Path, lines: impl Iterator<Item = T>) -> Result<()> {
+    let mut out = BufWriter::new(File::create(path).at_path(path)?);
+    for line in lines {
+        writeln!(out, "{}", serde_json::to_string(&line)?).at_path(path)?;
+    }
+    out.flush().at_path(path)?;
+    Ok(())
+}
+
+fn read_lines<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>> {
+    let reader = BufReader::new(File::open(path).at_path(path)?);
+    let mut lines = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.at_path(path)?;
+        let value = serde_json::from_str(&line).map_err(|e| {
+            RustyDiaryError::ContentIntegrity(format!("{}:{}: {}", path.display(), number + 1, e))
+        })?;
+        lines.push(value);
+    }
+    Ok(lines)
+}
+
+/// Reads an archive folder, checking every entry and attachment against
+/// its hash and the whole against the manifest's digest
+pub fn read(source: &Path) -> Result<Archive> {
+    let path = source.join(MANIFEST);
+    let manifest: Manifest = serde_json::from_str(&fs::read_to_string(&path).at_path(&path)?)?;
+    if manifest.format > FORMAT {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "{} is in archive format {}; this version reads up to {}",
+            source.display(),
+            manifest.format,
+            FORMAT
+        )));
+    }
+
+    let path = source.join(ENTRIES);
+    let archived: Vec<ArchivedEntry> = read_lines(&path)?;
+    let mut entries = Vec::with_capacity(archived.len());
+    for (number, archived) in archived.into_iter().enumerate() {
+        if archived.entry.content_hash() != archived.hash {
+            return Err(RustyDiaryError::ContentIntegrity(format!(
+                "{}:{}: content of {} does not match its hash",
+                path.display(),
+                number + 1,
+                archived.entry.date
+            )));
+        }
+        entries.push(archived.entry);
+    }
+    let days: Vec<ArchivedDay> = read_lines(&source.join(DAYS))?;
+
+    for (path, hash) in &manifest.attachments {
+        let file = source.join(FILES).join(path);
+        if file_hash(&file)? != *hash {
+            return Err(RustyDiaryError::ContentIntegrity(format!("{} does not match its hash", file.display())));
+        }
+    }
+    if entries.len() != manifest.entries || digest(&canonical(&entries, &days, &manifest.attachments)?) != manifest.digest {
+        return Err(RustyDiaryError::ContentIntegrity(format!(
+            "{} does not match the digest of its manifest",
+            source.display()
+        )));
+    }
+    Ok(Archive { manifest, entries, days, root: source.to_path_buf() })
+}
+
+impl Archive {
+    /// Stores the archive's entries and days as they were and copies its
+    /// attachments into `directory`. Attachments already there are kept
+    /// when their contents match.
+    fn load(&self, storage: &StorageManager, directory: &Path) -> Result<()> {
+        let mut copies = Vec::new();
+        for (path, hash) in &self.manifest.attachments {
+            let target = directory.join(path);
+            if !target.exists() {
+                copies.push((self.root.join(FILES).join(path), target));
+            } else if file_hash(&target)? != *hash {
+                return Err(RustyDiaryError::ContentIntegrity(format!(
+                    "{} already exists with other contents",
+                    target.display()
+                )));
+            }
+        }
+        for (source, target) in copies {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).at_path(parent)?;
+            }
+            fs::copy(&source, &target).at_path(&target)?;
+        }
+
+        storage.store_entries(self.entries.clone())?;
+        for day in &self.days {
+            if day.starred {
+                storage.set_starred(day.date, true)?;
+            }
+            for (key, value) in &day.meta {
+                storage.set_meta(day.date, key, value)?;
+            }
+        }
+        let tags: Vec<StoredTag> = self.days.iter().flat_map(|day| day.tags.iter().cloned()).collect();
+        storage.restore_tags(&tags)
+    }
+}
+
+/// The canonical records of what `storage` and `directory` hold of the
+/// entries
+fn stored_records(storage: &StorageManager, directory: &Path, entries: &[DiaryEntry]) -> Result<BTreeMap<String, String>> {
+    let hashes = attachments(directory, entries)
+        .into_iter()
+        .map(|(path, file)| Ok((path, file_hash(&file)?)))
+        .collect::<Result<BTreeMap<_, _>>>()?;
+    canonical(entries, &days(storage, entries)?, &hashes)
+}
+
+/// Restores the archive at `source` into `storage`, which must hold no
+/// entries yet, and its attachments into `directory`. Fails unless what
+/// was stored matches the archive's digest.
+pub fn restore(storage: &StorageManager, directory: &Path, source: &Path) -> Result<ArchiveReport> {
+    let archive = read(source)?;
+    let versions = storage.stats()?.versions;
+    if versions > 0 {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "Archives are imported into an empty database; this one holds {} entry versions",
+            versions
+        )));
+    }
+    archive.load(storage, directory)?;
+
+    let restored = digest(&stored_records(storage, directory, &storage.query(&EntryQuery::new())?)?);
+    if restored != archive.manifest.digest {
+        return Err(RustyDiaryError::ContentIntegrity(format!(
+            "The restored diary does not match the digest of {}",
+            source.display()
+        )));
+    }
+    Ok(ArchiveReport {
+        entries: archive.entries.len(),
+        days: archive.days.len(),
+        attachments: archive.manifest.attachments.len(),
+        digest: restored,
+    })
+}
+
+/// Exports the entries to an archive and imports it into a fresh database
+/// and folder, both in a scratch folder removed afterwards, then compares
+/// what was stored with the original
+pub fn verify_roundtrip(
+    storage: &StorageManager,
+    directory: &Path,
+    entries: &[DiaryEntry],
+    created_at: NaiveDateTime,
+) -> Result<RoundTrip> {
+    let scratch = Scratch::new(&std::env::temp_dir(), "roundtrip")?;
+    let exported = write(storage, directory, entries, &scratch.path().join("archive"), created_at)?;
+    let archive = read(&scratch.path().join("archive"))?;
+
+    let restored = StorageManager::new(scratch.path().join("diary.db"))?;
+    let restored_directory = scratch.path().join("diary");
+    archive.load(&restored, &restored_directory)?;
+
+    let before = stored_records(storage, directory, entries)?;
+    let after = stored_records(&restored, &restored_directory, &restored.query(&EntryQuery::new())?)?;
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    let differences = names.into_iter().filter(|name| before.get(*name) != after.get(*name)).cloned().collect();
+
+    Ok(RoundTrip {
+        entries: exported.entries,
+        days: exported.days,
+        attachments: exported.attachments,
+        digest: digest(&before),
+        restored_digest: digest(&after),
+        differences,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::config::Config;
+    use crate::diary::RustyDiary;
+    use chrono::{Duration, NaiveTime};
+    use proptest::prelude::*;
+    use proptest::sample::{select, Index};
+    use tempfile::TempDir;
+
+    fn diary(root: &Path) -> Result<RustyDiary> {
+        RustyDiary::new(Config::new().with_directory(root.join("diary")).with_db(root.join("diary.db")))
+    }
+
+    fn day(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 3, d).unwrap()
+    }
+
+    #[test]
+    fn test_export_and_import_archive() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let diary = diary(temp_dir.path())?;
+        fs::create_dir_all(diary.directory().join("attachments"))?;
+        fs::write(diary.directory().join("attachments/map.png"), b"png")?;
+        let at = day(1).and_time(NaiveTime::from_hms_nano_opt(9, 30, 0, 123_456_789).unwrap());
+        let entries = vec![
+            DiaryEntry { author: Some("ana".to_string()), updated_at: Some(at), ..DiaryEntry::new(1, day(1), "Walk ![](attachments/map.png)\r\n".to_string(), now) },
+            DiaryEntry { created_at: at, ..DiaryEntry::new(2, day(1), "Walk, edited #hike".to_string(), now) },
+            DiaryEntry::new(2, day(2), "---\ntags: [a]\n---\nKept as stored".to_string(), now),
+        ];
+        diary.storage().store_entries(entries)?;
+        diary.storage().set_starred(day(1), true)?;
+        diary.storage().set_meta(day(2), "mood", "calm")?;
+        diary.storage().restore_tags(&[StoredTag { date: day(1), tag: "outdoors".to_string(), exec_version: 2, source: "auto".to_string(), confidence: None }])?;
+
+        let out = temp_dir.path().join("export");
+        assert_eq!(diary.export_matching(NAME, &EntryQuery::new(), &out)?, 3);
+        assert!(is_archive(&out) && out.join("files/attachments/map.png").is_file());
+        assert!(matches!(diary.export_matching(NAME, &EntryQuery::new(), &out), Err(RustyDiaryError::InvalidConfig(_))));
+
+        let other = TempDir::new()?;
+        let restored = self::diary(other.path())?;
+        assert_eq!(restored.preview_import("auto", &out)?.items.len(), 3);
+        let report = restored.import("auto", &out)?;
+        assert_eq!((report.importer.as_str(), report.stored, report.attachments, report.exec_version), (NAME, 3, 1, 2));
+        assert_eq!(restored.storage().query(&EntryQuery::new())?, diary.storage().query(&EntryQuery::new())?);
+        assert_eq!(restored.storage().stored_tags(day(1))?, diary.storage().stored_tags(day(1))?);
+        assert_eq!(restored.storage().get_meta(day(2), "mood")?.as_deref(), Some("calm"));
+        assert_eq!(fs::read(restored.directory().join("attachments/map.png"))?, b"png");
+
+        // Only into an empty database, and never from a tampered archive
+        assert!(matches!(restored.import(NAME, &out), Err(RustyDiaryError::InvalidConfig(_))));
+        let entries = fs::read_to_string(out.join(ENTRIES))?.replace("Kept as stored", "Changed");
+        fs::write(out.join(ENTRIES), entries)?;
+        assert!(matches!(read(&out), Err(RustyDiaryError::ContentIntegrity(_))));
+        Ok(())
+    }
+
+    /// The first word of a text and the pieces after it
+    type Pieces = (&'static str, Vec<(&'static str, Option<Index>)>);
+
+    /// Text of the kinds real notes hold: several scripts and emoji, CRLF
+    /// and lone CR, tabs, trailing blanks, front matter, tags and links.
+    /// Each piece may be followed by a link to one of the attachments.
+    fn content() -> impl Strategy<Value = Pieces> {
+        const PIECES: &[&str] = &[
+            "plain words", "Café ñandú", "日記", "🙂👍🏽", "\r\n", "\r", "\n\n", "\t", "   ", "#tag", "#Über_tag",
+            "[[2024-01-02]]", "==highlight==", "> quote", "---\ntags: [x]\n---\n", "\u{feff}", "e\u{301}", "`code`",
+        ];
+        // Storage refuses entries without text
+        let first = select(&["Day", "día", "日"][..]);
+        let link = prop::option::weighted(0.1, any::<Index>());
+        (first, prop::collection::vec((select(PIECES), link), 0..12))
+    }
+
+    fn text((first, pieces): &Pieces, attachments: &[Vec<u8>]) -> String {
+        let mut out = first.to_string();
+        for (piece, link) in pieces {
+            out.push_str(piece);
+            if let (Some(link), false) = (link, attachments.is_empty()) {
+                let link = link.index(attachments.len());
+                out.push_str(&format!(" ![](attachments/pic {}.png) ", link));
+            }
+        }
+        out
+    }
+
+    /// One version of a day's entry: when it was written within the day,
+    /// seconds until its edit, its author and its text
+    type Version = (i64, Option<i64>, Option<&'static str>, Pieces);
+
+    fn version() -> impl Strategy<Value = Version> {
+        (
+            0..86_400_000_000_000i64,
+            prop::option::weighted(0.3, 1..1000i64),
+            prop::option::weighted(0.3, select(&["ana", "Bea Lima", "ζωή"][..])),
+            content(),
+        )
+    }
+
+    /// Everything kept about one day besides its entries: whether it is
+    /// starred, a metadata value and a tag with an optional confidence
+    type Extras = (bool, Option<(&'static str, Pieces)>, Option<(u32, Option<f64>)>);
+
+    fn extras() -> impl Strategy<Value = Extras> {
+        (
+            prop::bool::weighted(0.3),
+            prop::option::of((select(&["mood", "weather.temp", "steps"][..]), content())),
+            prop::option::of((0..3u32, prop::option::of(0.0..1.0f64))),
+        )
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(12))]
+
+        #[test]
+        fn test_roundtrip_property(
+            attachments in prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 0..4),
+            days in prop::collection::vec((prop::collection::vec(version(), 1..4), extras()), 1..8),
+        ) {
+            let temp_dir = TempDir::new()?;
+            let diary = diary(temp_dir.path())?;
+            let folder = diary.directory().join("attachments");
+            fs::create_dir_all(&folder)?;
+            for (i, bytes) in attachments.iter().enumerate() {
+                fs::write(folder.join(format!("pic {}.png", i)), bytes)?;
+            }
+
+            let mut entries = Vec::new();
+            for (d, (versions, (starred, meta, tag))) in (1..).zip(&days) {
+                for (exec_version, (nanos, edited, author, content)) in (1..).zip(versions) {
+                    let midnight = day(d).and_hms_opt(0, 0, 0).unwrap();
+                    let created_at = midnight + Duration::nanoseconds(*nanos);
+                    entries.push(DiaryEntry {
+                        exec_version,
+                        date: day(d),
+                        content: text(content, &attachments),
+                        created_at,
+                        updated_at: edited.map(|seconds| created_at + Duration::seconds(seconds)),
+                        author: author.map(str::to_string),
+                    });
+                }
+                if *starred {
+                    diary.storage().set_starred(day(d), true)?;
+                }
+                if let Some((key, value)) = meta {
+                    diary.storage().set_meta(day(d), key, &text(value, &[]))?;
+                }
+                if let Some((tag, confidence)) = tag {
+                    let tag = StoredTag {
+                        date: day(d),
+                        tag: format!("t{}", tag),
+                        exec_version: 1,
+                        source: "auto".to_string(),
+                        confidence: *confidence,
+                    };
+                    diary.storage().restore_tags(&[tag])?;
+                }
+            }
+            diary.storage().store_entries(entries.clone())?;
+
+            let trip = diary.verify_roundtrip()?;
+            prop_assert!(trip.is_identical(), "{:?}", trip.differences);
+            prop_assert_eq!(trip.entries, entries.len());
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! A single journal file updated in place. Every entry sits under a marker
+//! comment naming its date and version, so later runs can find it again:
+//!
+//! ```text
+//! <header>
+//! <!-- rusty-diary:entry 2024-03-02 v3 -->
+//! <entry>
+//! <!-- rusty-diary:end -->
+//! <footer>
+//! ```
+//!
+//! Only the marked blocks are kept between runs; the header and footer are
+//! rendered anew each time.
+
+use chrono::NaiveDate;
+
+use crate::error::{Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+use super::template::JournalRenderer;
+
+const ENTRY_MARKER: &str = "<!-- rusty-diary:entry ";
+const END_MARKER: &str = "<!-- rusty-diary:end -->";
+
+/// One entry's text in the journal
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Block {
+    date: NaiveDate,
+    exec_version: i64,
+    text: String,
+}
+
+/// The entries of a canonical journal, newest first
+#[derive(Debug, Default)]
+pub struct CanonicalJournal {
+    blocks: Vec<Block>,
+}
+
+impl CanonicalJournal {
+    /// Reads the entry blocks of an existing journal. Content that is not a
+    /// canonical journal is refused rather than overwritten.
+    pub fn parse(content: &str) -> Result<Self> {
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut ended = false;
+
+        for line in content.split_inclusive('\n') {
+            if line.trim_end() == END_MARKER {
+                ended = true;
+                break;
+            }
+            if let Some(marker) = line.trim_end().strip_prefix(ENTRY_MARKER) {
+                blocks.push(parse_marker(marker)?);
+            } else if let Some(block) = blocks.last_mut() {
+                block.text.push_str(line);
+            }
+        }
+
+        if !ended && !content.trim().is_empty() {
+            return Err(RustyDiaryError::ContentIntegrity(
+                "Journal file has no rusty-diary end marker; refusing to overwrite it".to_string(),
+            ));
+        }
+        Ok(Self { blocks })
+    }
+
+    /// Adds entries whose date is missing and replaces blocks of an older
+    /// version. Returns how many blocks changed.
+    pub fn merge(&mut self, entries: &[DiaryEntry], renderer: &JournalRenderer) -> Result<usize> {
+        let mut changed = 0;
+        for entry in entries {
+            let position = self.blocks.iter().position(|block| block.date == entry.date);
+            if position.is_some_and(|i| self.blocks[i].exec_version >= entry.exec_version) {
+                continue;
+            }
+
+            let block = Block {
+                date: entry.date,
+                exec_version: entry.exec_version,
+                text: renderer.entry(entry, 1)?,
+            };
+            match position {
+                Some(i) => self.blocks[i] = block,
+                None => {
+                    let i = self.blocks.iter().position(|b| b.date < entry.date).unwrap_or(self.blocks.len());
+                    self.blocks.insert(i, block);
+                }
+            }
+            changed += 1;
+        }
+        Ok(changed)
+    }
+
+    pub fn render(&self, renderer: &JournalRenderer) -> Result<String> {
+        let versions: Vec<(NaiveDate, i64)> = self.blocks.iter().map(|b| (b.date, b.exec_version)).collect();
+
+        let mut content = renderer.frame_for(&versions, false)?;
+        for block in &self.blocks {
+            content.push_str(&format!("{}{} v{} -->\n", ENTRY_MARKER, block.date, block.exec_version));
+            content.push_str(&block.text);
+        }
+        content.push_str(END_MARKER);
+        content.push('\n');
+        content.push_str(&renderer.frame_for(&versions, true)?);
+        Ok(content)
+    }
+}
+
+fn parse_marker(marker: &str) -> Result<Block> {
+    let invalid = || RustyDiaryError::ContentIntegrity(format!("Malformed journal entry marker '{}'", marker));
+
+    let mut parts = marker.trim_end_matches("-->").split_whitespace();
+    let date = parts.next().ok_or_else(invalid)?.parse::<NaiveDate>().map_err(|_| invalid())?;
+    let exec_version = parts
+        .next()
+        .and_then(|v| v.strip_prefix('v'))
+        .and_then(|v| v.parse::<i64>().ok())
+        .ok_or_else(invalid)?;
+
+    Ok(Block { date, exec_version, text: String::new() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::diary::template::JournalTemplate;
+
+    #[test]
+    fn test_merge_inserts_in_order_and_keeps_edits() -> Result<()> {
+        let now = SystemClock.now();
+        let renderer = JournalTemplate::default().compile()?;
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+
+        let mut journal = CanonicalJournal::parse("")?;
+        assert_eq!(journal.merge(&[DiaryEntry::new(1, ymd(3), "Third".to_string(), now), DiaryEntry::new(1, ymd(1), "First".to_string(), now)], &renderer)?, 2);
+        let content = journal.render(&renderer)?;
+        assert!(content.contains("total-entries(2)"));
+
+        // Hand edits survive, same versions are skipped, new dates slot in
+        let edited = content.replace("First", "First, edited");
+        let mut journal = CanonicalJournal::parse(&edited)?;
+        let changed = journal.merge(
+            &[
+                DiaryEntry::new(1, ymd(1), "First".to_string(), now),
+                DiaryEntry::new(2, ymd(2), "Second".to_string(), now),
+            ],
+            &renderer,
+        )?;
+        assert_eq!(changed, 1);
+
+        let content = journal.render(&renderer)?;
+        let third = content.find("Third").unwrap();
+        let second = content.find("Second").unwrap();
+        let first = content.find("First, edited").unwrap();
+        assert!(third < second && second < first);
+        assert!(content.contains("<!-- rusty-diary:entry 2024-03-02 v2 -->\n# 2024-03-02\nSecond"));
+
+        // A newer version replaces the block
+        let mut journal = CanonicalJournal::parse(&content)?;
+        journal.merge(&[DiaryEntry::new(3, ymd(1), "First, rewritten".to_string(), now)], &renderer)?;
+        let content = journal.render(&renderer)?;
+        assert!(content.contains("First, rewritten") && !content.contains("First, edited"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_refuses_foreign_files() {
+        assert!(matches!(
+            CanonicalJournal::parse("# My own notes\n"),
+            Err(RustyDiaryError::ContentIntegrity(_))
+        ));
+        assert!(CanonicalJournal::parse("<!-- rusty-diary:entry yesterday v1 -->\n<!-- rusty-diary:end -->\n").is_err());
+    }
+}
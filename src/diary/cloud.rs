@@ -0,0 +1,124 @@
+//! Care for diary folders kept in OneDrive, Dropbox or iCloud Drive. Such
+//! folders may hold placeholders whose content is not downloaded yet, and
+//! the sync client may briefly lock files it is updating.
+
+use std::fs::Metadata;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+const RETRIES: u32 = 4;
+const RETRY_DELAY: Duration = Duration::from_millis(250);
+
+/// Whether the file's content lives only in the cloud (Windows Cloud Files
+/// placeholders, macOS dataless files)
+#[cfg(windows)]
+pub fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+    metadata.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE | FILE_ATTRIBUTE_RECALL_ON_OPEN | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+/// Whether the file's content lives only in the cloud (Windows Cloud Files
+/// placeholders, macOS dataless files)
+#[cfg(target_os = "macos")]
+pub fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const SF_DATALESS: u32 = 0x40000000;
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+/// Whether the file's content lives only in the cloud (Windows Cloud Files
+/// placeholders, macOS dataless files)
+#[cfg(not(any(windows, target_os = "macos")))]
+pub fn is_placeholder(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// The file an iCloud Drive stub such as `.2024-03-01.md.icloud` stands in for
+pub fn icloud_stub_target(path: &Path) -> Option<&str> {
+    path.file_name()?
+        .to_str()?
+        .strip_prefix('.')?
+        .strip_suffix(".icloud")
+        .filter(|name| !name.is_empty())
+}
+
+/// Whether the error means another process holds the file open
+fn is_sharing_violation(err: &io::Error) -> bool {
+    // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+    cfg!(windows) && matches!(err.raw_os_error(), Some(32) | Some(33))
+}
+
+/// Runs a file operation, retrying for a moment while the file is locked
+pub fn retry_locked<T>(op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    retry_while(op, is_sharing_violation, RETRY_DELAY)
+}
+
+fn retry_while<T>(
+    mut op: impl FnMut() -> io::Result<T>,
+    transient: impl Fn(&io::Error) -> bool,
+    delay: Duration,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Err(err) if attempt < RETRIES && transient(&err) => {
+                attempt += 1;
+                tracing::debug!("File busy, retrying ({}/{}): {}", attempt, RETRIES, err);
+                thread::sleep(delay * attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_icloud_stub_target() {
+        assert_eq!(icloud_stub_target(Path::new("notes/.2024-03-01.md.icloud")), Some("2024-03-01.md"));
+        assert_eq!(icloud_stub_target(Path::new("2024-03-01.md")), None);
+        assert_eq!(icloud_stub_target(Path::new(".icloud")), None);
+    }
+
+    #[test]
+    fn test_retry_while_transient() {
+        let busy = || io::Error::other("busy");
+        let transient = |err: &io::Error| err.to_string() == "busy";
+
+        let mut calls = 0;
+        let result = retry_while(
+            || {
+                calls += 1;
+                if calls < 3 { Err(busy()) } else { Ok(calls) }
+            },
+            transient,
+            Duration::ZERO,
+        );
+        assert_eq!(result.unwrap(), 3);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_while(|| { calls += 1; Err(busy()) }, transient, Duration::ZERO);
+        assert!(result.is_err());
+        assert_eq!(calls, RETRIES + 1);
+
+        let mut calls = 0;
+        let result: io::Result<()> = retry_while(
+            || { calls += 1; Err(io::Error::new(io::ErrorKind::NotFound, "gone")) },
+            transient,
+            Duration::ZERO,
+        );
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,159 @@
+//! Built-in importer of Day One JSON exports: the `Journal.json` of an
+//! unzipped export, with its `photos` folder next to it. Entries are dated
+//! by when they were created, in this machine's time zone. Their tags are
+//! added as #hashtags, and their photos are filed under
+//! `attachments/dayone/`, replacing the `dayone-moment://` links.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
+use serde::Deserialize;
+
+use crate::error::{IoContext, Result};
+use crate::storage::models::DiaryEntry;
+
+use super::import::{ImportedAttachment, Imported, SourceItem};
+use super::processor::Importer;
+
+/// Folder of the filed photos inside the attachments folder
+const FOLDER: &str = "dayone";
+
+#[derive(Deserialize)]
+struct Export {
+    #[allow(dead_code)]
+    metadata: serde_json::Value,
+    entries: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    creation_date: DateTime<Utc>,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    photos: Vec<Photo>,
+}
+
+#[derive(Deserialize)]
+struct Photo {
+    identifier: String,
+    md5: String,
+    #[serde(rename = "type", default = "default_photo_type")]
+    kind: String,
+}
+
+fn default_photo_type() -> String {
+    "jpeg".to_string()
+}
+
+pub struct DayOneImporter;
+
+impl DayOneImporter {
+    fn read(source: &Path) -> Result<Export> {
+        Ok(serde_json::from_str(&fs::read_to_string(source).at_path(source)?)?)
+    }
+}
+
+impl Importer for DayOneImporter {
+    fn name(&self) -> &str {
+        "dayone"
+    }
+
+    fn detect(&self, source: &Path) -> bool {
+        source.is_file() && source.extension().is_some_and(|ext| ext == "json") && Self::read(source).is_ok()
+    }
+
+    fn enumerate(&self, source: &Path) -> Result<Vec<SourceItem>> {
+        let export = Self::read(source)?;
+        Ok(export
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| SourceItem {
+                source: PathBuf::from(format!("{}#{}", source.display(), i + 1)),
+                raw: entry.to_string(),
+            })
+            .collect())
+    }
+
+    fn convert(&self, item: &SourceItem, now: NaiveDateTime) -> Result<Imported> {
+        let entry: Entry = serde_json::from_str(&item.raw)?;
+        let photos = item.source.parent().unwrap_or(Path::new("")).join("photos");
+
+        let mut content = entry.text;
+        let mut attachments = Vec::new();
+        for photo in &entry.photos {
+            let name = format!("{}.{}", photo.md5, photo.kind);
+            content = content.replace(
+                &format!("dayone-moment://{}", photo.identifier),
+                &format!("attachments/{}/{}", FOLDER, name),
+            );
+            let source = photos.join(&name);
+            if source.is_file() {
+                attachments.push(ImportedAttachment { source, path: format!("{}/{}", FOLDER, name) });
+            }
+        }
+
+        let date = entry.creation_date.with_timezone(&Local).date_naive();
+        let mut imported = DiaryEntry::new(0, date, content, now);
+        let present = imported.tags();
+        let missing: Vec<String> = entry
+            .tags
+            .iter()
+            .map(|tag| tag.split_whitespace().collect::<Vec<_>>().join("-"))
+            .filter(|tag| !tag.is_empty() && !present.contains(&tag.to_lowercase()))
+            .map(|tag| format!("#{}", tag))
+            .collect();
+        if !missing.is_empty() {
+            imported.content = format!("{}\n\n{}", imported.content.trim_end(), missing.join(" "));
+        }
+        Ok(Imported { entry: imported, attachments })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dayone_export() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let journal = temp_dir.path().join("Journal.json");
+        fs::write(
+            &journal,
+            r#"{"metadata": {"version": "1.0"}, "entries": [
+                {"uuid": "A1", "creationDate": "2024-03-05T12:00:00Z", "text": "Sunny #walk\n\n![](dayone-moment://P1)",
+                 "tags": ["walk", "Long Weekend"], "photos": [{"identifier": "P1", "md5": "abc", "type": "png"}]},
+                {"uuid": "A2", "creationDate": "2024-03-06T12:00:00Z"}
+            ]}"#,
+        )?;
+        fs::create_dir(temp_dir.path().join("photos"))?;
+        fs::write(temp_dir.path().join("photos").join("abc.png"), b"png")?;
+        fs::write(temp_dir.path().join("other.json"), r#"{"entries": []}"#)?;
+
+        let importer = DayOneImporter;
+        assert!(importer.detect(&journal));
+        assert!(!importer.detect(&temp_dir.path().join("other.json")) && !importer.detect(temp_dir.path()));
+
+        let items = importer.enumerate(&journal)?;
+        assert_eq!(items.len(), 2);
+        assert!(items[1].source.to_string_lossy().ends_with("Journal.json#2"));
+        let now = NaiveDate::from_ymd_opt(2024, 4, 1).unwrap().and_hms_opt(8, 0, 0).unwrap();
+        let imported = importer.convert(&items[0], now)?;
+        assert_eq!(imported.entry.date, NaiveDate::from_ymd_opt(2024, 3, 5).unwrap());
+        assert_eq!(imported.entry.created_at, now);
+        assert_eq!(imported.entry.content, "Sunny #walk\n\n![](attachments/dayone/abc.png)\n\n#Long-Weekend");
+        assert_eq!(
+            imported.attachments,
+            [ImportedAttachment { source: temp_dir.path().join("photos").join("abc.png"), path: "dayone/abc.png".to_string() }]
+        );
+        assert_eq!(importer.convert(&items[1], now)?.entry.content, "");
+        Ok(())
+    }
+}
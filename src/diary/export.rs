@@ -0,0 +1,132 @@
+//! What every export shares. The diary picks the entries with an
+//! `EntryQuery` and streams them to an `Exporter`, reporting progress and
+//! stopping on cancellation as they are read; the exporter writes them
+//! through an `ExportSink`. The sink knows the destination and resolves the
+//! local files entries link to against the diary directory, copying them
+//! next to the output for exports that travel with their attachments.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::{IoContext, Result};
+use crate::storage::models::DiaryEntry;
+
+use super::links;
+use super::processor::Exporter;
+
+/// The entries of an export, oldest first, as they are read
+pub type EntryStream<'a> = dyn Iterator<Item = Result<DiaryEntry>> + 'a;
+
+/// Where an export is written and the attachments it brings along
+#[derive(Debug, Clone)]
+pub struct ExportSink {
+    destination: PathBuf,
+    /// Directory attachment links are relative to; links are left as
+    /// written without one
+    directory: Option<PathBuf>,
+    /// Attachments copied so far
+    copied: usize,
+}
+
+impl ExportSink {
+    pub fn new<P: Into<PathBuf>>(destination: P) -> Self {
+        Self { destination: destination.into(), directory: None, copied: 0 }
+    }
+
+    /// Resolves attachment links against the diary directory
+    pub fn with_directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    pub fn destination(&self) -> &Path {
+        &self.destination
+    }
+
+    /// Replaces the destination with `contents`
+    pub fn write(&self, contents: impl AsRef<[u8]>) -> Result<()> {
+        fs::write(&self.destination, contents).at_path(&self.destination)?;
+        Ok(())
+    }
+
+    /// The local file a link points at, or `None` for remote links, data
+    /// URIs, files that do not exist and sinks without a directory
+    pub fn attachment(&self, target: &str) -> Option<PathBuf> {
+        let directory = self.directory.as_ref()?;
+        if !links::is_local(target) || target.starts_with("data:") {
+            return None;
+        }
+        let path = directory.join(links::target_path(target));
+        path.is_file().then_some(path)
+    }
+
+    /// The local files an entry links to or embeds that exist, in the
+    /// order they appear
+    pub fn attachments(&self, entry: &DiaryEntry) -> Vec<PathBuf> {
+        links::attachment_targets(&entry.content).iter().filter_map(|target| self.attachment(target)).collect()
+    }
+
+    /// Folder copied attachments go to: `diary_files` next to `diary.html`
+    pub fn assets_folder(&self) -> PathBuf {
+        let stem = self.destination.file_stem().unwrap_or_default().to_string_lossy();
+        self.destination.with_file_name(format!("{}_files", stem))
+    }
+
+    /// Writes `bytes`, such as a thumbnail, as the copy of the attachment
+    /// at `path` and returns the link to it from the destination. Copies
+    /// are numbered, so attachments sharing a name stay apart.
+    pub fn copy_attachment(&mut self, path: &Path, bytes: &[u8]) -> Result<String> {
+        let folder = self.assets_folder();
+        fs::create_dir_all(&folder).at_path(&folder)?;
+        self.copied += 1;
+        let name = format!("{}-{}", self.copied, path.file_name().unwrap_or_default().to_string_lossy());
+        let copy = folder.join(&name);
+        fs::write(&copy, bytes).at_path(&copy)?;
+        Ok(format!("{}/{}", folder.file_name().unwrap_or_default().to_string_lossy(), name))
+    }
+
+    /// Number of attachments copied next to the destination
+    pub fn copied(&self) -> usize {
+        self.copied
+    }
+}
+
+/// Exports entries already in memory, such as those read from elsewhere
+pub fn export_all(exporter: &dyn Exporter, entries: &[DiaryEntry], sink: &mut ExportSink) -> Result<()> {
+    exporter.export(&mut entries.iter().cloned().map(Ok), sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sink_attachments() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let diary = temp_dir.path().join("diary");
+        fs::create_dir_all(diary.join("photos"))?;
+        fs::write(diary.join("photos/beach day.png"), b"png")?;
+        let out = temp_dir.path().join("out").join("diary.html");
+
+        let sink = ExportSink::new(&out);
+        assert_eq!(sink.attachment("photos/beach%20day.png"), None);
+        let mut sink = sink.with_directory(&diary);
+        let path = sink.attachment("photos/beach%20day.png").unwrap();
+        assert_eq!(path, diary.join("photos/beach day.png"));
+        for missing in ["missing.png", "https://example.com/a.png", "data:image/png;base64,AA"] {
+            assert_eq!(sink.attachment(missing), None);
+        }
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let entry = DiaryEntry::new(1, date, "![](missing.png) [[2024-04-30]] ![shore](photos/beach%20day.png)".to_string(), now);
+        assert_eq!(sink.attachments(&entry), std::slice::from_ref(&path));
+
+        assert_eq!(sink.copy_attachment(&path, b"small")?, "diary_files/1-beach day.png");
+        assert_eq!(sink.copy_attachment(&path, b"small")?, "diary_files/2-beach day.png");
+        assert_eq!(fs::read(temp_dir.path().join("out/diary_files/1-beach day.png"))?, b"small");
+        assert_eq!(sink.copied(), 2);
+        Ok(())
+    }
+}
@@ -1,10 +1,96 @@
+use std::collections::{BTreeMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{Datelike, NaiveDate};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use walkdir::WalkDir;
 use std::fs;
+use serde::Serialize;
 
-use crate::error::{Result, RustyDiaryError};
+use crate::cancel::CancellationToken;
+use crate::clock::{Clock, SystemClock};
+use crate::error::{IoContext, Location, Result, RustyDiaryError};
+use crate::progress::ProgressSink;
 use crate::storage::models::DiaryEntry;
-use super::processor::MarkdownProcessor;
+use super::JournalReport;
+use super::canonical::CanonicalJournal;
+use super::cloud;
+use super::filename::{available_path, FilenameTemplate, FilenameValues};
+use super::photos::{self, Photo};
+use super::processor::{MarkdownProcessor, Normalization, ProcessorRegistry};
+use super::split::{JournalSplit, JOURNAL_DIR};
+use super::template::{JournalRenderer, JournalTemplate};
+
+/// Per-directory ignore file, in gitignore syntax
+pub const IGNORE_FILE: &str = ".diaryignore";
+
+/// Timestamp in backup file names; no colons, which Windows does not allow
+const BACKUP_TIMESTAMP: &str = "%Y%m%d_%H%M%S";
+
+/// Locations the tool writes to itself, never collected
+const BUILTIN_IGNORES: [&str; 2] = [".backup/", "journal/"];
+
+/// How folders above a diary file relate to its date, for nested layouts
+/// such as `2024/03/2024-03-05.md`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PathDates {
+    /// Dates come from file names only
+    #[default]
+    Ignore,
+    /// Year (`2024`) and month (`03`, `03-march`) folders above a file must
+    /// agree with the date in its name
+    Verify,
+    /// Like `Verify`, and files named by day only (`2024/03/05.md`) take
+    /// their year and month from the folders
+    Derive,
+}
+
+impl PathDates {
+    pub const NAMES: &'static [&'static str] = &["ignore", "verify", "derive"];
+}
+
+impl std::str::FromStr for PathDates {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "ignore" => Ok(PathDates::Ignore),
+            "verify" => Ok(PathDates::Verify),
+            "derive" => Ok(PathDates::Derive),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown path dates mode '{}' (expected ignore, verify or derive)", name
+            ))),
+        }
+    }
+}
+
+/// A diary file that could not be turned into an entry
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Diary files found by collection
+#[derive(Debug, Default)]
+pub struct CollectedFiles {
+    pub files: Vec<PathBuf>,
+    /// Files left for a later run: cloud placeholders and recently modified files
+    pub pending: Vec<SkippedFile>,
+}
+
+/// Outcome of processing a batch of diary files
+#[derive(Debug, Default)]
+pub struct ProcessedFiles {
+    pub entries: Vec<DiaryEntry>,
+    pub skipped: Vec<SkippedFile>,
+    /// Files read with invalid bytes replaced; their entries are in
+    /// `entries`, but the files must not be removed
+    pub lossy: Vec<SkippedFile>,
+    /// Images for photo-journal mode, not yet referenced by any entry
+    pub photos: Vec<Photo>,
+}
 
 /// FileRepository handles all file system operations.
 /// It follows the Repository pattern to provide a clean abstraction
@@ -13,81 +99,357 @@ pub struct FileRepository {
     root_dir: PathBuf,
     diary_file_prefix: String,
     markdown_processor: MarkdownProcessor,
+    registry: ProcessorRegistry,
+    journal: JournalRenderer,
+    filename: FilenameTemplate,
+    ignore: Gitignore,
+    max_depth: usize,
+    path_dates: PathDates,
+    min_file_age: Duration,
+    lossy_utf8: bool,
+    photos: bool,
+    author: Option<String>,
+    journal_file: Option<PathBuf>,
+    cancel: CancellationToken,
+    clock: Arc<dyn Clock>,
 }
 
 impl FileRepository {
     pub fn new<P: AsRef<Path>>(root_dir: P, diary_file_prefix: String, date_pattern: &str) -> Result<Self> {
         let root_dir = root_dir.as_ref().to_path_buf();
         let markdown_processor = MarkdownProcessor::new(date_pattern)?;
+        let ignore = build_ignore(&root_dir, &[])?;
 
         Ok(Self {
             root_dir,
             diary_file_prefix,
             markdown_processor,
+            registry: ProcessorRegistry::default(),
+            journal: JournalTemplate::default().compile()?,
+            filename: FilenameTemplate::default(),
+            ignore,
+            max_depth: 1,
+            path_dates: PathDates::default(),
+            min_file_age: Duration::ZERO,
+            lossy_utf8: false,
+            photos: false,
+            author: None,
+            journal_file: None,
+            cancel: CancellationToken::default(),
+            clock: Arc::new(SystemClock),
         })
     }
 
-    /// Collects all markdown files that match our date pattern
-    pub fn collect_diary_files(&self) -> Result<Vec<PathBuf>> {
-        let entries: Vec<PathBuf> = WalkDir::new(&self.root_dir)
+    /// Leaves files modified less than `age` ago for a later run, so files
+    /// still being written or synced are not picked up half-way
+    pub fn with_min_file_age(mut self, age: Duration) -> Self {
+        self.min_file_age = age;
+        self
+    }
+
+    /// Normalizes the text of processed files; line endings always, Unicode
+    /// composition when `Normalization::Nfc` is chosen
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.markdown_processor = self.markdown_processor.with_normalization(normalization);
+        self
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.markdown_processor.normalization()
+    }
+
+    /// Collects images dated by name or EXIF data for their day's entry,
+    /// filing them under the attachments folder once stored
+    pub fn with_photos(mut self, enabled: bool) -> Self {
+        self.photos = enabled;
+        self
+    }
+
+    /// Splits files into one entry per heading made of a date alone, such
+    /// as `## 2024-03-04`, for files that hold a whole week
+    pub fn with_date_heading_split(mut self, enabled: bool) -> Self {
+        self.markdown_processor = self.markdown_processor.with_date_heading_split(enabled);
+        self
+    }
+
+    /// Reads files with invalid UTF-8 anyway, replacing the bad bytes with
+    /// U+FFFD. Such files are reported in `ProcessedFiles::lossy` and kept.
+    pub fn with_lossy_utf8(mut self, enabled: bool) -> Self {
+        self.lossy_utf8 = enabled;
+        self
+    }
+
+    /// Credits entries to `author` unless their frontmatter names another one
+    pub fn with_author(mut self, author: Option<String>) -> Self {
+        self.author = author;
+        self
+    }
+
+    /// Stops processing files and rendering journals once `cancel` is
+    /// cancelled. Journals are rendered in full before any is written, so a
+    /// cancelled write leaves every file as it was.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Stamps read entries, journal names and file backups with the time
+    /// of `clock` instead of this machine's
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.markdown_processor = self.markdown_processor.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// Collects files up to `max_depth` folders deep (1 is the root only)
+    /// and relates their dates to the folders as `path_dates` says
+    pub fn with_layout(mut self, max_depth: usize, path_dates: PathDates) -> Self {
+        self.max_depth = max_depth.max(1);
+        self.path_dates = path_dates;
+        self
+    }
+
+    /// Skips files matching the gitignore-style patterns during collection,
+    /// on top of the directory's `.diaryignore`
+    pub fn with_ignore_patterns(mut self, patterns: &[String]) -> Result<Self> {
+        self.ignore = build_ignore(&self.root_dir, patterns)?;
+        Ok(self)
+    }
+
+    /// Never collects `path`, the journal kept updated in place
+    pub fn with_journal_file(mut self, path: Option<PathBuf>) -> Self {
+        self.journal_file = path;
+        self
+    }
+
+    /// Names written journals after the template, e.g. `{prefix}-{start}-{end}.md`
+    pub fn with_filename_template(mut self, template: &str) -> Result<Self> {
+        self.filename = FilenameTemplate::parse(template)?;
+        Ok(self)
+    }
+
+    /// Formats written journals with the given templates
+    pub fn with_journal_template(mut self, template: &JournalTemplate) -> Result<Self> {
+        self.journal = template.compile()?;
+        Ok(self)
+    }
+
+    /// Parsers and exporters used by this repository
+    pub fn registry(&self) -> &ProcessorRegistry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut ProcessorRegistry {
+        &mut self.registry
+    }
+
+    /// Collects all markdown files that match our date pattern. Files not
+    /// ready yet are returned as pending instead.
+    pub fn collect_diary_files(&self) -> Result<CollectedFiles> {
+        let mut collected = CollectedFiles::default();
+        let paths = WalkDir::new(&self.root_dir)
             .min_depth(0)
-            .max_depth(1)  // Only look in the immediate directory
+            .max_depth(self.max_depth)
             .into_iter()
+            .filter_entry(|entry| !self.is_ignored(entry.path(), entry.file_type().is_dir()))
             .filter_map(|entry| entry.ok())
-            .map(|entry| entry.path().to_path_buf())
-            .filter(|path| self.is_valid_diary_file(path))
-            .collect();
+            .map(|entry| entry.into_path());
 
-        if entries.is_empty() {
+        for path in paths {
+            if let Some(target) = cloud::icloud_stub_target(&path) {
+                if self.markdown_processor.extract_date(target).is_ok() {
+                    let reason = format!("iCloud has not downloaded {} yet", target);
+                    collected.pending.push(SkippedFile { path, reason });
+                }
+                continue;
+            }
+            if !self.is_valid_diary_file(&path) {
+                continue;
+            }
+            if self.is_tool_output(&path) {
+                tracing::debug!("Not collecting {:?}, which rusty-diary wrote", path);
+                continue;
+            }
+
+            match self.pending_reason(&path) {
+                Some(reason) => collected.pending.push(SkippedFile { path, reason }),
+                None => collected.files.push(path),
+            }
+        }
+
+        for pending in &collected.pending {
+            tracing::info!("Leaving {:?} for a later run: {}", pending.path, pending.reason);
+        }
+        if collected.files.is_empty() {
             return Err(RustyDiaryError::NoFilesFound(self.root_dir.clone()));
         }
 
-        Ok(entries)
+        Ok(collected)
     }
 
-    pub fn write_entries(&self, entries: Vec<DiaryEntry>) -> Result<()> {
-        let today = chrono::Local::now().format("%Y-%m-%d");
-        let max_exec_version = entries.iter().map(|entry| entry.exec_version).max().unwrap_or(0);
-        let filename = format!("{}_{}_{}.md", self.diary_file_prefix, today, max_exec_version);
+    /// Writes entries into a new journal file. A file with the same name and
+    /// content is reused; any other existing file is never overwritten, the
+    /// name gets a `-2`, `-3`, ... suffix instead.
+    pub fn write_entries(&self, entries: Vec<DiaryEntry>, progress: &dyn ProgressSink) -> Result<JournalReport> {
+        let today = self.clock.today();
+        let filename = self.filename.render(&FilenameValues {
+            prefix: &self.diary_file_prefix,
+            today,
+            exec_version: entries.iter().map(|entry| entry.exec_version).max().unwrap_or(0),
+            start: entries.last().map_or(today, |entry| entry.date),
+            end: entries.first().map_or(today, |entry| entry.date),
+        });
 
-        let mut content = String::new();
+        progress.start("write", entries.len() as u64);
+        let content = self.render_journal(&entries, progress)?;
+
+        if filename.parent() == Some(Path::new("")) && self.markdown_processor.extract_date(&filename).is_ok() {
+            // The next sync would ingest and delete it
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "Journal file name {:?} matches the diary date pattern", filename
+            )));
+        }
+        let path = self.root_dir.join(filename);
+        progress.finish();
+        if is_up_to_date(&path, &content) {
+            return Ok(JournalReport { path, written: Vec::new() });
+        }
 
-        content.push_str(
-            &format!(
-                "---\nmetadata\n- rusty-diary:date-range {} to {}\n",
-                entries.last().map_or("unknown".to_string(), |e| e.date.to_string()),
-                entries.first().map_or("unknown".to_string(), |e| e.date.to_string())
-            )
-        );
-        content.push_str(
-            &format!(
-                "- rusty-diary:total-entries({})\n---\n",
-                entries.len()
-            )
-        );
+        let path = available_path(path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        fs::write(&path, content).at_path(&path)?;
+
+        Ok(JournalReport { written: vec![path.clone()], path })
+    }
+
+    /// Writes entries into one journal file per period under the journal
+    /// directory. Files whose content would not change are left untouched;
+    /// returns the ones that were (re)written.
+    pub fn write_periods(
+        &self,
+        entries: Vec<DiaryEntry>,
+        split: JournalSplit,
+        progress: &dyn ProgressSink,
+    ) -> Result<Vec<PathBuf>> {
+        progress.start("write", entries.len() as u64);
+        let mut periods: BTreeMap<PathBuf, Vec<DiaryEntry>> = BTreeMap::new();
         for entry in entries {
-            content.push_str(&format!("# {}\n", entry.date.to_string()));
-            content.push_str(&entry.content);
-            content.push_str("\n\n***\n");
+            periods.entry(split.relative_path(entry.date)).or_default().push(entry);
         }
 
-        let path = self.root_dir.join(filename);
-        fs::write(&path, content)?;
+        let mut rendered = Vec::with_capacity(periods.len());
+        for (relative, entries) in periods {
+            rendered.push((self.journal_dir().join(relative), self.render_journal(&entries, progress)?));
+        }
+
+        let mut written = Vec::new();
+        for (path, content) in rendered {
+            if is_up_to_date(&path, &content) {
+                continue;
+            }
 
-        Ok(())
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).at_path(parent)?;
+            }
+            fs::write(&path, content).at_path(&path)?;
+            written.push(path);
+        }
+        progress.finish();
+
+        Ok(written)
+    }
+
+    /// Merges entries into the canonical journal at `path`, creating it if
+    /// needed. Returns whether the file changed.
+    pub fn update_journal(&self, path: &Path, entries: &[DiaryEntry], progress: &dyn ProgressSink) -> Result<bool> {
+        let existing = match fs::read_to_string(path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).at_path(path),
+        };
+        let mut journal =
+            CanonicalJournal::parse(existing.as_deref().unwrap_or_default()).map_err(|e| e.in_file(path))?;
+
+        progress.start("write", entries.len() as u64);
+        let changed = journal.merge(entries, &self.journal)?;
+        progress.advance(entries.len() as u64);
+        progress.finish();
+        if changed == 0 && existing.is_some() {
+            return Ok(false);
+        }
+
+        let content = journal.render(&self.journal)?;
+        self.cancel.check()?;
+        if is_up_to_date(path, &content) {
+            return Ok(false);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        fs::write(path, content).at_path(path)?;
+        Ok(true)
+    }
+
+    /// Where split journals are written
+    pub fn journal_dir(&self) -> PathBuf {
+        self.root_dir.join(JOURNAL_DIR)
+    }
+
+    fn render_journal(&self, entries: &[DiaryEntry], progress: &dyn ProgressSink) -> Result<String> {
+        let mut content = self.journal.frame(entries, false)?;
+        content.push_str(&self.journal.contents(entries));
+        for (index, entry) in entries.iter().enumerate() {
+            self.cancel.check()?;
+            content.push_str(&self.journal.entry(entry, index + 1)?);
+            progress.advance(1);
+        }
+        content.push_str(&self.journal.tag_index(entries));
+        content.push_str(&self.journal.frame(entries, true)?);
+        Ok(content)
     }
 
     /// Process a set of files into DiaryEntries
-    pub fn process_files(&self, files: &[PathBuf], exec_version: i64) -> Result<Vec<DiaryEntry>> {
+    pub fn process_files(
+        &self,
+        files: &[PathBuf],
+        exec_version: i64,
+        progress: &dyn ProgressSink,
+    ) -> Result<ProcessedFiles> {
         let mut entries = Vec::new();
         let mut errors = Vec::new();
+        let mut lossy = Vec::new();
+        let mut photos = Vec::new();
+        let mut claimed = HashSet::new();
 
+        progress.start("process", files.len() as u64);
         for file in files {
+            self.cancel.check()?;
+            if self.photos && photos::is_photo(file) {
+                match self.process_photo(file, &mut claimed) {
+                    Ok(photo) => photos.push(photo),
+                    Err(e) => errors.push((file.clone(), e.in_file(file))),
+                }
+                progress.advance(1);
+                continue;
+            }
             match self.process_single_file(file, exec_version) {
-                Ok(entry) => entries.push(entry),
-                Err(e) => errors.push((file.clone(), e)),
+                Ok((file_entries, invalid_at)) => {
+                    if let Some(offset) = invalid_at {
+                        tracing::warn!("File {:?} is not valid UTF-8 from byte {}; keeping it after a lossy read", file, offset);
+                        lossy.push(SkippedFile {
+                            path: file.clone(),
+                            reason: format!("Invalid UTF-8 at byte {}, replaced with U+FFFD", offset),
+                        });
+                    }
+                    entries.extend(file_entries);
+                }
+                Err(e) => errors.push((file.clone(), e.in_file(file))),
             }
+            progress.advance(1);
         }
+        progress.finish();
 
         // Log any errors encountered during processing
         if !errors.is_empty() {
@@ -96,64 +458,275 @@ impl FileRepository {
             }
         }
 
-        Ok(entries)
+        let skipped = errors
+            .into_iter()
+            .map(|(path, error)| SkippedFile { path, reason: error.to_string() })
+            .collect();
+
+        Ok(ProcessedFiles { entries: merge_same_dates(entries), skipped, lossy, photos })
     }
 
-    /// Clean up processed files
-    pub fn cleanup_files(&self, files: &[PathBuf]) -> Result<()> {
+    /// Clean up processed files. Returns the files that could not be removed.
+    pub fn cleanup_files(&self, files: &[PathBuf]) -> Result<Vec<SkippedFile>> {
+        let mut failed = Vec::new();
         for file in files {
-            if let Err(e) = fs::remove_file(file) {
+            if let Err(e) = cloud::retry_locked(|| fs::remove_file(file)) {
                 tracing::warn!("Failed to remove file {:?}: {}", file, e);
+                failed.push(SkippedFile { path: file.clone(), reason: e.to_string() });
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Moves images into the attachments folder, or removes them where an
+    /// identical copy is already filed. Returns the images left in place.
+    pub fn file_photos(&self, photos: &[Photo]) -> Vec<SkippedFile> {
+        let mut failed = Vec::new();
+        for photo in photos {
+            let filed = if photo.destination.exists() {
+                cloud::retry_locked(|| fs::remove_file(&photo.source))
+            } else {
+                let folder = photo.destination.parent().unwrap_or(&self.root_dir);
+                fs::create_dir_all(folder).and_then(|_| cloud::retry_locked(|| fs::rename(&photo.source, &photo.destination)))
+            };
+            if let Err(e) = filed {
+                tracing::warn!("Failed to file {:?} as {:?}: {}", photo.source, photo.destination, e);
+                failed.push(SkippedFile { path: photo.source.clone(), reason: e.to_string() });
             }
         }
-        Ok(())
+        failed
     }
 
     // Private helper methods
 
+    /// Why a diary file should wait for a later run, if it should
+    fn pending_reason(&self, path: &Path) -> Option<String> {
+        let metadata = fs::metadata(path).ok()?;
+        if cloud::is_placeholder(&metadata) {
+            return Some("Cloud placeholder, content not downloaded yet".to_string());
+        }
+
+        let age = metadata.modified().ok()?.elapsed().unwrap_or_default();
+        (age < self.min_file_age).then(|| {
+            format!("Modified {}s ago, waiting until it is {}s old", age.as_secs(), self.min_file_age.as_secs())
+        })
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        // The root itself is never ignored, whatever the patterns say
+        path != self.root_dir && self.ignore.matched_path_or_any_parents(path, is_dir).is_ignore()
+    }
+
+    /// Journals this repository writes, which a loose date pattern could
+    /// otherwise take for diary files: the `--journal-file` journal and
+    /// files named after the output prefix. `.backup` and split journals
+    /// are among the built-in ignores, and `Config::validate` refuses
+    /// output filenames a diary file could have.
+    fn is_tool_output(&self, path: &Path) -> bool {
+        if self.journal_file.as_deref() == Some(path) {
+            return true;
+        }
+        !self.diary_file_prefix.is_empty()
+            && path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(&self.diary_file_prefix))
+    }
+
     fn is_valid_diary_file(&self, path: &Path) -> bool {
         if !path.is_file() {
             return false;
         }
 
-        // Check if it's a markdown file
+        if self.photos && photos::is_photo(path) {
+            let filed = path.starts_with(self.root_dir.join(photos::ATTACHMENTS_DIR));
+            return !filed && photos::photo_date(path).is_some();
+        }
+
+        // Check if it's a markdown file or one a registered parser handles
         match path.extension() {
             Some(ext) if ext == "md" => (),
+            _ if self.registry.parser_for(path).is_some() => (),
             _ => return false,
         }
 
-        // Verify the filename matches our date pattern
-        self.markdown_processor
-            .extract_date(path)
-            .is_ok()
+        // Verify the filename matches our date pattern, or the folders give a date
+        self.markdown_processor.extract_date(path).is_ok()
+            || (self.path_dates == PathDates::Derive && self.derive_date(path).is_some())
     }
 
-    fn process_single_file(&self, path: &Path, exec_version: i64) -> Result<DiaryEntry> {
-        let content = fs::read_to_string(path)?;
+    /// The entry date of a file, checked against its folders if configured
+    fn file_date(&self, path: &Path) -> Result<NaiveDate> {
+        let from_name = self.markdown_processor.extract_date(path);
+        if self.path_dates == PathDates::Ignore {
+            return from_name;
+        }
 
-        // Validate content before processing
-        self.markdown_processor.validate_content(&content)?;
+        let date = match from_name {
+            Ok(date) => date,
+            Err(e) => match self.path_dates {
+                PathDates::Derive => self.derive_date(path).ok_or(e)?,
+                _ => return Err(e),
+            },
+        };
 
-        // Extract date from filename
-        let date = self.markdown_processor.extract_date(path)?;
+        let (year, month) = self.folder_year_month(path);
+        if year.is_some_and(|year| year != date.year()) || month.is_some_and(|month| month != date.month()) {
+            return Err(RustyDiaryError::ContentIntegrity(format!("Date {} does not match its folders", date)));
+        }
+        Ok(date)
+    }
+
+    /// Date of a day-only file name under year and month folders
+    fn derive_date(&self, path: &Path) -> Option<NaiveDate> {
+        let (year, month) = self.folder_year_month(path);
+        let day = leading_number(path.file_stem()?.to_str()?, 2)?;
+        NaiveDate::from_ymd_opt(year?, month?, day)
+    }
+
+    /// The innermost year folder above a file, and the month folder right inside it
+    fn folder_year_month(&self, path: &Path) -> (Option<i32>, Option<u32>) {
+        let folders: Vec<&str> = path
+            .strip_prefix(&self.root_dir)
+            .ok()
+            .and_then(Path::parent)
+            .map(|parent| parent.iter().filter_map(|c| c.to_str()).collect())
+            .unwrap_or_default();
+
+        let Some(index) = folders.iter().rposition(|f| f.len() == 4 && f.bytes().all(|b| b.is_ascii_digit())) else {
+            return (None, None);
+        };
+        let year = folders[index].parse().ok();
+        let month = folders.get(index + 1).and_then(|f| leading_number(f, 2)).filter(|m| (1..=12).contains(m));
+        (year, month)
+    }
+
+    /// The file's entries, and where its first invalid UTF-8 byte was if it
+    /// had to be read lossily. A file holds one entry unless it is split by
+    /// date headings.
+    fn process_single_file(&self, path: &Path, exec_version: i64) -> Result<(Vec<DiaryEntry>, Option<usize>)> {
+        let bytes = cloud::retry_locked(|| fs::read(path)).at_path(path)?;
+        let (mut content, invalid_at) = decode_text(path, bytes, self.lossy_utf8)?;
+        if let Some(parser) = self.registry.parser_for(path) {
+            content = parser.parse(path, &content)?;
+        }
 
-        Ok(DiaryEntry::new(exec_version, date, content))
+        // Date from the filename, or folders
+        let date = self.file_date(path)?;
+        let entries = self.markdown_processor.entries(&content, date, exec_version, self.author.clone())?;
+        Ok((entries, invalid_at))
+    }
+
+    /// The image with a place under `attachments/<year>/` that no other
+    /// image of the run takes; an identical copy already filed is reused
+    fn process_photo(&self, path: &Path, claimed: &mut HashSet<PathBuf>) -> Result<Photo> {
+        let date = photos::photo_date(path)
+            .ok_or_else(|| RustyDiaryError::ContentIntegrity("No date in the image name or EXIF data".to_string()))?;
+        let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let base = self.root_dir.join(photos::ATTACHMENTS_DIR).join(date.year().to_string()).join(&name);
+
+        let bytes = cloud::retry_locked(|| fs::read(path)).at_path(path)?;
+        let stem = base.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let extension = base.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+        let destination = std::iter::once(base.clone())
+            .chain((2..).map(|n| base.with_file_name(format!("{}-{}{}", stem, n, extension))))
+            .find(|candidate| {
+                !claimed.contains(candidate)
+                    && (!candidate.exists() || fs::read(candidate).is_ok_and(|filed| filed == bytes))
+            })
+            .unwrap();
+        claimed.insert(destination.clone());
+        Ok(Photo { source: path.to_path_buf(), destination, date })
     }
 
     pub fn backup_file<P: AsRef<Path>>(&self, path: P) -> Result<PathBuf> {
         let path = path.as_ref();
         let backup_dir = self.root_dir.join(".backup");
-        fs::create_dir_all(&backup_dir)?;
+        fs::create_dir_all(&backup_dir).at_path(&backup_dir)?;
 
         let filename = path.file_name()
             .ok_or_else(|| RustyDiaryError::ContentIntegrity(
                 "Invalid filename".to_string()
             ))?;
 
-        let timestamp = chrono::Local::now().format("%Y%m%d_%H%M%S");
+        let timestamp = self.clock.now().format(BACKUP_TIMESTAMP);
         let backup_path = backup_dir.join(format!("{}_{}", timestamp, filename.to_string_lossy()));
 
-        fs::copy(path, &backup_path)?;
+        fs::copy(path, &backup_path).at_path(path)?;
         Ok(backup_path)
     }
 }
+
+/// The number made of the first (up to `max_len`) digits of `text`
+fn leading_number(text: &str, max_len: usize) -> Option<u32> {
+    let digits: String = text.chars().take_while(char::is_ascii_digit).collect();
+    if digits.is_empty() || digits.len() > max_len {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Builds the ignore matcher from the built-in locations, the directory's
+/// `.diaryignore` and the configured patterns, later ones taking precedence
+pub(crate) fn build_ignore(root_dir: &Path, patterns: &[String]) -> Result<Gitignore> {
+    let invalid = |e: ignore::Error| RustyDiaryError::InvalidConfig(format!("Invalid ignore pattern: {}", e));
+
+    let mut builder = GitignoreBuilder::new(root_dir);
+    for pattern in BUILTIN_IGNORES {
+        builder.add_line(None, pattern).map_err(invalid)?;
+    }
+    let ignore_file = root_dir.join(IGNORE_FILE);
+    if ignore_file.is_file() {
+        if let Some(e) = builder.add(&ignore_file) {
+            return Err(invalid(e));
+        }
+    }
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(invalid)?;
+    }
+    builder.build().map_err(invalid)
+}
+
+/// Whether `path` already holds exactly `content`, so writing it again would
+/// only bump its modification time
+fn is_up_to_date(path: &Path, content: &str) -> bool {
+    let same_size = fs::metadata(path).is_ok_and(|meta| meta.len() == content.len() as u64);
+    same_size && fs::read(path).is_ok_and(|existing| existing == content.as_bytes())
+}
+
+/// A file's bytes as text, and where its first invalid UTF-8 byte was if
+/// `lossy` let it be read anyway with the bad bytes replaced
+pub(crate) fn decode_text(path: &Path, bytes: Vec<u8>, lossy: bool) -> Result<(String, Option<usize>)> {
+    match String::from_utf8(bytes) {
+        Ok(content) => Ok((content, None)),
+        Err(e) => {
+            let offset = e.utf8_error().valid_up_to();
+            if !lossy {
+                let at = Location::of_offset(e.as_bytes(), offset);
+                return Err(RustyDiaryError::Located {
+                    location: Location { path: Some(path.to_path_buf()), ..at },
+                    source: Box::new(RustyDiaryError::ContentIntegrity(format!("Invalid UTF-8 at byte {}", offset))),
+                });
+            }
+            Ok((String::from_utf8_lossy(e.as_bytes()).into_owned(), Some(offset)))
+        }
+    }
+}
+
+/// Joins entries of the same date, such as a day file and the matching
+/// section of a week file, so one run stores a single version per date
+pub(crate) fn merge_same_dates(entries: Vec<DiaryEntry>) -> Vec<DiaryEntry> {
+    let mut merged: Vec<DiaryEntry> = Vec::with_capacity(entries.len());
+    let mut positions: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+    for entry in entries {
+        match positions.get(&entry.date) {
+            Some(&i) => {
+                let existing = &mut merged[i];
+                existing.content.push_str("\n\n");
+                existing.content.push_str(&entry.content);
+            }
+            None => {
+                positions.insert(entry.date, merged.len());
+                merged.push(entry);
+            }
+        }
+    }
+    merged
+}
@@ -0,0 +1,213 @@
+use std::path::{Component, Path, PathBuf};
+
+use chrono::NaiveDate;
+
+use crate::error::{Result, RustyDiaryError};
+use super::portable::{self, file_name_problem};
+
+/// The journal file name used before templates existed
+pub const DEFAULT_FILENAME: &str = "{prefix}_{date}_{exec_version}.md";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Prefix,
+    Date,
+    ExecVersion,
+    Start,
+    End,
+    Year,
+    Month,
+    Day,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "prefix" => Field::Prefix,
+            "date" => Field::Date,
+            "exec_version" => Field::ExecVersion,
+            "start" => Field::Start,
+            "end" => Field::End,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// Values filled into a filename template
+#[derive(Debug, Clone, Copy)]
+pub struct FilenameValues<'a> {
+    pub prefix: &'a str,
+    /// Day the journal is written
+    pub today: NaiveDate,
+    pub exec_version: i64,
+    /// Oldest and newest entry in the journal
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+/// A journal file name such as `{prefix}-{start}-{end}.md` or
+/// `{year}/{month}.md`, relative to the diary directory.
+///
+/// `{date}` is the day of writing; `{year}`, `{month}` and `{day}` come from
+/// the oldest entry in the journal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilenameTemplate {
+    segments: Vec<Segment>,
+}
+
+impl FilenameTemplate {
+    pub fn parse(template: &str) -> Result<Self> {
+        let invalid = |reason: String| {
+            RustyDiaryError::InvalidConfig(format!("Invalid output filename '{}': {}", template, reason))
+        };
+
+        let mut segments = Vec::new();
+        let mut rest = template;
+        while let Some(open) = rest.find(['{', '}']) {
+            if rest[open..].starts_with('}') {
+                return Err(invalid("unmatched '}'".to_string()));
+            }
+            if open > 0 {
+                segments.push(Segment::Literal(rest[..open].to_string()));
+            }
+
+            let close = rest[open..].find('}').ok_or_else(|| invalid("unclosed '{'".to_string()))?;
+            let name = &rest[open + 1..open + close];
+            let field = Field::parse(name).ok_or_else(|| invalid(format!("unknown placeholder {{{}}}", name)))?;
+            segments.push(Segment::Field(field));
+            rest = &rest[open + close + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        // Placeholders never produce separators, so checking with stand-ins is enough
+        let sample: String = segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Literal(text) => text.as_str(),
+                Segment::Field(_) => "x",
+            })
+            .collect();
+        if sample.is_empty() || sample.ends_with('/') {
+            return Err(invalid("must name a file".to_string()));
+        }
+        // Windows would split on a backslash where other platforms keep it in the name
+        if sample.contains('\\') {
+            return Err(invalid("folders are separated by '/'".to_string()));
+        }
+        if !Path::new(&sample).components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(invalid("must be a relative path without '..'".to_string()));
+        }
+        for name in sample.split('/') {
+            if let Some(problem) = file_name_problem(name) {
+                return Err(invalid(format!("'{}' {}", name, problem)));
+            }
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// The file name for `values`, with the platform's folder separator
+    pub fn render(&self, values: &FilenameValues<'_>) -> PathBuf {
+        let mut name = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => name.push_str(text),
+                Segment::Field(field) => name.push_str(&match field {
+                    Field::Prefix => values.prefix.to_string(),
+                    Field::Date => values.today.to_string(),
+                    Field::ExecVersion => values.exec_version.to_string(),
+                    Field::Start => values.start.to_string(),
+                    Field::End => values.end.to_string(),
+                    Field::Year => values.start.format("%Y").to_string(),
+                    Field::Month => values.start.format("%m").to_string(),
+                    Field::Day => values.start.format("%d").to_string(),
+                }),
+            }
+        }
+        portable::native_path(&name)
+    }
+}
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_FILENAME).unwrap()
+    }
+}
+
+/// `path` if it is free, otherwise the first free `name-2.ext`, `name-3.ext`, ...
+pub fn available_path(path: PathBuf) -> PathBuf {
+    if !path.exists() {
+        return path;
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{}-{}{}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn values() -> FilenameValues<'static> {
+        FilenameValues {
+            prefix: "log",
+            today: NaiveDate::from_ymd_opt(2024, 5, 1).unwrap(),
+            exec_version: 7,
+            start: NaiveDate::from_ymd_opt(2024, 3, 9).unwrap(),
+            end: NaiveDate::from_ymd_opt(2024, 4, 2).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_render() -> Result<()> {
+        assert_eq!(FilenameTemplate::default().render(&values()), PathBuf::from("log_2024-05-01_7.md"));
+        assert_eq!(
+            FilenameTemplate::parse("{prefix}-{start}-{end}.md")?.render(&values()),
+            PathBuf::from("log-2024-03-09-2024-04-02.md")
+        );
+        assert_eq!(FilenameTemplate::parse("{year}/{month}.md")?.render(&values()), PathBuf::from("2024/03.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_validation() {
+        for template in [
+            "{prefix", "log}.md", "{week}.md", "", "out/", "/tmp/{date}.md", "../{date}.md", "a/../b.md",
+            "logs\\{date}.md", "C:{date}.md", "{date} 12:00.md", "con/{date}.md", "aux.md", "logs./{date}.md",
+        ] {
+            assert!(
+                matches!(FilenameTemplate::parse(template), Err(RustyDiaryError::InvalidConfig(_))),
+                "{} should be rejected",
+                template
+            );
+        }
+    }
+
+    #[test]
+    fn test_available_path() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("journal.md");
+        assert_eq!(available_path(path.clone()), path);
+
+        std::fs::write(&path, "")?;
+        std::fs::write(temp_dir.path().join("journal-2.md"), "")?;
+        assert_eq!(available_path(path), temp_dir.path().join("journal-3.md"));
+        Ok(())
+    }
+}
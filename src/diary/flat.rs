@@ -0,0 +1,115 @@
+//! A flat SQLite export for exploring the diary with Datasette or
+//! sqlite-utils: a single `entries` table, one row per exported entry, with
+//! tags and links as JSON arrays. Its columns are documented in the schema
+//! itself and stay put while the internal schema evolves. Built in as the
+//! `sqlite` export target.
+
+use std::fs;
+use std::io;
+
+use rusqlite::{params, Connection};
+
+use crate::error::{IoContext, Result};
+
+use super::export::{EntryStream, ExportSink};
+use super::processor::Exporter;
+
+/// Schema of the exported file; SQLite keeps the comments, so tools showing
+/// the schema show them too
+const SCHEMA: &str = "
+CREATE TABLE entries (
+    -- Day of the entry, as YYYY-MM-DD
+    date TEXT NOT NULL,
+    -- Sync run that stored this version of the day; later runs have higher numbers
+    exec_version INTEGER NOT NULL,
+    -- Who wrote the entry, in diaries shared by several people
+    author TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT,
+    word_count INTEGER NOT NULL,
+    -- Lowercased #hashtags of the content, as a JSON array
+    tags TEXT NOT NULL,
+    -- Targets of the [[wiki links]] in the content, as a JSON array
+    links TEXT NOT NULL,
+    -- The entry as markdown
+    content TEXT NOT NULL,
+    PRIMARY KEY (date, exec_version)
+);
+";
+
+/// Built-in exporter writing entries into a new single-table SQLite file
+pub struct FlatSqliteExporter;
+
+impl Exporter for FlatSqliteExporter {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        let destination = sink.destination();
+        // Written from scratch, like the other targets' files
+        match fs::remove_file(destination) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err).at_path(destination),
+            _ => {}
+        }
+
+        let mut conn = Connection::open(destination)?;
+        conn.execute_batch(SCHEMA)?;
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare(
+                "INSERT OR REPLACE INTO entries
+                    (date, exec_version, author, created_at, updated_at, word_count, tags, links, content)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            )?;
+            for entry in entries {
+                let entry = entry?;
+                let links: Vec<String> = entry.links().into_iter().map(|link| link.target).collect();
+                insert.execute(params![
+                    entry.date.to_string(),
+                    entry.exec_version,
+                    entry.author,
+                    entry.created_at.to_string(),
+                    entry.updated_at.map(|at| at.to_string()),
+                    entry.word_count(),
+                    serde_json::to_string(&entry.tags())?,
+                    serde_json::to_string(&links)?,
+                    entry.content,
+                ])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::diary::export::export_all;
+    use crate::storage::models::DiaryEntry;
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_flat_export() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let out = temp_dir.path().join("flat.db");
+        fs::write(&out, "not a database")?;
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        let entry = DiaryEntry::new(3, date, "Hiking #Outdoors with [[Ana]], see [[2024-03-04]]".to_string(), now);
+        export_all(&FlatSqliteExporter, &[entry], &mut ExportSink::new(&out))?;
+
+        let conn = Connection::open(&out)?;
+        let row: (String, i64, i64, String, String) = conn.query_row(
+            "SELECT date, exec_version, word_count, tags, links FROM entries",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+        )?;
+        assert_eq!(row, ("2024-03-05".to_string(), 3, 6, r#"["outdoors"]"#.to_string(), r#"["Ana","2024-03-04"]"#.to_string()));
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+//! Markdown flavors entries are written in. Entries are stored as written;
+//! other flavors are turned into the CommonMark the renderers read when an
+//! entry is shown or exported.
+//!
+//! The `obsidian` flavor covers what Obsidian vaults use beyond CommonMark:
+//! `%%comments%%` are dropped, `![[embeds]]` become images or links,
+//! `[[target|alias]]` wiki links become markdown links and `> [!note]`
+//! callouts become quotes under a bold title.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Serialize;
+
+use crate::error::{Result, RustyDiaryError};
+
+/// Extensions of the files an embed shows as an image, lowercase
+const IMAGE_EXTENSIONS: [&str; 8] = ["jpg", "jpeg", "png", "gif", "webp", "svg", "bmp", "heic"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MarkdownFlavor {
+    /// CommonMark with GitHub's tables, task lists and footnotes
+    #[default]
+    CommonMark,
+    /// Obsidian's embeds, comments, callouts and aliased wiki links
+    Obsidian,
+}
+
+impl MarkdownFlavor {
+    pub const NAMES: &'static [&'static str] = &["commonmark", "obsidian"];
+
+    pub fn as_str(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+
+    /// The content as CommonMark, for rendering
+    pub fn to_commonmark(self, content: &str) -> Cow<'_, str> {
+        match self {
+            MarkdownFlavor::CommonMark => Cow::Borrowed(content),
+            MarkdownFlavor::Obsidian => Cow::Owned(obsidian(content)),
+        }
+    }
+}
+
+impl FromStr for MarkdownFlavor {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "commonmark" => Ok(MarkdownFlavor::CommonMark),
+            "obsidian" => Ok(MarkdownFlavor::Obsidian),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown markdown flavor '{}' (expected one of {})",
+                name,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for MarkdownFlavor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Whether an embed target is shown as an image
+pub(crate) fn is_image(target: &str) -> bool {
+    target
+        .rsplit_once('.')
+        .is_some_and(|(_, ext)| IMAGE_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+fn obsidian(content: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    // The fence of the code block being read, kept as written
+    let mut fence: Option<&str> = None;
+    let mut in_comment = false;
+    let mut in_quote = false;
+
+    for line in content.split_inclusive('\n') {
+        let text = line.trim_end_matches(['\n', '\r']);
+        let trimmed = text.trim_start();
+        if let Some(open) = fence {
+            if trimmed.starts_with(open) {
+                fence = None;
+            }
+            out.push_str(line);
+            continue;
+        }
+        if !in_comment {
+            if let Some(open) = ["```", "~~~"].into_iter().find(|open| trimmed.starts_with(open)) {
+                fence = Some(open);
+                out.push_str(line);
+                continue;
+            }
+        }
+
+        let commented = in_comment || text.contains("%%");
+        let converted = inline(text, &mut in_comment);
+        if commented && converted.trim().is_empty() {
+            // A line holding only a comment goes with it
+            continue;
+        }
+
+        let quoted = converted.trim_start().starts_with('>');
+        match callout(&converted).filter(|_| !in_quote) {
+            Some((prefix, title)) => out.push_str(&format!("{}**{}**\n{}", prefix, title, prefix.trim_end())),
+            None => out.push_str(&converted),
+        }
+        in_quote = quoted;
+        out.push_str(&line[text.len()..]);
+    }
+    out
+}
+
+/// A line with comments dropped and embeds and wiki links turned into
+/// markdown. Code spans are kept as written.
+fn inline(text: &str, in_comment: &mut bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("%%") {
+            *in_comment = !*in_comment;
+            rest = after;
+            continue;
+        }
+        if *in_comment {
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        if c == '`' {
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let end = rest[ticks..].find(&rest[..ticks]).map_or(ticks, |end| 2 * ticks + end);
+            out.push_str(&rest[..end]);
+            rest = &rest[end..];
+            continue;
+        }
+
+        let embed = rest.starts_with("![[");
+        let start = if embed { 3 } else { 2 };
+        if embed || rest.starts_with("[[") {
+            if let Some(end) = rest[start..].find("]]").filter(|end| !rest[start..start + end].contains(['[', ']'])) {
+                if let Some(markdown) = wiki(&rest[start..start + end], embed) {
+                    out.push_str(&markdown);
+                    rest = &rest[start + end + 2..];
+                    continue;
+                }
+            }
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// The markdown for the inside of `[[...]]`, or of `![[...]]` when `embed`
+fn wiki(inner: &str, embed: bool) -> Option<String> {
+    let (target, alias) = match inner.split_once('|') {
+        Some((target, alias)) => (target.trim(), Some(alias.trim())),
+        None => (inner.trim(), None),
+    };
+    let (path, heading) = match target.split_once('#') {
+        Some((path, heading)) => (path.trim(), Some(heading.trim())),
+        None => (target, None),
+    };
+    if path.is_empty() && heading.is_none() {
+        return None;
+    }
+
+    if embed && is_image(path) {
+        // The alias of an image embed is its size, such as `|300`
+        let stem = path.rsplit('/').next().unwrap_or(path);
+        let stem = stem.rsplit_once('.').map_or(stem, |(stem, _)| stem);
+        return Some(format!("![{}]({})", stem, url(path)));
+    }
+
+    let mut href = if path.is_empty() || path.contains('.') { path.to_string() } else { format!("{}.md", path) };
+    href = url(&href);
+    if let Some(heading) = heading {
+        href.push('#');
+        href.push_str(&heading.to_lowercase().split_whitespace().collect::<Vec<_>>().join("-"));
+    }
+    let text = match (alias, heading) {
+        (Some(alias), _) if !alias.is_empty() => alias.to_string(),
+        (_, Some(heading)) if !path.is_empty() => format!("{} > {}", path, heading),
+        (_, Some(heading)) => heading.to_string(),
+        _ => path.to_string(),
+    };
+    Some(format!("[{}]({})", text, href))
+}
+
+fn url(path: &str) -> String {
+    path.replace(' ', "%20").replace('(', "%28").replace(')', "%29")
+}
+
+/// The quote prefix and title of a callout's first line, e.g.
+/// `> [!warning]- Careful`
+fn callout(line: &str) -> Option<(&str, String)> {
+    let marker = line.find("[!")?;
+    let prefix = &line[..marker];
+    if !prefix.trim_start().starts_with('>') || !prefix.trim_start().trim_start_matches(['>', ' ']).is_empty() {
+        return None;
+    }
+    let (kind, title) = line[marker + 2..].split_once(']')?;
+    if kind.is_empty() || !kind.chars().all(|c| c.is_alphanumeric() || c == '-') {
+        return None;
+    }
+    let kind = kind.to_lowercase();
+    let title = title.trim_start_matches(['+', '-']).trim();
+    let title = if title.is_empty() {
+        let mut chars = kind.chars();
+        chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default()
+    } else {
+        title.to_string()
+    };
+    let icon = match kind.as_str() {
+        "note" => "📝",
+        "abstract" | "summary" | "tldr" => "📋",
+        "info" | "todo" => "ℹ️",
+        "tip" | "hint" | "important" => "💡",
+        "success" | "check" | "done" => "✅",
+        "question" | "help" | "faq" => "❓",
+        "warning" | "caution" | "attention" => "⚠️",
+        "failure" | "fail" | "missing" => "❌",
+        "danger" | "error" => "⛔",
+        "bug" => "🐛",
+        "example" => "🧪",
+        "quote" | "cite" => "💬",
+        _ => return Some((prefix, title)),
+    };
+    Some((prefix, format!("{} {}", icon, title)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(content: &str) -> String {
+        MarkdownFlavor::Obsidian.to_commonmark(content).into_owned()
+    }
+
+    #[test]
+    fn test_obsidian_links_and_embeds() {
+        assert_eq!(
+            convert("See [[2024-01-02]], [[Reading List|books]] and [[Plans#Next Week]].\n"),
+            "See [2024-01-02](2024-01-02.md), [books](Reading%20List.md) and [Plans > Next Week](Plans.md#next-week).\n"
+        );
+        assert_eq!(
+            convert("![[attachments/2024/beach day.jpg|300]] ![[2024-01-02]] `[[code]]`"),
+            "![beach day](attachments/2024/beach%20day.jpg) [2024-01-02](2024-01-02.md) `[[code]]`"
+        );
+        assert_eq!(convert("[[ ]] [[a]b]]"), "[[ ]] [[a]b]]");
+        assert_eq!(MarkdownFlavor::CommonMark.to_commonmark("[[a|b]]"), "[[a|b]]");
+    }
+
+    #[test]
+    fn test_obsidian_comments() {
+        assert_eq!(convert("Kept %%hidden%% text\n%%\nwhole\nblock\n%%\nafter `%%code%%`\n"), "Kept  text\nafter `%%code%%`\n");
+        assert_eq!(convert("```\n%% not a comment\n```\n"), "```\n%% not a comment\n```\n");
+    }
+
+    #[test]
+    fn test_obsidian_callouts() {
+        assert_eq!(
+            convert("> [!warning]- Mind the gap\n> Trains are late\n\n> [!custom]\n> x\n> [!note] not a callout"),
+            "> **⚠️ Mind the gap**\n>\n> Trains are late\n\n> **Custom**\n>\n> x\n> [!note] not a callout"
+        );
+    }
+
+    #[test]
+    fn test_flavor_names() {
+        assert_eq!("obsidian".parse::<MarkdownFlavor>().unwrap(), MarkdownFlavor::Obsidian);
+        assert_eq!(MarkdownFlavor::default().to_string(), "commonmark");
+        assert!("roam".parse::<MarkdownFlavor>().is_err());
+    }
+}
@@ -0,0 +1,151 @@
+//! Habit tracking from checklists written in entries, e.g.
+//! `- [x] meditate` or `- [ ] run`. Only the habits named in the
+//! configuration are tracked; a day whose entry has no item for a habit is
+//! left out of its statistics rather than counted as missed.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use serde::Serialize;
+
+use crate::storage::HabitCheck;
+
+/// The configured habits found in `content`'s checklist items, as
+/// (habit, done). A habit listed twice is done if either item is ticked.
+pub fn parse_checks(content: &str, habits: &[String]) -> Vec<(String, bool)> {
+    let mut checks: BTreeMap<&str, bool> = BTreeMap::new();
+    for line in content.lines() {
+        let Some((done, item)) = checklist_item(line) else { continue };
+        if let Some(habit) = habits.iter().find(|habit| names_habit(item, habit)) {
+            *checks.entry(habit.as_str()).or_default() |= done;
+        }
+    }
+    checks.into_iter().map(|(habit, done)| (habit.to_string(), done)).collect()
+}
+
+/// Whether a `- [x] text` item is ticked, and its text
+pub(crate) fn checklist_item(line: &str) -> Option<(bool, &str)> {
+    let item = line.trim_start().strip_prefix(['-', '*', '+'])?.strip_prefix(' ')?.trim_start();
+    let (done, text) = if let Some(text) = item.strip_prefix("[ ]") {
+        (false, text)
+    } else {
+        (true, item.strip_prefix("[x]").or_else(|| item.strip_prefix("[X]"))?)
+    };
+    Some((done, text.trim()))
+}
+
+/// Whether an item's text is the habit, alone or followed by details such
+/// as `meditate (20 min)`
+pub(crate) fn names_habit(text: &str, habit: &str) -> bool {
+    let habit = habit.trim();
+    let Some(head) = text.get(..habit.len()) else { return false };
+    head.eq_ignore_ascii_case(habit) && text[habit.len()..].chars().next().is_none_or(|c| !c.is_alphanumeric())
+}
+
+/// Completion of a habit in one month
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MonthlyCompletion {
+    /// The month, as `YYYY-MM`
+    pub month: String,
+    pub done: usize,
+    /// Days whose entry had an item for the habit
+    pub checked: usize,
+    pub percent: f64,
+}
+
+/// Streaks and monthly completion of one habit
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct HabitStats {
+    pub habit: String,
+    /// Consecutive days done up to the last day checked
+    pub current_streak: usize,
+    pub longest_streak: usize,
+    pub months: Vec<MonthlyCompletion>,
+}
+
+/// Statistics of each habit in `checks`, by habit name
+pub fn habit_stats(checks: &[HabitCheck]) -> Vec<HabitStats> {
+    let mut by_habit: BTreeMap<&str, Vec<&HabitCheck>> = BTreeMap::new();
+    for check in checks {
+        by_habit.entry(&check.habit).or_default().push(check);
+    }
+
+    by_habit
+        .into_iter()
+        .map(|(habit, mut checks)| {
+            checks.sort_by_key(|check| check.date);
+
+            let (mut current, mut longest) = (0, 0);
+            let mut previous: Option<NaiveDate> = None;
+            let mut months: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+            for check in checks {
+                let follows = previous.and_then(|date| date.succ_opt()) == Some(check.date);
+                current = match (check.done, follows) {
+                    (false, _) => 0,
+                    (true, true) => current + 1,
+                    (true, false) => 1,
+                };
+                longest = longest.max(current);
+                previous = Some(check.date);
+
+                let month = months.entry(format!("{}-{:02}", check.date.year(), check.date.month())).or_default();
+                month.0 += usize::from(check.done);
+                month.1 += 1;
+            }
+
+            HabitStats {
+                habit: habit.to_string(),
+                current_streak: current,
+                longest_streak: longest,
+                months: months
+                    .into_iter()
+                    .map(|(month, (done, checked))| MonthlyCompletion {
+                        month,
+                        done,
+                        checked,
+                        percent: done as f64 * 100.0 / checked as f64,
+                    })
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checks() {
+        let habits = vec!["meditate".to_string(), "Run".to_string(), "read".to_string()];
+        let content = "Morning\n- [x] Meditate (20 min)\n  * [ ] run\n- [ ] meditate\n- [x] reading list\nText [x] read";
+        assert_eq!(
+            parse_checks(content, &habits),
+            vec![("Run".to_string(), false), ("meditate".to_string(), true)]
+        );
+        assert!(parse_checks("- [x] meditate", &[]).is_empty());
+    }
+
+    #[test]
+    fn test_streaks_and_months() {
+        let check = |day: &str, done| HabitCheck {
+            date: day.parse().unwrap(),
+            habit: "run".to_string(),
+            done,
+        };
+        let checks = [
+            check("2024-01-30", true),
+            check("2024-01-31", true),
+            check("2024-02-01", true),
+            check("2024-02-02", false),
+            check("2024-02-04", true),
+            check("2024-02-05", true),
+        ];
+
+        let stats = habit_stats(&checks);
+        assert_eq!((stats[0].current_streak, stats[0].longest_streak), (2, 3));
+        assert_eq!(stats[0].months[0], MonthlyCompletion { month: "2024-01".to_string(), done: 2, checked: 2, percent: 100.0 });
+        assert_eq!((stats[0].months[1].done, stats[0].months[1].checked), (3, 4));
+        assert_eq!(stats[0].months[1].percent, 75.0);
+    }
+}
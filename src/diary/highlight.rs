@@ -0,0 +1,145 @@
+//! Syntax highlighting of fenced code blocks for the HTML export and the
+//! terminal, using the syntaxes and themes bundled with syntect. Builds
+//! without the `highlight` feature leave code blocks as they are.
+
+use std::fmt;
+
+use crate::error::{Result, RustyDiaryError};
+
+/// Theme for pages, which have a light background
+pub const DEFAULT_HTML_THEME: &str = "InspiredGitHub";
+/// Theme for terminals, which mostly have a dark one
+pub const DEFAULT_TERMINAL_THEME: &str = "base16-ocean.dark";
+
+/// A run of code in one color
+pub type Piece = ((u8, u8, u8), String);
+
+/// Highlights code blocks with one theme
+pub struct Highlighter {
+    #[cfg(feature = "highlight")]
+    theme: syntect::highlighting::Theme,
+    #[cfg(not(feature = "highlight"))]
+    theme: String,
+}
+
+impl fmt::Debug for Highlighter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "highlight")]
+        let theme = self.theme.name.as_deref().unwrap_or_default();
+        #[cfg(not(feature = "highlight"))]
+        let theme = &self.theme;
+        f.debug_struct("Highlighter").field("theme", &theme).finish()
+    }
+}
+
+#[cfg(feature = "highlight")]
+fn syntaxes() -> &'static syntect::parsing::SyntaxSet {
+    // Loading the bundled syntaxes takes a while; only do it once, and only
+    // when a code block needs them
+    static SYNTAXES: std::sync::OnceLock<syntect::parsing::SyntaxSet> = std::sync::OnceLock::new();
+    SYNTAXES.get_or_init(syntect::parsing::SyntaxSet::load_defaults_newlines)
+}
+
+impl Highlighter {
+    /// Highlights with one of `Highlighter::themes()`
+    pub fn new(theme: &str) -> Result<Self> {
+        #[cfg(feature = "highlight")]
+        {
+            let mut themes = syntect::highlighting::ThemeSet::load_defaults().themes;
+            let found = themes.remove(theme).ok_or_else(|| {
+                RustyDiaryError::InvalidConfig(format!(
+                    "Unknown highlight theme '{}' (expected one of {})",
+                    theme,
+                    Self::themes().join(", ")
+                ))
+            })?;
+            Ok(Self { theme: found })
+        }
+        #[cfg(not(feature = "highlight"))]
+        {
+            if theme.is_empty() {
+                return Err(RustyDiaryError::InvalidConfig("Empty highlight theme".to_string()));
+            }
+            Ok(Self { theme: theme.to_string() })
+        }
+    }
+
+    /// Names of the themes available; none without the `highlight` feature
+    pub fn themes() -> Vec<String> {
+        #[cfg(feature = "highlight")]
+        return syntect::highlighting::ThemeSet::load_defaults().themes.into_keys().collect();
+        #[cfg(not(feature = "highlight"))]
+        Vec::new()
+    }
+
+    /// The code as a `<pre>` block with inline colors, or `None` when the
+    /// language is not known
+    pub fn html(&self, code: &str, language: &str) -> Option<String> {
+        #[cfg(feature = "highlight")]
+        {
+            let syntax = syntaxes().find_syntax_by_token(language)?;
+            syntect::html::highlighted_html_for_string(code, syntaxes(), syntax, &self.theme).ok()
+        }
+        #[cfg(not(feature = "highlight"))]
+        {
+            let _ = (code, language);
+            None
+        }
+    }
+
+    /// The code's lines split into colored pieces, or `None` when the
+    /// language is not known
+    pub fn lines(&self, code: &str, language: &str) -> Option<Vec<Vec<Piece>>> {
+        #[cfg(feature = "highlight")]
+        {
+            use syntect::easy::HighlightLines;
+            use syntect::util::LinesWithEndings;
+
+            let syntax = syntaxes().find_syntax_by_token(language)?;
+            let mut highlighter = HighlightLines::new(syntax, &self.theme);
+            let mut lines = Vec::new();
+            for line in LinesWithEndings::from(code) {
+                let regions = highlighter.highlight_line(line, syntaxes()).ok()?;
+                let pieces = regions
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let color = style.foreground;
+                        ((color.r, color.g, color.b), text.trim_end_matches(['\n', '\r']).to_string())
+                    })
+                    .filter(|(_, text)| !text.is_empty())
+                    .collect();
+                lines.push(pieces);
+            }
+            Some(lines)
+        }
+        #[cfg(not(feature = "highlight"))]
+        {
+            let _ = (code, language);
+            None
+        }
+    }
+}
+
+#[cfg(all(test, feature = "highlight"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlighter() -> Result<()> {
+        assert!(Highlighter::themes().iter().any(|theme| theme == DEFAULT_HTML_THEME));
+        assert!(Highlighter::new("neon").is_err());
+
+        let highlighter = Highlighter::new(DEFAULT_TERMINAL_THEME)?;
+        let code = "fn main() {\n    let x = 1;\n}\n";
+        let lines = highlighter.lines(code, "rust").unwrap();
+        assert_eq!(lines.len(), 3);
+        let text: String = lines[1].iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(text, "    let x = 1;");
+        assert!(lines[0].len() > 1, "{:?}", lines[0]);
+
+        let html = Highlighter::new(DEFAULT_HTML_THEME)?.html(code, "rs").unwrap();
+        assert!(html.starts_with("<pre style=\"background-color:#ffffff;\">"), "{}", html);
+        assert!(highlighter.lines(code, "no-such-language").is_none());
+        Ok(())
+    }
+}
@@ -0,0 +1,590 @@
+//! A single-page HTML export: every exported entry rendered from markdown,
+//! oldest first as given. Built in as the `html` export target.
+//!
+//! Local images are resolved against the diary directory. By default they
+//! link to the original files; `AttachmentMode::Embed` and `Copy` instead
+//! put thumbnails in the page as data URIs or in a `<name>_files` folder
+//! next to it, each linking to its original, so an export of a photo-heavy
+//! diary stays small. Thumbnails need the `images` feature; without it,
+//! images are embedded or copied at full size.
+//!
+//! Fenced `mermaid` blocks become diagrams drawn in the browser by the
+//! mermaid script, which the page loads only when it has one. A
+//! `DiagramRenderer` can draw them at export time instead.
+//!
+//! Entries in another markdown flavor are turned into CommonMark first.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use pulldown_cmark::{escape, html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::hooks;
+
+use super::flavor::MarkdownFlavor;
+use super::highlight::Highlighter;
+use super::export::{EntryStream, ExportSink};
+use super::processor::{resolve_shortcodes, Exporter};
+
+/// Longest side of a thumbnail unless configured otherwise, in pixels
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 640;
+
+/// Where pages load the mermaid script from unless configured otherwise
+pub const DEFAULT_MERMAID_SCRIPT: &str = "https://cdn.jsdelivr.net/npm/mermaid@10/dist/mermaid.min.js";
+
+/// Language of the fenced blocks drawn as diagrams
+const MERMAID: &str = "mermaid";
+
+const STYLE: &str = "body{max-width:42em;margin:2em auto;padding:0 1em;font-family:serif;line-height:1.5}\
+img{max-width:100%}article{margin-bottom:3em}";
+
+/// How local images travel with an HTML export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AttachmentMode {
+    /// Point at the original files
+    #[default]
+    Link,
+    /// Embed thumbnails in the page as data URIs
+    Embed,
+    /// Copy thumbnails into a folder next to the page
+    Copy,
+}
+
+impl AttachmentMode {
+    pub const NAMES: &'static [&'static str] = &["link", "embed", "copy"];
+}
+
+impl FromStr for AttachmentMode {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "link" => Ok(AttachmentMode::Link),
+            "embed" => Ok(AttachmentMode::Embed),
+            "copy" => Ok(AttachmentMode::Copy),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown attachment mode '{}' (expected one of {})",
+                name,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for AttachmentMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(Self::NAMES[*self as usize])
+    }
+}
+
+/// Draws diagrams when exporting, e.g. as SVG, so pages need no script
+pub trait DiagramRenderer: Send + Sync {
+    /// Markup for the diagram in `source`, written in `language`, or none
+    /// to leave it to the browser
+    fn render(&self, language: &str, source: &str) -> Result<Option<String>>;
+}
+
+/// Renders diagrams with a shell command, such as a wrapper around the
+/// mermaid CLI, that reads the source on stdin and writes markup (usually
+/// an SVG) to stdout. `RUSTY_DIARY_DIAGRAM` holds the diagram's language.
+#[derive(Debug, Clone)]
+pub struct CommandRenderer {
+    command: String,
+}
+
+impl CommandRenderer {
+    pub fn new(command: &str) -> Self {
+        Self { command: command.to_string() }
+    }
+}
+
+impl DiagramRenderer for CommandRenderer {
+    fn render(&self, language: &str, source: &str) -> Result<Option<String>> {
+        let mut process = hooks::shell(&self.command);
+        process.env("RUSTY_DIARY_DIAGRAM", language).stdin(Stdio::piped()).stdout(Stdio::piped());
+        let mut child = process.spawn()?;
+        let stdin = child.stdin.take();
+        // Feed the source while reading the markup, so a command writing
+        // before it has read everything cannot block on a full pipe
+        let output = std::thread::scope(|scope| {
+            scope.spawn(|| stdin.map(|mut pipe| pipe.write_all(source.as_bytes())));
+            child.wait_with_output()
+        })?;
+
+        if !output.status.success() {
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "Diagram command `{}` exited with {}",
+                self.command, output.status
+            )));
+        }
+        let markup = String::from_utf8_lossy(&output.stdout).into_owned();
+        Ok(Some(markup).filter(|markup| !markup.trim().is_empty()))
+    }
+}
+
+/// What a page needs beyond its entries, found while rendering them
+#[derive(Debug, Default)]
+struct Page {
+    /// Whether any diagram is left for the browser to draw
+    diagrams: bool,
+}
+
+/// Built-in exporter writing entries as one HTML page
+#[derive(Clone)]
+pub struct HtmlExporter {
+    attachments: AttachmentMode,
+    thumbnail_size: u32,
+    /// Colors fenced code blocks in a language it knows
+    highlighter: Option<Arc<Highlighter>>,
+    /// Draws diagrams at export time; the browser does otherwise
+    diagrams: Option<Arc<dyn DiagramRenderer>>,
+    mermaid_script: String,
+    flavor: MarkdownFlavor,
+}
+
+impl fmt::Debug for HtmlExporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HtmlExporter")
+            .field("attachments", &self.attachments)
+            .field("thumbnail_size", &self.thumbnail_size)
+            .field("highlighter", &self.highlighter)
+            .field("diagrams", &self.diagrams.is_some())
+            .field("mermaid_script", &self.mermaid_script)
+            .field("flavor", &self.flavor)
+            .finish()
+    }
+}
+
+impl Default for HtmlExporter {
+    fn default() -> Self {
+        Self {
+            attachments: AttachmentMode::Link,
+            thumbnail_size: DEFAULT_THUMBNAIL_SIZE,
+            highlighter: None,
+            diagrams: None,
+            mermaid_script: DEFAULT_MERMAID_SCRIPT.to_string(),
+            flavor: MarkdownFlavor::CommonMark,
+        }
+    }
+}
+
+impl HtmlExporter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Embeds or copies images as thumbnails whose longest side is at most
+    /// `thumbnail_size` pixels
+    pub fn with_attachments(mut self, mode: AttachmentMode, thumbnail_size: u32) -> Self {
+        self.attachments = mode;
+        self.thumbnail_size = thumbnail_size;
+        self
+    }
+
+    /// Highlights fenced code blocks that name their language
+    pub fn with_highlighter(mut self, highlighter: Arc<Highlighter>) -> Self {
+        self.highlighter = Some(highlighter);
+        self
+    }
+
+    /// Draws diagrams at export time, falling back to the browser for those
+    /// the renderer leaves out or fails on
+    pub fn with_diagram_renderer(mut self, renderer: Arc<dyn DiagramRenderer>) -> Self {
+        self.diagrams = Some(renderer);
+        self
+    }
+
+    /// Loads the mermaid script from `src`, e.g. a local copy for pages
+    /// read offline
+    pub fn with_mermaid_script(mut self, src: &str) -> Self {
+        self.mermaid_script = src.to_string();
+        self
+    }
+
+    /// Reads entries as written in `flavor`
+    pub fn with_flavor(mut self, flavor: MarkdownFlavor) -> Self {
+        self.flavor = flavor;
+        self
+    }
+
+    /// The page for the entries
+    fn render(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<String> {
+        let mut out = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Diary</title>\n\
+             <style>{}</style>\n</head>\n<body>\n",
+            STYLE
+        );
+        let mut page = Page::default();
+        for entry in entries {
+            let entry = entry?;
+            out.push_str(&format!(
+                "<article id=\"{date}-v{version}\">\n<header><time datetime=\"{date}\">{date}</time></header>\n",
+                date = entry.date,
+                version = entry.exec_version
+            ));
+            let content = self.flavor.to_commonmark(&entry.content);
+            html::push_html(&mut out, self.events(&content, sink, &mut page)?.into_iter());
+            out.push_str("</article>\n");
+        }
+        if page.diagrams {
+            out.push_str(&format!(
+                "<script src=\"{}\"></script>\n<script>mermaid.initialize({{startOnLoad:true}});</script>\n",
+                href(&self.mermaid_script)
+            ));
+        }
+        out.push_str("</body>\n</html>\n");
+        Ok(out)
+    }
+
+    /// The markdown events of an entry, with local images resolved, fenced
+    /// code highlighted and diagrams drawn or left for the browser
+    fn events<'a>(&self, content: &'a str, sink: &mut ExportSink, page: &mut Page) -> Result<Vec<Event<'a>>> {
+        let mut linked = false;
+        // Inside a code block, where text is shown as written
+        let mut verbatim = false;
+        // Language and events of the fenced code block being replaced
+        let mut code: Option<(String, Vec<Event>)> = None;
+        let mut events = Vec::new();
+        for event in Parser::new_ext(content, options()) {
+            match event {
+                Event::Start(Tag::CodeBlock(_)) => verbatim = true,
+                Event::End(Tag::CodeBlock(_)) => verbatim = false,
+                _ => {}
+            }
+            if let Some((language, block)) = &mut code {
+                if !matches!(event, Event::End(Tag::CodeBlock(_))) {
+                    block.push(event);
+                    continue;
+                }
+                match self.code_block(language, &block_text(block), page) {
+                    Some(replaced) => {
+                        events.pop();
+                        events.push(Event::Html(replaced.into()));
+                    }
+                    None => {
+                        events.append(block);
+                        events.push(event);
+                    }
+                }
+                code = None;
+                continue;
+            }
+            match event {
+                Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(ref language)))
+                    if self.highlighter.is_some() || fence_language(language) == MERMAID =>
+                {
+                    code = Some((fence_language(language).to_string(), Vec::new()));
+                    events.push(event);
+                }
+                Event::Start(Tag::Image(kind, target, title)) => match self.image(&target, sink)? {
+                    Some((src, original)) => {
+                        events.push(Event::Html(format!("<a href=\"{}\">", href(&original)).into()));
+                        events.push(Event::Start(Tag::Image(kind, CowStr::from(src), title)));
+                        linked = true;
+                    }
+                    None => events.push(Event::Start(Tag::Image(kind, target, title))),
+                },
+                Event::End(Tag::Image(..)) => {
+                    events.push(event);
+                    if std::mem::take(&mut linked) {
+                        events.push(Event::Html("</a>".into()));
+                    }
+                }
+                Event::Text(text) if !verbatim => match resolve_shortcodes(&text) {
+                    Cow::Owned(resolved) => events.push(Event::Text(resolved.into())),
+                    Cow::Borrowed(_) => events.push(Event::Text(text)),
+                },
+                event => events.push(event),
+            }
+        }
+        Ok(events)
+    }
+
+    /// The HTML replacing a fenced block: a diagram, or code highlighted
+    /// as a `<pre>` block. `None` keeps the block as plain code.
+    fn code_block(&self, language: &str, source: &str, page: &mut Page) -> Option<String> {
+        if language != MERMAID {
+            return self.highlighter.as_ref()?.html(source, language);
+        }
+        if let Some(renderer) = &self.diagrams {
+            match renderer.render(language, source) {
+                Ok(Some(markup)) => return Some(format!("<figure class=\"diagram\">{}</figure>\n", markup.trim())),
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Leaving a {} diagram to the browser: {}", language, e),
+            }
+        }
+        page.diagrams = true;
+        let mut out = String::from("<pre class=\"mermaid\">");
+        // Writing to a String cannot fail
+        let _ = escape::escape_html(&mut out, source);
+        out.push_str("</pre>\n");
+        Some(out)
+    }
+
+    /// The `src` for an image and the original it links to, or `None` to
+    /// leave the image as written
+    fn image(&self, target: &str, sink: &mut ExportSink) -> Result<Option<(String, String)>> {
+        let Some(path) = sink.attachment(target) else { return Ok(None) };
+        let original = fs::canonicalize(&path).at_path(&path)?.to_string_lossy().into_owned();
+
+        let src = match self.attachments {
+            AttachmentMode::Link => original.clone(),
+            AttachmentMode::Embed => {
+                let bytes = thumbnail(&path, self.thumbnail_size)?;
+                format!("data:{};base64,{}", mime_type(&path), STANDARD.encode(bytes))
+            }
+            AttachmentMode::Copy => sink.copy_attachment(&path, &thumbnail(&path, self.thumbnail_size)?)?,
+        };
+        Ok(Some((src, original)))
+    }
+}
+
+impl Exporter for HtmlExporter {
+    fn name(&self) -> &str {
+        "html"
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        let page = self.render(entries, sink)?;
+        sink.write(page)
+    }
+}
+
+/// GitHub-flavored markdown as diaries use it: tables, task lists,
+/// footnotes and strikethrough
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS | Options::ENABLE_FOOTNOTES | Options::ENABLE_STRIKETHROUGH
+}
+
+/// The language a fence names, e.g. `rust` for ` ```rust title="x" `
+fn fence_language(info: &str) -> &str {
+    info.split_whitespace().next().unwrap_or_default()
+}
+
+/// The text of a code block's events
+fn block_text(block: &[Event]) -> String {
+    block
+        .iter()
+        .filter_map(|event| match event {
+            Event::Text(text) => Some(&**text),
+            _ => None,
+        })
+        .collect()
+}
+
+fn href(target: &str) -> String {
+    let mut out = String::new();
+    // Writing to a String cannot fail
+    let _ = escape::escape_href(&mut out, target);
+    out
+}
+
+fn mime_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).map(str::to_lowercase).as_deref() {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+/// The image scaled down to fit `size` pixels, in its own format. Images
+/// already small enough, and formats that cannot be decoded, are returned
+/// as they are.
+#[cfg(feature = "images")]
+fn thumbnail(path: &Path, size: u32) -> Result<Vec<u8>> {
+    use image::{GenericImageView, ImageFormat};
+
+    let bytes = fs::read(path).at_path(path)?;
+    let format = match ImageFormat::from_path(path) {
+        Ok(format @ (ImageFormat::Jpeg | ImageFormat::Png)) => format,
+        _ => return Ok(bytes),
+    };
+    let image = match image::load_from_memory_with_format(&bytes, format) {
+        Ok(image) => image,
+        Err(e) => {
+            tracing::warn!("Keeping {} at full size: {}", path.display(), e);
+            return Ok(bytes);
+        }
+    };
+    let (width, height) = image.dimensions();
+    if width <= size && height <= size {
+        return Ok(bytes);
+    }
+    let mut out = std::io::Cursor::new(Vec::new());
+    image
+        .thumbnail(size, size)
+        .write_to(&mut out, format)
+        .map_err(|e| RustyDiaryError::InvalidConfig(format!("Cannot write a thumbnail of {}: {}", path.display(), e)))?;
+    Ok(out.into_inner())
+}
+
+#[cfg(not(feature = "images"))]
+fn thumbnail(path: &Path, _size: u32) -> Result<Vec<u8>> {
+    fs::read(path).at_path(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::diary::export::export_all;
+    use crate::storage::models::DiaryEntry;
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_html_export() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let diary = temp_dir.path().join("diary");
+        fs::create_dir_all(diary.join("photos"))?;
+        fs::write(diary.join("photos/beach day.png"), b"not really a png")?;
+
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let content = "# Beach\nSun & *sea*\n\n![shore](photos/beach%20day.png) ![gone](missing.jpg) ![web](https://example.com/a.png)";
+        let entries = vec![DiaryEntry::new(2, date, content.to_string(), now)];
+        let out = temp_dir.path().join("diary.html");
+
+        export_all(&HtmlExporter::new(), &entries, &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains("<article id=\"2024-05-01-v2\">"));
+        assert!(page.contains("<h1>Beach</h1>\n<p>Sun &amp; <em>sea</em></p>"), "{}", page);
+        assert!(page.contains("<img src=\"photos/beach%20day.png\" alt=\"shore\" />"));
+
+        let original = fs::canonicalize(diary.join("photos/beach day.png"))?;
+        let original = href(&original.to_string_lossy());
+        export_all(&HtmlExporter::new(), &entries, &mut ExportSink::new(&out).with_directory(&diary))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains(&format!("<a href=\"{0}\"><img src=\"{0}\" alt=\"shore\" /></a>", original)), "{}", page);
+        assert!(page.contains("<img src=\"missing.jpg\" alt=\"gone\" />"));
+        assert!(page.contains("<img src=\"https://example.com/a.png\" alt=\"web\" />"));
+
+        export_all(&HtmlExporter::new().with_attachments(AttachmentMode::Embed, 64), &entries, &mut ExportSink::new(&out).with_directory(&diary))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains(&format!("<a href=\"{}\"><img src=\"data:image/png;base64,", original)));
+
+        export_all(&HtmlExporter::new().with_attachments(AttachmentMode::Copy, 64), &entries, &mut ExportSink::new(&out).with_directory(&diary))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains("<img src=\"diary_files/1-beach%20day.png\" alt=\"shore\" />"), "{}", page);
+        assert_eq!(fs::read(temp_dir.path().join("diary_files/1-beach day.png"))?, b"not really a png");
+
+        let content = "Done :tada:\n\n- [x] tests\n- [ ] docs\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\nSee[^1].\n\n\
+                       [^1]: A note\n\n```\n:tada:\n```";
+        let entry = DiaryEntry::new(1, date, content.to_string(), now);
+        export_all(&HtmlExporter::new(), &[entry], &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        for expected in [
+            "<p>Done 🎉</p>",
+            "<li><input disabled=\"\" type=\"checkbox\" checked=\"\"/>\ntests</li>",
+            "<td>1</td>",
+            "<sup class=\"footnote-reference\"><a href=\"#1\">1</a></sup>",
+            "<div class=\"footnote-definition\" id=\"1\">",
+            "<pre><code>:tada:\n</code></pre>",
+        ] {
+            assert!(page.contains(expected), "{} not in {}", expected, page);
+        }
+
+        assert_eq!("copy".parse::<AttachmentMode>()?, AttachmentMode::Copy);
+        assert!("inline".parse::<AttachmentMode>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_obsidian_flavor() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let content = "> [!tip] Pack light\n> Just %%not%% one bag\n\n![[shore.png|200]] with [[Ana|her]]";
+        let out = temp_dir.path().join("diary.html");
+
+        export_all(&HtmlExporter::new().with_flavor(MarkdownFlavor::Obsidian), &[DiaryEntry::new(1, date, content.to_string(), now)], &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains("<blockquote>\n<p><strong>💡 Pack light</strong></p>\n<p>Just  one bag</p>\n</blockquote>"), "{}", page);
+        assert!(page.contains("<p><img src=\"shore.png\" alt=\"shore\" /> with <a href=\"Ana.md\">her</a></p>"), "{}", page);
+        Ok(())
+    }
+
+    #[test]
+    fn test_mermaid_diagrams() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let out = temp_dir.path().join("diary.html");
+        let diagram = DiaryEntry::new(1, date, "```mermaid\ngraph TD\n  A-->B & C\n```".to_string(), SystemClock.now());
+        let plain = DiaryEntry::new(1, date, "```sh\nls\n```".to_string(), SystemClock.now());
+
+        export_all(&HtmlExporter::new(), std::slice::from_ref(&plain), &mut ExportSink::new(&out))?;
+        assert!(!fs::read_to_string(&out)?.contains("<script"));
+
+        export_all(&HtmlExporter::new().with_mermaid_script("mermaid.js"), &[diagram.clone(), plain], &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains("<pre class=\"mermaid\">graph TD\n  A--&gt;B &amp; C\n</pre>"), "{}", page);
+        assert!(page.contains("<script src=\"mermaid.js\"></script>"), "{}", page);
+        assert!(page.contains("<pre><code class=\"language-sh\">ls\n</code></pre>"), "{}", page);
+
+        struct Svg;
+        impl DiagramRenderer for Svg {
+            fn render(&self, language: &str, source: &str) -> Result<Option<String>> {
+                Ok(Some(format!("<svg data-lang=\"{}\">{}</svg>", language, source.len())))
+            }
+        }
+        export_all(&HtmlExporter::new().with_diagram_renderer(Arc::new(Svg)), std::slice::from_ref(&diagram), &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        assert!(page.contains("<figure class=\"diagram\"><svg data-lang=\"mermaid\">21</svg></figure>"), "{}", page);
+        assert!(!page.contains("<script"));
+
+        if cfg!(unix) {
+            let renderer = CommandRenderer::new("printf '<svg>%s' \"$RUSTY_DIARY_DIAGRAM\"; wc -l | tr -d ' '; echo '</svg>'");
+            assert_eq!(renderer.render("mermaid", "a\nb\n")?.as_deref(), Some("<svg>mermaid2\n</svg>\n"));
+
+            let failing = HtmlExporter::new().with_diagram_renderer(Arc::new(CommandRenderer::new("exit 3")));
+            export_all(&failing, &[diagram], &mut ExportSink::new(&out))?;
+            assert!(fs::read_to_string(&out)?.contains("<pre class=\"mermaid\">"));
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "highlight")]
+    #[test]
+    fn test_highlighted_code_blocks() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let content = "```rust\nlet x = 1;\n```\n\n```klingon\nqapla'\n```";
+        let entries = vec![DiaryEntry::new(1, date, content.to_string(), SystemClock.now())];
+        let out = temp_dir.path().join("diary.html");
+
+        let highlighter = Arc::new(Highlighter::new(super::super::highlight::DEFAULT_HTML_THEME)?);
+        export_all(&HtmlExporter::new().with_highlighter(highlighter), &entries, &mut ExportSink::new(&out))?;
+        let page = fs::read_to_string(&out)?;
+        let keyword = "<span style=\"font-weight:bold;color:#a71d5d;\">let</span>";
+        assert!(page.contains(&format!("<pre style=\"background-color:#ffffff;\">\n{}", keyword)), "{}", page);
+        assert!(page.contains("<pre><code class=\"language-klingon\">qapla'\n</code></pre>"), "{}", page);
+        Ok(())
+    }
+
+    #[cfg(feature = "images")]
+    #[test]
+    fn test_thumbnail_fits_size() -> Result<()> {
+        use image::GenericImageView;
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("wide.png");
+        image::RgbImage::new(400, 100).save(&path).unwrap();
+
+        let small = image::load_from_memory(&thumbnail(&path, 80)?).unwrap();
+        assert_eq!(small.dimensions(), (80, 20));
+        assert_eq!(thumbnail(&path, 400)?, fs::read(&path)?);
+        Ok(())
+    }
+}
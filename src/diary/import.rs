@@ -0,0 +1,252 @@
+//! Imports from other apps, through the `Importer`s of the processor
+//! registry, and previews of what an import or sync would store. Each
+//! source item is shown with the day it maps to, the tags and attachments
+//! found in it, and how it meets the entries already stored, so mapping
+//! options can be fixed before anything is written.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+
+use super::file::SkippedFile;
+use super::links;
+use super::photos::Photo;
+use super::processor::Importer;
+
+/// One item of an import source, as an importer enumerated it
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceItem {
+    /// Where the item comes from: its file, followed by `#` and the item's
+    /// place for files holding several
+    pub source: PathBuf,
+    /// The item as read, for `convert`
+    pub raw: String,
+}
+
+/// A file an imported entry brings along
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportedAttachment {
+    pub source: PathBuf,
+    /// Where it is filed, relative to the attachments folder; the entry
+    /// links to it as `attachments/<path>`
+    pub path: String,
+}
+
+/// An item converted by an importer
+#[derive(Debug, Clone, PartialEq)]
+pub struct Imported {
+    pub entry: DiaryEntry,
+    pub attachments: Vec<ImportedAttachment>,
+}
+
+/// Outcome of an import
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportReport {
+    /// The importer that read the source
+    pub importer: String,
+    /// The run the entries were stored under
+    pub exec_version: i64,
+    pub stored: usize,
+    /// Items left out because a stored version has the same content
+    pub duplicates: usize,
+    /// Files copied into the attachments folder
+    pub attachments: usize,
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Built-in importer of a folder of markdown notes named by their day,
+/// such as `2024-01-02.md` or `2024-01-02 Lisbon.md`, or of one such note.
+/// The notes are left where they are.
+pub struct MarkdownImporter;
+
+impl MarkdownImporter {
+    fn date(path: &Path) -> Option<NaiveDate> {
+        let pattern = Regex::new(r"^(\d{4}-\d{2}-\d{2})").unwrap();
+        if path.extension()? != "md" {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?;
+        NaiveDate::parse_from_str(pattern.captures(stem)?.get(1)?.as_str(), "%Y-%m-%d").ok()
+    }
+
+    fn notes(source: &Path) -> impl Iterator<Item = PathBuf> {
+        WalkDir::new(source)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| entry.into_path())
+            .filter(|path| Self::date(path).is_some())
+    }
+}
+
+impl Importer for MarkdownImporter {
+    fn name(&self) -> &str {
+        "markdown"
+    }
+
+    fn detect(&self, source: &Path) -> bool {
+        Self::notes(source).next().is_some()
+    }
+
+    fn enumerate(&self, source: &Path) -> Result<Vec<SourceItem>> {
+        Self::notes(source)
+            .map(|path| {
+                let raw = fs::read_to_string(&path).at_path(&path)?;
+                Ok(SourceItem { source: path, raw })
+            })
+            .collect()
+    }
+
+    fn convert(&self, item: &SourceItem, now: NaiveDateTime) -> Result<Imported> {
+        let date = Self::date(&item.source).ok_or_else(|| {
+            RustyDiaryError::ContentIntegrity(format!("No date in the name of {}", item.source.display()))
+        })?;
+        Ok(Imported { entry: DiaryEntry::new(0, date, item.raw.clone(), now), attachments: Vec::new() })
+    }
+}
+
+/// How an imported item meets what is stored or imported with it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ImportConflict {
+    /// A stored version of the day has the same content; nothing is stored
+    Duplicate,
+    /// The day has stored entries; the item becomes its latest version
+    NewVersion,
+    /// Other sources of the import fall on the same day and are merged with it
+    SameDay { sources: Vec<PathBuf> },
+}
+
+/// One source item and the entry it would become
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ImportItem {
+    pub source: PathBuf,
+    pub date: NaiveDate,
+    pub words: usize,
+    pub tags: Vec<String>,
+    /// Local files the item links to or brings along, such as images
+    pub attachments: usize,
+    pub conflicts: Vec<ImportConflict>,
+}
+
+/// What an import would do, changing nothing
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ImportPreview {
+    pub items: Vec<ImportItem>,
+    /// Sources that could not be read or mapped to a day
+    pub skipped: Vec<SkippedFile>,
+    /// Sources left for a later run
+    pub pending: Vec<SkippedFile>,
+}
+
+impl ImportItem {
+    pub fn from_entry(source: &Path, entry: &DiaryEntry) -> Self {
+        Self {
+            source: source.to_path_buf(),
+            date: entry.date,
+            words: entry.word_count(),
+            tags: entry.tags(),
+            attachments: links::attachment_count(&entry.content),
+            conflicts: Vec::new(),
+        }
+    }
+
+    /// An image filed under its day's entry in photo-journal mode
+    pub fn from_photo(photo: &Photo) -> Self {
+        Self {
+            source: photo.source.clone(),
+            date: photo.date,
+            words: 0,
+            tags: Vec::new(),
+            attachments: 1,
+            conflicts: Vec::new(),
+        }
+    }
+}
+
+impl ImportPreview {
+    /// Marks the items sharing a day and orders them by day
+    pub fn finish(mut self) -> Self {
+        self.mark_same_days();
+        self.items.sort_by(|a, b| (a.date, &a.source).cmp(&(b.date, &b.source)));
+        self
+    }
+
+    /// Marks the items that share their day with items from other sources.
+    /// Items of one source, such as the days of a file split by date
+    /// headings, never share a day.
+    pub fn mark_same_days(&mut self) {
+        let mut sources: BTreeMap<NaiveDate, Vec<PathBuf>> = BTreeMap::new();
+        for item in &self.items {
+            let day = sources.entry(item.date).or_default();
+            if !day.contains(&item.source) {
+                day.push(item.source.clone());
+            }
+        }
+        for item in &mut self.items {
+            let others: Vec<PathBuf> =
+                sources[&item.date].iter().filter(|source| **source != item.source).cloned().collect();
+            if !others.is_empty() {
+                item.conflicts.push(ImportConflict::SameDay { sources: others });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_markdown_importer() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let folder = temp_dir.path().join("notes");
+        fs::create_dir_all(folder.join("2024"))?;
+        fs::write(folder.join("2024").join("2024-01-02 Lisbon.md"), "By the river")?;
+        fs::write(folder.join("2024-01-01.md"), "New year")?;
+        fs::write(folder.join("todo.md"), "Not a day")?;
+
+        let importer = MarkdownImporter;
+        assert!(importer.detect(&folder) && importer.detect(&folder.join("2024-01-01.md")));
+        assert!(!importer.detect(&folder.join("todo.md")));
+        let items = importer.enumerate(&folder)?;
+        assert_eq!(items.len(), 2);
+        // Folders sort before the notes next to them
+        let imported = importer.convert(&items[0], SystemClock.now())?;
+        assert_eq!(imported.entry.date, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        assert_eq!(imported.entry.content, "By the river");
+        Ok(())
+    }
+
+    #[test]
+    fn test_items_and_same_days() {
+        let now = SystemClock.now();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let entry = DiaryEntry::new(1, date, "At the #beach with #Friends\n\n![sea](attachments/sea.jpg) [[2024-01-01]]".to_string(), now);
+        let item = ImportItem::from_entry(Path::new("a.md"), &entry);
+        assert_eq!(item.tags, ["beach", "friends"]);
+        assert_eq!((item.words, item.attachments), (7, 1));
+
+        let photo = Photo { source: "2024-01-02-1.jpg".into(), destination: "attachments/2024-01-02-1.jpg".into(), date };
+        let other = DiaryEntry::new(1, date.succ_opt().unwrap(), "Next day".to_string(), now);
+        let mut preview = ImportPreview {
+            items: vec![item, ImportItem::from_photo(&photo), ImportItem::from_entry(Path::new("b.md"), &other)],
+            ..ImportPreview::default()
+        };
+        preview.mark_same_days();
+        assert_eq!(preview.items[0].conflicts, [ImportConflict::SameDay { sources: vec!["2024-01-02-1.jpg".into()] }]);
+        assert_eq!(preview.items[1].conflicts, [ImportConflict::SameDay { sources: vec!["a.md".into()] }]);
+        assert!(preview.items[2].conflicts.is_empty());
+    }
+}
@@ -0,0 +1,333 @@
+//! Dead-link checks over stored entries. Markdown links and images, and
+//! Obsidian-style `![[embeds]]` of files, are resolved against the diary
+//! directory; links naming a day, such as `[[2024-01-02]]` or
+//! `[yesterday](2024-01-02.md)`, must have an entry.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Serialize;
+use walkdir::WalkDir;
+
+use crate::storage::models::DiaryEntry;
+use super::portable;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkProblem {
+    /// The linked file does not exist
+    MissingFile,
+    /// The linked day has no entry
+    MissingEntry,
+}
+
+/// A broken link in the latest version of an entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokenLink {
+    pub date: NaiveDate,
+    pub exec_version: i64,
+    /// Link target as written
+    pub target: String,
+    pub problem: LinkProblem,
+    /// File of the same name found among the archived attachments
+    pub replacement: Option<String>,
+}
+
+/// A file in the attachments folder no stored entry links to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UnusedAttachment {
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+}
+
+/// One link found in entry content
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Link {
+    /// Byte range of the target inside the content
+    span: Range<usize>,
+    target: String,
+    /// Written as a `![[file]]` embed, whose target is not URL-encoded
+    embed: bool,
+}
+
+/// Markdown links and images with a local target, plus wiki links to days
+/// and embedded files
+fn find_links(content: &str) -> Vec<Link> {
+    let markdown = Regex::new(r#"!?\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#).unwrap();
+    let wiki = Regex::new(r"(!?)\[\[([^\[\]|#]+)(?:[|#][^\[\]]*)?\]\]").unwrap();
+
+    let mut links: Vec<Link> = markdown
+        .captures_iter(content)
+        .filter_map(|caps| caps.get(1))
+        .filter(|m| is_local(m.as_str()))
+        .map(|m| Link { span: m.range(), target: m.as_str().to_string(), embed: false })
+        .collect();
+    for caps in wiki.captures_iter(content) {
+        let (Some(bang), Some(m)) = (caps.get(1), caps.get(2)) else { continue };
+        let target = m.as_str().trim();
+        let embed = !bang.as_str().is_empty() && link_date(target).is_none();
+        if embed || link_date(target).is_some() {
+            let start = m.start() + (m.as_str().len() - m.as_str().trim_start().len());
+            links.push(Link { span: start..start + target.len(), target: target.to_string(), embed });
+        }
+    }
+    links.sort_by_key(|link| link.span.start);
+    links
+}
+
+impl Link {
+    /// The file path the link names
+    fn path(&self) -> String {
+        if self.embed {
+            self.target.clone()
+        } else {
+            target_path(&self.target)
+        }
+    }
+}
+
+/// Whether a markdown link target points into the file system
+pub(crate) fn is_local(target: &str) -> bool {
+    !(target.starts_with('#') || target.contains("://") || target.starts_with("mailto:"))
+}
+
+/// The file path a link target names, without fragment or query
+pub(crate) fn target_path(target: &str) -> String {
+    let end = target.find(['#', '?']).unwrap_or(target.len());
+    target[..end].replace("%20", " ")
+}
+
+/// Links to local files in the content, leaving out links to days
+pub(crate) fn attachment_count(content: &str) -> usize {
+    attachment_targets(content).len()
+}
+
+/// Targets of the links to local files in the content, as written
+pub(crate) fn attachment_targets(content: &str) -> Vec<String> {
+    find_links(content)
+        .into_iter()
+        .filter(|link| link.embed || link_date(&link.target).is_none())
+        .map(|link| link.target)
+        .collect()
+}
+
+/// Paths of the local files the content links to, relative to the diary
+/// directory and normalized. Data URIs and paths leading out of the
+/// directory are left out.
+#[cfg(feature = "sqlite")]
+pub(crate) fn attachment_paths(content: &str) -> Vec<String> {
+    attachment_targets(content)
+        .iter()
+        .filter(|target| !target.starts_with("data:"))
+        .filter_map(|target| {
+            let path = target_path(target);
+            let mut parts = Vec::new();
+            for component in Path::new(&path).components() {
+                match component {
+                    std::path::Component::Normal(part) => parts.push(part.to_str()?),
+                    std::path::Component::CurDir => {}
+                    _ => return None,
+                }
+            }
+            (!parts.is_empty()).then(|| parts.join("/"))
+        })
+        .collect()
+}
+
+/// The day a link names: `2024-01-02`, or a file such as `2024-01-02.md`
+fn link_date(target: &str) -> Option<NaiveDate> {
+    let path = target_path(target);
+    let stem = Path::new(&path).file_stem()?.to_str()?;
+    (path == stem || path.ends_with(".md"))
+        .then(|| NaiveDate::parse_from_str(stem, "%Y-%m-%d").ok())
+        .flatten()
+}
+
+/// Finds broken links in the latest entry versions. `known_dates` are the
+/// days with an entry, live or archived; `attachments` is searched by file
+/// name for replacements of missing files.
+pub fn check(
+    entries: &[DiaryEntry],
+    directory: &Path,
+    known_dates: &BTreeSet<NaiveDate>,
+    attachments: Option<&Path>,
+) -> Vec<BrokenLink> {
+    let archived = attachments.map(|dir| index_attachments(dir, directory)).unwrap_or_default();
+
+    let mut broken = Vec::new();
+    for entry in entries {
+        for link in find_links(&entry.content) {
+            let problem = match link_date(&link.target).filter(|_| !link.embed) {
+                Some(date) if known_dates.contains(&date) => continue,
+                Some(_) => LinkProblem::MissingEntry,
+                None if directory.join(link.path()).exists() => continue,
+                None => LinkProblem::MissingFile,
+            };
+            let replacement = (problem == LinkProblem::MissingFile)
+                .then(|| {
+                    let path = link.path();
+                    let name = Path::new(&path).file_name()?.to_str()?.to_string();
+                    archived.get(&name).cloned()
+                })
+                .flatten();
+            broken.push(BrokenLink {
+                date: entry.date,
+                exec_version: entry.exec_version,
+                target: link.target,
+                problem,
+                replacement,
+            });
+        }
+    }
+    broken
+}
+
+/// Files under `folder` that none of the entries link to, sorted by path.
+/// Links are resolved against the diary directory.
+pub fn unreferenced(entries: &[DiaryEntry], directory: &Path, folder: &Path) -> Vec<UnusedAttachment> {
+    let referenced: BTreeSet<PathBuf> = entries
+        .iter()
+        .flat_map(|entry| find_links(&entry.content))
+        .filter_map(|link| fs::canonicalize(directory.join(link.path())).ok())
+        .collect();
+
+    WalkDir::new(folder)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter(|e| fs::canonicalize(e.path()).is_ok_and(|path| !referenced.contains(&path)))
+        .map(|e| UnusedAttachment { size: e.metadata().map_or(0, |meta| meta.len()), path: e.into_path() })
+        .collect()
+}
+
+/// Points the entry's broken links at their replacements. Returns `None`
+/// when nothing in the content changes.
+pub fn rewrite(entry: &DiaryEntry, broken: &[BrokenLink]) -> Option<String> {
+    let replacements: BTreeMap<&str, &str> = broken
+        .iter()
+        .filter(|link| link.date == entry.date)
+        .filter_map(|link| Some((link.target.as_str(), link.replacement.as_deref()?)))
+        .collect();
+    if replacements.is_empty() {
+        return None;
+    }
+
+    let mut content = String::with_capacity(entry.content.len());
+    let mut last = 0;
+    for link in find_links(&entry.content) {
+        if let Some(replacement) = replacements.get(link.target.as_str()) {
+            // Keep any #fragment or ?query of the original target
+            let suffix = link.target.find(['#', '?']).map_or("", |i| &link.target[i..]);
+            content.push_str(&entry.content[last..link.span.start]);
+            if link.embed {
+                content.push_str(replacement);
+            } else {
+                content.push_str(&replacement.replace(' ', "%20"));
+            }
+            content.push_str(suffix);
+            last = link.span.end;
+        }
+    }
+    content.push_str(&entry.content[last..]);
+    (content != entry.content).then_some(content)
+}
+
+/// Files under `dir` by name, as paths relative to the diary directory
+/// when `dir` is inside it. The first file found wins for each name.
+fn index_attachments(dir: &Path, directory: &Path) -> BTreeMap<String, String> {
+    let mut index = BTreeMap::new();
+    let files = WalkDir::new(dir).sort_by_file_name().into_iter().filter_map(|e| e.ok());
+    for file in files.filter(|e| e.file_type().is_file()) {
+        let Some(name) = file.file_name().to_str() else { continue };
+        let path = file.path().strip_prefix(directory).unwrap_or(file.path());
+        index.entry(name.to_string()).or_insert_with(|| portable::slash_path(path));
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_and_rewrite() -> std::io::Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("img"))?;
+        std::fs::create_dir_all(dir.join("archive/2023"))?;
+        std::fs::write(dir.join("img/here.png"), "")?;
+        std::fs::write(dir.join("archive/2023/gone.png"), "")?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let entry = DiaryEntry::new(
+            4,
+            ymd(3),
+            "![ok](img/here.png) ![gone](img/gone.png#top) [web](https://example.com) \
+             [[2024-01-01]] [yesterday](2024-01-02.md) [lost](notes/lost.md)"
+                .to_string(),
+            SystemClock.now(),
+        );
+        let known: BTreeSet<NaiveDate> = [ymd(2), ymd(3)].into();
+
+        let broken = check(std::slice::from_ref(&entry), dir, &known, Some(&dir.join("archive")));
+        let summary: Vec<_> = broken
+            .iter()
+            .map(|b| (b.target.as_str(), b.problem, b.replacement.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("img/gone.png#top", LinkProblem::MissingFile, Some("archive/2023/gone.png")),
+                ("2024-01-01", LinkProblem::MissingEntry, None),
+                ("notes/lost.md", LinkProblem::MissingFile, None),
+            ]
+        );
+
+        let fixed = rewrite(&entry, &broken).unwrap();
+        assert!(fixed.starts_with("![ok](img/here.png) ![gone](archive/2023/gone.png#top) "));
+        assert!(rewrite(&entry, &broken[1..]).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_embeds() -> std::io::Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("attachments/2024"))?;
+        std::fs::write(dir.join("attachments/2024/here.png"), "")?;
+        std::fs::write(dir.join("attachments/2024/old photo.png"), "")?;
+        std::fs::write(dir.join("attachments/2024/unused.png"), "")?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let entry = DiaryEntry::new(
+            1,
+            ymd(3),
+            "![[attachments/2024/here.png|300]] ![[photo.png]] ![[2024-01-02]] [[Reading List]]".to_string(),
+            now,
+        );
+        let known: BTreeSet<NaiveDate> = [ymd(2), ymd(3)].into();
+
+        let broken = check(std::slice::from_ref(&entry), dir, &known, Some(&dir.join("attachments")));
+        let summary: Vec<_> = broken.iter().map(|b| (b.target.as_str(), b.problem)).collect();
+        assert_eq!(summary, vec![("photo.png", LinkProblem::MissingFile)]);
+
+        let mut fixed = broken.clone();
+        fixed[0].replacement = Some("attachments/2024/old photo.png".to_string());
+        let content = rewrite(&entry, &fixed).unwrap();
+        assert!(content.contains(" ![[attachments/2024/old photo.png]] "), "{}", content);
+
+        let unused = unreferenced(&[DiaryEntry::new(2, ymd(3), content, now)], dir, &dir.join("attachments"));
+        let names: Vec<_> = unused.iter().map(|file| file.path.file_name().unwrap().to_owned()).collect();
+        assert_eq!(names, vec!["unused.png"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+//! How generated journals render dates: weekday and month names of a
+//! locale, the first day of the week, and optionally the date in a second
+//! calendar next to the Gregorian one.
+
+use std::str::FromStr;
+
+use calendrical_calculations::gregorian::fixed_from_gregorian;
+use calendrical_calculations::hebrew::BookHebrew;
+use calendrical_calculations::islamic::{tabular_islamic_from_fixed, ISLAMIC_EPOCH_FRIDAY};
+use chrono::{Datelike, Duration, Locale, NaiveDate, Weekday};
+
+use crate::error::{Result, RustyDiaryError};
+
+/// Locale, first day of the week and secondary calendar used in journals
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateLocale {
+    pub locale: Locale,
+    pub first_weekday: Weekday,
+    pub secondary: Option<SecondaryCalendar>,
+}
+
+impl Default for DateLocale {
+    fn default() -> Self {
+        Self { locale: Locale::en_US, first_weekday: Weekday::Mon, secondary: None }
+    }
+}
+
+impl DateLocale {
+    /// A POSIX locale name such as `pt_PT` or `de_DE`
+    pub fn parse_locale(name: &str) -> Result<Locale> {
+        Locale::from_str(name).map_err(|_| {
+            RustyDiaryError::InvalidConfig(format!("Unknown locale '{}' (expected a name such as en_US or pt_PT)", name))
+        })
+    }
+
+    /// Formats the date with `chrono` strftime specifiers, naming weekdays
+    /// and months in the locale's language
+    pub fn format(&self, date: NaiveDate, format: &str) -> String {
+        date.format_localized(format, self.locale).to_string()
+    }
+
+    pub fn weekday(&self, date: NaiveDate) -> String {
+        self.format(date, "%A")
+    }
+
+    pub fn month_name(&self, date: NaiveDate) -> String {
+        self.format(date, "%B")
+    }
+
+    /// First day of the week containing the date
+    pub fn week_start(&self, date: NaiveDate) -> NaiveDate {
+        let back = (7 + date.weekday().num_days_from_monday() - self.first_weekday.num_days_from_monday()) % 7;
+        date - Duration::days(back.into())
+    }
+
+    /// The date in the secondary calendar, when one is set
+    pub fn secondary_date(&self, date: NaiveDate) -> Option<String> {
+        self.secondary.map(|calendar| calendar.format(date))
+    }
+}
+
+/// A calendar shown next to the Gregorian date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecondaryCalendar {
+    /// The Hebrew calendar, e.g. `22 Adar I 5784`
+    Hebrew,
+    /// The tabular Islamic calendar, e.g. `1 Ramadan 1445 AH`. Dates based
+    /// on moon sightings may differ by a day.
+    Hijri,
+}
+
+const HEBREW_MONTHS: [&str; 13] = [
+    "Nisan", "Iyar", "Sivan", "Tammuz", "Av", "Elul", "Tishrei", "Cheshvan", "Kislev", "Tevet", "Shevat", "Adar",
+    "Adar II",
+];
+
+const HIJRI_MONTHS: [&str; 12] = [
+    "Muharram", "Safar", "Rabi al-Awwal", "Rabi al-Thani", "Jumada al-Ula", "Jumada al-Akhirah", "Rajab", "Shaban",
+    "Ramadan", "Shawwal", "Dhu al-Qadah", "Dhu al-Hijjah",
+];
+
+impl SecondaryCalendar {
+    pub const NAMES: &'static [&'static str] = &["hebrew", "hijri"];
+
+    pub fn format(self, date: NaiveDate) -> String {
+        let fixed = fixed_from_gregorian(date.year(), date.month() as u8, date.day() as u8);
+        match self {
+            SecondaryCalendar::Hebrew => {
+                let hebrew = BookHebrew::book_hebrew_from_fixed(fixed);
+                let month = match hebrew.month {
+                    12 if BookHebrew::is_hebrew_leap_year(hebrew.year) => "Adar I",
+                    month => HEBREW_MONTHS[usize::from(month) - 1],
+                };
+                format!("{} {} {}", hebrew.day, month, hebrew.year)
+            }
+            SecondaryCalendar::Hijri => {
+                let (year, month, day) = tabular_islamic_from_fixed(fixed, ISLAMIC_EPOCH_FRIDAY);
+                format!("{} {} {} AH", day, HIJRI_MONTHS[usize::from(month) - 1], year)
+            }
+        }
+    }
+}
+
+impl FromStr for SecondaryCalendar {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "hebrew" => Ok(SecondaryCalendar::Hebrew),
+            "hijri" => Ok(SecondaryCalendar::Hijri),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown calendar '{}' (expected hebrew or hijri)", name
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ymd(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    #[test]
+    fn test_localized_names_and_week_start() -> Result<()> {
+        let date = ymd(2024, 3, 2);
+        let portuguese = DateLocale { locale: DateLocale::parse_locale("pt_PT")?, ..DateLocale::default() };
+
+        assert_eq!(DateLocale::default().format(date, "%A, %-d %B %Y"), "Saturday, 2 March 2024");
+        assert_eq!(portuguese.weekday(date), "sábado");
+        assert_eq!(portuguese.month_name(date), "março");
+        assert!(DateLocale::parse_locale("xx_XX").is_err());
+
+        assert_eq!(DateLocale::default().week_start(date), ymd(2024, 2, 26));
+        let sunday_first = DateLocale { first_weekday: Weekday::Sun, ..DateLocale::default() };
+        assert_eq!(sunday_first.week_start(date), ymd(2024, 2, 25));
+        assert_eq!(sunday_first.week_start(ymd(2024, 3, 3)), ymd(2024, 3, 3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_secondary_calendars() {
+        assert_eq!(SecondaryCalendar::Hebrew.format(ymd(2024, 3, 2)), "22 Adar I 5784");
+        assert_eq!(SecondaryCalendar::Hebrew.format(ymd(2023, 9, 16)), "1 Tishrei 5784");
+        assert_eq!(SecondaryCalendar::Hijri.format(ymd(2024, 3, 11)), "1 Ramadan 1445 AH");
+        assert_eq!(DateLocale::default().secondary_date(ymd(2024, 3, 2)), None);
+        assert!("julian".parse::<SecondaryCalendar>().is_err());
+    }
+}
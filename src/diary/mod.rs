@@ -1,76 +1,2116 @@
+pub mod activity;
+#[cfg(feature = "sqlite")]
+pub mod archive;
+pub mod canonical;
+pub mod cloud;
+pub mod dayone;
+pub mod export;
 pub mod file;
+pub mod filename;
+#[cfg(feature = "sqlite")]
+pub mod flat;
+pub mod flavor;
+pub mod habits;
+pub mod highlight;
+pub mod html;
+pub mod import;
+pub mod links;
+pub mod locale;
+pub mod photos;
+#[cfg(feature = "plugins")]
+pub mod plugin;
+pub mod portable;
 pub mod processor;
+pub mod quotes;
+pub mod related;
+pub mod review;
+pub mod saved;
+#[cfg(feature = "spellcheck")]
+pub mod spelling;
+pub mod split;
+pub mod style;
+#[cfg(feature = "stamp")]
+pub mod stamp;
+pub mod tagging;
+pub mod template;
+pub mod terms;
+pub mod timeline;
 
+#[cfg(feature = "sqlite")]
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+#[cfg(feature = "sqlite")]
+use std::path::Path;
+#[cfg(feature = "sqlite")]
+use std::sync::Arc;
+#[cfg(feature = "sqlite")]
+use std::time::Instant;
 use chrono::NaiveDate;
+#[cfg(feature = "sqlite")]
+use chrono::NaiveDateTime;
+use serde::Serialize;
+#[cfg(feature = "sqlite")]
+use tracing::{debug, info, info_span, warn};
 
-use crate::error::Result;
+#[cfg(feature = "sqlite")]
+use crate::cancel::CancellationToken;
+#[cfg(feature = "sqlite")]
+use crate::clock::Clock;
+#[cfg(feature = "sqlite")]
+use crate::error::{IoContext, Result, RustyDiaryError};
+#[cfg(feature = "sqlite")]
 use crate::config::Config;
-use crate::storage::StorageManager;
-use self::file::FileRepository;
+#[cfg(feature = "sqlite")]
+use crate::dates::DateRange;
+#[cfg(feature = "sqlite")]
+use crate::events::{DiaryEvent, EventBus};
+#[cfg(feature = "sqlite")]
+use crate::hooks::{HookEvent, HookPayload, Hooks};
+#[cfg(feature = "sqlite")]
+use crate::progress::{NoProgress, ProgressSink};
+#[cfg(feature = "sqlite")]
+use crate::reminder::{Reminder, ReminderReason};
+#[cfg(feature = "sqlite")]
+use crate::storage::{Attachments, DiaryEntry, EntryQuery, RunStats, StorageManager};
+#[cfg(feature = "sqlite")]
+use self::archive::RoundTrip;
+#[cfg(feature = "sqlite")]
+use self::export::ExportSink;
+#[cfg(feature = "sqlite")]
+use self::file::{CollectedFiles, FileRepository};
+use self::file::SkippedFile;
+#[cfg(feature = "sqlite")]
+use self::habits::HabitStats;
+#[cfg(feature = "sqlite")]
+use self::import::{ImportConflict, ImportItem, ImportPreview, ImportReport, Imported};
+#[cfg(feature = "sqlite")]
+use self::links::{BrokenLink, UnusedAttachment};
+#[cfg(feature = "sqlite")]
+use self::highlight::Highlighter;
+#[cfg(feature = "sqlite")]
+use self::photos::Photo;
+#[cfg(feature = "sqlite")]
+use self::related::{RelatedEntry, RelatedFinder};
+#[cfg(feature = "sqlite")]
+use self::saved::SavedSearches;
+#[cfg(feature = "sqlite")]
+use self::style::{StyleReport, StyleRules};
+#[cfg(feature = "sqlite")]
+use self::tagging::{TagSuggester, TagSuggestion};
+#[cfg(feature = "sqlite")]
+use self::terms::TermCount;
+#[cfg(feature = "sqlite")]
+use self::processor::{Enricher, Exporter, FileParser, Importer};
+use self::processor::Normalization;
+#[cfg(feature = "sqlite")]
+use self::split::JournalSplit;
 
+/// Converted import items by where they came from, and the items skipped
+#[cfg(feature = "sqlite")]
+type ConvertedItems = (Vec<(PathBuf, Imported)>, Vec<SkippedFile>);
+
+/// Outcome of a sync run
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    pub exec_version: i64,
+    /// New entries stored by this run
+    pub stored: usize,
+    pub skipped: Vec<SkippedFile>,
+    /// Files with invalid UTF-8 that were stored lossily and kept
+    pub lossy: Vec<SkippedFile>,
+    /// Tags applied to the stored entries by `--auto-tag`
+    pub auto_tagged: usize,
+    /// Metadata fields the registered enrichers stored
+    pub enriched: usize,
+    /// Images filed under the attachments folder and referenced from
+    /// their day's entry, where they were filed
+    pub photos: Vec<PathBuf>,
+    /// Synced files that could not be removed afterwards
+    pub cleanup_failed: Vec<SkippedFile>,
+    /// Files left for a later run, such as cloud placeholders or files
+    /// modified too recently
+    pub pending: Vec<SkippedFile>,
+    /// Text normalization applied to the entries, also recorded in storage
+    pub normalization: Normalization,
+    /// Date range of the synced files
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+}
+
+/// Outcome of writing a journal
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JournalReport {
+    /// The journal file, or the journal directory when split
+    pub path: PathBuf,
+    /// Files written by this run; empty when everything was up to date
+    pub written: Vec<PathBuf>,
+}
+
+impl JournalReport {
+    pub fn is_up_to_date(&self) -> bool {
+        self.written.is_empty()
+    }
+}
+
+#[cfg(feature = "sqlite")]
 pub struct RustyDiary {
     file_repo: FileRepository,
     storage: StorageManager,
+    progress: Arc<dyn ProgressSink>,
+    cancel: CancellationToken,
+    hooks: Hooks,
+    events: EventBus,
+    directory: PathBuf,
+    journal_split: JournalSplit,
+    journal_file: Option<PathBuf>,
+    auto_tag: Option<f64>,
+    enrichers: Vec<Arc<dyn Enricher>>,
+    habits: Vec<String>,
+    saved_searches: SavedSearches,
+    clock: Arc<dyn Clock>,
 }
 
+#[cfg(feature = "sqlite")]
 impl RustyDiary {
     pub fn new(config: Config) -> Result<Self> {
-        let file_repo = FileRepository::new(
+        config.validate()?;
+        let mut file_repo = FileRepository::new(
             &config.directory,
             config.output_file_prefix,
             &config.date_pattern
-        )?;
-        let storage = StorageManager::new(&config.db_path)?;
+        )?
+        .with_journal_template(&config.journal_template)?
+        .with_filename_template(&config.output_filename)?
+        .with_ignore_patterns(&config.ignore_patterns)?
+        .with_layout(config.max_depth, config.path_dates)
+        .with_min_file_age(config.min_file_age)
+        .with_normalization(config.normalization)
+        .with_lossy_utf8(config.lossy_utf8)
+        .with_date_heading_split(config.split_date_headings)
+        .with_photos(config.photo_journal)
+        .with_author(config.author.clone())
+        .with_journal_file(config.journal_file.as_ref().map(|file| config.directory.join(file)))
+        .with_clock(config.clock.clone());
+        #[cfg(not(feature = "highlight"))]
+        if let Some(theme) = &config.highlight_theme {
+            warn!("Ignoring highlight theme {:?}: built without the `highlight` feature", theme);
+        }
+        let highlighter = Highlighter::new(config.highlight_theme.as_deref().unwrap_or(highlight::DEFAULT_HTML_THEME))?;
+        let html = html::HtmlExporter::new()
+            .with_highlighter(Arc::new(highlighter))
+            .with_flavor(config.markdown_flavor);
+        file_repo.registry_mut().register_exporter(Arc::new(html));
+        if let Some(plugin_dir) = &config.plugin_dir {
+            #[cfg(feature = "plugins")]
+            {
+                let loaded = plugin::load_plugins(plugin_dir, file_repo.registry_mut())?;
+                info!(plugins = loaded.len(), "Loaded plugins");
+            }
+            #[cfg(not(feature = "plugins"))]
+            warn!(
+                "Ignoring plugin directory {:?}: built without the `plugins` feature",
+                plugin_dir
+            );
+        }
+        #[allow(unused_mut)]
+        let mut enrichers: Vec<Arc<dyn Enricher>> = Vec::new();
+        if let Some(location) = &config.location {
+            #[cfg(feature = "stamp")]
+            enrichers.push(Arc::new(stamp::StampEnricher::new().with_location(location)));
+            #[cfg(not(feature = "stamp"))]
+            warn!("Ignoring location {:?}: built without the `stamp` feature", location);
+        }
+        if !config.git_repos.is_empty() {
+            enrichers.push(Arc::new(activity::GitActivity::new(config.git_repos.clone())));
+        }
+        if config.journal_file.is_some() && config.journal_split != JournalSplit::None {
+            return Err(RustyDiaryError::InvalidConfig(
+                "A canonical journal file cannot be combined with a journal split".to_string(),
+            ));
+        }
+        let saved_searches = SavedSearches::load(&config.directory, &config.saved_searches, config.clock.today())?;
+        let storage = if config.in_memory {
+            StorageManager::in_memory_with_settings(&config.sqlite)?
+        } else if config.read_only {
+            StorageManager::open_read_only_with_settings(&config.db_path, &config.sqlite)?
+        } else {
+            StorageManager::with_settings(&config.db_path, &config.sqlite, config.clock.clone())?
+        };
+        let storage = storage
+            .with_read_cache(config.read_cache_size)
+            .with_compression(config.compress_content)
+            .with_search_settings(&config.search)
+            .with_clock(config.clock.clone());
 
         Ok(Self {
             file_repo,
             storage,
+            progress: Arc::new(NoProgress),
+            cancel: CancellationToken::default(),
+            hooks: config.hooks,
+            events: EventBus::new(),
+            journal_file: config.journal_file.map(|file| config.directory.join(file)),
+            directory: config.directory,
+            journal_split: config.journal_split,
+            auto_tag: config.auto_tag,
+            enrichers,
+            habits: config.habits,
+            saved_searches,
+            clock: config.clock,
         })
     }
 
+    /// Stamps entries, sync runs, journal names and archives with the time
+    /// of `clock` instead of the one in the configuration
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.file_repo = self.file_repo.with_clock(clock.clone());
+        self.storage = self.storage.with_clock(clock.clone());
+        self.clock = clock;
+        self
+    }
+
+    /// The time the diary works at
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    /// Reports progress of syncing and journal writing to the given sink
+    pub fn with_progress(mut self, progress: Arc<dyn ProgressSink>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Lets syncs, journal writes, exports and recompression be stopped
+    /// through `cancel`; they then fail with `RustyDiaryError::Cancelled`.
+    /// Nothing is stored and no file is written or removed by a cancelled
+    /// operation, except that a sync whose entries are stored already runs
+    /// to the end.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.file_repo = self.file_repo.with_cancellation(cancel.clone());
+        self.storage = self.storage.with_cancellation(cancel.clone());
+        self.cancel = cancel;
+        self
+    }
+
+    /// Handles files with the parser's extensions in addition to markdown
+    pub fn with_parser(mut self, parser: Arc<dyn FileParser>) -> Self {
+        self.file_repo.registry_mut().register_parser(parser);
+        self
+    }
+
+    /// Adds an importer, replacing a built-in or plugin one with the same
+    /// name; `import auto` tries it first
+    pub fn with_importer(mut self, importer: Arc<dyn Importer>) -> Self {
+        self.file_repo.registry_mut().register_importer(importer);
+        self
+    }
+
+    /// Names of the available importers, in the order `import auto` tries them
+    pub fn importers(&self) -> Vec<&str> {
+        let mut names = vec![archive::NAME];
+        names.extend(self.file_repo.registry().importer_names());
+        names
+    }
+
+    /// Adds an export target, replacing a built-in or plugin one with the
+    /// same name
+    pub fn with_exporter(mut self, exporter: Arc<dyn Exporter>) -> Self {
+        self.file_repo.registry_mut().register_exporter(exporter);
+        self
+    }
+
+    /// Runs the enricher on every entry a sync stores, storing the fields
+    /// it returns as the day's metadata. Enrichers run in the order they
+    /// were added; one that fails is logged and does not fail the sync.
+    pub fn with_enricher(mut self, enricher: Arc<dyn Enricher>) -> Self {
+        self.enrichers.push(enricher);
+        self
+    }
+
+    /// Names of the available export targets
+    pub fn export_targets(&self) -> Vec<&str> {
+        let mut names = self.file_repo.registry().exporter_names();
+        names.push(archive::NAME);
+        names
+    }
+
+    /// Exports every stored version of the entries in the range through the
+    /// named export target
+    pub fn export(&self, target: &str, range: DateRange, destination: &Path) -> Result<usize> {
+        self.export_matching(target, &EntryQuery::within(range), destination)
+    }
+
+    /// Exports every stored version matching the query, e.g. a saved search
+    pub fn export_matching(&self, target: &str, query: &EntryQuery, destination: &Path) -> Result<usize> {
+        self.export_entries(target, self.storage.query(query)?, destination)
+    }
+
+    /// Exports the latest version of each day matching the query, one
+    /// entry per day
+    pub fn export_latest_matching(&self, target: &str, query: &EntryQuery, destination: &Path) -> Result<usize> {
+        let mut entries = self.storage.query(query)?;
+        entries.dedup_by_key(|entry| entry.date);
+        self.export_entries(target, entries, destination)
+    }
+
+    /// Streams the entries to the named exporter, reporting progress and
+    /// stopping on cancellation between entries. Attachment links are
+    /// resolved against the diary directory, and entries carry their
+    /// byline as in the journal.
+    fn export_entries(&self, target: &str, entries: Vec<DiaryEntry>, destination: &Path) -> Result<usize> {
+        // An archive carries more than the entries, so the diary writes it
+        if target == archive::NAME {
+            self.cancel.check()?;
+            let _export = info_span!("export", target, entries = entries.len()).entered();
+            return Ok(archive::write(&self.storage, &self.directory, &entries, destination, self.clock.now())?.entries);
+        }
+        let entries = self.bylined(entries)?;
+        let exporter = self.file_repo.registry().exporter(target).ok_or_else(|| {
+            RustyDiaryError::InvalidConfig(format!(
+                "Unknown export target '{}' (available: {})",
+                target,
+                self.export_targets().join(", ")
+            ))
+        })?;
+
+        let total = entries.len();
+        let _export = info_span!("export", target, entries = total).entered();
+        let mut sink = ExportSink::new(destination).with_directory(&self.directory);
+        self.progress.start("Exporting entries", total as u64);
+        let mut stream = entries.into_iter().map(|entry| {
+            self.cancel.check()?;
+            self.progress.advance(1);
+            Ok(entry)
+        });
+        let exported = exporter.export(&mut stream, &mut sink);
+        self.progress.finish();
+        exported?;
+        debug!(attachments = sink.copied(), "Exported to {}", destination.display());
+        Ok(total)
+    }
+
+    /// The query saved under `name` in `.diarysearches` or the configuration
+    pub fn saved_search(&self, name: &str) -> Result<&EntryQuery> {
+        self.saved_searches.get(name)
+    }
+
+    /// Broken links in the latest version of every entry. Missing files are
+    /// looked up by name in `attachments`, when given, for a replacement.
+    pub fn check_links(&self, attachments: Option<&Path>) -> Result<Vec<BrokenLink>> {
+        let entries = self.storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        let archived = self.storage.archived_summaries(NaiveDate::MIN, NaiveDate::MAX)?;
+        let known_dates: BTreeSet<NaiveDate> = entries
+            .iter()
+            .map(|entry| entry.date)
+            .chain(archived.iter().map(|summary| summary.date))
+            .collect();
+
+        Ok(links::check(&entries, &self.directory, &known_dates, attachments))
+    }
+
+    /// Files in the attachments folder that no stored version of any entry
+    /// links to, cold-archived ones included, such as images of deleted or
+    /// pruned days. They are removed unless `dry_run` only lists them.
+    pub fn collect_attachment_garbage(&self, dry_run: bool) -> Result<Vec<UnusedAttachment>> {
+        let folder = self.directory.join(photos::ATTACHMENTS_DIR);
+        if !folder.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut entries = self.storage.entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+        entries.extend(self.storage.archived_entries()?);
+
+        let unused = links::unreferenced(&entries, &self.directory, &folder);
+        if !dry_run {
+            for attachment in &unused {
+                self.cancel.check()?;
+                std::fs::remove_file(&attachment.path).at_path(&attachment.path)?;
+            }
+            info!(removed = unused.len(), "Removed unreferenced attachments");
+        }
+        Ok(unused)
+    }
+
+    /// Points broken links at their replacements, storing the changed
+    /// entries as a new version. Returns the number of rewritten entries.
+    pub fn fix_links(&self, broken: &[BrokenLink]) -> Result<usize> {
+        let dates: BTreeSet<NaiveDate> = broken
+            .iter()
+            .filter(|link| link.replacement.is_some())
+            .map(|link| link.date)
+            .collect();
+        let started = Instant::now();
+
+        let mut rewritten = Vec::new();
+        for date in dates {
+            let Some(entry) = self.storage.latest_entry(date)? else { continue };
+            if let Some(content) = links::rewrite(&entry, broken) {
+                rewritten.push((entry, content));
+            }
+        }
+        if rewritten.is_empty() {
+            return Ok(0);
+        }
+
+        // The fixed entries are a run of their own, like a sync's
+        let exec_version = self.storage.start_sync_run(self.file_repo.normalization().as_str())?;
+        let fixed: Vec<DiaryEntry> = rewritten
+            .into_iter()
+            .map(|(entry, content)| DiaryEntry {
+                author: entry.author,
+                ..DiaryEntry::new(exec_version, entry.date, content, self.clock.now())
+            })
+            .collect();
+
+        let count = fixed.len();
+        if let Err(e) = self.storage.store_entries(fixed.clone()) {
+            self.close_failed_run(exec_version, RunStats::default(), &e)?;
+            return Err(e);
+        }
+        self.storage.finish_sync_run(exec_version, &RunStats {
+            stored: count,
+            duration_ms: started.elapsed().as_millis() as u64,
+            ..RunStats::default()
+        })?;
+        for entry in fixed {
+            self.events.publish(DiaryEvent::EntryStored(entry));
+        }
+        Ok(count)
+    }
+
+    /// Tags proposed for the latest entry of a day, scored against every
+    /// other entry. `None` when the day has no entry.
+    pub fn suggest_tags(&self, date: NaiveDate, limit: usize) -> Result<Option<Vec<TagSuggestion>>> {
+        let Some(entry) = self.storage.latest_entry(date)? else { return Ok(None) };
+        let corpus = self.storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        Ok(Some(TagSuggester::new(&corpus).suggest(&entry, limit)))
+    }
+
+    /// Other days related to the latest entry of a day through shared tags,
+    /// links or similar words, most related first. `None` when the day has
+    /// no entry.
+    pub fn related(&self, date: NaiveDate, limit: usize) -> Result<Option<Vec<RelatedEntry>>> {
+        let Some(entry) = self.storage.latest_entry(date)? else { return Ok(None) };
+        let corpus = self.storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        Ok(Some(RelatedFinder::new(&corpus).find(&entry, limit)))
+    }
+
+    /// Stores suggestions at least `threshold` confident as `auto` tags of
+    /// the entries. Returns the number of tags stored.
+    fn auto_tag_entries(&self, entries: &[DiaryEntry], threshold: f64) -> Result<usize> {
+        let corpus = self.storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        let suggester = TagSuggester::new(&corpus);
+
+        let mut applied = 0;
+        for entry in entries {
+            let tags: Vec<(String, f64)> = suggester
+                .suggest(entry, tagging::SUGGESTION_LIMIT)
+                .into_iter()
+                .filter(|suggestion| suggestion.confidence >= threshold)
+                .map(|suggestion| (suggestion.tag, suggestion.confidence))
+                .collect();
+            self.storage.replace_tags(entry, tagging::AUTO_SOURCE, &tags)?;
+            applied += tags.len();
+        }
+        debug!(applied, "Applied automatic tags");
+        Ok(applied)
+    }
+
+    /// Records the configured habits' checklist items of the entries
+    fn record_habits(&self, entries: &[DiaryEntry]) -> Result<()> {
+        if self.habits.is_empty() {
+            return Ok(());
+        }
+        for entry in entries {
+            self.storage.replace_habit_checks(entry.date, &habits::parse_checks(&entry.content, &self.habits))?;
+        }
+        Ok(())
+    }
+
+    /// Re-reads the habit checklists of every day's latest entry, e.g.
+    /// after a habit was added to the configuration. Returns the number
+    /// of days read.
+    pub fn rescan_habits(&self) -> Result<usize> {
+        let entries = self.storage.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?;
+        for entry in &entries {
+            self.cancel.check()?;
+            self.storage.replace_habit_checks(entry.date, &habits::parse_checks(&entry.content, &self.habits))?;
+        }
+        Ok(entries.len())
+    }
+
+    /// Checks a due reminder against the entry stored for its day and fires
+    /// the `Reminder` hooks when the day has no entry or is short of the
+    /// word goal. Returns why it fired, or none when it is not due or the
+    /// day is written.
+    pub fn remind(&self, reminder: &mut Reminder, now: NaiveDateTime) -> Result<Option<ReminderReason>> {
+        let Some(date) = reminder.due(now) else { return Ok(None) };
+        let words = self.storage.list_entries(date, date)?.first().map(|summary| summary.word_count);
+        let reason = reminder.check(date, words);
+        if let Some(reason) = &reason {
+            info!(%date, "Reminder: {}", reason);
+            self.hooks.fire(HookPayload::Reminder { date, reason })?;
+        }
+        Ok(reason)
+    }
+
+    /// The review of `week`, from the latest version of each of its days,
+    /// closing with `template` or the default questions
+    pub fn weekly_review(&self, week: DateRange, template: Option<&str>) -> Result<String> {
+        let mut entries = self.storage.latest_entries(week.start, week.end)?;
+        entries.sort_by_key(|entry| entry.date);
+        Ok(review::weekly_review(week, &entries, &self.habits, template.unwrap_or(review::DEFAULT_TEMPLATE)))
+    }
+
+    /// The `limit` most used terms of the latest entries within the range,
+    /// most used first, optionally grouping inflections by stem
+    pub fn top_terms(&self, range: DateRange, limit: usize, stemmed: bool) -> Result<Vec<TermCount>> {
+        Ok(terms::top_terms(&self.storage.latest_entries(range.start, range.end)?, limit, stemmed))
+    }
+
+    /// The style report of the latest entry of each day within the range,
+    /// oldest first
+    pub fn check_style(&self, range: DateRange, rules: &StyleRules) -> Result<Vec<StyleReport>> {
+        let mut entries = self.storage.latest_entries(range.start, range.end)?;
+        entries.sort_by_key(|entry| entry.date);
+        Ok(style::check(&entries, rules))
+    }
+
+    /// Likely misspellings in the latest entries within the range, checked
+    /// against the `lang` dictionary (or the one at `dictionary`) and the
+    /// diary's own words for it
+    #[cfg(feature = "spellcheck")]
+    pub fn check_spelling(
+        &self,
+        range: DateRange,
+        lang: &str,
+        dictionary: Option<&Path>,
+        suggestions: usize,
+    ) -> Result<Vec<spelling::Misspelling>> {
+        let dictionary = match dictionary {
+            Some(path) => spelling::Dictionary::open(path)?,
+            None => spelling::Dictionary::find(lang)?,
+        };
+        let dictionary = dictionary.with_words(self.storage.spelling_words(lang)?);
+        let mut entries = self.storage.latest_entries(range.start, range.end)?;
+        entries.sort_by_key(|entry| entry.date);
+        Ok(spelling::check(&entries, &dictionary, suggestions))
+    }
+
+    /// Streaks and monthly completion of each habit within the range
+    pub fn habit_stats(&self, range: DateRange) -> Result<Vec<HabitStats>> {
+        Ok(habits::habit_stats(&self.storage.habit_checks(range.start, range.end)?))
+    }
+
+    /// Stores the fields the enrichers return for the entries. Returns the
+    /// number of fields stored.
+    fn enrich_entries(&self, entries: &[DiaryEntry]) -> Result<usize> {
+        let mut stored = 0;
+        for enricher in &self.enrichers {
+            for entry in entries {
+                let fields = match enricher.enrich(entry) {
+                    Ok(fields) => fields,
+                    Err(e) => {
+                        warn!(enricher = enricher.name(), date = %entry.date, "Enrichment failed: {}", e);
+                        continue;
+                    }
+                };
+                for (key, value) in fields {
+                    match self.storage.set_meta(entry.date, &key, &value) {
+                        Ok(()) => stored += 1,
+                        Err(RustyDiaryError::InvalidConfig(reason)) => {
+                            warn!(enricher = enricher.name(), date = %entry.date, "Field not stored: {}", reason);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+        }
+        debug!(stored, "Stored enrichment fields");
+        Ok(stored)
+    }
+
+    /// Subscribes to the events this diary publishes from now on
+    pub fn subscribe(&self) -> std::sync::mpsc::Receiver<DiaryEvent> {
+        self.events.subscribe()
+    }
+
+    /// Deletes every stored version of the entry for a date.
+    /// Returns the number of removed versions.
+    pub fn delete_entry(&self, date: NaiveDate) -> Result<usize> {
+        let versions = self.storage.delete_entries(date)?;
+        if versions > 0 {
+            self.events.publish(DiaryEvent::EntryDeleted { date, versions });
+        }
+        Ok(versions)
+    }
+
+    /// Moves the entries dated before `before` into a cold archive, along
+    /// with the files in the directory only they link to. Returns the
+    /// number of archived entries.
+    pub fn cold_archive(&self, before: NaiveDate) -> Result<usize> {
+        let linked = |entry: &DiaryEntry| links::attachment_paths(&entry.content);
+        let attachments = Attachments { directory: &self.directory, linked: &linked };
+        self.storage.cold_archive(before, Some(&attachments))
+    }
+
+    /// Restores the archived entries within the range, putting their
+    /// archived files back in the directory. Returns the restored count.
+    pub fn unarchive(&self, from: NaiveDate, to: NaiveDate) -> Result<usize> {
+        self.storage.unarchive(from, to, &self.directory)
+    }
+
+    /// The directory diary files are collected from
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Gives direct access to the underlying storage for queries and maintenance
+    pub fn storage(&self) -> &StorageManager {
+        &self.storage
+    }
+
     pub fn synchronize(&self) -> Result<(NaiveDate, NaiveDate)> {
-        // Collect files that match our pattern
-        let files = self.file_repo.collect_diary_files()?;
+        self.sync().map(|report| (report.start_date, report.end_date))
+    }
+
+    /// What `sync` would store from the files in the directory, changing
+    /// nothing: each file with the day it maps to, checked against the
+    /// stored entries and the other files
+    pub fn preview_sync(&self) -> Result<ImportPreview> {
+        let collected = self.file_repo.collect_diary_files()?;
+        let exec_version = self.storage.latest_exec_version()? + 1;
+        let mut preview = ImportPreview { pending: collected.pending, ..ImportPreview::default() };
+
+        // One file at a time, so each entry is known by the file it came from
+        for file in &collected.files {
+            let processed = self.file_repo.process_files(std::slice::from_ref(file), exec_version, &NoProgress)?;
+            preview.skipped.extend(processed.skipped);
+            for entry in &processed.entries {
+                preview.items.push(self.preview_item(file, entry)?);
+            }
+            for photo in &processed.photos {
+                let mut item = ImportItem::from_photo(photo);
+                if self.storage.latest_entry(photo.date)?.is_some() {
+                    item.conflicts.push(ImportConflict::NewVersion);
+                }
+                preview.items.push(item);
+            }
+        }
+        Ok(preview.finish())
+    }
 
-        // Get the next execution version
+    /// What `import` would store from `source`, changing nothing
+    pub fn preview_import(&self, format: &str, source: &Path) -> Result<ImportPreview> {
+        if Self::is_archive_import(format, source) {
+            let archive = archive::read(source)?;
+            let mut preview = ImportPreview::default();
+            for (i, entry) in archive.entries.iter().enumerate() {
+                let source = source.join(format!("entries.jsonl#{}", i + 1));
+                preview.items.push(self.preview_item(&source, entry)?);
+            }
+            return Ok(preview.finish());
+        }
+        let importer = self.importer_for(format, source)?;
         let exec_version = self.storage.latest_exec_version()? + 1;
+        let (converted, skipped) = self.convert_items(importer.as_ref(), source, exec_version)?;
+
+        let mut preview = ImportPreview { skipped, ..ImportPreview::default() };
+        for (source, imported) in &converted {
+            preview.items.push(self.preview_item(source, &imported.entry)?);
+        }
+        Ok(preview.finish())
+    }
+
+    /// Imports `source` with the named importer, or with the first that
+    /// recognizes it for "auto". The entries are stored as a new run,
+    /// leaving out those whose content is stored already, and the files
+    /// they bring along are copied into the attachments folder. Items on
+    /// the same day are merged into one entry.
+    ///
+    /// An archive is restored as it was exported instead, into a database
+    /// that holds no entries yet, keeping every version and what its day
+    /// stores apart from the content.
+    pub fn import(&self, format: &str, source: &Path) -> Result<ImportReport> {
+        if Self::is_archive_import(format, source) {
+            let _import = info_span!("import", importer = archive::NAME).entered();
+            let restored = archive::restore(&self.storage, &self.directory, source)?;
+            info!(stored = restored.entries, attachments = restored.attachments, "Restored {}", source.display());
+            return Ok(ImportReport {
+                importer: archive::NAME.to_string(),
+                exec_version: self.storage.latest_exec_version()?,
+                stored: restored.entries,
+                duplicates: 0,
+                attachments: restored.attachments,
+                skipped: Vec::new(),
+            });
+        }
+        let started = Instant::now();
+        let importer = self.importer_for(format, source)?;
+        let _import = info_span!("import", importer = importer.name()).entered();
+
+        let exec_version = self.storage.start_sync_run(self.file_repo.normalization().as_str())?;
+        let mut stats = RunStats::default();
+        let report = self.import_run(importer.as_ref(), source, exec_version, started, &mut stats);
+        if let Err(e) = &report {
+            stats.duration_ms = started.elapsed().as_millis() as u64;
+            self.close_failed_run(exec_version, stats, e)?;
+        }
+        report
+    }
+
+    /// Imports the items of `source` as the entries of run `exec_version`,
+    /// keeping `stats` up to date as it goes
+    fn import_run(
+        &self,
+        importer: &dyn Importer,
+        source: &Path,
+        exec_version: i64,
+        started: Instant,
+        stats: &mut RunStats,
+    ) -> Result<ImportReport> {
+        let (mut converted, skipped) = self.convert_items(importer, source, exec_version)?;
+        if converted.is_empty() {
+            return Err(RustyDiaryError::AllFilesSkipped(skipped.len()));
+        }
+        converted.sort_by_key(|(_, imported)| imported.entry.date);
+        let read = converted.len();
+
+        let folder = self.directory.join(photos::ATTACHMENTS_DIR);
+        let mut entries = Vec::with_capacity(converted.len());
+        let mut attachments = 0;
+        for (_, imported) in converted {
+            for attachment in &imported.attachments {
+                let target = folder.join(&attachment.path);
+                if target.exists() {
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent).at_path(parent)?;
+                }
+                std::fs::copy(&attachment.source, &target).at_path(&target)?;
+                attachments += 1;
+            }
+            entries.push(imported.entry);
+        }
+
+        let new_entries = self.storage.unstored_entries(file::merge_same_dates(entries))?;
+        let stored = new_entries.len();
+        stats.files = read + skipped.len();
+        stats.skipped = skipped.len();
+        self.storage.store_entries_with_progress(new_entries, self.progress.as_ref())?;
+        stats.stored = stored;
+        stats.duplicates = read - stored;
+        stats.duration_ms = started.elapsed().as_millis() as u64;
+        self.storage.finish_sync_run(exec_version, stats)?;
+        info!(stored, attachments, "Imported {}", source.display());
+
+        Ok(ImportReport {
+            importer: importer.name().to_string(),
+            exec_version,
+            stored,
+            duplicates: read - stored,
+            attachments,
+            skipped,
+        })
+    }
+
+    fn is_archive_import(format: &str, source: &Path) -> bool {
+        format == archive::NAME || (format == "auto" && archive::is_archive(source))
+    }
+
+    /// Exports every stored version to an archive and imports it into a
+    /// fresh database in a scratch folder, reporting what does not come
+    /// back the same
+    pub fn verify_roundtrip(&self) -> Result<RoundTrip> {
+        let entries = self.storage.query(&EntryQuery::new())?;
+        archive::verify_roundtrip(&self.storage, &self.directory, &entries, self.clock.now())
+    }
+
+    /// The importer named `format`, or the first that recognizes the source
+    /// for "auto"
+    fn importer_for(&self, format: &str, source: &Path) -> Result<Arc<dyn Importer>> {
+        std::fs::metadata(source).at_path(source)?;
+        let registry = self.file_repo.registry();
+        let importer = match format {
+            "auto" => registry.detect_importer(source),
+            name => registry.importer(name),
+        };
+        importer.cloned().ok_or_else(|| {
+            let available = self.importers().join(", ");
+            RustyDiaryError::InvalidConfig(match format {
+                "auto" => format!("No importer recognizes {} (available: {})", source.display(), available),
+                name => format!("Unknown importer '{}' (available: {})", name, available),
+            })
+        })
+    }
+
+    /// Every item of the source converted into an entry of run
+    /// `exec_version`, by where it came from, and the items that failed
+    /// to convert or came out empty
+    fn convert_items(&self, importer: &dyn Importer, source: &Path, exec_version: i64) -> Result<ConvertedItems> {
+        let items = importer.enumerate(source)?;
+        if items.is_empty() {
+            return Err(RustyDiaryError::NoFilesFound(source.to_path_buf()));
+        }
+
+        let mut converted = Vec::with_capacity(items.len());
+        let mut skipped = Vec::new();
+        for item in items {
+            self.cancel.check()?;
+            match importer.convert(&item, self.clock.now()) {
+                Ok(mut imported) => {
+                    imported.entry.exec_version = exec_version;
+                    imported.entry.content = self.file_repo.normalization().apply(&imported.entry.content);
+                    if imported.entry.content.trim().is_empty() {
+                        skipped.push(SkippedFile { path: item.source, reason: "Empty content".to_string() });
+                    } else {
+                        converted.push((item.source, imported));
+                    }
+                }
+                Err(e) => skipped.push(SkippedFile { path: item.source, reason: e.to_string() }),
+            }
+        }
+        Ok((converted, skipped))
+    }
+
+    /// An entry about to be stored, checked against the stored versions of its day
+    fn preview_item(&self, source: &Path, entry: &DiaryEntry) -> Result<ImportItem> {
+        let mut item = ImportItem::from_entry(source, entry);
+        if self.storage.has_content(entry.date, &entry.content_hash())? {
+            item.conflicts.push(ImportConflict::Duplicate);
+        } else if self.storage.latest_entry(entry.date)?.is_some() {
+            item.conflicts.push(ImportConflict::NewVersion);
+        }
+        Ok(item)
+    }
+
+    /// Syncs new diary files into storage and reports what happened
+    pub fn sync(&self) -> Result<SyncReport> {
+        let _sync = info_span!("sync").entered();
+        let started = Instant::now();
+
+        self.hooks.fire(HookPayload::PreSync { directory: &self.directory })?;
+
+        // Collect files that match our pattern
+        let collected = info_span!("collect").in_scope(|| self.file_repo.collect_diary_files())?;
+        info!(files = collected.files.len(), pending = collected.pending.len(), "Collected diary files");
+
+        // Open the run; its id is the version the new entries are stored under
+        let exec_version = self.storage.start_sync_run(self.file_repo.normalization().as_str())?;
+        let mut stats = RunStats::default();
+        let report = self.sync_run(exec_version, started, collected, &mut stats);
+        if let Err(e) = &report {
+            stats.duration_ms = started.elapsed().as_millis() as u64;
+            self.close_failed_run(exec_version, stats, e)?;
+        }
+        report
+    }
+
+    /// Closes a run that failed: one that stored nothing is not kept, and
+    /// one that did is finished with the error in its stats
+    fn close_failed_run(&self, exec_version: i64, mut stats: RunStats, error: &RustyDiaryError) -> Result<()> {
+        if stats.stored == 0 {
+            return self.storage.delete_sync_run(exec_version);
+        }
+        stats.error = Some(error.to_string());
+        self.storage.finish_sync_run(exec_version, &stats)
+    }
+
+    /// Stores the collected files as the entries of run `exec_version`
+    /// and cleans them up, keeping `stats` up to date as it goes
+    fn sync_run(
+        &self,
+        exec_version: i64,
+        started: Instant,
+        collected: CollectedFiles,
+        stats: &mut RunStats,
+    ) -> Result<SyncReport> {
+        let files = collected.files;
+        stats.files = files.len();
 
         // Process files into domain entries
-        let mut file_entries = self.file_repo.process_files(&files, exec_version)?;
+        let processed = info_span!("process", exec_version)
+            .in_scope(|| self.file_repo.process_files(&files, exec_version, self.progress.as_ref()))?;
+        for skipped in &processed.skipped {
+            self.events.publish(DiaryEvent::FileSkipped {
+                path: skipped.path.clone(),
+                reason: skipped.reason.clone(),
+            });
+        }
+        stats.skipped = processed.skipped.len();
+        let mut file_entries = processed.entries;
+        self.attach_photos(&mut file_entries, &processed.photos, exec_version)?;
+        if file_entries.is_empty() {
+            return Err(RustyDiaryError::AllFilesSkipped(processed.skipped.len()));
+        }
         // sort entries by date
         file_entries.sort_by_key(|entry| entry.date);
         let end_date = file_entries.last().unwrap().date;
         let start_date = file_entries.first().unwrap().date;
 
-        // Fetch all stored entries
-        let stored_entries = self.storage.entries_by_date_range(
-            start_date,
-            end_date
-        )?;
-
         // Filter out new entries with the same content
-        let new_entries: Vec<_> = file_entries.into_iter()
-            .filter(|entry| {
-            !stored_entries.iter().any(|stored_entry| {
-                stored_entry.date == entry.date && stored_entry.content == entry.content
-            })
+        self.cancel.check()?;
+        let read = file_entries.len();
+        let new_entries = info_span!("dedup", %start_date, %end_date).in_scope(|| {
+            let new_entries = self.storage.unstored_entries(file_entries)?;
+            debug!(new = new_entries.len(), "Deduplicated entries");
+            Ok::<_, RustyDiaryError>(new_entries)
+        })?;
+
+        // Store new entries in database
+        let stored = new_entries.len();
+        let notify = !self.hooks.is_empty(HookEvent::EntryStored)
+            || self.events.has_subscribers()
+            || self.auto_tag.is_some()
+            || !self.enrichers.is_empty()
+            || !self.habits.is_empty();
+        let stored_entries = if notify { new_entries.clone() } else { Vec::new() };
+        info_span!("store").in_scope(|| {
+            self.storage.store_entries_with_progress(new_entries, self.progress.as_ref())
+        })?;
+        stats.stored = stored;
+        stats.duplicates = read - stored;
+        info!(stored, "Stored new entries");
+        // Not cancelled past this point, so the files of stored entries are cleaned up
+
+        let auto_tagged = match self.auto_tag {
+            Some(threshold) if stored > 0 => {
+                info_span!("auto_tag").in_scope(|| self.auto_tag_entries(&stored_entries, threshold))?
+            }
+            _ => 0,
+        };
+
+        let enriched = info_span!("enrich").in_scope(|| self.enrich_entries(&stored_entries))?;
+        self.record_habits(&stored_entries)?;
+
+        for entry in stored_entries {
+            self.hooks.fire(HookPayload::EntryStored { entry: &entry })?;
+            self.events.publish(DiaryEvent::EntryStored(entry));
+        }
+
+        // Clean up processed files; skipped and lossily read ones stay so
+        // nothing unread is lost
+        let synced: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|file| {
+                !processed.skipped.iter().chain(&processed.lossy).any(|kept| &kept.path == file)
+                    && !processed.photos.iter().any(|photo| &photo.source == file)
             })
             .collect();
+        let mut cleanup_failed = info_span!("cleanup").in_scope(|| self.file_repo.cleanup_files(&synced))?;
+        let photo_failed = self.file_repo.file_photos(&processed.photos);
+        let photos = processed
+            .photos
+            .iter()
+            .filter(|photo| !photo_failed.iter().any(|failed| failed.path == photo.source))
+            .map(|photo| photo.destination.clone())
+            .collect();
+        cleanup_failed.extend(photo_failed);
+        stats.duration_ms = started.elapsed().as_millis() as u64;
+        self.storage.finish_sync_run(exec_version, stats)?;
 
-        // Store new entries in database
-        self.storage.store_entries(new_entries)?;
+        self.events.publish(DiaryEvent::SyncCompleted { exec_version, stored, start_date, end_date });
+        Ok(SyncReport {
+            exec_version,
+            stored,
+            skipped: processed.skipped,
+            lossy: processed.lossy,
+            auto_tagged,
+            enriched,
+            photos,
+            cleanup_failed,
+            pending: collected.pending,
+            normalization: self.file_repo.normalization(),
+            start_date,
+            end_date,
+        })
+    }
+
+    /// References the images of a photo-journal sync from their day's
+    /// entry: the one read from files in this run, else a new version of
+    /// the latest stored one, else a stub holding only the images
+    fn attach_photos(&self, entries: &mut Vec<DiaryEntry>, photos: &[Photo], exec_version: i64) -> Result<()> {
+        for photo in photos {
+            let reference = photo.reference(&self.directory);
+            let position = match entries.iter().position(|entry| entry.date == photo.date) {
+                Some(position) => position,
+                None => {
+                    let now = self.clock.now();
+                    let entry = match self.storage.latest_entry(photo.date)? {
+                        Some(latest) => DiaryEntry {
+                            author: latest.author,
+                            ..DiaryEntry::new(exec_version, photo.date, latest.content, now)
+                        },
+                        None => DiaryEntry::new(exec_version, photo.date, String::new(), now),
+                    };
+                    entries.push(entry);
+                    entries.len() - 1
+                }
+            };
+            let content = &mut entries[position].content;
+            if content.contains(&reference) {
+                continue;
+            }
+            if !content.is_empty() {
+                content.push_str("\n\n");
+            }
+            content.push_str(&reference);
+        }
+        if !photos.is_empty() {
+            info!(photos = photos.len(), "Attached photos");
+        }
+        Ok(())
+    }
+
+    /// Writes the stored entries in the range into a new journal file. With a
+    /// journal split, rewrites the changed period files covering the range
+    /// instead. With a canonical journal file, merges the latest version of
+    /// each day into it. Files that would not change are not rewritten.
+    pub fn write_journal(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<JournalReport> {
+        self.write_journal_matching(&EntryQuery::between(start_date, end_date))
+    }
+
+    /// Like `write_journal`, limited to the stored entries matching the query
+    pub fn write_journal_matching(&self, query: &EntryQuery) -> Result<JournalReport> {
+        let (start_date, end_date) = query.bounds();
+        let _write = info_span!("write_journal", %start_date, %end_date).entered();
+        self.cancel.check()?;
+
+        let report = self.write_journal_files(query)?;
+        if report.is_up_to_date() {
+            info!("Journal {} is up to date", report.path.display());
+        }
+        for path in &report.written {
+            self.hooks.fire(HookPayload::JournalWritten { path })?;
+        }
+        Ok(report)
+    }
+
+    /// Entries with the location and weather of their day above their content
+    #[cfg(feature = "stamp")]
+    fn bylined(&self, mut entries: Vec<DiaryEntry>) -> Result<Vec<DiaryEntry>> {
+        for entry in &mut entries {
+            stamp::add_byline(entry, &self.storage.meta(entry.date)?);
+        }
+        Ok(entries)
+    }
+
+    #[cfg(not(feature = "stamp"))]
+    fn bylined(&self, entries: Vec<DiaryEntry>) -> Result<Vec<DiaryEntry>> {
+        Ok(entries)
+    }
+
+    fn write_journal_files(&self, query: &EntryQuery) -> Result<JournalReport> {
+        let (start_date, end_date) = query.bounds();
+
+        if let Some(path) = &self.journal_file {
+            let mut entries = self.bylined(self.storage.query(query)?)?;
+            entries.dedup_by_key(|entry| entry.date);
+
+            let changed = self.file_repo.update_journal(path, &entries, self.progress.as_ref())?;
+            let written = if changed { vec![path.clone()] } else { Vec::new() };
+            return Ok(JournalReport { path: path.clone(), written });
+        }
+
+        if self.journal_split != JournalSplit::None {
+            // Period files always hold the whole period
+            let query = EntryQuery {
+                start_date: Some(self.journal_split.period(start_date).start),
+                end_date: Some(self.journal_split.period(end_date).end),
+                ..query.clone()
+            };
+            let entries = self.bylined(self.storage.query(&query)?)?;
+
+            let written = self.file_repo.write_periods(entries, self.journal_split, self.progress.as_ref())?;
+            debug!(written = written.len(), split = %self.journal_split, "Wrote changed journal periods");
+            return Ok(JournalReport { path: self.file_repo.journal_dir(), written });
+        }
+
+        let entries = self.bylined(self.storage.query(query)?)?;
+        debug!(entries = entries.len(), "Writing journal");
+        self.file_repo.write_entries(entries, self.progress.as_ref())
+    }
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use crate::clock::{FixedClock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_sync_publishes_events() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "First day")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "   ")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+        let events = diary.subscribe();
+
+        diary.synchronize()?;
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        diary.delete_entry(date)?;
+
+        let received: Vec<DiaryEvent> = events.try_iter().collect();
+        assert!(matches!(&received[0], DiaryEvent::FileSkipped { path, .. } if path.ends_with("2024-01-02.md")));
+        assert!(matches!(&received[1], DiaryEvent::EntryStored(entry) if entry.content == "First day"));
+        assert!(matches!(received[2], DiaryEvent::SyncCompleted { stored: 1, .. }));
+        assert_eq!(received[3], DiaryEvent::EntryDeleted { date, versions: 1 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_clock_stamps_entries_runs_and_journals() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "First day")?;
+        let now = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap().and_hms_opt(7, 30, 0).unwrap();
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_saved_search("recent", "from:yesterday")
+            .with_clock(Arc::new(FixedClock(now)));
+        let diary = RustyDiary::new(config)?;
+        let (start, end) = diary.synchronize()?;
+
+        let entry = diary.storage.latest_entry(start)?.unwrap();
+        assert_eq!((entry.created_at, entry.updated_at), (now, Some(now)));
+        let run = diary.storage.sync_run(entry.exec_version)?.unwrap();
+        assert_eq!((run.started_at, run.finished_at), (now, Some(now)));
+        let report = diary.write_journal(start, end)?;
+        assert_eq!(report.path.file_name().unwrap(), "rusty-diary-log_2024-03-06_1.md");
+        assert_eq!(diary.saved_search("recent")?.start_date, NaiveDate::from_ymd_opt(2024, 3, 5));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_with_only_skipped_files_fails() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "   ")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+
+        assert!(matches!(diary.sync(), Err(RustyDiaryError::AllFilesSkipped(1))));
+        // The run stored nothing, so it is not left behind unfinished
+        assert!(diary.storage().sync_runs()?.is_empty());
+        Ok(())
+    }
 
-        // Clean up processed files
-        self.file_repo.cleanup_files(&files)?;
+    #[test]
+    fn test_cancelled_sync_leaves_files_and_database_alone() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("2024-01-02.md");
+        std::fs::write(&file, "Imported later")?;
 
-        Ok((start_date, end_date))
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let cancel = CancellationToken::new();
+        let diary = RustyDiary::new(config)?.with_cancellation(cancel.clone());
+        cancel.cancel();
+
+        assert!(matches!(diary.sync(), Err(RustyDiaryError::Cancelled)));
+        assert!(file.exists());
+        assert!(diary.storage().latest_entries(NaiveDate::MIN, NaiveDate::MAX)?.is_empty());
+        assert!(diary.storage().sync_runs()?.is_empty());
+        let journal = diary.write_journal(NaiveDate::MIN, NaiveDate::MAX);
+        assert!(matches!(journal, Err(RustyDiaryError::Cancelled)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized_text_deduplicates_across_systems() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("2024-01-01.md");
+        std::fs::write(&file, "\u{feff}Caf\u{e9} notes\r\nsecond line\r\n")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_normalization(Normalization::Nfc);
+        let diary = RustyDiary::new(config)?;
+
+        let report = diary.sync()?;
+        assert_eq!((report.stored, report.normalization), (1, Normalization::Nfc));
+        assert_eq!(diary.storage.sync_run_normalization(report.exec_version)?.as_deref(), Some("nfc"));
+
+        // The same text saved on another system is not stored again
+        std::fs::write(&file, "Cafe\u{301} notes\nsecond line\n")?;
+        let report = diary.sync()?;
+        assert_eq!((report.exec_version, report.stored), (2, 0));
+        let stats = diary.storage.sync_run(2)?.and_then(|run| run.stats).unwrap();
+        assert_eq!((stats.files, stats.stored, stats.duplicates, stats.skipped), (1, 0, 1, 0));
+        assert_eq!(diary.storage.sync_runs()?.iter().map(|run| run.id).collect::<Vec<_>>(), vec![2, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reimporting_a_backup_is_a_no_op() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let backup = [("2018-03-01.md", "Sharded day"), ("2019-06-01.md", "Archived day"), ("2019-06-02.md", "Live day")];
+        let write_backup = || -> Result<()> {
+            for (name, content) in backup {
+                std::fs::write(temp_dir.path().join(name), content)?;
+            }
+            Ok(())
+        };
+
+        let diary = RustyDiary::new(Config::new().with_directory(temp_dir.path()).with_db(temp_dir.path().join("test.db")))?;
+        write_backup()?;
+        assert_eq!(diary.sync()?.stored, 3);
+        diary.storage.archive_year(2018)?;
+        diary.cold_archive(NaiveDate::from_ymd_opt(2019, 6, 2).unwrap())?;
+
+        // A later sync of recent days leaves the old range out of view
+        std::fs::write(temp_dir.path().join("2024-05-01.md"), "Recent day")?;
+        assert_eq!(diary.sync()?.stored, 1);
+
+        write_backup()?;
+        let report = diary.sync()?;
+        assert_eq!(report.stored, 0);
+        assert_eq!(diary.storage.sync_run(report.exec_version)?.and_then(|run| run.stats).unwrap().duplicates, 3);
+
+        // The same text on another day is still new
+        std::fs::write(temp_dir.path().join("2019-06-03.md"), "Live day")?;
+        assert_eq!(diary.sync()?.stored, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_preview_sync_changes_nothing() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_layout(2, file::PathDates::Ignore);
+        let diary = RustyDiary::new(config)?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Stored day")?;
+        diary.sync()?;
+
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Stored day")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "A #work day\n\n![chart](attachments/chart.png)")?;
+        std::fs::create_dir(temp_dir.path().join("old"))?;
+        std::fs::write(temp_dir.path().join("old").join("2024-01-02.md"), "Older notes")?;
+        std::fs::write(temp_dir.path().join("2024-01-03.md"), "   ")?;
+
+        let preview = diary.preview_sync()?;
+        let items: Vec<(NaiveDate, &[ImportConflict])> =
+            preview.items.iter().map(|item| (item.date, item.conflicts.as_slice())).collect();
+        assert_eq!(items[0], (NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(), &[ImportConflict::Duplicate][..]));
+        assert_eq!(preview.items[1].tags, ["work"]);
+        assert_eq!(preview.items[1].attachments, 1);
+        assert_eq!(
+            preview.items[1].conflicts,
+            [ImportConflict::SameDay { sources: vec![temp_dir.path().join("old").join("2024-01-02.md")] }]
+        );
+        assert_eq!(preview.items.len(), 3);
+        assert!(preview.skipped[0].path.ends_with("2024-01-03.md"));
+
+        assert_eq!(diary.storage.latest_exec_version()?, 1);
+        assert!(temp_dir.path().join("2024-01-02.md").exists());
+        Ok(())
     }
 
+    #[test]
+    fn test_preview_in_memory_creates_no_database() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "First day")?;
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_in_memory(true);
+        let diary = RustyDiary::new(config)?;
+
+        let preview = diary.preview_sync()?;
+        assert_eq!(preview.items.len(), 1);
+        assert!(preview.items[0].conflicts.is_empty());
+        let files: Vec<_> = std::fs::read_dir(temp_dir.path())?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<_>>()?;
+        assert_eq!(files, ["2024-01-01.md"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_auto() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let export = TempDir::new()?;
+        let config = Config::new().with_directory(temp_dir.path()).with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+
+        let journal = export.path().join("Journal.json");
+        std::fs::write(
+            &journal,
+            r#"{"metadata": {}, "entries": [
+                {"creationDate": "2024-03-05T12:00:00Z", "text": "Hike ![](dayone-moment://P1)", "tags": ["outdoors"],
+                 "photos": [{"identifier": "P1", "md5": "abc", "type": "jpeg"}]},
+                {"creationDate": "2024-03-06T12:00:00Z", "text": ""}
+            ]}"#,
+        )?;
+        std::fs::create_dir(export.path().join("photos"))?;
+        std::fs::write(export.path().join("photos").join("abc.jpeg"), b"jpeg")?;
+
+        let preview = diary.preview_import("auto", &journal)?;
+        assert_eq!((preview.items.len(), preview.skipped.len()), (1, 1));
+        assert_eq!(preview.items[0].tags, ["outdoors"]);
+        assert_eq!(diary.storage.latest_exec_version()?, 0);
+
+        let report = diary.import("auto", &journal)?;
+        assert_eq!(report.importer, "dayone");
+        assert_eq!((report.stored, report.duplicates, report.attachments, report.skipped.len()), (1, 0, 1, 1));
+        assert!(temp_dir.path().join("attachments").join("dayone").join("abc.jpeg").exists());
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert!(diary.storage.latest_entry(date)?.unwrap().content.contains("attachments/dayone/abc.jpeg"));
+
+        let report = diary.import("dayone", &journal)?;
+        assert_eq!((report.stored, report.duplicates, report.attachments), (0, 1, 0));
+
+        let notes = export.path().join("notes");
+        std::fs::create_dir(&notes)?;
+        std::fs::write(notes.join("2024-03-05.md"), "Evening notes")?;
+        assert_eq!(diary.import("auto", &notes)?.importer, "markdown");
+        assert_eq!(diary.storage.latest_entry(date)?.unwrap().content, "Evening notes");
+
+        let err = diary.import("evernote", &notes).unwrap_err().to_string();
+        assert!(err.contains("Unknown importer 'evernote'") && err.contains("dayone, markdown"));
+        assert!(diary.import("auto", &export.path().join("photos")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_utf8_is_kept_on_disk() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let broken = temp_dir.path().join("2024-01-01.md");
+        std::fs::write(&broken, b"Caf\xe9 au lait")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "Fine")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let report = RustyDiary::new(config.clone())?.sync()?;
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].reason.contains("Invalid UTF-8 at byte 3"));
+        assert!(report.skipped[0].reason.starts_with(&format!("{}:1:4: ", broken.display())));
+        assert!(broken.exists());
+
+        let diary = RustyDiary::new(config.with_lossy_utf8(true))?;
+        let report = diary.sync()?;
+        assert_eq!((report.stored, report.lossy.len()), (1, 1));
+        assert!(broken.exists());
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        assert_eq!(diary.storage.latest_entry(date)?.unwrap().content, "Caf\u{fffd} au lait");
+        Ok(())
+    }
+
+    #[test]
+    fn test_week_file_split_by_date_headings() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(
+            temp_dir.path().join("2024-03-04.md"),
+            "## 2024-03-04\nMonday\n\n## 2024-03-05\nTuesday\n",
+        )?;
+        std::fs::write(temp_dir.path().join("2024-03-05.md"), "Tuesday evening")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_date_heading_split(true);
+        let diary = RustyDiary::new(config)?;
+        let report = diary.sync()?;
+        assert_eq!(report.stored, 2);
+
+        let tuesday = diary.storage.latest_entry(NaiveDate::from_ymd_opt(2024, 3, 5).unwrap())?.unwrap();
+        assert_eq!(tuesday.content, "Tuesday\n\nTuesday evening");
+        Ok(())
+    }
+
+    #[test]
+    fn test_photos_attached_to_their_day() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("2024-03-04.md"), "Monday")?;
+        std::fs::write(dir.join("2024-03-04-1.jpg"), "jpeg")?;
+        std::fs::write(dir.join("2024-03-05 beach.png"), "png")?;
+        std::fs::write(dir.join("IMG_0042.jpg"), "undated")?;
+
+        let config = Config::new().with_directory(dir).with_db(dir.join("test.db")).with_photo_journal(true);
+        let diary = RustyDiary::new(config)?;
+        let report = diary.sync()?;
+        assert_eq!(report.stored, 2);
+        assert_eq!(report.photos.len(), 2);
+        assert!(dir.join("attachments/2024/2024-03-04-1.jpg").exists() && !dir.join("2024-03-04-1.jpg").exists());
+        assert!(dir.join("IMG_0042.jpg").exists());
 
-    pub fn write_journal(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<()> {
-        let entries = self.storage.entries_by_date_range(start_date, end_date)?;
-        self.file_repo.write_entries(entries)?;
+        let monday = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let tuesday = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        assert_eq!(diary.storage.latest_entry(monday)?.unwrap().content, "Monday\n\n![2024-03-04-1](attachments/2024/2024-03-04-1.jpg)");
+        assert_eq!(
+            diary.storage.latest_entry(tuesday)?.unwrap().content,
+            "![2024-03-05 beach](attachments/2024/2024-03-05%20beach.png)"
+        );
+        assert!(diary.check_links(None)?.is_empty());
 
+        // A later photo of a stored day extends its latest version; a
+        // different image with a filed name gets a suffix
+        std::fs::write(dir.join("2024-03-04-1.jpg"), "another jpeg")?;
+        let report = diary.sync()?;
+        assert_eq!(report.photos, vec![dir.join("attachments/2024/2024-03-04-1-2.jpg")]);
+        assert_eq!(
+            diary.storage.latest_entry(monday)?.unwrap().content,
+            "Monday\n\n![2024-03-04-1](attachments/2024/2024-03-04-1.jpg)\n\n![2024-03-04-1-2](attachments/2024/2024-03-04-1-2.jpg)"
+        );
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_attachment_garbage_collection() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        std::fs::create_dir_all(dir.join("attachments/2023"))?;
+        for name in ["kept.png", "old.png", "archived.png", "orphan.png"] {
+            std::fs::write(dir.join("attachments/2023").join(name), name)?;
+        }
+        std::fs::write(dir.join("2023-01-01.md"), "![](attachments/2023/archived.png)")?;
+        std::fs::write(dir.join("2023-06-01.md"), "![](attachments/2023/old.png)")?;
+
+        let config = Config::new().with_directory(dir).with_db(dir.join("test.db"));
+        let diary = RustyDiary::new(config)?;
+        diary.sync()?;
+        std::fs::write(dir.join("2023-06-01.md"), "Replaced ![](attachments/2023/kept.png)")?;
+        diary.sync()?;
+        diary.storage.cold_archive(NaiveDate::from_ymd_opt(2023, 2, 1).unwrap(), None)?;
+
+        // Older versions and archived entries still hold on to their files
+        let unused = diary.collect_attachment_garbage(true)?;
+        assert_eq!(unused, vec![UnusedAttachment { path: dir.join("attachments/2023/orphan.png"), size: 10 }]);
+        assert!(unused[0].path.exists());
+
+        diary.storage.delete_entries(NaiveDate::from_ymd_opt(2023, 6, 1).unwrap())?;
+        let unused = diary.collect_attachment_garbage(false)?;
+        let names: Vec<_> = unused.iter().map(|attachment| attachment.path.file_name().unwrap()).collect();
+        assert_eq!(names, ["kept.png", "old.png", "orphan.png"]);
+        assert!(!dir.join("attachments/2023/old.png").exists() && dir.join("attachments/2023/archived.png").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fix_links_stores_new_version() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::create_dir_all(temp_dir.path().join("attachments/2023"))?;
+        std::fs::write(temp_dir.path().join("attachments/2023/cat.png"), "")?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "![cat](cat.png) [[2023-12-31]]")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_author("Ana");
+        let diary = RustyDiary::new(config)?;
+        diary.sync()?;
+
+        let broken = diary.check_links(Some(&temp_dir.path().join("attachments")))?;
+        assert_eq!(broken.len(), 2);
+        assert_eq!(diary.fix_links(&broken)?, 1);
+
+        let entry = diary.storage.latest_entry(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())?.unwrap();
+        assert_eq!((entry.exec_version, entry.content.as_str()), (2, "![cat](attachments/2023/cat.png) [[2023-12-31]]"));
+        assert_eq!(entry.author.as_deref(), Some("Ana"));
+        assert_eq!(diary.check_links(None)?.len(), 1);
+
+        // The fix is recorded as a finished run of its own
+        let runs = diary.storage.sync_runs()?;
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].stats.as_ref().map(|stats| stats.stored), Some(1));
+        assert_eq!(diary.fix_links(&diary.check_links(None)?)?, 0);
+        assert_eq!(diary.storage.sync_runs()?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_tag_stores_confident_suggestions() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Work and meetings all day")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "Work, then climbing. Climbing is fun")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_auto_tag(0.5);
+        let diary = RustyDiary::new(config)?;
+        let report = diary.sync()?;
+        assert!(report.auto_tagged > 0);
+
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let stored = diary.storage.stored_tags(date)?;
+        assert_eq!(stored[0].tag, "climbing");
+        assert!(stored.iter().all(|tag| tag.source == "auto" && tag.confidence >= Some(0.5)));
+
+        let suggestions = diary.suggest_tags(date, 10)?.unwrap();
+        assert!(suggestions.len() > stored.len());
+        assert!(diary.suggest_tags(NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(), 10)?.is_none());
+        Ok(())
+    }
+
+    struct WordCount;
+
+    impl Enricher for WordCount {
+        fn name(&self) -> &str {
+            "word-count"
+        }
+
+        fn enrich(&self, entry: &DiaryEntry) -> Result<Vec<(String, String)>> {
+            if entry.content.contains("offline") {
+                return Err(RustyDiaryError::InvalidConfig("cache unavailable".to_string()));
+            }
+            Ok(vec![("words".to_string(), entry.word_count().to_string()), ("bad key".to_string(), "x".to_string())])
+        }
+    }
+
+    #[test]
+    fn test_enrichers_store_metadata() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Three words here")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "Worked offline")?;
+
+        let config = Config::new().with_directory(temp_dir.path()).with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?.with_enricher(Arc::new(WordCount));
+        let report = diary.sync()?;
+        assert_eq!((report.stored, report.enriched), (2, 1));
+
+        let fields = diary.storage.meta(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap())?;
+        assert_eq!(fields.into_iter().collect::<Vec<_>>(), vec![("words".to_string(), "3".to_string())]);
+        assert!(diary.storage.meta(NaiveDate::from_ymd_opt(2024, 1, 2).unwrap())?.is_empty());
+        Ok(())
+    }
+
+    #[cfg(feature = "stamp")]
+    #[test]
+    fn test_location_is_stamped_and_shown_as_byline() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "New year walk")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_location("Lisbon");
+        let diary = RustyDiary::new(config)?;
+        assert_eq!(diary.sync()?.enriched, 1);
+
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let journal = std::fs::read_to_string(diary.write_journal(date, date)?.path)?;
+        assert!(journal.contains("# 2024-01-01\n*Lisbon*\n\nNew year walk"), "{}", journal);
+        Ok(())
+    }
+
+    #[cfg(feature = "stamp")]
+    #[test]
+    fn test_exports_show_the_byline() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "New year walk")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_location("Lisbon");
+        let diary = RustyDiary::new(config)?;
+        diary.sync()?;
+
+        let out = temp_dir.path().join("diary.html");
+        assert_eq!(diary.export("html", DateRange::all(), &out)?, 1);
+        let html = std::fs::read_to_string(&out)?;
+        assert!(html.contains("<em>Lisbon</em>"), "{}", html);
+        Ok(())
+    }
+
+    #[test]
+    fn test_habits_are_recorded_and_rescanned() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "- [x] meditate\n- [x] run")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "- [x] meditate\n- [ ] run")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_habit("meditate");
+        let diary = RustyDiary::new(config.clone())?;
+        diary.sync()?;
+        let stats = diary.habit_stats(DateRange::all())?;
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].habit.as_str(), stats[0].current_streak), ("meditate", 2));
+
+        let diary = RustyDiary::new(config.with_habit("run"))?;
+        assert_eq!(diary.rescan_habits()?, 2);
+        let stats = diary.habit_stats(DateRange::all())?;
+        assert_eq!((stats[1].habit.as_str(), stats[1].current_streak, stats[1].longest_streak), ("run", 0, 1));
+        assert_eq!(stats[1].months[0].percent, 50.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reminder_fires_hook_for_unwritten_day() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-03-05.md"), "Short day")?;
+
+        let fired = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = fired.clone();
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_hook(HookEvent::Reminder, crate::hooks::Hook::callback(move |payload| {
+                if let &HookPayload::Reminder { date, reason } = payload {
+                    sink.lock().unwrap().push((date, *reason));
+                }
+                Ok(())
+            }));
+        let diary = RustyDiary::new(config)?;
+        diary.sync()?;
+
+        let evening = |day| NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(21, 0, 0).unwrap();
+        let mut reminder = Reminder::new(chrono::NaiveTime::from_hms_opt(20, 0, 0).unwrap()).with_word_goal(100);
+        assert_eq!(diary.remind(&mut reminder, evening(5))?, Some(ReminderReason::BelowGoal { words: 2, goal: 100 }));
+        assert_eq!(diary.remind(&mut reminder, evening(5))?, None);
+        assert_eq!(diary.remind(&mut reminder, evening(6))?, Some(ReminderReason::NoEntry));
+        assert_eq!(fired.lock().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_journal_rewrites_changed_periods() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-02-10.md"), "February")?;
+        std::fs::write(temp_dir.path().join("2024-03-01.md"), "March")?;
+
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = written.clone();
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_journal_split(JournalSplit::Month)
+            .with_hook(HookEvent::JournalWritten, crate::hooks::Hook::callback(move |payload| {
+                if let HookPayload::JournalWritten { path } = payload {
+                    sink.lock().unwrap().push(path.to_path_buf());
+                }
+                Ok(())
+            }));
+        let diary = RustyDiary::new(config)?;
+
+        let (start, end) = diary.synchronize()?;
+        let journal = diary.write_journal(start, end)?.path;
+        assert_eq!(journal, temp_dir.path().join("journal"));
+        assert!(std::fs::read_to_string(journal.join("2024/2024-02.md"))?.contains("February"));
+        assert_eq!(written.lock().unwrap().len(), 2);
+
+        // A later day in March only touches the March file, which keeps March 1st
+        std::fs::write(temp_dir.path().join("2024-03-20.md"), "Spring")?;
+        let (start, end) = diary.synchronize()?;
+        diary.write_journal(start, end)?;
+        let march = std::fs::read_to_string(journal.join("2024/2024-03.md"))?;
+        assert!(march.contains("March") && march.contains("Spring"));
+        assert_eq!(written.lock().unwrap().len(), 3);
+
+        // Nothing changed, nothing rewritten
+        diary.write_journal(start, end)?;
+        assert_eq!(written.lock().unwrap().len(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_journal_matching_query() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2023-06-01.md"), "Flew to Porto #travel")?;
+        std::fs::write(temp_dir.path().join("2023-06-02.md"), "Stayed home")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+        diary.synchronize()?;
+
+        let journal = diary.write_journal_matching(&EntryQuery::new().with_tag("travel"))?.path;
+        let content = std::fs::read_to_string(journal)?;
+        assert!(content.contains("Flew to Porto"));
+        assert!(!content.contains("Stayed home"));
+        assert!(content.contains("total-entries(1)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_saved_search_filters_search_and_export() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join(saved::SEARCHES_FILE), "work-frustrations = tag:work AND text:\"frustrated\"\n")?;
+        std::fs::write(temp_dir.path().join("2024-04-01.md"), "Meeting ran late.\nSo Frustrated today #work")?;
+        std::fs::write(temp_dir.path().join("2024-04-02.md"), "Calm day #work")?;
+        std::fs::write(temp_dir.path().join("2024-04-03.md"), "Frustrated by the weather")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_saved_search("calm", "calm");
+        let diary = RustyDiary::new(config)?;
+        diary.synchronize()?;
+
+        let query = diary.saved_search("work-frustrations")?.clone();
+        let hits = diary.storage().search_matching(&query, 5)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].line, "So Frustrated today #work");
+
+        let out = temp_dir.path().join("export.json");
+        assert_eq!(diary.export_matching("json", &query, &out)?, 1);
+        let short_work = EntryQuery::new().with_tag("work").with_max_words(3);
+        assert_eq!(diary.export_matching("quotes", &short_work, &temp_dir.path().join("quotes.md"))?, 1);
+        assert_eq!(diary.storage().search_matching(diary.saved_search("calm")?, 5)?.len(), 1);
+        assert!(diary.saved_search("gym").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_authors_from_config_and_frontmatter() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-05-01.md"), "Ana's day")?;
+        std::fs::write(temp_dir.path().join("2024-05-02.md"), "---\nauthor: Ben\n---\nBen's day, longer")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_author("Ana");
+        let diary = RustyDiary::new(config)?;
+        diary.synchronize()?;
+
+        let ben = EntryQuery::new().with_author("ben");
+        let out = temp_dir.path().join("export.json");
+        assert_eq!(diary.export_matching("json", &ben, &out)?, 1);
+        assert!(std::fs::read_to_string(&out)?.contains("\"author\": \"Ben\""));
+
+        let stats = diary.storage().author_stats(DateRange::all())?;
+        let authors: Vec<_> = stats.iter().map(|s| (s.author.as_deref(), s.stats.days, s.stats.total_words)).collect();
+        // The frontmatter stays part of the content
+        assert_eq!(authors, vec![(Some("Ana"), 1, 2), (Some("Ben"), 1, 7)]);
+
+        // Authors stay with entries moved into cold archives and shards
+        let date = |day| NaiveDate::from_ymd_opt(2024, 5, day).unwrap();
+        let author = |day| -> Result<Option<String>> {
+            Ok(diary.storage().latest_entry(date(day))?.and_then(|entry| entry.author))
+        };
+        assert_eq!(diary.cold_archive(date(2))?, 1);
+        diary.unarchive(date(1), date(1))?;
+        assert_eq!(author(1)?.as_deref(), Some("Ana"));
+        diary.storage().archive_year(2024)?;
+        assert_eq!(author(2)?.as_deref(), Some("Ben"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cold_archive_takes_days_and_attachments_along() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::create_dir(temp_dir.path().join("photos"))?;
+        std::fs::write(temp_dir.path().join("photos/beach.png"), [137, 80, 78, 71])?;
+        std::fs::write(temp_dir.path().join("map.png"), "shared")?;
+        std::fs::write(
+            temp_dir.path().join("2019-06-01.md"),
+            "Swam with @Ana, see [[2019-06-02]].\n\n==The sea was cold==\n\n![](./photos/beach.png) ![](map.png)",
+        )?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Back home ![](map.png)")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+        diary.synchronize()?;
+
+        let day = NaiveDate::from_ymd_opt(2019, 6, 1).unwrap();
+        diary.storage().set_starred(day, true)?;
+        diary.storage().set_meta(day, "mood", "calm")?;
+        diary.storage().replace_habit_checks(day, &[("swim".to_string(), true)])?;
+        let highlight = diary.storage().due_highlights(NaiveDate::MAX, None)?[0].id;
+        diary.storage().review_highlight(highlight, NaiveDate::from_ymd_opt(2019, 6, 9).unwrap())?;
+
+        assert_eq!(diary.cold_archive(NaiveDate::from_ymd_opt(2020, 1, 1).unwrap())?, 1);
+        // The photo only the archived day links to moves into the archive
+        assert!(!temp_dir.path().join("photos/beach.png").exists());
+        assert!(temp_dir.path().join("map.png").exists());
+        // Nothing of the day lingers in the main database
+        let storage = diary.storage();
+        assert!(storage.people(day, day)?.is_empty());
+        assert!(storage.starred(day, day)?.is_empty());
+        assert!(storage.habit_checks(day, day)?.is_empty());
+        assert!(storage.links()?.is_empty());
+        assert!(storage.meta(day)?.is_empty());
+        assert!(storage.due_highlights(NaiveDate::MAX, None)?.is_empty());
+        // The archived content is still known to sync
+        assert!(storage.has_content(day, &storage.archived_entries()?[0].content_hash())?);
+
+        assert_eq!(diary.unarchive(day, day)?, 1);
+        assert_eq!(std::fs::read(temp_dir.path().join("photos/beach.png"))?, [137, 80, 78, 71]);
+        assert_eq!(storage.people(day, day)?.len(), 1);
+        assert_eq!(storage.starred(day, day)?, vec![day]);
+        assert_eq!(storage.habit_checks(day, day)?.len(), 1);
+        assert_eq!(storage.links()?.len(), 1);
+        assert_eq!(storage.get_meta(day, "mood")?.as_deref(), Some("calm"));
+        let highlights = storage.due_highlights(NaiveDate::MAX, None)?;
+        assert_eq!(highlights[0].stage, 1);
+        assert_eq!(highlights[0].due, NaiveDate::from_ymd_opt(2019, 7, 9));
+        Ok(())
+    }
+
+    #[test]
+    fn test_journal_filename_template() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-03-09.md"), "Saturday")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_output_filename("{year}/{month}.md");
+        let diary = RustyDiary::new(config.clone())?;
+        let (start, end) = diary.synchronize()?;
+        let path = temp_dir.path().join("2024/03.md");
+        assert_eq!(diary.write_journal(start, end)?.written, vec![path.clone()]);
+
+        // Same content again is up to date; a different file is not overwritten
+        assert!(diary.write_journal(start, end)?.is_up_to_date());
+        std::fs::write(&path, "hand written")?;
+        assert_eq!(diary.write_journal(start, end)?.written, vec![temp_dir.path().join("2024/03-2.md")]);
+
+        // Journals named like diary files would be read back by the next sync
+        assert!(matches!(
+            RustyDiary::new(config.clone().with_output_filename("{start}.md")),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+        assert!(RustyDiary::new(config.with_output_filename("{week}.md")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_canonical_journal_file() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-03-01.md"), "First")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_journal_file("journal.md");
+        let diary = RustyDiary::new(config.clone())?;
+        let (start, end) = diary.synchronize()?;
+        let path = diary.write_journal(start, end)?.path;
+        assert_eq!(path, temp_dir.path().join("journal.md"));
+        assert!(diary.write_journal(start, end)?.is_up_to_date());
+
+        std::fs::write(temp_dir.path().join("2024-03-02.md"), "Second")?;
+        let (start, end) = diary.synchronize()?;
+        diary.write_journal(start, end)?;
+        let content = std::fs::read_to_string(&path)?;
+        assert!(content.find("Second").unwrap() < content.find("First").unwrap());
+        assert!(content.contains("total-entries(2)"));
+
+        assert!(RustyDiary::new(config.with_journal_split(JournalSplit::Year)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ignore_patterns() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for day in ["2024-01-01", "2024-01-02", "2024-01-03", "2024-01-04"] {
+            std::fs::write(temp_dir.path().join(format!("{}.md", day)), day)?;
+        }
+        std::fs::write(temp_dir.path().join(file::IGNORE_FILE), "2024-01-0*.md\n!2024-01-01.md\n")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_ignore_pattern("!2024-01-04.md");
+        let diary = RustyDiary::new(config)?;
+
+        let report = diary.sync()?;
+        assert_eq!(report.start_date, NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        assert_eq!(report.end_date, NaiveDate::from_ymd_opt(2024, 1, 4).unwrap());
+        assert_eq!(report.stored, 2);
+        assert!(temp_dir.path().join("2024-01-02.md").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_own_output_is_not_collected() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        for dir in ["notes", ".backup"] {
+            std::fs::create_dir(temp_dir.path().join(dir))?;
+        }
+        for name in [
+            "2024-01-01.md",
+            "rusty-diary-log_2024-05-01_1.md",
+            "notes/rusty-diary-log-2024-03-09-2.md",
+            "notes/2024-03-10.md",
+            ".backup/20240501_120000_2024-01-02.md",
+            "kept-2024-01-03.md",
+        ] {
+            std::fs::write(temp_dir.path().join(name), "Written")?;
+        }
+
+        // A pattern loose enough to match every one of them
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"))
+            .with_date_pattern(r"(\d{4}-\d{2}-\d{2})")
+            .with_layout(2, file::PathDates::Ignore)
+            .with_output_filename("notes/{prefix}-{start}.md")
+            .with_journal_file("kept-2024-01-03.md");
+        let diary = RustyDiary::new(config)?;
+
+        // Diary files next to the journals are still collected
+        let mut collected = diary.file_repo.collect_diary_files()?.files;
+        collected.sort();
+        assert_eq!(collected, vec![temp_dir.path().join("2024-01-01.md"), temp_dir.path().join("notes/2024-03-10.md")]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_names_are_portable() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let file = temp_dir.path().join("2024-01-01.md");
+        std::fs::write(&file, "Written")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?;
+        let backup = diary.file_repo.backup_file(&file)?;
+        let name = backup.file_name().unwrap().to_string_lossy();
+        assert_eq!(portable::file_name_problem(&name), None, "{}", name);
+        assert!(name.ends_with("_2024-01-01.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_nested_layout_dates() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let march = temp_dir.path().join("2024").join("03-march");
+        std::fs::create_dir_all(&march)?;
+        std::fs::write(march.join("2024-03-05.md"), "Named")?;
+        std::fs::write(march.join("06.md"), "Day only")?;
+        std::fs::write(march.join("2024-04-01.md"), "Misfiled")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+
+        // The default only looks at the top level
+        let diary = RustyDiary::new(config.clone())?;
+        assert!(matches!(diary.sync(), Err(RustyDiaryError::NoFilesFound(_))));
+
+        let diary = RustyDiary::new(config.with_layout(3, file::PathDates::Derive))?;
+        let report = diary.sync()?;
+        assert_eq!(report.stored, 2);
+        assert_eq!(report.end_date, NaiveDate::from_ymd_opt(2024, 3, 6).unwrap());
+        assert_eq!(report.skipped.len(), 1);
+        assert!(report.skipped[0].path.ends_with("2024-04-01.md"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_pending_files_are_left_alone() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-01-01.md"), "Written")?;
+        std::fs::write(temp_dir.path().join("2024-01-02.md"), "   ")?;
+        std::fs::write(temp_dir.path().join(".2024-01-03.md.icloud"), "")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+
+        let young = RustyDiary::new(config.clone().with_min_file_age(std::time::Duration::from_secs(3600)))?;
+        assert!(matches!(young.sync(), Err(RustyDiaryError::NoFilesFound(_))));
+
+        let report = RustyDiary::new(config)?.sync()?;
+        assert_eq!(report.stored, 1);
+        assert_eq!(report.pending.len(), 1);
+        assert!(report.pending[0].reason.contains("2024-01-03.md"));
+
+        // Synced files are removed, skipped ones kept
+        assert!(!temp_dir.path().join("2024-01-01.md").exists());
+        assert!(temp_dir.path().join("2024-01-02.md").exists());
+        Ok(())
+    }
+
+    struct TextParser;
+
+    impl FileParser for TextParser {
+        fn name(&self) -> &str {
+            "text"
+        }
+
+        fn extensions(&self) -> Vec<String> {
+            vec!["txt".to_string()]
+        }
+
+        fn parse(&self, _path: &Path, raw: &str) -> Result<String> {
+            Ok(format!("> {}", raw.trim()))
+        }
+    }
+
+    /// Writes one line per entry, naming the local files it links to
+    struct LineExporter;
+
+    impl Exporter for LineExporter {
+        fn name(&self) -> &str {
+            "lines"
+        }
+
+        fn export(&self, entries: &mut export::EntryStream, sink: &mut ExportSink) -> Result<()> {
+            let mut out = String::new();
+            for entry in entries {
+                let entry = entry?;
+                let attachments: Vec<String> = sink
+                    .attachments(&entry)
+                    .iter()
+                    .map(|path| path.file_name().unwrap_or_default().to_string_lossy().into_owned())
+                    .collect();
+                out.push_str(&format!("{} {:?}\n", entry.date, attachments));
+            }
+            sink.write(out)
+        }
+    }
+
+    #[test]
+    fn test_registered_parser_and_export() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join("2024-02-01.txt"), "plain text")?;
+
+        let config = Config::new()
+            .with_directory(temp_dir.path())
+            .with_db(temp_dir.path().join("test.db"));
+        let diary = RustyDiary::new(config)?.with_parser(Arc::new(TextParser));
+        let (start, end) = diary.synchronize()?;
+
+        let out = temp_dir.path().join("export.json");
+        assert_eq!(diary.export("json", DateRange::new(start, end), &out)?, 1);
+        let exported: Vec<crate::storage::models::DiaryEntry> =
+            serde_json::from_str(&std::fs::read_to_string(&out)?)?;
+        assert_eq!(exported[0].content, "> plain text");
+
+        diary.storage().store_entries(vec![DiaryEntry::new(9, start, "> edited".to_string(), now)])?;
+        assert_eq!(diary.export_matching("sqlite", &EntryQuery::new(), &temp_dir.path().join("all.db"))?, 2);
+        assert_eq!(diary.export_latest_matching("sqlite", &EntryQuery::new(), &temp_dir.path().join("flat.db"))?, 1);
+
+        assert!(matches!(
+            diary.export("pdf", DateRange::year_of(start), &out),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+
+        std::fs::write(temp_dir.path().join("map.png"), b"png")?;
+        diary.storage().store_entries(vec![DiaryEntry::new(10, start, "![](map.png) ![](gone.png)".to_string(), now)])?;
+        let cancel = CancellationToken::new();
+        let diary = diary.with_exporter(Arc::new(LineExporter)).with_cancellation(cancel.clone());
+        let out = temp_dir.path().join("lines.txt");
+        assert_eq!(diary.export_latest_matching("lines", &EntryQuery::new(), &out)?, 1);
+        assert_eq!(std::fs::read_to_string(&out)?, "2024-02-01 [\"map.png\"]\n");
+        cancel.cancel();
+        assert!(matches!(diary.export("lines", DateRange::year_of(start), &out), Err(RustyDiaryError::Cancelled)));
+        Ok(())
+    }
+}
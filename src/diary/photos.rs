@@ -0,0 +1,126 @@
+//! Photo-journal mode: images dropped in the diary folder are attached to
+//! their day's entry. A sync files each image under `attachments/<year>/`
+//! and references it at the end of the day's content, starting a stub
+//! entry for days without one.
+//!
+//! Images are dated by their file name (`2024-03-05-1.jpg`,
+//! `2024-03-05 beach.png`) or, in builds with the `images` feature, by when
+//! their EXIF data says they were taken.
+
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use super::portable;
+
+/// Folder under the diary directory images are filed in, by year
+pub const ATTACHMENTS_DIR: &str = "attachments";
+
+/// Extensions of the images a sync picks up, lowercase
+pub const EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "heic", "webp"];
+
+/// An image collected by a sync and where it is filed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Photo {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub date: NaiveDate,
+}
+
+impl Photo {
+    /// Markdown showing the filed image, relative to the diary directory
+    pub fn reference(&self, root_dir: &Path) -> String {
+        let relative = self.destination.strip_prefix(root_dir).unwrap_or(&self.destination);
+        let alt = self.destination.file_stem().unwrap_or_default().to_string_lossy();
+        format!("![{}]({})", alt, portable::slash_path(relative).replace(' ', "%20"))
+    }
+}
+
+pub fn is_photo(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// The date an image was taken: from its name, or its EXIF data
+pub fn photo_date(path: &Path) -> Option<NaiveDate> {
+    name_date(path).or_else(|| exif_date(path))
+}
+
+/// A date the file name starts with
+fn name_date(path: &Path) -> Option<NaiveDate> {
+    let stem = path.file_stem()?.to_str()?;
+    let date = stem.get(..10)?;
+    let rest = &stem[10..];
+    if !(rest.is_empty() || rest.starts_with(['-', '_', ' ', '.'])) {
+        return None;
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+#[cfg(feature = "images")]
+fn exif_date(path: &Path) -> Option<NaiveDate> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new().read_from_container(&mut std::io::BufReader::new(file)).ok()?;
+    [exif::Tag::DateTimeOriginal, exif::Tag::DateTimeDigitized, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| match &exif.get_field(tag, exif::In::PRIMARY)?.value {
+            exif::Value::Ascii(values) => {
+                let taken = exif::DateTime::from_ascii(values.first()?).ok()?;
+                NaiveDate::from_ymd_opt(taken.year.into(), taken.month.into(), taken.day.into())
+            }
+            _ => None,
+        })
+}
+
+#[cfg(not(feature = "images"))]
+fn exif_date(_path: &Path) -> Option<NaiveDate> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_photo_names() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 5);
+        assert!(is_photo(Path::new("2024-03-05-1.JPG")));
+        assert!(!is_photo(Path::new("2024-03-05.md")));
+        assert_eq!(name_date(Path::new("2024-03-05-1.jpg")), date);
+        assert_eq!(name_date(Path::new("2024-03-05 beach.png")), date);
+        assert_eq!(name_date(Path::new("2024-03-05.heic")), date);
+        assert_eq!(name_date(Path::new("2024-03-051.jpg")), None);
+        assert_eq!(name_date(Path::new("IMG_0042.jpg")), None);
+
+        let photo = Photo {
+            source: PathBuf::from("/diary/2024-03-05 beach.png"),
+            destination: PathBuf::from("/diary/attachments/2024/2024-03-05 beach.png"),
+            date: date.unwrap(),
+        };
+        assert_eq!(photo.reference(Path::new("/diary")), "![2024-03-05 beach](attachments/2024/2024-03-05%20beach.png)");
+    }
+
+    #[cfg(feature = "images")]
+    #[test]
+    fn test_exif_date() -> crate::error::Result<()> {
+        // A JPEG holding only an EXIF segment: IFD0 points to an Exif IFD
+        // with DateTimeOriginal
+        let mut tiff = b"II\x2a\x00\x08\x00\x00\x00".to_vec();
+        tiff.extend([1, 0, 0x69, 0x87, 4, 0, 1, 0, 0, 0, 26, 0, 0, 0, 0, 0, 0, 0]);
+        tiff.extend([1, 0, 0x03, 0x90, 2, 0, 20, 0, 0, 0, 44, 0, 0, 0, 0, 0, 0, 0]);
+        tiff.extend(b"2023:07:14 10:20:30\0");
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend(((tiff.len() + 8) as u16).to_be_bytes());
+        jpeg.extend(b"Exif\0\0");
+        jpeg.extend(tiff);
+        jpeg.extend([0xff, 0xd9]);
+
+        let temp_dir = tempfile::TempDir::new()?;
+        let path = temp_dir.path().join("IMG_0042.jpg");
+        std::fs::write(&path, jpeg)?;
+        assert_eq!(photo_date(&path), NaiveDate::from_ymd_opt(2023, 7, 14));
+        Ok(())
+    }
+}
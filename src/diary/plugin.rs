@@ -0,0 +1,338 @@
+//! Native plugins loaded from a plugins directory.
+//!
+//! A plugin is a shared library exporting `rusty_diary_plugin_v1`, which
+//! returns a pointer to a static [`PluginV1`] table, or
+//! `rusty_diary_importer_v1` returning an [`ImporterV1`] table, or both.
+//! Strings cross the boundary as NUL-terminated UTF-8; strings returned by
+//! the plugin are released through its own `free_string`.
+
+use std::ffi::{c_char, c_int, CStr, CString};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use libloading::Library;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::Deserialize;
+
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+use super::import::{ImportedAttachment, Imported, SourceItem};
+use super::export::{EntryStream, ExportSink};
+use super::processor::{Exporter, FileParser, Importer, ProcessorRegistry};
+
+/// Name of the symbol of parser and exporter plugins
+pub const ENTRY_SYMBOL: &[u8] = b"rusty_diary_plugin_v1";
+
+/// Name of the symbol of importer plugins
+pub const IMPORTER_SYMBOL: &[u8] = b"rusty_diary_importer_v1";
+
+/// Function table a plugin hands to the host.
+/// `parse` and `export` are optional; a null pointer means the plugin does
+/// not provide that capability.
+#[repr(C)]
+pub struct PluginV1 {
+    pub name: *const c_char,
+    /// Comma-separated file extensions handled by `parse`
+    pub extensions: *const c_char,
+    /// `(path, raw content) -> markdown`, or null on failure
+    pub parse: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char>,
+    /// `(entries as JSON, destination) -> error message`, or null on success
+    pub export: Option<unsafe extern "C" fn(*const c_char, *const c_char) -> *mut c_char>,
+    pub free_string: unsafe extern "C" fn(*mut c_char),
+}
+
+/// Function table of an importer plugin
+#[repr(C)]
+pub struct ImporterV1 {
+    pub name: *const c_char,
+    /// `(path) -> non-zero` when the file or folder is in the importer's format
+    pub detect: unsafe extern "C" fn(*const c_char) -> c_int,
+    /// `(path) -> JSON array of items`, or null on failure. Each item is
+    /// `{"source", "date", "content", "author"?, "attachments"?: [{"source", "path"}]}`,
+    /// attachment paths being relative to the attachments folder.
+    pub import: unsafe extern "C" fn(*const c_char) -> *mut c_char,
+    pub free_string: unsafe extern "C" fn(*mut c_char),
+}
+
+/// A loaded plugin. Keeps its library mapped for as long as any parser or
+/// exporter built from it is alive.
+struct LoadedPlugin {
+    name: String,
+    extensions: Vec<String>,
+    table: *const PluginV1,
+    _library: Library,
+}
+
+// The function table is immutable static data inside the library
+unsafe impl Send for LoadedPlugin {}
+unsafe impl Sync for LoadedPlugin {}
+
+fn plugin_error(path: &Path, reason: impl std::fmt::Display) -> RustyDiaryError {
+    RustyDiaryError::Plugin(format!("{}: {}", path.display(), reason))
+}
+
+/// Opens a plugin library and calls one of its entry symbols for the
+/// function table. `None` when the library does not export the symbol.
+fn load_table<T>(path: &Path, symbol: &[u8]) -> Result<Option<(Library, *const T)>> {
+    unsafe {
+        let library = Library::new(path).map_err(|e| plugin_error(path, e))?;
+        let table = match library.get::<unsafe extern "C" fn() -> *const T>(symbol) {
+            Ok(entry) => entry(),
+            Err(_) => return Ok(None),
+        };
+        if table.is_null() {
+            return Err(plugin_error(path, "plugin returned no function table"));
+        }
+        Ok(Some((library, table)))
+    }
+}
+
+impl LoadedPlugin {
+    fn load(path: &Path) -> Result<Option<Self>> {
+        let Some((library, table)) = load_table::<PluginV1>(path, ENTRY_SYMBOL)? else {
+            return Ok(None);
+        };
+
+        unsafe {
+            if (*table).name.is_null() {
+                return Err(plugin_error(path, "plugin returned no function table"));
+            }
+            let name = CStr::from_ptr((*table).name).to_string_lossy().into_owned();
+            let extensions = if (*table).extensions.is_null() {
+                Vec::new()
+            } else {
+                CStr::from_ptr((*table).extensions)
+                    .to_string_lossy()
+                    .split(',')
+                    .map(|ext| ext.trim().trim_start_matches('.').to_string())
+                    .filter(|ext| !ext.is_empty())
+                    .collect()
+            };
+
+            Ok(Some(Self { name, extensions, table, _library: library }))
+        }
+    }
+
+    fn table(&self) -> &PluginV1 {
+        unsafe { &*self.table }
+    }
+
+    /// Copies a plugin-owned string and hands it back to the plugin
+    fn take_string(&self, ptr: *mut c_char) -> Option<String> {
+        if ptr.is_null() {
+            return None;
+        }
+        unsafe {
+            let value = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+            (self.table().free_string)(ptr);
+            Some(value)
+        }
+    }
+}
+
+fn c_string(value: &str) -> Result<CString> {
+    CString::new(value)
+        .map_err(|_| RustyDiaryError::Plugin("string contains a NUL byte".to_string()))
+}
+
+struct PluginParser(Arc<LoadedPlugin>);
+
+impl FileParser for PluginParser {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn extensions(&self) -> Vec<String> {
+        self.0.extensions.clone()
+    }
+
+    fn parse(&self, path: &Path, raw: &str) -> Result<String> {
+        let parse = self.0.table().parse.ok_or_else(|| {
+            RustyDiaryError::Plugin(format!("{} does not provide a parser", self.0.name))
+        })?;
+        let path = c_string(&path.to_string_lossy())?;
+        let raw = c_string(raw)?;
+
+        let parsed = unsafe { parse(path.as_ptr(), raw.as_ptr()) };
+        self.0.take_string(parsed).ok_or_else(|| {
+            RustyDiaryError::Plugin(format!("{} failed to parse file", self.0.name))
+        })
+    }
+}
+
+struct PluginExporter(Arc<LoadedPlugin>);
+
+impl Exporter for PluginExporter {
+    fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        let export = self.0.table().export.ok_or_else(|| {
+            RustyDiaryError::Plugin(format!("{} does not provide an exporter", self.0.name))
+        })?;
+        let entries = c_string(&serde_json::to_string(&entries.collect::<Result<Vec<_>>>()?)?)?;
+        let destination = c_string(&sink.destination().to_string_lossy())?;
+
+        let error = unsafe { export(entries.as_ptr(), destination.as_ptr()) };
+        match self.0.take_string(error) {
+            Some(message) => Err(RustyDiaryError::Plugin(format!("{}: {}", self.0.name, message))),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A loaded importer plugin, keeping its library mapped
+struct PluginImporter {
+    name: String,
+    table: *const ImporterV1,
+    _library: Library,
+}
+
+// The function table is immutable static data inside the library
+unsafe impl Send for PluginImporter {}
+unsafe impl Sync for PluginImporter {}
+
+/// An item as an importer plugin returns it
+#[derive(Deserialize)]
+struct PluginItem {
+    source: PathBuf,
+    date: NaiveDate,
+    content: String,
+    #[serde(default)]
+    author: Option<String>,
+    #[serde(default)]
+    attachments: Vec<PluginAttachment>,
+}
+
+#[derive(Deserialize)]
+struct PluginAttachment {
+    source: PathBuf,
+    path: String,
+}
+
+impl PluginImporter {
+    fn load(path: &Path) -> Result<Option<Self>> {
+        let Some((library, table)) = load_table::<ImporterV1>(path, IMPORTER_SYMBOL)? else {
+            return Ok(None);
+        };
+        unsafe {
+            if (*table).name.is_null() {
+                return Err(plugin_error(path, "plugin returned no importer table"));
+            }
+            let name = CStr::from_ptr((*table).name).to_string_lossy().into_owned();
+            Ok(Some(Self { name, table, _library: library }))
+        }
+    }
+
+    fn table(&self) -> &ImporterV1 {
+        unsafe { &*self.table }
+    }
+}
+
+impl Importer for PluginImporter {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn detect(&self, source: &Path) -> bool {
+        let Ok(source) = c_string(&source.to_string_lossy()) else { return false };
+        unsafe { (self.table().detect)(source.as_ptr()) != 0 }
+    }
+
+    /// The plugin converts every item at once; each is kept as its JSON
+    fn enumerate(&self, source: &Path) -> Result<Vec<SourceItem>> {
+        let path = c_string(&source.to_string_lossy())?;
+        let items = unsafe { (self.table().import)(path.as_ptr()) };
+        if items.is_null() {
+            return Err(RustyDiaryError::Plugin(format!("{} failed to import {}", self.name, source.display())));
+        }
+        let json = unsafe {
+            let json = CStr::from_ptr(items).to_string_lossy().into_owned();
+            (self.table().free_string)(items);
+            json
+        };
+
+        let items: Vec<serde_json::Value> = serde_json::from_str(&json)?;
+        items
+            .into_iter()
+            .map(|item| {
+                let source = item.get("source").and_then(|source| source.as_str()).unwrap_or_default();
+                Ok(SourceItem { source: PathBuf::from(source), raw: item.to_string() })
+            })
+            .collect()
+    }
+
+    fn convert(&self, item: &SourceItem, now: NaiveDateTime) -> Result<Imported> {
+        let item: PluginItem = serde_json::from_str(&item.raw)?;
+        let attachments = item
+            .attachments
+            .into_iter()
+            .map(|attachment| ImportedAttachment { source: attachment.source, path: attachment.path })
+            .collect();
+        let entry = DiaryEntry { author: item.author, ..DiaryEntry::new(0, item.date, item.content, now) };
+        tracing::trace!(source = %item.source.display(), "Converted plugin item");
+        Ok(Imported { entry, attachments })
+    }
+}
+
+/// Loads every shared library in `dir` and registers what it provides.
+/// Returns the paths of the plugins that were loaded.
+pub fn load_plugins(dir: &Path, registry: &mut ProcessorRegistry) -> Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir).at_path(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.is_file()
+                && path.extension().is_some_and(|ext| ext == std::env::consts::DLL_EXTENSION)
+        })
+        .collect();
+    paths.sort();
+
+    for path in &paths {
+        let plugin = LoadedPlugin::load(path)?.map(Arc::new);
+        let importer = PluginImporter::load(path)?;
+        if plugin.is_none() && importer.is_none() {
+            return Err(plugin_error(path, "exports neither rusty_diary_plugin_v1 nor rusty_diary_importer_v1"));
+        }
+
+        if let Some(plugin) = plugin {
+            tracing::debug!(plugin = %plugin.name, path = %path.display(), "Loaded plugin");
+            if plugin.table().parse.is_some() && !plugin.extensions.is_empty() {
+                registry.register_parser(Arc::new(PluginParser(Arc::clone(&plugin))));
+            }
+            if plugin.table().export.is_some() {
+                registry.register_exporter(Arc::new(PluginExporter(plugin)));
+            }
+        }
+        if let Some(importer) = importer {
+            tracing::debug!(importer = %importer.name, path = %path.display(), "Loaded importer plugin");
+            registry.register_importer(Arc::new(importer));
+        }
+    }
+
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_plugins_rejects_invalid_library() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let mut registry = ProcessorRegistry::default();
+        assert!(load_plugins(temp_dir.path(), &mut registry)?.is_empty());
+
+        let bogus = temp_dir.path().join(format!("bogus.{}", std::env::consts::DLL_EXTENSION));
+        fs::write(&bogus, "not a library")?;
+        assert!(matches!(
+            load_plugins(temp_dir.path(), &mut registry),
+            Err(RustyDiaryError::Plugin(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,80 @@
+//! File names that work on every platform. Diary folders often travel
+//! between machines through cloud sync, so names are checked against the
+//! strictest rules, Windows', whatever platform the check runs on.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Device names Windows reserves, with or without an extension
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9", "LPT1",
+    "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Why `name` cannot be used as a file or folder name on some platform
+pub fn file_name_problem(name: &str) -> Option<&'static str> {
+    if name.trim().is_empty() {
+        Some("is empty")
+    } else if name == "." || name == ".." {
+        Some("is not a file name")
+    } else if name.chars().any(|c| matches!(c, '/' | '\\')) {
+        Some("contains a path separator")
+    } else if name.chars().any(|c| matches!(c, '<' | '>' | ':' | '"' | '|' | '?' | '*')) {
+        Some("contains a character Windows does not allow in file names")
+    } else if name.chars().any(char::is_control) {
+        Some("contains control characters")
+    } else if name.ends_with(['.', ' ']) {
+        Some("ends with a dot or space, which Windows drops")
+    } else if is_reserved(name) {
+        Some("is a device name Windows reserves")
+    } else {
+        None
+    }
+}
+
+/// Whether Windows reserves the name, e.g. `con`, `NUL.md` or `com1.txt`
+pub fn is_reserved(name: &str) -> bool {
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    RESERVED_NAMES.iter().any(|reserved| reserved.eq_ignore_ascii_case(stem))
+}
+
+/// A path with `/` between its components, the same on every platform
+pub fn slash_path(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|c| match c {
+            // Joining puts the separator after it
+            Component::RootDir => "".into(),
+            c => c.as_os_str().to_string_lossy(),
+        })
+        .collect();
+    components.join("/")
+}
+
+/// A `/`-separated relative path with the platform's own separators
+pub fn native_path(slashed: &str) -> PathBuf {
+    slashed.split('/').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_name_problem() {
+        for name in ["rusty-diary-log", "2024-03-05.md", "notes.v2", "nullable", "console.md"] {
+            assert_eq!(file_name_problem(name), None, "{}", name);
+        }
+        for name in ["", " ", "..", "a/b", "a\\b", "12:30", "what?", "tab\there", "trailing.", "CON", "nul.md", "Com3.txt"] {
+            assert!(file_name_problem(name).is_some(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn test_slash_and_native_paths() {
+        let native = native_path("2024/03/05.md");
+        assert_eq!(native, Path::new("2024").join("03").join("05.md"));
+        assert_eq!(slash_path(&native), "2024/03/05.md");
+        assert_eq!(slash_path(Path::new("journal.md")), "journal.md");
+        assert_eq!(slash_path(&Path::new("/").join("notes").join("a.png")), "/notes/a.png");
+    }
+}
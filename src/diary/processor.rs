@@ -1,12 +1,218 @@
-use chrono::NaiveDate;
+use chrono::{NaiveDate, NaiveDateTime};
 use regex::Regex;
+use serde::Serialize;
+use std::borrow::Cow;
 use std::path::Path;
+use std::sync::Arc;
+use unicode_normalization::UnicodeNormalization;
 
+use crate::clock::{Clock, SystemClock};
 use crate::error::{Result, RustyDiaryError};
+use crate::pipeline::DiaryFiles;
 use crate::storage::models::DiaryEntry;
 
+use super::dayone::DayOneImporter;
+use super::export::{EntryStream, ExportSink};
+use super::file::decode_text;
+#[cfg(feature = "sqlite")]
+use super::flat::FlatSqliteExporter;
+use super::html::HtmlExporter;
+use super::import::{Imported, MarkdownImporter, SourceItem};
+use super::quotes::QuotesExporter;
+use super::timeline::TimelineExporter;
+
+/// Turns a non-markdown diary file into markdown entry content.
+/// The entry date still comes from the filename.
+pub trait FileParser: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// File extensions (without the dot) this parser handles
+    fn extensions(&self) -> Vec<String>;
+
+    fn parse(&self, path: &Path, raw: &str) -> Result<String>;
+}
+
+/// Writes entries to an external format or destination. The diary picks
+/// the entries, reports progress and handles cancellation; an exporter only
+/// turns the stream into its format, writing through the sink.
+pub trait Exporter: Send + Sync {
+    fn name(&self) -> &str;
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()>;
+}
+
+/// Reads the entries of another app's export, such as a Day One JSON
+/// file or a folder of dated notes, for `import`
+pub trait Importer: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Whether the file or folder is in this importer's format, so
+    /// `import auto` can pick it
+    fn detect(&self, source: &Path) -> bool;
+
+    /// The items of the source, each becoming one entry
+    fn enumerate(&self, source: &Path) -> Result<Vec<SourceItem>>;
+
+    /// One item as an entry created at `now` and the files it brings
+    /// along. The entry's `exec_version` is replaced by the run that stores
+    /// it.
+    fn convert(&self, item: &SourceItem, now: NaiveDateTime) -> Result<Imported>;
+}
+
+/// Adds metadata to entries as a sync stores them, such as the weather of
+/// the day from a local cache, calendar events or a count of git commits.
+/// The fields are stored as the day's custom metadata.
+pub trait Enricher: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Metadata fields (key, value) for a newly stored entry; keys follow
+    /// the rules of `StorageManager::set_meta`
+    fn enrich(&self, entry: &DiaryEntry) -> Result<Vec<(String, String)>>;
+}
+
+/// Built-in exporter writing entries as a pretty-printed JSON array
+pub struct JsonExporter;
+
+impl Exporter for JsonExporter {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        let entries = entries.collect::<Result<Vec<_>>>()?;
+        sink.write(serde_json::to_string_pretty(&entries)?)
+    }
+}
+
+/// Parsers, exporters and importers available to a diary, whether built
+/// in, registered by library users or loaded from plugins
+#[derive(Clone)]
+pub struct ProcessorRegistry {
+    parsers: Vec<Arc<dyn FileParser>>,
+    exporters: Vec<Arc<dyn Exporter>>,
+    importers: Vec<Arc<dyn Importer>>,
+}
+
+impl Default for ProcessorRegistry {
+    fn default() -> Self {
+        Self {
+            parsers: Vec::new(),
+            exporters: vec![
+                Arc::new(JsonExporter),
+                Arc::new(QuotesExporter),
+                Arc::new(TimelineExporter),
+                Arc::new(HtmlExporter::default()),
+                #[cfg(feature = "sqlite")]
+                Arc::new(FlatSqliteExporter),
+            ],
+            // Detection tries these in order, so the catch-all folder of notes comes last
+            importers: vec![Arc::new(DayOneImporter), Arc::new(MarkdownImporter)],
+        }
+    }
+}
+
+impl ProcessorRegistry {
+    pub fn register_parser(&mut self, parser: Arc<dyn FileParser>) {
+        self.parsers.push(parser);
+    }
+
+    /// Registers an exporter, replacing any earlier one with the same name
+    pub fn register_exporter(&mut self, exporter: Arc<dyn Exporter>) {
+        self.exporters.retain(|existing| existing.name() != exporter.name());
+        self.exporters.push(exporter);
+    }
+
+    /// The parser responsible for a file, based on its extension
+    pub fn parser_for(&self, path: &Path) -> Option<&Arc<dyn FileParser>> {
+        let extension = path.extension()?.to_str()?;
+        self.parsers
+            .iter()
+            .find(|parser| parser.extensions().iter().any(|ext| ext == extension))
+    }
+
+    pub fn exporter(&self, name: &str) -> Option<&Arc<dyn Exporter>> {
+        self.exporters.iter().find(|exporter| exporter.name() == name)
+    }
+
+    pub fn exporter_names(&self) -> Vec<&str> {
+        self.exporters.iter().map(|exporter| exporter.name()).collect()
+    }
+
+    /// Registers an importer, replacing any earlier one with the same
+    /// name. Detection tries it before the built-in ones.
+    pub fn register_importer(&mut self, importer: Arc<dyn Importer>) {
+        self.importers.retain(|existing| existing.name() != importer.name());
+        self.importers.insert(0, importer);
+    }
+
+    pub fn importer(&self, name: &str) -> Option<&Arc<dyn Importer>> {
+        self.importers.iter().find(|importer| importer.name() == name)
+    }
+
+    /// The first importer that recognizes the source's format
+    pub fn detect_importer(&self, source: &Path) -> Option<&Arc<dyn Importer>> {
+        self.importers.iter().find(|importer| importer.detect(source))
+    }
+
+    pub fn importer_names(&self) -> Vec<&str> {
+        self.importers.iter().map(|importer| importer.name()).collect()
+    }
+}
+
+/// How entry text is normalized before it is stored, so the same text
+/// typed on different systems produces the same bytes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    /// Strip byte order marks and turn CRLF and CR line endings into LF
+    #[default]
+    Lf,
+    /// Like `Lf`, then compose Unicode text into NFC
+    Nfc,
+}
+
+impl Normalization {
+    pub const NAMES: &'static [&'static str] = &["lf", "nfc"];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Normalization::Lf => "lf",
+            Normalization::Nfc => "nfc",
+        }
+    }
+
+    pub fn apply(self, content: &str) -> String {
+        // Notes joined from files saved on Windows can open with several marks
+        let content = content.trim_start_matches('\u{feff}');
+        let content = content.replace("\r\n", "\n").replace('\r', "\n");
+        match self {
+            Normalization::Lf => content,
+            Normalization::Nfc => content.nfc().collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for Normalization {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "lf" => Ok(Normalization::Lf),
+            "nfc" => Ok(Normalization::Nfc),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown normalization '{}' (expected lf or nfc)", name
+            ))),
+        }
+    }
+}
+
 pub struct MarkdownProcessor {
     date_pattern: Regex,
+    normalization: Normalization,
+    /// Matches headings made of a date alone, when files are split by them
+    date_heading: Option<Regex>,
+    /// Stamps the entries read
+    clock: Arc<dyn Clock>,
 }
 
 impl MarkdownProcessor {
@@ -14,16 +220,107 @@ impl MarkdownProcessor {
         Ok(Self {
             date_pattern: Regex::new(date_pattern)
                 .map_err(RustyDiaryError::InvalidPattern)?,
+            normalization: Normalization::default(),
+            date_heading: None,
+            clock: Arc::new(SystemClock),
         })
     }
 
-    pub fn process_file<P: AsRef<Path>>(&self, path: P, exec_version: i64) -> Result<DiaryEntry> {
-        let path = path.as_ref();
-        let content = std::fs::read_to_string(path)?;
+    /// Splits files into one entry per date heading, such as `## 2024-03-04`.
+    /// Headings with more than the date are left as they are.
+    pub fn with_date_heading_split(mut self, enabled: bool) -> Self {
+        self.date_heading = enabled.then(|| Regex::new(r"^#{1,6}[ \t]+(\d{4}-\d{2}-\d{2})[ \t]*$").unwrap());
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: Normalization) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    /// Stamps entries with the time of `clock` instead of this machine's
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Applies the configured normalization to file content
+    pub fn normalize(&self, content: &str) -> String {
+        self.normalization.apply(content)
+    }
+
+    /// The entries of the markdown file at `path`, read from `files`
+    pub fn process_file(&self, files: &dyn DiaryFiles, path: &Path, exec_version: i64) -> Result<Vec<DiaryEntry>> {
+        let date = self.extract_date(path).map_err(|e| e.in_file(path))?;
+        let (content, _) = decode_text(path, files.read(path)?, false)?;
+        self.entries(&content, date, exec_version, None).map_err(|e| e.in_file(path))
+    }
+
+    /// The dated sections of a file's content. Without date heading splitting
+    /// this is the whole content under `file_date`; with it, every date
+    /// heading starts a section, text before the first one stays under
+    /// `file_date`, and sections of the same date are joined.
+    pub fn sections(&self, content: &str, file_date: NaiveDate) -> Result<Vec<(NaiveDate, String)>> {
+        let Some(date_heading) = &self.date_heading else {
+            return Ok(vec![(file_date, content.to_string())]);
+        };
 
-        let date = self.extract_date(path)?;
+        let mut sections: Vec<(NaiveDate, String)> = vec![(file_date, String::new())];
+        let mut current = 0;
+        let mut in_fence = false;
+        for (number, line) in content.split_inclusive('\n').enumerate() {
+            if line.trim_start().starts_with("```") {
+                in_fence = !in_fence;
+            }
+            let heading = if in_fence { None } else { date_heading.captures(line.trim_end()) };
+            match heading {
+                Some(caps) => {
+                    let date = NaiveDate::parse_from_str(&caps[1], "%Y-%m-%d").map_err(|e| {
+                        let column = line[..caps.get(1).map_or(0, |m| m.start())].chars().count() + 1;
+                        RustyDiaryError::from(e).at(number + 1, column)
+                    })?;
+                    current = match sections.iter().position(|(d, _)| *d == date) {
+                        Some(i) => {
+                            // Keep sections of the same date apart by a blank line
+                            let text = &mut sections[i].1;
+                            if !text.trim().is_empty() && !text.ends_with("\n\n") {
+                                text.push_str(if text.ends_with('\n') { "\n" } else { "\n\n" });
+                            }
+                            i
+                        }
+                        None => {
+                            sections.push((date, String::new()));
+                            sections.len() - 1
+                        }
+                    };
+                }
+                None => sections[current].1.push_str(line),
+            }
+        }
 
-        Ok(DiaryEntry::new(exec_version, date, content))
+        Ok(sections
+            .into_iter()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(date, text)| (date, text.trim_matches('\n').to_string()))
+            .collect())
+    }
+
+    /// The `author:` of a `---` frontmatter block opening the content
+    pub fn frontmatter_author(content: &str) -> Option<String> {
+        let mut lines = content.lines();
+        if lines.next()?.trim_end() != "---" {
+            return None;
+        }
+
+        lines
+            .take_while(|line| line.trim_end() != "---")
+            .find_map(|line| line.strip_prefix("author:"))
+            .map(|value| value.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|author| !author.is_empty())
     }
 
     pub fn extract_date<P: AsRef<Path>>(&self, path: P) -> Result<NaiveDate> {
@@ -35,9 +332,12 @@ impl MarkdownProcessor {
                 "Invalid filename".to_string()
             ))?;
 
+        // Files handled by plugin parsers may only match without their extension
+        let stem = path.as_ref().file_stem().and_then(|stem| stem.to_str());
         let date_str = self
             .date_pattern
             .captures(filename)
+            .or_else(|| stem.and_then(|stem| self.date_pattern.captures(stem)))
             .and_then(|caps| caps.get(1))
             .map(|m| m.as_str())
             .ok_or_else(|| RustyDiaryError::ContentIntegrity(
@@ -48,6 +348,32 @@ impl MarkdownProcessor {
             .map_err(RustyDiaryError::from)
     }
 
+    /// The entries of a file's text: normalized, checked and split into
+    /// sections dated `date` unless a date heading says otherwise. Entries
+    /// are credited to the frontmatter author, or else to `author`.
+    pub fn entries(
+        &self,
+        content: &str,
+        date: NaiveDate,
+        exec_version: i64,
+        author: Option<String>,
+    ) -> Result<Vec<DiaryEntry>> {
+        let content = self.normalize(content);
+        self.validate_content(&content)?;
+
+        let author = Self::frontmatter_author(&content).or(author);
+        let now = self.clock.now();
+        let entries = self
+            .sections(&content, date)?
+            .into_iter()
+            .map(|(date, text)| DiaryEntry::new(exec_version, date, text, now).with_author(author.clone()))
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return Err(RustyDiaryError::ContentIntegrity("No text under any date heading".to_string()));
+        }
+        Ok(entries)
+    }
+
     pub fn validate_content(&self, content: &str) -> Result<()> {
         if content.trim().is_empty() {
             return Err(RustyDiaryError::ContentIntegrity(
@@ -58,4 +384,275 @@ impl MarkdownProcessor {
         // Add more content validation as needed
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+/// Text with GitHub emoji shortcodes such as `:sparkles:` replaced by the
+/// emoji, for rendering; stored entries keep the shortcodes. Unknown names
+/// and colons in times like `10:30:45` stay as written.
+pub fn resolve_shortcodes(text: &str) -> Cow<'_, str> {
+    if !text.contains(':') {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(':') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let name_len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '+' | '-')))
+            .unwrap_or(after.len());
+        let emoji = after[name_len..]
+            .starts_with(':')
+            .then(|| emojis::get_by_shortcode(&after[..name_len]))
+            .flatten();
+        match emoji {
+            Some(emoji) => {
+                out.push_str(emoji.as_str());
+                rest = &after[name_len + 1..];
+            }
+            None => {
+                out.push(':');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::pipeline::MemoryFiles;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+    use std::path::PathBuf;
+
+    struct UpperParser;
+
+    impl FileParser for UpperParser {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn extensions(&self) -> Vec<String> {
+            vec!["txt".to_string()]
+        }
+
+        fn parse(&self, _path: &Path, raw: &str) -> Result<String> {
+            Ok(raw.to_uppercase())
+        }
+    }
+
+    #[test]
+    fn test_registry_lookup() {
+        let mut registry = ProcessorRegistry::default();
+        registry.register_parser(Arc::new(UpperParser));
+
+        assert!(registry.parser_for(Path::new("2024-01-01.txt")).is_some());
+        assert!(registry.parser_for(Path::new("2024-01-01.md")).is_none());
+        assert_eq!(&registry.exporter_names()[..2], ["json", "quotes"]);
+    }
+
+    #[test]
+    fn test_normalization() {
+        let windows = "\u{feff}Caf\u{e9}\r\nline\rend\n";
+        let mac = "Cafe\u{301}\nline\nend\n";
+
+        assert_eq!(Normalization::Lf.apply(windows), "Caf\u{e9}\nline\nend\n");
+        assert_ne!(Normalization::Lf.apply(windows), Normalization::Lf.apply(mac));
+        assert_eq!(Normalization::Nfc.apply(windows), Normalization::Nfc.apply(mac));
+        assert_eq!("nfc".parse::<Normalization>().unwrap(), Normalization::Nfc);
+        assert!("nfd".parse::<Normalization>().is_err());
+    }
+
+    #[test]
+    fn test_sections_split_on_date_headings() -> Result<()> {
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let content = "# Week 10\n\n## 2024-03-04\nMonday\n## 2024-03-05 \nTuesday\n```\n## 2024-03-06\n```\n## 2024-03-04\nMore Monday\n## 2024-03-07 Thursday\n";
+
+        let processor = MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?;
+        assert_eq!(processor.sections(content, ymd(4))?, vec![(ymd(4), content.to_string())]);
+
+        let processor = processor.with_date_heading_split(true);
+        assert_eq!(
+            processor.sections(content, ymd(4))?,
+            vec![
+                (ymd(4), "# Week 10\n\nMonday\n\nMore Monday\n## 2024-03-07 Thursday".to_string()),
+                (ymd(5), "Tuesday\n```\n## 2024-03-06\n```".to_string()),
+            ]
+        );
+        let invalid = processor.sections("Intro\n## 2024-02-30\nNo such day\n", ymd(4)).unwrap_err();
+        assert_eq!(invalid.location().map(|at| (at.line, at.column)), Some((Some(2), Some(4))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_frontmatter_author() {
+        assert_eq!(
+            MarkdownProcessor::frontmatter_author("---\ntags: [work]\nauthor: \"Ana\"\n---\nText"),
+            Some("Ana".to_string())
+        );
+        assert_eq!(MarkdownProcessor::frontmatter_author("---\ntags: [work]\n---\nauthor: Ana"), None);
+        assert_eq!(MarkdownProcessor::frontmatter_author("author: Ana\n"), None);
+        assert_eq!(MarkdownProcessor::frontmatter_author("---\nauthor:\n---\n"), None);
+    }
+
+    #[test]
+    fn test_resolve_shortcodes() {
+        assert_eq!(resolve_shortcodes("Shipped :sparkles: at 10:30:45 :+1:"), "Shipped ✨ at 10:30:45 👍");
+        assert_eq!(resolve_shortcodes(":nope: a::b :tada"), ":nope: a::b :tada");
+        assert!(matches!(resolve_shortcodes("no colons"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_extract_date_falls_back_to_stem() -> Result<()> {
+        let processor = MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?;
+
+        assert_eq!(
+            processor.extract_date("notes/2024-03-05.txt")?,
+            NaiveDate::from_ymd_opt(2024, 3, 5).unwrap()
+        );
+        assert!(processor.extract_date("notes/journal.txt").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_file_with_fixed_clock() -> Result<()> {
+        let now = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap().and_hms_opt(7, 30, 0).unwrap();
+        let processor = MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?.with_clock(Arc::new(FixedClock(now)));
+        let files = MemoryFiles::new().with_file("2024-03-05.md", "\u{feff}Tuesday\r\n").with_file("2024-03-07.md", "  ");
+
+        let entries = processor.process_file(&files, Path::new("2024-03-05.md"), 3)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].content.as_str(), entries[0].created_at, entries[0].updated_at), ("Tuesday", now, Some(now)));
+        let empty = processor.process_file(&files, Path::new("2024-03-07.md"), 3).unwrap_err();
+        assert_eq!(empty.location().and_then(|at| at.path.clone()), Some("2024-03-07.md".into()));
+        assert!(processor.process_file(&files, Path::new("2024-03-08.md"), 3).is_err());
+        Ok(())
+    }
+
+    /// Pieces of the names real notes get: other scripts, emoji, spaces,
+    /// digits of other numeral systems and stray dots and dashes
+    const NAME_PIECES: &[&str] = &[
+        "", " ", "-", ".", "..", "_", "Lisbon", "日記", "🙂", "Café", "e\u{301}", "(1)", "٢٠", "２０",
+        "0", "12", ".md", ".txt", "~",
+    ];
+
+    /// Text with the line endings, marks and blanks notes pick up when
+    /// moved between systems and editors
+    const TEXT_PIECES: &[&str] = &[
+        "words", "Café", "Cafe\u{301}", "日記", "🙂", "\r\n", "\r", "\n", "\n\n", "\r\r\n", "\t", " ",
+        "\u{feff}", "\u{200b}", "#tag", "---",
+    ];
+
+    fn any_date() -> impl Strategy<Value = NaiveDate> {
+        (1..=9999i32, 0..365u64)
+            .prop_map(|(year, day)| NaiveDate::from_ymd_opt(year, 1, 1).unwrap() + chrono::Days::new(day))
+    }
+
+    fn name() -> impl Strategy<Value = String> {
+        prop::collection::vec(select(NAME_PIECES), 0..6).prop_map(|pieces| pieces.concat())
+    }
+
+    fn folder() -> impl Strategy<Value = PathBuf> {
+        let folders = prop::collection::vec(name(), 0..3);
+        folders.prop_map(|names| names.iter().map(|name| format!("{}x", name)).collect())
+    }
+
+    /// Lines of a file holding several days: date headings at any level,
+    /// headings with more than the date, fences, blanks and text. Every
+    /// line of text is marked `[tN]` so it can be followed into a section.
+    fn section_lines() -> impl Strategy<Value = Vec<String>> {
+        let line = prop_oneof![
+            (select(&["#", "##", "######"][..]), 1..=9u32, select(&["", " ", "\t"][..]))
+                .prop_map(|(level, day, blank)| format!("{} 2024-03-0{}{}", level, day, blank)),
+            select(&["```", "", " ", "## 2024-03-0"][..]).prop_map(String::from),
+            // Headings with more than the date are text
+            (1..=9u32).prop_map(|day| format!("## 2024-03-0{} [t]", day)),
+            (name(), name()).prop_map(|(before, after)| format!("{}[t]{}", before, after)),
+            (name(), name()).prop_map(|(before, after)| format!("{}[t]{}", before, after)),
+            (name(), name()).prop_map(|(before, after)| format!("{}[t]{}", before, after)),
+        ];
+        prop::collection::vec(line, 0..24).prop_map(|lines| {
+            let mut tokens = 0;
+            lines
+                .into_iter()
+                .map(|line| {
+                    if !line.contains("[t]") {
+                        return line;
+                    }
+                    tokens += 1;
+                    line.replace("[t]", &format!("[t{}]", tokens - 1))
+                })
+                .collect()
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_extract_date_property(
+            date in any_date(),
+            folder in folder(),
+            suffix in name(),
+            (before, dated, after) in (name(), any::<bool>(), name()),
+        ) {
+            let strict = MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?;
+            let prefix = MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})")?;
+
+            // A date-named note is found under any folder, and with any
+            // suffix when the pattern only anchors the date
+            let note = folder.join(format!("{}{}.md", date.format("%Y-%m-%d"), suffix));
+            prop_assert_eq!(prefix.extract_date(&note)?, date);
+            prop_assert_eq!(strict.extract_date(folder.join(format!("{}.md", date)))?, date);
+
+            // Anything else is a date spelled out in the name, or an error
+            let spelled = if dated { date.to_string() } else { String::new() };
+            let name = format!("{}{}{}", before, spelled, after);
+            for processor in [&strict, &prefix] {
+                if let Ok(found) = processor.extract_date(folder.join(&name)) {
+                    let found_spelled = found.format("%Y-%m-%d").to_string();
+                    prop_assert!(name.starts_with(&found_spelled), "{:?} gave {}", name, found);
+                }
+            }
+        }
+
+        #[test]
+        fn test_normalization_property(pieces in prop::collection::vec(select(TEXT_PIECES), 0..16)) {
+            let text = pieces.concat();
+            for normalization in [Normalization::Lf, Normalization::Nfc] {
+                let once = normalization.apply(&text);
+                prop_assert_eq!(normalization.apply(&once), once.clone());
+                prop_assert!(!once.contains('\r') && !once.starts_with('\u{feff}'), "{:?}", once);
+            }
+
+            // The same note saved on another system reads the same
+            let nfc = Normalization::Nfc.apply(&text);
+            let elsewhere: String = format!("\u{feff}{}", nfc.replace('\n', "\r\n")).nfd().collect();
+            prop_assert_eq!(Normalization::Nfc.apply(&elsewhere), nfc);
+        }
+
+        #[test]
+        fn test_sections_property(lines in section_lines()) {
+            let processor =
+                MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?.with_date_heading_split(true);
+            let content: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+            let tokens = lines.iter().filter(|line| line.contains("[t")).count();
+
+            let sections = processor.sections(&content, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap())?;
+            let mut dates: Vec<NaiveDate> = sections.iter().map(|(date, _)| *date).collect();
+            dates.sort();
+            dates.dedup();
+            prop_assert_eq!(dates.len(), sections.len());
+            let text: String = sections.iter().map(|(_, text)| text.as_str()).collect();
+            for token in 0..tokens {
+                prop_assert_eq!(text.matches(&format!("[t{}]", token)).count(), 1);
+            }
+            for (_, text) in &sections {
+                let trimmed = !text.starts_with('\n') && !text.ends_with('\n');
+                prop_assert!(!text.trim().is_empty() && trimmed, "{:?}", text);
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! A commonplace book: the blockquotes and `==highlights==` of entries,
+//! gathered into one markdown file with the day each came from. Built in
+//! as the `quotes` export target.
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use regex::Regex;
+
+use crate::error::Result;
+use crate::storage::models::{DiaryEntry, HIGHLIGHT_PATTERN};
+
+use super::export::{EntryStream, ExportSink};
+use super::processor::Exporter;
+
+/// Blockquotes and highlights outside them, in order of appearance. A
+/// `> !review` marker is left out of its quote.
+pub fn quotes(content: &str) -> Vec<String> {
+    let highlight = Regex::new(HIGHLIGHT_PATTERN).unwrap();
+    let mut quotes: Vec<String> = Vec::new();
+    let mut push = |lines: &[&str]| {
+        let text = lines.join("\n").trim().to_string();
+        if !text.is_empty() && !quotes.contains(&text) {
+            quotes.push(text);
+        }
+    };
+
+    let mut quote: Vec<&str> = Vec::new();
+    for line in content.lines() {
+        if let Some(text) = line.trim_start().strip_prefix('>') {
+            let text = text.trim();
+            let marker = text.strip_prefix("!review").filter(|rest| rest.is_empty() || rest.starts_with(' '));
+            quote.push(match marker {
+                Some(rest) if quote.is_empty() => rest.trim(),
+                _ => text,
+            });
+            continue;
+        }
+        push(&quote);
+        quote.clear();
+        for capture in highlight.captures_iter(line) {
+            push(&[&capture[1]]);
+        }
+    }
+    push(&quote);
+    quotes
+}
+
+/// The quotes of the latest version of each day, oldest first, as markdown
+pub fn commonplace_book(entries: &[DiaryEntry]) -> String {
+    let mut latest: BTreeMap<NaiveDate, &DiaryEntry> = BTreeMap::new();
+    for entry in entries {
+        let current = latest.entry(entry.date).or_insert(entry);
+        if entry.exec_version > current.exec_version {
+            *current = entry;
+        }
+    }
+
+    let mut out = String::from("# Quotes\n");
+    for (date, entry) in latest {
+        for quote in quotes(&entry.content) {
+            out.push('\n');
+            for line in quote.lines() {
+                match line {
+                    "" => out.push_str(">\n"),
+                    line => out.push_str(&format!("> {}\n", line)),
+                }
+            }
+            out.push_str(&format!(">\n> — [[{}]]\n", date));
+        }
+    }
+    out
+}
+
+/// Built-in exporter writing a commonplace book of entries' quotes
+pub struct QuotesExporter;
+
+impl Exporter for QuotesExporter {
+    fn name(&self) -> &str {
+        "quotes"
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        sink.write(commonplace_book(&entries.collect::<Result<Vec<_>>>()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    #[test]
+    fn test_commonplace_book() {
+        let day = |d| NaiveDate::from_ymd_opt(2024, 5, d).unwrap();
+        let content = "Read ==less, but better== today.\n\n> !review\n> Slow is smooth,\n>\n> smooth is fast.\nMore\n> Short";
+        assert_eq!(quotes(content), vec!["less, but better", "Slow is smooth,\n\nsmooth is fast.", "Short"]);
+
+        let entries = vec![
+            DiaryEntry::new(2, day(2), "> Newer".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(2), "> Older".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(1), "No quotes".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(3), "==Seize the day==".to_string(), SystemClock.now()),
+        ];
+        assert_eq!(
+            commonplace_book(&entries),
+            "# Quotes\n\n> Newer\n>\n> — [[2024-05-02]]\n\n> Seize the day\n>\n> — [[2024-05-03]]\n"
+        );
+    }
+}
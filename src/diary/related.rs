@@ -0,0 +1,128 @@
+//! Related entries: other days sharing `#tags`, linked with `[[date]]` in
+//! either direction, or written in similar words, for rediscovering
+//! connected thoughts.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::storage::models::DiaryEntry;
+use super::tagging::TagSuggester;
+
+/// Score added when either entry links to the other
+const LINK_SCORE: f64 = 1.0;
+
+/// Another day and why it is related. `score` is the share of tags the two
+/// have in common (0 to 1), plus 1 when they link, plus their text
+/// similarity (0 to 1).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RelatedEntry {
+    pub date: NaiveDate,
+    pub score: f64,
+    pub shared_tags: Vec<String>,
+    pub linked: bool,
+    /// Cosine similarity of the two entries' TF-IDF word weights
+    pub similarity: f64,
+}
+
+/// What the finder keeps of each entry
+struct Indexed {
+    date: NaiveDate,
+    tags: Vec<String>,
+    links: Vec<NaiveDate>,
+    words: HashMap<String, f64>,
+}
+
+/// Ranks the entries of a corpus, normally the latest version of every
+/// day, by how related they are to a given entry
+pub struct RelatedFinder {
+    suggester: TagSuggester,
+    entries: Vec<Indexed>,
+}
+
+impl RelatedFinder {
+    pub fn new(corpus: &[DiaryEntry]) -> Self {
+        let suggester = TagSuggester::new(corpus);
+        let entries = corpus.iter().map(|entry| index(&suggester, entry)).collect();
+        Self { suggester, entries }
+    }
+
+    /// Up to `limit` other days related to the entry, most related first.
+    /// Days with nothing in common are left out.
+    pub fn find(&self, entry: &DiaryEntry, limit: usize) -> Vec<RelatedEntry> {
+        let this = index(&self.suggester, entry);
+        let mut related: Vec<RelatedEntry> = self
+            .entries
+            .iter()
+            .filter(|other| other.date != this.date)
+            .filter_map(|other| {
+                let shared_tags: Vec<String> =
+                    this.tags.iter().filter(|tag| other.tags.contains(tag)).cloned().collect();
+                let all_tags = this.tags.len() + other.tags.len() - shared_tags.len();
+                let tag_score = if all_tags == 0 { 0.0 } else { shared_tags.len() as f64 / all_tags as f64 };
+                let linked = this.links.contains(&other.date) || other.links.contains(&this.date);
+                let similarity = cosine(&this.words, &other.words);
+
+                let score = tag_score + if linked { LINK_SCORE } else { 0.0 } + similarity;
+                (score > 0.0).then_some(RelatedEntry { date: other.date, score, shared_tags, linked, similarity })
+            })
+            .collect();
+
+        related.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| b.date.cmp(&a.date)));
+        related.truncate(limit);
+        related
+    }
+}
+
+fn index(suggester: &TagSuggester, entry: &DiaryEntry) -> Indexed {
+    Indexed {
+        date: entry.date,
+        tags: entry.tags(),
+        links: entry.links().into_iter().filter_map(|link| link.target_date).collect(),
+        words: suggester.weights(&entry.content).into_iter().collect(),
+    }
+}
+
+fn cosine(a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+    let dot: f64 = a.iter().filter_map(|(word, x)| b.get(word).map(|y| x * y)).sum();
+    let norm = |v: &HashMap<String, f64>| v.values().map(|x| x * x).sum::<f64>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    fn entry(day: u32, content: &str) -> DiaryEntry {
+        DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 1, day).unwrap(), content.to_string(), SystemClock.now())
+    }
+
+    #[test]
+    fn test_tags_links_and_words_rank() {
+        let corpus = vec![
+            entry(1, "Bouldering at the gym, new project on the overhang #climbing"),
+            entry(2, "Groceries and laundry."),
+            entry(3, "Back on the overhang project, sent it! #climbing #fitness"),
+            entry(4, "Quiet evening, see [[2024-01-02]]"),
+            entry(5, "Long meeting about budgets."),
+        ];
+        let finder = RelatedFinder::new(&corpus);
+
+        let related = finder.find(&corpus[2], 5);
+        assert_eq!(related[0].date, corpus[0].date);
+        assert_eq!(related[0].shared_tags, vec!["climbing"]);
+        assert!(related[0].similarity > 0.0 && related[0].similarity <= 1.0);
+        assert!(related.iter().all(|r| r.date != corpus[2].date && r.score > 0.0));
+
+        let linked = finder.find(&corpus[1], 5);
+        assert_eq!(linked.len(), 1);
+        assert!(linked[0].linked);
+        assert_eq!(linked[0].date, corpus[3].date);
+
+        assert!(finder.find(&corpus[4], 5).is_empty());
+        assert_eq!(finder.find(&corpus[2], 1).len(), 1);
+    }
+}
@@ -0,0 +1,113 @@
+//! The weekly review: a week's entries, the tasks left open in them and a
+//! few numbers, followed by questions to answer, written as a new entry.
+
+use std::collections::BTreeMap;
+
+use chrono::Datelike;
+
+use crate::dates::DateRange;
+use crate::storage::models::DiaryEntry;
+
+use super::habits;
+
+/// Questions closing a review unless a template replaces them
+pub const DEFAULT_TEMPLATE: &str = "\
+## Review
+
+### What went well?
+
+### What didn't?
+
+### What matters most next week?
+";
+
+/// Tags listed in the review's numbers
+const TOP_TAGS: usize = 5;
+
+/// Unticked checklist items of `content`, such as `- [ ] call the bank`,
+/// except the tracked habits, which recur every day
+pub fn open_tasks(content: &str, habits: &[String]) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(habits::checklist_item)
+        .filter(|(done, task)| !done && !task.is_empty())
+        .filter(|(_, task)| !habits.iter().any(|habit| habits::names_habit(task, habit)))
+        .map(|(_, task)| task.to_string())
+        .collect()
+}
+
+/// The review of `week` as markdown, from the latest version of each of
+/// its days, oldest first
+pub fn weekly_review(week: DateRange, entries: &[DiaryEntry], habits: &[String], template: &str) -> String {
+    let iso = week.start.iso_week();
+    let mut out = format!("# Weekly review {}-W{:02} ({} to {})\n\n", iso.year(), iso.week(), week.start, week.end);
+
+    let words: usize = entries.iter().map(DiaryEntry::word_count).sum();
+    let mut tags: BTreeMap<String, usize> = BTreeMap::new();
+    for tag in entries.iter().flat_map(DiaryEntry::tags) {
+        *tags.entry(tag).or_default() += 1;
+    }
+    let mut tags: Vec<(String, usize)> = tags.into_iter().collect();
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    out.push_str("## Numbers\n\n");
+    out.push_str(&format!("- Days written: {} of {}\n", entries.len(), week.days()));
+    out.push_str(&format!("- Words: {}\n", words));
+    if !tags.is_empty() {
+        let top: Vec<String> = tags.iter().take(TOP_TAGS).map(|(tag, count)| format!("#{} ({})", tag, count)).collect();
+        out.push_str(&format!("- Tags: {}\n", top.join(", ")));
+    }
+
+    let tasks: Vec<(String, String)> = entries
+        .iter()
+        .flat_map(|entry| open_tasks(&entry.content, habits).into_iter().map(move |task| (task, entry.date.to_string())))
+        .collect();
+    if !tasks.is_empty() {
+        out.push_str("\n## Open tasks\n\n");
+        for (task, date) in tasks {
+            out.push_str(&format!("- [ ] {} ([[{}]])\n", task, date));
+        }
+    }
+
+    out.push_str("\n## Entries\n\n");
+    if entries.is_empty() {
+        out.push_str("Nothing written this week.\n");
+    }
+    for entry in entries {
+        let first_line = entry
+            .content
+            .lines()
+            .map(|line| line.trim().trim_start_matches('#').trim())
+            .find(|line| !line.is_empty())
+            .unwrap_or_default();
+        out.push_str(&format!("- [[{}]] {}\n", entry.date, first_line));
+    }
+
+    out.push('\n');
+    out.push_str(template);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_weekly_review() {
+        let now = SystemClock.now();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let entries = vec![
+            DiaryEntry::new(1, day(11), "# Monday\n- [ ] call the bank\n- [x] pay rent\n- [ ] meditate #home".to_string(), now),
+            DiaryEntry::new(2, day(13), "Busy #work day\n- [ ] book flights".to_string(), now),
+        ];
+
+        let review = weekly_review(DateRange::week_of(day(11)), &entries, &["meditate".to_string()], DEFAULT_TEMPLATE);
+        assert!(review.starts_with("# Weekly review 2024-W11 (2024-03-11 to 2024-03-17)\n"));
+        assert!(review.contains("- Days written: 2 of 7\n- Words: 25\n- Tags: #home (1), #work (1)\n"), "{}", review);
+        assert!(review.contains("## Open tasks\n\n- [ ] call the bank ([[2024-03-11]])\n- [ ] book flights ([[2024-03-13]])\n"));
+        assert!(review.contains("- [[2024-03-11]] Monday\n- [[2024-03-13]] Busy #work day\n"));
+        assert!(review.ends_with(DEFAULT_TEMPLATE));
+    }
+}
@@ -0,0 +1,298 @@
+//! The query language of `--query` and of named queries ("saved searches")
+//! such as
+//!
+//! ```text
+//! work-frustrations = tag:work AND text:"frustrated"
+//! ```
+//!
+//! read from `.diarysearches` in the diary directory, one per line, and from
+//! the configuration. Terms are joined with `AND` (or just spaces):
+//!
+//! - `tag:work` or `#work`: the entry has the hashtag
+//! - `text:"some phrase"`, `"some phrase"` or a bare word: the entry contains it
+//! - `from:2024-01-01`, `to:"last month"`: date bounds, both included, taking
+//!   any date expression `crate::dates` understands
+//! - `after:2024-01-01`, `before:2024-03`: date bounds leaving the period
+//!   itself out
+//! - `min-words:300`, `max-words:1000`: at least or at most that many words
+//! - `author:ana`: written by that author, case-insensitively
+//! - `is:starred`: the day is starred
+//! - `meta:mood=happy`: the day's custom metadata field has that value
+//!
+//! A tag or text term prefixed with `-` or `NOT`, as in `-tag:meeting` or
+//! `NOT "performance review"`, leaves out the entries it would match.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDate;
+
+use crate::dates;
+use crate::error::{Result, RustyDiaryError};
+use crate::storage::{self, EntryQuery};
+
+/// File in the diary directory holding saved searches
+pub const SEARCHES_FILE: &str = ".diarysearches";
+
+/// Saved searches by name
+#[derive(Debug, Clone, Default)]
+pub struct SavedSearches {
+    searches: BTreeMap<String, EntryQuery>,
+    /// Day relative date bounds such as `from:yesterday` are resolved against
+    today: NaiveDate,
+}
+
+impl SavedSearches {
+    /// Reads the directory's `.diarysearches`, then adds `definitions` as
+    /// (name, query) pairs, which replace file entries of the same name.
+    /// Relative dates are resolved against `today`.
+    pub fn load(directory: &Path, definitions: &[(String, String)], today: NaiveDate) -> Result<Self> {
+        let mut saved = Self { today, ..Self::default() };
+
+        let path = directory.join(SEARCHES_FILE);
+        if path.is_file() {
+            for (number, line) in fs::read_to_string(&path)?.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (name, query) = parse_definition(line).map_err(|e| {
+                    RustyDiaryError::InvalidConfig(format!("{}:{}: {}", SEARCHES_FILE, number + 1, e))
+                })?;
+                saved.insert(&name, &query)?;
+            }
+        }
+        for (name, query) in definitions {
+            saved.insert(name, query)?;
+        }
+        Ok(saved)
+    }
+
+    pub fn insert(&mut self, name: &str, query: &str) -> Result<()> {
+        let name = name.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(RustyDiaryError::InvalidConfig(format!("Invalid saved search name '{}'", name)));
+        }
+        self.searches.insert(name.to_string(), parse_query(query, self.today)?);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Result<&EntryQuery> {
+        self.searches.get(name).ok_or_else(|| {
+            RustyDiaryError::InvalidConfig(format!(
+                "Unknown saved search '{}' (available: {})",
+                name,
+                self.names().join(", ")
+            ))
+        })
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.searches.keys().map(String::as_str).collect()
+    }
+}
+
+/// Splits `name = query`
+pub fn parse_definition(definition: &str) -> Result<(String, String)> {
+    let (name, query) = definition.split_once('=').ok_or_else(|| {
+        RustyDiaryError::InvalidConfig(format!("Expected 'name = query', got '{}'", definition))
+    })?;
+    Ok((name.trim().to_string(), query.trim().to_string()))
+}
+
+/// Parses the query language described in the module documentation
+pub fn parse_query(query: &str, today: NaiveDate) -> Result<EntryQuery> {
+    let invalid = |reason: String| RustyDiaryError::InvalidConfig(format!("Invalid query '{}': {}", query, reason));
+    let period = |value: &str| {
+        dates::resolve(value, today).map_err(|_| invalid(format!("'{}' is not a date", value)))
+    };
+
+    let mut parsed = EntryQuery::new();
+    let mut terms = 0;
+    let mut negate_next = false;
+    for (token, quoted, negated) in tokenize(query).map_err(invalid)? {
+        if !quoted && !negated && token == "AND" {
+            continue;
+        }
+        if !quoted && !negated && token == "NOT" {
+            negate_next = true;
+            continue;
+        }
+        if !quoted && token == "OR" {
+            return Err(invalid("OR is not supported; terms are always combined with AND".to_string()));
+        }
+        let negated = negated || std::mem::take(&mut negate_next);
+
+        let (key, value) = match token.split_once(':') {
+            Some((key, value)) if !quoted => (key, value),
+            _ => ("text", token.as_str()),
+        };
+        if value.is_empty() {
+            return Err(invalid(format!("'{}:' needs a value", key)));
+        }
+        if negated {
+            parsed = match key {
+                "tag" => parsed.without_tag(value),
+                "text" if !quoted && value.starts_with('#') => parsed.without_tag(value),
+                "text" => parsed.without_text(value),
+                _ => return Err(invalid(format!("'{}:' cannot be negated; only tags and text can", key))),
+            };
+            terms += 1;
+            continue;
+        }
+        parsed = match key {
+            "tag" => parsed.with_tag(value),
+            "text" if !quoted && value.starts_with('#') => parsed.with_tag(value),
+            "text" => parsed.with_text(value),
+            "author" => parsed.with_author(value),
+            "is" if value == "starred" => parsed.with_starred(),
+            "is" => return Err(invalid(format!("'is:{}' is unknown; only 'is:starred' is", value))),
+            "meta" => {
+                let (field, field_value) = value
+                    .split_once('=')
+                    .ok_or_else(|| invalid(format!("'meta:{}' needs a key=value", value)))?;
+                storage::validate_meta_key(field).map_err(|e| invalid(e.to_string()))?;
+                parsed.with_meta(field, field_value)
+            }
+            "from" => EntryQuery { start_date: Some(period(value)?.start), ..parsed },
+            "to" => EntryQuery { end_date: Some(period(value)?.end), ..parsed },
+            "after" => {
+                let after = period(value)?.end.succ_opt();
+                let after = after.ok_or_else(|| invalid(format!("nothing is after {}", value)))?;
+                EntryQuery { start_date: Some(after), ..parsed }
+            }
+            "before" => {
+                let before = period(value)?.start.pred_opt();
+                let before = before.ok_or_else(|| invalid(format!("nothing is before {}", value)))?;
+                EntryQuery { end_date: Some(before), ..parsed }
+            }
+            "min-words" => parsed.with_min_words(
+                value.parse().map_err(|_| invalid(format!("'{}' is not a number", value)))?,
+            ),
+            "max-words" => parsed.with_max_words(
+                value.parse().map_err(|_| invalid(format!("'{}' is not a number", value)))?,
+            ),
+            _ => return Err(invalid(format!("unknown term '{}:'", key))),
+        };
+        terms += 1;
+    }
+
+    if negate_next {
+        return Err(invalid("NOT needs a term after it".to_string()));
+    }
+    if terms == 0 {
+        return Err(invalid("no terms".to_string()));
+    }
+    Ok(parsed)
+}
+
+/// Splits on whitespace outside double quotes, dropping the quotes. Each
+/// token says whether it was entirely quoted, and whether it was negated
+/// with a leading `-`, which is dropped too.
+fn tokenize(query: &str) -> std::result::Result<Vec<(String, bool, bool)>, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut quoted = false;
+    let mut negated = false;
+    let mut started = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                quoted = quoted || !started;
+                started = true;
+            }
+            '-' if !started && !negated => negated = true,
+            c if c.is_whitespace() && !in_quotes => {
+                if started {
+                    tokens.push((std::mem::take(&mut current), quoted, negated));
+                } else if negated {
+                    return Err("'-' needs a term after it".to_string());
+                }
+                quoted = false;
+                negated = false;
+                started = false;
+            }
+            c => {
+                current.push(c);
+                started = true;
+            }
+        }
+    }
+    if in_quotes {
+        return Err("unclosed '\"'".to_string());
+    }
+    if started {
+        tokens.push((current, quoted, negated));
+    } else if negated {
+        return Err("'-' needs a term after it".to_string());
+    }
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn today() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 6, 12).unwrap()
+    }
+
+    #[test]
+    fn test_parse_query() -> Result<()> {
+        let query = parse_query(r#"tag:work AND text:"frustrated at" "the boss" #late min-words:10 from:2024-01-01"#, today())?;
+        assert_eq!(query.tags, vec!["work", "late"]);
+        assert_eq!(query.text, vec!["frustrated at", "the boss"]);
+        assert_eq!(query.min_words, Some(10));
+        assert_eq!(parse_query("max-words:800 #travel", today())?.max_words, Some(800));
+        assert_eq!(query.start_date, NaiveDate::from_ymd_opt(2024, 1, 1));
+        assert_eq!(parse_query("to:2023-Q1", today())?.end_date, NaiveDate::from_ymd_opt(2023, 3, 31));
+        assert_eq!(parse_query(r#"author:"Ana Maria""#, today())?.author.as_deref(), Some("ana maria"));
+        assert!(parse_query("is:starred #travel", today())?.starred);
+        let meta = parse_query(r#"meta:mood=happy meta:place="Lisbon, Portugal""#, today())?;
+        assert_eq!(meta.meta[0], ("mood".to_string(), "happy".to_string()));
+        assert_eq!(meta.meta[1], ("place".to_string(), "Lisbon, Portugal".to_string()));
+
+        // A quoted term is text even when it looks like a key
+        assert_eq!(parse_query(r#""note: AND""#, today())?.text, vec!["note: and"]);
+
+        let compound = parse_query(r#"tag:work after:2024-01-01 before:2024-03 "performance review" -tag:meeting NOT "1:1" -#ooo"#, today())?;
+        assert_eq!((compound.tags, compound.text), (vec!["work".to_string()], vec!["performance review".to_string()]));
+        assert_eq!(compound.start_date, NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!(compound.end_date, NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(compound.exclude_tags, vec!["meeting", "ooo"]);
+        assert_eq!(compound.exclude_text, vec!["1:1"]);
+        assert_eq!(parse_query("-walk well-being", today())?.text, vec!["well-being"]);
+        let recent = parse_query("from:yesterday before:\"this month\"", today())?;
+        assert_eq!((recent.start_date, recent.end_date), (NaiveDate::from_ymd_opt(2024, 6, 11), NaiveDate::from_ymd_opt(2024, 5, 31)));
+
+        for invalid in ["", "AND", "tag:", "mood:happy", "a OR b", "from:someday", "is:pinned", "meta:mood", "meta:a b=c", "\"open",
+            "-", "a -", "rain NOT", "-is:starred", "NOT from:2024-01-01"] {
+            assert!(parse_query(invalid, today()).is_err(), "{} should be rejected", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_file_and_overrides() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(
+            temp_dir.path().join(SEARCHES_FILE),
+            "# Saved searches\nwork-frustrations = tag:work AND text:\"frustrated\"\nrunning = #running\n",
+        )?;
+
+        let saved = SavedSearches::load(temp_dir.path(), &[("running".to_string(), "tag:run".to_string())], today())?;
+        assert_eq!(saved.names(), vec!["running", "work-frustrations"]);
+        assert_eq!(saved.get("running")?.tags, vec!["run"]);
+        assert_eq!(saved.get("work-frustrations")?.text, vec!["frustrated"]);
+        assert!(matches!(saved.get("gym"), Err(RustyDiaryError::InvalidConfig(_))));
+
+        fs::write(temp_dir.path().join(SEARCHES_FILE), "broken\n")?;
+        assert!(SavedSearches::load(temp_dir.path(), &[], today()).is_err());
+        Ok(())
+    }
+}
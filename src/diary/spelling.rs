@@ -0,0 +1,538 @@
+//! Spell checking of stored entries. Built with the `spellcheck` feature.
+//!
+//! Words are checked against a hunspell dictionary, as most systems install
+//! them (`/usr/share/hunspell/en_US.dic` with its `.aff`), or a plain word
+//! list such as `/usr/share/dict/words`. Affix rules are expanded when the
+//! dictionary is loaded, so `walk/DGS` also accepts `walked` and `walks`.
+//! Suggestions are the known words one or two edits away.
+//!
+//! Code, links, tags, shortcodes and HTML are skipped, as are words with
+//! digits and all-caps acronyms. Words a diary uses on purpose, such as
+//! names, go into its own dictionary, stored in the database per language.
+
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use encoding_rs::Encoding;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+
+/// Language checked unless another is asked for
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// Folders searched for `<lang>.dic`, after those in `DICPATH`
+const DICTIONARY_DIRS: [&str; 4] = ["/usr/share/hunspell", "/usr/share/myspell", "/usr/share/myspell/dicts", "/Library/Spelling"];
+
+/// Word list used for English when no hunspell dictionary is installed
+const WORD_LIST: &str = "/usr/share/dict/words";
+
+/// Words of context kept on each side of a misspelling
+const CONTEXT_WORDS: usize = 5;
+
+/// A word the dictionary does not know, where it was found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Misspelling {
+    pub date: NaiveDate,
+    pub exec_version: i64,
+    /// Line of the entry, from 1
+    pub line: usize,
+    pub word: String,
+    /// The words around it on its line
+    pub context: String,
+    /// Known words close to it, closest first
+    pub suggestions: Vec<String>,
+}
+
+/// Words of one language
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// The installed dictionary for `lang`, such as `en` or `pt_BR`
+    pub fn find(lang: &str) -> Result<Self> {
+        let mut dirs: Vec<PathBuf> = std::env::var_os("DICPATH")
+            .map(|paths| std::env::split_paths(&paths).collect())
+            .unwrap_or_default();
+        dirs.extend(DICTIONARY_DIRS.iter().map(PathBuf::from));
+        if let Some(home) = std::env::var_os("HOME") {
+            dirs.push(Path::new(&home).join("Library/Spelling"));
+        }
+
+        for dir in &dirs {
+            if let Some(path) = dictionary_in(dir, lang) {
+                tracing::debug!("Spell checking with {}", path.display());
+                return Self::open(&path);
+            }
+        }
+        if lang.split(['_', '-']).next() == Some("en") && Path::new(WORD_LIST).is_file() {
+            return Self::open(Path::new(WORD_LIST));
+        }
+        Err(RustyDiaryError::InvalidConfig(format!(
+            "No dictionary for '{}' in {}; install a hunspell dictionary or pass one with --dictionary",
+            lang,
+            dirs.iter().map(|dir| dir.display().to_string()).collect::<Vec<_>>().join(", ")
+        )))
+    }
+
+    /// A hunspell `.dic`, read with the `.aff` next to it, or a word list
+    /// with one word per line
+    pub fn open(path: &Path) -> Result<Self> {
+        let bytes = fs::read(path).at_path(path)?;
+        let aff_path = path.with_extension("aff");
+        if path.extension().is_some_and(|ext| ext == "dic") && aff_path.is_file() {
+            let aff = fs::read(&aff_path).at_path(&aff_path)?;
+            return Self::from_hunspell(&bytes, &aff).map_err(|e| e.in_file(&aff_path));
+        }
+        Ok(Self::from_words(&String::from_utf8_lossy(&bytes)))
+    }
+
+    pub fn from_words(list: &str) -> Self {
+        Self::default().with_words(list.lines().map(str::trim).filter(|word| !word.is_empty()).map(String::from))
+    }
+
+    /// A hunspell dictionary, with its affix rules applied to every word.
+    /// Both files are read in the encoding the `SET` line of the `.aff`
+    /// names; encodings other than Unicode and the 8-bit ones are refused.
+    pub fn from_hunspell(dic: &[u8], aff: &[u8]) -> Result<Self> {
+        let encoding = aff_encoding(aff)?;
+        let decode = |bytes: &[u8]| encoding.decode_without_bom_handling(bytes).0.into_owned();
+        let affixes = Affixes::parse(&decode(aff));
+
+        let mut words = HashSet::new();
+        for line in decode(dic).lines().skip(1) {
+            let entry = line.split(['\t', ' ']).next().unwrap_or_default();
+            let (word, flags) = match entry.split_once('/') {
+                Some((word, flags)) => (word, affixes.flags(flags)),
+                None => (entry, Vec::new()),
+            };
+            if word.is_empty() || flags.iter().any(|flag| affixes.skipped.contains(flag)) {
+                continue;
+            }
+            affixes.expand(word, &flags, &mut words);
+        }
+        Ok(Self { words })
+    }
+
+    /// Also accepts `words`, e.g. those of the diary's own dictionary
+    pub fn with_words(mut self, words: impl IntoIterator<Item = String>) -> Self {
+        self.words.extend(words.into_iter().map(|word| word.replace('’', "'")));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Whether the word is spelled right. Capitalized words, such as those
+    /// starting a sentence, may be known in lowercase.
+    pub fn check(&self, word: &str) -> bool {
+        let word = word.replace('’', "'");
+        if self.words.contains(&word) {
+            return true;
+        }
+        let mut chars = word.chars();
+        let capitalized = chars.next().is_some_and(char::is_uppercase) && chars.all(|c| !c.is_uppercase());
+        capitalized && self.words.contains(&word.to_lowercase())
+    }
+
+    /// Known words one edit from `word`, or else two, closest first; at
+    /// most `limit` of them
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        let lower = word.to_lowercase();
+        let alphabet: BTreeSet<char> = lower.chars().chain('a'..='z').collect();
+        let first = edits(&lower, &alphabet);
+        let mut found: Vec<String> = first.iter().filter(|candidate| self.check(candidate)).cloned().collect();
+        if found.is_empty() {
+            found = first
+                .iter()
+                .flat_map(|candidate| edits(candidate, &alphabet))
+                .filter(|candidate| self.check(candidate))
+                .collect();
+        }
+
+        let mut seen = HashSet::new();
+        found.retain(|candidate| candidate != &lower && seen.insert(candidate.clone()));
+        // Closest first, and of those the ones with the same letters, such
+        // as `the` for `teh`
+        let letters = |word: &str| {
+            let mut letters: Vec<char> = word.chars().collect();
+            letters.sort_unstable();
+            letters
+        };
+        let own = letters(&lower);
+        found.sort_by_key(|candidate| {
+            (strsim::damerau_levenshtein(&lower, candidate), letters(candidate) != own, candidate.clone())
+        });
+        found.truncate(limit);
+        if word.chars().next().is_some_and(char::is_uppercase) {
+            for candidate in &mut found {
+                let mut chars = candidate.chars();
+                *candidate = chars.next().map(|first| first.to_uppercase().chain(chars).collect()).unwrap_or_default();
+            }
+        }
+        found
+    }
+}
+
+/// The dictionary named after `lang` in `dir`, such as `en_US.dic` for `en`
+fn dictionary_in(dir: &Path, lang: &str) -> Option<PathBuf> {
+    let exact = dir.join(format!("{}.dic", lang));
+    if exact.is_file() {
+        return Some(exact);
+    }
+    let mut regional: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == "dic")
+                && path.file_stem().and_then(|stem| stem.to_str()).is_some_and(|stem| {
+                    stem.strip_prefix(lang).is_some_and(|rest| rest.starts_with(['_', '-']))
+                })
+        })
+        .collect();
+    regional.sort();
+    regional.into_iter().next()
+}
+
+/// Every string one deletion, transposition, replacement or insertion away
+fn edits(word: &str, alphabet: &BTreeSet<char>) -> Vec<String> {
+    let chars: Vec<char> = word.chars().collect();
+    let mut out = Vec::new();
+    for i in 0..=chars.len() {
+        let (left, right) = chars.split_at(i);
+        let left: String = left.iter().collect();
+        if let Some((first, rest)) = right.split_first() {
+            let rest: String = rest.iter().collect();
+            out.push(format!("{}{}", left, rest));
+            if let Some((second, rest)) = right[1..].split_first() {
+                out.push(format!("{}{}{}{}", left, second, first, rest.iter().collect::<String>()));
+            }
+            for c in alphabet {
+                if c != first {
+                    out.push(format!("{}{}{}", left, c, rest));
+                }
+            }
+        }
+        let right: String = right.iter().collect();
+        for c in alphabet {
+            out.push(format!("{}{}{}", left, c, right));
+        }
+    }
+    out
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagType {
+    /// One character per flag, the default
+    Char,
+    /// Two characters per flag
+    Long,
+    /// Comma-separated numbers
+    Num,
+}
+
+/// One prefix or suffix rule of an `.aff` file
+#[derive(Debug)]
+struct Affix {
+    flag: String,
+    suffix: bool,
+    /// Combines with affixes of the other kind
+    cross: bool,
+    strip: String,
+    add: String,
+    condition: Option<Regex>,
+}
+
+impl Affix {
+    fn apply(&self, word: &str) -> Option<String> {
+        if self.condition.as_ref().is_some_and(|condition| !condition.is_match(word)) {
+            return None;
+        }
+        if self.suffix {
+            let stem = word.strip_suffix(self.strip.as_str())?;
+            Some(format!("{}{}", stem, self.add))
+        } else {
+            let stem = word.strip_prefix(self.strip.as_str())?;
+            Some(format!("{}{}", self.add, stem))
+        }
+    }
+}
+
+/// The affix rules of a hunspell `.aff` file, as far as spell checking
+/// single words needs them
+#[derive(Debug)]
+struct Affixes {
+    flag_type: FlagType,
+    rules: Vec<Affix>,
+    /// Flags of words that are not words on their own: forbidden, only in
+    /// compounds or only with an affix
+    skipped: Vec<String>,
+}
+
+impl Affixes {
+    fn parse(aff: &str) -> Self {
+        let mut affixes = Self { flag_type: FlagType::Char, rules: Vec::new(), skipped: Vec::new() };
+        // Whether the affix class of each flag combines with the other kind
+        let mut cross: Vec<(String, bool)> = Vec::new();
+        for line in aff.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["FLAG", "long", ..] => affixes.flag_type = FlagType::Long,
+                ["FLAG", "num", ..] => affixes.flag_type = FlagType::Num,
+                ["FORBIDDENWORD" | "ONLYINCOMPOUND" | "NEEDAFFIX", flag, ..] => affixes.skipped.push(flag.to_string()),
+                ["PFX" | "SFX", flag, combine, count] if count.parse::<usize>().is_ok() && !cross.iter().any(|(f, _)| f == flag) => {
+                    cross.push((flag.to_string(), *combine == "Y"));
+                }
+                [kind @ ("PFX" | "SFX"), flag, strip, add, rest @ ..] => {
+                    let suffix = *kind == "SFX";
+                    let add = add.split('/').next().unwrap_or_default();
+                    let condition = rest.first().copied().filter(|condition| *condition != ".").and_then(|condition| {
+                        let pattern = if suffix { format!("{}$", condition) } else { format!("^{}", condition) };
+                        Regex::new(&pattern).ok()
+                    });
+                    affixes.rules.push(Affix {
+                        flag: flag.to_string(),
+                        suffix,
+                        cross: cross.iter().any(|(f, combine)| f == flag && *combine),
+                        strip: if *strip == "0" { String::new() } else { strip.to_string() },
+                        add: if add == "0" { String::new() } else { add.to_string() },
+                        condition,
+                    });
+                }
+                _ => {}
+            }
+        }
+        affixes
+    }
+
+    /// The flags of a dictionary word, as written after its `/`
+    fn flags(&self, flags: &str) -> Vec<String> {
+        match self.flag_type {
+            FlagType::Char => flags.chars().map(String::from).collect(),
+            FlagType::Long => {
+                let chars: Vec<char> = flags.chars().collect();
+                chars.chunks(2).map(|pair| pair.iter().collect()).collect()
+            }
+            FlagType::Num => flags.split(',').map(|flag| flag.trim().to_string()).collect(),
+        }
+    }
+
+    /// Adds the word and every form its affix flags give it
+    fn expand(&self, word: &str, flags: &[String], words: &mut HashSet<String>) {
+        let rules: Vec<&Affix> = self.rules.iter().filter(|rule| flags.contains(&rule.flag)).collect();
+        words.insert(word.to_string());
+        for suffix in rules.iter().filter(|rule| rule.suffix) {
+            let Some(suffixed) = suffix.apply(word) else { continue };
+            if suffix.cross {
+                for prefix in rules.iter().filter(|rule| !rule.suffix && rule.cross) {
+                    words.extend(prefix.apply(&suffixed));
+                }
+            }
+            words.insert(suffixed);
+        }
+        for prefix in rules.iter().filter(|rule| !rule.suffix) {
+            words.extend(prefix.apply(word));
+        }
+    }
+}
+
+/// Likely misspellings in the entries, each with up to `suggestions`
+/// corrections, in order of entry and position
+pub fn check(entries: &[DiaryEntry], dictionary: &Dictionary, suggestions: usize) -> Vec<Misspelling> {
+    let ignored = Regex::new(concat!(
+        r"`[^`]*`",                 // code spans
+        r"|\w+://\S+|\S+@\S+\.\w+", // URLs and email addresses
+        r"|\]\([^)]*\)",            // link targets
+        r"|\[\[[^\]]*\]\]",         // wiki links
+        r"|(?:^|\s)#[\p{L}\p{N}_-]+", // tags
+        r"|:[a-z0-9_+-]+:",         // emoji shortcodes
+        r"|<[^>]+>",                // HTML
+    ))
+    .unwrap();
+    let words = Regex::new(r"[\p{L}\p{N}_]+(?:['’][\p{L}]+)*").unwrap();
+
+    let mut found = Vec::new();
+    // Suggestions for each misspelled word, found once per check
+    let mut suggested: Vec<(String, Vec<String>)> = Vec::new();
+    for entry in entries {
+        let mut fence: Option<&str> = None;
+        for (number, line) in entry.content.lines().enumerate() {
+            let trimmed = line.trim_start();
+            if let Some(open) = ["```", "~~~"].into_iter().find(|open| trimmed.starts_with(open)) {
+                match fence {
+                    Some(current) if current == open => fence = None,
+                    Some(_) => {}
+                    None => fence = Some(open),
+                }
+                continue;
+            }
+            if fence.is_some() {
+                continue;
+            }
+
+            let mut masked = line.to_string();
+            for span in ignored.find_iter(line) {
+                masked.replace_range(span.range(), &" ".repeat(span.len()));
+            }
+            for word in words.find_iter(&masked) {
+                let text = word.as_str();
+                let acronym = text.chars().filter(|c| c.is_alphabetic()).all(char::is_uppercase);
+                if text.chars().count() < 2 || acronym || text.contains(|c: char| c.is_numeric() || c == '_') {
+                    continue;
+                }
+                if dictionary.check(text) {
+                    continue;
+                }
+
+                let suggestions = match suggested.iter().find(|(word, _)| word == text) {
+                    Some((_, found)) => found.clone(),
+                    None => {
+                        let found = dictionary.suggest(text, suggestions);
+                        suggested.push((text.to_string(), found.clone()));
+                        found
+                    }
+                };
+                found.push(Misspelling {
+                    date: entry.date,
+                    exec_version: entry.exec_version,
+                    line: number + 1,
+                    word: text.to_string(),
+                    context: context(line, word.start(), word.end()),
+                    suggestions,
+                });
+            }
+        }
+    }
+    found
+}
+
+/// A few words either side of `line[start..end]`
+fn context(line: &str, start: usize, end: usize) -> String {
+    let mut before: Vec<&str> = line[..start].split_whitespace().rev().take(CONTEXT_WORDS).collect();
+    before.reverse();
+    let after: Vec<&str> = line[end..].split_whitespace().take(CONTEXT_WORDS).collect();
+    let word = &line[start..end];
+    // Keep what is glued to the word, like punctuation
+    let glued_before = !line[..start].ends_with(char::is_whitespace) && !before.is_empty();
+    let glued_after = !line[end..].starts_with(char::is_whitespace) && !after.is_empty();
+
+    let mut out = String::new();
+    for (i, part) in before.iter().enumerate() {
+        if i > 0 {
+            out.push(' ');
+        }
+        out.push_str(part);
+    }
+    if !before.is_empty() && !glued_before {
+        out.push(' ');
+    }
+    out.push_str(word);
+    for (i, part) in after.iter().enumerate() {
+        if i > 0 || !glued_after {
+            out.push(' ');
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+/// The encoding an `.aff` file's `SET` line names, ISO 8859-1 without one
+fn aff_encoding(aff: &[u8]) -> Result<&'static Encoding> {
+    let set = aff
+        .split(|&byte| byte == b'\n')
+        .find_map(|line| String::from_utf8_lossy(line).trim().strip_prefix("SET ").map(|name| name.trim().to_string()));
+    let Some(name) = set else { return Ok(encoding_rs::WINDOWS_1252) };
+
+    // Hunspell's names for what the WHATWG labels call otherwise
+    let lower = name.to_ascii_lowercase();
+    let label = match lower.as_str() {
+        "microsoft-cp1251" => "windows-1251",
+        "tis620-2533" => "tis-620",
+        other => other,
+    };
+    Encoding::for_label(label.as_bytes())
+        .filter(|encoding| encoding.is_single_byte() || *encoding == encoding_rs::UTF_8)
+        .ok_or_else(|| RustyDiaryError::InvalidConfig(format!("Unsupported dictionary encoding '{}'", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    const AFF: &str = "SET UTF-8\n\
+                       SFX S Y 2\nSFX S y ies [^aeiou]y\nSFX S 0 s [^y]\n\
+                       SFX D Y 1\nSFX D 0 ed .\n\
+                       PFX U Y 1\nPFX U 0 un .\n\
+                       NEEDAFFIX X\n";
+    const DIC: &str = "6\nwalk/DSU\ncity/S\nthe\npark\nwe\nwent/X\n";
+
+    #[test]
+    fn test_hunspell_affixes() {
+        let dictionary = Dictionary::from_hunspell(DIC.as_bytes(), AFF.as_bytes()).unwrap();
+        for word in ["walk", "walks", "walked", "unwalked", "unwalk", "cities", "The", "Walked"] {
+            assert!(dictionary.check(word), "{}", word);
+        }
+        for word in ["citys", "went", "wALK", "parked"] {
+            assert!(!dictionary.check(word), "{}", word);
+        }
+    }
+
+    #[test]
+    fn test_hunspell_encodings() -> Result<()> {
+        // "źle" in ISO 8859-2 and "мир" in KOI8-R
+        let polish = Dictionary::from_hunspell(b"1\n\xbcle\n", b"SET ISO8859-2\n")?;
+        assert!(polish.check("źle"));
+        let russian = Dictionary::from_hunspell(b"1\n\xcd\xc9\xd2\n", b"SET KOI8-R\n")?;
+        assert!(russian.check("мир"));
+        let latin1 = Dictionary::from_hunspell(b"1\ncaf\xe9\n", b"SFX S N 0\n")?;
+        assert!(latin1.check("café"));
+
+        assert!(matches!(
+            Dictionary::from_hunspell(b"1\nword\n", b"SET ISCII-DEVANAGARI\n"),
+            Err(RustyDiaryError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_suggestions() {
+        let dictionary = Dictionary::from_words("the\nthen\nten\npark\nwalked\n");
+        assert_eq!(dictionary.suggest("teh", 3), vec!["the", "ten"]);
+        assert_eq!(dictionary.suggest("Prak", 3), vec!["Park"]);
+        assert_eq!(dictionary.suggest("wlkd", 3), vec!["walked"]);
+        assert!(dictionary.suggest("xyzzyq", 3).is_empty());
+    }
+
+    #[test]
+    fn test_check_entries() {
+        let dictionary = Dictionary::from_words("we\nwent\nto\nthe\npark\nwith\nsee\nand\nit's\n").with_words(["Ana".to_string()]);
+        let date = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        let content = "We went to teh park with Ana, see [NASA](https://nasa.gov/spase) #tripp :tada:\n\
+                       ```\nlet speling = 1;\n```\n`codez` and it’s 3rd <b>wrold</b> [[Plase]] mp3\nwe went to teh park";
+        let found = check(&[DiaryEntry::new(2, date, content.to_string(), SystemClock.now())], &dictionary, 2);
+
+        let summary: Vec<_> = found.iter().map(|m| (m.line, m.word.as_str(), m.context.as_str())).collect();
+        assert_eq!(summary, vec![
+            (1, "teh", "We went to teh park with Ana, see [NASA](https://nasa.gov/spase)"),
+            (5, "wrold", "`codez` and it’s 3rd <b>wrold</b> [[Plase]] mp3"),
+            (6, "teh", "we went to teh park"),
+        ]);
+        assert_eq!(found[0].suggestions, vec!["the"]);
+        assert_eq!(context("(teh)", 1, 4), "(teh)");
+    }
+}
@@ -0,0 +1,89 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+
+use crate::dates::DateRange;
+use crate::error::RustyDiaryError;
+
+/// Directory under the diary directory that holds split journals
+pub const JOURNAL_DIR: &str = "journal";
+
+/// How `write_journal` lays out its output
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JournalSplit {
+    /// One new journal file per write
+    #[default]
+    None,
+    /// `journal/2024/2024-03.md`, one file per month
+    Month,
+    /// `journal/2024.md`, one file per year
+    Year,
+}
+
+impl JournalSplit {
+    pub const NAMES: &'static [&'static str] = &["none", "month", "year"];
+
+    /// The period containing `date`
+    pub fn period(&self, date: NaiveDate) -> DateRange {
+        match self {
+            JournalSplit::None => DateRange::day(date),
+            JournalSplit::Month => DateRange::month_of(date),
+            JournalSplit::Year => DateRange::year_of(date),
+        }
+    }
+
+    /// File of the period containing `date`, relative to the journal directory
+    pub fn relative_path(&self, date: NaiveDate) -> PathBuf {
+        match self {
+            JournalSplit::Month => PathBuf::from(date.format("%Y").to_string()).join(date.format("%Y-%m.md").to_string()),
+            JournalSplit::Year | JournalSplit::None => PathBuf::from(date.format("%Y.md").to_string()),
+        }
+    }
+}
+
+impl FromStr for JournalSplit {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "none" => Ok(JournalSplit::None),
+            "month" => Ok(JournalSplit::Month),
+            "year" => Ok(JournalSplit::Year),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown journal split '{}' (expected none, month or year)", name
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for JournalSplit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JournalSplit::None => "none",
+            JournalSplit::Month => "month",
+            JournalSplit::Year => "year",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_periods_and_paths() {
+        let date = NaiveDate::from_ymd_opt(2024, 2, 14).unwrap();
+        let ymd = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+
+        assert_eq!(JournalSplit::Month.period(date), DateRange::new(ymd(2024, 2, 1), ymd(2024, 2, 29)));
+        assert_eq!(JournalSplit::Month.period(ymd(2023, 12, 5)), DateRange::new(ymd(2023, 12, 1), ymd(2023, 12, 31)));
+        assert_eq!(JournalSplit::Year.period(date), DateRange::new(ymd(2024, 1, 1), ymd(2024, 12, 31)));
+        assert_eq!(JournalSplit::Month.relative_path(date), PathBuf::from("2024/2024-02.md"));
+        assert_eq!(JournalSplit::Year.relative_path(date), PathBuf::from("2024.md"));
+
+        assert_eq!("month".parse::<JournalSplit>().unwrap(), JournalSplit::Month);
+        assert!("week".parse::<JournalSplit>().is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Stamps new entries with where they were written and the weather of the
+//! day, stored as the `location` and `weather` metadata fields, and shows
+//! both as a byline under the entry's heading in written journals. Built
+//! with the `stamp` feature.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use crate::error::Result;
+use crate::storage::models::DiaryEntry;
+
+use super::processor::Enricher;
+
+/// Metadata field holding the place an entry was written
+pub const LOCATION_FIELD: &str = "location";
+/// Metadata field holding the weather of the entry's day
+pub const WEATHER_FIELD: &str = "weather";
+
+/// Looks up the weather of a day, e.g. from a local cache of observations
+pub trait WeatherProvider: Send + Sync {
+    /// A short description such as "Light rain, 14°C", or none when unknown
+    fn weather(&self, date: NaiveDate, location: Option<&str>) -> Result<Option<String>>;
+}
+
+/// The default provider: offline, so the weather is never known
+pub struct NoWeather;
+
+impl WeatherProvider for NoWeather {
+    fn weather(&self, _date: NaiveDate, _location: Option<&str>) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+type LocationFn = dyn Fn(NaiveDate) -> Option<String> + Send + Sync;
+
+/// Enricher recording the location and weather of new entries
+pub struct StampEnricher {
+    location: Option<Arc<LocationFn>>,
+    weather: Arc<dyn WeatherProvider>,
+}
+
+impl Default for StampEnricher {
+    fn default() -> Self {
+        Self { location: None, weather: Arc::new(NoWeather) }
+    }
+}
+
+impl StampEnricher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stamps every entry with the same place, e.g. from the configuration
+    pub fn with_location(self, location: &str) -> Self {
+        let location = location.to_string();
+        self.with_location_fn(move |_| Some(location.clone()))
+    }
+
+    /// Asks `location` where the entry of a day was written, e.g. a phone's
+    /// location history
+    pub fn with_location_fn(mut self, location: impl Fn(NaiveDate) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.location = Some(Arc::new(location));
+        self
+    }
+
+    pub fn with_weather(mut self, provider: Arc<dyn WeatherProvider>) -> Self {
+        self.weather = provider;
+        self
+    }
+}
+
+impl Enricher for StampEnricher {
+    fn name(&self) -> &str {
+        "stamp"
+    }
+
+    fn enrich(&self, entry: &DiaryEntry) -> Result<Vec<(String, String)>> {
+        let location = self.location.as_ref().and_then(|location| location(entry.date));
+        let weather = self.weather.weather(entry.date, location.as_deref())?;
+
+        let fields = [(LOCATION_FIELD, location), (WEATHER_FIELD, weather)];
+        Ok(fields
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value.filter(|value| !value.trim().is_empty())?)))
+            .collect())
+    }
+}
+
+/// The byline for a day's metadata, e.g. `*Lisbon · Light rain, 14°C*`, or
+/// none when neither the location nor the weather is known
+pub fn byline(fields: &BTreeMap<String, String>) -> Option<String> {
+    let parts: Vec<&str> = [LOCATION_FIELD, WEATHER_FIELD]
+        .iter()
+        .filter_map(|key| fields.get(*key))
+        .map(|value| value.trim())
+        .collect();
+    (!parts.is_empty()).then(|| format!("*{}*", parts.join(" · ")))
+}
+
+/// Puts the byline, if there is one, above the entry's content
+pub fn add_byline(entry: &mut DiaryEntry, fields: &BTreeMap<String, String>) {
+    if let Some(byline) = byline(fields) {
+        entry.content = format!("{}\n\n{}", byline, entry.content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use chrono::Datelike;
+
+    struct Sunny;
+
+    impl WeatherProvider for Sunny {
+        fn weather(&self, _date: NaiveDate, location: Option<&str>) -> Result<Option<String>> {
+            Ok(location.map(|_| "Sunny, 21°C".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_stamp_and_byline() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 1).unwrap();
+        let mut entry = DiaryEntry::new(1, date, "Beach day".to_string(), SystemClock.now());
+
+        assert!(StampEnricher::new().enrich(&entry)?.is_empty());
+        let fields: BTreeMap<String, String> =
+            StampEnricher::new().with_location("Lisbon").with_weather(Arc::new(Sunny)).enrich(&entry)?.into_iter().collect();
+        assert_eq!(byline(&fields).as_deref(), Some("*Lisbon · Sunny, 21°C*"));
+
+        let travelling = StampEnricher::new().with_location_fn(|date| (date.month0() == 5).then(|| "Porto".to_string()));
+        assert_eq!(travelling.enrich(&entry)?, vec![("location".to_string(), "Porto".to_string())]);
+
+        add_byline(&mut entry, &fields);
+        assert_eq!(entry.content, "*Lisbon · Sunny, 21°C*\n\nBeach day");
+        add_byline(&mut entry, &BTreeMap::new());
+        assert!(entry.content.starts_with("*Lisbon"));
+        Ok(())
+    }
+}
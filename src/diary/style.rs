@@ -0,0 +1,399 @@
+//! Writing-style checks of stored entries, for journals kept as writing
+//! practice. Each rule is a heuristic that points at a sentence worth a
+//! second look, not a grammar check:
+//!
+//! - `passive`: a form of "to be" followed by a past participle, as in
+//!   "the cake was eaten", optionally with an adverb between
+//! - `filler`: words that rarely add anything, such as "really" and
+//!   "basically"
+//! - `long-sentence`: sentences over a number of words
+//! - `repeated`: the same word twice in a row, as in "the the"
+//!
+//! Headings, tables, code, links and HTML are left out. Sentences end at
+//! `.`, `!` or `?`, at blank lines, headings and list items.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::error::{Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+
+/// Words a sentence may have before `long-sentence` reports it
+pub const DEFAULT_MAX_SENTENCE_WORDS: usize = 30;
+
+/// Filler words and phrases checked unless others are given
+pub const DEFAULT_FILLER_WORDS: [&str; 14] = [
+    "really", "very", "just", "actually", "basically", "literally", "quite", "somewhat", "totally", "simply",
+    "honestly", "kind of", "sort of", "a bit",
+];
+
+/// Forms of "to be" that start a passive construction
+const BE: [&str; 8] = ["am", "is", "are", "was", "were", "be", "been", "being"];
+
+/// Common past participles that do not end in -ed
+const IRREGULAR_PARTICIPLES: [&str; 48] = [
+    "been", "begun", "bitten", "blown", "born", "bought", "broken", "brought", "built", "caught", "chosen", "done",
+    "drawn", "driven", "eaten", "fallen", "felt", "forgiven", "forgotten", "found", "given", "gone", "grown", "heard",
+    "held", "hidden", "hit", "hurt", "kept", "known", "left", "lost", "made", "meant", "paid", "said", "seen", "sent",
+    "shown", "sold", "spent", "stolen", "taken", "taught", "thrown", "told", "won", "written",
+];
+
+/// Words ending in -ed that mostly describe a state rather than a passive
+/// action, as in "I was tired"
+const ADJECTIVES: [&str; 16] = [
+    "tired", "bored", "excited", "interested", "worried", "scared", "married", "relaxed", "stressed", "pleased",
+    "annoyed", "confused", "supposed", "used", "exhausted", "overwhelmed",
+];
+
+/// Abbreviations whose dot does not end a sentence, lowercase
+const ABBREVIATIONS: [&str; 9] = ["e.g.", "i.e.", "etc.", "vs.", "mr.", "mrs.", "ms.", "dr.", "st."];
+
+/// Words of a long sentence quoted in its issue
+const QUOTED_WORDS: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StyleRule {
+    Passive,
+    Filler,
+    LongSentence,
+    Repeated,
+}
+
+impl StyleRule {
+    pub const NAMES: &'static [&'static str] = &["passive", "filler", "long-sentence", "repeated"];
+    pub const ALL: [StyleRule; 4] = [StyleRule::Passive, StyleRule::Filler, StyleRule::LongSentence, StyleRule::Repeated];
+
+    pub fn as_str(self) -> &'static str {
+        Self::NAMES[self as usize]
+    }
+}
+
+impl FromStr for StyleRule {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self> {
+        Self::NAMES
+            .iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+            .ok_or_else(|| {
+                RustyDiaryError::InvalidConfig(format!(
+                    "Unknown style rule '{}' (expected one of {})",
+                    name,
+                    Self::NAMES.join(", ")
+                ))
+            })
+    }
+}
+
+impl fmt::Display for StyleRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Which rules `check` applies, and their settings
+#[derive(Debug, Clone)]
+pub struct StyleRules {
+    pub rules: Vec<StyleRule>,
+    /// Longest sentence, in words, `long-sentence` lets through
+    pub max_sentence_words: usize,
+    /// Lowercase words and phrases `filler` reports
+    pub filler_words: Vec<String>,
+}
+
+impl Default for StyleRules {
+    fn default() -> Self {
+        Self {
+            rules: StyleRule::ALL.to_vec(),
+            max_sentence_words: DEFAULT_MAX_SENTENCE_WORDS,
+            filler_words: DEFAULT_FILLER_WORDS.iter().map(|word| word.to_string()).collect(),
+        }
+    }
+}
+
+impl StyleRules {
+    /// Applies only these rules
+    pub fn with_rules(mut self, rules: Vec<StyleRule>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_max_sentence_words(mut self, words: usize) -> Self {
+        self.max_sentence_words = words;
+        self
+    }
+
+    /// Reports these words and phrases as filler instead of the defaults
+    pub fn with_filler_words(mut self, words: Vec<String>) -> Self {
+        self.filler_words = words.iter().map(|word| word.trim().to_lowercase()).filter(|word| !word.is_empty()).collect();
+        self
+    }
+
+    fn applies(&self, rule: StyleRule) -> bool {
+        self.rules.contains(&rule)
+    }
+}
+
+/// Something a rule found
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StyleIssue {
+    /// Line of the entry, from 1
+    pub line: usize,
+    pub rule: StyleRule,
+    /// The words found, or the start of a long sentence
+    pub text: String,
+    pub message: String,
+}
+
+/// The style of one entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StyleReport {
+    pub date: NaiveDate,
+    pub exec_version: i64,
+    pub words: usize,
+    pub sentences: usize,
+    pub issues: Vec<StyleIssue>,
+}
+
+/// A word of a sentence, with the line it is on
+struct Word<'a> {
+    line: usize,
+    /// As written, with punctuation
+    text: &'a str,
+    /// Lowercase, without the punctuation around it
+    plain: String,
+}
+
+impl Word<'_> {
+    /// Whether punctuation comes right after the word, breaking it off the
+    /// next one
+    fn ends_clause(&self) -> bool {
+        self.text.ends_with(|c: char| !c.is_alphanumeric())
+    }
+
+    fn ends_sentence(&self) -> bool {
+        let end = self.text.trim_end_matches(['"', '\'', ')', ']', '*', '_', '”', '’']);
+        end.ends_with(['.', '!', '?']) && !ABBREVIATIONS.contains(&end.to_lowercase().as_str())
+    }
+}
+
+/// The style report of each entry, in order
+pub fn check(entries: &[DiaryEntry], rules: &StyleRules) -> Vec<StyleReport> {
+    let ignored = Regex::new(concat!(
+        r"`[^`]*`",                   // code spans
+        r"|!?\[([^\]]*)\]\([^)]*\)",  // links and images, keeping their text
+        r"|\w+://\S+|\S+@\S+\.\w+",   // URLs and email addresses
+        r"|\[\[[^\]]*\]\]",           // wiki links
+        r"|<[^>]+>",                  // HTML
+    ))
+    .unwrap();
+    let fillers: Vec<Vec<&str>> = rules.filler_words.iter().map(|filler| filler.split_whitespace().collect()).collect();
+
+    entries
+        .iter()
+        .map(|entry| {
+            let mut report = StyleReport {
+                date: entry.date,
+                exec_version: entry.exec_version,
+                words: 0,
+                sentences: 0,
+                issues: Vec::new(),
+            };
+            let lines: Vec<(usize, String)> = prose(&entry.content)
+                .map(|(number, line)| (number, ignored.replace_all(line, "$1").into_owned()))
+                .collect();
+            let mut sentence: Vec<Word> = Vec::new();
+            for (number, line) in &lines {
+                if line.is_empty() {
+                    check_sentence(&sentence, rules, &fillers, &mut report);
+                    sentence.clear();
+                    continue;
+                }
+                for text in line.split_whitespace() {
+                    let plain = text.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+                    if plain.is_empty() {
+                        continue;
+                    }
+                    let word = Word { line: *number, text, plain };
+                    let ends = word.ends_sentence();
+                    sentence.push(word);
+                    if ends {
+                        check_sentence(&sentence, rules, &fillers, &mut report);
+                        sentence.clear();
+                    }
+                }
+            }
+            check_sentence(&sentence, rules, &fillers, &mut report);
+            report
+        })
+        .collect()
+}
+
+/// The prose lines of an entry, numbered from 1, with list and quote
+/// markers cut off. Lines that break a paragraph, such as headings, blank
+/// lines and list items, come through as an empty line first.
+fn prose(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    let list_marker = Regex::new(r"^(?:[-*+]|\d+[.)])\s+(?:\[[ xX]\]\s+)?").unwrap();
+    let mut fence: Option<&str> = None;
+    content.lines().enumerate().flat_map(move |(index, line)| {
+        let number = index + 1;
+        let trimmed = line.trim_start().trim_start_matches(['>', ' ']);
+        if let Some(open) = ["```", "~~~"].into_iter().find(|open| trimmed.starts_with(open)) {
+            match fence {
+                Some(current) if current == open => fence = None,
+                Some(_) => {}
+                None => fence = Some(open),
+            }
+            return vec![(number, "")];
+        }
+        if fence.is_some() || trimmed.is_empty() || trimmed.starts_with(['#', '|']) {
+            return vec![(number, "")];
+        }
+        match list_marker.find(trimmed) {
+            Some(marker) => vec![(number, ""), (number, &trimmed[marker.end()..])],
+            None => vec![(number, trimmed)],
+        }
+    })
+}
+
+fn check_sentence(sentence: &[Word], rules: &StyleRules, fillers: &[Vec<&str>], report: &mut StyleReport) {
+    if sentence.is_empty() {
+        return;
+    }
+    report.words += sentence.len();
+    report.sentences += 1;
+    let issues = &mut report.issues;
+
+    if rules.applies(StyleRule::LongSentence) && sentence.len() > rules.max_sentence_words {
+        let quoted: Vec<&str> = sentence.iter().take(QUOTED_WORDS).map(|word| word.text).collect();
+        let cut = if sentence.len() > QUOTED_WORDS { " …" } else { "" };
+        issues.push(StyleIssue {
+            line: sentence[0].line,
+            rule: StyleRule::LongSentence,
+            text: format!("{}{}", quoted.join(" "), cut),
+            message: format!("sentence of {} words", sentence.len()),
+        });
+    }
+
+    for (i, word) in sentence.iter().enumerate() {
+        let next = sentence.get(i + 1).filter(|_| !word.ends_clause());
+
+        if rules.applies(StyleRule::Repeated) {
+            if let Some(next) = next.filter(|next| next.plain == word.plain && !word.plain.chars().all(char::is_numeric)) {
+                issues.push(StyleIssue {
+                    line: next.line,
+                    rule: StyleRule::Repeated,
+                    text: format!("{} {}", word.text, next.text),
+                    message: format!("'{}' twice in a row", word.plain),
+                });
+            }
+        }
+
+        if rules.applies(StyleRule::Filler) {
+            let filler = fillers.iter().find(|filler| {
+                sentence.len() >= i + filler.len()
+                    && filler.iter().enumerate().all(|(j, part)| sentence[i + j].plain == *part)
+                    && sentence[i..i + filler.len() - 1].iter().all(|word| !word.ends_clause())
+            });
+            if let Some(filler) = filler {
+                issues.push(StyleIssue {
+                    line: word.line,
+                    rule: StyleRule::Filler,
+                    text: filler.join(" "),
+                    message: "filler word".to_string(),
+                });
+            }
+        }
+
+        if rules.applies(StyleRule::Passive) && BE.contains(&word.plain.as_str()) {
+            // An adverb may come between, as in "was quickly eaten"
+            let adverb = next.is_some_and(|adverb| adverb.plain.ends_with("ly") && !adverb.ends_clause());
+            let end = i + 1 + usize::from(adverb);
+            if next.is_some() && sentence.get(end).is_some_and(|participle| is_participle(&participle.plain)) {
+                let words: Vec<&str> = sentence[i..=end].iter().map(|word| word.plain.as_str()).collect();
+                issues.push(StyleIssue {
+                    line: word.line,
+                    rule: StyleRule::Passive,
+                    text: words.join(" "),
+                    message: "possible passive voice".to_string(),
+                });
+            }
+        }
+    }
+}
+
+fn is_participle(word: &str) -> bool {
+    if ADJECTIVES.contains(&word) {
+        return false;
+    }
+    IRREGULAR_PARTICIPLES.contains(&word) || (word.len() > 4 && word.ends_with("ed") && word.is_ascii())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    fn issues(content: &str, rules: &StyleRules) -> Vec<(usize, StyleRule, String)> {
+        let now = SystemClock.now();
+        let entry = DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 3, 4).unwrap(), content.to_string(), now);
+        check(&[entry], rules)
+            .remove(0)
+            .issues
+            .into_iter()
+            .map(|issue| (issue.line, issue.rule, issue.text))
+            .collect()
+    }
+
+    #[test]
+    fn test_style_rules() {
+        let content = "# The the heading\n\n\
+                       The cake was quickly eaten by the dog. I was tired,\n\
+                       so I really went to to bed. The letter had been written `is done` kind of late.\n\
+                       ```\nit was broken broken\n```\n\
+                       - A list item was painted [at home](x.md)\n";
+        assert_eq!(
+            issues(content, &StyleRules::default()),
+            vec![
+                (3, StyleRule::Passive, "was quickly eaten".to_string()),
+                (4, StyleRule::Filler, "really".to_string()),
+                (4, StyleRule::Repeated, "to to".to_string()),
+                (4, StyleRule::Passive, "been written".to_string()),
+                (4, StyleRule::Filler, "kind of".to_string()),
+                (8, StyleRule::Passive, "was painted".to_string()),
+            ]
+        );
+
+        let rules = StyleRules::default()
+            .with_rules(vec![StyleRule::Filler, StyleRule::LongSentence])
+            .with_max_sentence_words(5)
+            .with_filler_words(vec!["Went ".to_string()]);
+        assert_eq!(
+            issues(content, &rules),
+            vec![
+                (3, StyleRule::LongSentence, "The cake was quickly eaten by the dog.".to_string()),
+                (3, StyleRule::LongSentence, "I was tired, so I really went to …".to_string()),
+                (4, StyleRule::Filler, "went".to_string()),
+                (4, StyleRule::LongSentence, "The letter had been written kind of late.".to_string()),
+                (8, StyleRule::LongSentence, "A list item was painted at home".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_style_report_counts() {
+        let now = SystemClock.now();
+        let entry = DiaryEntry::new(2, NaiveDate::from_ymd_opt(2024, 3, 5).unwrap(), "One two. Three, e.g. four!\n\n- five\n".to_string(), now);
+        let report = check(&[entry], &StyleRules::default()).remove(0);
+        assert_eq!((report.exec_version, report.words, report.sentences), (2, 6, 3));
+        assert!(report.issues.is_empty());
+        assert_eq!("long-sentence".parse::<StyleRule>().unwrap(), StyleRule::LongSentence);
+        assert!("grammar".parse::<StyleRule>().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Tag suggestions from TF-IDF: words frequent in one entry but rare in the
+//! rest of the diary make good tags.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::storage::models::DiaryEntry;
+
+/// Common English words that never make useful tags
+const STOPWORDS: &[&str] = &[
+    "about", "above", "after", "again", "against", "all", "also", "and", "any", "are", "because",
+    "been", "before", "being", "below", "between", "both", "but", "can", "could", "did", "does",
+    "doing", "down", "during", "each", "even", "ever", "few", "for", "from", "further", "get", "got",
+    "had", "has", "have", "having", "her", "here", "hers", "herself", "him", "himself", "his", "how",
+    "into", "its", "itself", "just", "like", "made", "make", "many", "more", "most", "much", "must",
+    "myself", "never", "not", "now", "off", "once", "one", "only", "other", "our", "ours",
+    "ourselves", "out", "over", "own", "really", "same", "she", "should", "some", "still", "such",
+    "than", "that", "the", "their", "theirs", "them", "themselves", "then", "there", "these", "they",
+    "thing", "things", "this", "those", "through", "today", "too", "under", "until", "very", "was",
+    "way", "well", "were", "what", "when", "where", "which", "while", "who", "whom", "why", "will",
+    "with", "would", "yesterday", "yet", "you", "your", "yours", "yourself", "yourselves",
+];
+
+/// Words shorter than this are not considered
+const MIN_WORD_LEN: usize = 3;
+
+/// Most tags suggested or applied per entry
+pub const SUGGESTION_LIMIT: usize = 5;
+
+/// `StoredTag::source` of tags applied by `--auto-tag`
+pub const AUTO_SOURCE: &str = "auto";
+
+/// A proposed tag and how strongly the entry is about it, from 0 to 1
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TagSuggestion {
+    pub tag: String,
+    pub confidence: f64,
+}
+
+/// Scores words against a corpus of entries
+pub struct TagSuggester {
+    documents: usize,
+    /// Entries each word appears in
+    document_frequency: HashMap<String, usize>,
+}
+
+impl TagSuggester {
+    /// Builds the word statistics of the corpus, normally the latest version
+    /// of every entry
+    pub fn new(corpus: &[DiaryEntry]) -> Self {
+        let mut document_frequency = HashMap::new();
+        for entry in corpus {
+            let words: HashSet<String> = terms(&entry.content).into_iter().collect();
+            for word in words {
+                *document_frequency.entry(word).or_insert(0) += 1;
+            }
+        }
+        Self { documents: corpus.len(), document_frequency }
+    }
+
+    /// Up to `limit` tags for the entry, most confident first. Words already
+    /// used as `#tags` in the entry are not suggested again.
+    ///
+    /// Confidence is the word's share of the entry's TF-IDF vector (its
+    /// weight divided by the vector's length), so it is comparable across
+    /// entries of different lengths.
+    pub fn suggest(&self, entry: &DiaryEntry, limit: usize) -> Vec<TagSuggestion> {
+        let weights = self.weights(&entry.content);
+        let norm = weights.iter().map(|(_, w)| w * w).sum::<f64>().sqrt();
+        if norm == 0.0 {
+            return Vec::new();
+        }
+
+        let existing = entry.tags();
+        let mut suggestions: Vec<TagSuggestion> = weights
+            .into_iter()
+            .filter(|(word, _)| !existing.contains(word))
+            .map(|(tag, weight)| TagSuggestion { tag, confidence: weight / norm })
+            .collect();
+        suggestions.sort_by(|a, b| b.confidence.total_cmp(&a.confidence).then_with(|| a.tag.cmp(&b.tag)));
+        suggestions.truncate(limit);
+        suggestions
+    }
+
+    /// The TF-IDF weight of each distinct word of the content, sorted by word
+    pub(crate) fn weights(&self, content: &str) -> Vec<(String, f64)> {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for word in terms(content) {
+            *counts.entry(word).or_insert(0) += 1;
+        }
+
+        counts
+            .into_iter()
+            .map(|(word, count)| {
+                let df = self.document_frequency.get(&word).copied().unwrap_or(0);
+                // Smoothed so words in every entry still count a little
+                let idf = ((1 + self.documents) as f64 / (1 + df) as f64).ln() + 1.0;
+                (word, count as f64 * idf)
+            })
+            .collect()
+    }
+}
+
+/// Lowercased candidate words of the content, in order, skipping stopwords,
+/// short words and markdown link targets
+pub(crate) fn terms(content: &str) -> Vec<String> {
+    let links = Regex::new(r"\]\([^)]*\)").unwrap();
+    let words = Regex::new(r"\p{L}+").unwrap();
+
+    let text = links.replace_all(content, "]");
+    words
+        .find_iter(&text)
+        .map(|m| m.as_str().to_lowercase())
+        .filter(|word| word.chars().count() >= MIN_WORD_LEN && !STOPWORDS.contains(&word.as_str()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use chrono::NaiveDate;
+
+    fn entry(day: u32, content: &str) -> DiaryEntry {
+        DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 1, day).unwrap(), content.to_string(), SystemClock.now())
+    }
+
+    #[test]
+    fn test_rare_words_rank_first() {
+        let corpus = vec![
+            entry(1, "Work was long, the meeting ran over."),
+            entry(2, "Work again, then a climbing session. Climbing felt great! #fitness"),
+            entry(3, "Work and more work. The meeting was fine."),
+        ];
+        let suggester = TagSuggester::new(&corpus);
+
+        let suggestions = suggester.suggest(&corpus[1], 3);
+        assert_eq!(suggestions[0].tag, "climbing");
+        assert!(suggestions.iter().all(|s| s.tag != "fitness" && s.tag != "then"));
+        assert!(suggestions.windows(2).all(|w| w[0].confidence >= w[1].confidence));
+        assert!(suggestions[0].confidence > 0.0 && suggestions[0].confidence <= 1.0);
+
+        assert!(suggester.suggest(&entry(4, "It is so."), 3).is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use minijinja::{context, Environment};
+
+use crate::error::{Result, RustyDiaryError};
+use crate::storage::models::DiaryEntry;
+
+use super::locale::DateLocale;
+
+/// Names of the template parts, also used as file names by `from_dir`
+const PARTS: [&str; 4] = ["header", "entry_header", "separator", "footer"];
+
+/// Minijinja templates for the parts of a generated journal file.
+///
+/// `header` and `footer` see `start_date`, `end_date` (none without entries),
+/// `total_entries` and `exec_version`. `entry_header` and `separator` see
+/// `date`, `exec_version`, `word_count`, the 1-based `index`, and from the
+/// locale `weekday`, `month_name`, `week_start` and `secondary_date` (none
+/// without a secondary calendar). Each entry is written as its header, its
+/// content, then the separator. The `localdate` filter formats a date in the
+/// locale, e.g. `{{ date | localdate("%A, %-d %B") }}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalTemplate {
+    pub header: String,
+    pub entry_header: String,
+    pub separator: String,
+    pub footer: String,
+    /// Adds a table of contents after the header and an index by tag before
+    /// the footer, linking to anchors placed before each entry
+    pub toc: bool,
+    pub locale: DateLocale,
+}
+
+impl Default for JournalTemplate {
+    fn default() -> Self {
+        Self {
+            header: concat!(
+                "---\nmetadata\n",
+                "- rusty-diary:date-range {{ start_date or \"unknown\" }} to {{ end_date or \"unknown\" }}\n",
+                "- rusty-diary:total-entries({{ total_entries }})\n---\n",
+            )
+            .to_string(),
+            entry_header: "# {{ date }}{% if secondary_date %} · {{ secondary_date }}{% endif %}\n".to_string(),
+            separator: "\n\n***\n".to_string(),
+            footer: String::new(),
+            toc: false,
+            locale: DateLocale::default(),
+        }
+    }
+}
+
+impl JournalTemplate {
+    /// Loads `header.j2`, `entry_header.j2`, `separator.j2` and `footer.j2`
+    /// from a directory. Missing files keep the default for that part.
+    pub fn from_dir<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let dir = dir.as_ref();
+        if !dir.is_dir() {
+            return Err(RustyDiaryError::InvalidDirectory(dir.to_path_buf()));
+        }
+
+        let mut template = Self::default();
+        for part in PARTS {
+            let path = dir.join(format!("{}.j2", part));
+            if path.is_file() {
+                *template.part_mut(part) = fs::read_to_string(&path)?;
+            }
+        }
+        Ok(template)
+    }
+
+    fn part_mut(&mut self, part: &str) -> &mut String {
+        match part {
+            "header" => &mut self.header,
+            "entry_header" => &mut self.entry_header,
+            "separator" => &mut self.separator,
+            _ => &mut self.footer,
+        }
+    }
+
+    /// Parses every part, failing on the first syntax error
+    pub fn compile(&self) -> Result<JournalRenderer> {
+        let mut env = Environment::new();
+        // Templates are fragments of one file; their newlines are significant
+        env.set_keep_trailing_newline(true);
+        let locale = self.locale;
+        env.add_filter("localdate", move |date: String, format: String| {
+            NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                .map(|date| locale.format(date, &format))
+                .map_err(|_| minijinja::Error::new(minijinja::ErrorKind::InvalidOperation, format!("'{}' is not a date", date)))
+        });
+
+        for (part, source) in PARTS.into_iter().zip([&self.header, &self.entry_header, &self.separator, &self.footer]) {
+            env.add_template_owned(part, source.clone()).map_err(|e| {
+                RustyDiaryError::InvalidConfig(format!("Invalid journal {} template: {}", part, e))
+            })?;
+        }
+        Ok(JournalRenderer { env, toc: self.toc, locale: self.locale })
+    }
+}
+
+/// Compiled journal templates
+#[derive(Debug, Clone)]
+pub struct JournalRenderer {
+    env: Environment<'static>,
+    toc: bool,
+    locale: DateLocale,
+}
+
+/// Link target of an entry; unique even with several versions of a day
+fn anchor(entry: &DiaryEntry) -> String {
+    format!("{}-v{}", entry.date, entry.exec_version)
+}
+
+impl JournalRenderer {
+    /// Renders the journal header, or the footer when `footer` is set.
+    /// `entries` are sorted newest first.
+    pub fn frame(&self, entries: &[DiaryEntry], footer: bool) -> Result<String> {
+        let versions: Vec<(NaiveDate, i64)> = entries.iter().map(|e| (e.date, e.exec_version)).collect();
+        self.frame_for(&versions, footer)
+    }
+
+    /// Like `frame`, from the date and exec version of each entry
+    pub fn frame_for(&self, versions: &[(NaiveDate, i64)], footer: bool) -> Result<String> {
+        let ctx = context! {
+            start_date => versions.last().map(|(date, _)| date.to_string()),
+            end_date => versions.first().map(|(date, _)| date.to_string()),
+            total_entries => versions.len(),
+            exec_version => versions.iter().map(|(_, version)| *version).max().unwrap_or(0),
+        };
+        self.render(if footer { "footer" } else { "header" }, ctx)
+    }
+
+    /// Renders one entry: its header, content and separator
+    pub fn entry(&self, entry: &DiaryEntry, index: usize) -> Result<String> {
+        let ctx = context! {
+            date => entry.date.to_string(),
+            exec_version => entry.exec_version,
+            word_count => entry.word_count(),
+            index => index,
+            weekday => self.locale.weekday(entry.date),
+            month_name => self.locale.month_name(entry.date),
+            week_start => self.locale.week_start(entry.date).to_string(),
+            secondary_date => self.locale.secondary_date(entry.date),
+        };
+
+        let mut out = String::new();
+        if self.toc {
+            out.push_str(&format!("<a id=\"{}\"></a>\n", anchor(entry)));
+        }
+        out.push_str(&self.render("entry_header", ctx.clone())?);
+        out.push_str(&entry.content);
+        out.push_str(&self.render("separator", ctx)?);
+        Ok(out)
+    }
+
+    /// Table of contents linking every entry, or nothing when disabled
+    pub fn contents(&self, entries: &[DiaryEntry]) -> String {
+        if !self.toc || entries.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("## Contents\n\n");
+        for entry in entries {
+            let versions = entries.iter().filter(|e| e.date == entry.date).count();
+            let label = if versions > 1 {
+                format!("{} (v{})", entry.date, entry.exec_version)
+            } else {
+                entry.date.to_string()
+            };
+            out.push_str(&format!("- [{}](#{})\n", label, anchor(entry)));
+        }
+        out.push('\n');
+        out
+    }
+
+    /// Entries grouped by `#tag`, tags sorted, or nothing when disabled
+    pub fn tag_index(&self, entries: &[DiaryEntry]) -> String {
+        let mut tags: BTreeMap<String, Vec<&DiaryEntry>> = BTreeMap::new();
+        if self.toc {
+            for entry in entries {
+                for tag in entry.tags() {
+                    tags.entry(tag).or_default().push(entry);
+                }
+            }
+        }
+        if tags.is_empty() {
+            return String::new();
+        }
+
+        let mut out = String::from("\n## Tags\n\n");
+        for (tag, entries) in tags {
+            let links: Vec<String> = entries
+                .iter()
+                .map(|entry| format!("[{}](#{})", entry.date, anchor(entry)))
+                .collect();
+            out.push_str(&format!("- **#{}**: {}\n", tag, links.join(", ")));
+        }
+        out
+    }
+
+    fn render(&self, part: &str, ctx: minijinja::Value) -> Result<String> {
+        self.env
+            .get_template(part)
+            .and_then(|template| template.render(ctx))
+            .map_err(|e| RustyDiaryError::InvalidConfig(format!("Failed to render journal {} template: {}", part, e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::diary::locale::SecondaryCalendar;
+    use tempfile::TempDir;
+
+    fn entries() -> Vec<DiaryEntry> {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 2).unwrap();
+        vec![
+            DiaryEntry::new(3, date, "Second day".to_string(), SystemClock.now()),
+            DiaryEntry::new(2, date.pred_opt().unwrap(), "First".to_string(), SystemClock.now()),
+        ]
+    }
+
+    #[test]
+    fn test_default_template_matches_legacy_format() -> Result<()> {
+        let renderer = JournalTemplate::default().compile()?;
+        let entries = entries();
+
+        assert_eq!(
+            renderer.frame(&entries, false)?,
+            "---\nmetadata\n- rusty-diary:date-range 2024-03-01 to 2024-03-02\n- rusty-diary:total-entries(2)\n---\n"
+        );
+        assert_eq!(renderer.entry(&entries[0], 1)?, "# 2024-03-02\nSecond day\n\n***\n");
+        assert_eq!(renderer.frame(&entries, true)?, "");
+        assert!(renderer.frame(&[], false)?.contains("unknown to unknown"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_contents_and_tag_index() -> Result<()> {
+        let renderer = JournalTemplate { toc: true, ..JournalTemplate::default() }.compile()?;
+        let date = NaiveDate::from_ymd_opt(2024, 3, 2).unwrap();
+        let entries = vec![
+            DiaryEntry::new(3, date, "Trip #travel".to_string(), SystemClock.now()),
+            DiaryEntry::new(2, date, "Draft".to_string(), SystemClock.now()),
+            DiaryEntry::new(2, date.pred_opt().unwrap(), "#food and #travel".to_string(), SystemClock.now()),
+        ];
+
+        assert_eq!(
+            renderer.contents(&entries),
+            "## Contents\n\n- [2024-03-02 (v3)](#2024-03-02-v3)\n- [2024-03-02 (v2)](#2024-03-02-v2)\n- [2024-03-01](#2024-03-01-v2)\n\n"
+        );
+        assert!(renderer.entry(&entries[0], 1)?.starts_with("<a id=\"2024-03-02-v3\"></a>\n# 2024-03-02\n"));
+        assert_eq!(
+            renderer.tag_index(&entries),
+            "\n## Tags\n\n- **#food**: [2024-03-01](#2024-03-01-v2)\n- **#travel**: [2024-03-02](#2024-03-02-v3), [2024-03-01](#2024-03-01-v2)\n"
+        );
+
+        let plain = JournalTemplate::default().compile()?;
+        assert_eq!(plain.contents(&entries), "");
+        assert_eq!(plain.tag_index(&entries), "");
+        Ok(())
+    }
+
+    #[test]
+    fn test_templates_from_dir() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        fs::write(temp_dir.path().join("entry_header.j2"), "## {{ index }}. {{ date }} ({{ word_count }} words)\n")?;
+        fs::write(temp_dir.path().join("footer.j2"), "<!-- v{{ exec_version }} -->\n")?;
+
+        let renderer = JournalTemplate::from_dir(temp_dir.path())?.compile()?;
+        let entries = entries();
+        assert_eq!(renderer.entry(&entries[1], 2)?, "## 2. 2024-03-01 (1 words)\nFirst\n\n***\n");
+        assert_eq!(renderer.frame(&entries, true)?, "<!-- v3 -->\n");
+
+        let broken = JournalTemplate { separator: "{% if %}".to_string(), ..JournalTemplate::default() };
+        assert!(matches!(broken.compile(), Err(RustyDiaryError::InvalidConfig(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_locale_in_entry_headers() -> Result<()> {
+        let locale = DateLocale {
+            locale: DateLocale::parse_locale("pt_PT")?,
+            first_weekday: chrono::Weekday::Sun,
+            secondary: Some(SecondaryCalendar::Hebrew),
+        };
+        let entries = entries();
+
+        let renderer = JournalTemplate { locale, ..JournalTemplate::default() }.compile()?;
+        assert_eq!(renderer.entry(&entries[0], 1)?, "# 2024-03-02 · 22 Adar I 5784\nSecond day\n\n***\n");
+
+        let template = JournalTemplate {
+            entry_header: "## {{ date | localdate(\"%A, %-d de %B\") }} ({{ weekday }}, week of {{ week_start }})\n".to_string(),
+            locale,
+            ..JournalTemplate::default()
+        };
+        assert!(template
+            .compile()?
+            .entry(&entries[0], 1)?
+            .starts_with("## sábado, 2 de março (sábado, week of 2024-02-25)\n"));
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+//! Most used terms of a period, as term/count pairs for word clouds and
+//! dashboards. Stopwords and short words are left out, as for tag
+//! suggestions; with stemming, `walked` and `walking` count as one term.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Serialize;
+
+use crate::storage::models::DiaryEntry;
+
+use super::tagging;
+
+/// How often a term was used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TermCount {
+    /// The term; with stemming, its most used spelling
+    pub term: String,
+    pub count: usize,
+}
+
+/// The crude English stem of a lowercase word: common inflections are cut
+/// as long as three letters remain. Good enough to group counts, not to
+/// show; see `TermCount::term`.
+pub fn stem(word: &str) -> String {
+    if let Some(base) = word.strip_suffix("ies").filter(|base| base.chars().count() >= 2) {
+        return format!("{}y", base);
+    }
+    for suffix in ["ing", "ed", "ly", "s"] {
+        if let Some(base) = word.strip_suffix(suffix) {
+            if base.chars().count() >= 3 && !(suffix == "s" && (base.ends_with('s') || base.ends_with('u'))) {
+                return base.to_string();
+            }
+        }
+    }
+    word.to_string()
+}
+
+/// The `limit` most used terms of the entries, most used first
+pub fn top_terms(entries: &[DiaryEntry], limit: usize, stemmed: bool) -> Vec<TermCount> {
+    // Counts by stem, and of each spelling within a stem
+    let mut counts: HashMap<String, (usize, BTreeMap<String, usize>)> = HashMap::new();
+    for word in entries.iter().flat_map(|entry| tagging::terms(&entry.content)) {
+        let key = if stemmed { stem(&word) } else { word.clone() };
+        let (count, spellings) = counts.entry(key).or_default();
+        *count += 1;
+        *spellings.entry(word).or_default() += 1;
+    }
+
+    let mut terms: Vec<TermCount> = counts
+        .into_values()
+        .map(|(count, spellings)| {
+            let term = spellings
+                .into_iter()
+                .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(&a.0)))
+                .map(|(spelling, _)| spelling)
+                .unwrap_or_default();
+            TermCount { term, count }
+        })
+        .collect();
+    terms.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.term.cmp(&b.term)));
+    terms.truncate(limit);
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_top_terms() {
+        let now = SystemClock.now();
+        let day = |d| NaiveDate::from_ymd_opt(2024, 3, d).unwrap();
+        let entries = vec![
+            DiaryEntry::new(1, day(1), "Walked to the river. Walking helps, the river was calm.".to_string(), now),
+            DiaryEntry::new(1, day(2), "We walked again and read about cities and bus routes.".to_string(), now),
+        ];
+
+        let plain = top_terms(&entries, 2, false);
+        assert_eq!(plain, vec![
+            TermCount { term: "river".to_string(), count: 2 },
+            TermCount { term: "walked".to_string(), count: 2 },
+        ]);
+
+        let stemmed = top_terms(&entries, 3, true);
+        assert_eq!(stemmed[0], TermCount { term: "walked".to_string(), count: 3 });
+        assert_eq!(stemmed[1].term, "river");
+        let stems: Vec<String> = ["cities", "bus", "calmly", "sing"].iter().map(|word| stem(word)).collect();
+        assert_eq!(stems, vec!["city", "bus", "calm", "sing"]);
+    }
+}
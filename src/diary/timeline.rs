@@ -0,0 +1,121 @@
+//! A timeline of life events: the lines of entries marked with
+//! `!event: text`, oldest first under a heading per year. Built in as the
+//! `timeline` export target, which writes markdown, or HTML or JSON when
+//! the destination ends in `.html` or `.json`.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, NaiveDate};
+use pulldown_cmark::{html, Parser};
+
+use crate::error::Result;
+use crate::storage::models::{DiaryEntry, LifeEvent};
+
+use super::export::{EntryStream, ExportSink};
+use super::processor::Exporter;
+
+const STYLE: &str = "body{max-width:42em;margin:2em auto;padding:0 1em;font-family:serif;line-height:1.5}\
+ol{list-style:none;padding-left:1em;border-left:2px solid #ccc}li{margin:.5em 0}time{color:#666;margin-right:.5em}";
+
+/// The events of the latest version of each day, oldest first
+pub fn events(entries: &[DiaryEntry]) -> Vec<LifeEvent> {
+    let mut latest: BTreeMap<NaiveDate, &DiaryEntry> = BTreeMap::new();
+    for entry in entries {
+        let current = latest.entry(entry.date).or_insert(entry);
+        if entry.exec_version > current.exec_version {
+            *current = entry;
+        }
+    }
+    latest
+        .into_iter()
+        .flat_map(|(date, entry)| entry.events().into_iter().map(move |text| LifeEvent { date, text }))
+        .collect()
+}
+
+/// The events as markdown, each linking to its day
+pub fn markdown(events: &[LifeEvent]) -> String {
+    let mut out = String::from("# Timeline\n");
+    for (year, events) in by_year(events) {
+        out.push_str(&format!("\n## {}\n\n", year));
+        for event in events {
+            out.push_str(&format!("- [[{}]] {}\n", event.date, event.text));
+        }
+    }
+    out
+}
+
+/// The events as a self-contained HTML page, their text rendered from
+/// markdown
+pub fn page(events: &[LifeEvent]) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Timeline</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>Timeline</h1>\n",
+        STYLE
+    );
+    for (year, events) in by_year(events) {
+        out.push_str(&format!("<h2>{}</h2>\n<ol>\n", year));
+        for event in events {
+            let mut text = String::new();
+            html::push_html(&mut text, Parser::new(&event.text));
+            let text = text.trim().trim_start_matches("<p>").trim_end_matches("</p>");
+            out.push_str(&format!("<li><time datetime=\"{date}\">{date}</time>{}</li>\n", text, date = event.date));
+        }
+        out.push_str("</ol>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn by_year(events: &[LifeEvent]) -> BTreeMap<i32, Vec<&LifeEvent>> {
+    let mut years: BTreeMap<i32, Vec<&LifeEvent>> = BTreeMap::new();
+    for event in events {
+        years.entry(event.date.year()).or_default().push(event);
+    }
+    years
+}
+
+/// Built-in exporter writing a timeline of the entries' life events
+pub struct TimelineExporter;
+
+impl Exporter for TimelineExporter {
+    fn name(&self) -> &str {
+        "timeline"
+    }
+
+    fn export(&self, entries: &mut EntryStream, sink: &mut ExportSink) -> Result<()> {
+        let events = events(&entries.collect::<Result<Vec<_>>>()?);
+        let extension = sink.destination().extension().map(|ext| ext.to_string_lossy().to_lowercase());
+        let out = match extension.as_deref() {
+            Some("json") => serde_json::to_string_pretty(&events)?,
+            Some("html" | "htm") => page(&events),
+            _ => markdown(&events),
+        };
+        sink.write(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+
+    #[test]
+    fn test_timeline() {
+        let day = |y, m, d| NaiveDate::from_ymd_opt(y, m, d).unwrap();
+        let entries = vec![
+            DiaryEntry::new(2, day(2021, 5, 2), "!event: Moved to Lisbon & Porto".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(2021, 5, 2), "!event: Moving soon".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(2019, 9, 1), "# First day at *Acme*\n!event:".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(2021, 1, 3), "Nothing happened".to_string(), SystemClock.now()),
+        ];
+        let events = events(&entries);
+        assert_eq!(
+            markdown(&events),
+            "# Timeline\n\n## 2019\n\n- [[2019-09-01]] First day at *Acme*\n\n## 2021\n\n- [[2021-05-02]] Moved to Lisbon & Porto\n"
+        );
+
+        let page = page(&events);
+        assert!(page.contains("<h2>2019</h2>\n<ol>\n<li><time datetime=\"2019-09-01\">2019-09-01</time>First day at <em>Acme</em></li>"));
+        assert!(page.contains("Moved to Lisbon &amp; Porto</li>"));
+    }
+}
@@ -1,13 +1,29 @@
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
+/// Errors returned by the library. New variants may be added in any
+/// release, so match on [`RustyDiaryError::kind`] where a catch-all is
+/// not enough.
 #[derive(Error, Debug)]
+#[non_exhaustive]
 pub enum RustyDiaryError {
-    #[error("IO operation failed: {0}")]
-    Io(#[from] std::io::Error),
+    #[error("IO operation failed{}: {source}", on_path(.path))]
+    Io {
+        source: std::io::Error,
+        /// The file or directory involved, when known
+        path: Option<PathBuf>,
+    },
 
-    #[error("Database error: {0}")]
-    Database(#[from] rusqlite::Error),
+    #[cfg(feature = "sqlite")]
+    #[error("Database error: {source}{}", while_running(.sql, .params))]
+    Database {
+        source: rusqlite::Error,
+        /// The statement that failed, when known
+        sql: Option<String>,
+        /// Its parameters, as they would print in a debugger
+        params: Vec<String>,
+    },
 
     #[error("Date parsing failed: {0}")]
     DateParse(#[from] chrono::ParseError),
@@ -21,8 +37,307 @@ pub enum RustyDiaryError {
     #[error("No matching files found in {0}")]
     NoFilesFound(PathBuf),
 
+    #[error("All {0} diary files were skipped")]
+    AllFilesSkipped(usize),
+
     #[error("Content integrity error: {0}")]
     ContentIntegrity(String),
+
+    #[error("Serialization failed: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("Database schema version {found} is newer than this build supports ({supported}); upgrade rusty_diary to open it")]
+    SchemaTooNew { found: i32, supported: i32 },
+
+    #[error("Incompatible database: {0}")]
+    IncompatibleSchema(String),
+
+    #[error("Hook failed: {0}")]
+    HookFailed(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Plugin error: {0}")]
+    Plugin(String),
+
+    #[error("Search gave up after {0:?}; narrow the date range or the pattern")]
+    SearchTimeout(std::time::Duration),
+
+    #[error("The write queue has stopped")]
+    WriteQueueStopped,
+
+    #[error("Cancelled before finishing")]
+    Cancelled,
+
+    #[error("Encryption failed: {0}")]
+    Encryption(String),
+
+    /// An error tied to a place in a diary file
+    #[error("{location}: {source}")]
+    Located {
+        location: Location,
+        source: Box<RustyDiaryError>,
+    },
+}
+
+/// A file, and optionally a line and column within it, both 1-based
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Location {
+    pub path: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
 }
 
-pub type Result<T> = std::result::Result<T, RustyDiaryError>;
\ No newline at end of file
+impl Location {
+    /// Line and column of the byte at `offset` in `text`
+    pub fn of_offset(text: &[u8], offset: usize) -> Self {
+        let before = &text[..offset.min(text.len())];
+        let line_start = before.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+        Self {
+            path: None,
+            line: Some(before.iter().filter(|&&b| b == b'\n').count() + 1),
+            column: Some(before.len() - line_start + 1),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let path = self.path.as_ref().map(|path| path.display().to_string());
+        let parts: Vec<String> = [path, self.line.map(|l| l.to_string()), self.column.map(|c| c.to_string())]
+            .into_iter()
+            .flatten()
+            .collect();
+        write!(f, "{}", parts.join(":"))
+    }
+}
+
+/// What went wrong, without the details. Each kind has a stable name and
+/// numeric code that are never reused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+#[repr(u16)]
+pub enum ErrorKind {
+    Io = 1,
+    Database = 2,
+    DateParse = 3,
+    InvalidDirectory = 4,
+    InvalidPattern = 5,
+    NoFilesFound = 6,
+    AllFilesSkipped = 7,
+    ContentIntegrity = 8,
+    Serialization = 9,
+    SchemaTooNew = 10,
+    IncompatibleSchema = 11,
+    HookFailed = 12,
+    InvalidConfig = 13,
+    Plugin = 14,
+    SearchTimeout = 15,
+    WriteQueueStopped = 16,
+    Cancelled = 17,
+    Encryption = 18,
+}
+
+impl ErrorKind {
+    /// Short, stable name of the kind, used as a metrics label
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Io => "io",
+            ErrorKind::Database => "database",
+            ErrorKind::DateParse => "date_parse",
+            ErrorKind::InvalidDirectory => "invalid_directory",
+            ErrorKind::InvalidPattern => "invalid_pattern",
+            ErrorKind::NoFilesFound => "no_files_found",
+            ErrorKind::AllFilesSkipped => "all_files_skipped",
+            ErrorKind::ContentIntegrity => "content_integrity",
+            ErrorKind::Serialization => "serialization",
+            ErrorKind::SchemaTooNew => "schema_too_new",
+            ErrorKind::IncompatibleSchema => "incompatible_schema",
+            ErrorKind::HookFailed => "hook_failed",
+            ErrorKind::InvalidConfig => "invalid_config",
+            ErrorKind::Plugin => "plugin",
+            ErrorKind::SearchTimeout => "search_timeout",
+            ErrorKind::WriteQueueStopped => "write_queue_stopped",
+            ErrorKind::Cancelled => "cancelled",
+            ErrorKind::Encryption => "encryption",
+        }
+    }
+
+    /// Stable numeric code of the kind
+    pub fn code(self) -> u16 {
+        self as u16
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "E{:03} {}", self.code(), self.as_str())
+    }
+}
+
+impl RustyDiaryError {
+    /// The kind of the error; for a located error, the kind of the one inside
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            RustyDiaryError::Io { .. } => ErrorKind::Io,
+            #[cfg(feature = "sqlite")]
+            RustyDiaryError::Database { .. } => ErrorKind::Database,
+            RustyDiaryError::DateParse(_) => ErrorKind::DateParse,
+            RustyDiaryError::InvalidDirectory(_) => ErrorKind::InvalidDirectory,
+            RustyDiaryError::InvalidPattern(_) => ErrorKind::InvalidPattern,
+            RustyDiaryError::NoFilesFound(_) => ErrorKind::NoFilesFound,
+            RustyDiaryError::AllFilesSkipped(_) => ErrorKind::AllFilesSkipped,
+            RustyDiaryError::ContentIntegrity(_) => ErrorKind::ContentIntegrity,
+            RustyDiaryError::Serialization(_) => ErrorKind::Serialization,
+            RustyDiaryError::SchemaTooNew { .. } => ErrorKind::SchemaTooNew,
+            RustyDiaryError::IncompatibleSchema(_) => ErrorKind::IncompatibleSchema,
+            RustyDiaryError::HookFailed(_) => ErrorKind::HookFailed,
+            RustyDiaryError::InvalidConfig(_) => ErrorKind::InvalidConfig,
+            RustyDiaryError::Plugin(_) => ErrorKind::Plugin,
+            RustyDiaryError::SearchTimeout(_) => ErrorKind::SearchTimeout,
+            RustyDiaryError::WriteQueueStopped => ErrorKind::WriteQueueStopped,
+            RustyDiaryError::Cancelled => ErrorKind::Cancelled,
+            RustyDiaryError::Encryption(_) => ErrorKind::Encryption,
+            RustyDiaryError::Located { source, .. } => source.kind(),
+        }
+    }
+
+    /// Ties the error to a line and column of the file being read
+    pub fn at(self, line: usize, column: usize) -> Self {
+        RustyDiaryError::Located {
+            location: Location { path: None, line: Some(line), column: Some(column) },
+            source: Box::new(self),
+        }
+    }
+
+    /// Ties the error to `path`, unless it already names a file
+    pub fn in_file(self, path: impl AsRef<Path>) -> Self {
+        match self {
+            RustyDiaryError::Located { mut location, source } => {
+                location.path.get_or_insert_with(|| path.as_ref().to_path_buf());
+                RustyDiaryError::Located { location, source }
+            }
+            RustyDiaryError::Io { path: Some(_), .. } => self,
+            error => RustyDiaryError::Located {
+                location: Location { path: Some(path.as_ref().to_path_buf()), ..Location::default() },
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// Where in a diary file the error happened, when known
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            RustyDiaryError::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Stable numeric code of the error's kind
+    pub fn code(&self) -> u16 {
+        self.kind().code()
+    }
+
+    /// The SQLite error underneath a database error
+    #[cfg(feature = "sqlite")]
+    pub fn sqlite_error(&self) -> Option<&rusqlite::Error> {
+        match self {
+            RustyDiaryError::Database { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for RustyDiaryError {
+    fn from(source: std::io::Error) -> Self {
+        RustyDiaryError::Io { source, path: None }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<rusqlite::Error> for RustyDiaryError {
+    fn from(source: rusqlite::Error) -> Self {
+        RustyDiaryError::Database { source, sql: None, params: Vec::new() }
+    }
+}
+
+/// Names the file an IO error happened on
+pub trait IoContext<T> {
+    fn at_path(self, path: impl AsRef<Path>) -> Result<T>;
+}
+
+impl<T> IoContext<T> for std::result::Result<T, std::io::Error> {
+    fn at_path(self, path: impl AsRef<Path>) -> Result<T> {
+        self.map_err(|source| RustyDiaryError::Io { source, path: Some(path.as_ref().to_path_buf()) })
+    }
+}
+
+/// Names the statement, and its parameters, a database error happened in
+#[cfg(feature = "sqlite")]
+pub trait SqlContext<T> {
+    fn with_sql(self, sql: &str, params: &[&dyn fmt::Debug]) -> Result<T>;
+}
+
+#[cfg(feature = "sqlite")]
+impl<T> SqlContext<T> for std::result::Result<T, rusqlite::Error> {
+    fn with_sql(self, sql: &str, params: &[&dyn fmt::Debug]) -> Result<T> {
+        self.map_err(|source| RustyDiaryError::Database {
+            source,
+            sql: Some(sql.split_whitespace().collect::<Vec<_>>().join(" ")),
+            params: params.iter().map(|param| format!("{:?}", param)).collect(),
+        })
+    }
+}
+
+fn on_path(path: &Option<PathBuf>) -> String {
+    path.as_ref().map(|path| format!(" on {}", path.display())).unwrap_or_default()
+}
+
+#[cfg(feature = "sqlite")]
+fn while_running(sql: &Option<String>, params: &[String]) -> String {
+    match sql {
+        Some(sql) if params.is_empty() => format!(" (while running `{}`)", sql),
+        Some(sql) => format!(" (while running `{}` with {})", sql, params.join(", ")),
+        None => String::new(),
+    }
+}
+
+pub type Result<T> = std::result::Result<T, RustyDiaryError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kinds_codes_and_context() {
+        let missing = std::fs::read("/nonexistent/2024-01-01.md").at_path("/nonexistent/2024-01-01.md").unwrap_err();
+        assert_eq!(missing.kind(), ErrorKind::Io);
+        assert_eq!(missing.code(), 1);
+        assert!(missing.to_string().starts_with("IO operation failed on /nonexistent/2024-01-01.md: "));
+
+        #[cfg(feature = "sqlite")]
+        {
+            let conn = rusqlite::Connection::open_in_memory().unwrap();
+            let failed = conn
+                .execute("INSERT INTO missing VALUES (?1)", ["2024-01-01"])
+                .with_sql("INSERT INTO missing\n   VALUES (?1)", &[&"2024-01-01"])
+                .unwrap_err();
+            assert_eq!(failed.kind().to_string(), "E002 database");
+            assert!(failed.sqlite_error().is_some());
+            assert!(failed.to_string().ends_with(r#"(while running `INSERT INTO missing VALUES (?1)` with "2024-01-01")"#));
+        }
+
+        let located = RustyDiaryError::ContentIntegrity("Invalid UTF-8 at byte 9".to_string())
+            .at(2, 4)
+            .in_file("notes/2024-01-01.md");
+        assert_eq!(located.kind(), ErrorKind::ContentIntegrity);
+        assert_eq!(located.to_string(), "notes/2024-01-01.md:2:4: Content integrity error: Invalid UTF-8 at byte 9");
+        assert_eq!(Location::of_offset(b"one\ntwo", 6), Location { path: None, line: Some(2), column: Some(3) });
+        assert!(matches!(missing.in_file("elsewhere.md"), RustyDiaryError::Io { .. }));
+
+        let bare: RustyDiaryError = std::io::Error::other("boom").into();
+        assert_eq!(bare.to_string(), "IO operation failed: boom");
+        assert_eq!(RustyDiaryError::WriteQueueStopped.kind().as_str(), "write_queue_stopped");
+    }
+}
\ No newline at end of file
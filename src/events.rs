@@ -0,0 +1,80 @@
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use chrono::NaiveDate;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::storage::DiaryEntry;
+
+/// Things that happen inside a `RustyDiary` that consumers may react to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum DiaryEvent {
+    /// A new entry was committed to the database
+    EntryStored(DiaryEntry),
+    /// A candidate file could not be turned into an entry
+    FileSkipped { path: PathBuf, reason: String },
+    /// A sync run finished
+    SyncCompleted {
+        exec_version: i64,
+        stored: usize,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    },
+    /// All stored versions of the entry for a date were deleted
+    EntryDeleted { date: NaiveDate, versions: usize },
+}
+
+/// Fan-out of diary events to any number of subscribers.
+/// Each subscriber gets its own channel; dropped receivers are pruned
+/// on the next publish.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: Mutex<Vec<Sender<DiaryEvent>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new subscriber that receives every event published from now on
+    pub fn subscribe(&self) -> Receiver<DiaryEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().push(sender);
+        receiver
+    }
+
+    /// Whether anybody is listening; lets publishers skip building events
+    pub fn has_subscribers(&self) -> bool {
+        !self.subscribers.lock().is_empty()
+    }
+
+    pub fn publish(&self, event: DiaryEvent) {
+        self.subscribers
+            .lock()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fan_out_and_pruning() {
+        let bus = EventBus::new();
+        assert!(!bus.has_subscribers());
+
+        let first = bus.subscribe();
+        let second = bus.subscribe();
+        let event = DiaryEvent::FileSkipped { path: PathBuf::from("x.md"), reason: "empty".to_string() };
+        bus.publish(event.clone());
+
+        assert_eq!(first.try_recv().unwrap(), event);
+        assert_eq!(second.try_recv().unwrap(), event);
+
+        drop(second);
+        bus.publish(event);
+        assert_eq!(bus.subscribers.lock().len(), 1);
+    }
+}
@@ -0,0 +1,320 @@
+//! C bindings for apps embedding the diary, such as an iOS or Android
+//! companion writing to the same database format as the command line tool.
+//! Built with the `ffi` feature; `include/rusty_diary.h` declares them.
+//!
+//! Every call returns 0 on success or the failing `ErrorKind` code, and
+//! `rusty_diary_last_error` describes the last failure on the calling
+//! thread. A panic never unwinds into the caller; it is reported as
+//! `PANIC` instead. Strings cross the boundary as NUL-terminated UTF-8, dates as
+//! `YYYY-MM-DD`, and results as JSON; strings handed out must be released
+//! with `rusty_diary_free_string`.
+
+use std::cell::RefCell;
+use std::ffi::{c_char, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::clock::{Clock, SystemClock};
+use crate::dates::DateRange;
+use crate::diary::processor::Normalization;
+use crate::error::{Result, RustyDiaryError};
+use crate::storage::{DiaryEntry, RunStats, StorageManager};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// An open diary database
+pub struct DiaryHandle {
+    storage: StorageManager,
+    /// Stamps captured entries and the runs storing them
+    clock: Arc<dyn Clock>,
+}
+
+/// Returned when a call panicked, which is a bug in the library; the
+/// handle it was given should not be used again
+pub const PANIC: i32 = -1;
+
+/// Runs `call`, recording its error or panic for `rusty_diary_last_error`
+fn status(call: impl FnOnce() -> Result<()>) -> i32 {
+    let (code, error) = match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(Ok(())) => (0, None),
+        Ok(Err(e)) => (i32::from(e.code()), Some(format!("{} {}", e.kind(), e))),
+        Err(payload) => (PANIC, Some(format!("panic {}", panic_message(payload.as_ref())))),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = error);
+    code
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("without a message")
+}
+
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn argument<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(RustyDiaryError::InvalidConfig(format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| RustyDiaryError::InvalidConfig(format!("{} is not valid UTF-8", name)))
+}
+
+/// # Safety
+/// `ptr` must be null or point to a NUL-terminated string.
+unsafe fn date_argument(ptr: *const c_char, name: &str) -> Result<NaiveDate> {
+    Ok(NaiveDate::parse_from_str(argument(ptr, name)?, "%Y-%m-%d")?)
+}
+
+/// # Safety
+/// `handle` must be null or come from `rusty_diary_open` and not be closed.
+unsafe fn handle<'a>(handle: *mut DiaryHandle) -> Result<&'a DiaryHandle> {
+    handle.as_ref().ok_or_else(|| RustyDiaryError::InvalidConfig("handle is null".to_string()))
+}
+
+/// # Safety
+/// `out` must be null or valid for writes.
+unsafe fn write_json(out: *mut *mut c_char, value: &impl Serialize) -> Result<()> {
+    if out.is_null() {
+        return Err(RustyDiaryError::InvalidConfig("output pointer is null".to_string()));
+    }
+    let json = CString::new(serde_json::to_string(value)?)
+        .map_err(|_| RustyDiaryError::ContentIntegrity("result contains a NUL byte".to_string()))?;
+    *out = json.into_raw();
+    Ok(())
+}
+
+/// Stores one entry typed into the app as a run of its own, unless the day
+/// already has the same text. Returns the run's exec_version, or 0.
+fn capture(diary: &DiaryHandle, date: NaiveDate, content: &str) -> Result<i64> {
+    let storage = &diary.storage;
+    let normalization = Normalization::default();
+    let content = normalization.apply(content);
+    if content.trim().is_empty() {
+        return Err(RustyDiaryError::ContentIntegrity("Empty content".to_string()));
+    }
+    let entry = DiaryEntry::new(0, date, content, diary.clock.now());
+    if storage.has_content(date, &entry.content_hash())? {
+        return Ok(0);
+    }
+
+    let exec_version = storage.start_sync_run(normalization.as_str())?;
+    storage.store_entries(vec![DiaryEntry { exec_version, ..entry }])?;
+    storage.finish_sync_run(exec_version, &RunStats { stored: 1, ..RunStats::default() })?;
+    Ok(exec_version)
+}
+
+/// Opens the database at `db_path`, creating and migrating it as needed,
+/// and stores a handle to it in `out`.
+///
+/// # Safety
+/// `db_path` must be a NUL-terminated string and `out` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_open(db_path: *const c_char, out: *mut *mut DiaryHandle) -> i32 {
+    status(|| {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let storage = StorageManager::new(argument(db_path, "db_path")?)?.with_clock(clock.clone());
+        if out.is_null() {
+            return Err(RustyDiaryError::InvalidConfig("output pointer is null".to_string()));
+        }
+        *out = Box::into_raw(Box::new(DiaryHandle { storage, clock }));
+        Ok(())
+    })
+}
+
+/// Closes a handle from `rusty_diary_open`. Null is ignored.
+///
+/// # Safety
+/// `handle` must be null or an open handle; it must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_close(handle: *mut DiaryHandle) {
+    if !handle.is_null() {
+        status(|| {
+            drop(Box::from_raw(handle));
+            Ok(())
+        });
+    }
+}
+
+/// Stores `content` as the entry for `date` and writes its exec_version to
+/// `exec_version`, or 0 when the day already has this text.
+///
+/// # Safety
+/// `handle` must be open, `date` and `content` NUL-terminated strings, and
+/// `exec_version` null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_capture(
+    handle: *mut DiaryHandle,
+    date: *const c_char,
+    content: *const c_char,
+    exec_version: *mut i64,
+) -> i32 {
+    status(|| {
+        let stored = capture(self::handle(handle)?, date_argument(date, "date")?, argument(content, "content")?)?;
+        if let Some(out) = exec_version.as_mut() {
+            *out = stored;
+        }
+        Ok(())
+    })
+}
+
+/// Writes the latest version of each day from `start` to `end`, newest
+/// first, to `json` as an array of entries.
+///
+/// # Safety
+/// `handle` must be open, `start` and `end` NUL-terminated strings, and
+/// `json` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_query(
+    handle: *mut DiaryHandle,
+    start: *const c_char,
+    end: *const c_char,
+    json: *mut *mut c_char,
+) -> i32 {
+    status(|| {
+        let entries = self::handle(handle)?
+            .storage
+            .latest_entries(date_argument(start, "start")?, date_argument(end, "end")?)?;
+        write_json(json, &entries)
+    })
+}
+
+/// Writes statistics over every stored entry to `json`.
+///
+/// # Safety
+/// `handle` must be open and `json` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_stats(handle: *mut DiaryHandle, json: *mut *mut c_char) -> i32 {
+    status(|| write_json(json, &self::handle(handle)?.storage.stats_in(DateRange::all())?))
+}
+
+/// The last failure on this thread, as `E002 database Database error: ...`,
+/// or null. Release it with `rusty_diary_free_string`.
+#[no_mangle]
+pub extern "C" fn rusty_diary_last_error() -> *mut c_char {
+    LAST_ERROR
+        .with(|last| last.borrow().clone())
+        .and_then(|message| CString::new(message).ok())
+        .map_or(ptr::null_mut(), CString::into_raw)
+}
+
+/// Releases a string handed out by these bindings. Null is ignored.
+///
+/// # Safety
+/// `string` must be null or come from these bindings, and be freed once.
+#[no_mangle]
+pub unsafe extern "C" fn rusty_diary_free_string(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::error::ErrorKind;
+    use tempfile::TempDir;
+
+    const HEADER: &str = include_str!("../include/rusty_diary.h");
+
+    fn take_string(string: *mut c_char) -> String {
+        assert!(!string.is_null());
+        unsafe {
+            let value = CStr::from_ptr(string).to_str().unwrap().to_string();
+            rusty_diary_free_string(string);
+            value
+        }
+    }
+
+    #[test]
+    fn test_open_capture_query_stats() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = CString::new(temp_dir.path().join("phone.db").to_string_lossy().as_ref()).unwrap();
+        let date = CString::new("2024-03-05").unwrap();
+        let content = CString::new("Typed on the train").unwrap();
+
+        unsafe {
+            let mut diary = ptr::null_mut();
+            assert_eq!(rusty_diary_open(db_path.as_ptr(), &mut diary), 0);
+
+            let mut exec_version = -1;
+            assert_eq!(rusty_diary_capture(diary, date.as_ptr(), content.as_ptr(), &mut exec_version), 0);
+            assert!(exec_version > 0);
+            assert_eq!(rusty_diary_capture(diary, date.as_ptr(), content.as_ptr(), &mut exec_version), 0);
+            assert_eq!(exec_version, 0);
+
+            let mut json = ptr::null_mut();
+            let (start, end) = (CString::new("2024-03-01").unwrap(), CString::new("2024-03-31").unwrap());
+            assert_eq!(rusty_diary_query(diary, start.as_ptr(), end.as_ptr(), &mut json), 0);
+            let entries: Vec<DiaryEntry> = serde_json::from_str(&take_string(json))?;
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].content, "Typed on the train");
+
+            assert_eq!(rusty_diary_stats(diary, &mut json), 0);
+            assert!(take_string(json).contains(r#""days":1"#));
+            assert!(rusty_diary_last_error().is_null());
+
+            let bad_date = CString::new("5 March").unwrap();
+            let code = rusty_diary_capture(diary, bad_date.as_ptr(), content.as_ptr(), ptr::null_mut());
+            assert_eq!(code, i32::from(ErrorKind::DateParse.code()));
+            assert!(take_string(rusty_diary_last_error()).starts_with("E003 date_parse "));
+            assert_eq!(rusty_diary_stats(ptr::null_mut(), &mut json), i32::from(ErrorKind::InvalidConfig.code()));
+
+            rusty_diary_close(diary);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_capture_is_stamped_by_the_handle_clock() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let now = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap().and_hms_opt(8, 15, 0).unwrap();
+        let clock: Arc<dyn Clock> = Arc::new(FixedClock(now));
+        let storage = StorageManager::new(temp_dir.path().join("phone.db"))?.with_clock(clock.clone());
+        let diary = DiaryHandle { storage, clock };
+
+        let date = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap();
+        let exec_version = capture(&diary, date, "Written the morning after")?;
+        let entry = diary.storage.latest_entry(date)?.unwrap();
+        assert_eq!((entry.exec_version, entry.created_at, entry.updated_at), (exec_version, now, Some(now)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_panics_are_reported_not_unwound() {
+        assert_eq!(status(|| panic!("index out of bounds")), PANIC);
+        assert_eq!(take_string(rusty_diary_last_error()), "panic index out of bounds");
+        assert_eq!(status(|| panic!("{} is gone", "handle")), PANIC);
+        assert_eq!(take_string(rusty_diary_last_error()), "panic handle is gone");
+        assert_eq!(status(|| Ok(())), 0);
+        assert!(rusty_diary_last_error().is_null());
+    }
+
+    #[test]
+    fn test_header_matches_bindings() {
+        for function in [
+            "rusty_diary_open(", "rusty_diary_close(", "rusty_diary_capture(", "rusty_diary_query(",
+            "rusty_diary_stats(", "rusty_diary_last_error(", "rusty_diary_free_string(",
+        ] {
+            assert!(HEADER.contains(function), "{} is not declared", function);
+        }
+        for kind in [
+            ErrorKind::Io, ErrorKind::Database, ErrorKind::DateParse, ErrorKind::ContentIntegrity,
+            ErrorKind::Serialization, ErrorKind::SchemaTooNew, ErrorKind::IncompatibleSchema, ErrorKind::InvalidConfig,
+        ] {
+            let define = format!("#define RUSTY_DIARY_E_{} {}\n", kind.as_str().to_uppercase(), kind.code());
+            assert!(HEADER.contains(&define), "missing {}", define.trim());
+        }
+        assert!(HEADER.contains(&format!("#define RUSTY_DIARY_E_PANIC ({})\n", PANIC)));
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+use crate::error::{Result, RustyDiaryError};
+use crate::reminder::ReminderReason;
+use crate::storage::DiaryEntry;
+
+/// Points in the diary lifecycle where hooks fire
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookEvent {
+    /// Before any file is collected. A failing hook aborts the sync.
+    PreSync,
+    /// After each new entry has been committed to the database
+    EntryStored,
+    /// After a journal file has been written
+    JournalWritten,
+    /// When a due reminder finds the day unwritten
+    Reminder,
+}
+
+impl HookEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookEvent::PreSync => "pre-sync",
+            HookEvent::EntryStored => "entry-stored",
+            HookEvent::JournalWritten => "journal-written",
+            HookEvent::Reminder => "reminder",
+        }
+    }
+}
+
+/// Data handed to a hook when it fires
+#[derive(Debug, Clone, Copy)]
+pub enum HookPayload<'a> {
+    PreSync { directory: &'a Path },
+    EntryStored { entry: &'a DiaryEntry },
+    JournalWritten { path: &'a Path },
+    Reminder { date: NaiveDate, reason: &'a ReminderReason },
+}
+
+impl HookPayload<'_> {
+    pub fn event(&self) -> HookEvent {
+        match self {
+            HookPayload::PreSync { .. } => HookEvent::PreSync,
+            HookPayload::EntryStored { .. } => HookEvent::EntryStored,
+            HookPayload::JournalWritten { .. } => HookEvent::JournalWritten,
+            HookPayload::Reminder { .. } => HookEvent::Reminder,
+        }
+    }
+}
+
+pub type HookCallback = Arc<dyn Fn(&HookPayload<'_>) -> Result<()> + Send + Sync>;
+
+/// Something to run when a lifecycle event fires
+#[derive(Clone)]
+pub enum Hook {
+    /// Shell command. Receives `RUSTY_DIARY_EVENT` plus event specific
+    /// variables, and the stored entry as JSON on stdin for `EntryStored`.
+    Command(String),
+    /// In-process callback registered by library users
+    Callback(HookCallback),
+}
+
+impl Hook {
+    pub fn command<S: Into<String>>(command: S) -> Self {
+        Hook::Command(command.into())
+    }
+
+    pub fn callback<F>(callback: F) -> Self
+    where
+        F: Fn(&HookPayload<'_>) -> Result<()> + Send + Sync + 'static,
+    {
+        Hook::Callback(Arc::new(callback))
+    }
+
+    fn run(&self, payload: &HookPayload<'_>) -> Result<()> {
+        match self {
+            Hook::Callback(callback) => callback(payload),
+            Hook::Command(command) => run_command(command, payload),
+        }
+    }
+}
+
+impl fmt::Debug for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hook::Command(command) => f.debug_tuple("Command").field(command).finish(),
+            Hook::Callback(_) => f.write_str("Callback(..)"),
+        }
+    }
+}
+
+/// Hooks registered per lifecycle event
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    pre_sync: Vec<Hook>,
+    entry_stored: Vec<Hook>,
+    journal_written: Vec<Hook>,
+    reminder: Vec<Hook>,
+}
+
+impl Hooks {
+    pub fn register(&mut self, event: HookEvent, hook: Hook) {
+        match event {
+            HookEvent::PreSync => self.pre_sync.push(hook),
+            HookEvent::EntryStored => self.entry_stored.push(hook),
+            HookEvent::JournalWritten => self.journal_written.push(hook),
+            HookEvent::Reminder => self.reminder.push(hook),
+        }
+    }
+
+    pub fn is_empty(&self, event: HookEvent) -> bool {
+        self.for_event(event).is_empty()
+    }
+
+    /// Runs every hook for the payload's event in registration order.
+    /// Pre-sync failures are returned so the sync can abort; failures of
+    /// later hooks are only logged since the data is already committed.
+    pub fn fire(&self, payload: HookPayload<'_>) -> Result<()> {
+        let event = payload.event();
+
+        for hook in self.for_event(event) {
+            if let Err(e) = hook.run(&payload) {
+                if event == HookEvent::PreSync {
+                    return Err(e);
+                }
+                tracing::warn!(event = event.name(), "Hook failed: {}", e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn for_event(&self, event: HookEvent) -> &[Hook] {
+        match event {
+            HookEvent::PreSync => &self.pre_sync,
+            HookEvent::EntryStored => &self.entry_stored,
+            HookEvent::JournalWritten => &self.journal_written,
+            HookEvent::Reminder => &self.reminder,
+        }
+    }
+}
+
+/// The command run by the platform's shell
+pub(crate) fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+fn run_command(command: &str, payload: &HookPayload<'_>) -> Result<()> {
+    let mut process = shell(command);
+    process.env("RUSTY_DIARY_EVENT", payload.event().name());
+
+    let stdin = match payload {
+        HookPayload::PreSync { directory } => {
+            process.env("RUSTY_DIARY_DIRECTORY", directory);
+            None
+        }
+        HookPayload::EntryStored { entry } => {
+            process.env("RUSTY_DIARY_DATE", entry.date.to_string());
+            Some(serde_json::to_vec(entry)?)
+        }
+        HookPayload::JournalWritten { path } => {
+            process.env("RUSTY_DIARY_JOURNAL", path);
+            None
+        }
+        HookPayload::Reminder { date, reason } => {
+            process.env("RUSTY_DIARY_DATE", date.to_string());
+            process.env("RUSTY_DIARY_REMINDER", reason.to_string());
+            None
+        }
+    };
+
+    process.stdin(if stdin.is_some() { Stdio::piped() } else { Stdio::null() });
+    let mut child = process.spawn()?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        // A hook that ignores its input closes the pipe early; that's fine
+        let _ = pipe.write_all(&input);
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(RustyDiaryError::HookFailed(format!(
+            "{} hook `{}` exited with {}", payload.event().name(), command, status
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_callbacks_and_failure_policy() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut hooks = Hooks::default();
+
+        let counter = calls.clone();
+        hooks.register(HookEvent::JournalWritten, Hook::callback(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            Err(RustyDiaryError::HookFailed("boom".to_string()))
+        }));
+        hooks.register(HookEvent::PreSync, Hook::callback(|_| {
+            Err(RustyDiaryError::HookFailed("not now".to_string()))
+        }));
+
+        // Post hooks only warn; pre-sync failures propagate
+        assert!(hooks.fire(HookPayload::JournalWritten { path: Path::new("log.md") }).is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(hooks.fire(HookPayload::PreSync { directory: Path::new(".") }).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_command_receives_entry_on_stdin() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        let out = temp_dir.path().join("out.json");
+        let hook = Hook::command(format!("cat > '{}'", out.display()));
+
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let entry = DiaryEntry::new(1, date, "Hooked".to_string(), SystemClock.now());
+        hook.run(&HookPayload::EntryStored { entry: &entry })?;
+
+        let received: DiaryEntry = serde_json::from_slice(&std::fs::read(&out)?)?;
+        assert_eq!(received, entry);
+
+        assert!(Hook::command("exit 3").run(&HookPayload::PreSync { directory: Path::new(".") }).is_err());
+        Ok(())
+    }
+}
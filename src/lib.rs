@@ -1,12 +1,32 @@
+#[cfg(feature = "sqlite")]
+pub mod backup;
+pub mod cancel;
+pub mod clock;
 pub mod config;
+pub mod dates;
 pub mod error;
 pub mod storage;
 pub mod diary;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod hooks;
+pub mod metrics;
+pub mod pipeline;
+pub mod progress;
+pub mod reminder;
+#[cfg(feature = "sqlite")]
+pub mod setup;
 
 // Re-export the essential types, like stars made visible
+pub use cancel::CancellationToken;
+pub use clock::{Clock, FixedClock, SystemClock};
 pub use config::Config;
-pub use error::RustyDiaryError;
+pub use error::{ErrorKind, RustyDiaryError};
+#[cfg(feature = "sqlite")]
 pub use diary::RustyDiary;
+pub use events::DiaryEvent;
+pub use progress::{NoProgress, ProgressSink};
 
 // Version whispers
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
\ No newline at end of file
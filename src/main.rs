@@ -1,96 +1,2850 @@
+mod cli;
+
 use anyhow::Context;
-use rusty_diary::{Config, RustyDiary};
-use std::path::PathBuf;
+use indicatif::{ProgressBar, ProgressStyle};
+use parking_lot::Mutex;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use rusty_diary::{CancellationToken, Clock, Config, FixedClock, ProgressSink, RustyDiary, RustyDiaryError, SystemClock};
+use rusty_diary::backup;
+use rusty_diary::dates::{self, DateRange};
+use rusty_diary::diary::{JournalReport, SyncReport};
+use rusty_diary::diary::file::PathDates;
+use rusty_diary::diary::flavor::MarkdownFlavor;
+use rusty_diary::diary::highlight::{self, Highlighter};
+use rusty_diary::diary::html::{self, AttachmentMode, CommandRenderer, HtmlExporter};
+use rusty_diary::diary::import::{ImportConflict, ImportPreview};
+use rusty_diary::diary::links::LinkProblem;
+use rusty_diary::diary::locale::{DateLocale, SecondaryCalendar};
+use rusty_diary::diary::processor::Normalization;
+use rusty_diary::diary::saved;
+use rusty_diary::diary::split::JournalSplit;
+use rusty_diary::diary::style::{StyleRule, StyleRules};
+use rusty_diary::diary::template::JournalTemplate;
+use rusty_diary::hooks::{Hook, HookEvent};
+use rusty_diary::metrics::SyncMetrics;
+use rusty_diary::reminder::Reminder;
+use rusty_diary::setup::{self, Status};
+use rusty_diary::storage::{
+    self, migrations, EntryQuery, MatchMode, Ranking, SearchSettings, SqliteSettings, StemLanguage, WriteQueue,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::ffi::OsString;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use structopt::clap::Shell;
 use structopt::StructOpt;
-use tracing::info;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{debug, info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[derive(StructOpt, Debug)]
+#[structopt(
+    name = "rusty_diary",
+    about = "A markdown diary with SQLite persistence",
+    author,
+    after_help = "Dates may be YYYY-MM-DD or expressions such as 'yesterday', 'last monday', '2 weeks ago', \
+                  'march', 'last month', '2023-Q2' or '2024-W12'. A period starts --from at its first day \
+                  and ends --to at its last."
+)]
+struct Cli {
+    /// Directory containing markdown files
+    #[structopt(parse(from_os_str))]
+    directory: Option<PathBuf>,
+
+    /// Database file path
+    #[structopt(long, parse(from_os_str))]
+    db: Option<PathBuf>,
+
+    /// Custom date pattern for files
+    #[structopt(long)]
+    date_pattern: Option<String>,
+
+    /// Verbosity level
+    #[structopt(short, long, parse(from_occurrences))]
+    verbose: usize,
+
+    /// Only report warnings (-q) or errors (-qq)
+    #[structopt(short, long, parse(from_occurrences))]
+    quiet: usize,
+
+    /// Log format: text or json
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    log_format: String,
+
+    /// Output format for command results: text or json
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    output: String,
+
+    /// Fail with a non-zero exit code when files are skipped or cannot be cleaned up
+    #[structopt(long)]
+    strict: bool,
+
+    /// Show what a sync would store, file by file, with the day, tags, attachments and conflicts
+    /// with stored entries of each, changing nothing
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Store new entry content uncompressed
+    #[structopt(long)]
+    no_compress: bool,
+
+    /// Shell command to run before syncing; a failure aborts the sync
+    #[structopt(long)]
+    pre_sync_hook: Vec<String>,
+
+    /// Shell command to run for each stored entry (entry JSON on stdin)
+    #[structopt(long)]
+    entry_hook: Vec<String>,
+
+    /// Shell command to run after the journal is written
+    #[structopt(long)]
+    journal_hook: Vec<String>,
+
+    /// Shell command to run when the daemon's --remind-at reminder finds the day unwritten
+    #[structopt(long)]
+    reminder_hook: Vec<String>,
+
+    /// SQLite durability profile: default, safe or fast
+    #[structopt(long, possible_values = SqliteSettings::PROFILES)]
+    sqlite_profile: Option<SqliteSettings>,
+
+    /// Match search words by their stem in this language (repeatable)
+    #[structopt(long, number_of_values = 1, possible_values = StemLanguage::NAMES)]
+    search_stemmer: Vec<StemLanguage>,
+
+    /// Match search words regardless of accents, e.g. coracao finds coração
+    #[structopt(long)]
+    search_fold_diacritics: bool,
+
+    /// Word to leave out of search queries (repeatable)
+    #[structopt(long, number_of_values = 1)]
+    search_stopword: Vec<String>,
+
+    /// Theme for code blocks in html exports, show, browse and find (needs the highlight feature)
+    #[structopt(long)]
+    highlight_theme: Option<String>,
+
+    /// Markdown dialect of the entries, for html exports, show, browse and find: commonmark or obsidian
+    #[structopt(long, default_value = "commonmark", possible_values = MarkdownFlavor::NAMES)]
+    markdown_flavor: MarkdownFlavor,
+
+    /// Directory to load parser/exporter plugins from
+    #[structopt(long, parse(from_os_str))]
+    plugin_dir: Option<PathBuf>,
+
+    /// Directory with journal templates (header.j2, entry_header.j2, separator.j2, footer.j2)
+    #[structopt(long, parse(from_os_str))]
+    journal_template: Option<PathBuf>,
+
+    /// Folder levels to search for diary files; 1 is the diary directory only
+    #[structopt(long, default_value = "1")]
+    max_depth: usize,
+
+    /// How year/month folders relate to file dates: ignore, verify or derive
+    #[structopt(long, default_value = "ignore", possible_values = PathDates::NAMES)]
+    path_dates: PathDates,
+
+    /// Leave files modified less than this many seconds ago for a later run
+    #[structopt(long, default_value = "0")]
+    min_file_age: u64,
+
+    /// Text normalization for new entries: lf (line endings only) or nfc (also Unicode NFC)
+    #[structopt(long, default_value = "lf", possible_values = Normalization::NAMES)]
+    normalize: Normalization,
+
+    /// Read files with invalid UTF-8 by replacing the bad bytes; such files are kept, not removed
+    #[structopt(long)]
+    lossy_utf8: bool,
+
+    /// Split files into one entry per heading made of a date alone, e.g. "## 2024-03-04"
+    #[structopt(long)]
+    split_date_headings: bool,
+
+    /// Attach images dropped in the directory (e.g. 2024-03-05-1.jpg) to their day's entry, filing them under attachments/
+    #[structopt(long)]
+    photos: bool,
+
+    /// Store suggested tags for new entries, marked as automatic
+    #[structopt(long)]
+    auto_tag: bool,
+
+    /// Lowest confidence (0 to 1) of a tag applied by --auto-tag
+    #[structopt(long, default_value = "0.3")]
+    auto_tag_threshold: f64,
+
+    /// Credit synced entries to this author unless their frontmatter has an "author:" line
+    #[structopt(long)]
+    default_author: Option<String>,
+
+    /// Stamp new entries with this location, shown in journals with the weather (needs the `stamp` feature)
+    #[structopt(long)]
+    location: Option<String>,
+
+    /// Store the number and subjects of the commits made on each new entry's day in this git repository;
+    /// repeatable
+    #[structopt(long, parse(from_os_str))]
+    git_repo: Vec<PathBuf>,
+
+    /// Track this habit from checklist items such as "- [x] meditate"; repeatable
+    #[structopt(long)]
+    habit: Vec<String>,
+
+    /// Define a saved search as NAME=QUERY, e.g. 'work-frustrations=tag:work AND text:"frustrated"'; repeatable
+    #[structopt(long)]
+    saved_search: Vec<String>,
+
+    /// Skip files matching this gitignore-style pattern; repeatable, adds to .diaryignore
+    #[structopt(long)]
+    ignore: Vec<String>,
+
+    /// Keep one journal file, relative to the diary directory, updated in place
+    #[structopt(long, parse(from_os_str))]
+    journal_file: Option<PathBuf>,
+
+    /// Journal file name, e.g. "{prefix}-{start}-{end}.md" or "{year}/{month}.md"
+    #[structopt(long)]
+    output_filename: Option<String>,
+
+    /// Add a table of contents and a tag index to written journals
+    #[structopt(long)]
+    journal_toc: bool,
+
+    /// Locale for weekday and month names in written journals, e.g. pt_PT
+    #[structopt(long, default_value = "en_US", parse(try_from_str = DateLocale::parse_locale))]
+    locale: chrono::Locale,
+
+    /// First day of the week in written journals
+    #[structopt(long, default_value = "monday")]
+    first_weekday: chrono::Weekday,
+
+    /// Also show each entry's date in this calendar: hebrew or hijri
+    #[structopt(long, possible_values = SecondaryCalendar::NAMES)]
+    secondary_calendar: Option<SecondaryCalendar>,
+
+    /// Keep one journal file per period instead of a new file per run: none, month or year
+    #[structopt(long, default_value = "none", possible_values = JournalSplit::NAMES)]
+    journal_split: JournalSplit,
+
+    /// Run as if it were this moment (YYYY-MM-DD or YYYY-MM-DDTHH:MM[:SS]), for dates such as
+    /// 'yesterday', entry and run timestamps, journal names and backups; reproduces a run
+    #[structopt(long, parse(try_from_str = parse_now))]
+    now: Option<NaiveDateTime>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+/// The clock of this run: the moment `--now` gives, or the system clock.
+/// It is set before the arguments are converted, so date expressions
+/// resolve against it too.
+static CLOCK: OnceLock<FixedClock> = OnceLock::new();
+
+fn clock() -> &'static dyn Clock {
+    match CLOCK.get() {
+        Some(clock) => clock,
+        None => &SystemClock,
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Create the diary directory, its .diaryignore and .diarysearches, and the database
+    Init,
+
+    /// Check the directory, patterns and database for problems, changing nothing
+    Doctor,
+
+    /// Database maintenance commands
+    Db(DbCommand),
+
+    /// Compressed, optionally age-encrypted snapshots of the database and attachments
+    Backup(BackupCommand),
+
+    /// Rebuild the diary as it stood on a past day into another folder, leaving the live one untouched
+    Restore {
+        /// Day to go back to (YYYY-MM-DD or an expression); versions stored by runs started later are left out
+        #[structopt(long, parse(try_from_str = parse_end))]
+        as_of: NaiveDate,
+
+        /// Folder for the rebuilt database and journal
+        #[structopt(long, parse(from_os_str))]
+        into: PathBuf,
+    },
+
+    /// Import entries from another app: `dayone` reads a Day One JSON export, `markdown` a folder of
+    /// notes named by their day, and `auto` picks the first importer that recognizes the source
+    Import {
+        /// Importer name, or "auto"
+        format: String,
+
+        /// Export file or folder to import
+        #[structopt(parse(from_os_str))]
+        source: PathBuf,
+
+        /// Show each item with its day, tags, attachments and conflicts, changing nothing
+        #[structopt(long)]
+        dry_run: bool,
+    },
+
+    /// Export every stored version to an archive and import it into a scratch database, checking
+    /// that entries, their days' stars, metadata and tags, and attachments come back the same
+    VerifyRoundtrip,
+
+    /// Wiki-link graph between entries
+    Graph(GraphCommand),
+
+    /// Consistency checks over stored entries
+    Check(CheckCommand),
+
+    /// Files in the attachments folder, such as images filed by --photos
+    Attachments(AttachmentsCommand),
+
+    /// Words this diary's spell checks accept beyond the dictionary
+    Dictionary(DictionaryCommand),
+
+    /// People mentioned in entries, as @name or capitalized names inside sentences
+    People(PeopleCommand),
+
+    /// Life events marked in entries with `!event: text`; `export timeline` writes them out
+    Events(EventsCommand),
+
+    /// Tags suggested for and stored with entries
+    Tags(TagsCommand),
+
+    /// Custom key/value metadata of days, e.g. from a mood or location app
+    Meta(MetaCommand),
+
+    /// History of sync runs and what each one did
+    Runs(RunsCommand),
+
+    /// Write a review of past entries as today's entry and open it in $EDITOR
+    Review(ReviewCommand),
+
+    /// List the days whose latest entry links to a date with [[YYYY-MM-DD]]
+    Backlinks {
+        /// Linked day (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+    },
+
+    /// Show the highlights due for review and schedule their next one: marked with ==text== or a
+    /// `> !review` quote, a highlight comes back a week after its day, then a month and a year on
+    Resurface {
+        /// Show at most this many highlights
+        #[structopt(long)]
+        limit: Option<usize>,
+
+        /// Show the due highlights without marking them reviewed
+        #[structopt(long)]
+        peek: bool,
+    },
+
+    /// Star a day to revisit it; see --starred and the favorites in `stats`
+    Pin {
+        /// Day to star (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+    },
+
+    /// Remove a day's star
+    Unpin {
+        /// Day to unstar (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+    },
+
+    /// Move old entries out of the live database into a compressed archive
+    Archive {
+        /// Archive every entry dated before this day (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        before: NaiveDate,
+    },
+
+    /// Restore archived entries back into the live database
+    Unarchive {
+        /// First day to restore (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: NaiveDate,
+
+        /// Last day to restore (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: NaiveDate,
+    },
+
+    /// List stored entries, newest first
+    List {
+        /// First day to list (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to list (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Only starred days
+        #[structopt(long)]
+        starred: bool,
+    },
+
+    /// Show totals over all stored entries
+    Stats {
+        /// Report to show instead of the totals: habits (streaks and monthly completion of each --habit)
+        /// or terms (the most used words, e.g. for a word cloud)
+        #[structopt(possible_values = &["habits", "terms"])]
+        report: Option<String>,
+
+        /// First day to count (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to count (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Count one month (YYYY-MM or an expression, e.g. "last month") instead of --from/--to
+        #[structopt(long, conflicts_with_all = &["from", "to"], parse(try_from_str = parse_period))]
+        month: Option<DateRange>,
+
+        /// Terms to list in the terms report
+        #[structopt(long, default_value = "50")]
+        limit: usize,
+
+        /// Count inflections of a word (walk, walked, walking) as one term in the terms report
+        #[structopt(long)]
+        stem: bool,
+
+        /// Show totals for each author
+        #[structopt(long, conflicts_with = "report")]
+        by_author: bool,
+    },
+
+    /// Find entries whose latest version contains the text (case-insensitive)
+    Search {
+        #[structopt(required_unless_one = &["saved", "dsl"])]
+        query: Option<String>,
+
+        /// Run a saved search from .diarysearches or --saved-search instead
+        #[structopt(long, conflicts_with_all = &["query", "dsl"])]
+        saved: Option<String>,
+
+        /// Search with the query language instead, e.g. 'tag:work after:2024-01-01 "review" -tag:meeting'
+        #[structopt(long = "query", value_name = "QUERY", conflicts_with = "query")]
+        dsl: Option<String>,
+
+        /// Treat the query as a case-sensitive regular expression, e.g. 'PROJ-\d+'
+        #[structopt(long, conflicts_with_all = &["saved", "dsl"])]
+        regex: bool,
+
+        /// Seconds a --regex search may run before giving up
+        #[structopt(long, default_value = "10")]
+        timeout: u64,
+
+        /// First day to search (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to search (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Words of context around each match
+        #[structopt(long, default_value = "5")]
+        context: usize,
+
+        /// Only starred days
+        #[structopt(long)]
+        starred: bool,
+
+        /// Match the query's words next to each other, in order
+        #[structopt(long, conflicts_with_all = &["regex", "any-term"])]
+        phrase: bool,
+
+        /// Match entries with any of the query's words
+        #[structopt(long, conflicts_with = "regex")]
+        any_term: bool,
+
+        /// Order hits by relevance (BM25) instead of newest first
+        #[structopt(long, conflicts_with = "regex")]
+        rank: bool,
+
+        /// With --rank, how much a word in an entry's first line counts
+        #[structopt(long, default_value = "3")]
+        title_weight: f64,
+
+        /// With --rank, how much a word among an entry's tags counts
+        #[structopt(long, default_value = "2")]
+        tags_weight: f64,
+
+        /// With --rank, how much a word in an entry's body counts
+        #[structopt(long, default_value = "1")]
+        body_weight: f64,
+
+        /// With --rank, how much more recent entries score, e.g. 1 for up to twice as much
+        #[structopt(long, default_value = "0")]
+        recency_boost: f64,
+
+        /// When to use colors: auto, always or never
+        #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+        color: String,
+    },
+
+    /// Show the latest entry for a date, rendered for the terminal
+    Show {
+        /// Day to show (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+
+        /// Print the stored markdown unchanged
+        #[structopt(long)]
+        raw: bool,
+
+        /// Show every stored version, newest first
+        #[structopt(long)]
+        all_versions: bool,
+
+        /// Most related days listed after the entry; 0 lists none
+        #[structopt(long, default_value = "3")]
+        related: usize,
+
+        /// When to use colors: auto, always or never
+        #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+        color: String,
+    },
+
+    /// Browse the journal in a terminal UI
+    #[cfg(feature = "tui")]
+    Browse,
+
+    /// Fuzzy-pick an entry and show it in $PAGER
+    #[cfg(feature = "tui")]
+    Find {
+        /// Initial filter text
+        #[structopt(default_value = "")]
+        query: String,
+
+        /// Open the pick in $EDITOR instead; saved changes are stored on the next sync
+        #[structopt(long)]
+        edit: bool,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        #[structopt(possible_values = &Shell::variants(), case_insensitive = true)]
+        shell: Shell,
+    },
+
+    /// Keep running and sync on a fixed interval
+    Daemon {
+        /// Seconds between syncs
+        #[structopt(long, default_value = "3600")]
+        interval: u64,
+
+        /// Serve Prometheus metrics on this address, e.g. 127.0.0.1:9898
+        #[structopt(long)]
+        metrics_addr: Option<SocketAddr>,
+
+        /// From this time (HH:MM) on, fire the reminder hooks once a day if today has no entry
+        #[structopt(long, parse(try_from_str = parse_time))]
+        remind_at: Option<NaiveTime>,
+
+        /// Also remind when today's entry has fewer words than this
+        #[structopt(long, requires = "remind-at")]
+        word_goal: Option<usize>,
+    },
+
+    /// Export stored entries through a built-in or plugin export target
+    Export {
+        /// Export target, e.g. json, quotes, timeline, html or sqlite
+        target: String,
+
+        /// Destination file
+        #[structopt(long, parse(from_os_str))]
+        out: PathBuf,
+
+        /// Only entries matching this saved search
+        #[structopt(long)]
+        saved: Option<String>,
+
+        /// Only entries matching this query, e.g. 'tag:travel after:2019 -tag:work'
+        #[structopt(long = "query", value_name = "QUERY", conflicts_with = "saved")]
+        dsl: Option<String>,
+
+        /// First day to export (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to export (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Only entries with this #tag; repeat to require several
+        #[structopt(long)]
+        tag: Vec<String>,
+
+        /// Only entries with at least this many words
+        #[structopt(long)]
+        min_words: Option<usize>,
+
+        /// Only entries with at most this many words
+        #[structopt(long)]
+        max_words: Option<usize>,
+
+        /// Only entries by this author
+        #[structopt(long)]
+        author: Option<String>,
+
+        /// Only starred days
+        #[structopt(long)]
+        starred: bool,
+
+        /// Only days whose metadata field KEY is VALUE; repeat to require several
+        #[structopt(long, value_name = "KEY=VALUE", parse(try_from_str = parse_meta_filter))]
+        meta: Vec<(String, String)>,
+
+        /// Only the latest version of each day, one entry per day (e.g. one row per day with sqlite)
+        #[structopt(long)]
+        flat: bool,
+
+        /// How html exports include local images: link to the originals, embed thumbnails or copy them alongside
+        #[structopt(long, default_value = "link", possible_values = AttachmentMode::NAMES)]
+        attachments: AttachmentMode,
+
+        /// Longest side of embedded or copied thumbnails, in pixels
+        #[structopt(long, default_value = "640")]
+        thumbnail_size: u32,
+
+        /// Where html exports load the script drawing mermaid diagrams from, e.g. a local copy
+        #[structopt(long, value_name = "URL", default_value = html::DEFAULT_MERMAID_SCRIPT)]
+        mermaid_script: String,
+
+        /// Draw diagrams when exporting html with this command, reading a diagram on stdin and writing SVG to stdout
+        #[structopt(long, value_name = "COMMAND")]
+        diagram_command: Option<String>,
+    },
+
+    /// Write a journal of stored entries without syncing, optionally filtered
+    Journal {
+        /// Start from this saved search; the other filters narrow it further
+        #[structopt(long)]
+        saved: Option<String>,
+
+        /// Start from this query instead, e.g. 'tag:work -"1:1"'; the other filters narrow it further
+        #[structopt(long = "query", value_name = "QUERY", conflicts_with = "saved")]
+        dsl: Option<String>,
+
+        /// First day to include (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to include (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Only entries with this #tag; repeat to require several
+        #[structopt(long)]
+        tag: Vec<String>,
+
+        /// Only entries with at least this many words
+        #[structopt(long)]
+        min_words: Option<usize>,
+
+        /// Only versions stored by this sync run
+        #[structopt(long)]
+        exec_version: Option<i64>,
+
+        /// Only entries by this author
+        #[structopt(long)]
+        author: Option<String>,
+
+        /// Only starred days
+        #[structopt(long)]
+        starred: bool,
+
+        /// Only days whose metadata field KEY is VALUE; repeat to require several
+        #[structopt(long, value_name = "KEY=VALUE", parse(try_from_str = parse_meta_filter))]
+        meta: Vec<(String, String)>,
+    },
+}
+
+impl Command {
+    /// Commands that only read the database open it read-only, so they
+    /// never change it and can run while a sync is in progress
+    fn reads_only(&self) -> bool {
+        #[cfg(feature = "tui")]
+        if matches!(self, Command::Browse) {
+            return true;
+        }
+        #[cfg(feature = "spellcheck")]
+        if matches!(self, Command::Check(CheckCommand::Spelling { .. })) {
+            return true;
+        }
+        matches!(
+            self,
+            Command::List { .. }
+                | Command::Show { .. }
+                | Command::Search { .. }
+                | Command::Stats { .. }
+                | Command::Export { .. }
+                | Command::Attachments(_)
+                | Command::Runs(_)
+                | Command::Meta(MetaCommand::Get { .. })
+                | Command::Dictionary(DictionaryCommand::List { .. })
+                | Command::People(PeopleCommand::List { .. } | PeopleCommand::Show { .. })
+                | Command::Events(EventsCommand::List { .. })
+                | Command::Check(CheckCommand::Style { .. })
+                | Command::Review(_)
+                | Command::Resurface { peek: true, .. }
+                | Command::VerifyRoundtrip
+        )
+    }
+}
+
+#[derive(StructOpt, Debug)]
+enum DbCommand {
+    /// Rewrite stored content using the current compression setting
+    Recompress,
+
+    /// Show schema version, compatibility and size of the database
+    Info,
+
+    /// Upgrade or downgrade the database schema
+    Migrate {
+        /// Target schema version; defaults to the latest one
+        #[structopt(long)]
+        to: Option<i32>,
+
+        /// Show the planned steps without changing anything
+        #[structopt(long)]
+        dry_run: bool,
+    },
+
+    /// Move completed years into per-year shard databases
+    Shard {
+        /// Year to archive; defaults to every completed year
+        year: Option<i32>,
+    },
+
+    /// Refresh the statistics SQLite uses to plan queries
+    Analyze,
+
+    /// Re-read the --habit checklists of every stored entry, e.g. after adding a habit
+    RescanHabits,
+
+    /// Re-read the highlights of every stored entry, e.g. after upgrading
+    RescanHighlights,
+
+    /// Rebuild the word counts, links, highlights, people and events derived from stored entries, and the
+    /// database indexes, reporting any table that had drifted from the entries
+    Reindex,
+}
+
+#[derive(StructOpt, Debug)]
+enum GraphCommand {
+    /// Write the links between entries and notes as Graphviz DOT or JSON
+    Export {
+        /// Output format: dot or json
+        #[structopt(long, default_value = "dot", possible_values = cli::graph::FORMATS)]
+        format: String,
+
+        /// Destination file; defaults to stdout
+        #[structopt(long, parse(from_os_str))]
+        out: Option<PathBuf>,
+    },
+
+    /// Re-read the links of every stored entry, e.g. after upgrading
+    Rebuild,
+}
+
+#[derive(StructOpt, Debug)]
+enum CheckCommand {
+    /// Report links and images to missing files, and day links without an entry
+    Links {
+        /// Folder of archived attachments searched by file name for missing files
+        #[structopt(long, parse(from_os_str))]
+        attachments: Option<PathBuf>,
+
+        /// Point links at the archived attachments found, storing a new version of each entry
+        #[structopt(long, requires = "attachments")]
+        fix: bool,
+    },
+
+    /// Report writing-style issues in the latest entries: passive voice, filler words, long sentences
+    /// and words repeated twice in a row
+    Style {
+        /// First day to check (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to check (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Rules to apply, comma-separated; all by default
+        #[structopt(long, use_delimiter = true, possible_values = StyleRule::NAMES)]
+        rules: Vec<StyleRule>,
+
+        /// Longest sentence, in words, let through by long-sentence
+        #[structopt(long, default_value = "30")]
+        max_sentence_words: usize,
+
+        /// Words and phrases reported as filler instead of the defaults (really, very, just, kind of, ...),
+        /// comma-separated
+        #[structopt(long, use_delimiter = true)]
+        filler_words: Vec<String>,
+    },
+
+    /// Report likely misspellings in the latest entries, with suggestions and the line they are on
+    #[cfg(feature = "spellcheck")]
+    Spelling {
+        /// Language of the hunspell dictionary, looked up in $DICPATH and the system's dictionary folders
+        #[structopt(long, default_value = "en")]
+        lang: String,
+
+        /// First day to check (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to check (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Dictionary to check against instead: a hunspell .dic next to its .aff, or a word list
+        #[structopt(long, parse(from_os_str))]
+        dictionary: Option<PathBuf>,
+
+        /// Suggestions to show for each word
+        #[structopt(long, default_value = "3")]
+        suggestions: usize,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum PeopleCommand {
+    /// List the people mentioned, those on the most days first
+    List {
+        /// First day to count (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to count (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+
+        /// Show at most this many people
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+
+    /// Show the latest entry of every day mentioning a person, oldest first
+    Show {
+        /// Name as listed, ignoring case
+        name: String,
+
+        /// When to use colors: auto, always or never
+        #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"])]
+        color: String,
+    },
+
+    /// Re-read the people mentioned in every stored entry, e.g. after upgrading
+    Rebuild,
+}
+
+#[derive(StructOpt, Debug)]
+enum EventsCommand {
+    /// List the life events, oldest first
+    List {
+        /// First day to list (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        from: Option<NaiveDate>,
+
+        /// Last day to list (YYYY-MM-DD or an expression)
+        #[structopt(long, parse(try_from_str = parse_end))]
+        to: Option<NaiveDate>,
+    },
+
+    /// Re-read the life events of every stored entry, e.g. after upgrading
+    Rebuild,
+}
+
+#[derive(StructOpt, Debug)]
+enum DictionaryCommand {
+    /// Accept words in spell checks of this diary, such as names and places
+    Add {
+        #[structopt(required = true)]
+        words: Vec<String>,
+
+        /// Language the words belong to
+        #[structopt(long, default_value = "en")]
+        lang: String,
+    },
+
+    /// Stop accepting a word added earlier
+    Remove {
+        word: String,
+
+        /// Language the word belongs to
+        #[structopt(long, default_value = "en")]
+        lang: String,
+    },
+
+    /// List the words added for a language
+    List {
+        #[structopt(long, default_value = "en")]
+        lang: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum BackupCommand {
+    /// Write the database, its shards and archives, the attachments and a config fingerprint to one `.rdbk` file
+    Create {
+        /// File to write; defaults to rusty-diary-<timestamp>.rdbk in the current folder
+        #[structopt(parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Only the entries and attachments changed since the latest backup
+        #[structopt(long)]
+        incremental: bool,
+
+        #[structopt(flatten)]
+        encryption: EncryptionArgs,
+
+        /// Command run as age
+        #[structopt(long, default_value = "age")]
+        age: String,
+    },
+
+    /// Restore a full backup and the incrementals after it into a temporary folder, and check hashes,
+    /// integrity and row counts
+    Verify {
+        /// The full backup, then its incrementals in the order they were taken
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+
+        /// age identity file to decrypt with; repeatable
+        #[structopt(short, long = "identity", parse(from_os_str))]
+        identities: Vec<PathBuf>,
+
+        /// Command run as age
+        #[structopt(long, default_value = "age")]
+        age: String,
+    },
+
+    /// Restore a full backup and the incrementals after it to --db and the attachments folder, once they check out
+    Restore {
+        /// The full backup, then its incrementals in the order they were taken
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+
+        /// age identity file to decrypt with; repeatable
+        #[structopt(short, long = "identity", parse(from_os_str))]
+        identities: Vec<PathBuf>,
+
+        /// Command run as age
+        #[structopt(long, default_value = "age")]
+        age: String,
+
+        /// Replace an existing database
+        #[structopt(long)]
+        force: bool,
+    },
+
+    /// Fold a full backup and the incrementals after it into a fresh full backup
+    Compact {
+        /// The full backup, then its incrementals in the order they were taken
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+
+        /// File to write
+        #[structopt(short, long, parse(from_os_str))]
+        output: PathBuf,
+
+        /// age identity file to decrypt with; repeatable
+        #[structopt(short, long = "identity", parse(from_os_str))]
+        identities: Vec<PathBuf>,
+
+        #[structopt(flatten)]
+        encryption: EncryptionArgs,
+
+        /// Command run as age
+        #[structopt(long, default_value = "age")]
+        age: String,
+    },
+
+    /// Backups recorded in the database, newest first
+    List,
+}
+
+#[derive(StructOpt, Debug)]
+struct EncryptionArgs {
+    /// Encrypt for this age public key; repeatable
+    #[structopt(short, long = "recipient")]
+    recipients: Vec<String>,
+
+    /// Encrypt for the age public keys listed in this file; repeatable
+    #[structopt(long = "recipients-file", parse(from_os_str))]
+    recipients_files: Vec<PathBuf>,
+
+    /// Encrypt with a passphrase age prompts for
+    #[structopt(long)]
+    passphrase: bool,
+
+    /// Write the backup unencrypted
+    #[structopt(long, conflicts_with_all = &["recipients", "recipients-files", "passphrase"])]
+    no_encrypt: bool,
+}
+
+#[derive(StructOpt, Debug)]
+enum AttachmentsCommand {
+    /// Remove attachments no stored entry links to any more, e.g. after deleting or pruning days
+    Gc {
+        /// Only list what would be removed
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum TagsCommand {
+    /// Propose tags for a day's entry from words rare in the rest of the diary
+    Suggest {
+        /// Day to suggest tags for (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+
+        /// Most tags to propose
+        #[structopt(long, default_value = "5")]
+        limit: usize,
+    },
+
+    /// List a day's #tags and the tags stored for it, with their source
+    Show {
+        /// Day to show tags of (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum MetaCommand {
+    /// Set a day's field, replacing any earlier value
+    Set {
+        /// Day to annotate (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+
+        /// Field name: letters, digits, '_', '-' and '.'
+        key: String,
+
+        value: String,
+    },
+
+    /// Print a day's fields, or the value of one
+    Get {
+        /// Day to read (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+
+        key: Option<String>,
+    },
+
+    /// Remove a day's field
+    Unset {
+        /// Day to change (YYYY-MM-DD or an expression)
+        #[structopt(parse(try_from_str = parse_start))]
+        date: NaiveDate,
+
+        key: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum ReviewCommand {
+    /// Last week's entries, the tasks left open in them, numbers and review questions
+    Week {
+        /// Review the ISO week containing this day instead (YYYY-MM-DD or an expression, e.g. 2024-W12)
+        #[structopt(long, parse(try_from_str = parse_start))]
+        week: Option<NaiveDate>,
+
+        /// Markdown file with the questions closing the review
+        #[structopt(long, parse(from_os_str))]
+        template: Option<PathBuf>,
+
+        /// Only write the file; don't open it in the editor
+        #[structopt(long)]
+        no_edit: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum RunsCommand {
+    /// List sync runs, newest first
+    List,
+
+    /// Show a sync run's statistics and the entries it stored
+    Show {
+        /// Run id, the exec_version of the entries it stored
+        id: i64,
+    },
+}
+
+/// Process exit codes; part of the CLI contract for scripts and cron jobs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExitCode {
+    /// Any error without a more specific code, including invalid arguments
+    Failure = 1,
+    /// The diary directory has no files matching the date pattern
+    NoFilesFound = 3,
+    /// Some files were skipped or left behind (always an error with --strict)
+    PartialFailure = 4,
+    /// The database schema conflicts with this build
+    Conflict = 5,
+    /// Another process holds the database lock
+    LockHeld = 6,
+    /// Stopped with Ctrl-C before finishing; nothing was changed
+    Interrupted = 130,
+}
+
+/// Warnings promoted to an error by `--strict`
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+struct StrictViolation(String);
+
+fn exit_code(err: &anyhow::Error) -> ExitCode {
+    if err.chain().any(|cause| cause.is::<StrictViolation>()) {
+        return ExitCode::PartialFailure;
+    }
+
+    match err.chain().find_map(|cause| cause.downcast_ref::<RustyDiaryError>()) {
+        Some(RustyDiaryError::NoFilesFound(_)) => ExitCode::NoFilesFound,
+        Some(RustyDiaryError::Cancelled) => ExitCode::Interrupted,
+        Some(RustyDiaryError::AllFilesSkipped(_)) => ExitCode::PartialFailure,
+        Some(RustyDiaryError::SchemaTooNew { .. } | RustyDiaryError::IncompatibleSchema(_)) => {
+            ExitCode::Conflict
+        }
+        Some(RustyDiaryError::Database { source: rusqlite::Error::SqliteFailure(failure, _), .. })
+            if matches!(
+                failure.code,
+                rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+            ) =>
+        {
+            ExitCode::LockHeld
+        }
+        _ => ExitCode::Failure,
+    }
+}
+
+/// Cancels the running operation on the first Ctrl-C, so it can roll back
+/// and leave the files alone, and exits right away on the second
+async fn cancel_on_ctrl_c(cancel: CancellationToken) {
+    if tokio::signal::ctrl_c().await.is_err() {
+        return;
+    }
+    eprintln!("Cancelling; press Ctrl-C again to quit immediately");
+    cancel.cancel();
+    if tokio::signal::ctrl_c().await.is_ok() {
+        std::process::exit(ExitCode::Interrupted as i32);
+    }
+}
+
+/// Terminal progress bars, one per phase. Hidden automatically when
+/// stderr is not a terminal.
+#[derive(Default)]
+struct TerminalProgress {
+    bar: Mutex<Option<ProgressBar>>,
+}
+
+impl ProgressSink for TerminalProgress {
+    fn start(&self, phase: &str, total: u64) {
+        let bar = ProgressBar::new(total).with_message(phase.to_string());
+        bar.set_style(
+            ProgressStyle::with_template("{msg:>8} [{bar:40}] {pos}/{len}")
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+        *self.bar.lock() = Some(bar);
+    }
+
+    fn advance(&self, n: u64) {
+        if let Some(bar) = self.bar.lock().as_ref() {
+            bar.inc(n);
+        }
+    }
+
+    fn finish(&self) {
+        if let Some(bar) = self.bar.lock().take() {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn log_level(verbose: usize, quiet: usize) -> tracing::Level {
+    match verbose as isize - quiet as isize {
+        isize::MIN..=-2 => tracing::Level::ERROR,
+        -1 => tracing::Level::WARN,
+        0 => tracing::Level::INFO,
+        1 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    }
+}
+
+fn setup_logging(cli: &Cli) {
+    // RUST_LOG takes precedence over -v/-q when set
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(log_level(cli.verbose, cli.quiet).as_str()));
+
+    // Logs go to stderr so stdout only carries command output
+    let builder = tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_env_filter(filter)
+        .with_target(false)
+        .with_thread_ids(false)
+        .with_file(true)
+        .with_line_number(true);
+
+    if cli.log_format == "json" {
+        builder.json().with_current_span(true).init();
+    } else {
+        builder.init();
+    }
+}
+
+fn build_config(cli: &Cli) -> anyhow::Result<Config> {
+    let mut config = Config::new();
+
+    if let Some(now) = cli.now {
+        config = config.with_clock(Arc::new(FixedClock(now)));
+    }
+
+    if let Some(dir) = &cli.directory {
+        config = config.with_directory(dir);
+    }
+
+    if let Some(db) = &cli.db {
+        config = config.with_db(db);
+    }
+
+    if let Some(pattern) = &cli.date_pattern {
+        config = config.with_date_pattern(pattern);
+    }
+
+    config = config
+        .with_layout(cli.max_depth, cli.path_dates)
+        .with_min_file_age(Duration::from_secs(cli.min_file_age))
+        .with_normalization(cli.normalize)
+        .with_lossy_utf8(cli.lossy_utf8)
+        .with_date_heading_split(cli.split_date_headings)
+        .with_photo_journal(cli.photos);
+
+    if cli.auto_tag {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&cli.auto_tag_threshold),
+            "--auto-tag-threshold must be between 0 and 1"
+        );
+        config = config.with_auto_tag(cli.auto_tag_threshold);
+    }
+
+    if let Some(author) = &cli.default_author {
+        config = config.with_author(author);
+    }
+
+    if let Some(location) = &cli.location {
+        config = config.with_location(location);
+    }
+
+    for repo in &cli.git_repo {
+        config = config.with_git_repo(repo);
+    }
+
+    for habit in &cli.habit {
+        config = config.with_habit(habit);
+    }
+
+    for definition in &cli.saved_search {
+        let (name, query) = saved::parse_definition(definition)?;
+        config = config.with_saved_search(&name, &query);
+    }
+
+    for pattern in &cli.ignore {
+        config = config.with_ignore_pattern(pattern);
+    }
+
+    if cli.no_compress {
+        config = config.with_compression(false);
+    }
+
+    if let Some(settings) = &cli.sqlite_profile {
+        config = config.with_sqlite_settings(settings.clone());
+    }
+
+    config = config.with_search_settings(SearchSettings {
+        stemming: cli.search_stemmer.clone(),
+        fold_diacritics: cli.search_fold_diacritics,
+        stopwords: cli.search_stopword.iter().map(|word| word.to_lowercase()).collect(),
+    });
+
+    if let Some(theme) = &cli.highlight_theme {
+        config = config.with_highlight_theme(theme);
+    }
+    config = config.with_markdown_flavor(cli.markdown_flavor);
+
+    if let Some(dir) = &cli.plugin_dir {
+        config = config.with_plugin_dir(dir);
+    }
+
+    if let Some(template) = &cli.output_filename {
+        config = config.with_output_filename(template);
+    }
+
+    config = config.with_journal_split(cli.journal_split);
+
+    if let Some(file) = &cli.journal_file {
+        config = config.with_journal_file(file);
+    }
+
+    let mut template = match &cli.journal_template {
+        Some(dir) => JournalTemplate::from_dir(dir)
+            .with_context(|| format!("Failed to load journal templates from {}", dir.display()))?,
+        None => JournalTemplate::default(),
+    };
+    template.toc = cli.journal_toc;
+    template.locale = DateLocale {
+        locale: cli.locale,
+        first_weekday: cli.first_weekday,
+        secondary: cli.secondary_calendar,
+    };
+    config = config.with_journal_template(template);
+
+    let hooks = [
+        (HookEvent::PreSync, &cli.pre_sync_hook),
+        (HookEvent::EntryStored, &cli.entry_hook),
+        (HookEvent::JournalWritten, &cli.journal_hook),
+        (HookEvent::Reminder, &cli.reminder_hook),
+    ];
+    for (event, commands) in hooks {
+        for command in commands {
+            config = config.with_hook(event, Hook::command(command.as_str()));
+        }
+    }
+
+    Ok(config)
+}
+
+async fn run(cli: Cli) -> anyhow::Result<()> {
+    if let Some(Command::Completions { shell }) = &cli.command {
+        Cli::clap().gen_completions_to(env!("CARGO_PKG_NAME"), *shell, &mut std::io::stdout());
+        return Ok(());
+    }
+
+    info!("Starting Rusty Diary...");
 
-#[derive(StructOpt, Debug)]
-#[structopt(
-    name = "rusty_diary",
-    about = "A markdown diary with SQLite persistence",
-    author
-)]
-struct Cli {
-    /// Directory containing markdown files
-    #[structopt(parse(from_os_str))]
-    directory: Option<PathBuf>,
+    anyhow::ensure!(!cli.dry_run || cli.command.is_none(), "--dry-run previews a sync and takes no subcommand");
+    let config = build_config(&cli)?;
+    // A dry run opens the database read-only, or previews against an
+    // empty one in memory while there is none, so it never writes one
+    let dry_run = match &cli.command {
+        Some(Command::Import { dry_run, .. }) => *dry_run,
+        _ => cli.dry_run,
+    };
+    let exists = config.db_path.exists();
+    let read_only = (dry_run && exists) || cli.command.as_ref().is_some_and(Command::reads_only);
+    let config = config.with_read_only(read_only).with_in_memory(dry_run && !exists);
+    info!("Configuration loaded from CLI: {:?}", config);
+    let db_path = config.db_path.clone();
+    let json = cli.output == "json";
 
-    /// Database file path
-    #[structopt(long, parse(from_os_str))]
-    db: Option<PathBuf>,
+    // Opening the diary migrates to the latest schema, so explicit
+    // migrations have to run against the raw database first
+    match &cli.command {
+        Some(Command::Db(DbCommand::Migrate { to, dry_run })) => {
+            return run_migrate(&config, *to, *dry_run, json);
+        }
+        Some(Command::Db(DbCommand::Info)) => return run_info(&config, json),
+        Some(Command::Backup(command)) => return run_backup_command(&config, command, json),
+        Some(Command::Restore { as_of, into }) => return run_restore_as_of(&config, *as_of, into, json),
+        Some(Command::Init) => return run_init(&config, json),
+        Some(Command::Doctor) => return run_doctor(&config, json),
+        _ => {}
+    }
 
-    /// Custom date pattern for files
-    #[structopt(long)]
-    date_pattern: Option<String>,
+    let highlight_theme = config.highlight_theme.clone();
+    let flavor = config.markdown_flavor;
+    let mut diary = RustyDiary::new(config)
+        .context("Failed to initialize diary")?;
+    #[cfg(feature = "tui")]
+    let interactive = matches!(cli.command, Some(Command::Browse | Command::Find { .. }));
+    #[cfg(not(feature = "tui"))]
+    let interactive = false;
+    let interactive = interactive || matches!(cli.command, Some(Command::Review(ReviewCommand::Week { no_edit: false, .. })));
+    if cli.quiet == 0 && cli.log_format == "text" && !json && !interactive {
+        diary = diary.with_progress(Arc::new(TerminalProgress::default()));
+    }
+    if !interactive {
+        let cancel = CancellationToken::new();
+        diary = diary.with_cancellation(cancel.clone());
+        tokio::spawn(cancel_on_ctrl_c(cancel));
+    }
 
-    /// Verbosity level
-    #[structopt(short, long, parse(from_occurrences))]
-    verbose: usize,
+    if let Some(Command::Export { attachments, thumbnail_size, mermaid_script, diagram_command, .. }) = &cli.command {
+        let mut html = HtmlExporter::new()
+            .with_attachments(*attachments, *thumbnail_size)
+            .with_highlighter(Arc::new(Highlighter::new(highlight_theme.as_deref().unwrap_or(highlight::DEFAULT_HTML_THEME))?))
+            .with_mermaid_script(mermaid_script)
+            .with_flavor(flavor);
+        if let Some(command) = diagram_command {
+            html = html.with_diagram_renderer(Arc::new(CommandRenderer::new(command)));
+        }
+        diary = diary.with_exporter(Arc::new(html));
+    }
+
+    match &cli.command {
+        None if cli.dry_run => preview_sync(&diary, json),
+        None => sync_and_write(&diary, json, cli.strict),
+        Some(Command::Import { format, source, dry_run: true }) => {
+            let preview = diary.preview_import(format, source).context("Failed to preview the import")?;
+            let base = if source.is_dir() { source.as_path() } else { source.parent().unwrap_or(source) };
+            print_preview(&preview, base, json)
+        }
+        Some(Command::Import { format, source, dry_run: false }) => {
+            let report = diary.import(format, source).with_context(|| format!("Failed to import {}", source.display()))?;
+            if json {
+                return print_json(&report);
+            }
+            for file in &report.skipped {
+                println!("SKIPPED  {}: {}", file.path.display(), file.reason);
+            }
+            println!(
+                "Imported {} entries with {} ({} duplicates, {} attachments, {} skipped) as run {}",
+                report.stored,
+                report.importer,
+                report.duplicates,
+                report.attachments,
+                report.skipped.len(),
+                report.exec_version
+            );
+            Ok(())
+        }
+        Some(Command::VerifyRoundtrip) => {
+            let trip = diary.verify_roundtrip().context("Failed to verify the round trip")?;
+            if json {
+                print_json(&trip)?;
+            } else {
+                for difference in &trip.differences {
+                    println!("DIFFERS  {}", difference);
+                }
+                println!(
+                    "{} versions of {} days and {} attachments; digest {}{}",
+                    trip.entries,
+                    trip.days,
+                    trip.attachments,
+                    trip.digest,
+                    if trip.is_identical() { ", restored identically".to_string() } else { format!(", restored as {}", trip.restored_digest) }
+                );
+            }
+            if !trip.is_identical() {
+                return Err(RustyDiaryError::ContentIntegrity(format!(
+                    "{} entries, days or attachments differ after a round trip",
+                    trip.differences.len()
+                ))
+                .into());
+            }
+            Ok(())
+        }
+        Some(Command::Db(command)) => run_db_command(&diary, command, json),
+        Some(Command::Graph(command)) => run_graph_command(&diary, command, json),
+        Some(Command::Tags(command)) => run_tags_command(&diary, command, json),
+        Some(Command::Meta(command)) => run_meta_command(&diary, command, json),
+        Some(Command::Runs(command)) => run_runs_command(&diary, command, json),
+        Some(Command::Review(command)) => run_review_command(&diary, command, json),
+        Some(Command::Check(CheckCommand::Links { attachments, fix })) => {
+            run_check_links(&diary, attachments.as_deref(), *fix, json, cli.strict)
+        }
+        Some(Command::Check(CheckCommand::Style { from, to, rules, max_sentence_words, filler_words })) => {
+            let mut style = StyleRules::default().with_max_sentence_words(*max_sentence_words);
+            if !rules.is_empty() {
+                style = style.with_rules(rules.clone());
+            }
+            if !filler_words.is_empty() {
+                style = style.with_filler_words(filler_words.clone());
+            }
+            run_check_style(&diary, date_range(*from, *to), &style, json, cli.strict)
+        }
+        #[cfg(feature = "spellcheck")]
+        Some(Command::Check(CheckCommand::Spelling { lang, from, to, dictionary, suggestions })) => {
+            let range = date_range(*from, *to);
+            run_check_spelling(&diary, range, lang, dictionary.as_deref(), *suggestions, json, cli.strict)
+        }
+        Some(Command::Dictionary(command)) => run_dictionary_command(&diary, command, json),
+        Some(Command::Events(command)) => run_events_command(&diary, command, json),
+        Some(Command::People(command)) => run_people_command(&diary, command, highlight_theme.as_deref(), flavor, json),
+        Some(Command::Attachments(AttachmentsCommand::Gc { dry_run })) => run_attachments_gc(&diary, *dry_run, json),
+        Some(Command::Backlinks { date }) => {
+            let dates = diary.storage().backlinks(*date).context("Failed to look up backlinks")?;
+            if json {
+                return print_json(&dates);
+            }
+            for date in &dates {
+                println!("{}", date);
+            }
+            Ok(())
+        }
+        Some(Command::Resurface { limit, peek }) => {
+            let today = clock().today();
+            let mut highlights = diary.storage().due_highlights(today, *limit).context("Failed to read highlights")?;
+            if !*peek {
+                for highlight in &mut highlights {
+                    highlight.due = diary.storage().review_highlight(highlight.id, today)
+                        .context("Failed to schedule the next review")?;
+                    highlight.stage += 1;
+                    highlight.last_reviewed = Some(today);
+                }
+            }
+            if json {
+                return print_json(&highlights);
+            }
+            if highlights.is_empty() {
+                info!("No highlights due");
+            }
+            for highlight in &highlights {
+                println!("{}  {}", highlight.date, highlight.text.replace('\n', "\n            "));
+                if !*peek {
+                    match highlight.due {
+                        Some(due) => println!("            (again on {})", due),
+                        None => println!("            (done)"),
+                    }
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Archive { before }) => {
+            let archived = diary.cold_archive(*before)
+                .context("Failed to archive entries")?;
+            info!("Archived {} entries dated before {}", archived, before);
+            if json {
+                print_json(&json!({ "archived": archived, "before": before }))?;
+            }
+            Ok(())
+        }
+        Some(Command::Unarchive { from, to }) => {
+            let restored = diary.unarchive(*from, *to)
+                .context("Failed to restore archived entries")?;
+            info!("Restored {} entries from {} to {}", restored, from, to);
+            if json {
+                print_json(&json!({ "restored": restored, "from": from, "to": to }))?;
+            }
+            Ok(())
+        }
+        Some(Command::Pin { date }) => {
+            let stored = diary.storage().latest_entry(*date)?.is_some()
+                || !diary.storage().archived_summaries(*date, *date)?.is_empty();
+            anyhow::ensure!(stored, "No entry for {}", date);
+            let changed = diary.storage().set_starred(*date, true).context("Failed to star the day")?;
+            info!("{} {}", if changed { "Starred" } else { "Already starred:" }, date);
+            if json {
+                print_json(&json!({ "date": date, "starred": true, "changed": changed }))?;
+            }
+            Ok(())
+        }
+        Some(Command::Unpin { date }) => {
+            let changed = diary.storage().set_starred(*date, false).context("Failed to unstar the day")?;
+            info!("{} {}", if changed { "Unstarred" } else { "Not starred:" }, date);
+            if json {
+                print_json(&json!({ "date": date, "starred": false, "changed": changed }))?;
+            }
+            Ok(())
+        }
+        Some(Command::List { from, to, starred }) => {
+            let range = date_range(*from, *to);
+            let mut summaries = diary.storage().list_entries(range.start, range.end)
+                .context("Failed to list entries")?;
+            if *starred {
+                let starred = diary.storage().starred(range.start, range.end)?;
+                summaries.retain(|summary| starred.contains(&summary.date));
+            }
+            if json {
+                return print_json(&summaries);
+            }
+            for summary in &summaries {
+                let author = summary.author.as_ref().map_or(String::new(), |author| format!("  {}", author));
+                println!("{}  v{:<4} {:>6} words{}", summary.date, summary.exec_version, summary.word_count, author);
+            }
+            Ok(())
+        }
+        Some(Command::Stats { report: Some(report), from, to, month, limit, stem, .. }) if report == "terms" => {
+            let range = month.unwrap_or_else(|| date_range(*from, *to));
+            let terms = diary.top_terms(range, *limit, *stem).context("Failed to count terms")?;
+            if json {
+                return print_json(&terms);
+            }
+            for term in &terms {
+                println!("{:>6}  {}", term.count, term.term);
+            }
+            Ok(())
+        }
+        Some(Command::Stats { report: Some(_), from, to, month, .. }) => {
+            let range = month.unwrap_or_else(|| date_range(*from, *to));
+            let habits = diary.habit_stats(range).context("Failed to compute habit statistics")?;
+            if json {
+                return print_json(&habits);
+            }
+            if habits.is_empty() {
+                println!("No habits recorded; track some with --habit and run `db rescan-habits`");
+            }
+            for habit in &habits {
+                println!("{}  streak {} days, longest {}", habit.habit, habit.current_streak, habit.longest_streak);
+                for month in &habit.months {
+                    println!("  {}  {:>3.0}%  ({} of {} days)", month.month, month.percent, month.done, month.checked);
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Stats { from, to, month, by_author: true, .. }) => {
+            let stats = diary.storage().author_stats(month.unwrap_or_else(|| date_range(*from, *to)))
+                .context("Failed to compute statistics")?;
+            if json {
+                return print_json(&stats);
+            }
+            println!("{:<20} {:>6} {:>9} {:>9}  {:<10}  {:<10}", "Author", "Days", "Versions", "Words", "First", "Last");
+            for author in &stats {
+                let date = |date: Option<NaiveDate>| date.map_or("-".to_string(), |d| d.to_string());
+                println!(
+                    "{:<20} {:>6} {:>9} {:>9}  {:<10}  {:<10}",
+                    author.author.as_deref().unwrap_or("(none)"),
+                    author.stats.days,
+                    author.stats.versions,
+                    author.stats.total_words,
+                    date(author.stats.first_date),
+                    date(author.stats.last_date),
+                );
+            }
+            Ok(())
+        }
+        Some(Command::Stats { from, to, month, by_author: false, .. }) => {
+            let range = month.unwrap_or_else(|| date_range(*from, *to));
+            let stats = diary.storage().stats_in(range).context("Failed to compute statistics")?;
+            if json {
+                return print_json(&stats);
+            }
+            let date = |date: Option<NaiveDate>| date.map_or("-".to_string(), |d| d.to_string());
+            println!("Days:            {}", stats.days);
+            println!("Versions:        {}", stats.versions);
+            println!("Words:           {}", stats.total_words);
+            println!("First entry:     {}", date(stats.first_date));
+            println!("Last entry:      {}", date(stats.last_date));
+            println!("Latest run:      {}", stats.latest_exec_version);
+            println!("Archived:        {}", stats.archived);
+            if !stats.starred.is_empty() {
+                println!("\nFavorites");
+                for date in &stats.starred {
+                    let entry = diary.storage().latest_entry(*date)?;
+                    let title = entry.as_ref().and_then(|entry| entry.content.lines().find(|line| !line.trim().is_empty()));
+                    println!("  {}  {}", date, title.unwrap_or("(archived)").trim());
+                }
+            }
+            if !stats.people.is_empty() {
+                println!("\nPeople");
+                for person in &stats.people {
+                    println!("  {:<24} {:>4} days  {:>5} mentions", person.name, person.days, person.mentions);
+                }
+            }
+            Ok(())
+        }
+        Some(Command::Search {
+            query,
+            saved,
+            dsl,
+            regex,
+            timeout,
+            from,
+            to,
+            context,
+            starred,
+            phrase,
+            any_term,
+            rank,
+            title_weight,
+            tags_weight,
+            body_weight,
+            recency_boost,
+            color,
+        }) => {
+            let mut hits = match query {
+                Some(pattern) if *regex => {
+                    let range = date_range(*from, *to);
+                    diary.storage().search_regex(pattern, range.start, range.end, *context, Duration::from_secs(*timeout))
+                }
+                _ => {
+                    let query = match query {
+                        Some(query) => EntryQuery::within(date_range(*from, *to)).with_text(query),
+                        None => base_query(&diary, saved.as_deref(), dsl.as_deref(), *from, *to)?,
+                    };
+                    let mode = match (phrase, any_term) {
+                        (true, _) => MatchMode::Phrase,
+                        (_, true) => MatchMode::AnyTerm,
+                        _ => query.mode,
+                    };
+                    let ranking = Ranking {
+                        title_weight: *title_weight,
+                        tags_weight: *tags_weight,
+                        body_weight: *body_weight,
+                        recency_boost: *recency_boost,
+                    };
+                    let query = EntryQuery { ranking: rank.then_some(ranking).or(query.ranking), ..query.with_mode(mode) };
+                    diary.storage().search_matching(&query, *context)
+                }
+            }
+            .context("Failed to search entries")?;
+            if *starred {
+                let range = date_range(*from, *to);
+                let starred = diary.storage().starred(range.start, range.end)?;
+                hits.retain(|hit| starred.contains(&hit.date));
+            }
+            if json {
+                return print_json(&hits);
+            }
+            print!("{}", cli::search::format_hits(&hits, use_color(color)));
+            Ok(())
+        }
+        Some(Command::Show { date, raw, all_versions, related, color }) => {
+            let entries = if *all_versions {
+                diary.storage().entries_by_date_range(*date, *date)?
+            } else {
+                diary.storage().latest_entry(*date)?.into_iter().collect()
+            };
+            anyhow::ensure!(!entries.is_empty(), "No entry for {}", date);
+
+            if json {
+                return if *all_versions { print_json(&entries) } else { print_json(&entries[0]) };
+            }
+            let highlighter = terminal_highlighter(highlight_theme.as_deref())?;
+            print!("{}", cli::show::format_entries(&entries, *raw, use_color(color), Some(&highlighter), flavor));
+            if !*raw && *related > 0 {
+                let related = diary.related(*date, *related)?.unwrap_or_default();
+                print!("{}", cli::show::format_related(&related, use_color(color)));
+            }
+            Ok(())
+        }
+        #[cfg(feature = "tui")]
+        Some(Command::Browse) => {
+            let highlighter = terminal_highlighter(highlight_theme.as_deref())?;
+            cli::browse::run(diary.storage(), clock().today(), Some(highlighter), flavor)
+        }
+        #[cfg(feature = "tui")]
+        Some(Command::Find { query, edit }) => {
+            let highlighter = terminal_highlighter(highlight_theme.as_deref())?;
+            cli::find::run(diary.storage(), diary.directory(), query, *edit, Some(&highlighter), flavor)
+        }
+        Some(Command::Completions { .. }) => unreachable!("handled before loading the configuration"),
+        Some(Command::Init | Command::Doctor | Command::Backup(_) | Command::Restore { .. }) => {
+            unreachable!("handled before opening the diary")
+        }
+        Some(Command::Daemon { interval, metrics_addr, remind_at, word_goal }) => {
+            let reminder = remind_at.map(|at| {
+                let reminder = Reminder::new(at);
+                word_goal.map_or(reminder, |goal| reminder.with_word_goal(goal))
+            });
+            run_daemon(diary, &db_path, *interval, *metrics_addr, reminder).await
+        }
+        Some(Command::Export { target, out, saved, dsl, from, to, tag, min_words, max_words, author, starred, meta, flat, .. }) => {
+            let query = base_query(&diary, saved.as_deref(), dsl.as_deref(), *from, *to)?;
+            let query = EntryQuery {
+                min_words: min_words.or(query.min_words),
+                max_words: max_words.or(query.max_words),
+                starred: *starred || query.starred,
+                ..query
+            };
+            let query = tag.iter().fold(query, |query, tag| query.with_tag(tag));
+            let query = meta.iter().fold(query, |query, (key, value)| query.with_meta(key, value));
+            let query = match author {
+                Some(author) => query.with_author(author),
+                None => query,
+            };
+            let exported = if *flat {
+                diary.export_latest_matching(target, &query, out)
+            } else {
+                diary.export_matching(target, &query, out)
+            }
+            .with_context(|| format!("Failed to export entries to {}", out.display()))?;
+            info!("Exported {} entries to {}", exported, out.display());
+            if json {
+                print_json(&json!({ "exported": exported, "target": target, "path": out }))?;
+            }
+            Ok(())
+        }
+        Some(Command::Journal { saved, dsl, from, to, tag, min_words, exec_version, author, starred, meta }) => {
+            let query = base_query(&diary, saved.as_deref(), dsl.as_deref(), *from, *to)?;
+            let query = EntryQuery {
+                min_words: min_words.or(query.min_words),
+                exec_version: exec_version.or(query.exec_version),
+                starred: *starred || query.starred,
+                ..query
+            };
+            let query = tag.iter().fold(query, |query, tag| query.with_tag(tag));
+            let query = meta.iter().fold(query, |query, (key, value)| query.with_meta(key, value));
+            let query = match author {
+                Some(author) => query.with_author(author),
+                None => query,
+            };
+
+            let journal = diary.write_journal_matching(&query).context("Failed to write journal")?;
+            if json {
+                return print_json(&journal);
+            }
+            println!("{}", journal.path.display());
+            Ok(())
+        }
+    }
 }
 
-fn setup_logging(verbosity: usize) {
-    let level = match verbosity {
-        0 => tracing::Level::INFO,
-        1 => tracing::Level::DEBUG,
-        _ => tracing::Level::TRACE,
+/// `--output json` result of a sync
+#[derive(Serialize)]
+struct SyncOutput {
+    #[serde(flatten)]
+    report: SyncReport,
+    journal: JournalReport,
+}
+
+/// Prints a command result as JSON on stdout
+fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Resolves a `--color` setting against whether stdout is a terminal
+fn use_color(color: &str) -> bool {
+    match color {
+        "always" => true,
+        "never" => false,
+        _ => std::io::stdout().is_terminal(),
+    }
+}
+
+/// The named saved search, the parsed `--query` or an empty query, with
+/// the CLI date bounds replacing its own
+fn base_query(
+    diary: &RustyDiary,
+    saved: Option<&str>,
+    dsl: Option<&str>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> anyhow::Result<EntryQuery> {
+    let query = match (saved, dsl) {
+        (Some(name), _) => diary.saved_search(name)?.clone(),
+        (None, Some(dsl)) => saved::parse_query(dsl, clock().today())?,
+        (None, None) => EntryQuery::default(),
     };
+    Ok(EntryQuery {
+        start_date: from.or(query.start_date),
+        end_date: to.or(query.end_date),
+        ..query
+    })
+}
 
-    tracing_subscriber::fmt()
-        .with_max_level(level)
-        .with_target(false)
-        .with_thread_ids(false)
-        .with_file(true)
-        .with_line_number(true)
-        .init();
+/// A `--meta KEY=VALUE` filter
+fn parse_meta_filter(filter: &str) -> Result<(String, String), String> {
+    let (key, value) = filter.split_once('=').ok_or_else(|| format!("'{}' is not KEY=VALUE", filter))?;
+    storage::validate_meta_key(key).map_err(|e| e.to_string())?;
+    Ok((key.to_string(), value.to_string()))
 }
 
-fn build_config(cli: &Cli) -> Config {
-    let mut config = Config::new();
+/// The period a date expression names, such as `2024-03`
+fn parse_period(expression: &str) -> Result<DateRange, RustyDiaryError> {
+    dates::resolve(expression, clock().today())
+}
 
-    if let Some(dir) = &cli.directory {
-        config = config.with_directory(dir);
+/// First day of the period a date expression names
+fn parse_start(expression: &str) -> Result<NaiveDate, RustyDiaryError> {
+    dates::parse_start(expression, clock().today())
+}
+
+/// Last day of the period a date expression names
+fn parse_end(expression: &str) -> Result<NaiveDate, RustyDiaryError> {
+    dates::parse_end(expression, clock().today())
+}
+
+/// The moment of `--now`: a date, at midnight, or a date and time
+fn parse_now(now: &str) -> Result<NaiveDateTime, String> {
+    ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(now, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(now, "%Y-%m-%d").ok().map(|date| date.and_time(NaiveTime::MIN)))
+        .ok_or_else(|| format!("'{}' is not a date (YYYY-MM-DD) or a date and time (YYYY-MM-DDTHH:MM[:SS])", now))
+}
+
+/// Highlighter for code blocks shown in the terminal
+fn terminal_highlighter(theme: Option<&str>) -> Result<Highlighter, RustyDiaryError> {
+    Highlighter::new(theme.unwrap_or(highlight::DEFAULT_TERMINAL_THEME))
+}
+
+/// Optional CLI bounds as a date range; missing bounds are open-ended
+fn date_range(from: Option<NaiveDate>, to: Option<NaiveDate>) -> DateRange {
+    DateRange::new(from.unwrap_or(NaiveDate::MIN), to.unwrap_or(NaiveDate::MAX))
+}
+
+fn sync_and_write(diary: &RustyDiary, json: bool, strict: bool) -> anyhow::Result<()> {
+    info!("Processing diary entries...");
+    let report = diary.sync()
+        .context("Failed to synchronize new entries")?;
+
+    info!("Writing journal entries from {} to {}", report.start_date, report.end_date);
+    let journal = diary.write_journal(report.start_date, report.end_date)
+        .context("Failed to write journal")?;
+
+    let (skipped, lossy, cleanup_failed) =
+        (report.skipped.len(), report.lossy.len(), report.cleanup_failed.len());
+    if json {
+        print_json(&SyncOutput { report, journal })?;
     }
 
-    if let Some(db) = &cli.db {
-        config = config.with_db(db);
+    if skipped + lossy + cleanup_failed == 0 {
+        info!("Successfully processed all entries");
+    } else if strict {
+        return Err(StrictViolation(format!(
+            "{} files skipped, {} read lossily and {} files not cleaned up", skipped, lossy, cleanup_failed
+        )).into());
     }
+    Ok(())
+}
 
-    if let Some(pattern) = &cli.date_pattern {
-        config = config.with_date_pattern(pattern);
+fn preview_sync(diary: &RustyDiary, json: bool) -> anyhow::Result<()> {
+    let preview = diary.preview_sync().context("Failed to preview the sync")?;
+    print_preview(&preview, diary.directory(), json)
+}
+
+/// Prints what a sync or import would store, with sources shown relative to `base`
+fn print_preview(preview: &ImportPreview, base: &Path, json: bool) -> anyhow::Result<()> {
+    if json {
+        return print_json(preview);
+    }
+
+    let relative = |path: &Path| path.strip_prefix(base).unwrap_or(path).display().to_string();
+    println!("{:<32}  {:<10}  {:>6}  {:>11}  {:<24}  Conflicts", "Source", "Date", "Words", "Attachments", "Tags");
+    for item in &preview.items {
+        let conflicts: Vec<String> = item
+            .conflicts
+            .iter()
+            .map(|conflict| match conflict {
+                ImportConflict::Duplicate => "duplicate, not stored".to_string(),
+                ImportConflict::NewVersion => "new version of a stored day".to_string(),
+                ImportConflict::SameDay { sources } => {
+                    format!("merged with {}", sources.iter().map(|source| relative(source)).collect::<Vec<_>>().join(", "))
+                }
+            })
+            .collect();
+        println!(
+            "{:<32}  {:<10}  {:>6}  {:>11}  {:<24}  {}",
+            relative(&item.source),
+            item.date,
+            item.words,
+            item.attachments,
+            item.tags.iter().map(|tag| format!("#{}", tag)).collect::<Vec<_>>().join(" "),
+            conflicts.join("; ")
+        );
+    }
+    for (label, files) in [("SKIPPED", &preview.skipped), ("PENDING", &preview.pending)] {
+        for file in files {
+            println!("{}  {}: {}", label, relative(&file.path), file.reason);
+        }
     }
 
-    config
+    let duplicates = preview.items.iter().filter(|item| item.conflicts.contains(&ImportConflict::Duplicate)).count();
+    println!(
+        "Would store {} of {} items; {} duplicates, {} skipped and {} pending files",
+        preview.items.len() - duplicates,
+        preview.items.len(),
+        duplicates,
+        preview.skipped.len(),
+        preview.pending.len()
+    );
+    Ok(())
 }
 
-async fn run(cli: Cli) -> anyhow::Result<()> {
-    info!("Starting Rusty Diary...");
+async fn run_daemon(
+    diary: RustyDiary,
+    db_path: &Path,
+    interval: u64,
+    metrics_addr: Option<SocketAddr>,
+    mut reminder: Option<Reminder>,
+) -> anyhow::Result<()> {
+    let metrics = Arc::new(SyncMetrics::new());
+    if let Some(addr) = metrics_addr {
+        let listener = TcpListener::bind(addr).await
+            .with_context(|| format!("Failed to bind metrics endpoint on {}", addr))?;
+        info!("Serving metrics on http://{}/metrics", addr);
+        tokio::spawn(serve_metrics(listener, Arc::clone(&metrics)));
+    }
 
-    let config = build_config(&cli);
-    info!("Configuration loaded from CLI: {:?}", config);
+    // Reads use the storage's read-only pool directly; every write goes
+    // through the queue's single worker, one at a time
+    let events = diary.subscribe();
+    let diary = Arc::new(diary);
+    let writes = WriteQueue::spawn(Arc::clone(&diary)).context("Failed to start the write queue")?;
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval.max(1)));
+    let mut reminder_ticker = tokio::time::interval(Duration::from_secs(60));
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            // A due reminder syncs first, so entries written since the last sync count
+            _ = reminder_ticker.tick(), if reminder.is_some() => {
+                if reminder.and_then(|reminder| reminder.due(clock().now())).is_none() {
+                    continue;
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                info!("Shutting down");
+                return Ok(());
+            }
+        }
 
-    let diary = RustyDiary::new(config)
-        .context("Failed to initialize diary")?;
+        let started = Instant::now();
+        let result = tokio::task::block_in_place(|| {
+            writes.submit(|diary: &RustyDiary| {
+                let (start_date, end_date) = diary.synchronize()?;
+                diary.write_journal(start_date, end_date)
+            })
+        });
+        metrics.observe_sync(started.elapsed(), &result);
+        for event in events.try_iter() {
+            metrics.observe_event(&event);
+        }
+        if let Ok(meta) = std::fs::metadata(db_path) {
+            metrics.set_db_size(meta.len());
+        }
 
-    info!("Processing diary entries...");
-    let (start_date, end_date) = diary.synchronize()
-        .context("Failed to synchronize new entries")?;
+        match result {
+            Ok(journal) if journal.is_up_to_date() => info!("Sync finished; journal up to date"),
+            Ok(journal) => info!("Sync finished; wrote {} journal files", journal.written.len()),
+            Err(RustyDiaryError::NoFilesFound(_)) => debug!("No new diary files"),
+            Err(err) => warn!("Sync failed: {}", err),
+        }
+
+        if let Some(reminder) = &mut reminder {
+            if let Err(err) = diary.remind(reminder, clock().now()) {
+                warn!("Reminder check failed: {}", err);
+            }
+        }
+    }
+}
+
+/// A time of day as HH:MM
+fn parse_time(time: &str) -> anyhow::Result<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M").with_context(|| format!("'{}' is not a time as HH:MM", time))
+}
+
+async fn serve_metrics(listener: TcpListener, metrics: Arc<SyncMetrics>) {
+    loop {
+        let mut stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                warn!("Failed to accept metrics connection: {}", err);
+                continue;
+            }
+        };
+
+        let metrics = Arc::clone(&metrics);
+        tokio::spawn(async move {
+            let mut request = [0u8; 1024];
+            let read = stream.read(&mut request).await.unwrap_or(0);
+            let response = metrics_response(&String::from_utf8_lossy(&request[..read]), &metrics);
+            if let Err(err) = stream.write_all(response.as_bytes()).await {
+                debug!("Failed to write metrics response: {}", err);
+            }
+        });
+    }
+}
+
+/// Minimal HTTP/1.1 response for a metrics scrape; only `GET /metrics` is served
+fn metrics_response(request: &str, metrics: &SyncMetrics) -> String {
+    if !request.starts_with("GET /metrics ") {
+        return "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string();
+    }
+
+    let body = metrics.render();
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn run_tags_command(diary: &RustyDiary, command: &TagsCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        TagsCommand::Suggest { date, limit } => {
+            let suggestions = diary.suggest_tags(*date, *limit)
+                .context("Failed to suggest tags")?
+                .with_context(|| format!("No entry for {}", date))?;
+            if json {
+                return print_json(&suggestions);
+            }
+            for suggestion in &suggestions {
+                println!("#{:<24} {:.2}", suggestion.tag, suggestion.confidence);
+            }
+        }
+        TagsCommand::Show { date } => {
+            let entry = diary.storage().latest_entry(*date)?
+                .with_context(|| format!("No entry for {}", date))?;
+            let stored = diary.storage().stored_tags(*date).context("Failed to read stored tags")?;
+            if json {
+                return print_json(&json!({ "content": entry.tags(), "stored": stored }));
+            }
+            for tag in entry.tags() {
+                println!("#{:<24} content", tag);
+            }
+            for tag in &stored {
+                match tag.confidence {
+                    Some(confidence) => println!("#{:<24} {} ({:.2})", tag.tag, tag.source, confidence),
+                    None => println!("#{:<24} {}", tag.tag, tag.source),
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_review_command(diary: &RustyDiary, command: &ReviewCommand, json: bool) -> anyhow::Result<()> {
+    let ReviewCommand::Week { week, template, no_edit } = command;
+    let today = clock().today();
+    let week = DateRange::week_of(week.unwrap_or(today - chrono::Duration::days(7)));
+    let template = template
+        .as_ref()
+        .map(|path| std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display())))
+        .transpose()?;
+    let review = diary.weekly_review(week, template.as_deref()).context("Failed to assemble the review")?;
+
+    // An entry already begun today keeps its text, with the review after
+    // it; one that has the review already is only opened again
+    let path = diary.directory().join(format!("{}.md", today));
+    let heading = review.lines().next().unwrap_or_default();
+    let content = match std::fs::read_to_string(&path) {
+        Ok(existing) if existing.lines().any(|line| line == heading) => None,
+        Ok(existing) => Some(format!("{}\n\n{}", existing.trim_end(), review)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some(review),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if let Some(content) = content {
+        std::fs::write(&path, content).with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+
+    if !*no_edit {
+        cli::editor::edit(&path)?;
+    }
+    if json {
+        return print_json(&json!({ "path": path, "week": week }));
+    }
+    println!("Wrote the review of {} to {}; run a sync to store it", week.start.format("%G-W%V"), path.display());
+    Ok(())
+}
+
+fn run_meta_command(diary: &RustyDiary, command: &MetaCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        MetaCommand::Set { date, key, value } => {
+            diary.storage().set_meta(*date, key, value).context("Failed to set metadata")?;
+            info!("Set {} of {}", key, date);
+            if json {
+                print_json(&json!({ "date": date, "key": key, "value": value }))?;
+            }
+        }
+        MetaCommand::Get { date, key: Some(key) } => {
+            let value = diary.storage().get_meta(*date, key).context("Failed to read metadata")?
+                .with_context(|| format!("{} has no {}", date, key))?;
+            if json {
+                return print_json(&json!({ "date": date, "key": key, "value": value }));
+            }
+            println!("{}", value);
+        }
+        MetaCommand::Get { date, key: None } => {
+            let fields = diary.storage().meta(*date).context("Failed to read metadata")?;
+            if json {
+                return print_json(&fields);
+            }
+            for (key, value) in &fields {
+                println!("{:<24} {}", key, value);
+            }
+        }
+        MetaCommand::Unset { date, key } => {
+            let removed = diary.storage().unset_meta(*date, key).context("Failed to remove metadata")?;
+            info!("{} {} of {}", if removed { "Removed" } else { "Nothing to remove:" }, key, date);
+            if json {
+                print_json(&json!({ "date": date, "key": key, "removed": removed }))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_runs_command(diary: &RustyDiary, command: &RunsCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        RunsCommand::List => {
+            let runs = diary.storage().sync_runs().context("Failed to read sync runs")?;
+            if json {
+                return print_json(&runs);
+            }
+            println!("{:>5}  {:<19}  {:>6} {:>6} {:>10} {:>7} {:>9}", "Run", "Started", "Files", "Added", "Duplicates", "Skipped", "Duration");
+            for run in &runs {
+                let started = run.started_at.format("%Y-%m-%d %H:%M:%S");
+                match (&run.stats, run.finished_at) {
+                    (Some(stats), Some(_)) => println!(
+                        "{:>5}  {:<19}  {:>6} {:>6} {:>10} {:>7} {:>8.1}s",
+                        run.id, started, stats.files, stats.stored, stats.duplicates, stats.skipped,
+                        stats.duration_ms as f64 / 1000.0
+                    ),
+                    (None, Some(_)) => println!("{:>5}  {:<19}  (no statistics recorded)", run.id, started),
+                    (_, None) => println!("{:>5}  {:<19}  (unfinished)", run.id, started),
+                }
+            }
+        }
+        RunsCommand::Show { id } => {
+            let run = diary.storage().sync_run(*id)?.with_context(|| format!("No sync run {}", id))?;
+            let entries: Vec<_> = diary.storage().get_entries_by_exec_version(*id)?
+                .iter()
+                .map(|entry| entry.summary())
+                .collect();
+            if json {
+                return print_json(&json!({ "run": run, "entries": entries }));
+            }
+            let time = |time: Option<chrono::NaiveDateTime>| {
+                time.map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            };
+            println!("Run:             {}", run.id);
+            println!("Started:         {}", time(Some(run.started_at)));
+            println!("Finished:        {}", time(run.finished_at));
+            println!("Normalization:   {}", run.normalization.as_deref().unwrap_or("-"));
+            if let Some(stats) = &run.stats {
+                println!("Files:           {}", stats.files);
+                println!("Added:           {}", stats.stored);
+                println!("Duplicates:      {}", stats.duplicates);
+                println!("Skipped:         {}", stats.skipped);
+                println!("Duration:        {:.1}s", stats.duration_ms as f64 / 1000.0);
+                if let Some(error) = &stats.error {
+                    println!("Error:           {}", error);
+                }
+            }
+            for entry in &entries {
+                println!("  {}  {:>6} words", entry.date, entry.word_count);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_check_links(
+    diary: &RustyDiary,
+    attachments: Option<&Path>,
+    fix: bool,
+    json: bool,
+    strict: bool,
+) -> anyhow::Result<()> {
+    let broken = diary.check_links(attachments).context("Failed to check links")?;
+    let fixed = if fix { diary.fix_links(&broken).context("Failed to rewrite links")? } else { 0 };
+    if fixed > 0 {
+        info!("Rewrote links in {} entries", fixed);
+    }
+
+    if json {
+        print_json(&json!({ "broken": broken, "fixed_entries": fixed }))?;
+    } else {
+        for link in &broken {
+            let problem = match link.problem {
+                LinkProblem::MissingFile => "missing file ",
+                LinkProblem::MissingEntry => "missing entry",
+            };
+            match &link.replacement {
+                Some(replacement) => println!("{}  v{:<4} {}  {} -> {}", link.date, link.exec_version, problem, link.target, replacement),
+                None => println!("{}  v{:<4} {}  {}", link.date, link.exec_version, problem, link.target),
+            }
+        }
+    }
+
+    let unresolved = broken.iter().filter(|link| !(fix && link.replacement.is_some())).count();
+    if unresolved > 0 && strict {
+        return Err(StrictViolation(format!("{} broken links", unresolved)).into());
+    }
+    Ok(())
+}
+
+fn run_check_style(diary: &RustyDiary, range: DateRange, rules: &StyleRules, json: bool, strict: bool) -> anyhow::Result<()> {
+    let reports = diary.check_style(range, rules).context("Failed to check style")?;
+    let issues: usize = reports.iter().map(|report| report.issues.len()).sum();
+    if json {
+        print_json(&reports)?;
+    } else {
+        for report in reports.iter().filter(|report| !report.issues.is_empty()) {
+            println!(
+                "{}  v{:<4} {} words, {} sentences, {} issues",
+                report.date, report.exec_version, report.words, report.sentences, report.issues.len()
+            );
+            for issue in &report.issues {
+                println!("  {:>4}  {:<13} {}: {}", issue.line, issue.rule, issue.message, issue.text);
+            }
+        }
+        let flagged = reports.iter().filter(|report| !report.issues.is_empty()).count();
+        println!("{} issues in {} of {} entries", issues, flagged, reports.len());
+    }
+
+    if issues > 0 && strict {
+        return Err(StrictViolation(format!("{} style issues", issues)).into());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "spellcheck")]
+fn run_check_spelling(
+    diary: &RustyDiary,
+    range: DateRange,
+    lang: &str,
+    dictionary: Option<&Path>,
+    suggestions: usize,
+    json: bool,
+    strict: bool,
+) -> anyhow::Result<()> {
+    let misspelled = diary.check_spelling(range, lang, dictionary, suggestions).context("Failed to check spelling")?;
+    if json {
+        print_json(&misspelled)?;
+    } else {
+        for misspelling in &misspelled {
+            let suggested = if misspelling.suggestions.is_empty() {
+                String::new()
+            } else {
+                format!(" -> {}", misspelling.suggestions.join(", "))
+            };
+            println!(
+                "{}  v{:<4} {:>4}  {}{}  {}",
+                misspelling.date, misspelling.exec_version, misspelling.line, misspelling.word, suggested, misspelling.context
+            );
+        }
+    }
+
+    if !misspelled.is_empty() && strict {
+        return Err(StrictViolation(format!("{} likely misspellings", misspelled.len())).into());
+    }
+    Ok(())
+}
+
+fn run_people_command(
+    diary: &RustyDiary,
+    command: &PeopleCommand,
+    highlight_theme: Option<&str>,
+    flavor: MarkdownFlavor,
+    json: bool,
+) -> anyhow::Result<()> {
+    match command {
+        PeopleCommand::List { from, to, limit } => {
+            let range = date_range(*from, *to);
+            let mut people = diary.storage().people(range.start, range.end).context("Failed to read people")?;
+            people.truncate(limit.unwrap_or(usize::MAX));
+            if json {
+                return print_json(&people);
+            }
+            println!("{:<24} {:>6} {:>9}  {:<10}  {:<10}", "Name", "Days", "Mentions", "First", "Last");
+            for person in &people {
+                println!(
+                    "{:<24} {:>6} {:>9}  {:<10}  {:<10}",
+                    person.name, person.days, person.mentions, person.first_date, person.last_date
+                );
+            }
+        }
+        PeopleCommand::Show { name, color } => {
+            let entries = diary.storage().person_entries(name).context("Failed to read entries")?;
+            anyhow::ensure!(!entries.is_empty(), "No entry mentions {}", name);
+            if json {
+                return print_json(&entries);
+            }
+            let highlighter = terminal_highlighter(highlight_theme)?;
+            print!("{}", cli::show::format_entries(&entries, false, use_color(color), Some(&highlighter), flavor));
+        }
+        PeopleCommand::Rebuild => {
+            let pairs = diary.storage().rebuild_people().context("Failed to rebuild the people index")?;
+            info!("Recorded {} mentions of people by day", pairs);
+            if json {
+                print_json(&json!({ "people_by_day": pairs }))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_events_command(diary: &RustyDiary, command: &EventsCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        EventsCommand::List { from, to } => {
+            let range = date_range(*from, *to);
+            let events = diary.storage().events(range.start, range.end).context("Failed to read events")?;
+            if json {
+                return print_json(&events);
+            }
+            for event in &events {
+                println!("{}  {}", event.date, event.text);
+            }
+        }
+        EventsCommand::Rebuild => {
+            let events = diary.storage().rebuild_events().context("Failed to rebuild events")?;
+            info!("Recorded {} events", events);
+            if json {
+                print_json(&json!({ "events": events }))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_dictionary_command(diary: &RustyDiary, command: &DictionaryCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        DictionaryCommand::Add { words, lang } => {
+            let added = diary.storage().add_spelling_words(lang, words).context("Failed to add words")?;
+            info!("Added {} words to the {} dictionary", added, lang);
+            if json {
+                print_json(&json!({ "lang": lang, "added": added }))?;
+            }
+        }
+        DictionaryCommand::Remove { word, lang } => {
+            let removed = diary.storage().remove_spelling_word(lang, word).context("Failed to remove word")?;
+            info!("{} {} from the {} dictionary", if removed { "Removed" } else { "Nothing to remove:" }, word, lang);
+            if json {
+                print_json(&json!({ "lang": lang, "word": word, "removed": removed }))?;
+            }
+        }
+        DictionaryCommand::List { lang } => {
+            let words = diary.storage().spelling_words(lang).context("Failed to read the dictionary")?;
+            if json {
+                return print_json(&words);
+            }
+            for word in &words {
+                println!("{}", word);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_attachments_gc(diary: &RustyDiary, dry_run: bool, json: bool) -> anyhow::Result<()> {
+    let unused = diary.collect_attachment_garbage(dry_run).context("Failed to collect unused attachments")?;
+    let bytes: u64 = unused.iter().map(|attachment| attachment.size).sum();
+    if json {
+        return print_json(&json!({ "dry_run": dry_run, "unused": unused, "bytes": bytes }));
+    }
+
+    for attachment in &unused {
+        println!("{:>10}  {}", attachment.size, attachment.path.display());
+    }
+    println!(
+        "{} {} unused attachments, {} bytes",
+        if dry_run { "Would remove" } else { "Removed" },
+        unused.len(),
+        bytes
+    );
+    Ok(())
+}
+
+fn run_graph_command(diary: &RustyDiary, command: &GraphCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        GraphCommand::Export { format, out } => {
+            let links = diary.storage().links().context("Failed to read links")?;
+            let graph = cli::graph::LinkGraph::new(&links);
+            let rendered = match format.as_str() {
+                "json" => serde_json::to_string_pretty(&graph)? + "\n",
+                _ => graph.to_dot(),
+            };
+            match out {
+                Some(path) => {
+                    std::fs::write(path, rendered)
+                        .with_context(|| format!("Failed to write {}", path.display()))?;
+                    info!("Exported {} links to {}", links.len(), path.display());
+                    if json {
+                        print_json(&json!({ "links": links.len(), "path": path }))?;
+                    }
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        GraphCommand::Rebuild => {
+            let links = diary.storage().rebuild_links().context("Failed to rebuild links")?;
+            info!("Recorded {} links", links);
+            if json {
+                print_json(&json!({ "links": links }))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run_db_command(diary: &RustyDiary, command: &DbCommand, json: bool) -> anyhow::Result<()> {
+    match command {
+        DbCommand::Recompress => {
+            let changed = diary.storage().recompress()
+                .context("Failed to recompress stored entries")?;
+            info!("Recompressed {} entries", changed);
+            if json {
+                print_json(&json!({ "recompressed": changed }))?;
+            }
+        }
+        DbCommand::Migrate { .. } | DbCommand::Info => {
+            unreachable!("handled before opening the diary")
+        }
+        DbCommand::Shard { year } => {
+            let years = match year {
+                Some(year) => vec![*year],
+                None => completed_years(diary)?,
+            };
+
+            let mut sharded = Vec::new();
+            for year in years {
+                let moved = diary.storage().archive_year(year)
+                    .with_context(|| format!("Failed to shard {}", year))?;
+                info!("Moved {} entries from {} into its shard", moved, year);
+                sharded.push(json!({ "year": year, "moved": moved }));
+            }
+            if json {
+                print_json(&sharded)?;
+            }
+        }
+        DbCommand::RescanHabits => {
+            let days = diary.rescan_habits().context("Failed to rescan habits")?;
+            info!("Read the habits of {} days", days);
+            if json {
+                print_json(&json!({ "days": days }))?;
+            }
+        }
+        DbCommand::Reindex => {
+            let report = diary.storage().reindex().context("Failed to reindex the database")?;
+            info!("Reindexed {} entries", report.entries);
+            for table in report.tables.iter().filter(|table| table.drifted()) {
+                warn!("{} had drifted: {} rows, now {}", table.table, table.before, table.after);
+            }
+            if json {
+                print_json(&report)?;
+            }
+        }
+        DbCommand::RescanHighlights => {
+            let highlights = diary.storage().rebuild_highlights().context("Failed to rescan highlights")?;
+            info!("Found {} highlights", highlights);
+            if json {
+                print_json(&json!({ "highlights": highlights }))?;
+            }
+        }
+        DbCommand::Analyze => {
+            let analyzed = diary.storage().analyze()
+                .context("Failed to analyze the database")?;
+            info!("Analyzed {} databases", analyzed);
+            if json {
+                print_json(&json!({ "analyzed": analyzed }))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn run_migrate(config: &Config, to: Option<i32>, dry_run: bool, json: bool) -> anyhow::Result<()> {
+    let target = to.unwrap_or_else(migrations::latest_version);
+    let (plan, backup_path) = migrations::migrate_to(&config.db_path, target, dry_run, config.clock.now())
+        .context("Failed to migrate database")?;
+
+    if json {
+        return print_json(&json!({ "dry_run": dry_run, "plan": plan, "backup": backup_path }));
+    }
+
+    if plan.is_empty() {
+        println!("Schema is at version {}; nothing to do", plan.current_version);
+        return Ok(());
+    }
+
+    println!(
+        "{} schema from version {} to {}:",
+        if dry_run { "Would migrate" } else { "Migrated" },
+        plan.current_version,
+        plan.target_version
+    );
+    for step in &plan.steps {
+        println!("  {:?} v{}: {}", step.direction, step.version, step.description);
+    }
+    if let Some(path) = backup_path {
+        println!("Backup written to {}", path.display());
+    }
+
+    Ok(())
+}
+
+fn run_backup_command(config: &Config, command: &BackupCommand, json: bool) -> anyhow::Result<()> {
+    let age = |command: &str, identities: &[PathBuf], encryption: Option<&EncryptionArgs>| -> anyhow::Result<backup::Age> {
+        let mut age = backup::Age::new().with_command(command);
+        for identity in identities {
+            age = age.with_identity(identity);
+        }
+        if let Some(encryption) = encryption {
+            age = age.with_passphrase(encryption.passphrase);
+            for recipient in &encryption.recipients {
+                age = age.with_recipient(recipient);
+            }
+            for file in &encryption.recipients_files {
+                age = age.with_recipients_file(file);
+            }
+            anyhow::ensure!(
+                age.encrypts() || encryption.no_encrypt,
+                "Backups are encrypted: pass --recipient, --recipients-file or --passphrase, \
+                 or --no-encrypt to write one in the clear"
+            );
+        }
+        Ok(age)
+    };
+    let print_written = |output: &Path, manifest: &backup::BackupManifest, encrypted: bool| {
+        if json {
+            return print_json(&json!({ "path": output, "encrypted": encrypted, "manifest": manifest }));
+        }
+        println!(
+            "Backed up {} files ({} bytes) to {}{}{}",
+            manifest.files.len(),
+            manifest.size(),
+            output.display(),
+            manifest.parent.as_ref().map_or(String::new(), |parent| format!(", building on {}", parent)),
+            if encrypted { ", encrypted" } else { "" }
+        );
+        Ok(())
+    };
+    let print_verification = |verification: &backup::Verification| {
+        let manifest = &verification.manifest;
+        println!(
+            "Chain of {} backups, the last one {} taken {} by rusty_diary {}, schema version {}",
+            verification.backups,
+            manifest.id,
+            manifest.created_at.format("%Y-%m-%d %H:%M:%S"),
+            manifest.crate_version,
+            manifest.schema_version
+        );
+        for (table, rows) in &manifest.tables {
+            println!("  {:<24} {:>8} rows", table, rows);
+        }
+        if !verification.config_matches {
+            warn!("The backup was taken with different settings than the current configuration");
+        }
+        for problem in &verification.problems {
+            println!("PROBLEM  {}", problem);
+        }
+    };
+
+    match command {
+        BackupCommand::Create { output, incremental, encryption, age: command } => {
+            let age = age(command, &[], Some(encryption))?;
+            let output = output.clone().unwrap_or_else(|| {
+                PathBuf::from(format!("rusty-diary-{}.{}", clock().now().format("%Y%m%d-%H%M%S"), backup::EXTENSION))
+            });
+            let manifest = if *incremental {
+                backup::create_incremental(config, &output, &age)
+            } else {
+                backup::create(config, &output, &age)
+            };
+            let manifest = manifest.context("Failed to back up the diary")?;
+            print_written(&output, &manifest, age.encrypts())
+        }
+        BackupCommand::Verify { files, identities, age: command } => {
+            let verification = backup::verify(config, files, &age(command, identities, None)?)
+                .context("Failed to verify the backup")?;
+            if json {
+                print_json(&verification)?;
+            } else {
+                print_verification(&verification);
+            }
+            anyhow::ensure!(verification.is_ok(), "The backup has {} problems", verification.problems.len());
+            if !json {
+                println!("ok");
+            }
+            Ok(())
+        }
+        BackupCommand::Restore { files, identities, age: command, force } => {
+            let verification = backup::restore(config, files, &age(command, identities, None)?, *force)
+                .context("Failed to restore the backup")?;
+            if json {
+                return print_json(&verification);
+            }
+            print_verification(&verification);
+            println!("Restored to {}", config.db_path.display());
+            Ok(())
+        }
+        BackupCommand::Compact { files, output, identities, encryption, age: command } => {
+            let age = age(command, identities, Some(encryption))?;
+            let manifest = backup::compact(config, files, output, &age).context("Failed to compact the backups")?;
+            print_written(output, &manifest, age.encrypts())
+        }
+        BackupCommand::List => {
+            let backups = backup::history(config).context("Failed to read the recorded backups")?;
+            if json {
+                return print_json(&backups);
+            }
+            for backup in &backups {
+                println!(
+                    "{}  {}  {:<11} run {:<5} {}",
+                    backup.id,
+                    backup.created_at.format("%Y-%m-%d %H:%M"),
+                    if backup.parent.is_some() { "incremental" } else { "full" },
+                    backup.exec_version,
+                    backup.path
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+fn run_restore_as_of(config: &Config, as_of: NaiveDate, into: &Path, json: bool) -> anyhow::Result<()> {
+    let restored = backup::restore_as_of(config, as_of, into)
+        .with_context(|| format!("Failed to restore the diary as of {}", as_of))?;
+    if json {
+        return print_json(&restored);
+    }
+    println!(
+        "Restored {} versions of {} days as of {} (run {}) to {}",
+        restored.entries,
+        restored.days,
+        restored.as_of,
+        restored.exec_version,
+        restored.db_path.display()
+    );
+    for path in &restored.journal.written {
+        println!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_init(config: &Config, json: bool) -> anyhow::Result<()> {
+    let created = setup::init(config).context("Failed to initialize the diary")?;
+    if json {
+        return print_json(&json!({ "created": created }));
+    }
+
+    if created.is_empty() {
+        println!("Already initialized: {}", config.directory.display());
+    }
+    for path in &created {
+        println!("Created {}", path.display());
+    }
+    Ok(())
+}
+
+fn run_doctor(config: &Config, json: bool) -> anyhow::Result<()> {
+    let findings = setup::doctor(config);
+    if json {
+        print_json(&findings)?;
+    } else {
+        for finding in &findings {
+            let status = match finding.status {
+                Status::Ok => "ok",
+                Status::Warning => "warning",
+                Status::Problem => "PROBLEM",
+            };
+            println!("{:<8} {:<12} {}", status, finding.check, finding.message);
+        }
+    }
+
+    let problems = findings.iter().filter(|finding| finding.status == Status::Problem).count();
+    if problems > 0 {
+        anyhow::bail!("doctor found {} problems", problems);
+    }
+    Ok(())
+}
+
+fn run_info(config: &Config, json: bool) -> anyhow::Result<()> {
+    let info = storage::inspect(&config.db_path)
+        .with_context(|| format!("Failed to inspect {}", config.db_path.display()))?;
+    if json {
+        return print_json(&info);
+    }
 
-    info!("Writing journal entries from {} to {}", start_date, end_date);
-    diary.write_journal(start_date, end_date).context("Failed to write journal")?;
+    let schema = &info.schema;
+    let count = |value: Option<i64>| value.map_or("-".to_string(), |n| n.to_string());
+
+    println!("Database:        {}", info.path.display());
+    println!("Size:            {} bytes", info.size_bytes);
+    println!("Schema version:  {}", schema.version);
+    println!("Supported:       {} to {} (rusty_diary {})",
+        schema.min_supported, schema.max_supported, info.crate_version);
+    match &schema.incompatibility {
+        Some(reason) => println!("Compatible:      no — {}", reason),
+        None => println!("Compatible:      yes"),
+    }
+    for step in &schema.pending {
+        println!("Pending:         v{} {}", step.version, step.description);
+    }
+    println!("Entries:         {}", count(info.entry_count));
+    println!("Year shards:     {}", count(info.shard_count));
+    println!("Cold archives:   {}", count(info.cold_archive_count));
 
-    info!("Successfully processed all entries");
     Ok(())
 }
 
+/// Years before the current one that still have entries in the main database
+fn completed_years(diary: &RustyDiary) -> anyhow::Result<Vec<i32>> {
+    let current_year = clock().today().year();
+    let last_completed = DateRange::year(current_year - 1)
+        .context("Invalid current date")?
+        .end;
+    let archived = diary.storage().shard_years()?;
+
+    let mut years: Vec<i32> = diary.storage()
+        .list_entries(NaiveDate::MIN, last_completed)?
+        .iter()
+        .map(|summary| summary.date.year())
+        .filter(|year| !archived.contains(year))
+        .collect();
+    years.sort_unstable();
+    years.dedup();
+
+    Ok(years)
+}
+
 #[tokio::main]
 async fn main() {
-    let cli = Cli::from_args();
-    setup_logging(cli.verbose);
+    let args = match with_config_file(std::env::args_os().collect()) {
+        Ok(args) => args,
+        Err(err) => fail(err),
+    };
+    let matches = Cli::clap().get_matches_from(args);
+    if let Some(now) = matches.value_of("now").and_then(|now| parse_now(now).ok()) {
+        let _ = CLOCK.set(FixedClock(now));
+    }
+    let cli = Cli::from_clap(&matches);
+    setup_logging(&cli);
 
     if let Err(err) = run(cli).await {
-        eprintln!("Error: {:?}", err);
-        std::process::exit(1);
+        fail(err);
+    }
+}
+
+fn fail(err: anyhow::Error) -> ! {
+    match err.chain().find_map(|cause| cause.downcast_ref::<RustyDiaryError>()) {
+        Some(cause) => eprintln!("Error [{}]: {:?}", cause.kind(), err),
+        None => eprintln!("Error: {:?}", err),
     }
+    std::process::exit(exit_code(&err) as i32);
+}
+
+/// The command line with the options of the diary's config file in front,
+/// leaving out those the command line sets itself
+fn with_config_file(args: Vec<OsString>) -> anyhow::Result<Vec<OsString>> {
+    // Bad arguments are left for the real parse to report
+    let Ok(matches) = Cli::clap().get_matches_from_safe(&args) else { return Ok(args) };
+    let directory = matches.value_of_os("directory").map_or_else(|| Config::new().directory, PathBuf::from);
+    let options = setup::config_options(&directory).context("Failed to read the config file")?;
+    let Some((program, given)) = args.split_first() else { return Ok(args) };
+
+    let is_given = |name: &str| {
+        let flag = format!("--{}", name);
+        given.iter().any(|arg| {
+            let arg = arg.to_string_lossy();
+            arg == flag || arg.starts_with(&format!("{}=", flag))
+        })
+    };
+    let mut merged = vec![program.clone()];
+    for (name, value) in options.iter().filter(|(name, _)| !is_given(name)) {
+        merged.push(match value {
+            Some(value) => format!("--{}={}", name, value).into(),
+            None => format!("--{}", name).into(),
+        });
+    }
+    merged.extend(given.iter().cloned());
+    Ok(merged)
 }
 
 #[cfg(test)]
@@ -108,10 +2862,305 @@ mod tests {
             db: Some(temp_db),
             date_pattern: None,
             verbose: 0,
+            quiet: 0,
+            log_format: "text".to_string(),
+            output: "text".to_string(),
+            strict: false,
+            dry_run: false,
+            no_compress: false,
+            pre_sync_hook: Vec::new(),
+            entry_hook: Vec::new(),
+            journal_hook: Vec::new(),
+            reminder_hook: Vec::new(),
+            sqlite_profile: None,
+            search_stemmer: Vec::new(),
+            search_fold_diacritics: false,
+            search_stopword: Vec::new(),
+            highlight_theme: None,
+            markdown_flavor: MarkdownFlavor::CommonMark,
+            plugin_dir: None,
+            journal_template: None,
+            max_depth: 1,
+            path_dates: PathDates::Ignore,
+            min_file_age: 0,
+            normalize: Normalization::Lf,
+            lossy_utf8: false,
+            split_date_headings: false,
+            photos: false,
+            auto_tag: false,
+            auto_tag_threshold: 0.3,
+            default_author: None,
+            location: None,
+            git_repo: Vec::new(),
+            habit: Vec::new(),
+            saved_search: Vec::new(),
+            ignore: Vec::new(),
+            journal_file: None,
+            output_filename: None,
+            journal_toc: false,
+            locale: chrono::Locale::en_US,
+            first_weekday: chrono::Weekday::Mon,
+            secondary_calendar: None,
+            journal_split: JournalSplit::None,
+            now: None,
+            command: None,
         };
 
-        let config = build_config(&cli);
+        let config = build_config(&cli)?;
         assert_eq!(config.directory, temp_dir.path());
         Ok(())
     }
+
+    #[test]
+    fn test_now_override() -> anyhow::Result<()> {
+        let at = |h, m, sec| NaiveDate::from_ymd_opt(2024, 3, 6).unwrap().and_hms_opt(h, m, sec).unwrap();
+        assert_eq!(parse_now("2024-03-06"), Ok(at(0, 0, 0)));
+        assert_eq!(parse_now("2024-03-06T07:30"), Ok(at(7, 30, 0)));
+        assert_eq!(parse_now("2024-03-06 07:30:15"), Ok(at(7, 30, 15)));
+        assert!(parse_now("yesterday").is_err() && parse_now("2024-03-06T25:00").is_err());
+
+        let cli = Cli::from_iter(["rusty_diary", "--now", "2024-03-06T07:30", "list"]);
+        assert_eq!(cli.now, Some(at(7, 30, 0)));
+        assert_eq!(build_config(&cli)?.clock.now(), at(7, 30, 0));
+        assert!(Cli::from_iter_safe(["rusty_diary", "--now", "soon", "list"]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_file_options_yield_to_the_command_line() -> anyhow::Result<()> {
+        let temp_dir = TempDir::new()?;
+        std::fs::write(temp_dir.path().join(setup::CONFIG_FILE), "# Mine\nphotos\nhabit = run\ndb = diary.db\n")?;
+
+        let args: Vec<OsString> = vec![
+            "rusty_diary".into(),
+            "--db".into(),
+            "mine.db".into(),
+            temp_dir.path().into(),
+            "list".into(),
+        ];
+        let cli = Cli::from_iter(with_config_file(args)?);
+        assert!(cli.photos);
+        assert_eq!(cli.habit, ["run"]);
+        assert_eq!(cli.db, Some(PathBuf::from("mine.db")));
+
+        std::fs::write(temp_dir.path().join(setup::CONFIG_FILE), "habit run\n")?;
+        assert!(with_config_file(vec!["rusty_diary".into(), temp_dir.path().into()]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_log_level_from_flags() {
+        assert_eq!(log_level(0, 0), tracing::Level::INFO);
+        assert_eq!(log_level(2, 0), tracing::Level::TRACE);
+        assert_eq!(log_level(0, 1), tracing::Level::WARN);
+        assert_eq!(log_level(0, 3), tracing::Level::ERROR);
+        assert_eq!(log_level(1, 1), tracing::Level::INFO);
+    }
+
+    #[test]
+    fn test_subcommand_parsing() {
+        let cli = Cli::from_iter(["rusty_diary", "--db", "x.db", "db", "recompress"]);
+        assert!(matches!(cli.command, Some(Command::Db(DbCommand::Recompress))));
+        assert!(cli.directory.is_none());
+
+        let cli = Cli::from_iter(["rusty_diary", "db", "analyze"]);
+        assert!(matches!(cli.command, Some(Command::Db(DbCommand::Analyze))));
+
+        let cli = Cli::from_iter(["rusty_diary", "notes", "doctor"]);
+        assert!(matches!(cli.command, Some(Command::Doctor)));
+        assert_eq!(cli.directory, Some(PathBuf::from("notes")));
+
+        let cli = Cli::from_iter(["rusty_diary", "notes"]);
+        assert_eq!(cli.directory, Some(PathBuf::from("notes")));
+        assert!(cli.command.is_none());
+
+        let cli = Cli::from_iter(["rusty_diary", "daemon", "--metrics-addr", "127.0.0.1:9898"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Daemon { interval: 3600, metrics_addr: Some(_), remind_at: None, .. })
+        ));
+        let cli = Cli::from_iter(["rusty_diary", "daemon", "--remind-at", "21:30", "--word-goal", "300"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Daemon { remind_at: Some(at), word_goal: Some(300), .. }) if at == NaiveTime::from_hms_opt(21, 30, 0).unwrap()
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "daemon", "--word-goal", "300"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "review", "week", "--week", "2024-W12", "--no-edit"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Review(ReviewCommand::Week { week: Some(week), no_edit: true, .. }))
+                if week == NaiveDate::from_ymd_opt(2024, 3, 18).unwrap()
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "daemon", "--remind-at", "9pm"]).is_err());
+
+        let cli = Cli::from_iter(["rusty_diary", "--output", "json", "search", "rain", "--from", "2024-01-01"]);
+        assert_eq!(cli.output, "json");
+        assert!(matches!(cli.command, Some(Command::Search { query: Some(ref query), saved: None, from: Some(_), to: None, context: 5, .. }) if query == "rain"));
+        let cli = Cli::from_iter(["rusty_diary", "search", "--saved", "work-frustrations"]);
+        assert!(matches!(cli.command, Some(Command::Search { query: None, saved: Some(_), .. })));
+        assert!(Cli::from_iter_safe(["rusty_diary", "search"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "search", "--regex", r"PROJ-\d+", "--timeout", "2"]);
+        assert!(matches!(cli.command, Some(Command::Search { regex: true, timeout: 2, .. })));
+        let cli = Cli::from_iter(["rusty_diary", "search", "rain walk", "--any-term", "--rank", "--recency-boost", "1.5"]);
+        assert!(matches!(cli.command, Some(Command::Search { any_term: true, rank: true, recency_boost, .. }) if recency_boost == 1.5));
+        assert!(Cli::from_iter_safe(["rusty_diary", "search", "rain", "--phrase", "--any-term"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "search", "--query", "tag:work -tag:meeting"]);
+        assert!(matches!(cli.command, Some(Command::Search { query: None, dsl: Some(_), .. })));
+        assert!(Cli::from_iter_safe(["rusty_diary", "search", "--query", "tag:work", "--saved", "x"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "export", "json", "--out", "x.json", "--query", "#travel after:2019"]);
+        assert!(matches!(cli.command, Some(Command::Export { dsl: Some(_), .. })));
+        let cli = Cli::from_iter(["rusty_diary", "export", "json", "--out", "x.json", "--tag", "travel", "--max-words", "800"]);
+        assert!(matches!(cli.command, Some(Command::Export { ref tag, max_words: Some(800), .. }) if tag == &["travel"]));
+        let cli = Cli::from_iter(["rusty_diary", "export", "html", "--out", "x.html", "--attachments", "embed"]);
+        assert!(matches!(cli.command, Some(Command::Export { attachments: AttachmentMode::Embed, thumbnail_size: 640, .. })));
+        assert!(Cli::from_iter_safe(["rusty_diary", "export", "html", "--out", "x.html", "--attachments", "inline"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "--markdown-flavor", "obsidian", "show", "2024-05-01"]);
+        assert_eq!(cli.markdown_flavor, MarkdownFlavor::Obsidian);
+        let cli = Cli::from_iter(["rusty_diary", "export", "html", "--out", "x.html", "--diagram-command", "mmdc"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Export { ref mermaid_script, diagram_command: Some(_), .. }) if mermaid_script == html::DEFAULT_MERMAID_SCRIPT
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "search", "--regex", "--saved", "x"]).is_err());
+        assert_eq!(date_range(None, None), DateRange::all());
+        let cli = Cli::from_iter(["rusty_diary", "--default-author", "Ana", "stats", "--by-author"]);
+        assert_eq!(cli.default_author.as_deref(), Some("Ana"));
+        assert!(matches!(cli.command, Some(Command::Stats { by_author: true, .. })));
+        let cli = Cli::from_iter(["rusty_diary", "--habit=meditate", "stats", "habits", "--from", "2024-01-01"]);
+        assert!(matches!(cli.command, Some(Command::Stats { report: Some(ref report), from: Some(_), .. }) if report == "habits"));
+        let cli = Cli::from_iter(["rusty_diary", "stats", "terms", "--month", "2024-03", "--stem"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Stats { month: Some(month), limit: 50, stem: true, .. })
+                if month == DateRange::month(2024, 3).unwrap()
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "stats", "--month", "2024-03", "--from", "2024-01-01"]).is_err());
+        assert!(Cli::from_iter_safe(["rusty_diary", "stats", "habits", "--by-author"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "runs", "show", "12"]);
+        assert!(matches!(cli.command, Some(Command::Runs(RunsCommand::Show { id: 12 }))));
+        assert!(cli.command.as_ref().is_some_and(Command::reads_only));
+        let cli = Cli::from_iter(["rusty_diary", "list", "--from", "2023-Q2", "--to", "2023-Q2"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::List { from: Some(from), to: Some(to), starred: false })
+                if from == NaiveDate::from_ymd_opt(2023, 4, 1).unwrap() && to == NaiveDate::from_ymd_opt(2023, 6, 30).unwrap()
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "show", "someday"]).is_err());
+
+        let cli = Cli::from_iter(["rusty_diary", "show", "2024-03-01", "--all-versions", "--color", "never"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Show { raw: false, all_versions: true, ref color, .. }) if color == "never"
+        ));
+
+        let cli = Cli::from_iter(["rusty_diary", "journal", "--tag", "travel", "--tag", "food", "--min-words", "500"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Journal { ref tag, min_words: Some(500), exec_version: None, .. }) if tag.len() == 2
+        ));
+
+        let cli = Cli::from_iter(["rusty_diary", "--dry-run", "--split-date-headings"]);
+        assert!(cli.dry_run && cli.command.is_none());
+        let cli = Cli::from_iter(["rusty_diary", "restore", "--as-of", "2024-06", "--into", "then"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Restore { as_of, ref into }) if as_of == NaiveDate::from_ymd_opt(2024, 6, 30).unwrap() && into == Path::new("then")
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "restore", "--as-of", "2024-06-01"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "import", "auto", "Journal.json", "--dry-run"]);
+        assert!(matches!(cli.command, Some(Command::Import { ref format, dry_run: true, .. }) if format == "auto"));
+        assert!(Cli::from_iter(["rusty_diary", "verify-roundtrip"]).command.unwrap().reads_only());
+        let cli = Cli::from_iter(["rusty_diary", "check", "links", "--attachments", "archive", "--fix"]);
+        assert!(matches!(cli.command, Some(Command::Check(CheckCommand::Links { attachments: Some(_), fix: true }))));
+        assert!(Cli::from_iter_safe(["rusty_diary", "check", "links", "--fix"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "check", "style", "--rules", "passive,filler", "--filler-words", "so,kind of"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Check(CheckCommand::Style { ref rules, ref filler_words, max_sentence_words: 30, .. }))
+                if rules == &[StyleRule::Passive, StyleRule::Filler] && filler_words.len() == 2
+        ));
+        assert!(Cli::from_iter_safe(["rusty_diary", "check", "style", "--rules", "grammar"]).is_err());
+        #[cfg(feature = "spellcheck")]
+        {
+            let cli = Cli::from_iter(["rusty_diary", "check", "spelling", "--from", "2024-01-01", "--suggestions", "1"]);
+            assert!(matches!(
+                cli.command,
+                Some(Command::Check(CheckCommand::Spelling { ref lang, from: Some(_), to: None, dictionary: None, suggestions: 1 }))
+                    if lang == "en"
+            ));
+        }
+        let cli = Cli::from_iter(["rusty_diary", "dictionary", "add", "Ana", "Lisboa", "--lang", "pt"]);
+        assert!(matches!(cli.command, Some(Command::Dictionary(DictionaryCommand::Add { ref words, ref lang })) if words.len() == 2 && lang == "pt"));
+        assert!(Cli::from_iter_safe(["rusty_diary", "dictionary", "add"]).is_err());
+        let cli = Cli::from_iter(["rusty_diary", "people", "list", "--from", "2024", "--limit", "5"]);
+        assert!(matches!(cli.command, Some(Command::People(PeopleCommand::List { from: Some(_), to: None, limit: Some(5) }))));
+        let cli = Cli::from_iter(["rusty_diary", "people", "show", "Ana Silva"]);
+        assert!(matches!(cli.command, Some(Command::People(PeopleCommand::Show { ref name, .. })) if name == "Ana Silva"));
+        assert!(cli.command.unwrap().reads_only());
+        let cli = Cli::from_iter(["rusty_diary", "events", "list", "--to", "2020"]);
+        assert!(matches!(cli.command, Some(Command::Events(EventsCommand::List { from: None, to: Some(_) }))));
+        let cli = Cli::from_iter(["rusty_diary", "attachments", "gc", "--dry-run"]);
+        assert!(matches!(cli.command, Some(Command::Attachments(AttachmentsCommand::Gc { dry_run: true }))));
+
+        let cli = Cli::from_iter(["rusty_diary", "--auto-tag", "tags", "suggest", "2024-03-01", "--limit", "3"]);
+        assert!(cli.auto_tag);
+        assert!(matches!(cli.command, Some(Command::Tags(TagsCommand::Suggest { limit: 3, .. }))));
+
+        let cli = Cli::from_iter(["rusty_diary", "--locale", "pt_PT", "--first-weekday", "sun", "--secondary-calendar", "hijri"]);
+        assert_eq!((cli.locale, cli.first_weekday), (chrono::Locale::pt_PT, chrono::Weekday::Sun));
+        assert_eq!(cli.secondary_calendar, Some(SecondaryCalendar::Hijri));
+        assert!(Cli::from_iter_safe(["rusty_diary", "--locale", "klingon"]).is_err());
+
+        let cli = Cli::from_iter(["rusty_diary", "graph", "export", "--format", "json"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Graph(GraphCommand::Export { ref format, out: None })) if format == "json"
+        ));
+    }
+
+    #[test]
+    fn test_completions_cover_subcommands_and_profiles() {
+        let cli = Cli::from_iter(["rusty_diary", "completions", "Bash"]);
+        assert!(matches!(cli.command, Some(Command::Completions { shell: Shell::Bash })));
+
+        let mut script = Vec::new();
+        Cli::clap().gen_completions_to("rusty_diary", Shell::Zsh, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.contains("unarchive"));
+        assert!(script.contains("default safe fast"));
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let exit = |err: RustyDiaryError| exit_code(&anyhow::Error::from(err).context("Failed"));
+
+        assert_eq!(exit(RustyDiaryError::NoFilesFound(PathBuf::from("."))), ExitCode::NoFilesFound);
+        assert_eq!(exit(RustyDiaryError::AllFilesSkipped(2)), ExitCode::PartialFailure);
+        assert_eq!(exit(RustyDiaryError::SchemaTooNew { found: 9, supported: 5 }), ExitCode::Conflict);
+        assert_eq!(
+            exit(RustyDiaryError::from(rusqlite::Error::SqliteFailure(
+                rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+                None
+            ))),
+            ExitCode::LockHeld
+        );
+        assert_eq!(exit(RustyDiaryError::HookFailed("x".to_string())), ExitCode::Failure);
+        assert_eq!(
+            exit_code(&StrictViolation("1 files skipped".to_string()).into()),
+            ExitCode::PartialFailure
+        );
+    }
+
+    #[test]
+    fn test_metrics_response() {
+        let metrics = SyncMetrics::new();
+        metrics.set_db_size(10);
+
+        let ok = metrics_response("GET /metrics HTTP/1.1\r\nHost: x\r\n\r\n", &metrics);
+        assert!(ok.starts_with("HTTP/1.1 200 OK"));
+        assert!(ok.ends_with(&metrics.render()));
+
+        let missing = metrics_response("GET / HTTP/1.1\r\n\r\n", &metrics);
+        assert!(missing.starts_with("HTTP/1.1 404"));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,152 @@
+use std::time::Duration;
+
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+use crate::error::{Result, RustyDiaryError};
+use crate::events::DiaryEvent;
+
+/// Prometheus metrics for long-running syncs (the CLI `daemon` mode).
+/// Fed from sync results and `DiaryEvent`s; rendered in the text exposition format.
+pub struct SyncMetrics {
+    registry: Registry,
+    entries_synced: IntCounter,
+    syncs: IntCounterVec,
+    sync_duration: Histogram,
+    db_size: IntGauge,
+    errors: IntCounterVec,
+    last_success: IntGauge,
+}
+
+impl SyncMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("rusty_diary".to_string()), None)
+            .expect("valid metrics prefix");
+
+        let entries_synced = IntCounter::new("entries_synced_total", "Entries stored by syncs")
+            .expect("valid metric");
+        let syncs = IntCounterVec::new(
+            Opts::new("syncs_total", "Sync runs by outcome (success, idle, failure)"),
+            &["outcome"],
+        )
+        .expect("valid metric");
+        let sync_duration = Histogram::with_opts(HistogramOpts::new(
+            "sync_duration_seconds",
+            "Wall-clock duration of sync runs",
+        ))
+        .expect("valid metric");
+        let db_size = IntGauge::new("db_size_bytes", "Size of the main database file")
+            .expect("valid metric");
+        let errors = IntCounterVec::new(
+            Opts::new("errors_total", "Failed syncs and skipped files by error kind"),
+            &["kind"],
+        )
+        .expect("valid metric");
+        let last_success = IntGauge::new(
+            "last_success_timestamp_seconds",
+            "Unix time of the last sync that did not fail",
+        )
+        .expect("valid metric");
+
+        registry.register(Box::new(entries_synced.clone())).expect("unique metric");
+        registry.register(Box::new(syncs.clone())).expect("unique metric");
+        registry.register(Box::new(sync_duration.clone())).expect("unique metric");
+        registry.register(Box::new(db_size.clone())).expect("unique metric");
+        registry.register(Box::new(errors.clone())).expect("unique metric");
+        registry.register(Box::new(last_success.clone())).expect("unique metric");
+
+        Self { registry, entries_synced, syncs, sync_duration, db_size, errors, last_success }
+    }
+
+    /// Records one sync run. Finding no files to sync counts as an idle run.
+    pub fn observe_sync<T>(&self, duration: Duration, result: &Result<T>) {
+        self.sync_duration.observe(duration.as_secs_f64());
+
+        let outcome = match result {
+            Ok(_) => "success",
+            Err(RustyDiaryError::NoFilesFound(_)) => "idle",
+            Err(err) => {
+                self.errors.with_label_values(&[err.kind().as_str()]).inc();
+                "failure"
+            }
+        };
+        self.syncs.with_label_values(&[outcome]).inc();
+
+        if outcome != "failure" {
+            self.last_success.set(chrono::Utc::now().timestamp());
+        }
+    }
+
+    pub fn observe_event(&self, event: &DiaryEvent) {
+        match event {
+            DiaryEvent::SyncCompleted { stored, .. } => self.entries_synced.inc_by(*stored as u64),
+            DiaryEvent::FileSkipped { .. } => self.errors.with_label_values(&["file_skipped"]).inc(),
+            _ => {}
+        }
+    }
+
+    pub fn set_db_size(&self, bytes: u64) {
+        self.db_size.set(bytes as i64);
+    }
+
+    /// All metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding into a Vec cannot fail");
+        String::from_utf8(buffer).expect("metrics are valid UTF-8")
+    }
+}
+
+impl Default for SyncMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_render_records_syncs_and_errors() {
+        let metrics = SyncMetrics::new();
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        metrics.observe_sync(Duration::from_millis(20), &Ok(()));
+        metrics.observe_event(&DiaryEvent::SyncCompleted {
+            exec_version: 1,
+            stored: 3,
+            start_date: date,
+            end_date: date,
+        });
+        metrics.observe_event(&DiaryEvent::FileSkipped {
+            path: PathBuf::from("2024-01-02.md"),
+            reason: "Empty content".to_string(),
+        });
+        metrics.observe_sync::<()>(
+            Duration::from_millis(5),
+            &Err(RustyDiaryError::NoFilesFound(PathBuf::from("."))),
+        );
+        metrics.observe_sync::<()>(
+            Duration::from_millis(5),
+            &Err(RustyDiaryError::HookFailed("git pull".to_string())),
+        );
+        metrics.set_db_size(4096);
+
+        let rendered = metrics.render();
+        assert!(rendered.contains("rusty_diary_entries_synced_total 3"));
+        assert!(rendered.contains(r#"rusty_diary_syncs_total{outcome="success"} 1"#));
+        assert!(rendered.contains(r#"rusty_diary_syncs_total{outcome="idle"} 1"#));
+        assert!(rendered.contains(r#"rusty_diary_syncs_total{outcome="failure"} 1"#));
+        assert!(rendered.contains(r#"rusty_diary_errors_total{kind="hook_failed"} 1"#));
+        assert!(rendered.contains(r#"rusty_diary_errors_total{kind="file_skipped"} 1"#));
+        assert!(rendered.contains("rusty_diary_sync_duration_seconds_count 3"));
+        assert!(rendered.contains("rusty_diary_db_size_bytes 4096"));
+    }
+}
@@ -0,0 +1,354 @@
+//! The sync pipeline with the filesystem and the database left to the
+//! caller: entries are read from any `DiaryFiles`, checked against any
+//! `EntryStore` for duplicates and summarized from it. Nothing here needs a
+//! disk or SQLite, so this part of the crate also builds for `wasm32` with
+//! `--no-default-features`, e.g. for a viewer running in a browser.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use walkdir::WalkDir;
+
+use crate::dates::DateRange;
+use crate::diary::file::{decode_text, merge_same_dates, ProcessedFiles, SkippedFile};
+use crate::diary::processor::{MarkdownProcessor, ProcessorRegistry};
+use crate::error::{IoContext, Result};
+use crate::storage::{DiaryEntry, DiaryStats, EntrySummary};
+
+/// Where diary files come from
+pub trait DiaryFiles {
+    /// Every file there is, diary file or not
+    fn paths(&self) -> Result<Vec<PathBuf>>;
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+}
+
+/// Diary files held in memory, such as files dropped into a web page
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFiles {
+    files: BTreeMap<PathBuf, Vec<u8>>,
+}
+
+impl MemoryFiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file<P: Into<PathBuf>>(mut self, path: P, content: impl Into<Vec<u8>>) -> Self {
+        self.files.insert(path.into(), content.into());
+        self
+    }
+}
+
+impl DiaryFiles for MemoryFiles {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        Ok(self.files.keys().cloned().collect())
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.files.get(path).cloned().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotFound, format!("{} is not loaded", path.display())).into()
+        })
+    }
+}
+
+/// The files under a folder on disk, in name order
+#[derive(Debug, Clone)]
+pub struct DiskFiles {
+    root: PathBuf,
+}
+
+impl DiskFiles {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl DiaryFiles for DiskFiles {
+    fn paths(&self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in WalkDir::new(&self.root).sort_by_file_name() {
+            let entry = entry.map_err(std::io::Error::from).at_path(&self.root)?;
+            if entry.file_type().is_file() {
+                paths.push(entry.into_path());
+            }
+        }
+        Ok(paths)
+    }
+
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        fs::read(path).at_path(path)
+    }
+}
+
+/// Where entries are kept
+pub trait EntryStore {
+    /// Whether an entry of `date` with this content hash is stored already
+    fn has_content(&self, date: NaiveDate, hash: &str) -> Result<bool>;
+
+    fn store(&mut self, entries: Vec<DiaryEntry>) -> Result<()>;
+
+    /// Summaries of the entries in the range, newest first
+    fn summaries(&self, range: DateRange) -> Result<Vec<EntrySummary>>;
+}
+
+/// Entries held in memory, one per date and version
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    entries: BTreeMap<(NaiveDate, i64), DiaryEntry>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Every stored version, newest first
+    pub fn entries(&self) -> impl Iterator<Item = &DiaryEntry> {
+        self.entries.values().rev()
+    }
+}
+
+impl EntryStore for MemoryStore {
+    fn has_content(&self, date: NaiveDate, hash: &str) -> Result<bool> {
+        let versions = self.entries.range((date, i64::MIN)..=(date, i64::MAX));
+        Ok(versions.into_iter().any(|(_, entry)| entry.content_hash() == hash))
+    }
+
+    fn store(&mut self, entries: Vec<DiaryEntry>) -> Result<()> {
+        for entry in entries {
+            self.entries.insert((entry.date, entry.exec_version), entry);
+        }
+        Ok(())
+    }
+
+    fn summaries(&self, range: DateRange) -> Result<Vec<EntrySummary>> {
+        let versions = self.entries.range((range.start, i64::MIN)..=(range.end, i64::MAX));
+        Ok(versions.rev().map(|(_, entry)| entry.summary()).collect())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl EntryStore for crate::storage::StorageManager {
+    fn has_content(&self, date: NaiveDate, hash: &str) -> Result<bool> {
+        self.has_content(date, hash)
+    }
+
+    fn store(&mut self, entries: Vec<DiaryEntry>) -> Result<()> {
+        self.store_entries(entries)
+    }
+
+    fn summaries(&self, range: DateRange) -> Result<Vec<EntrySummary>> {
+        self.list_entries(range.start, range.end)
+    }
+}
+
+/// Reads diary files into entries and stores the new ones
+pub struct Pipeline {
+    processor: MarkdownProcessor,
+    registry: ProcessorRegistry,
+    author: Option<String>,
+}
+
+impl Pipeline {
+    pub fn new(processor: MarkdownProcessor) -> Self {
+        Self { processor, registry: ProcessorRegistry::default(), author: None }
+    }
+
+    /// Parses files the registry has a parser for, besides markdown
+    pub fn with_registry(mut self, registry: ProcessorRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    /// Credits entries to `author` unless their frontmatter names another one
+    pub fn with_author(mut self, author: Option<String>) -> Self {
+        self.author = author;
+        self
+    }
+
+    /// The entries of every diary file among `files`. Files that are not
+    /// markdown, or whose name does not match the date pattern, are passed
+    /// over; files that cannot be read are reported as skipped.
+    pub fn read(&self, files: &dyn DiaryFiles, exec_version: i64) -> Result<ProcessedFiles> {
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+        for path in files.paths()? {
+            let is_markdown = path.extension().is_some_and(|ext| ext == "md");
+            let Ok(date) = self.processor.extract_date(&path) else { continue };
+            if !is_markdown && self.registry.parser_for(&path).is_none() {
+                continue;
+            }
+
+            match self.read_file(files, &path, date, exec_version) {
+                Ok(file_entries) => entries.extend(file_entries),
+                Err(e) => skipped.push(SkippedFile { reason: e.in_file(&path).to_string(), path }),
+            }
+        }
+
+        Ok(ProcessedFiles { entries: merge_same_dates(entries), skipped, lossy: Vec::new(), photos: Vec::new() })
+    }
+
+    fn read_file(
+        &self,
+        files: &dyn DiaryFiles,
+        path: &Path,
+        date: NaiveDate,
+        exec_version: i64,
+    ) -> Result<Vec<DiaryEntry>> {
+        let (mut content, _) = decode_text(path, files.read(path)?, false)?;
+        if let Some(parser) = self.registry.parser_for(path) {
+            content = parser.parse(path, &content)?;
+        }
+        self.processor.entries(&content, date, exec_version, self.author.clone())
+    }
+
+    /// Reads `files` and stores the entries `store` does not hold yet.
+    /// Returns what was read, with only the newly stored entries.
+    pub fn sync(
+        &self,
+        files: &dyn DiaryFiles,
+        store: &mut dyn EntryStore,
+        exec_version: i64,
+    ) -> Result<ProcessedFiles> {
+        let mut processed = self.read(files, exec_version)?;
+        processed.entries = unstored_entries(store, processed.entries)?;
+        store.store(processed.entries.clone())?;
+        Ok(processed)
+    }
+}
+
+/// The entries whose content `store` does not hold yet for their date
+pub fn unstored_entries(store: &dyn EntryStore, entries: Vec<DiaryEntry>) -> Result<Vec<DiaryEntry>> {
+    let mut unstored = Vec::with_capacity(entries.len());
+    for entry in entries {
+        if !store.has_content(entry.date, &entry.content_hash())? {
+            unstored.push(entry);
+        }
+    }
+    Ok(unstored)
+}
+
+/// Statistics of the stored entries in the range
+pub fn stats(store: &dyn EntryStore, range: DateRange) -> Result<DiaryStats> {
+    let summaries = store.summaries(range)?;
+    Ok(DiaryStats {
+        latest_exec_version: summaries.iter().map(|summary| summary.exec_version).max().unwrap_or(0),
+        ..DiaryStats::from_summaries(&summaries)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::diary::processor::Normalization;
+    use proptest::prelude::*;
+    use proptest::sample::select;
+    use std::sync::Arc;
+    use unicode_normalization::UnicodeNormalization;
+
+    fn files() -> MemoryFiles {
+        MemoryFiles::new()
+            .with_file("2024-03-04.md", "Monday")
+            .with_file("2024-03-05.md", "---\nauthor: sam\n---\nTuesday, two words")
+            .with_file("2024-03-06.md", b"Broken \xff".to_vec())
+            .with_file("notes.md", "Not a diary file")
+    }
+
+    fn pipeline() -> Pipeline {
+        Pipeline::new(MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$").unwrap())
+    }
+
+    fn sync_twice(store: &mut dyn EntryStore) -> Result<()> {
+        let first = pipeline().sync(&files(), store, 1)?;
+        assert_eq!(first.entries.len(), 2);
+        assert_eq!(first.skipped.len(), 1);
+        assert!(first.skipped[0].reason.starts_with("2024-03-06.md:1:8: "));
+        assert_eq!(first.entries[1].author.as_deref(), Some("sam"));
+
+        let again = pipeline().sync(&files().with_file("2024-03-07.md", "Thursday"), store, 2)?;
+        assert_eq!(again.entries.len(), 1);
+
+        let stats = stats(store, DateRange::all())?;
+        assert_eq!((stats.days, stats.versions, stats.total_words), (3, 3, 9));
+        assert_eq!(stats.first_date, NaiveDate::from_ymd_opt(2024, 3, 4));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_into_memory() -> Result<()> {
+        let mut store = MemoryStore::new();
+        sync_twice(&mut store)?;
+        assert_eq!(store.entries().next().map(|entry| entry.content.as_str()), Some("Thursday"));
+        Ok(())
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn test_sync_into_sqlite() -> Result<()> {
+        let temp_dir = tempfile::TempDir::new()?;
+        sync_twice(&mut crate::storage::StorageManager::new(temp_dir.path().join("test.db"))?)
+    }
+
+    /// Diary files as they pile up in a real folder: notes of the same day
+    /// in several files, names that are not quite dates, empty notes and
+    /// text with mixed line endings and decomposed accents
+    fn any_files() -> impl Strategy<Value = MemoryFiles> {
+        const NAMES: &[&str] = &[
+            "2024-03-04.md", "2024-03-05.md", "2024-02-29.md", "2024-02-30.md", "2024-3-04.md", "notes.md",
+            "2024-03-04.txt",
+        ];
+        const TEXT: &[&str] = &[
+            "Monday", "Café", "Cafe\u{301}", "日記 🙂", "\r\n", "\n", "\r", "\u{feff}", "## 2024-03-06",
+            "#tag", " ", "---\nauthor: sam\n---\n",
+        ];
+        let folder = select(&["", "week/", "2024/"][..]);
+        let file = (folder, select(NAMES), prop::collection::vec(select(TEXT), 0..8));
+        prop::collection::vec(file, 0..8).prop_map(|files| {
+            files.into_iter().fold(MemoryFiles::new(), |files, (folder, name, text)| {
+                files.with_file(format!("{}{}", folder, name), text.concat())
+            })
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn test_sync_property(split in any::<bool>(), files in any_files()) {
+            let now = NaiveDate::from_ymd_opt(2024, 3, 6).unwrap().and_hms_opt(7, 30, 0).unwrap();
+            let pipeline = Pipeline::new(
+                MarkdownProcessor::new(r"^(\d{4}-\d{2}-\d{2})(\.md)?$")?
+                    .with_normalization(Normalization::Nfc)
+                    .with_date_heading_split(split)
+                    .with_clock(Arc::new(FixedClock(now))),
+            );
+
+            // Reading is deterministic, and a run stores one version per day
+            let read = pipeline.read(&files, 1)?;
+            prop_assert_eq!(&pipeline.read(&files, 1)?.entries, &read.entries);
+            let mut dates: Vec<NaiveDate> = read.entries.iter().map(|entry| entry.date).collect();
+            dates.sort();
+            dates.dedup();
+            prop_assert_eq!(dates.len(), read.entries.len());
+            prop_assert!(read.entries.iter().all(|entry| entry.created_at == now), "{:?}", read.entries);
+
+            // Syncing again, or the same notes saved on another system,
+            // finds nothing new
+            let mut store = MemoryStore::new();
+            let first = pipeline.sync(&files, &mut store, 1)?;
+            prop_assert_eq!(&first.entries, &read.entries);
+            let again = pipeline.sync(&files, &mut store, 2)?;
+            prop_assert!(again.entries.is_empty(), "{:?}", again.entries);
+            let mut elsewhere = MemoryFiles::new();
+            for path in files.paths()? {
+                let text = Normalization::Nfc.apply(&String::from_utf8(files.read(&path)?).unwrap());
+                let saved: String = format!("\u{feff}{}", text.replace('\n', "\r\n")).nfd().collect();
+                elsewhere = elsewhere.with_file(path, saved);
+            }
+            let moved = pipeline.sync(&elsewhere, &mut store, 3)?;
+            prop_assert!(moved.entries.is_empty(), "{:?}", moved.entries);
+        }
+    }
+}
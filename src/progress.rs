@@ -0,0 +1,19 @@
+/// Receives progress updates from long-running operations such as
+/// processing files, storing entries and writing journals.
+/// Every method has a no-op default so sinks only implement what they show.
+pub trait ProgressSink: Send + Sync {
+    /// A new phase begins with `total` units of work
+    fn start(&self, _phase: &str, _total: u64) {}
+
+    /// `n` more units of the current phase are done
+    fn advance(&self, _n: u64) {}
+
+    /// The current phase is complete
+    fn finish(&self) {}
+}
+
+/// Default sink for library use: ignores all progress
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoProgress;
+
+impl ProgressSink for NoProgress {}
@@ -0,0 +1,87 @@
+//! Evening reminders to write: once a day, from a set time on, a day
+//! without an entry, or with fewer words than the daily goal, fires the
+//! `Reminder` hooks. The daemon polls a `Reminder` and asks
+//! `RustyDiary::remind` to check it once it is due.
+
+use std::fmt;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime};
+use serde::Serialize;
+
+/// Why a reminder fired
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum ReminderReason {
+    NoEntry,
+    BelowGoal { words: usize, goal: usize },
+}
+
+impl fmt::Display for ReminderReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReminderReason::NoEntry => write!(f, "No diary entry yet today"),
+            ReminderReason::BelowGoal { words, goal } => write!(f, "{} of {} words written today", words, goal),
+        }
+    }
+}
+
+/// A daily reminder. It waits until `at`, is then due until it has been
+/// checked for the day, and waits again for the next day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reminder {
+    at: NaiveTime,
+    word_goal: Option<usize>,
+    checked: Option<NaiveDate>,
+}
+
+impl Reminder {
+    pub fn new(at: NaiveTime) -> Self {
+        Self { at, word_goal: None, checked: None }
+    }
+
+    /// Also reminds on days with an entry of fewer than `goal` words
+    pub fn with_word_goal(mut self, goal: usize) -> Self {
+        self.word_goal = Some(goal);
+        self
+    }
+
+    /// The day to check, when it is past the reminder time and the day has
+    /// not been checked yet
+    pub fn due(&self, now: NaiveDateTime) -> Option<NaiveDate> {
+        (now.time() >= self.at && self.checked != Some(now.date())).then(|| now.date())
+    }
+
+    /// Marks `date` as checked, given the words of its entry (none without
+    /// one). Returns why to remind, or none when the day is written.
+    pub fn check(&mut self, date: NaiveDate, words: Option<usize>) -> Option<ReminderReason> {
+        self.checked = Some(date);
+        match (words, self.word_goal) {
+            (None, _) => Some(ReminderReason::NoEntry),
+            (Some(words), Some(goal)) if words < goal => Some(ReminderReason::BelowGoal { words, goal }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reminder_fires_once_a_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 3, 5).unwrap();
+        let at = |hour| day.and_hms_opt(hour, 0, 0).unwrap();
+        let mut reminder = Reminder::new(NaiveTime::from_hms_opt(21, 0, 0).unwrap()).with_word_goal(200);
+
+        assert_eq!(reminder.due(at(20)), None);
+        assert_eq!(reminder.due(at(21)), Some(day));
+        assert_eq!(reminder.check(day, Some(150)), Some(ReminderReason::BelowGoal { words: 150, goal: 200 }));
+        assert_eq!(reminder.due(at(22)), None);
+
+        let next = day.succ_opt().unwrap();
+        assert_eq!(reminder.due(next.and_hms_opt(21, 30, 0).unwrap()), Some(next));
+        assert_eq!(reminder.check(next, Some(250)), None);
+        assert_eq!(reminder.check(next, None), Some(ReminderReason::NoEntry));
+        assert_eq!(ReminderReason::NoEntry.to_string(), "No diary entry yet today");
+    }
+}
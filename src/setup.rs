@@ -0,0 +1,345 @@
+//! First-run setup and health checks: `init` lays out a new diary, `doctor`
+//! looks over an existing one without changing it.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::diary::file::{build_ignore, IGNORE_FILE};
+use crate::diary::saved::{SavedSearches, SEARCHES_FILE};
+use crate::error::{IoContext, Result, RustyDiaryError};
+use crate::storage::{self, StorageManager};
+
+const IGNORE_TEMPLATE: &str = "\
+# Files and folders left out of syncs, in .gitignore syntax, e.g.
+# drafts/
+# *-private.md
+";
+
+const SEARCHES_TEMPLATE: &str = "\
+# Saved searches, one NAME = QUERY per line, run with `search --saved NAME`, e.g.
+# work-frustrations = tag:work AND text:\"frustrated\"
+";
+
+/// File in the diary directory holding the options it is used with
+pub const CONFIG_FILE: &str = ".diaryconfig";
+
+const CONFIG_TEMPLATE: &str = "\
+# Options for this diary, one NAME = VALUE per line or NAME alone for a switch.
+# Names are those of the command-line options without the dashes; an option
+# given on the command line wins. The defaults:
+# db = rusty-diary.db
+# date-pattern = ^(\\d{4}-\\d{2}-\\d{2})(\\.md)?$
+# max-depth = 1
+# path-dates = ignore
+# normalize = lf
+# sqlite-profile = default
+# markdown-flavor = commonmark
+# Repeatable options take one line each, e.g.
+# habit = meditate
+# habit = run
+";
+
+/// The options of the config file in `directory`, in file order, as their
+/// name and value, if any; none when there is no file
+pub fn config_options(directory: &Path) -> Result<Vec<(String, Option<String>)>> {
+    let path = directory.join(CONFIG_FILE);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).at_path(&path),
+    };
+
+    let mut options = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim(), Some(value.trim())),
+            None => (line, None),
+        };
+        if name.is_empty() || name.starts_with('-') || name.contains(char::is_whitespace) {
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "{} line {}: expected 'name = value' or a switch name, got '{}'",
+                path.display(),
+                number + 1,
+                line
+            )));
+        }
+        options.push((name.to_string(), value.map(str::to_string)));
+    }
+    Ok(options)
+}
+
+/// Creates the diary directory, commented `.diaryconfig`, `.diaryignore`
+/// and `.diarysearches` files and a database with the current schema.
+/// Existing files are left as they are. Returns the paths that were created.
+pub fn init(config: &Config) -> Result<Vec<PathBuf>> {
+    config.validate()?;
+    let mut created = Vec::new();
+
+    if !config.directory.is_dir() {
+        fs::create_dir_all(&config.directory).at_path(&config.directory)?;
+        created.push(config.directory.clone());
+    }
+
+    let templates = [
+        (CONFIG_FILE, CONFIG_TEMPLATE),
+        (IGNORE_FILE, IGNORE_TEMPLATE),
+        (SEARCHES_FILE, SEARCHES_TEMPLATE),
+    ];
+    for (name, template) in templates {
+        let path = config.directory.join(name);
+        if !path.exists() {
+            fs::write(&path, template).at_path(&path)?;
+            created.push(path);
+        }
+    }
+
+    if !config.db_path.exists() {
+        if let Some(parent) = config.db_path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).at_path(parent)?;
+        }
+        StorageManager::with_settings(&config.db_path, &config.sqlite, config.clock.clone())?;
+        created.push(config.db_path.clone());
+    }
+
+    Ok(created)
+}
+
+/// How a check turned out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Warning,
+    Problem,
+}
+
+/// The outcome of one `doctor` check
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub status: Status,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(check: &'static str, status: Status, message: impl Into<String>) -> Self {
+        Self { check, status, message: message.into() }
+    }
+}
+
+/// Checks the diary directory, the configured patterns, the database and
+/// the files left next to it. Nothing is created or modified.
+pub fn doctor(config: &Config) -> Vec<Finding> {
+    vec![
+        check_directory(&config.directory),
+        check_config(config),
+        check_config_file(&config.directory),
+        check_diary_files(config),
+        check_database(&config.db_path),
+        check_backups(config),
+        check_leftovers(&config.db_path),
+    ]
+}
+
+fn check_directory(directory: &Path) -> Finding {
+    const CHECK: &str = "directory";
+    if !directory.is_dir() {
+        return Finding::new(CHECK, Status::Problem, format!("{} does not exist; run `init`", directory.display()));
+    }
+    if let Err(e) = fs::read_dir(directory) {
+        return Finding::new(CHECK, Status::Problem, format!("{} cannot be read: {}", directory.display(), e));
+    }
+
+    // Synced files are removed and journals written here, so probe for write access
+    let probe = directory.join(".rusty-diary-doctor");
+    match fs::write(&probe, "") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Finding::new(CHECK, Status::Ok, format!("{} is readable and writable", directory.display()))
+        }
+        Err(e) => Finding::new(CHECK, Status::Problem, format!("{} is not writable: {}", directory.display(), e)),
+    }
+}
+
+fn check_config(config: &Config) -> Finding {
+    const CHECK: &str = "config";
+    match config.validate() {
+        Ok(()) => Finding::new(CHECK, Status::Ok, format!("Date pattern '{}' and paths are valid", config.date_pattern)),
+        Err(e) => Finding::new(CHECK, Status::Problem, e.to_string()),
+    }
+}
+
+fn check_config_file(directory: &Path) -> Finding {
+    const CHECK: &str = "config_file";
+    if !directory.join(CONFIG_FILE).is_file() {
+        let message = format!("No {}; run `init` to write one with the defaults", CONFIG_FILE);
+        return Finding::new(CHECK, Status::Warning, message);
+    }
+    match config_options(directory) {
+        Ok(options) => Finding::new(CHECK, Status::Ok, format!("{} sets {} options", CONFIG_FILE, options.len())),
+        Err(e) => Finding::new(CHECK, Status::Problem, e.to_string()),
+    }
+}
+
+fn check_diary_files(config: &Config) -> Finding {
+    const CHECK: &str = "diary_files";
+    if let Err(e) = build_ignore(&config.directory, &config.ignore_patterns) {
+        return Finding::new(CHECK, Status::Problem, format!("{}: {}", IGNORE_FILE, e));
+    }
+    match SavedSearches::load(&config.directory, &config.saved_searches, config.clock.today()) {
+        Ok(_) => Finding::new(CHECK, Status::Ok, format!("{} and {} are valid", IGNORE_FILE, SEARCHES_FILE)),
+        Err(e) => Finding::new(CHECK, Status::Problem, e.to_string()),
+    }
+}
+
+fn check_database(db_path: &Path) -> Finding {
+    const CHECK: &str = "database";
+    if !db_path.exists() {
+        return Finding::new(CHECK, Status::Warning, format!("{} does not exist yet; run `init` or a sync", db_path.display()));
+    }
+
+    let info = match storage::inspect(db_path) {
+        Ok(info) => info,
+        Err(e) => return Finding::new(CHECK, Status::Problem, format!("{} cannot be opened: {}", db_path.display(), e)),
+    };
+    if let Some(reason) = &info.schema.incompatibility {
+        return Finding::new(CHECK, Status::Problem, reason.clone());
+    }
+
+    let integrity = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .and_then(|conn| conn.query_row("PRAGMA integrity_check", [], |row| row.get::<_, String>(0)));
+    match integrity {
+        Ok(result) if result == "ok" => {}
+        Ok(result) => return Finding::new(CHECK, Status::Problem, format!("Integrity check failed: {}", result)),
+        Err(e) => return Finding::new(CHECK, Status::Problem, format!("Integrity check could not run: {}", e)),
+    }
+
+    match info.schema.pending.len() {
+        0 => Finding::new(CHECK, Status::Ok, format!("Schema v{} passes the integrity check", info.schema.version)),
+        pending => Finding::new(
+            CHECK,
+            Status::Warning,
+            format!("{} migrations pending; they run, after a backup, the next time the database is written", pending),
+        ),
+    }
+}
+
+/// Migration backups next to the database and copies in the diary's `.backup` folder
+fn check_backups(config: &Config) -> Finding {
+    const CHECK: &str = "backups";
+    let file_name = config.db_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut backups = sibling_files(&config.db_path, |name| name.starts_with(&format!("{}.v", file_name)) && name.ends_with(".bak"));
+    backups.extend(fs::read_dir(config.directory.join(".backup")).into_iter().flatten().flatten().map(|entry| entry.path()));
+
+    if backups.is_empty() {
+        return Finding::new(CHECK, Status::Ok, "No leftover backups");
+    }
+    let bytes: u64 = backups.iter().filter_map(|path| fs::metadata(path).ok()).map(|meta| meta.len()).sum();
+    Finding::new(
+        CHECK,
+        Status::Warning,
+        format!("{} backups ({} bytes) can be removed once they are no longer needed", backups.len(), bytes),
+    )
+}
+
+/// Files an interrupted write leaves behind
+fn check_leftovers(db_path: &Path) -> Finding {
+    const CHECK: &str = "leftovers";
+    let stem = db_path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    let mut leftovers = sibling_files(db_path, |name| name.starts_with(&stem) && name.ends_with(".partial"));
+
+    let journal = PathBuf::from(format!("{}-journal", db_path.display()));
+    if journal.exists() {
+        leftovers.push(journal);
+    }
+
+    if leftovers.is_empty() {
+        return Finding::new(CHECK, Status::Ok, "No files left by interrupted writes");
+    }
+    let names: Vec<String> = leftovers.iter().map(|path| path.display().to_string()).collect();
+    Finding::new(
+        CHECK,
+        Status::Warning,
+        format!(
+            "Left by an interrupted write: {}. A rollback journal is undone when the database is next written; \
+             partial archives can be removed",
+            names.join(", ")
+        ),
+    )
+}
+
+/// Files in the database's folder whose name passes `matches`
+fn sibling_files(db_path: &Path, matches: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+    let folder = db_path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let mut files: Vec<PathBuf> = fs::read_dir(folder)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| matches(&entry.file_name().to_string_lossy()))
+        .map(|entry| entry.path())
+        .collect();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn status(findings: &[Finding], check: &str) -> Status {
+        findings.iter().find(|finding| finding.check == check).unwrap().status
+    }
+
+    #[test]
+    fn test_init_then_doctor() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let config = Config::new()
+            .with_directory(temp_dir.path().join("diary"))
+            .with_db(temp_dir.path().join("data/diary.db"));
+
+        let findings = doctor(&config);
+        assert_eq!(status(&findings, "directory"), Status::Problem);
+        assert_eq!(status(&findings, "database"), Status::Warning);
+
+        assert_eq!(init(&config)?.len(), 5);
+        assert!(init(&config)?.is_empty());
+        let findings = doctor(&config);
+        assert!(findings.iter().all(|finding| finding.status == Status::Ok), "{:?}", findings);
+
+        // The config file is all comments until options are set, and init keeps it
+        let config_file = config.directory.join(CONFIG_FILE);
+        assert!(config_options(&config.directory)?.is_empty());
+        fs::write(&config_file, "# Mine\nphotos\nhabit = run\n")?;
+        assert!(init(&config)?.is_empty());
+        let options = config_options(&config.directory)?;
+        assert_eq!(options, [("photos".to_string(), None), ("habit".to_string(), Some("run".to_string()))]);
+        assert_eq!(status(&doctor(&config), "config_file"), Status::Ok);
+        fs::write(&config_file, "--photos\n")?;
+        assert_eq!(status(&doctor(&config), "config_file"), Status::Problem);
+        fs::remove_file(&config_file)?;
+        assert_eq!(status(&doctor(&config), "config_file"), Status::Warning);
+
+        fs::write(temp_dir.path().join("data/diary.db.v3-20240101000000000.bak"), "old")?;
+        fs::write(temp_dir.path().join("data/diary-archive-1.jsonl.partial"), "")?;
+        fs::write(config.directory.join(SEARCHES_FILE), "broken line\n")?;
+        let findings = doctor(&config);
+        assert_eq!(status(&findings, "backups"), Status::Warning);
+        assert_eq!(status(&findings, "leftovers"), Status::Warning);
+        assert_eq!(status(&findings, "diary_files"), Status::Problem);
+
+        let loose = Config { date_pattern: r"^\d{4}-\d{2}-\d{2}\.md$".to_string(), ..config };
+        assert_eq!(status(&doctor(&loose), "config"), Status::Problem);
+        assert!(init(&loose).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{IoContext, Result, RustyDiaryError};
+use super::models::{DiaryEntry, StoredTag};
+
+/// zstd level for cold archives; they are written once and rarely read,
+/// so trading speed for ratio is worth it
+const ARCHIVE_COMPRESSION_LEVEL: i32 = 19;
+
+/// Location of a new cold archive, next to the main database:
+/// `diary.db` archives into `diary-archive-20240101120000000.jsonl.zst`
+pub fn path(db_path: &Path, created_at: NaiveDateTime) -> PathBuf {
+    let stem = db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "rusty-diary".to_string());
+
+    db_path.with_file_name(format!(
+        "{}-archive-{}.jsonl.zst",
+        stem,
+        created_at.format("%Y%m%d%H%M%S%3f")
+    ))
+}
+
+/// The files archived entries link to, which move into the archive with
+/// them and come back when they are unarchived
+pub struct Attachments<'a> {
+    /// Folder the linked paths are relative to
+    pub directory: &'a Path,
+    /// Paths, relative to `directory`, of the local files an entry links to
+    pub linked: &'a dyn Fn(&DiaryEntry) -> Vec<String>,
+}
+
+/// What an archived day kept besides its entries. Links, highlights,
+/// people and events are found again in the entries when they come back.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedDayState {
+    pub date: NaiveDate,
+    pub starred_at: Option<String>,
+    pub tags: Vec<StoredTag>,
+    pub fields: Vec<(String, String)>,
+    pub habits: Vec<(String, bool)>,
+    /// Review progress of the day's highlights: text, stage, due date and
+    /// last review
+    pub highlights: Vec<(String, i64, Option<String>, Option<String>)>,
+}
+
+/// A file archived entries link to, with the days whose entries link to it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchivedFile {
+    pub path: String,
+    pub dates: Vec<NaiveDate>,
+    /// Base64 of the file's bytes
+    pub data: String,
+}
+
+impl ArchivedFile {
+    pub fn new(path: String, dates: Vec<NaiveDate>, bytes: &[u8]) -> Self {
+        Self { path, dates, data: STANDARD.encode(bytes) }
+    }
+
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        STANDARD.decode(&self.data).map_err(|e| {
+            RustyDiaryError::ContentIntegrity(format!("Archived file {} is not valid base64: {}", self.path, e))
+        })
+    }
+
+    /// Where the file goes back to under `directory`. Only plain relative
+    /// paths are accepted, so an archive cannot write outside it.
+    pub fn target(&self, directory: &Path) -> Result<PathBuf> {
+        let path = Path::new(&self.path);
+        if path.as_os_str().is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(RustyDiaryError::ContentIntegrity(format!(
+                "Archived file has an unsafe path: {}",
+                self.path
+            )));
+        }
+        Ok(directory.join(path))
+    }
+}
+
+/// Everything a cold archive file holds
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ColdArchive {
+    pub entries: Vec<DiaryEntry>,
+    pub days: Vec<ArchivedDayState>,
+    pub files: Vec<ArchivedFile>,
+}
+
+/// One line of an archive file: an entry as it is, or `{"day": ...}` or
+/// `{"file": ...}`. Archives written before days and files were kept hold
+/// entry lines only.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Record<E, D, F> {
+    Entry(E),
+    Day { day: D },
+    File { file: F },
+}
+
+/// Writes the archive as zstd-compressed JSON lines. The file is written
+/// under a temporary name and renamed into place once complete.
+pub fn write(path: &Path, archive: &ColdArchive) -> Result<()> {
+    let partial = path.with_extension("partial");
+
+    {
+        let file = File::create(&partial).at_path(&partial)?;
+        let mut encoder = zstd::Encoder::new(BufWriter::new(file), ARCHIVE_COMPRESSION_LEVEL)?;
+        let records = archive
+            .entries
+            .iter()
+            .map(Record::Entry)
+            .chain(archive.days.iter().map(|day| Record::Day { day }))
+            .chain(archive.files.iter().map(|file| Record::File { file }));
+        for record in records {
+            serde_json::to_writer(&mut encoder, &record)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+
+    fs::rename(&partial, path).at_path(path)?;
+    Ok(())
+}
+
+/// Reads a cold archive back
+pub fn read(path: &Path) -> Result<ColdArchive> {
+    let decoder = zstd::Decoder::new(File::open(path).at_path(path)?)?;
+    let mut archive = ColdArchive::default();
+
+    for line in BufReader::new(decoder).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record<DiaryEntry, ArchivedDayState, ArchivedFile>>(&line)? {
+            Record::Entry(entry) => archive.entries.push(entry),
+            Record::Day { day } => archive.days.push(day),
+            Record::File { file } => archive.files.push(file),
+        }
+    }
+
+    Ok(archive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_roundtrip() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let created_at = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap()
+            .and_hms_opt(12, 0, 0).unwrap();
+        let path = path(&temp_dir.path().join("diary.db"), created_at);
+        assert!(path.ends_with("diary-archive-20240101120000000.jsonl.zst"));
+
+        let day = NaiveDate::from_ymd_opt(2019, 1, 1).unwrap();
+        let entries = vec![
+            DiaryEntry::new(1, day, "One\nTwo ![](a.png)".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, NaiveDate::from_ymd_opt(2019, 1, 2).unwrap(), "Three".to_string(), SystemClock.now()),
+        ];
+        let archive = ColdArchive {
+            entries: entries.clone(),
+            days: vec![ArchivedDayState {
+                date: day,
+                starred_at: Some("2019-01-03 10:00:00".to_string()),
+                tags: Vec::new(),
+                fields: vec![("mood".to_string(), "calm".to_string())],
+                habits: vec![("run".to_string(), true)],
+                highlights: Vec::new(),
+            }],
+            files: vec![ArchivedFile::new("a.png".to_string(), vec![day], &[0, 159, 255])],
+        };
+        write(&path, &archive)?;
+        let read_back = read(&path)?;
+        assert_eq!(read_back, archive);
+        assert_eq!(read_back.files[0].bytes()?, [0, 159, 255]);
+
+        // Archives from before days and files were kept still read
+        let mut encoder = zstd::Encoder::new(File::create(&path)?, 3)?;
+        for entry in &entries {
+            serde_json::to_writer(&mut encoder, entry)?;
+            encoder.write_all(b"\n")?;
+        }
+        encoder.finish()?;
+        assert_eq!(read(&path)?, ColdArchive { entries, ..ColdArchive::default() });
+        Ok(())
+    }
+}
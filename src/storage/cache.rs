@@ -0,0 +1,46 @@
+use std::num::NonZeroUsize;
+use chrono::NaiveDate;
+use lru::LruCache;
+use parking_lot::Mutex;
+
+use super::models::DiaryEntry;
+
+/// In-process LRU cache for the hottest read paths.
+/// Any write through the StorageManager clears it entirely, which keeps
+/// invalidation trivially correct at the cost of some extra misses.
+pub struct ReadCache {
+    ranges: Mutex<LruCache<(NaiveDate, NaiveDate), Vec<DiaryEntry>>>,
+    latest: Mutex<LruCache<NaiveDate, Option<DiaryEntry>>>,
+}
+
+impl ReadCache {
+    /// Creates a cache holding up to `capacity` results per query kind
+    pub fn new(capacity: NonZeroUsize) -> Self {
+        Self {
+            ranges: Mutex::new(LruCache::new(capacity)),
+            latest: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    pub fn get_range(&self, start_date: NaiveDate, end_date: NaiveDate) -> Option<Vec<DiaryEntry>> {
+        self.ranges.lock().get(&(start_date, end_date)).cloned()
+    }
+
+    pub fn put_range(&self, start_date: NaiveDate, end_date: NaiveDate, entries: Vec<DiaryEntry>) {
+        self.ranges.lock().put((start_date, end_date), entries);
+    }
+
+    pub fn get_latest(&self, date: NaiveDate) -> Option<Option<DiaryEntry>> {
+        self.latest.lock().get(&date).cloned()
+    }
+
+    pub fn put_latest(&self, date: NaiveDate, entry: Option<DiaryEntry>) {
+        self.latest.lock().put(date, entry);
+    }
+
+    /// Drops every cached result
+    pub fn invalidate(&self) {
+        self.ranges.lock().clear();
+        self.latest.lock().clear();
+    }
+}
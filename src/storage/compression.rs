@@ -0,0 +1,78 @@
+use rusqlite::types::{Type, Value, ValueRef};
+
+use crate::error::{Result, RustyDiaryError};
+
+/// zstd level used for entry content; favours speed over ratio
+const COMPRESSION_LEVEL: i32 = 3;
+
+/// Encodes entry content for the `content` column.
+/// Returns the value to store and whether it was compressed. Content is
+/// only stored compressed when that actually saves space, so short
+/// entries stay as plain text.
+pub fn encode(content: &str, compress: bool) -> Result<(Value, bool)> {
+    if compress {
+        let compressed = zstd::encode_all(content.as_bytes(), COMPRESSION_LEVEL)?;
+        if compressed.len() < content.len() {
+            return Ok((Value::Blob(compressed), true));
+        }
+    }
+
+    Ok((Value::Text(content.to_string()), false))
+}
+
+/// Decodes a `content` column value according to its row flag
+pub fn decode(value: ValueRef<'_>, compressed: bool) -> Result<String> {
+    match (value, compressed) {
+        (ValueRef::Blob(bytes), true) => {
+            let raw = zstd::decode_all(bytes)?;
+            String::from_utf8(raw).map_err(|e| RustyDiaryError::ContentIntegrity(
+                format!("Decompressed content is not valid UTF-8: {}", e)
+            ))
+        }
+        (ValueRef::Text(text), false) => Ok(String::from_utf8_lossy(text).into_owned()),
+        (other, _) => Err(RustyDiaryError::ContentIntegrity(format!(
+            "Unexpected {} content value (compressed = {})",
+            other.data_type(),
+            compressed
+        ))),
+    }
+}
+
+/// Same as `decode` but reports failures as a rusqlite conversion error,
+/// for use inside row mapping closures
+pub fn decode_column(value: ValueRef<'_>, compressed: bool, column: usize) -> rusqlite::Result<String> {
+    decode(value, compressed).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(column, Type::Blob, Box::new(e))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() -> Result<()> {
+        let content = "Today was a long day. ".repeat(50);
+
+        let (value, compressed) = encode(&content, true)?;
+        assert!(compressed);
+        assert!(matches!(value, Value::Blob(_)));
+
+        let Value::Blob(bytes) = &value else { unreachable!() };
+        assert_eq!(decode(ValueRef::Blob(bytes), true)?, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_short_or_disabled_content_stays_plain() -> Result<()> {
+        let (_, compressed) = encode("tiny", true)?;
+        assert!(!compressed);
+
+        let (value, compressed) = encode(&"long ".repeat(100), false)?;
+        assert!(!compressed);
+        assert!(matches!(value, Value::Text(_)));
+
+        Ok(())
+    }
+}
@@ -1,241 +1,1813 @@
-use rusqlite::{Connection, Transaction, params, Result as SqlResult};
-use chrono::{NaiveDate};
-use std::path::Path;
+use rusqlite::{Connection, OpenFlags, OptionalExtension, Row, Transaction, params, Result as SqlResult};
+use chrono::{Datelike, NaiveDate};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
-use parking_lot::RwLock;
-
-use crate::error::{Result, RustyDiaryError};
-use super::models::{DiaryEntry, EntryMetadata};
-
-const PRAGMAS: &str = "
-    PRAGMA foreign_keys = ON;
-    PRAGMA journal_mode = WAL;
-    PRAGMA synchronous = NORMAL;
-";
-
-const MIGRATIONS: &[&str] = &[
-    // V1: Initial schema
-    "CREATE TABLE IF NOT EXISTS diary_entries (
-        exec_version INTEGER NOT NULL,
-        date TEXT NOT NULL,
-        content TEXT NOT NULL,
-        created_at TEXT NOT NULL,
-        updated_at TEXT,
-        PRIMARY KEY (exec_version, date)
-    );
-
-    CREATE INDEX IF NOT EXISTS idx_diary_entries_date
-    ON diary_entries(date);",
-
-    // V2: Add metadata table
-    "CREATE TABLE IF NOT EXISTS entry_metadata (
-        entry_id INTEGER PRIMARY KEY,
-        exec_version INTEGER NOT NULL,
-        date TEXT NOT NULL,
-        word_count INTEGER NOT NULL,
-        FOREIGN KEY (exec_version, date)
-        REFERENCES diary_entries(exec_version, date)
-        ON DELETE CASCADE
-    );"
-];
+use parking_lot::{Mutex, MutexGuard};
+
+use crate::cancel::CancellationToken;
+use crate::clock::{Clock, SystemClock};
+use crate::error::{IoContext, Result, RustyDiaryError, SqlContext};
+use crate::progress::ProgressSink;
+use super::archive;
+use super::compression;
+use super::migrations;
+use super::models::{BackupRecord, DiaryEntry, EntryLink, EntryMetadata, EntrySummary, HabitCheck, Highlight, LifeEvent, PersonCount, ReindexReport, RunStats, StoredTag, SyncRun, TableCount};
+use super::settings::{BatchMode, SqliteSettings};
+use super::shard::{self, ReaderConnection, MAX_ATTACHED_SHARDS};
+
+/// Number of read-only connections kept open alongside the writer.
+/// In WAL mode these read concurrently while a write is in flight.
+const READ_POOL_SIZE: usize = 4;
+
+/// Capacity of each connection's prepared statement cache
+const STATEMENT_CACHE_CAPACITY: usize = 32;
+
+/// Authors live in the main database only, so they survive sharding
+const ENTRY_COLUMNS_SELECT: &str =
+    "SELECT e.exec_version, e.date, e.content, e.created_at, e.updated_at, e.compressed, a.author
+     FROM {db}.diary_entries e
+     LEFT JOIN main.entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+     WHERE e.date BETWEEN ?1 AND ?2";
+
+/// The latest version of each day within one source. A day can have
+/// versions in both a shard and the main database, so results from several
+/// sources still need `latest_per_date`.
+const LATEST_ENTRY_COLUMNS_SELECT: &str =
+    "SELECT e.exec_version, e.date, e.content, e.created_at, e.updated_at, e.compressed, a.author
+     FROM {db}.diary_entries e
+     LEFT JOIN main.entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+     WHERE e.date BETWEEN ?1 AND ?2
+       AND e.exec_version = (SELECT MAX(l.exec_version) FROM {db}.diary_entries l WHERE l.date = e.date)";
+
+/// Highest run id, counting runs and entries in shards from before runs had a table
+const LATEST_EXEC_VERSION_SELECT: &str =
+    "SELECT MAX(
+        COALESCE((SELECT MAX(id) FROM sync_runs), 0),
+        COALESCE((SELECT MAX(exec_version) FROM diary_entries), 0),
+        COALESCE((SELECT MAX(max_exec_version) FROM shards), 0)
+     )";
+
+/// Keeps the latest version of each day, newest day first. This is the one
+/// place deciding which version of a day is current.
+fn latest_per_date(mut entries: Vec<DiaryEntry>) -> Vec<DiaryEntry> {
+    entries.sort_by_key(|e| std::cmp::Reverse((e.date, e.exec_version)));
+    entries.dedup_by_key(|entry| entry.date);
+    entries
+}
+
+/// Dates are stored as `YYYY-MM-DD` text, so range bounds outside years
+/// 0..=9999 (like `NaiveDate::MIN`/`MAX`) are clamped to compare correctly
+fn range_bound(date: NaiveDate) -> String {
+    match date.year() {
+        year if year < 0 => "0000-01-01".to_string(),
+        year if year > 9999 => "9999-12-31".to_string(),
+        _ => date.format("%Y-%m-%d").to_string(),
+    }
+}
+
+/// A `file:` URI opening the database read-only. Characters SQLite would
+/// take for URI syntax are percent-encoded.
+fn read_only_uri(path: &Path) -> String {
+    let mut uri = String::from("file:");
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' | '?' | '#' => uri.push_str(&format!("%{:02X}", c as u32)),
+            _ => uri.push(c),
+        }
+    }
+    uri.push_str("?mode=ro");
+    uri
+}
 
 /// Repository implementation for diary entries
-/// Follows the Repository pattern to provide a clean persistence abstraction
+/// Follows the Repository pattern to provide a clean persistence abstraction.
+/// All writes go through a single connection while reads are spread
+/// over a small pool of read-only connections. Completed years can be
+/// moved into per-year shard databases, which range queries read
+/// transparently alongside the main database.
 pub struct DiaryRepository {
-    conn: Arc<RwLock<Connection>>,
+    db_path: PathBuf,
+    writer: Mutex<Connection>,
+    readers: Vec<Mutex<ReaderConnection>>,
+    next_reader: AtomicUsize,
+    compress: bool,
+    cancel: CancellationToken,
+    clock: Arc<dyn Clock>,
 }
 
-impl DiaryRepository {
-    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
-        let conn = Connection::open(db_path)?;
+impl DiaryRepository {
+    pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Self::with_settings(db_path, &SqliteSettings::default(), Arc::new(SystemClock))
+    }
+
+    /// Opens the repository applying the given PRAGMA settings. The time of
+    /// `clock` names the backup taken before migrating.
+    pub fn with_settings<P: AsRef<Path>>(db_path: P, settings: &SqliteSettings, clock: Arc<dyn Clock>) -> Result<Self> {
+        let db_path = db_path.as_ref();
+        let writer = Connection::open(db_path)?;
+
+        // Initialize database with the requested tuning
+        writer.execute_batch(&settings.writer_pragmas())?;
+        writer.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
+        let mut repo = Self {
+            db_path: db_path.to_path_buf(),
+            writer: Mutex::new(writer),
+            readers: Vec::with_capacity(READ_POOL_SIZE),
+            next_reader: AtomicUsize::new(0),
+            compress: true,
+            cancel: CancellationToken::default(),
+            clock,
+        };
+
+        // Readers are opened after migrating so they see the final schema
+        repo.migrate()?;
+        repo.open_readers(settings)?;
+
+        Ok(repo)
+    }
+
+    /// Opens an existing database through read-only (`mode=ro`) URIs only.
+    /// Nothing is created or migrated and every write fails, so this is safe
+    /// while another process syncs into the same database.
+    pub fn open_read_only<P: AsRef<Path>>(db_path: P, settings: &SqliteSettings) -> Result<Self> {
+        let db_path = db_path.as_ref();
+        if !db_path.exists() {
+            return Err(RustyDiaryError::InvalidConfig(format!("No database at {}", db_path.display())));
+        }
+
+        let conn = Self::open_reader(db_path, settings)?;
+        let schema = migrations::inspect(&conn)?;
+        if let Some(reason) = schema.incompatibility {
+            return Err(RustyDiaryError::InvalidConfig(reason));
+        }
+        if !schema.pending.is_empty() {
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "{} is at schema version {} and needs migrating to {}; open it once for writing, e.g. by syncing",
+                db_path.display(), schema.version, schema.max_supported
+            )));
+        }
+
+        let mut repo = Self {
+            db_path: db_path.to_path_buf(),
+            writer: Mutex::new(conn),
+            readers: Vec::with_capacity(READ_POOL_SIZE),
+            next_reader: AtomicUsize::new(0),
+            compress: true,
+            cancel: CancellationToken::default(),
+            clock: Arc::new(SystemClock),
+        };
+        repo.open_readers(settings)?;
+
+        Ok(repo)
+    }
+
+    /// An empty database that lives in memory for as long as the
+    /// repository does, so previews can run where no database exists yet
+    /// without creating one
+    pub fn in_memory(settings: &SqliteSettings) -> Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let uri = format!(
+            "file:rusty-diary-{}-{}?mode=memory&cache=shared",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        );
+        let flags = OpenFlags::SQLITE_OPEN_URI | OpenFlags::SQLITE_OPEN_NO_MUTEX;
+        let writer = Connection::open_with_flags(
+            &uri,
+            flags | OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE,
+        )?;
+        writer.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+
+        let mut repo = Self {
+            db_path: PathBuf::from(":memory:"),
+            writer: Mutex::new(writer),
+            readers: Vec::with_capacity(READ_POOL_SIZE),
+            next_reader: AtomicUsize::new(0),
+            compress: true,
+            cancel: CancellationToken::default(),
+            clock: Arc::new(SystemClock),
+        };
+        repo.migrate()?;
+        for _ in 0..READ_POOL_SIZE {
+            let reader = Connection::open_with_flags(&uri, flags | OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            reader.execute_batch(&settings.reader_pragmas())?;
+            reader.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+            repo.readers.push(Mutex::new(ReaderConnection::new(reader)));
+        }
+
+        Ok(repo)
+    }
+
+    fn open_readers(&mut self, settings: &SqliteSettings) -> Result<()> {
+        for _ in 0..READ_POOL_SIZE {
+            let reader = Self::open_reader(&self.db_path, settings)?;
+            self.readers.push(Mutex::new(ReaderConnection::new(reader)));
+        }
+        Ok(())
+    }
+
+    /// Enables or disables zstd compression for newly written content.
+    /// Existing rows keep their format until `recompress` is run.
+    pub fn set_compression(&mut self, enabled: bool) {
+        self.compress = enabled;
+    }
+
+    /// Stops batch writes, rolling back their transaction, once `cancel` is cancelled
+    pub fn set_cancellation(&mut self, cancel: CancellationToken) {
+        self.cancel = cancel;
+    }
+
+    /// Stamps runs, stars, shards and archives with the time of `clock`
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Rewrites every stored entry in the currently configured format.
+    /// Returns the number of rows whose encoding changed.
+    pub fn recompress(&self) -> Result<usize> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        let mut changed = 0;
+
+        {
+            let mut select = tx.prepare(
+                "SELECT exec_version, date, content, compressed FROM diary_entries"
+            )?;
+            let mut update = tx.prepare(
+                "UPDATE diary_entries SET content = ?1, compressed = ?2
+                 WHERE exec_version = ?3 AND date = ?4"
+            )?;
+
+            let mut rows = select.query([])?;
+            while let Some(row) = rows.next()? {
+                self.cancel.check()?;
+                let exec_version: i64 = row.get(0)?;
+                let date: String = row.get(1)?;
+                let was_compressed: bool = row.get(3)?;
+                let content = compression::decode(row.get_ref(2)?, was_compressed)?;
+
+                let (value, compressed) = compression::encode(&content, self.compress)?;
+                if compressed != was_compressed {
+                    update.execute(params![value, compressed, exec_version, date])?;
+                    changed += 1;
+                }
+            }
+        }
+
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    /// Stores a batch of entries atomically
+    pub fn store_batch(&self, entries: Vec<DiaryEntry>, progress: &dyn ProgressSink) -> Result<()> {
+        self.store_batch_with(entries, BatchMode::default(), progress)
+    }
+
+    /// Stores a batch of entries atomically, written as `mode` says
+    pub fn store_batch_with(&self, entries: Vec<DiaryEntry>, mode: BatchMode, progress: &dyn ProgressSink) -> Result<()> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+
+        tracing::debug!(entries = entries.len(), ?mode, "Storing batch");
+        progress.start("store", entries.len() as u64);
+
+        match mode {
+            BatchMode::Replace | BatchMode::Upsert => {
+                for entry in &entries {
+                    self.cancel.check()?;
+                    self.store_entry_row(&tx, entry, mode)?;
+                    Self::store_entry_details(&tx, entry)?;
+                    progress.advance(1);
+                }
+            }
+            BatchMode::DeferredMetadata => {
+                for entry in &entries {
+                    self.cancel.check()?;
+                    self.store_entry_row(&tx, entry, mode)?;
+                    progress.advance(1);
+                }
+                for entry in &entries {
+                    self.cancel.check()?;
+                    Self::store_entry_details(&tx, entry)?;
+                }
+            }
+        }
+
+        tx.commit()?;
+        progress.finish();
+        Ok(())
+    }
+
+    /// Opens a new sync run with the next id and returns it
+    pub fn start_sync_run(&self, normalization: &str) -> Result<i64> {
+        let conn = self.writer.lock();
+        let id = conn.query_row(LATEST_EXEC_VERSION_SELECT, [], |row| row.get::<_, i64>(0))? + 1;
+        conn.execute(
+            "INSERT INTO sync_runs (id, started_at, normalization) VALUES (?1, ?2, ?3)",
+            params![id, self.clock.now().to_string(), normalization],
+        )?;
+        Ok(id)
+    }
+
+    pub fn finish_sync_run(&self, id: i64, stats: &RunStats) -> Result<()> {
+        self.writer.lock().execute(
+            "UPDATE sync_runs SET finished_at = ?2, stats = ?3 WHERE id = ?1",
+            params![id, self.clock.now().to_string(), serde_json::to_string(stats)?],
+        )?;
+        Ok(())
+    }
+
+    /// Forgets a run that never finished, such as a cancelled one
+    pub fn delete_sync_run(&self, id: i64) -> Result<()> {
+        self.writer.lock().execute(
+            "DELETE FROM sync_runs WHERE id = ?1 AND finished_at IS NULL",
+            params![id],
+        )?;
+        Ok(())
+    }
+
+    /// Every recorded sync run, newest first
+    pub fn get_sync_runs(&self) -> Result<Vec<SyncRun>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, started_at, finished_at, normalization, stats FROM sync_runs ORDER BY id DESC"
+        )?;
+        let runs = stmt.query_map([], Self::map_row_to_run)?;
+        runs.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    pub fn get_sync_run(&self, id: i64) -> Result<Option<SyncRun>> {
+        self.reader()
+            .query_row(
+                "SELECT id, started_at, finished_at, normalization, stats FROM sync_runs WHERE id = ?1",
+                params![id],
+                Self::map_row_to_run,
+            )
+            .optional()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Deletes every version of the entry for a date from the main
+    /// database. Returns the number of deleted versions.
+    pub fn delete_entries(&self, date: NaiveDate) -> Result<usize> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM entry_metadata WHERE date = ?1", params![date.to_string()])?;
+        tx.execute("DELETE FROM entry_authors WHERE date = ?1", params![date.to_string()])?;
+        tx.execute("DELETE FROM entry_hashes WHERE date = ?1", params![date.to_string()])?;
+        Self::delete_day_details(&tx, date)?;
+        let deleted = tx.execute("DELETE FROM diary_entries WHERE date = ?1", params![date.to_string()])?;
+
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Retrieves entries within a date range, including archived shards
+    pub fn get_entries_by_date_range(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<DiaryEntry>> {
+        let mut entries = self.query_range(
+            start_date,
+            end_date,
+            ENTRY_COLUMNS_SELECT,
+            |row| self.map_row_to_entry(row),
+        )?;
+
+        entries.sort_by_key(|e| std::cmp::Reverse((e.date, e.exec_version)));
+        Ok(entries)
+    }
+
+    /// Whether some version of the entry for `date`, wherever it is kept,
+    /// has content with this hash
+    pub fn has_content(&self, date: NaiveDate, hash: &str) -> Result<bool> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT EXISTS (SELECT 1 FROM entry_hashes WHERE hash = ?1 AND date = ?2)
+                 OR EXISTS (SELECT 1 FROM archived_summaries WHERE hash = ?1 AND date = ?2)"
+        )?;
+        Ok(stmt.query_row(params![hash, date.to_string()], |row| row.get(0))?)
+    }
+
+    /// Retrieves the most recent version of the entry for a given date
+    pub fn get_latest_entry(&self, date: NaiveDate) -> Result<Option<DiaryEntry>> {
+        Ok(self.get_latest_entries(date, date)?.into_iter().next())
+    }
+
+    /// The latest version of each entry within a date range, newest first
+    pub fn get_latest_entries(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<DiaryEntry>> {
+        let entries = self.query_range(
+            start_date,
+            end_date,
+            LATEST_ENTRY_COLUMNS_SELECT,
+            |row| self.map_row_to_entry(row),
+        )?;
+
+        Ok(latest_per_date(entries))
+    }
+
+    /// Lists entry summaries within a date range without loading content
+    pub fn list_entries(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<EntrySummary>> {
+        let mut summaries = self.query_range(
+            start_date,
+            end_date,
+            "SELECT
+                e.exec_version,
+                e.date,
+                COALESCE((
+                    SELECT MAX(m.word_count) FROM {db}.entry_metadata m
+                    WHERE m.exec_version = e.exec_version AND m.date = e.date
+                ), 0),
+                e.created_at,
+                a.author
+             FROM {db}.diary_entries e
+             LEFT JOIN main.entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+             WHERE e.date BETWEEN ?1 AND ?2",
+            |row| Ok(EntrySummary {
+                exec_version: row.get(0)?,
+                date: row.get(1)?,
+                word_count: row.get(2)?,
+                created_at: row.get(3)?,
+                author: row.get(4)?,
+            })
+        )?;
+
+        summaries.sort_by_key(|e| std::cmp::Reverse((e.date, e.exec_version)));
+        Ok(summaries)
+    }
+
+    /// Retrieves entries within a date range
+    pub fn get_entries_by_exec_version(
+        &self,
+        exec_version: i64,
+    ) -> Result<Vec<DiaryEntry>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT
+                e.exec_version, e.date, e.content, e.created_at, e.updated_at, e.compressed, a.author
+             FROM diary_entries e
+             LEFT JOIN entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+             WHERE e.exec_version = :exec_version
+             ORDER BY e.date DESC"
+        )?;
+
+        let entries = stmt.query_map(
+            &[(":exec_version", &exec_version)],
+            |row| self.map_row_to_entry(row)
+        )?;
+
+        entries.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Gets the latest execution version, including archived shards
+    pub fn get_latest_exec_version(&self) -> Result<i64> {
+        self.reader()
+            .query_row(LATEST_EXEC_VERSION_SELECT, [], |row| row.get(0))
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Moves every entry dated in `year` into that year's shard database.
+    /// Only completed years can be archived. Returns the number of moved entries.
+    pub fn archive_year(&self, year: i32) -> Result<usize> {
+        if year >= self.clock.today().year() {
+            return Err(RustyDiaryError::InvalidConfig(format!(
+                "Cannot shard {}: only completed years can be archived", year
+            )));
+        }
+
+        let alias = shard::alias(year);
+        let path = shard::path(&self.db_path, year);
+        let mut conn = self.writer.lock();
+
+        conn.execute(
+            &format!("ATTACH DATABASE ?1 AS {}", alias),
+            params![path.to_string_lossy()],
+        )?;
+        let moved = Self::move_year_into_shard(&mut conn, &alias, year, &self.clock.now().to_string());
+        conn.flush_prepared_statement_cache();
+        conn.execute_batch(&format!("DETACH DATABASE {}", alias))?;
+
+        moved
+    }
+
+    /// Rebuilds what the store routine derives from each entry (word counts,
+    /// links, highlights, people and events) from the stored content, and SQLite's indexes
+    /// of the main database. The word counts must then cover every version,
+    /// or the rebuild fails rather than leave them drifting.
+    pub fn reindex(&self) -> Result<ReindexReport> {
+        let count = |table: &str| -> Result<usize> {
+            let conn = self.writer.lock();
+            Ok(conn.query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |row| row.get::<_, i64>(0))? as usize)
+        };
+        let before = [count("entry_metadata")?, count("entry_links")?, count("highlights")?, count("entry_people")?,
+            count("entry_events")?];
+
+        let entries = {
+            let mut conn = self.writer.lock();
+            let tx = conn.transaction()?;
+            let entries = tx
+                .prepare(
+                    "SELECT exec_version, date, content, created_at, updated_at, compressed, NULL
+                     FROM diary_entries",
+                )?
+                .query_map([], |row| self.map_row_to_entry(row))?
+                .collect::<SqlResult<Vec<_>>>()?;
+
+            tx.execute("DELETE FROM entry_metadata", [])?;
+            let mut insert = tx.prepare_cached(
+                "INSERT INTO entry_metadata (exec_version, date, word_count) VALUES (?1, ?2, ?3)",
+            )?;
+            for entry in &entries {
+                self.cancel.check()?;
+                insert.execute(params![entry.exec_version, entry.date.to_string(), entry.word_count()])?;
+            }
+            drop(insert);
+            tx.execute_batch("REINDEX;")?;
+            tx.commit()?;
+            entries.len()
+        };
+
+        let after = [count("entry_metadata")?, self.rebuild_links()?, self.rebuild_highlights()?, self.rebuild_people()?,
+            self.rebuild_events()?];
+        if after[0] != entries {
+            return Err(RustyDiaryError::ContentIntegrity(format!(
+                "{} entries but {} word counts after reindexing", entries, after[0]
+            )));
+        }
+
+        let tables = ["entry_metadata", "entry_links", "highlights", "entry_people", "entry_events"]
+            .iter()
+            .zip(before.iter().zip(after))
+            .map(|(table, (&before, after))| TableCount { table: table.to_string(), before, after })
+            .collect();
+        Ok(ReindexReport { entries, tables })
+    }
+
+    /// Refreshes the statistics the query planner relies on, in the main
+    /// database and every year shard. Shards created before the current
+    /// indexes existed get them first. Returns the number of databases.
+    pub fn analyze(&self) -> Result<usize> {
+        let conn = self.writer.lock();
+        let years = conn
+            .prepare("SELECT year FROM shards ORDER BY year")?
+            .query_map([], |row| row.get(0))?
+            .collect::<SqlResult<Vec<i32>>>()?;
+
+        for &year in &years {
+            let alias = shard::alias(year);
+            conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {}", alias),
+                params![shard::path(&self.db_path, year).to_string_lossy()],
+            )?;
+            let analyzed = conn.execute_batch(&format!(
+                "{} ANALYZE {alias}; PRAGMA {alias}.optimize;",
+                shard::schema(&alias),
+                alias = alias,
+            ));
+            conn.execute_batch(&format!("DETACH DATABASE {}", alias))?;
+            analyzed?;
+        }
+
+        conn.execute_batch("ANALYZE main; PRAGMA main.optimize;")?;
+        tracing::debug!(shards = years.len(), "Analyzed database");
+        Ok(years.len() + 1)
+    }
+
+    /// Moves every entry in the main database dated before `before` into a
+    /// new compressed cold archive file, keeping only their summaries. What
+    /// their days keep besides, such as stars, tags and habits, goes along,
+    /// and so do the `attachments` no entry that stays links to.
+    /// Returns the number of archived entries.
+    pub fn cold_archive(&self, before: NaiveDate, attachments: Option<&archive::Attachments>) -> Result<usize> {
+        let mut conn = self.writer.lock();
+
+        let entries = {
+            let mut stmt = conn.prepare_cached(
+                "SELECT e.exec_version, e.date, e.content, e.created_at, e.updated_at, e.compressed, a.author
+                 FROM main.diary_entries e
+                 LEFT JOIN main.entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+                 WHERE e.date < ?1
+                 ORDER BY e.date, e.exec_version"
+            )?;
+            let rows = stmt.query_map(params![before.to_string()], |row| self.map_row_to_entry(row))?;
+            rows.collect::<SqlResult<Vec<_>>>()?
+        };
+
+        if entries.is_empty() {
+            return Ok(0);
+        }
+
+        let mut dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+        dates.dedup();
+        let mut days = Vec::new();
+        for date in dates {
+            days.extend(Self::archived_day(&conn, date)?);
+        }
+        let files = match attachments {
+            Some(attachments) => self.archived_files(&entries, attachments)?,
+            None => Vec::new(),
+        };
+
+        let created_at = self.clock.now();
+        let path = archive::path(&self.db_path, created_at);
+        let cold = archive::ColdArchive { entries, days, files };
+        archive::write(&path, &cold)?;
+
+        // The archive file only becomes authoritative once the live rows are gone
+        if let Err(e) = Self::record_cold_archive(&mut conn, &path, &cold.entries, created_at) {
+            let _ = std::fs::remove_file(&path);
+            return Err(e);
+        }
+
+        // The attachments are safe in the archive now; one left behind only takes space
+        if let Some(attachments) = attachments {
+            for file in &cold.files {
+                let path = attachments.directory.join(&file.path);
+                if let Err(e) = std::fs::remove_file(&path) {
+                    tracing::warn!("Failed to remove archived attachment {:?}: {}", path, e);
+                }
+            }
+        }
+
+        Ok(cold.entries.len())
+    }
+
+    /// What the main database keeps for a day besides its entries, to be
+    /// archived with them; `None` when there is nothing
+    fn archived_day(conn: &Connection, date: NaiveDate) -> Result<Option<archive::ArchivedDayState>> {
+        let day = date.to_string();
+        let starred_at = conn
+            .prepare_cached("SELECT starred_at FROM entry_stars WHERE date = ?1")?
+            .query_row(params![day], |row| row.get(0))
+            .optional()?;
+        let tags = conn
+            .prepare_cached(
+                "SELECT date, tag, exec_version, source, confidence FROM entry_tags
+                 WHERE date = ?1 ORDER BY tag"
+            )?
+            .query_map(params![day], |row| {
+                Ok(StoredTag {
+                    date: row.get(0)?,
+                    tag: row.get(1)?,
+                    exec_version: row.get(2)?,
+                    source: row.get(3)?,
+                    confidence: row.get(4)?,
+                })
+            })?
+            .collect::<SqlResult<Vec<_>>>()?;
+        let fields = conn
+            .prepare_cached("SELECT key, value FROM entry_fields WHERE date = ?1 ORDER BY key")?
+            .query_map(params![day], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+        let habits = conn
+            .prepare_cached("SELECT habit, done FROM habit_checks WHERE date = ?1 ORDER BY habit")?
+            .query_map(params![day], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+        let highlights = conn
+            .prepare_cached("SELECT text, stage, due, last_reviewed FROM highlights WHERE date = ?1 ORDER BY id")?
+            .query_map(params![day], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)))?
+            .collect::<SqlResult<Vec<_>>>()?;
+
+        if starred_at.is_none() && tags.is_empty() && fields.is_empty() && habits.is_empty() && highlights.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(archive::ArchivedDayState { date, starred_at, tags, fields, habits, highlights }))
+    }
+
+    /// The files the archived entries link to that no entry staying behind
+    /// does, read into the archive with the days linking to them
+    fn archived_files(
+        &self,
+        entries: &[DiaryEntry],
+        attachments: &archive::Attachments,
+    ) -> Result<Vec<archive::ArchivedFile>> {
+        let mut linked: BTreeMap<String, Vec<NaiveDate>> = BTreeMap::new();
+        for entry in entries {
+            for path in (attachments.linked)(entry) {
+                let dates = linked.entry(path).or_default();
+                if dates.last() != Some(&entry.date) {
+                    dates.push(entry.date);
+                }
+            }
+        }
+        if linked.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let archived: HashSet<(i64, NaiveDate)> = entries
+            .iter()
+            .map(|entry| (entry.exec_version, entry.date))
+            .collect();
+        for entry in self.get_entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)? {
+            if !archived.contains(&(entry.exec_version, entry.date)) {
+                for path in (attachments.linked)(&entry) {
+                    linked.remove(&path);
+                }
+            }
+        }
+
+        let mut files = Vec::new();
+        for (path, dates) in linked {
+            let full_path = attachments.directory.join(&path);
+            if full_path.is_file() {
+                let bytes = std::fs::read(&full_path).at_path(&full_path)?;
+                files.push(archive::ArchivedFile::new(path, dates, &bytes));
+            }
+        }
+        Ok(files)
+    }
+
+    /// Restores archived entries dated within the range back into the main
+    /// database, with what their days kept, and their attachments into
+    /// `directory` unless a file is there already. Archives left empty are
+    /// deleted. Returns the restored count.
+    pub fn unarchive(&self, start_date: NaiveDate, end_date: NaiveDate, directory: &Path) -> Result<usize> {
+        let mut conn = self.writer.lock();
+
+        let archives = {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, file_name FROM cold_archives
+                 WHERE start_date <= ?2 AND end_date >= ?1"
+            )?;
+            let rows = stmt.query_map(
+                params![range_bound(start_date), range_bound(end_date)],
+                |row| Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            )?;
+            rows.collect::<SqlResult<Vec<_>>>()?
+        };
+
+        let in_range = |date: &NaiveDate| *date >= start_date && *date <= end_date;
+        let mut restored = 0;
+        for (id, file_name) in archives {
+            let path = self.db_path.with_file_name(&file_name);
+            let cold = archive::read(&path)?;
+            let (restore, entries): (Vec<_>, Vec<_>) = cold.entries.into_iter().partition(|e| in_range(&e.date));
+            let (restore_days, days): (Vec<_>, Vec<_>) = cold.days.into_iter().partition(|d| in_range(&d.date));
+
+            // A file linked from days on both sides comes back and stays archived too
+            let mut restore_files = Vec::new();
+            let mut files = Vec::new();
+            for file in cold.files {
+                let (back, kept): (Vec<_>, Vec<_>) = file.dates.iter().copied().partition(in_range);
+                if !back.is_empty() {
+                    restore_files.push((file.target(directory)?, file.bytes()?));
+                }
+                if !kept.is_empty() {
+                    files.push(archive::ArchivedFile { dates: kept, ..file });
+                }
+            }
+            let keep = archive::ColdArchive { entries, days, files };
+
+            let tx = conn.transaction()?;
+            for entry in &restore {
+                self.store_entry_internal(&tx, entry)?;
+            }
+            for day in &restore_days {
+                Self::restore_day(&tx, day)?;
+            }
+
+            if keep.entries.is_empty() {
+                tx.execute("DELETE FROM cold_archives WHERE id = ?1", params![id])?;
+            } else {
+                tx.execute(
+                    "DELETE FROM archived_summaries
+                     WHERE archive_id = ?1 AND date BETWEEN ?2 AND ?3",
+                    params![id, range_bound(start_date), range_bound(end_date)],
+                )?;
+                tx.execute(
+                    "UPDATE cold_archives SET start_date = ?2, end_date = ?3, entry_count = ?4
+                     WHERE id = ?1",
+                    params![
+                        id,
+                        keep.entries.first().map(|e| e.date.to_string()),
+                        keep.entries.last().map(|e| e.date.to_string()),
+                        keep.entries.len() as i64,
+                    ],
+                )?;
+            }
+            tx.commit()?;
+
+            for (target, bytes) in &restore_files {
+                if target.exists() {
+                    tracing::warn!("Keeping {:?} instead of its archived copy", target);
+                    continue;
+                }
+                if let Some(parent) = target.parent() {
+                    std::fs::create_dir_all(parent).at_path(parent)?;
+                }
+                std::fs::write(target, bytes).at_path(target)?;
+            }
+
+            // Rewrite the file last: a crash before this point only leaves
+            // restored entries duplicated in the archive, never lost
+            if keep.entries.is_empty() {
+                std::fs::remove_file(&path)?;
+            } else {
+                archive::write(&path, &keep)?;
+            }
+
+            restored += restore.len();
+        }
+
+        Ok(restored)
+    }
+
+    /// Every cold-archived entry, read back from the archive files
+    pub fn get_archived_entries(&self) -> Result<Vec<DiaryEntry>> {
+        let file_names = {
+            let conn = self.reader();
+            let mut stmt = conn.prepare_cached("SELECT file_name FROM cold_archives ORDER BY id")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            rows.collect::<SqlResult<Vec<_>>>()?
+        };
+
+        let mut entries = Vec::new();
+        for file_name in file_names {
+            entries.extend(archive::read(&self.db_path.with_file_name(&file_name))?.entries);
+        }
+        Ok(entries)
+    }
+
+    /// Summaries of cold-archived entries within a date range
+    pub fn get_archived_summaries(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<EntrySummary>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT exec_version, date, word_count, created_at
+             FROM archived_summaries
+             WHERE date BETWEEN ?1 AND ?2
+             ORDER BY date DESC, exec_version DESC"
+        )?;
+
+        let summaries = stmt.query_map(
+            params![range_bound(start_date), range_bound(end_date)],
+            |row| Ok(EntrySummary {
+                exec_version: row.get(0)?,
+                date: row.get(1)?,
+                word_count: row.get(2)?,
+                created_at: row.get(3)?,
+                author: None,
+            })
+        )?;
+
+        summaries.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Days whose latest entry links to `date`, oldest first
+    pub fn get_backlinks(&self, date: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date FROM entry_links WHERE target_date = ?1 ORDER BY date"
+        )?;
+        let dates = stmt.query_map(params![date.to_string()], |row| row.get(0))?;
+        dates.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Every link of the latest entry versions, ordered by source day
+    pub fn get_links(&self) -> Result<Vec<EntryLink>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date, target, target_date FROM entry_links ORDER BY date, target"
+        )?;
+        let links = stmt.query_map([], |row| {
+            Ok(EntryLink { source: row.get(0)?, target: row.get(1)?, target_date: row.get(2)? })
+        })?;
+        links.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Re-reads the links of every live or sharded entry, e.g. for entries
+    /// stored before links were tracked. Returns the number of links.
+    pub fn rebuild_links(&self) -> Result<usize> {
+        // Oldest first, so each day ends up with its latest version's links
+        let mut entries = self.get_entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+        entries.reverse();
+
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM entry_links", [])?;
+        for entry in &entries {
+            Self::replace_links(&tx, entry)?;
+        }
+        let count = tx.query_row("SELECT COUNT(*) FROM entry_links", [], |row| row.get::<_, i64>(0))?;
+        tx.commit()?;
+        Ok(count as usize)
+    }
+
+    /// Replaces the tags a source recorded for an entry's day. Like links,
+    /// stored tags live in the main database only.
+    pub fn replace_tags(&self, entry: &DiaryEntry, source: &str, tags: &[(String, f64)]) -> Result<()> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM entry_tags WHERE date = ?1 AND source = ?2",
+            params![entry.date.to_string(), source],
+        )?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR IGNORE INTO entry_tags (date, tag, exec_version, source, confidence)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for (tag, confidence) in tags {
+                insert.execute(params![entry.date.to_string(), tag, entry.exec_version, source, confidence])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stores tags exactly as given, replacing those of the same day and name
+    pub fn restore_tags(&self, tags: &[StoredTag]) -> Result<()> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO entry_tags (date, tag, exec_version, source, confidence)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?;
+            for tag in tags {
+                insert.execute(params![tag.date.to_string(), tag.tag, tag.exec_version, tag.source, tag.confidence])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stored tags of a day, most confident first
+    pub fn get_tags(&self, date: NaiveDate) -> Result<Vec<StoredTag>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date, tag, exec_version, source, confidence FROM entry_tags
+             WHERE date = ?1
+             ORDER BY confidence DESC, tag"
+        )?;
+        let tags = stmt.query_map(params![date.to_string()], |row| {
+            Ok(StoredTag {
+                date: row.get(0)?,
+                tag: row.get(1)?,
+                exec_version: row.get(2)?,
+                source: row.get(3)?,
+                confidence: row.get(4)?,
+            })
+        })?;
+        tags.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Stars or unstars a day. Returns whether that changed anything.
+    pub fn set_starred(&self, date: NaiveDate, starred: bool) -> Result<bool> {
+        let conn = self.writer.lock();
+        let changed = if starred {
+            conn.execute(
+                "INSERT OR IGNORE INTO entry_stars (date, starred_at) VALUES (?1, ?2)",
+                params![date.to_string(), self.clock.now().to_string()],
+            )?
+        } else {
+            conn.execute("DELETE FROM entry_stars WHERE date = ?1", params![date.to_string()])?
+        };
+        Ok(changed > 0)
+    }
+
+    /// Starred days within a date range, newest first
+    pub fn get_starred(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<NaiveDate>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date FROM entry_stars WHERE date BETWEEN ?1 AND ?2 ORDER BY date DESC"
+        )?;
+        let dates = stmt.query_map(params![range_bound(start_date), range_bound(end_date)], |row| row.get(0))?;
+        dates.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Sets a custom metadata field of a day, replacing its value
+    pub fn set_field(&self, date: NaiveDate, key: &str, value: &str) -> Result<()> {
+        self.writer.lock().execute(
+            "INSERT INTO entry_fields (date, key, value) VALUES (?1, ?2, ?3)
+             ON CONFLICT (date, key) DO UPDATE SET value = excluded.value",
+            params![date.to_string(), key, value],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a custom metadata field. Returns whether it was set.
+    pub fn delete_field(&self, date: NaiveDate, key: &str) -> Result<bool> {
+        let deleted = self.writer.lock().execute(
+            "DELETE FROM entry_fields WHERE date = ?1 AND key = ?2",
+            params![date.to_string(), key],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Adds words to the spelling dictionary of a language. Returns how
+    /// many were new.
+    pub fn add_spelling_words(&self, lang: &str, words: &[String]) -> Result<usize> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        let added_at = self.clock.now().to_string();
+        let mut added = 0;
+        {
+            let mut insert = tx.prepare("INSERT OR IGNORE INTO spelling_words (lang, word, added_at) VALUES (?1, ?2, ?3)")?;
+            for word in words {
+                added += insert.execute(params![lang, word, added_at])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Removes a word from the spelling dictionary of a language. Returns
+    /// whether it was there.
+    pub fn delete_spelling_word(&self, lang: &str, word: &str) -> Result<bool> {
+        let deleted = self.writer.lock().execute(
+            "DELETE FROM spelling_words WHERE lang = ?1 AND word = ?2",
+            params![lang, word],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// The words of a language's spelling dictionary, sorted
+    pub fn get_spelling_words(&self, lang: &str) -> Result<Vec<String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT word FROM spelling_words WHERE lang = ?1 ORDER BY word")?;
+        let words = stmt.query_map(params![lang], |row| row.get(0))?;
+        words.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    pub fn insert_backup(&self, backup: &BackupRecord, attachments: &BTreeMap<String, String>) -> Result<()> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO backups (id, parent, exec_version, entries, created_at, path) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![backup.id, backup.parent, backup.exec_version, backup.entries, backup.created_at.to_string(), backup.path],
+        )?;
+        {
+            let mut insert = tx.prepare("INSERT INTO backup_attachments (backup_id, path, sha256) VALUES (?1, ?2, ?3)")?;
+            for (path, sha256) in attachments {
+                insert.execute(params![backup.id, path, sha256])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Every recorded backup, newest first
+    pub fn get_backups(&self) -> Result<Vec<BackupRecord>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, parent, exec_version, entries, created_at, path FROM backups ORDER BY created_at DESC, rowid DESC",
+        )?;
+        let backups = stmt.query_map([], |row| {
+            Ok(BackupRecord {
+                id: row.get(0)?,
+                parent: row.get(1)?,
+                exec_version: row.get(2)?,
+                entries: row.get(3)?,
+                created_at: row.get(4)?,
+                path: row.get(5)?,
+            })
+        })?;
+        backups.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    pub fn get_backup_attachments(&self, id: &str) -> Result<BTreeMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT path, sha256 FROM backup_attachments WHERE backup_id = ?1")?;
+        let attachments = stmt.query_map(params![id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        attachments.collect::<SqlResult<BTreeMap<_, _>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Versions in the main database with `after < exec_version <= through`,
+    /// oldest first
+    pub fn get_entries_between_versions(&self, after: i64, through: i64) -> Result<Vec<DiaryEntry>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT
+                e.exec_version, e.date, e.content, e.created_at, e.updated_at, e.compressed, a.author
+             FROM diary_entries e
+             LEFT JOIN entry_authors a ON a.exec_version = e.exec_version AND a.date = e.date
+             WHERE e.exec_version > ?1 AND e.exec_version <= ?2
+             ORDER BY e.exec_version, e.date"
+        )?;
+        let entries = stmt.query_map(params![after, through], |row| self.map_row_to_entry(row))?;
+        entries.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    pub fn count_entries_through_version(&self, through: i64) -> Result<i64> {
+        self.reader()
+            .query_row("SELECT COUNT(*) FROM diary_entries WHERE exec_version <= ?1", params![through], |row| row.get(0))
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// The custom metadata fields of a day
+    pub fn get_fields(&self, date: NaiveDate) -> Result<BTreeMap<String, String>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT key, value FROM entry_fields WHERE date = ?1")?;
+        let fields = stmt.query_map(params![date.to_string()], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        fields.collect::<SqlResult<BTreeMap<_, _>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Days within a date range whose field `key` is `value`, newest first
+    pub fn get_dates_with_field(
+        &self,
+        key: &str,
+        value: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<NaiveDate>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date FROM entry_fields
+             WHERE key = ?1 AND value = ?2 AND date BETWEEN ?3 AND ?4
+             ORDER BY date DESC"
+        )?;
+        let dates = stmt.query_map(
+            params![key, value, range_bound(start_date), range_bound(end_date)],
+            |row| row.get(0),
+        )?;
+        dates.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Replaces the habit checks of a day
+    pub fn replace_habit_checks(&self, date: NaiveDate, checks: &[(String, bool)]) -> Result<()> {
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+
+        tx.execute("DELETE FROM habit_checks WHERE date = ?1", params![date.to_string()])?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO habit_checks (date, habit, done) VALUES (?1, ?2, ?3)",
+            )?;
+            for (habit, done) in checks {
+                insert.execute(params![date.to_string(), habit, done])?;
+            }
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Habit checks within a date range, by habit, then oldest first
+    pub fn get_habit_checks(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<HabitCheck>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date, habit, done FROM habit_checks
+             WHERE date BETWEEN ?1 AND ?2
+             ORDER BY habit, date"
+        )?;
+        let checks = stmt.query_map(params![range_bound(start_date), range_bound(end_date)], |row| {
+            Ok(HabitCheck { date: row.get(0)?, habit: row.get(1)?, done: row.get(2)? })
+        })?;
+        checks.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Years that have been archived into shard databases
+    pub fn get_shard_years(&self) -> Result<Vec<i32>> {
+        self.reader().shard_years(NaiveDate::MIN, NaiveDate::MAX)
+    }
+
+    /// Retrieves metadata for statistical analysis
+    pub fn get_metadata(&self) -> Result<Vec<EntryMetadata>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT
+                e.date,
+                m.word_count,
+                e.exec_version
+             FROM diary_entries e
+             JOIN entry_metadata m ON
+                e.exec_version = m.exec_version AND
+                e.date = m.date
+             ORDER BY 1,3 DESC"
+        )?;
+
+        let metadata = stmt.query_map([], |row| {
+            Ok(EntryMetadata {
+                date: NaiveDate::parse_from_str(&row.get::<_, String>(0)?, "%Y-%m-%d")
+                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
+                word_count: row.get(1)?,
+                exec_version: row.get(2)?,
+            })
+        })?;
+
+        metadata.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    // Private helper methods
+
+    fn open_reader(db_path: &Path, settings: &SqliteSettings) -> Result<Connection> {
+        let conn = Connection::open_with_flags(
+            read_only_uri(db_path),
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        conn.execute_batch(&settings.reader_pragmas())?;
+        conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+        Ok(conn)
+    }
+
+    /// Hands out an idle reader if there is one, otherwise waits
+    /// on the next reader in round-robin order
+    fn reader(&self) -> MutexGuard<'_, ReaderConnection> {
+        if let Some(conn) = self.readers.iter().find_map(|reader| reader.try_lock()) {
+            return conn;
+        }
+
+        let index = self.next_reader.fetch_add(1, Ordering::Relaxed) % self.readers.len();
+        self.readers[index].lock()
+    }
+
+    /// Runs a `{db}`-templated SELECT against the main database and every
+    /// shard overlapping the range. Shards are read in batches that fit
+    /// SQLite's attach limit; rows come back unordered.
+    fn query_range<T, F>(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        select: &str,
+        mut map: F,
+    ) -> Result<Vec<T>>
+    where
+        F: FnMut(&Row) -> SqlResult<T>,
+    {
+        let mut reader = self.reader();
+        let years = reader.shard_years(start_date, end_date)?;
+
+        // The main database rides along with the first batch of shards
+        let mut batches: Vec<&[i32]> = years.chunks(MAX_ATTACHED_SHARDS).collect();
+        if batches.is_empty() {
+            batches.push(&[]);
+        }
+
+        let mut results = Vec::new();
+        for (i, batch) in batches.into_iter().enumerate() {
+            reader.attach(&self.db_path, batch)?;
+
+            let mut sources: Vec<String> = batch.iter().map(|year| shard::alias(*year)).collect();
+            if i == 0 {
+                sources.insert(0, "main".to_string());
+            }
+
+            let sql = shard::union_query(select, &sources);
+            let (start, end) = (range_bound(start_date), range_bound(end_date));
+            let rows = reader
+                .prepare_cached(&sql)
+                .and_then(|mut stmt| stmt.query_map(params![start, end], &mut map)?.collect::<SqlResult<Vec<_>>>())
+                .with_sql(&sql, &[&start, &end])?;
+            results.extend(rows);
+        }
+
+        Ok(results)
+    }
+
+    /// Copies the year into its shard, then records the shard and deletes
+    /// the year from the main database. A COMMIT spanning attached WAL
+    /// databases is not atomic, so each database gets its own transaction:
+    /// a crash in between leaves the year in main and an unlisted shard
+    /// copy, which the next run overwrites.
+    fn move_year_into_shard(conn: &mut Connection, alias: &str, year: i32, archived_at: &str) -> Result<usize> {
+        let moved = Self::copy_year_into_shard(conn, alias, year)?;
+
+        let (start, end) = (format!("{:04}-01-01", year), format!("{:04}-12-31", year));
+        let tx = conn.transaction()?;
+        let (count, max_exec_version): (i64, i64) = tx.query_row(
+            &format!("SELECT COUNT(*), COALESCE(MAX(exec_version), 0) FROM {}.diary_entries", alias),
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute(
+            "INSERT OR REPLACE INTO main.shards (year, entry_count, max_exec_version, archived_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![year, count, max_exec_version, archived_at],
+        )?;
+        tx.execute(
+            "DELETE FROM main.entry_metadata WHERE date BETWEEN ?1 AND ?2",
+            params![start, end],
+        )?;
+        tx.execute(
+            "DELETE FROM main.diary_entries WHERE date BETWEEN ?1 AND ?2",
+            params![start, end],
+        )?;
+        tx.commit()?;
+
+        Ok(moved)
+    }
+
+    /// Writes the year's entries and word counts into the shard and syncs
+    /// it to disk, leaving the main database untouched. Word counts already
+    /// in the shard for the copied entries are replaced, not duplicated.
+    fn copy_year_into_shard(conn: &mut Connection, alias: &str, year: i32) -> Result<usize> {
+        let (start, end) = (format!("{:04}-01-01", year), format!("{:04}-12-31", year));
+
+        conn.execute_batch(&format!("PRAGMA {}.synchronous = FULL", alias))?;
+        let tx = conn.transaction()?;
+        tx.execute_batch(&shard::schema(alias))?;
+
+        tx.execute(
+            &format!(
+                "DELETE FROM {db}.entry_metadata WHERE EXISTS (
+                    SELECT 1 FROM main.diary_entries e
+                    WHERE e.exec_version = {db}.entry_metadata.exec_version
+                      AND e.date = {db}.entry_metadata.date
+                      AND e.date BETWEEN ?1 AND ?2
+                 )",
+                db = alias
+            ),
+            params![start, end],
+        )?;
+        let moved = tx.execute(
+            &format!(
+                "INSERT OR REPLACE INTO {}.diary_entries
+                    (exec_version, date, content, created_at, updated_at, compressed)
+                 SELECT exec_version, date, content, created_at, updated_at, compressed
+                 FROM main.diary_entries WHERE date BETWEEN ?1 AND ?2",
+                alias
+            ),
+            params![start, end],
+        )?;
+        tx.execute(
+            &format!(
+                "INSERT INTO {}.entry_metadata (exec_version, date, word_count)
+                 SELECT exec_version, date, word_count
+                 FROM main.entry_metadata WHERE date BETWEEN ?1 AND ?2",
+                alias
+            ),
+            params![start, end],
+        )?;
+
+        tx.commit()?;
+        Ok(moved)
+    }
+
+    fn record_cold_archive(
+        conn: &mut Connection,
+        path: &Path,
+        entries: &[DiaryEntry],
+        created_at: chrono::NaiveDateTime,
+    ) -> Result<()> {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO cold_archives (file_name, start_date, end_date, entry_count, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                file_name,
+                entries.first().map(|e| e.date.to_string()),
+                entries.last().map(|e| e.date.to_string()),
+                entries.len() as i64,
+                created_at.to_string(),
+            ],
+        )?;
+        let archive_id = tx.last_insert_rowid();
+
+        for entry in entries {
+            tx.execute(
+                "INSERT OR REPLACE INTO archived_summaries
+                    (archive_id, exec_version, date, word_count, created_at, hash)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![
+                    archive_id,
+                    entry.exec_version,
+                    entry.date.to_string(),
+                    entry.word_count(),
+                    entry.created_at.to_string(),
+                    entry.content_hash(),
+                ],
+            )?;
+            for table in ["entry_metadata", "entry_hashes", "entry_authors", "diary_entries"] {
+                tx.execute(
+                    &format!("DELETE FROM main.{} WHERE exec_version = ?1 AND date = ?2", table),
+                    params![entry.exec_version, entry.date.to_string()],
+                )?;
+            }
+        }
+
+        let mut dates: Vec<NaiveDate> = entries.iter().map(|entry| entry.date).collect();
+        dates.dedup();
+        for date in dates {
+            Self::delete_day_details(&tx, date)?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Clears what the main database keeps for a day besides its entries:
+    /// links, tags, stars, fields, habits, highlights, people and events
+    fn delete_day_details(tx: &Transaction, date: NaiveDate) -> Result<()> {
+        for table in [
+            "entry_links",
+            "entry_tags",
+            "entry_stars",
+            "entry_fields",
+            "habit_checks",
+            "highlights",
+            "entry_people",
+            "entry_events",
+        ] {
+            tx.execute(&format!("DELETE FROM main.{} WHERE date = ?1", table), params![date.to_string()])?;
+        }
+        Ok(())
+    }
+
+    /// Puts back what an archived day kept besides its entries, which are
+    /// restored first. Anything set for the day while it was archived, such
+    /// as a star, is kept; highlights found again get their review progress.
+    fn restore_day(tx: &Transaction, day: &archive::ArchivedDayState) -> Result<()> {
+        let date = day.date.to_string();
+        if let Some(starred_at) = &day.starred_at {
+            tx.prepare_cached("INSERT OR IGNORE INTO entry_stars (date, starred_at) VALUES (?1, ?2)")?
+                .execute(params![date, starred_at])?;
+        }
+        for tag in &day.tags {
+            tx.prepare_cached(
+                "INSERT OR IGNORE INTO entry_tags (date, tag, exec_version, source, confidence)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?.execute(params![date, tag.tag, tag.exec_version, tag.source, tag.confidence])?;
+        }
+        for (key, value) in &day.fields {
+            tx.prepare_cached("INSERT OR IGNORE INTO entry_fields (date, key, value) VALUES (?1, ?2, ?3)")?
+                .execute(params![date, key, value])?;
+        }
+        for (habit, done) in &day.habits {
+            tx.prepare_cached("INSERT OR IGNORE INTO habit_checks (date, habit, done) VALUES (?1, ?2, ?3)")?
+                .execute(params![date, habit, done])?;
+        }
+        for (text, stage, due, last_reviewed) in &day.highlights {
+            tx.prepare_cached(
+                "UPDATE highlights SET stage = ?3, due = ?4, last_reviewed = ?5 WHERE date = ?1 AND text = ?2",
+            )?.execute(params![date, text, stage, due, last_reviewed])?;
+        }
+        Ok(())
+    }
+
+    /// Brings the schema up to date, backing the file up first when an
+    /// existing database needs changes
+    fn migrate(&self) -> Result<()> {
+        let mut conn = self.writer.lock();
 
-        // Initialize database with optimal settings
-        conn.execute_batch(PRAGMAS)?;
+        let plan = migrations::plan(&conn, migrations::latest_version())?;
+        if plan.is_empty() {
+            return Ok(());
+        }
 
-        let repo = Self {
-            conn: Arc::new(RwLock::new(conn)),
-        };
+        if plan.current_version > 0 {
+            let backup_path = migrations::backup(&conn, &self.db_path, plan.current_version, self.clock.now())?;
+            tracing::info!("Backed up database to {:?} before migrating", backup_path);
+        }
 
-        repo.migrate()?;
-        Ok(repo)
+        migrations::apply(&mut conn, &plan)
     }
 
-    /// Stores a batch of entries atomically
-    pub fn store_batch(&self, entries: Vec<DiaryEntry>) -> Result<()> {
-        let mut conn = self.conn.write();
-        let tx = conn.transaction()?;
+    fn store_entry_internal(&self, tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        self.store_entry_row(tx, entry, BatchMode::Replace)?;
+        Self::store_entry_details(tx, entry)
+    }
 
-        println!("Storing batch of entries: {:#?}", entries);
+    /// Stores the entry itself, and the run it belongs to
+    fn store_entry_row(&self, tx: &Transaction, entry: &DiaryEntry, mode: BatchMode) -> Result<()> {
+        let (content, compressed) = compression::encode(&entry.content, self.compress)?;
 
-        for entry in entries {
-            self.store_entry_internal(&tx, &entry)?;
-        }
+        // Entries stored outside a sync, or restored from an archive, get a
+        // run of their own so the foreign key holds
+        tx.prepare_cached(
+            "INSERT OR IGNORE INTO sync_runs (id, started_at, finished_at) VALUES (?1, ?2, ?2)",
+        )?.execute(params![entry.exec_version, entry.created_at.to_string()])?;
 
-        tx.commit()?;
+        let sql = match mode {
+            BatchMode::Upsert => {
+                // Nothing cascades on an update, so replace the metadata here
+                tx.prepare_cached("DELETE FROM entry_metadata WHERE exec_version = ?1 AND date = ?2")?
+                    .execute(params![entry.exec_version, entry.date.to_string()])?;
+                "INSERT INTO diary_entries
+                    (exec_version, date, content, created_at, updated_at, compressed)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT (exec_version, date) DO UPDATE SET
+                    content = excluded.content,
+                    created_at = excluded.created_at,
+                    updated_at = excluded.updated_at,
+                    compressed = excluded.compressed"
+            }
+            BatchMode::Replace | BatchMode::DeferredMetadata => {
+                "INSERT OR REPLACE INTO diary_entries
+                    (exec_version, date, content, created_at, updated_at, compressed)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+            }
+        };
+        tx.prepare_cached(sql)?.execute(params![
+            entry.exec_version,
+            entry.date.to_string(),
+            content,
+            entry.created_at.to_string(),
+            entry.updated_at.map(|dt| dt.to_string()),
+            compressed,
+        ])?;
         Ok(())
     }
 
-    /// Retrieves entries within a date range
-    pub fn get_entries_by_date_range(
-        &self,
-        start_date: NaiveDate,
-        end_date: NaiveDate,
-    ) -> Result<Vec<DiaryEntry>> {
-        let conn = self.conn.read();
-        let mut stmt = conn.prepare(
-            "SELECT
-                exec_version, date, content, created_at, updated_at
-             FROM diary_entries
-             WHERE date BETWEEN ?1 AND ?2
-             ORDER BY date DESC, exec_version DESC"
-        )?;
+    /// Stores what is derived from the entry: metadata, author, links, highlights, people and events
+    fn store_entry_details(tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        // Store metadata
+        tx.prepare_cached(
+            "INSERT OR REPLACE INTO entry_metadata
+                (exec_version, date, word_count)
+             VALUES (?1, ?2, ?3)",
+        )?.execute(params![
+            entry.exec_version,
+            entry.date.to_string(),
+            entry.word_count(),
+        ])?;
 
-        let entries = stmt.query_map(
-            params![start_date.to_string(), end_date.to_string()],
-            |row| self.map_row_to_entry(row)
-        )?;
+        // Hashes and authors live apart, where sharding leaves them in place
+        tx.prepare_cached(
+            "INSERT OR REPLACE INTO entry_hashes (exec_version, date, hash) VALUES (?1, ?2, ?3)",
+        )?.execute(params![entry.exec_version, entry.date.to_string(), entry.content_hash()])?;
 
-        entries.collect::<SqlResult<Vec<_>>>()
-            .map_err(RustyDiaryError::from)
+        match &entry.author {
+            Some(author) => tx.prepare_cached(
+                "INSERT OR REPLACE INTO entry_authors (exec_version, date, author) VALUES (?1, ?2, ?3)",
+            )?.execute(params![entry.exec_version, entry.date.to_string(), author])?,
+            None => tx.prepare_cached(
+                "DELETE FROM entry_authors WHERE exec_version = ?1 AND date = ?2",
+            )?.execute(params![entry.exec_version, entry.date.to_string()])?,
+        };
+
+        Self::replace_links(tx, entry)?;
+        Self::replace_highlights(tx, entry)?;
+        Self::replace_people(tx, entry)?;
+        Self::replace_events(tx, entry)
     }
 
-    /// Retrieves entries within a date range
-    pub fn get_entries_by_exec_version(
-        &self,
-        exec_version: i64,
-    ) -> Result<Vec<DiaryEntry>> {
-        let conn = self.conn.read();
-        let mut stmt = conn.prepare(
-            "SELECT
-                exec_version, date, content, created_at, updated_at
-             FROM diary_entries
-             WHERE exec_version = :exec_version
-             ORDER BY date DESC"
-        )?;
+    /// Makes the life events the entry marks the ones recorded for its day.
+    /// Like links, they live in the main database only.
+    fn replace_events(tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        tx.prepare_cached("DELETE FROM entry_events WHERE date = ?1")?
+            .execute(params![entry.date.to_string()])?;
 
-        let entries = stmt.query_map(
-            &[(":exec_version", &exec_version)],
-            |row| self.map_row_to_entry(row)
+        let mut insert = tx.prepare_cached(
+            "INSERT OR IGNORE INTO entry_events (date, text, exec_version) VALUES (?1, ?2, ?3)",
         )?;
+        for text in entry.events() {
+            insert.execute(params![entry.date.to_string(), text, entry.exec_version])?;
+        }
+        Ok(())
+    }
 
-        entries.collect::<SqlResult<Vec<_>>>()
+    /// Life events within the range, oldest first, in order of appearance
+    /// within a day
+    pub fn get_events(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<LifeEvent>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT date, text FROM entry_events WHERE date BETWEEN ?1 AND ?2 ORDER BY date, rowid",
+        )?;
+        let events = stmt.query_map(params![range_bound(start_date), range_bound(end_date)], |row| {
+            Ok(LifeEvent { date: row.get(0)?, text: row.get(1)? })
+        })?;
+        events.collect::<SqlResult<Vec<_>>>()
             .map_err(RustyDiaryError::from)
     }
 
-    /// Gets the latest execution version
-    pub fn get_latest_exec_version(&self) -> Result<i64> {
-        self.conn.read()
-            .query_row(
-                "SELECT COALESCE(MAX(exec_version), 0) FROM diary_entries",
-                [],
-                |row| row.get(0)
-            )
-            .map_err(RustyDiaryError::from)
+    /// Re-reads the life events of every live or sharded entry, e.g. for
+    /// entries stored before events were tracked. Returns the number of
+    /// events.
+    pub fn rebuild_events(&self) -> Result<usize> {
+        // Oldest first, so each day ends up with its latest version's events
+        let mut entries = self.get_entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+        entries.reverse();
+
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM entry_events", [])?;
+        for entry in &entries {
+            Self::replace_events(&tx, entry)?;
+        }
+        let count = tx.query_row("SELECT COUNT(*) FROM entry_events", [], |row| row.get::<_, i64>(0))?;
+        tx.commit()?;
+        Ok(count as usize)
     }
 
-    /// Retrieves metadata for statistical analysis
-    pub fn get_metadata(&self) -> Result<Vec<EntryMetadata>> {
-        let conn = self.conn.read();
-        let mut stmt = conn.prepare(
-            "SELECT
-                e.date,
-                m.word_count,
-                e.exec_version
-             FROM diary_entries e
-             JOIN entry_metadata m ON
-                e.exec_version = m.exec_version AND
-                e.date = m.date
-             ORDER BY 1,3 DESC"
+    /// Makes the people the entry mentions the ones recorded for its day.
+    /// Like links, they live in the main database only.
+    fn replace_people(tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        tx.prepare_cached("DELETE FROM entry_people WHERE date = ?1")?
+            .execute(params![entry.date.to_string()])?;
+
+        let mut insert = tx.prepare_cached(
+            "INSERT OR REPLACE INTO entry_people (date, name, mentions, exec_version) VALUES (?1, ?2, ?3, ?4)",
         )?;
+        for (name, mentions) in entry.people() {
+            insert.execute(params![entry.date.to_string(), name, mentions, entry.exec_version])?;
+        }
+        Ok(())
+    }
 
-        let metadata = stmt.query_map([], |row| {
-            Ok(EntryMetadata {
-                date: NaiveDate::parse_from_str(&row.get::<_, String>(0)?, "%Y-%m-%d")
-                    .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e)))?,
-                word_count: row.get(1)?,
-                exec_version: row.get(2)?,
+    /// People mentioned within the range, those on the most days first
+    pub fn get_people(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<PersonCount>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT name, COUNT(*), SUM(mentions), MIN(date), MAX(date) FROM entry_people
+             WHERE date BETWEEN ?1 AND ?2
+             GROUP BY name
+             ORDER BY COUNT(*) DESC, SUM(mentions) DESC, name",
+        )?;
+        let people = stmt.query_map(params![range_bound(start_date), range_bound(end_date)], |row| {
+            Ok(PersonCount {
+                name: row.get(0)?,
+                days: row.get::<_, i64>(1)? as usize,
+                mentions: row.get::<_, i64>(2)? as usize,
+                first_date: row.get(3)?,
+                last_date: row.get(4)?,
             })
         })?;
+        people.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
 
-        metadata.collect::<SqlResult<Vec<_>>>()
+    /// Days whose latest entry mentions the person, ignoring case, oldest first
+    pub fn get_person_days(&self, name: &str) -> Result<Vec<NaiveDate>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached("SELECT date FROM entry_people WHERE name = ?1 ORDER BY date")?;
+        let dates = stmt.query_map(params![name], |row| row.get(0))?;
+        dates.collect::<SqlResult<Vec<_>>>()
             .map_err(RustyDiaryError::from)
     }
 
-    // Private helper methods
+    /// Re-reads the people mentioned in every live or sharded entry, e.g.
+    /// for entries stored before people were tracked. Returns the number
+    /// of day/person pairs.
+    pub fn rebuild_people(&self) -> Result<usize> {
+        // Oldest first, so each day ends up with its latest version's people
+        let mut entries = self.get_entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+        entries.reverse();
 
-    fn migrate(&self) -> Result<()> {
-        let mut conn = self.conn.write();
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM entry_people", [])?;
+        for entry in &entries {
+            Self::replace_people(&tx, entry)?;
+        }
+        let count = tx.query_row("SELECT COUNT(*) FROM entry_people", [], |row| row.get::<_, i64>(0))?;
+        tx.commit()?;
+        Ok(count as usize)
+    }
 
-        // Create migration table if it doesn't exist
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS schema_migrations (
-                version INTEGER PRIMARY KEY
-            )",
-            [],
+    /// Makes the entry's links the ones recorded for its day. Links live in
+    /// the main database only, so they survive sharding.
+    fn replace_links(tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        tx.prepare_cached("DELETE FROM entry_links WHERE date = ?1")?
+            .execute(params![entry.date.to_string()])?;
+
+        let mut insert = tx.prepare_cached(
+            "INSERT OR REPLACE INTO entry_links (date, target, target_date, exec_version)
+             VALUES (?1, ?2, ?3, ?4)",
         )?;
+        for link in entry.links() {
+            insert.execute(params![
+                entry.date.to_string(),
+                link.target,
+                link.target_date.map(|date| date.to_string()),
+                entry.exec_version,
+            ])?;
+        }
+        Ok(())
+    }
 
-        // Get current version
-        let current_version: i32 = conn
-            .query_row(
-                "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
-                [],
-                |row| row.get(0),
-            )?;
+    /// Makes the entry's highlights the ones recorded for its day. Those
+    /// still marked keep their review schedule; new ones are due a week
+    /// after the day.
+    fn replace_highlights(tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
+        let highlights = entry.highlights();
 
-        // Apply pending migrations
-        let tx = conn.transaction()?;
-        for (i, migration) in MIGRATIONS.iter().enumerate() {
-            let version = (i + 1) as i32;
-            if version > current_version {
-                tx.execute_batch(migration)?;
-                tx.execute(
-                    "INSERT INTO schema_migrations (version) VALUES (?1)",
-                    params![version],
-                )?;
-            }
+        let stored: Vec<String> = tx
+            .prepare_cached("SELECT text FROM highlights WHERE date = ?1")?
+            .query_map(params![entry.date.to_string()], |row| row.get(0))?
+            .collect::<SqlResult<_>>()?;
+        let mut delete = tx.prepare_cached("DELETE FROM highlights WHERE date = ?1 AND text = ?2")?;
+        for text in stored.iter().filter(|text| !highlights.contains(text)) {
+            delete.execute(params![entry.date.to_string(), text])?;
         }
-        tx.commit()?;
 
+        let due = Highlight::due_after(0, entry.date).map(|due| due.to_string());
+        let mut insert = tx.prepare_cached(
+            "INSERT OR IGNORE INTO highlights (date, text, stage, due) VALUES (?1, ?2, 0, ?3)",
+        )?;
+        for text in &highlights {
+            insert.execute(params![entry.date.to_string(), text, due])?;
+        }
         Ok(())
     }
 
-    fn store_entry_internal(&self, tx: &Transaction, entry: &DiaryEntry) -> Result<()> {
-        // Store main entry
-        tx.execute(
-            "INSERT OR REPLACE INTO diary_entries
-                (exec_version, date, content, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?4, ?5)",
-            params![
-                entry.exec_version,
-                entry.date.to_string(),
-                entry.content,
-                entry.created_at.to_string(),
-                entry.updated_at.map(|dt| dt.to_string()),
-            ],
+    /// Highlights due on or before `today`, longest overdue first
+    pub fn get_due_highlights(&self, today: NaiveDate, limit: Option<usize>) -> Result<Vec<Highlight>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare_cached(
+            "SELECT id, date, text, stage, due, last_reviewed FROM highlights
+             WHERE due IS NOT NULL AND due <= ?1
+             ORDER BY due, date, id
+             LIMIT ?2"
         )?;
+        let limit = limit.map_or(-1, |limit| limit as i64);
+        let highlights = stmt.query_map(params![range_bound(today), limit], |row| {
+            Ok(Highlight {
+                id: row.get(0)?,
+                date: row.get(1)?,
+                text: row.get(2)?,
+                stage: row.get(3)?,
+                due: row.get(4)?,
+                last_reviewed: row.get(5)?,
+            })
+        })?;
+        highlights.collect::<SqlResult<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
 
-        // Store metadata
-        tx.execute(
-            "INSERT OR REPLACE INTO entry_metadata
-                (exec_version, date, word_count)
-             VALUES (?1, ?2, ?3)",
-            params![
-                entry.exec_version,
-                entry.date.to_string(),
-                entry.word_count(),
-            ],
+    /// Records a review of the highlight on `reviewed`, moving it to its
+    /// next stage. Returns when it is due again, or none once the schedule
+    /// is done or the highlight is gone.
+    pub fn review_highlight(&self, id: i64, reviewed: NaiveDate) -> Result<Option<NaiveDate>> {
+        let conn = self.writer.lock();
+        let Some(stage) = conn
+            .query_row("SELECT stage FROM highlights WHERE id = ?1", params![id], |row| row.get::<_, u32>(0))
+            .optional()?
+        else {
+            return Ok(None);
+        };
+
+        let due = Highlight::due_after(stage + 1, reviewed);
+        conn.execute(
+            "UPDATE highlights SET stage = ?2, due = ?3, last_reviewed = ?4 WHERE id = ?1",
+            params![id, stage + 1, due.map(|due| due.to_string()), reviewed.to_string()],
         )?;
+        Ok(due)
+    }
 
-        Ok(())
+    /// Re-reads the highlights of every day's latest entry, e.g. for
+    /// entries stored before highlights were tracked. Returns the number
+    /// of highlights.
+    pub fn rebuild_highlights(&self) -> Result<usize> {
+        // Oldest first, so each day ends up with its latest version's highlights
+        let mut entries = self.get_entries_by_date_range(NaiveDate::MIN, NaiveDate::MAX)?;
+        entries.reverse();
+
+        let mut conn = self.writer.lock();
+        let tx = conn.transaction()?;
+        for entry in &entries {
+            Self::replace_highlights(&tx, entry)?;
+        }
+        let count = tx.query_row("SELECT COUNT(*) FROM highlights", [], |row| row.get::<_, i64>(0))?;
+        tx.commit()?;
+        Ok(count as usize)
+    }
+
+    fn map_row_to_run(row: &rusqlite::Row) -> SqlResult<SyncRun> {
+        let stats: Option<String> = row.get(4)?;
+        Ok(SyncRun {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            finished_at: row.get(2)?,
+            normalization: row.get(3)?,
+            stats: stats.and_then(|stats| serde_json::from_str(&stats).ok()),
+        })
     }
 
     fn map_row_to_entry(&self, row: &rusqlite::Row) -> SqlResult<DiaryEntry> {
         Ok(DiaryEntry {
             exec_version: row.get(0)?,
             date: row.get(1)?,
-            content: row.get(2)?,
+            content: compression::decode_column(row.get_ref(2)?, row.get(5)?, 2)?,
             created_at: row.get(3)?,
             updated_at: row.get(4)?,
+            author: row.get(6)?,
         })
     }
 }
@@ -243,6 +1815,7 @@ impl DiaryRepository {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::progress::NoProgress;
     use tempfile::TempDir;
 
     #[test]
@@ -252,10 +1825,10 @@ mod tests {
         let repo = DiaryRepository::new(&db_path)?;
 
         let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
-        let entry = DiaryEntry::new(1, test_date, "Test content".to_string());
+        let entry = DiaryEntry::new(1, test_date, "Test content".to_string(), SystemClock.now());
 
         // Store entry
-        repo.store_batch(vec![entry.clone()])?;
+        repo.store_batch(vec![entry.clone()], &NoProgress)?;
 
         // Retrieve and verify
         let entries = repo.get_entries_by_date_range(test_date, test_date)?;
@@ -269,4 +1842,282 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_list_entries_skips_content() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let repo = DiaryRepository::new(&db_path)?;
+
+        let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let entry = DiaryEntry::new(1, test_date, "Test content".to_string(), SystemClock.now());
+        repo.store_batch(vec![entry.clone()], &NoProgress)?;
+
+        // Summaries carry the same word count without the content
+        let summaries = repo.list_entries(test_date, test_date)?;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0], entry.summary());
+        assert_eq!(summaries[0].word_count, 2);
+
+        // Unbounded ranges still compare correctly against stored dates
+        assert_eq!(repo.list_entries(NaiveDate::MIN, NaiveDate::MAX)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_readers_are_read_only_and_shared() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let repo = DiaryRepository::new(&db_path)?;
+
+        let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        repo.store_batch(vec![DiaryEntry::new(1, test_date, "Shared".to_string(), SystemClock.now())], &NoProgress)?;
+
+        // Writes must never go through a reader connection
+        assert!(repo.reader().execute("DELETE FROM diary_entries", []).is_err());
+
+        // Several threads can query at once, each seeing the committed write
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..READ_POOL_SIZE * 2)
+                .map(|_| scope.spawn(|| repo.get_latest_exec_version()))
+                .collect();
+
+            for handle in handles {
+                assert_eq!(handle.join().unwrap().unwrap(), 1);
+            }
+        });
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compression_toggle_and_recompress() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let mut repo = DiaryRepository::new(&db_path)?;
+
+        let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let content = "A long and repetitive entry. ".repeat(40);
+
+        // Stored uncompressed, then converted in place
+        repo.set_compression(false);
+        repo.store_batch(vec![DiaryEntry::new(1, test_date, content.clone(), SystemClock.now())], &NoProgress)?;
+        repo.set_compression(true);
+        assert_eq!(repo.recompress()?, 1);
+        assert_eq!(repo.recompress()?, 0);
+
+        let compressed: bool = repo.reader().query_row(
+            "SELECT compressed FROM diary_entries", [], |row| row.get(0)
+        )?;
+        assert!(compressed);
+
+        let entries = repo.get_entries_by_date_range(test_date, test_date)?;
+        assert_eq!(entries[0].content, content);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_settings_profiles_are_applied() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let repo = DiaryRepository::with_settings(&db_path, &SqliteSettings::safe(), Arc::new(SystemClock))?;
+
+        let journal_mode: String = repo.writer.lock()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        let synchronous: i64 = repo.writer.lock()
+            .query_row("PRAGMA synchronous", [], |row| row.get(0))?;
+
+        assert_eq!(journal_mode, "delete");
+        assert_eq!(synchronous, 2); // FULL
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_archived_years_are_read_transparently() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        let repo = DiaryRepository::new(&db_path)?;
+
+        // More years than can be attached at once, to exercise batching
+        let entries: Vec<DiaryEntry> = (2008..2020)
+            .map(|year| {
+                let date = NaiveDate::from_ymd_opt(year, 6, 1).unwrap();
+                DiaryEntry::new(year as i64 - 2000, date, format!("Entry of {}", year), SystemClock.now())
+            })
+            .collect();
+        repo.store_batch(entries, &NoProgress)?;
+
+        for year in 2008..2019 {
+            assert_eq!(repo.archive_year(year)?, 1);
+        }
+        assert!(temp_dir.path().join("diary-2010.db").is_file());
+        assert_eq!(repo.get_shard_years()?.len(), 11);
+
+        // Only the last year still lives in the main database
+        let main_count: i64 = repo.reader().query_row(
+            "SELECT COUNT(*) FROM main.diary_entries", [], |row| row.get(0)
+        )?;
+        assert_eq!(main_count, 1);
+
+        let start = NaiveDate::from_ymd_opt(2008, 1, 1).unwrap();
+        let end = NaiveDate::from_ymd_opt(2019, 12, 31).unwrap();
+        let all = repo.get_entries_by_date_range(start, end)?;
+        assert_eq!(all.len(), 12);
+        assert_eq!(all[0].content, "Entry of 2019");
+        assert_eq!(all[11].content, "Entry of 2008");
+        assert_eq!(repo.list_entries(start, end)?.len(), 12);
+
+        let archived = repo.get_latest_entry(NaiveDate::from_ymd_opt(2012, 6, 1).unwrap())?;
+        assert_eq!(archived.unwrap().content, "Entry of 2012");
+        assert_eq!(repo.get_latest_exec_version()?, 19);
+
+        // The running year can never be sharded
+        assert!(repo.archive_year(chrono::Local::now().year()).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_archiving_after_an_interrupted_copy_keeps_one_word_count() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        let repo = DiaryRepository::new(&db_path)?;
+
+        let date = NaiveDate::from_ymd_opt(2018, 3, 4).unwrap();
+        repo.store_batch(vec![DiaryEntry::new(1, date, "Three words here".to_string(), now)], &NoProgress)?;
+
+        // A crash after the shard copy but before main was cleaned up
+        {
+            let mut conn = repo.writer.lock();
+            let alias = shard::alias(2018);
+            conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {}", alias),
+                params![shard::path(&db_path, 2018).to_string_lossy()],
+            )?;
+            DiaryRepository::copy_year_into_shard(&mut conn, &alias, 2018)?;
+            conn.execute_batch(&format!("DETACH DATABASE {}", alias))?;
+        }
+        assert!(repo.get_shard_years()?.is_empty());
+        assert_eq!(repo.archive_year(2018)?, 1);
+
+        let shard = Connection::open(shard::path(&db_path, 2018))?;
+        let word_counts: i64 = shard.query_row("SELECT COUNT(*) FROM entry_metadata", [], |row| row.get(0))?;
+        assert_eq!(word_counts, 1);
+        let summaries = repo.list_entries(date, date)?;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].word_count, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cold_archive_and_unarchive() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        let repo = DiaryRepository::new(&db_path)?;
+
+        let old = NaiveDate::from_ymd_opt(2018, 5, 1).unwrap();
+        let older = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+        let recent = NaiveDate::from_ymd_opt(2021, 5, 1).unwrap();
+        repo.store_batch(vec![
+            DiaryEntry::new(1, older, "Older entry".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, old, "Old entry here".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, recent, "Recent".to_string(), SystemClock.now()),
+        ], &NoProgress)?;
+
+        let cutoff = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        assert_eq!(repo.cold_archive(cutoff, None)?, 2);
+        assert!(repo.get_entries_by_date_range(older, old)?.is_empty());
+        assert_eq!(repo.get_entries_by_date_range(older, recent)?.len(), 1);
+
+        // Statistics can still see what was archived
+        let summaries = repo.get_archived_summaries(older, cutoff)?;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].word_count, 3);
+
+        // Bring back a single year; the other stays archived
+        let end_2018 = NaiveDate::from_ymd_opt(2018, 12, 31).unwrap();
+        let start_2018 = NaiveDate::from_ymd_opt(2018, 1, 1).unwrap();
+        assert_eq!(repo.unarchive(start_2018, end_2018, temp_dir.path())?, 1);
+        assert_eq!(repo.get_entries_by_date_range(old, old)?[0].content, "Old entry here");
+        assert_eq!(repo.get_archived_summaries(older, cutoff)?.len(), 1);
+
+        // Restoring the rest removes the now empty archive file
+        assert_eq!(repo.unarchive(older, cutoff, temp_dir.path())?, 1);
+        assert!(repo.get_archived_summaries(older, cutoff)?.is_empty());
+        let leftovers = std::fs::read_dir(temp_dir.path())?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().contains("-archive-"))
+            .count();
+        assert_eq!(leftovers, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_batch_modes_store_the_same() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let day = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        let batch = || vec![
+            DiaryEntry::new(1, day(1), "First draft".to_string(), now),
+            DiaryEntry::new(1, day(2), "Back to [[2024-01-01]]".to_string(), now).with_author(Some("ana".to_string())),
+            DiaryEntry::new(2, day(1), "Second version of the day".to_string(), now),
+        ];
+
+        for (i, mode) in [BatchMode::Replace, BatchMode::Upsert, BatchMode::DeferredMetadata].into_iter().enumerate() {
+            let repo = DiaryRepository::new(temp_dir.path().join(format!("{}.db", i)))?;
+            repo.store_batch_with(batch(), mode, &NoProgress)?;
+            // Storing a version again updates it rather than adding another
+            repo.store_batch_with(
+                vec![DiaryEntry::new(1, day(1), "First draft, edited".to_string(), now)],
+                mode,
+                &NoProgress,
+            )?;
+
+            let entries = repo.get_entries_by_date_range(day(1), day(2))?;
+            assert_eq!(entries.len(), 3, "{:?}", mode);
+            assert!(entries.iter().any(|e| e.content == "First draft, edited"), "{:?}", mode);
+            assert_eq!(entries.iter().filter(|e| e.author.is_some()).count(), 1, "{:?}", mode);
+
+            let metadata = repo.get_metadata()?;
+            assert_eq!(metadata.len(), 3, "{:?}", mode);
+            assert!(metadata.iter().any(|m| m.exec_version == 1 && m.date == day(1) && m.word_count == 3));
+            assert_eq!(repo.get_backlinks(day(1))?, vec![day(2)], "{:?}", mode);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_range_queries_use_indexes() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let repo = DiaryRepository::new(temp_dir.path().join("test.db"))?;
+        let day = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        repo.store_batch(vec![DiaryEntry::new(1, day, "Indexed".to_string(), SystemClock.now())], &NoProgress)?;
+        assert_eq!(repo.analyze()?, 1);
+
+        let plan = |sql: &str| -> Result<String> {
+            let conn = repo.reader();
+            let mut stmt = conn.prepare(&format!("EXPLAIN QUERY PLAN {}", sql.replace("{db}", "main")))?;
+            let details = stmt.query_map(params!["2024-01-01", "2024-12-31"], |row| row.get::<_, String>(3))?;
+            Ok(details.collect::<SqlResult<Vec<_>>>()?.join("\n"))
+        };
+
+        let latest = plan(LATEST_ENTRY_COLUMNS_SELECT)?;
+        assert!(latest.contains("idx_diary_entries_date_version"), "{}", latest);
+        assert!(!latest.contains("SCAN e"), "{}", latest);
+
+        let metadata = plan(
+            "SELECT m.word_count FROM {db}.diary_entries e
+             JOIN {db}.entry_metadata m ON m.exec_version = e.exec_version AND m.date = e.date
+             WHERE e.date BETWEEN ?1 AND ?2",
+        )?;
+        assert!(metadata.contains("COVERING INDEX idx_entry_metadata_version_date"), "{}", metadata);
+        Ok(())
+    }
+}
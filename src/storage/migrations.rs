@@ -0,0 +1,999 @@
+use std::path::{Path, PathBuf};
+use chrono::NaiveDateTime;
+use rusqlite::{Connection, OpenFlags, Transaction, params};
+use serde::Serialize;
+
+use crate::error::{Result, RustyDiaryError};
+use super::models::content_hash;
+
+/// A single reversible schema change
+#[derive(Debug)]
+pub struct Migration {
+    pub version: i32,
+    pub description: &'static str,
+    pub up: &'static str,
+    /// Fills in what `up` cannot compute in SQL, run right after it
+    pub backfill: Option<fn(&Transaction) -> Result<()>>,
+    pub down: &'static str,
+    /// Query that must return 0 before `down` may run, with the reason
+    /// shown when it does not. Protects data the down step would destroy.
+    pub down_guard: Option<(&'static str, &'static str)>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Initial schema",
+        up: "CREATE TABLE IF NOT EXISTS diary_entries (
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT,
+                PRIMARY KEY (exec_version, date)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_diary_entries_date
+            ON diary_entries(date);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS diary_entries;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM diary_entries",
+            "the database still contains entries",
+        )),
+    },
+    Migration {
+        version: 2,
+        description: "Add metadata table",
+        up: "CREATE TABLE IF NOT EXISTS entry_metadata (
+                entry_id INTEGER PRIMARY KEY,
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                word_count INTEGER NOT NULL,
+                FOREIGN KEY (exec_version, date)
+                REFERENCES diary_entries(exec_version, date)
+                ON DELETE CASCADE
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_metadata;",
+        down_guard: None,
+    },
+    Migration {
+        version: 3,
+        description: "Per-row content compression flag",
+        up: "ALTER TABLE diary_entries
+                ADD COLUMN compressed INTEGER NOT NULL DEFAULT 0;",
+        backfill: None,
+        down: "ALTER TABLE diary_entries DROP COLUMN compressed;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM diary_entries WHERE compressed = 1",
+            "some entries are compressed; run `db recompress` with --no-compress first",
+        )),
+    },
+    Migration {
+        version: 4,
+        description: "Registry of years archived into their own shard databases",
+        up: "CREATE TABLE IF NOT EXISTS shards (
+                year INTEGER PRIMARY KEY,
+                entry_count INTEGER NOT NULL,
+                max_exec_version INTEGER NOT NULL,
+                archived_at TEXT NOT NULL
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS shards;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM shards",
+            "some years live in shard databases",
+        )),
+    },
+    Migration {
+        version: 5,
+        description: "Cold archives and the summaries kept for statistics",
+        up: "CREATE TABLE IF NOT EXISTS cold_archives (
+                id INTEGER PRIMARY KEY,
+                file_name TEXT NOT NULL,
+                start_date TEXT NOT NULL,
+                end_date TEXT NOT NULL,
+                entry_count INTEGER NOT NULL,
+                created_at TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS archived_summaries (
+                archive_id INTEGER NOT NULL
+                    REFERENCES cold_archives(id) ON DELETE CASCADE,
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                word_count INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                PRIMARY KEY (exec_version, date)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS archived_summaries;
+               DROP TABLE IF EXISTS cold_archives;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM cold_archives",
+            "some entries are in cold archives; unarchive them first",
+        )),
+    },
+    Migration {
+        version: 6,
+        description: "Text normalization applied by each sync run",
+        up: "CREATE TABLE IF NOT EXISTS sync_runs (
+                exec_version INTEGER PRIMARY KEY,
+                normalization TEXT NOT NULL,
+                synced_at TEXT NOT NULL
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS sync_runs;",
+        down_guard: None,
+    },
+    Migration {
+        version: 7,
+        description: "Wiki links of the latest version of each entry",
+        up: "CREATE TABLE IF NOT EXISTS entry_links (
+                date TEXT NOT NULL,
+                target TEXT NOT NULL,
+                target_date TEXT,
+                exec_version INTEGER NOT NULL,
+                PRIMARY KEY (date, target)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_entry_links_target_date
+            ON entry_links(target_date);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_links;",
+        down_guard: None,
+    },
+    Migration {
+        version: 8,
+        description: "Tags stored apart from entry content, with their source",
+        up: "CREATE TABLE IF NOT EXISTS entry_tags (
+                date TEXT NOT NULL,
+                tag TEXT NOT NULL,
+                exec_version INTEGER NOT NULL,
+                source TEXT NOT NULL,
+                confidence REAL,
+                PRIMARY KEY (date, tag)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_tags;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM entry_tags",
+            "some entries have stored tags",
+        )),
+    },
+    Migration {
+        version: 9,
+        description: "Author of each entry version, for shared diaries",
+        up: "CREATE TABLE IF NOT EXISTS entry_authors (
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                author TEXT NOT NULL,
+                PRIMARY KEY (exec_version, date)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_entry_authors_author
+            ON entry_authors(author);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_authors;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM entry_authors",
+            "some entries have an author",
+        )),
+    },
+    Migration {
+        version: 10,
+        description: "Sync runs as their own table, referenced by the entries they stored",
+        // Entries and their metadata are rebuilt around the new foreign key.
+        // Metadata is set aside first so dropping the old entries table
+        // cannot cascade into it.
+        up: "CREATE TABLE runs_new (
+                id INTEGER PRIMARY KEY,
+                started_at TEXT NOT NULL,
+                finished_at TEXT,
+                normalization TEXT,
+                stats TEXT
+            );
+            INSERT INTO runs_new (id, started_at, finished_at, normalization)
+                SELECT exec_version, synced_at, synced_at, normalization FROM sync_runs;
+            INSERT OR IGNORE INTO runs_new (id, started_at, finished_at)
+                SELECT exec_version, MIN(created_at), MAX(created_at) FROM diary_entries GROUP BY exec_version;
+            DROP TABLE sync_runs;
+            ALTER TABLE runs_new RENAME TO sync_runs;
+
+            CREATE TEMP TABLE metadata_copy AS SELECT * FROM entry_metadata;
+            DROP TABLE entry_metadata;
+
+            CREATE TABLE entries_new (
+                exec_version INTEGER NOT NULL REFERENCES sync_runs(id),
+                date TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT,
+                compressed INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (exec_version, date)
+            );
+            INSERT INTO entries_new (exec_version, date, content, created_at, updated_at, compressed)
+                SELECT exec_version, date, content, created_at, updated_at, compressed FROM diary_entries;
+            DROP TABLE diary_entries;
+            ALTER TABLE entries_new RENAME TO diary_entries;
+            CREATE INDEX idx_diary_entries_date ON diary_entries(date);
+
+            CREATE TABLE entry_metadata (
+                entry_id INTEGER PRIMARY KEY,
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                word_count INTEGER NOT NULL,
+                FOREIGN KEY (exec_version, date)
+                REFERENCES diary_entries(exec_version, date)
+                ON DELETE CASCADE
+            );
+            INSERT INTO entry_metadata SELECT * FROM temp.metadata_copy;
+            DROP TABLE temp.metadata_copy;",
+        backfill: None,
+        down: "CREATE TEMP TABLE metadata_copy AS SELECT * FROM entry_metadata;
+            DROP TABLE entry_metadata;
+
+            CREATE TABLE entries_old (
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                content TEXT NOT NULL,
+                created_at TEXT NOT NULL,
+                updated_at TEXT,
+                compressed INTEGER NOT NULL DEFAULT 0,
+                PRIMARY KEY (exec_version, date)
+            );
+            INSERT INTO entries_old SELECT exec_version, date, content, created_at, updated_at, compressed FROM diary_entries;
+            DROP TABLE diary_entries;
+            ALTER TABLE entries_old RENAME TO diary_entries;
+            CREATE INDEX idx_diary_entries_date ON diary_entries(date);
+
+            CREATE TABLE entry_metadata (
+                entry_id INTEGER PRIMARY KEY,
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                word_count INTEGER NOT NULL,
+                FOREIGN KEY (exec_version, date)
+                REFERENCES diary_entries(exec_version, date)
+                ON DELETE CASCADE
+            );
+            INSERT INTO entry_metadata SELECT * FROM temp.metadata_copy;
+            DROP TABLE temp.metadata_copy;
+
+            CREATE TABLE runs_old (
+                exec_version INTEGER PRIMARY KEY,
+                normalization TEXT NOT NULL,
+                synced_at TEXT NOT NULL
+            );
+            INSERT INTO runs_old
+                SELECT id, normalization, COALESCE(finished_at, started_at) FROM sync_runs
+                WHERE normalization IS NOT NULL;
+            DROP TABLE sync_runs;
+            ALTER TABLE runs_old RENAME TO sync_runs;",
+        down_guard: None,
+    },
+    Migration {
+        version: 11,
+        description: "Indexes for date range queries and metadata lookups",
+        // Range queries filter on date and order by date, then version, so
+        // one index serves both. Metadata is looked up by version and date
+        // and only its word count is read, so its index covers the lookup.
+        up: "DROP INDEX IF EXISTS idx_diary_entries_date;
+            CREATE INDEX IF NOT EXISTS idx_diary_entries_date_version
+            ON diary_entries(date DESC, exec_version DESC);
+
+            CREATE INDEX IF NOT EXISTS idx_entry_metadata_version_date
+            ON entry_metadata(exec_version, date, word_count);",
+        backfill: None,
+        down: "DROP INDEX IF EXISTS idx_entry_metadata_version_date;
+            DROP INDEX IF EXISTS idx_diary_entries_date_version;
+            CREATE INDEX IF NOT EXISTS idx_diary_entries_date
+            ON diary_entries(date);",
+        down_guard: None,
+    },
+    Migration {
+        version: 12,
+        description: "Content hashes of every stored entry, for finding duplicates",
+        // Kept in the main database only, like authors, so entries moved
+        // into shards or cold archives are still recognized
+        up: "CREATE TABLE IF NOT EXISTS entry_hashes (
+                exec_version INTEGER NOT NULL,
+                date TEXT NOT NULL,
+                hash TEXT NOT NULL,
+                PRIMARY KEY (exec_version, date)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_entry_hashes_hash
+            ON entry_hashes(hash, date);",
+        backfill: Some(backfill_content_hashes),
+        down: "DROP TABLE IF EXISTS entry_hashes;",
+        down_guard: None,
+    },
+    Migration {
+        version: 13,
+        description: "Starred days, for marking entries to revisit",
+        // Kept in the main database only, so stars survive archiving
+        up: "CREATE TABLE IF NOT EXISTS entry_stars (
+                date TEXT PRIMARY KEY,
+                starred_at TEXT NOT NULL
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_stars;",
+        down_guard: None,
+    },
+    Migration {
+        version: 14,
+        description: "Custom key/value metadata of each day, set by integrations",
+        // Main database only, like stars
+        up: "CREATE TABLE IF NOT EXISTS entry_fields (
+                date TEXT NOT NULL,
+                key TEXT NOT NULL,
+                value TEXT NOT NULL,
+                PRIMARY KEY (date, key)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_entry_fields_key
+            ON entry_fields(key, value);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_fields;",
+        down_guard: None,
+    },
+    Migration {
+        version: 15,
+        description: "Daily completion of the configured habits, read from entry checklists",
+        // Main database only, like stars
+        up: "CREATE TABLE IF NOT EXISTS habit_checks (
+                date TEXT NOT NULL,
+                habit TEXT NOT NULL,
+                done INTEGER NOT NULL,
+                PRIMARY KEY (date, habit)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS habit_checks;",
+        down_guard: None,
+    },
+    Migration {
+        version: 16,
+        description: "Highlighted passages and their spaced review schedule",
+        // Main database only, like links
+        up: "CREATE TABLE IF NOT EXISTS highlights (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                date TEXT NOT NULL,
+                text TEXT NOT NULL,
+                stage INTEGER NOT NULL DEFAULT 0,
+                due TEXT,
+                last_reviewed TEXT,
+                UNIQUE (date, text)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_highlights_due
+            ON highlights(due);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS highlights;",
+        down_guard: None,
+    },
+    Migration {
+        version: 17,
+        description: "Reporting views for BI tools, over the latest version of each day",
+        // Views only read the main database: days moved into shards or cold
+        // archives are left out. Their columns are kept stable for dashboards
+        // even as the tables under them change.
+        up: "CREATE VIEW IF NOT EXISTS daily_word_counts AS
+                SELECT m.date AS date,
+                       m.word_count AS word_count,
+                       (SELECT COUNT(*) FROM entry_metadata v WHERE v.date = m.date) AS versions,
+                       m.exec_version AS exec_version,
+                       a.author AS author,
+                       EXISTS (SELECT 1 FROM entry_stars s WHERE s.date = m.date) AS starred
+                FROM entry_metadata m
+                LEFT JOIN entry_authors a ON a.exec_version = m.exec_version AND a.date = m.date
+                WHERE m.exec_version = (SELECT MAX(l.exec_version) FROM entry_metadata l WHERE l.date = m.date);
+
+            CREATE VIEW IF NOT EXISTS monthly_summary AS
+                SELECT substr(date, 1, 7) AS month,
+                       COUNT(*) AS days,
+                       SUM(word_count) AS words,
+                       ROUND(AVG(word_count), 1) AS average_words,
+                       MAX(word_count) AS longest_words,
+                       SUM(starred) AS starred_days
+                FROM daily_word_counts
+                GROUP BY month;
+
+            CREATE VIEW IF NOT EXISTS tag_usage AS
+                SELECT tag,
+                       source,
+                       COUNT(*) AS days,
+                       MIN(date) AS first_date,
+                       MAX(date) AS last_date
+                FROM entry_tags
+                GROUP BY tag, source;",
+        backfill: None,
+        down: "DROP VIEW IF EXISTS tag_usage;
+            DROP VIEW IF EXISTS monthly_summary;
+            DROP VIEW IF EXISTS daily_word_counts;",
+        down_guard: None,
+    },
+    Migration {
+        version: 18,
+        description: "Words the spell check accepts, per language",
+        up: "CREATE TABLE IF NOT EXISTS spelling_words (
+                lang TEXT NOT NULL,
+                word TEXT NOT NULL,
+                added_at TEXT NOT NULL,
+                PRIMARY KEY (lang, word)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS spelling_words;",
+        down_guard: Some((
+            "SELECT COUNT(*) FROM spelling_words",
+            "the diary's spelling dictionary has words that would be lost",
+        )),
+    },
+    Migration {
+        version: 19,
+        description: "People mentioned in the latest version of each entry",
+        // Main database only, like links; `people rebuild` fills it for
+        // entries stored before
+        up: "CREATE TABLE IF NOT EXISTS entry_people (
+                date TEXT NOT NULL,
+                name TEXT NOT NULL COLLATE NOCASE,
+                mentions INTEGER NOT NULL,
+                exec_version INTEGER NOT NULL,
+                PRIMARY KEY (date, name)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_entry_people_name
+            ON entry_people(name);",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_people;",
+        down_guard: None,
+    },
+    Migration {
+        version: 20,
+        description: "Life events marked in the latest version of each entry",
+        // Main database only, like links; `events rebuild` fills it for
+        // entries stored before
+        up: "CREATE TABLE IF NOT EXISTS entry_events (
+                date TEXT NOT NULL,
+                text TEXT NOT NULL,
+                exec_version INTEGER NOT NULL,
+                PRIMARY KEY (date, text)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS entry_events;",
+        down_guard: None,
+    },
+    Migration {
+        version: 21,
+        description: "Backups taken, and the attachments each one saw, for incremental backups",
+        // `entries` counts the main database's versions at the time, so a
+        // later incremental can tell whether any were moved out or pruned
+        up: "CREATE TABLE IF NOT EXISTS backups (
+                id TEXT PRIMARY KEY,
+                parent TEXT,
+                exec_version INTEGER NOT NULL,
+                entries INTEGER NOT NULL,
+                created_at TEXT NOT NULL,
+                path TEXT NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS backup_attachments (
+                backup_id TEXT NOT NULL REFERENCES backups(id) ON DELETE CASCADE,
+                path TEXT NOT NULL,
+                sha256 TEXT NOT NULL,
+                PRIMARY KEY (backup_id, path)
+            );",
+        backfill: None,
+        down: "DROP TABLE IF EXISTS backup_attachments;
+               DROP TABLE IF EXISTS backups;",
+        down_guard: None,
+    },
+    Migration {
+        version: 22,
+        description: "Content hashes of cold-archived entries kept with their summaries",
+        // Archiving now clears everything a day left in the main database,
+        // so the hashes that keep archived content recognized move along
+        up: "ALTER TABLE archived_summaries ADD COLUMN hash TEXT;
+
+            UPDATE archived_summaries SET hash = (
+                SELECT h.hash FROM entry_hashes h
+                WHERE h.exec_version = archived_summaries.exec_version
+                  AND h.date = archived_summaries.date
+            );
+
+            DELETE FROM entry_hashes WHERE EXISTS (
+                SELECT 1 FROM archived_summaries s
+                WHERE s.exec_version = entry_hashes.exec_version AND s.date = entry_hashes.date
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_archived_summaries_hash
+            ON archived_summaries(hash, date);",
+        backfill: None,
+        down: "INSERT OR IGNORE INTO entry_hashes (exec_version, date, hash)
+                SELECT exec_version, date, hash FROM archived_summaries WHERE hash IS NOT NULL;
+
+            DROP INDEX IF EXISTS idx_archived_summaries_hash;
+            ALTER TABLE archived_summaries DROP COLUMN hash;",
+        down_guard: None,
+    },
+];
+
+/// Hashes the entries already stored in the main database, its shards and
+/// its cold archives
+fn backfill_content_hashes(tx: &Transaction) -> Result<()> {
+    let mut hashes = read_hashes(tx)?;
+
+    // Shards and archives sit next to the main file; an in-memory database has neither
+    let db_path: String = tx.query_row("SELECT file FROM pragma_database_list WHERE name = 'main'", [], |row| row.get(0))?;
+    if !db_path.is_empty() {
+        let db_path = Path::new(&db_path);
+        let years = tx.prepare("SELECT year FROM shards")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<i32>>>()?;
+        for year in years {
+            let path = super::shard::path(db_path, year);
+            if path.exists() {
+                hashes.extend(read_hashes(&Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?)?);
+            }
+        }
+
+        let archives = tx.prepare("SELECT file_name FROM cold_archives")?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?;
+        for file_name in archives {
+            let path = db_path.with_file_name(file_name);
+            if path.exists() {
+                hashes.extend(
+                    super::archive::read(&path)?
+                        .entries
+                        .into_iter()
+                        .map(|entry| (entry.exec_version, entry.date.to_string(), entry.content_hash())),
+                );
+            }
+        }
+    }
+
+    let mut insert = tx.prepare("INSERT OR IGNORE INTO entry_hashes (exec_version, date, hash) VALUES (?1, ?2, ?3)")?;
+    for (exec_version, date, hash) in hashes {
+        insert.execute(params![exec_version, date, hash])?;
+    }
+    Ok(())
+}
+
+/// Version, date and content hash of every entry in a database's `diary_entries`
+fn read_hashes(conn: &Connection) -> Result<Vec<(i64, String, String)>> {
+    let mut stmt = conn.prepare("SELECT exec_version, date, content, compressed FROM diary_entries")?;
+    let mut rows = stmt.query([])?;
+    let mut hashes = Vec::new();
+    while let Some(row) = rows.next()? {
+        let content = super::compression::decode(row.get_ref(2)?, row.get(3)?)?;
+        hashes.push((row.get(0)?, row.get(1)?, content_hash(&content)));
+    }
+    Ok(hashes)
+}
+
+/// Oldest schema this build can still upgrade from. Raise it when the
+/// migrations for older layouts are retired.
+pub const MIN_SUPPORTED_VERSION: i32 = 0;
+
+/// Highest schema version this build knows how to use
+pub fn latest_version() -> i32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// Snapshot of a database's schema state, as reported by `db info`
+#[derive(Debug, Clone, Serialize)]
+pub struct SchemaInfo {
+    pub version: i32,
+    pub applied: Vec<i32>,
+    pub min_supported: i32,
+    pub max_supported: i32,
+    pub pending: Vec<MigrationStep>,
+    /// Why this build cannot open the database, if it cannot
+    pub incompatibility: Option<String>,
+}
+
+impl SchemaInfo {
+    pub fn is_compatible(&self) -> bool {
+        self.incompatibility.is_none()
+    }
+}
+
+/// Versions recorded in `schema_migrations`, without creating anything,
+/// so it also works on read-only connections
+pub fn applied_versions(conn: &Connection) -> Result<Vec<i32>> {
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS (
+            SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_migrations'
+        )",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(Vec::new());
+    }
+
+    let mut stmt = conn.prepare("SELECT version FROM schema_migrations ORDER BY version")?;
+    let versions = stmt.query_map([], |row| row.get(0))?;
+    versions.collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(RustyDiaryError::from)
+}
+
+/// Checks that this build can safely use the database and returns its
+/// schema version. Refuses databases written by newer builds, ones older
+/// than `MIN_SUPPORTED_VERSION`, and ones with gaps in their history.
+pub fn check_compatibility(conn: &Connection) -> Result<i32> {
+    let applied = applied_versions(conn)?;
+    let version = applied.last().copied().unwrap_or(0);
+    let latest = latest_version();
+
+    if version > latest {
+        return Err(RustyDiaryError::SchemaTooNew { found: version, supported: latest });
+    }
+    if version < MIN_SUPPORTED_VERSION {
+        return Err(RustyDiaryError::IncompatibleSchema(format!(
+            "schema version {} is older than the oldest supported version {}; \
+             migrate it with an earlier release first",
+            version, MIN_SUPPORTED_VERSION
+        )));
+    }
+    if applied.iter().copied().ne(1..=version) {
+        return Err(RustyDiaryError::IncompatibleSchema(format!(
+            "migration history {:?} has gaps; the database was modified outside rusty_diary \
+             or is damaged, restore it from a backup",
+            applied
+        )));
+    }
+
+    Ok(version)
+}
+
+/// Describes the schema state of a database without modifying it
+pub fn inspect(conn: &Connection) -> Result<SchemaInfo> {
+    let applied = applied_versions(conn)?;
+    let version = applied.last().copied().unwrap_or(0);
+
+    let (pending, incompatibility) = match check_compatibility(conn) {
+        Ok(version) => (
+            MIGRATIONS.iter()
+                .filter(|m| m.version > version)
+                .map(|m| MigrationStep { version: m.version, direction: Direction::Up, description: m.description })
+                .collect(),
+            None,
+        ),
+        Err(e) => (Vec::new(), Some(e.to_string())),
+    };
+
+    Ok(SchemaInfo {
+        version,
+        applied,
+        min_supported: MIN_SUPPORTED_VERSION,
+        max_supported: latest_version(),
+        pending,
+        incompatibility,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+/// One step of a migration plan, as shown by `db migrate --dry-run`
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStep {
+    pub version: i32,
+    pub direction: Direction,
+    pub description: &'static str,
+}
+
+/// The ordered steps needed to bring a database to a target version
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationPlan {
+    pub current_version: i32,
+    pub target_version: i32,
+    pub steps: Vec<MigrationStep>,
+}
+
+impl MigrationPlan {
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+/// Version recorded in the database; 0 for one without a schema
+pub fn current_version(conn: &Connection) -> Result<i32> {
+    Ok(applied_versions(conn)?.last().copied().unwrap_or(0))
+}
+
+/// Works out the steps from the database's version to `target`.
+/// Fails if the database is not compatible with this build.
+pub fn plan(conn: &Connection, target: i32) -> Result<MigrationPlan> {
+    let current = check_compatibility(conn)?;
+    let latest = latest_version();
+
+    if !(0..=latest).contains(&target) {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "Unknown schema version {} (this build supports 0 to {})", target, latest
+        )));
+    }
+
+    let steps = if target >= current {
+        MIGRATIONS.iter()
+            .filter(|m| m.version > current && m.version <= target)
+            .map(|m| MigrationStep { version: m.version, direction: Direction::Up, description: m.description })
+            .collect()
+    } else {
+        MIGRATIONS.iter()
+            .rev()
+            .filter(|m| m.version <= current && m.version > target)
+            .map(|m| MigrationStep { version: m.version, direction: Direction::Down, description: m.description })
+            .collect()
+    };
+
+    Ok(MigrationPlan { current_version: current, target_version: target, steps })
+}
+
+/// Applies a plan in a single transaction, creating the bookkeeping
+/// table if needed
+pub fn apply(conn: &mut Connection, plan: &MigrationPlan) -> Result<()> {
+    let tx = conn.transaction()?;
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY
+        )",
+        [],
+    )?;
+
+    for step in &plan.steps {
+        let migration = MIGRATIONS.iter()
+            .find(|m| m.version == step.version)
+            .expect("plan only references known migrations");
+
+        match step.direction {
+            Direction::Up => {
+                tx.execute_batch(migration.up)?;
+                if let Some(backfill) = migration.backfill {
+                    backfill(&tx)?;
+                }
+                tx.execute(
+                    "INSERT INTO schema_migrations (version) VALUES (?1)",
+                    params![migration.version],
+                )?;
+            }
+            Direction::Down => {
+                if let Some((guard, reason)) = migration.down_guard {
+                    let blocking: i64 = tx.query_row(guard, [], |row| row.get(0))?;
+                    if blocking > 0 {
+                        return Err(RustyDiaryError::InvalidConfig(format!(
+                            "Cannot revert migration {}: {}", migration.version, reason
+                        )));
+                    }
+                }
+                tx.execute_batch(migration.down)?;
+                tx.execute(
+                    "DELETE FROM schema_migrations WHERE version = ?1",
+                    params![migration.version],
+                )?;
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+/// Copies the database next to itself before a schema change, named after
+/// the version it is leaving: `diary.db` backs up to `diary.db.v3-<timestamp>.bak`
+pub fn backup(conn: &Connection, db_path: &Path, version: i32, now: NaiveDateTime) -> Result<PathBuf> {
+    let file_name = db_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "rusty-diary.db".to_string());
+    let timestamp = now.format("%Y%m%d%H%M%S%3f").to_string();
+
+    // Never overwrite an earlier backup taken within the same millisecond
+    let mut backup_path = db_path.with_file_name(format!("{}.v{}-{}.bak", file_name, version, timestamp));
+    let mut attempt = 1;
+    while backup_path.exists() {
+        backup_path = db_path.with_file_name(format!(
+            "{}.v{}-{}-{}.bak", file_name, version, timestamp, attempt
+        ));
+        attempt += 1;
+    }
+
+    conn.execute("VACUUM INTO ?1", params![backup_path.to_string_lossy()])?;
+    Ok(backup_path)
+}
+
+/// Opens the database at `db_path` and migrates it to `target`, backing
+/// it up first if it already holds a schema. With `dry_run` the plan is
+/// only computed, from a read-only connection; a missing database is
+/// planned from version 0 without being created. The backup is named
+/// after `now`. Returns the plan and the backup location, if any.
+pub fn migrate_to<P: AsRef<Path>>(
+    db_path: P,
+    target: i32,
+    dry_run: bool,
+    now: NaiveDateTime,
+) -> Result<(MigrationPlan, Option<PathBuf>)> {
+    let db_path = db_path.as_ref();
+    if dry_run {
+        let conn = if db_path.exists() {
+            Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?
+        } else {
+            Connection::open_in_memory()?
+        };
+        return Ok((plan(&conn, target)?, None));
+    }
+
+    let mut conn = Connection::open(db_path)?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+
+    let plan = plan(&conn, target)?;
+    if plan.is_empty() {
+        return Ok((plan, None));
+    }
+
+    let backup_path = if plan.current_version > 0 {
+        Some(backup(&conn, db_path, plan.current_version, now)?)
+    } else {
+        None
+    };
+
+    apply(&mut conn, &plan)?;
+    Ok((plan, backup_path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_up_down_and_backups() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+
+        // Planning against a missing database leaves no file behind
+        let (plan, _) = migrate_to(&db_path, 2, true, SystemClock.now())?;
+        assert_eq!(plan.current_version, 0);
+        assert_eq!(plan.steps.len(), 2);
+        assert!(std::fs::read_dir(temp_dir.path())?.next().is_none());
+
+        // A fresh database needs no backup
+        let (plan, backup_path) = migrate_to(&db_path, 2, false, SystemClock.now())?;
+        assert_eq!(plan.steps.len(), 2);
+        assert!(backup_path.is_none());
+
+        // Dry runs only report what would happen
+        let (plan, _) = migrate_to(&db_path, latest_version(), true, SystemClock.now())?;
+        assert_eq!(plan.current_version, 2);
+        assert!(plan.steps.iter().all(|step| step.direction == Direction::Up));
+        let conn = Connection::open(&db_path)?;
+        assert_eq!(current_version(&conn)?, 2);
+
+        // The backup is named after the time it is given
+        let now = chrono::NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(9, 30, 0).unwrap();
+        let (_, backup_path) = migrate_to(&db_path, latest_version(), false, now)?;
+        let backup_path = backup_path.unwrap();
+        assert!(backup_path.is_file());
+        assert!(backup_path.ends_with("diary.db.v2-20240301093000000.bak"));
+        assert_eq!(current_version(&conn)?, latest_version());
+
+        // Reverting is refused while it would destroy data
+        conn.execute(
+            "INSERT INTO shards VALUES (2019, 1, 1, '2024-01-01')", []
+        )?;
+        assert!(migrate_to(&db_path, 3, false, SystemClock.now()).is_err());
+        conn.execute("DELETE FROM shards", [])?;
+
+        let (plan, _) = migrate_to(&db_path, 3, false, SystemClock.now())?;
+        assert_eq!(plan.steps[0].version, latest_version());
+        assert_eq!(plan.steps[0].direction, Direction::Down);
+        assert_eq!(current_version(&conn)?, 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_run_table_keeps_entries() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        migrate_to(&db_path, 9, false, SystemClock.now())?;
+
+        let conn = Connection::open(&db_path)?;
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+             INSERT INTO diary_entries (exec_version, date, content, created_at) VALUES
+                (1, '2024-01-01', 'First', '2024-01-01 08:00:00'),
+                (2, '2024-01-01', 'Second', '2024-01-02 08:00:00');
+             INSERT INTO entry_metadata (exec_version, date, word_count) VALUES (1, '2024-01-01', 1), (2, '2024-01-01', 1);
+             INSERT INTO sync_runs VALUES (2, 'nfc', '2024-01-02 08:00:01');",
+        )?;
+        let count = |sql: &str| -> Result<i64> { Ok(conn.query_row(sql, [], |row| row.get(0))?) };
+
+        migrate_to(&db_path, 10, false, SystemClock.now())?;
+        assert_eq!(count("SELECT COUNT(*) FROM entry_metadata")?, 2);
+        assert_eq!(count("SELECT COUNT(*) FROM sync_runs WHERE normalization IS NULL AND finished_at = '2024-01-01 08:00:00'")?, 1);
+        assert_eq!(count("SELECT COUNT(*) FROM sync_runs WHERE normalization = 'nfc'")?, 1);
+        assert!(conn
+            .execute("INSERT INTO diary_entries (exec_version, date, content, created_at) VALUES (9, '2024-01-03', 'x', 'now')", [])
+            .is_err());
+
+        migrate_to(&db_path, 9, false, SystemClock.now())?;
+        assert_eq!(count("SELECT COUNT(*) FROM diary_entries")?, 2);
+        assert_eq!(count("SELECT COUNT(*) FROM entry_metadata")?, 2);
+        assert_eq!(count("SELECT COUNT(*) FROM sync_runs")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_content_hashes_are_backfilled() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        migrate_to(&db_path, 11, false, SystemClock.now())?;
+
+        let shard = Connection::open(crate::storage::shard::path(&db_path, 2019))?;
+        shard.execute_batch(&crate::storage::shard::schema("main"))?;
+        shard.execute("INSERT INTO diary_entries (exec_version, date, content, created_at) VALUES (1, '2019-05-01', 'Sharded', 'now')", [])?;
+
+        let conn = Connection::open(&db_path)?;
+        conn.execute_batch(
+            "INSERT INTO sync_runs (id, started_at) VALUES (2, 'now');
+             INSERT INTO diary_entries (exec_version, date, content, created_at) VALUES (2, '2024-01-01', 'Live', 'now');
+             INSERT INTO shards VALUES (2019, 1, 1, 'now');",
+        )?;
+
+        migrate_to(&db_path, 12, false, SystemClock.now())?;
+        let hash = |date: &str| -> Result<String> {
+            Ok(conn.query_row("SELECT hash FROM entry_hashes WHERE date = ?1", [date], |row| row.get(0))?)
+        };
+        assert_eq!(hash("2024-01-01")?, content_hash("Live"));
+        assert_eq!(hash("2019-05-01")?, content_hash("Sharded"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_newer_database_is_rejected() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        let conn = Connection::open(&db_path)?;
+        conn.execute("CREATE TABLE schema_migrations (version INTEGER PRIMARY KEY)", [])?;
+        conn.execute(
+            "INSERT INTO schema_migrations (version) VALUES (?1)",
+            params![latest_version() + 1],
+        )?;
+
+        assert!(matches!(
+            migrate_to(&db_path, latest_version(), true, SystemClock.now()),
+            Err(RustyDiaryError::SchemaTooNew { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_reports_gaps_without_modifying() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("diary.db");
+        migrate_to(&db_path, 3, false, SystemClock.now())?;
+
+        let conn = Connection::open(&db_path)?;
+        let info = inspect(&conn)?;
+        assert!(info.is_compatible());
+        assert_eq!(info.version, 3);
+        assert_eq!(info.pending.len() as i32, latest_version() - 3);
+
+        conn.execute("DELETE FROM schema_migrations WHERE version = 2", [])?;
+        let info = inspect(&conn)?;
+        assert!(!info.is_compatible());
+        assert!(matches!(
+            check_compatibility(&conn),
+            Err(RustyDiaryError::IncompatibleSchema(_))
+        ));
+
+        Ok(())
+    }
+}
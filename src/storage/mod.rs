@@ -1,41 +1,592 @@
+#[cfg(feature = "sqlite")]
+mod archive;
+#[cfg(feature = "sqlite")]
+mod cache;
+#[cfg(feature = "sqlite")]
+mod compression;
+#[cfg(feature = "sqlite")]
 mod db;
+#[cfg(feature = "sqlite")]
+pub mod migrations;
 pub mod models;
+#[cfg(feature = "sqlite")]
+pub mod queue;
+pub mod rank;
+pub mod settings;
+#[cfg(feature = "sqlite")]
+pub(crate) mod shard;
+pub mod tokenize;
 
-use std::path::Path;
+#[cfg(feature = "sqlite")]
+use std::collections::BTreeMap;
+#[cfg(feature = "sqlite")]
+use std::num::NonZeroUsize;
+#[cfg(feature = "sqlite")]
+use std::path::{Path, PathBuf};
+#[cfg(feature = "sqlite")]
+use std::sync::Arc;
+#[cfg(feature = "sqlite")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "sqlite")]
 use chrono::NaiveDate;
+#[cfg(feature = "sqlite")]
+use regex::RegexBuilder;
+#[cfg(feature = "sqlite")]
+use rusqlite::{Connection, OpenFlags};
+#[cfg(feature = "sqlite")]
+use serde::Serialize;
 
-pub use self::models::{DiaryEntry, EntryMetadata};
+#[cfg(feature = "sqlite")]
+use self::cache::ReadCache;
+
+pub use self::models::{
+    AuthorStats, BackupRecord, DiaryEntry, DiaryStats, EntryLink, EntryMetadata, EntryQuery, EntrySummary, HabitCheck, Highlight,
+    LifeEvent, MatchMode, PersonCount, Ranking, ReindexReport, RunStats, SearchHit, StoredTag, SyncRun, TableCount,
+};
+#[cfg(feature = "sqlite")]
+pub use self::archive::Attachments;
+#[cfg(feature = "sqlite")]
+pub use self::queue::WriteQueue;
+pub use self::settings::{BatchMode, SearchSettings, SqliteSettings, StemLanguage};
+pub use self::tokenize::Tokenizer;
+#[cfg(feature = "sqlite")]
+use crate::cancel::CancellationToken;
+#[cfg(feature = "sqlite")]
+use crate::clock::Clock;
+#[cfg(feature = "sqlite")]
+use crate::dates::DateRange;
+#[cfg(feature = "sqlite")]
 use crate::error::Result;
+use crate::error::RustyDiaryError;
+#[cfg(feature = "sqlite")]
+use crate::progress::{NoProgress, ProgressSink};
+
+/// Largest compiled pattern `StorageManager::search_regex` accepts, in bytes
+pub const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+/// People listed in `DiaryStats`, the most mentioned
+#[cfg(feature = "sqlite")]
+const STATS_PEOPLE: usize = 10;
+
+/// Checks a custom metadata key: letters, digits, `_`, `-` and `.`
+pub fn validate_meta_key(key: &str) -> std::result::Result<(), RustyDiaryError> {
+    if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.')) {
+        return Err(RustyDiaryError::InvalidConfig(format!(
+            "Invalid metadata key '{}': use letters, digits, '_', '-' and '.'",
+            key
+        )));
+    }
+    Ok(())
+}
+
+/// What `db info` reports about a database file
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone, Serialize)]
+pub struct DatabaseInfo {
+    pub path: PathBuf,
+    pub size_bytes: u64,
+    pub crate_version: &'static str,
+    pub schema: migrations::SchemaInfo,
+    /// Row counts, only available once the schema is fully migrated
+    pub entry_count: Option<i64>,
+    pub shard_count: Option<i64>,
+    pub cold_archive_count: Option<i64>,
+}
+
+/// Outcome of `StorageManager::store_entries_partial`
+#[derive(Debug, Default)]
+pub struct BatchResult {
+    /// Number of entries stored
+    pub stored: usize,
+    /// Entries left out, with the reason each was rejected
+    pub rejected: Vec<(DiaryEntry, RustyDiaryError)>,
+}
+
+impl BatchResult {
+    /// Whether every entry was stored
+    pub fn is_complete(&self) -> bool {
+        self.rejected.is_empty()
+    }
+}
+
+/// Inspects a database without migrating or otherwise modifying it
+#[cfg(feature = "sqlite")]
+pub fn inspect<P: AsRef<Path>>(db_path: P) -> Result<DatabaseInfo> {
+    let db_path = db_path.as_ref();
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let schema = migrations::inspect(&conn)?;
+
+    let count = |table: &str| -> Result<Option<i64>> {
+        if !schema.is_compatible() || schema.version != schema.max_supported {
+            return Ok(None);
+        }
+        let sql = format!("SELECT COUNT(*) FROM {}", table);
+        Ok(Some(conn.query_row(&sql, [], |row| row.get(0))?))
+    };
+
+    Ok(DatabaseInfo {
+        path: db_path.to_path_buf(),
+        size_bytes: std::fs::metadata(db_path)?.len(),
+        crate_version: crate::VERSION,
+        entry_count: count("diary_entries")?,
+        shard_count: count("shards")?,
+        cold_archive_count: count("cold_archives")?,
+        schema,
+    })
+}
 
 /// StorageManager provides a clean facade over our persistence operations.
 /// It encapsulates the complexity of storage while providing a clear,
 /// focused interface to clients.
+#[cfg(feature = "sqlite")]
 pub struct StorageManager {
     repository: db::DiaryRepository,
+    cache: Option<ReadCache>,
+    /// Word-based matching for text search; substrings when unset
+    tokenizer: Option<Tokenizer>,
 }
 
+#[cfg(feature = "sqlite")]
 impl StorageManager {
     /// Creates a new StorageManager with the given database path
     pub fn new<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Self::with_settings(db_path, &SqliteSettings::default(), Arc::new(crate::clock::SystemClock))
+    }
+
+    /// Creates a new StorageManager with custom SQLite tuning, stamped with
+    /// the time of `clock` from the start, so a backup taken before
+    /// migrating is named after it too
+    pub fn with_settings<P: AsRef<Path>>(db_path: P, settings: &SqliteSettings, clock: Arc<dyn Clock>) -> Result<Self> {
+        Ok(Self {
+            repository: db::DiaryRepository::with_settings(db_path, settings, clock)?,
+            cache: None,
+            tokenizer: None,
+        })
+    }
+
+    /// Opens an existing database for reading only; see `open_read_only_with_settings`
+    pub fn open_read_only<P: AsRef<Path>>(db_path: P) -> Result<Self> {
+        Self::open_read_only_with_settings(db_path, &SqliteSettings::default())
+    }
+
+    /// Opens an existing database with read-only SQLite connections. The
+    /// database is neither created nor migrated, writes fail, and reading is
+    /// safe while a sync runs elsewhere.
+    pub fn open_read_only_with_settings<P: AsRef<Path>>(db_path: P, settings: &SqliteSettings) -> Result<Self> {
+        Ok(Self {
+            repository: db::DiaryRepository::open_read_only(db_path, settings)?,
+            cache: None,
+            tokenizer: None,
+        })
+    }
+
+    /// An empty database kept in memory and dropped with the manager; see
+    /// `DiaryRepository::in_memory`
+    pub fn in_memory_with_settings(settings: &SqliteSettings) -> Result<Self> {
         Ok(Self {
-            repository: db::DiaryRepository::new(db_path)?,
+            repository: db::DiaryRepository::in_memory(settings)?,
+            cache: None,
+            tokenizer: None,
         })
     }
 
+    /// Enables an in-process LRU cache for date-range and latest-entry
+    /// lookups. A capacity of zero leaves caching disabled.
+    pub fn with_read_cache(mut self, capacity: usize) -> Self {
+        self.cache = NonZeroUsize::new(capacity).map(ReadCache::new);
+        self
+    }
+
+    /// Enables or disables zstd compression of stored content
+    pub fn with_compression(mut self, enabled: bool) -> Self {
+        self.repository.set_compression(enabled);
+        self
+    }
+
+    /// Makes text search match whole words as configured, rather than
+    /// substrings; see `SearchSettings`
+    pub fn with_search_settings(mut self, settings: &SearchSettings) -> Self {
+        self.tokenizer = settings.is_tokenized().then(|| Tokenizer::new(settings));
+        self
+    }
+
+    /// Makes batch writes and `recompress` check `cancel` between entries.
+    /// A cancelled write fails with `RustyDiaryError::Cancelled` and stores
+    /// nothing, as its transaction is rolled back.
+    pub fn with_cancellation(mut self, cancel: CancellationToken) -> Self {
+        self.repository.set_cancellation(cancel);
+        self
+    }
+
+    /// Stamps sync runs, stars, shards and archives with the time of
+    /// `clock` instead of this machine's
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.repository.set_clock(clock);
+        self
+    }
+
+    /// Rewrites all stored content using the current compression setting.
+    /// Returns the number of entries that were converted.
+    pub fn recompress(&self) -> Result<usize> {
+        self.repository.recompress()
+    }
+
+    /// Rebuilds the word counts, links and highlights derived from the
+    /// stored entries, and the database's indexes, reporting the tables that
+    /// had drifted
+    pub fn reindex(&self) -> Result<ReindexReport> {
+        self.repository.reindex()
+    }
+
+    /// Refreshes query planner statistics in the main database and every
+    /// shard. Returns the number of databases analyzed.
+    pub fn analyze(&self) -> Result<usize> {
+        self.repository.analyze()
+    }
+
+    /// Moves all entries of a completed year into their own shard database.
+    /// Range queries keep returning them transparently.
+    pub fn archive_year(&self, year: i32) -> Result<usize> {
+        let result = self.repository.archive_year(year);
+        self.invalidate_cache();
+        result
+    }
+
+    /// Moves entries dated before `before` into a compressed cold archive
+    /// file, removing them and what their days keep from the live database.
+    /// The `attachments` only they link to move into the archive too.
+    pub fn cold_archive(&self, before: NaiveDate, attachments: Option<&Attachments>) -> Result<usize> {
+        let result = self.repository.cold_archive(before, attachments);
+        self.invalidate_cache();
+        result
+    }
+
+    /// Restores cold-archived entries within a date range, and their
+    /// archived attachments into `directory`
+    pub fn unarchive(&self, start_date: NaiveDate, end_date: NaiveDate, directory: &Path) -> Result<usize> {
+        let result = self.repository.unarchive(start_date, end_date, directory);
+        self.invalidate_cache();
+        result
+    }
+
+    /// Summaries of cold-archived entries, so statistics can cover them
+    pub fn archived_summaries(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<EntrySummary>> {
+        self.repository.get_archived_summaries(start_date, end_date)
+    }
+
+    /// Every cold-archived entry, read back from the archive files
+    pub fn archived_entries(&self) -> Result<Vec<DiaryEntry>> {
+        self.repository.get_archived_entries()
+    }
+
+    /// Years that currently live in shard databases
+    pub fn shard_years(&self) -> Result<Vec<i32>> {
+        self.repository.get_shard_years()
+    }
 
     /// Retrieves the latest execution version from storage
     pub fn latest_exec_version(&self) -> Result<i64> {
         self.repository.get_latest_exec_version()
     }
 
+    /// Whether an entry of `date` with this content hash is stored, in
+    /// any version, shard or cold archive
+    pub fn has_content(&self, date: NaiveDate, hash: &str) -> Result<bool> {
+        self.repository.has_content(date, hash)
+    }
+
+    /// The entries whose content is not stored for their date yet, in any
+    /// version. Shards and cold archives count, so re-importing old files
+    /// finds nothing new.
+    pub fn unstored_entries(&self, entries: Vec<DiaryEntry>) -> Result<Vec<DiaryEntry>> {
+        crate::pipeline::unstored_entries(self, entries)
+    }
+
     /// Stores a batch of diary entries atomically
     pub fn store_entries(&self, entries: Vec<DiaryEntry>) -> Result<()> {
+        self.store_entries_with_progress(entries, &NoProgress)
+    }
+
+    /// Stores a batch of diary entries atomically, reporting each stored entry
+    pub fn store_entries_with_progress(
+        &self,
+        entries: Vec<DiaryEntry>,
+        progress: &dyn ProgressSink,
+    ) -> Result<()> {
+        self.store_entries_with(entries, BatchMode::default(), progress)
+    }
+
+    /// Stores a batch of diary entries atomically, written as `mode` says.
+    /// The result is the same in every mode; only the speed differs.
+    pub fn store_entries_with(
+        &self,
+        entries: Vec<DiaryEntry>,
+        mode: BatchMode,
+        progress: &dyn ProgressSink,
+    ) -> Result<()> {
         // Pre-validate all entries before storage
         for entry in &entries {
             self.validate_entry(entry)?;
         }
 
-        self.repository.store_batch(entries)
+        let result = self.repository.store_batch_with(entries, mode, progress);
+        self.invalidate_cache();
+        result
+    }
+
+    /// Stores the entries that pass validation in one transaction and
+    /// returns the rest, so a malformed entry does not hold back the others.
+    /// Fails only if the database itself does.
+    pub fn store_entries_partial(&self, entries: Vec<DiaryEntry>, progress: &dyn ProgressSink) -> Result<BatchResult> {
+        let mut result = BatchResult::default();
+        let mut valid = Vec::with_capacity(entries.len());
+        for entry in entries {
+            match self.validate_entry(&entry) {
+                Ok(()) => valid.push(entry),
+                Err(e) => result.rejected.push((entry, e)),
+            }
+        }
+
+        result.stored = valid.len();
+        if !valid.is_empty() {
+            self.store_entries_with(valid, BatchMode::default(), progress)?;
+        }
+        Ok(result)
+    }
+
+    /// Days whose latest entry links to `date` with `[[date]]`
+    pub fn backlinks(&self, date: NaiveDate) -> Result<Vec<NaiveDate>> {
+        self.repository.get_backlinks(date)
+    }
+
+    /// Every wiki link between the latest entries, for graph exports
+    pub fn links(&self) -> Result<Vec<EntryLink>> {
+        self.repository.get_links()
+    }
+
+    /// People the latest entries within the range mention, those on the
+    /// most days first
+    pub fn people(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<PersonCount>> {
+        self.repository.get_people(start_date, end_date)
+    }
+
+    /// The latest entry of every day that mentions the person, ignoring
+    /// case, oldest first
+    pub fn person_entries(&self, name: &str) -> Result<Vec<DiaryEntry>> {
+        let mut entries = Vec::new();
+        for date in self.repository.get_person_days(name)? {
+            entries.extend(self.latest_entry(date)?);
+        }
+        Ok(entries)
+    }
+
+    /// Life events marked with `!event:` in the latest entries within the
+    /// range, oldest first
+    pub fn events(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<LifeEvent>> {
+        self.repository.get_events(start_date, end_date)
+    }
+
+    /// Re-reads the life events of every stored entry. Returns the number
+    /// of events.
+    pub fn rebuild_events(&self) -> Result<usize> {
+        self.repository.rebuild_events()
+    }
+
+    /// Re-reads the people mentioned in every stored entry. Returns the
+    /// number of day/person pairs.
+    pub fn rebuild_people(&self) -> Result<usize> {
+        self.repository.rebuild_people()
+    }
+
+    /// Re-reads the links of every stored entry. Returns the number of links.
+    pub fn rebuild_links(&self) -> Result<usize> {
+        self.repository.rebuild_links()
+    }
+
+    /// Highlights due on or before `today`, longest overdue first, at most `limit`
+    pub fn due_highlights(&self, today: NaiveDate, limit: Option<usize>) -> Result<Vec<Highlight>> {
+        self.repository.get_due_highlights(today, limit)
+    }
+
+    /// Records a review of a highlight on `reviewed` and schedules the next
+    /// one. Returns when it is due again, if ever.
+    pub fn review_highlight(&self, id: i64, reviewed: NaiveDate) -> Result<Option<NaiveDate>> {
+        self.repository.review_highlight(id, reviewed)
+    }
+
+    /// Re-reads the highlights of every stored entry, keeping the schedule
+    /// of those still marked. Returns the number of highlights.
+    pub fn rebuild_highlights(&self) -> Result<usize> {
+        self.repository.rebuild_highlights()
+    }
+
+    /// Replaces the tags `source` recorded for the entry's day with `tags`
+    /// and their confidence
+    pub fn replace_tags(&self, entry: &DiaryEntry, source: &str, tags: &[(String, f64)]) -> Result<()> {
+        self.repository.replace_tags(entry, source, tags)
+    }
+
+    /// Stores tags with their source, version and confidence as given,
+    /// e.g. from an archive
+    pub fn restore_tags(&self, tags: &[StoredTag]) -> Result<()> {
+        self.repository.restore_tags(tags)
+    }
+
+    /// Tags stored for a day apart from its content
+    pub fn stored_tags(&self, date: NaiveDate) -> Result<Vec<StoredTag>> {
+        self.repository.get_tags(date)
+    }
+
+    /// Stars (pins) or unstars a day, marking it to revisit. The star
+    /// belongs to the day, not a version, and is kept when the day is
+    /// archived. Returns whether that changed anything.
+    pub fn set_starred(&self, date: NaiveDate, starred: bool) -> Result<bool> {
+        self.repository.set_starred(date, starred)
+    }
+
+    /// Starred days within a date range, newest first
+    pub fn starred(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<NaiveDate>> {
+        self.repository.get_starred(start_date, end_date)
+    }
+
+    /// Sets a custom metadata field of a day, such as `mood` from a mood
+    /// tracker, replacing any earlier value. Like stars, fields belong to
+    /// the day and are kept when it is archived. Keys are letters, digits,
+    /// `_`, `-` and `.`, compared case-sensitively.
+    pub fn set_meta(&self, date: NaiveDate, key: &str, value: &str) -> Result<()> {
+        validate_meta_key(key)?;
+        self.repository.set_field(date, key, value)
+    }
+
+    /// The value of a day's custom metadata field, if set
+    pub fn get_meta(&self, date: NaiveDate, key: &str) -> Result<Option<String>> {
+        Ok(self.repository.get_fields(date)?.remove(key))
+    }
+
+    /// Every custom metadata field of a day, by key
+    pub fn meta(&self, date: NaiveDate) -> Result<BTreeMap<String, String>> {
+        self.repository.get_fields(date)
+    }
+
+    /// Removes a day's custom metadata field. Returns whether it was set.
+    pub fn unset_meta(&self, date: NaiveDate, key: &str) -> Result<bool> {
+        self.repository.delete_field(date, key)
+    }
+
+    /// Adds words to the diary's own spelling dictionary for `lang`, such
+    /// as names it uses. Returns how many were new.
+    pub fn add_spelling_words(&self, lang: &str, words: &[String]) -> Result<usize> {
+        self.repository.add_spelling_words(lang, words)
+    }
+
+    /// Removes a word from the diary's spelling dictionary for `lang`.
+    /// Returns whether it was there.
+    pub fn remove_spelling_word(&self, lang: &str, word: &str) -> Result<bool> {
+        self.repository.delete_spelling_word(lang, word)
+    }
+
+    /// The words of the diary's spelling dictionary for `lang`, sorted
+    pub fn spelling_words(&self, lang: &str) -> Result<Vec<String>> {
+        self.repository.get_spelling_words(lang)
+    }
+
+    /// Records a backup along with the attachments it saw, as path and hash
+    pub fn record_backup(&self, backup: &BackupRecord, attachments: &BTreeMap<String, String>) -> Result<()> {
+        self.repository.insert_backup(backup, attachments)
+    }
+
+    /// Every recorded backup, newest first
+    pub fn backups(&self) -> Result<Vec<BackupRecord>> {
+        self.repository.get_backups()
+    }
+
+    /// The attachments a backup saw, by path, with their hashes
+    pub fn backup_attachments(&self, id: &str) -> Result<BTreeMap<String, String>> {
+        self.repository.get_backup_attachments(id)
+    }
+
+    /// Versions in the main database stored by runs after `after` up to
+    /// and including `through`, oldest first
+    pub fn entries_between_runs(&self, after: i64, through: i64) -> Result<Vec<DiaryEntry>> {
+        self.repository.get_entries_between_versions(after, through)
+    }
+
+    /// Number of versions in the main database stored by runs up to and
+    /// including `through`
+    pub fn count_entries_through(&self, through: i64) -> Result<i64> {
+        self.repository.count_entries_through_version(through)
+    }
+
+    /// Replaces the habit checks recorded for a day with `checks`, as
+    /// (habit, done)
+    pub fn replace_habit_checks(&self, date: NaiveDate, checks: &[(String, bool)]) -> Result<()> {
+        self.repository.replace_habit_checks(date, checks)
+    }
+
+    /// Habit checks within a date range, by habit, then oldest first
+    pub fn habit_checks(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<HabitCheck>> {
+        self.repository.get_habit_checks(start_date, end_date)
+    }
+
+    /// Keeps the items whose day passes the query's filters on data stored
+    /// apart from the content: stars and custom metadata
+    fn retain_stored_filters<T>(
+        &self,
+        query: &EntryQuery,
+        items: &mut Vec<T>,
+        date: impl Fn(&T) -> NaiveDate,
+    ) -> Result<()> {
+        let (start_date, end_date) = query.bounds();
+        if query.starred {
+            let starred = self.starred(start_date, end_date)?;
+            items.retain(|item| starred.contains(&date(item)));
+        }
+        for (key, value) in &query.meta {
+            let dates = self.repository.get_dates_with_field(key, value, start_date, end_date)?;
+            items.retain(|item| dates.contains(&date(item)));
+        }
+        Ok(())
+    }
+
+    /// Starts a sync run applying the text normalization and returns its
+    /// id, the `exec_version` its entries are stored under
+    pub fn start_sync_run(&self, normalization: &str) -> Result<i64> {
+        self.repository.start_sync_run(normalization)
+    }
+
+    /// Marks a sync run finished with what it did
+    pub fn finish_sync_run(&self, id: i64, stats: &RunStats) -> Result<()> {
+        self.repository.finish_sync_run(id, stats)
+    }
+
+    /// Forgets a run that never finished, such as a cancelled one
+    pub fn delete_sync_run(&self, id: i64) -> Result<()> {
+        self.repository.delete_sync_run(id)
+    }
+
+    /// Every recorded sync run, newest first
+    pub fn sync_runs(&self) -> Result<Vec<SyncRun>> {
+        self.repository.get_sync_runs()
+    }
+
+    pub fn sync_run(&self, id: i64) -> Result<Option<SyncRun>> {
+        self.repository.get_sync_run(id)
+    }
+
+    /// The text normalization recorded for a sync run
+    pub fn sync_run_normalization(&self, exec_version: i64) -> Result<Option<String>> {
+        Ok(self.sync_run(exec_version)?.and_then(|run| run.normalization))
+    }
+
+    /// Deletes every stored version of the entry for a date
+    pub fn delete_entries(&self, date: NaiveDate) -> Result<usize> {
+        let result = self.repository.delete_entries(date);
+        self.invalidate_cache();
+        result
     }
 
     /// Retrieves entries within a date range
@@ -44,10 +595,190 @@ impl StorageManager {
         start_date: NaiveDate,
         end_date: NaiveDate,
     ) -> Result<Vec<DiaryEntry>> {
-        self.repository.get_entries_by_date_range(start_date, end_date)
+        let Some(cache) = &self.cache else {
+            return self.repository.get_entries_by_date_range(start_date, end_date);
+        };
+
+        if let Some(entries) = cache.get_range(start_date, end_date) {
+            return Ok(entries);
+        }
+
+        let entries = self.repository.get_entries_by_date_range(start_date, end_date)?;
+        cache.put_range(start_date, end_date, entries.clone());
+        Ok(entries)
+    }
+
+    /// Retrieves the most recent version of the entry for a date
+    pub fn latest_entry(&self, date: NaiveDate) -> Result<Option<DiaryEntry>> {
+        let Some(cache) = &self.cache else {
+            return self.repository.get_latest_entry(date);
+        };
+
+        if let Some(entry) = cache.get_latest(date) {
+            return Ok(entry);
+        }
+
+        let entry = self.repository.get_latest_entry(date)?;
+        cache.put_latest(date, entry.clone());
+        Ok(entry)
+    }
+
+    /// Lists entry summaries within a date range, skipping the content.
+    /// Prefer this over `entries_by_date_range` for listings and statistics.
+    pub fn list_entries(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> Result<Vec<EntrySummary>> {
+        self.repository.list_entries(start_date, end_date)
+    }
+
+    /// Every stored version matching the query, newest first
+    pub fn query(&self, query: &EntryQuery) -> Result<Vec<DiaryEntry>> {
+        let (start_date, end_date) = query.bounds();
+        let mut entries = self.entries_by_date_range(start_date, end_date)?;
+        entries.retain(|entry| query.matches(entry));
+        self.retain_stored_filters(query, &mut entries, |entry| entry.date)?;
+        Ok(entries)
+    }
+
+    /// Aggregate figures over every entry in the main database and its shards
+    pub fn stats(&self) -> Result<DiaryStats> {
+        self.stats_in(DateRange::all())
+    }
+
+    /// Statistics of the entries in the range, e.g. `DateRange::month(2024, 3)`
+    pub fn stats_in(&self, range: DateRange) -> Result<DiaryStats> {
+        let summaries = self.list_entries(range.start, range.end)?;
+
+        Ok(DiaryStats {
+            latest_exec_version: self.latest_exec_version()?,
+            archived: self.archived_summaries(range.start, range.end)?.len(),
+            starred: self.starred(range.start, range.end)?,
+            people: self.people(range.start, range.end)?.into_iter().take(STATS_PEOPLE).collect(),
+            ..DiaryStats::from_summaries(&summaries)
+        })
+    }
+
+    /// Statistics over the entries within a date range, one per author
+    pub fn author_stats(&self, range: DateRange) -> Result<Vec<AuthorStats>> {
+        Ok(AuthorStats::from_summaries(&self.list_entries(range.start, range.end)?))
+    }
+
+    /// The latest version of each entry in the range, newest first
+    pub fn latest_entries(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<DiaryEntry>> {
+        self.repository.get_latest_entries(start_date, end_date)
+    }
+
+    /// Case-insensitive search over the latest version of each entry in the
+    /// range, newest first, with `context` words around each match. With
+    /// search settings, every word of the query must match a word of the
+    /// entry instead.
+    pub fn search(
+        &self,
+        query: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        context: usize,
+    ) -> Result<Vec<SearchHit>> {
+        if let Some(tokenizer) = &self.tokenizer {
+            let terms = tokenizer.terms(query);
+            return Ok(self
+                .latest_entries(start_date, end_date)?
+                .into_iter()
+                .filter_map(|entry| entry.search_tokens(tokenizer, &terms, MatchMode::Default, context))
+                .collect());
+        }
+        let Some(pattern) = DiaryEntry::query_pattern(query) else {
+            return Ok(Vec::new());
+        };
+
+        Ok(self
+            .latest_entries(start_date, end_date)?
+            .into_iter()
+            .filter_map(|entry| entry.search_pattern(&pattern, context))
+            .collect())
+    }
+
+    /// Regex search over the latest version of each entry in the range,
+    /// newest first. The compiled pattern may take at most
+    /// `REGEX_SIZE_LIMIT` bytes, and entries are checked one at a time
+    /// until `timeout` runs out.
+    pub fn search_regex(
+        &self,
+        pattern: &str,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        context: usize,
+        timeout: Duration,
+    ) -> Result<Vec<SearchHit>> {
+        let pattern = RegexBuilder::new(pattern)
+            .size_limit(REGEX_SIZE_LIMIT)
+            .dfa_size_limit(REGEX_SIZE_LIMIT)
+            .build()?;
+        let started = Instant::now();
+
+        let mut hits = Vec::new();
+        for entry in self.latest_entries(start_date, end_date)? {
+            if started.elapsed() >= timeout {
+                return Err(RustyDiaryError::SearchTimeout(timeout));
+            }
+            hits.extend(entry.search_pattern(&pattern, context));
+        }
+        Ok(hits)
+    }
+
+    /// Latest versions matching the query, newest first or by relevance
+    /// with a ranking. Hits quote the first line containing any of the
+    /// query's text, with snippets of every match, or the first line when
+    /// the query has no text. Search settings and match modes other than
+    /// the default match the query's text word by word.
+    pub fn search_matching(&self, query: &EntryQuery, context: usize) -> Result<Vec<SearchHit>> {
+        let (start_date, end_date) = query.bounds();
+        let alternatives: Vec<String> = query.text.iter().map(|text| regex::escape(text)).collect();
+        let pattern = regex::Regex::new(&format!("(?i){}", alternatives.join("|")))
+            .map_err(|e| RustyDiaryError::InvalidConfig(e.to_string()))?;
+        let plain = Tokenizer::new(&SearchSettings::default());
+        let tokenizer = self.tokenizer.as_ref().unwrap_or(&plain);
+        let by_word = self.tokenizer.is_some() || query.mode != MatchMode::Default;
+        let terms = tokenizer.terms(&query.text.join(" "));
+        let filters = if by_word { EntryQuery { text: Vec::new(), ..query.clone() } } else { query.clone() };
+
+        let mut entries = self.latest_entries(start_date, end_date)?;
+        self.retain_stored_filters(query, &mut entries, |entry| entry.date)?;
+        entries.retain(|entry| filters.matches(entry));
+        let mut hits: Vec<(usize, SearchHit)> = entries
+            .iter()
+            .enumerate()
+            .filter_map(|(index, entry)| {
+                let hit = if query.text.is_empty() {
+                    Some(SearchHit {
+                        date: entry.date,
+                        exec_version: entry.exec_version,
+                        matches: 0,
+                        line: entry.content.lines().find(|line| !line.trim().is_empty()).unwrap_or("").trim().to_string(),
+                        snippets: Vec::new(),
+                        score: None,
+                    })
+                } else if by_word {
+                    entry.search_tokens(tokenizer, &terms, query.mode, context)
+                } else {
+                    entry.search_pattern(&pattern, context)
+                };
+                hit.map(|hit| (index, hit))
+            })
+            .collect();
+
+        if let Some(ranking) = &query.ranking {
+            let scores = rank::scores(&entries, tokenizer, &terms, ranking);
+            for (index, hit) in &mut hits {
+                hit.score = Some(scores[*index]);
+            }
+            hits.sort_by(|(a, _), (b, _)| scores[*b].total_cmp(&scores[*a]));
+        }
+        Ok(hits.into_iter().map(|(_, hit)| hit).collect())
     }
 
-    /// Retrieves entries within a date range
     pub fn get_entries_by_exec_version(
         &self,
         exec_version: i64,
@@ -62,9 +793,15 @@ impl StorageManager {
 
     // Private helper methods
 
+    fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate();
+        }
+    }
+
     fn validate_entry(&self, entry: &DiaryEntry) -> Result<()> {
         if entry.content.trim().is_empty() {
-            return Err(crate::error::RustyDiaryError::ContentIntegrity(
+            return Err(RustyDiaryError::ContentIntegrity(
                 "Empty content".to_string(),
             ));
         }
@@ -74,11 +811,13 @@ impl StorageManager {
 
 
 // Re-export essential types for convenience
+#[cfg(feature = "sqlite")]
 pub use self::db::DiaryRepository;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "sqlite"))]
 mod tests {
     use super::*;
+    use crate::clock::SystemClock;
     use tempfile::TempDir;
 
 
@@ -91,7 +830,7 @@ mod tests {
 
         // Test storing and retrieving entries
         let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
-        let entry = DiaryEntry::new(1, test_date, "Test content".to_string());
+        let entry = DiaryEntry::new(1, test_date, "Test content".to_string(), SystemClock.now());
 
         manager.store_entries(vec![entry])?;
 
@@ -105,4 +844,511 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_search_uses_latest_version() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, date, "Old coffee notes".to_string(), now),
+            DiaryEntry::new(2, date, "Tea today".to_string(), now),
+        ])?;
+
+        assert!(manager.search("coffee", NaiveDate::MIN, NaiveDate::MAX, 5)?.is_empty());
+        let hits = manager.search("TEA", NaiveDate::MIN, NaiveDate::MAX, 5)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].exec_version, 2);
+
+        let stats = manager.stats()?;
+        assert_eq!((stats.days, stats.versions, stats.total_words), (1, 2, 2));
+        assert_eq!(stats.latest_exec_version, 2);
+
+        let timeout = Duration::from_secs(10);
+        manager.store_entries(vec![DiaryEntry::new(3, date.succ_opt().unwrap(), "Fixed PROJ-1234, not proj-99".to_string(), now)])?;
+        let hits = manager.search_regex(r"[A-Z]+-\d{3,}", NaiveDate::MIN, NaiveDate::MAX, 0, timeout)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].snippets[0].matched, "PROJ-1234");
+        assert!(matches!(
+            manager.search_regex("(", NaiveDate::MIN, NaiveDate::MAX, 0, timeout),
+            Err(RustyDiaryError::InvalidPattern(_))
+        ));
+        assert!(manager.search_regex(r"\w{1000}{1000}", NaiveDate::MIN, NaiveDate::MAX, 0, timeout).is_err());
+        assert!(matches!(
+            manager.search_regex("Tea", NaiveDate::MIN, NaiveDate::MAX, 0, Duration::ZERO),
+            Err(RustyDiaryError::SearchTimeout(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_with_stemming_and_folding() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let settings = SearchSettings {
+            stemming: vec![StemLanguage::Portuguese, StemLanguage::English],
+            fold_diacritics: true,
+            stopwords: vec!["com".to_string()],
+        };
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?.with_search_settings(&settings);
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(1), "Dia de praia.\nO coração cheio #mar".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(2), "Walking along the river".to_string(), SystemClock.now()),
+        ])?;
+
+        let hits = manager.search("com coracao", NaiveDate::MIN, NaiveDate::MAX, 1)?;
+        assert_eq!(hits.len(), 1);
+        assert_eq!((hits[0].line.as_str(), hits[0].snippets[0].matched.as_str()), ("O coração cheio #mar", "coração"));
+        assert_eq!(manager.search("walked river", NaiveDate::MIN, NaiveDate::MAX, 0)?.len(), 1);
+        assert!(manager.search("walked sea", NaiveDate::MIN, NaiveDate::MAX, 0)?.is_empty());
+
+        let query = EntryQuery::new().with_tag("mar").with_text("Praias");
+        assert_eq!(manager.search_matching(&query, 0)?[0].date, ymd(1));
+        assert!(manager.search_matching(&query.with_tag("work"), 0)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_search_modes_and_ranking() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |m| NaiveDate::from_ymd_opt(2024, m, 1).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(1), "# Garden\nPlanted beans in the garden".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(4), "Rain all day, read about the garden".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(5), "Work, then rain again".to_string(), SystemClock.now()),
+        ])?;
+
+        let dates = |query: EntryQuery| -> Result<Vec<NaiveDate>> {
+            Ok(manager.search_matching(&query, 0)?.into_iter().map(|hit| hit.date).collect())
+        };
+        assert_eq!(dates(EntryQuery::new().with_text("rain garden"))?, Vec::<NaiveDate>::new());
+        assert_eq!(dates(EntryQuery::new().with_text("the garden").with_mode(MatchMode::Phrase))?, vec![ymd(4), ymd(1)]);
+        assert_eq!(dates(EntryQuery::new().with_text("garden the").with_mode(MatchMode::Phrase))?, Vec::<NaiveDate>::new());
+        assert_eq!(dates(EntryQuery::new().with_text("rain garden").with_mode(MatchMode::AnyTerm))?, vec![ymd(5), ymd(4), ymd(1)]);
+
+        let ranked = manager.search_matching(&EntryQuery::new().with_text("garden").with_ranking(Ranking::default()), 0)?;
+        assert_eq!(ranked.iter().map(|hit| hit.date).collect::<Vec<_>>(), vec![ymd(1), ymd(4)]);
+        assert!(ranked[0].score > ranked[1].score);
+
+        let recent = Ranking { title_weight: 1.0, recency_boost: 5.0, ..Ranking::default() };
+        assert_eq!(dates(EntryQuery::new().with_text("garden").with_ranking(recent))?, vec![ymd(4), ymd(1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backlinks_follow_latest_version() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(2), "Back to [[2024-01-01]]".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(3), "See [[2024-01-01]] and [[Reading List]]".to_string(), SystemClock.now()),
+        ])?;
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(2), "No links now".to_string(), SystemClock.now())])?;
+
+        assert_eq!(manager.backlinks(ymd(1))?, vec![ymd(3)]);
+        assert_eq!(manager.links()?.len(), 2);
+
+        manager.delete_entries(ymd(3))?;
+        assert!(manager.backlinks(ymd(1))?.is_empty());
+
+        // Rebuilding finds the same links from the stored content
+        manager.store_entries(vec![DiaryEntry::new(3, ymd(4), "[[2024-01-02]]".to_string(), SystemClock.now())])?;
+        assert_eq!(manager.rebuild_links()?, 1);
+        assert_eq!(manager.backlinks(ymd(2))?, vec![ymd(4)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_highlights_resurface_on_schedule() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        manager.store_entries(vec![DiaryEntry::new(1, ymd(1, 2), "==First== and ==second==".to_string(), now)])?;
+        assert!(manager.due_highlights(ymd(1, 8), None)?.is_empty());
+        let due = manager.due_highlights(ymd(1, 9), None)?;
+        assert_eq!(due.iter().map(|h| h.text.as_str()).collect::<Vec<_>>(), vec!["First", "second"]);
+
+        // A new version keeps the schedule of the highlights still marked
+        assert_eq!(manager.review_highlight(due[0].id, ymd(1, 10))?, Some(ymd(2, 10)));
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(1, 2), "==First== only".to_string(), now)])?;
+        assert!(manager.due_highlights(ymd(2, 9), Some(5))?.is_empty());
+        assert_eq!(manager.due_highlights(ymd(2, 10), None)?[0].stage, 1);
+
+        assert_eq!(manager.review_highlight(due[0].id, ymd(2, 10))?, Some(NaiveDate::from_ymd_opt(2025, 2, 10).unwrap()));
+        assert_eq!(manager.review_highlight(due[0].id, ymd(2, 10))?, None);
+        assert!(manager.due_highlights(NaiveDate::MAX, None)?.is_empty());
+        assert_eq!(manager.rebuild_highlights()?, 1);
+
+        manager.delete_entries(ymd(1, 2))?;
+        assert_eq!(manager.rebuild_highlights()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reindex_repairs_drift() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let manager = StorageManager::new(&db_path)?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(1), "See [[2024-01-02]] and ==this==".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(2), "Plain lunch with Ana !event: Met Ana".to_string(), SystemClock.now()),
+        ])?;
+
+        let conn = Connection::open(&db_path)?;
+        conn.execute_batch(
+            "DELETE FROM entry_metadata WHERE date = '2024-01-02';
+             DELETE FROM entry_links;
+             DELETE FROM entry_people;
+             DELETE FROM entry_events;
+             INSERT INTO highlights (date, text) VALUES ('2024-01-02', 'stale');",
+        )?;
+
+        let report = manager.reindex()?;
+        assert_eq!(report.entries, 2);
+        let counts: Vec<(&str, usize, usize)> =
+            report.tables.iter().map(|table| (table.table.as_str(), table.before, table.after)).collect();
+        assert_eq!(counts, vec![("entry_metadata", 1, 2), ("entry_links", 0, 1), ("highlights", 2, 1), ("entry_people", 0, 1), ("entry_events", 0, 1)]);
+        assert!(report.tables.iter().all(TableCount::drifted));
+        assert!(manager.reindex()?.tables.iter().all(|table| !table.drifted()));
+        assert_eq!(manager.backlinks(ymd(2))?, vec![ymd(1)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reporting_views() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let manager = StorageManager::new(&db_path)?;
+
+        let ymd = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(3, 1), "One two three".to_string(), now),
+            DiaryEntry::new(1, ymd(3, 2), "One".to_string(), now),
+            DiaryEntry::new(1, ymd(4, 1), "One two".to_string(), now),
+        ])?;
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(3, 2), "One two three four five".to_string(), now)])?;
+        manager.set_starred(ymd(3, 2), true)?;
+        manager.replace_tags(&manager.latest_entry(ymd(3, 1))?.unwrap(), "auto", &[("walks".to_string(), 0.5)])?;
+
+        let conn = Connection::open(&db_path)?;
+        let day: (i64, i64, i64, bool) = conn.query_row(
+            "SELECT word_count, versions, exec_version, starred FROM daily_word_counts WHERE date = '2024-03-02'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        assert_eq!(day, (5, 2, 2, true));
+
+        let march: (i64, i64, f64, i64) = conn.query_row(
+            "SELECT days, words, average_words, starred_days FROM monthly_summary WHERE month = '2024-03'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+        assert_eq!(march, (2, 8, 4.0, 1));
+
+        let tag: (String, i64) = conn.query_row("SELECT tag, days FROM tag_usage", [], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        assert_eq!(tag, ("walks".to_string(), 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_people_index() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(1), "Walked with Ana and @ana again.".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(2), "Called Bruno, then ana came over.".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, ymd(3), "Dinner with Bruno and Ana.".to_string(), SystemClock.now()),
+        ])?;
+        // The latest version of a day replaces its people
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(3), "Dinner with Bruno.".to_string(), SystemClock.now())])?;
+
+        let people = manager.people(ymd(1), ymd(31))?;
+        let counts: Vec<(&str, usize, usize)> =
+            people.iter().map(|person| (person.name.as_str(), person.days, person.mentions)).collect();
+        assert_eq!(counts, vec![("Bruno", 2, 2), ("Ana", 1, 2)]);
+        assert_eq!((people[0].first_date, people[0].last_date), (ymd(2), ymd(3)));
+        assert_eq!(manager.person_entries("ANA")?.iter().map(|entry| entry.date).collect::<Vec<_>>(), vec![ymd(1)]);
+        assert_eq!(manager.stats_in(DateRange::new(ymd(3), ymd(3)))?.people.len(), 1);
+
+        manager.delete_entries(ymd(1))?;
+        assert_eq!(manager.rebuild_people()?, 2);
+        assert!(manager.person_entries("Ana")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_events_follow_latest_version() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |m, d| NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        manager.store_entries(vec![
+            DiaryEntry::new(1, ymd(3, 1), "!event: New job\n!event: Bought a bike".to_string(), now),
+            DiaryEntry::new(1, ymd(1, 9), "# Moved out\n!event:".to_string(), now),
+        ])?;
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(3, 1), "!event: Started at Acme".to_string(), now)])?;
+
+        let events = manager.events(ymd(1, 1), ymd(12, 31))?;
+        let texts: Vec<(NaiveDate, &str)> = events.iter().map(|event| (event.date, event.text.as_str())).collect();
+        assert_eq!(texts, vec![(ymd(1, 9), "Moved out"), (ymd(3, 1), "Started at Acme")]);
+        assert_eq!(manager.events(ymd(2, 1), ymd(12, 31))?.len(), 1);
+        assert_eq!(manager.rebuild_events()?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spelling_words() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let words = ["Ana".to_string(), "Lisboa".to_string()];
+        assert_eq!(manager.add_spelling_words("en", &words)?, 2);
+        assert_eq!(manager.add_spelling_words("en", &words[..1])?, 0);
+        manager.add_spelling_words("pt", &words[1..])?;
+        assert!(manager.remove_spelling_word("en", "Lisboa")?);
+        assert!(!manager.remove_spelling_word("en", "Lisboa")?);
+        assert_eq!(manager.spelling_words("en")?, vec!["Ana"]);
+        assert_eq!(manager.spelling_words("pt")?, vec!["Lisboa"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_starred_days_filter_queries() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries((1..=3).map(|d| DiaryEntry::new(1, ymd(d), format!("Day {} #walk", d), now)).collect())?;
+        manager.store_entries(vec![DiaryEntry::new(2, ymd(2), "Day 2 rewritten #walk".to_string(), now)])?;
+
+        assert!(manager.set_starred(ymd(2), true)?);
+        assert!(!manager.set_starred(ymd(2), true)?);
+        assert!(manager.set_starred(ymd(3), true)?);
+        assert_eq!(manager.starred(NaiveDate::MIN, NaiveDate::MAX)?, vec![ymd(3), ymd(2)]);
+
+        let starred = EntryQuery::between(ymd(1), ymd(2)).with_starred();
+        let versions: Vec<i64> = manager.query(&starred)?.iter().map(|entry| entry.exec_version).collect();
+        assert_eq!(versions, vec![2, 1]);
+        let hits = manager.search_matching(&EntryQuery::new().with_tag("walk").with_starred(), 0)?;
+        assert_eq!(hits.len(), 2);
+        assert_eq!(manager.stats_in(DateRange::all())?.starred, vec![ymd(3), ymd(2)]);
+
+        assert!(manager.set_starred(ymd(3), false)?);
+        manager.delete_entries(ymd(2))?;
+        assert!(manager.starred(NaiveDate::MIN, NaiveDate::MAX)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_custom_metadata_filters_queries() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+
+        let ymd = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+        manager.store_entries((1..=3).map(|d| DiaryEntry::new(1, ymd(d), format!("Day {}", d), now)).collect())?;
+        manager.set_meta(ymd(1), "mood", "happy")?;
+        manager.set_meta(ymd(2), "mood", "tired")?;
+        manager.set_meta(ymd(2), "mood", "happy")?;
+        manager.set_meta(ymd(2), "geo.city", "Porto")?;
+        assert!(manager.set_meta(ymd(3), "two words", "x").is_err());
+
+        assert_eq!(manager.get_meta(ymd(2), "mood")?.as_deref(), Some("happy"));
+        assert_eq!(manager.get_meta(ymd(3), "mood")?, None);
+        assert_eq!(manager.meta(ymd(2))?.len(), 2);
+
+        let happy = manager.query(&EntryQuery::new().with_meta("mood", "happy"))?;
+        assert_eq!(happy.iter().map(|entry| entry.date).collect::<Vec<_>>(), vec![ymd(2), ymd(1)]);
+        let in_porto = EntryQuery::new().with_meta("mood", "happy").with_meta("geo.city", "Porto");
+        assert_eq!(manager.query(&in_porto)?.len(), 1);
+
+        assert!(manager.unset_meta(ymd(2), "geo.city")?);
+        assert!(!manager.unset_meta(ymd(2), "geo.city")?);
+        manager.delete_entries(ymd(1))?;
+        assert!(manager.meta(ymd(1))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_cache_invalidated_on_write() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+
+        let manager = StorageManager::new(&db_path)?.with_read_cache(8);
+        let test_date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        // Prime the cache with empty results
+        assert!(manager.entries_by_date_range(test_date, test_date)?.is_empty());
+        assert!(manager.latest_entry(test_date)?.is_none());
+
+        manager.store_entries(vec![DiaryEntry::new(1, test_date, "First".to_string(), SystemClock.now())])?;
+        assert_eq!(manager.entries_by_date_range(test_date, test_date)?.len(), 1);
+
+        manager.store_entries(vec![DiaryEntry::new(2, test_date, "Second".to_string(), SystemClock.now())])?;
+        let latest = manager.latest_entry(test_date)?.unwrap();
+        assert_eq!(latest.content, "Second");
+        assert_eq!(manager.entries_by_date_range(test_date, test_date)?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_in_memory_databases_are_separate() -> Result<()> {
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let first = StorageManager::in_memory_with_settings(&SqliteSettings::default())?;
+        let second = StorageManager::in_memory_with_settings(&SqliteSettings::default())?;
+        assert!(first.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?.is_empty());
+
+        first.store_entries(vec![DiaryEntry::new(1, date, "Only here".to_string(), SystemClock.now())])?;
+        assert_eq!(first.latest_entry(date)?.unwrap().content, "Only here");
+        assert!(second.latest_entry(date)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_never_writes() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test #1?.db");
+        let date = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        assert!(StorageManager::open_read_only(&db_path).is_err());
+        assert!(!db_path.exists());
+
+        migrations::migrate_to(&db_path, 1, false, SystemClock.now())?;
+        assert!(StorageManager::open_read_only(&db_path).is_err());
+        assert_eq!(inspect(&db_path)?.schema.version, 1);
+
+        let storage = StorageManager::new(&db_path)?;
+        storage.store_entries(vec![DiaryEntry::new(1, date, "Written".to_string(), now)])?;
+
+        let read_only = StorageManager::open_read_only(&db_path)?;
+        assert_eq!(read_only.latest_entry(date)?.unwrap().content, "Written");
+        assert!(read_only.store_entries(vec![DiaryEntry::new(2, date, "Changed".to_string(), now)]).is_err());
+
+        // Writes from another connection show up in open read-only managers
+        storage.store_entries(vec![DiaryEntry::new(2, date, "Synced".to_string(), now)])?;
+        assert_eq!(read_only.latest_entry(date)?.unwrap().content, "Synced");
+        Ok(())
+    }
+
+    #[test]
+    fn test_inspect_does_not_migrate() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+
+        migrations::migrate_to(&db_path, 1, false, SystemClock.now())?;
+        let info = inspect(&db_path)?;
+        assert_eq!(info.schema.version, 1);
+        assert!(info.entry_count.is_none());
+        assert_eq!(inspect(&db_path)?.schema.version, 1);
+
+        StorageManager::new(&db_path)?;
+        let info = inspect(&db_path)?;
+        assert!(info.schema.pending.is_empty());
+        assert_eq!(info.entry_count, Some(0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_reports_progress() -> Result<()> {
+        let now = SystemClock.now();
+        use std::sync::atomic::{AtomicU64, Ordering};
+
+        #[derive(Default)]
+        struct Counter {
+            total: AtomicU64,
+            done: AtomicU64,
+        }
+
+        impl ProgressSink for Counter {
+            fn start(&self, _phase: &str, total: u64) {
+                self.total.store(total, Ordering::SeqCst);
+            }
+
+            fn advance(&self, n: u64) {
+                self.done.fetch_add(n, Ordering::SeqCst);
+            }
+        }
+
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+        let counter = Counter::default();
+
+        let entries = (1..=3)
+            .map(|day| DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 1, day).unwrap(), "Text".to_string(), now))
+            .collect();
+        manager.store_entries_with_progress(entries, &counter)?;
+
+        assert_eq!(counter.total.load(Ordering::SeqCst), 3);
+        assert_eq!(counter.done.load(Ordering::SeqCst), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cancelled_batch_is_rolled_back() -> Result<()> {
+        let now = SystemClock.now();
+        /// Cancels the batch once its first entry is written
+        struct CancelAfterFirst(CancellationToken);
+
+        impl ProgressSink for CancelAfterFirst {
+            fn advance(&self, _n: u64) {
+                self.0.cancel();
+            }
+        }
+
+        let temp_dir = TempDir::new()?;
+        let cancel = CancellationToken::new();
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?.with_cancellation(cancel.clone());
+
+        let entries = (1..=3)
+            .map(|day| DiaryEntry::new(1, NaiveDate::from_ymd_opt(2024, 1, day).unwrap(), "Text".to_string(), now))
+            .collect();
+        let result = manager.store_entries_with_progress(entries, &CancelAfterFirst(cancel));
+
+        assert!(matches!(result, Err(RustyDiaryError::Cancelled)));
+        assert!(manager.latest_entries(NaiveDate::MIN, NaiveDate::MAX)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_store_keeps_valid_entries() -> Result<()> {
+        let temp_dir = TempDir::new()?;
+        let manager = StorageManager::new(temp_dir.path().join("test.db"))?;
+        let day = |d| NaiveDate::from_ymd_opt(2024, 1, d).unwrap();
+
+        let entries = vec![
+            DiaryEntry::new(1, day(1), "Kept".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(2), "  \n".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, day(3), "Also kept".to_string(), SystemClock.now()),
+        ];
+        assert!(manager.store_entries(entries.clone()).is_err());
+        assert!(manager.entries_by_date_range(day(1), day(3))?.is_empty());
+
+        let result = manager.store_entries_partial(entries, &NoProgress)?;
+        assert_eq!(result.stored, 2);
+        assert!(!result.is_complete());
+        assert_eq!(result.rejected.len(), 1);
+        assert_eq!(result.rejected[0].0.date, day(2));
+        assert_eq!(result.rejected[0].1.kind(), crate::error::ErrorKind::ContentIntegrity);
+        assert_eq!(manager.entries_by_date_range(day(1), day(3))?.len(), 2);
+        Ok(())
+    }
+}
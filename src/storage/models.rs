@@ -1,7 +1,29 @@
 use std::cmp::PartialEq;
-use chrono::{NaiveDate, NaiveDateTime};
+use std::collections::BTreeMap;
+use chrono::{Days, Months, NaiveDate, NaiveDateTime};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::dates::DateRange;
+
+use super::tokenize::Tokenizer;
+
+/// An inline `==highlight==`, its text captured without the marks
+pub(crate) const HIGHLIGHT_PATTERN: &str = r"==(\S(?:[^\n]*?\S)?)==";
+
+/// Marks a life event in an entry, followed by its text
+pub const EVENT_MARKER: &str = "!event:";
+
+/// Capitalized words that are not names: the pronoun, days, months and
+/// words that often open a sentence
+const NOT_NAMES: &[&str] = &[
+    "I", "I'm", "I've", "I'll", "I'd", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday",
+    "January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November",
+    "December", "Today", "Tonight", "Yesterday", "Tomorrow", "The", "A", "An", "This", "That", "These", "Those", "It",
+    "We", "He", "She", "They", "You", "My", "Our", "His", "Her", "Their", "Your", "Then", "When", "After", "Before",
+    "But", "And", "So", "Also", "Still", "Maybe", "Later", "Morning", "Afternoon", "Evening", "Dear", "TODO",
+];
 
 #[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
 pub struct DiaryEntry {
@@ -10,6 +32,9 @@ pub struct DiaryEntry {
     pub content: String,
     pub created_at: NaiveDateTime,
     pub updated_at: Option<NaiveDateTime>,
+    /// Who wrote the entry, in diaries shared by several people
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,9 +44,512 @@ pub struct EntryMetadata {
     pub exec_version: i64,
 }
 
+/// Lightweight view of an entry for listings and statistics.
+/// Carries everything but the content itself.
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+pub struct EntrySummary {
+    pub exec_version: i64,
+    pub date: NaiveDate,
+    pub word_count: usize,
+    pub created_at: NaiveDateTime,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+}
+
+/// Aggregate figures over every stored entry
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Deserialize)]
+pub struct DiaryStats {
+    /// Days with at least one stored entry
+    pub days: usize,
+    /// Stored versions across all days
+    pub versions: usize,
+    /// Words in the latest version of each day
+    pub total_words: usize,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+    pub latest_exec_version: i64,
+    /// Entries moved into cold archives, not counted above
+    pub archived: usize,
+    /// Starred days, the favorites, newest first
+    #[serde(default)]
+    pub starred: Vec<NaiveDate>,
+    /// The people mentioned on the most days, most first
+    #[serde(default)]
+    pub people: Vec<PersonCount>,
+}
+
+impl DiaryStats {
+    /// Aggregates summaries sorted newest first, as `list_entries` returns them
+    pub fn from_summaries(summaries: &[EntrySummary]) -> Self {
+        let mut stats = Self {
+            versions: summaries.len(),
+            first_date: summaries.last().map(|s| s.date),
+            last_date: summaries.first().map(|s| s.date),
+            ..Self::default()
+        };
+
+        let mut previous = None;
+        for summary in summaries {
+            if previous != Some(summary.date) {
+                stats.days += 1;
+                stats.total_words += summary.word_count;
+                previous = Some(summary.date);
+            }
+        }
+        stats
+    }
+}
+
+/// Aggregate figures over the entries of one author
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+pub struct AuthorStats {
+    /// `None` for entries nobody is credited with
+    pub author: Option<String>,
+    #[serde(flatten)]
+    pub stats: DiaryStats,
+}
+
+impl AuthorStats {
+    /// Groups summaries sorted newest first by author, in name order after
+    /// the unattributed entries
+    pub fn from_summaries(summaries: &[EntrySummary]) -> Vec<Self> {
+        let mut by_author: BTreeMap<Option<String>, Vec<EntrySummary>> = BTreeMap::new();
+        for summary in summaries {
+            by_author.entry(summary.author.clone()).or_default().push(summary.clone());
+        }
+
+        by_author
+            .into_iter()
+            .map(|(author, summaries)| AuthorStats {
+                author,
+                stats: DiaryStats {
+                    latest_exec_version: summaries.iter().map(|s| s.exec_version).max().unwrap_or(0),
+                    ..DiaryStats::from_summaries(&summaries)
+                },
+            })
+            .collect()
+    }
+}
+
+/// An entry matching a search query
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+pub struct SearchHit {
+    pub date: NaiveDate,
+    pub exec_version: i64,
+    /// Number of lines containing the query
+    pub matches: usize,
+    /// First matching line, trimmed
+    pub line: String,
+    /// Every match with words of context around it
+    #[serde(default)]
+    pub snippets: Vec<Snippet>,
+    /// Relevance of the entry when the search was ranked; higher is better
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<f64>,
+}
+
+/// One match of a search inside an entry, with the words around it.
+/// Whitespace in the context is collapsed to single spaces.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct Snippet {
+    /// Byte offset of the match in the entry content
+    pub start: usize,
+    /// Byte offset just past the match
+    pub end: usize,
+    pub before: String,
+    pub matched: String,
+    pub after: String,
+    /// The snippet as escaped HTML, with the match inside `<mark>`
+    pub html: String,
+}
+
+impl Snippet {
+    fn new(content: &str, start: usize, end: usize, context: usize) -> Self {
+        let before = context_before(&content[..start], context);
+        let matched = content[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+        let after = context_after(&content[end..], context);
+        let html = format!("{}<mark>{}</mark>{}", escape_html(&before), escape_html(&matched), escape_html(&after));
+        Self { start, end, before, matched, after, html }
+    }
+}
+
+/// The last `words` words of `text`. A word the match starts inside of
+/// stays attached to it.
+fn context_before(text: &str, words: usize) -> String {
+    let attached = !text.is_empty() && !text.ends_with(char::is_whitespace);
+    let mut parts: Vec<&str> = text.split_whitespace().rev().take(words + usize::from(attached)).collect();
+    parts.reverse();
+    let mut context = parts.join(" ");
+    if !context.is_empty() && !attached {
+        context.push(' ');
+    }
+    context
+}
+
+/// The first `words` words of `text`, likewise keeping a word the match
+/// ends inside of
+fn context_after(text: &str, words: usize) -> String {
+    let attached = !text.is_empty() && !text.starts_with(char::is_whitespace);
+    let context = text.split_whitespace().take(words + usize::from(attached)).collect::<Vec<_>>().join(" ");
+    if !context.is_empty() && !attached {
+        format!(" {}", context)
+    } else {
+        context
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/// A life event marked in an entry with `!event: text`
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct LifeEvent {
+    pub date: NaiveDate,
+    pub text: String,
+}
+
+/// A person the latest entries of a period mention
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct PersonCount {
+    pub name: String,
+    /// Days whose latest entry mentions them
+    pub days: usize,
+    pub mentions: usize,
+    pub first_date: NaiveDate,
+    pub last_date: NaiveDate,
+}
+
+/// A `[[wiki link]]` from one entry to another day or to a named note
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct EntryLink {
+    /// Day of the linking entry
+    pub source: NaiveDate,
+    /// Link target as written, without alias or heading
+    pub target: String,
+    /// Day the target names, when it is a date
+    pub target_date: Option<NaiveDate>,
+}
+
+/// A backup recorded when it was taken. Incremental backups build on the
+/// latest one, carrying what changed since.
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct BackupRecord {
+    pub id: String,
+    /// The backup this one builds on; unset for full backups
+    pub parent: Option<String>,
+    /// Latest run the backup covers
+    pub exec_version: i64,
+    /// Versions in the main database when it was taken
+    pub entries: i64,
+    pub created_at: NaiveDateTime,
+    /// Where the backup was written
+    pub path: String,
+}
+
+/// One sync run. Entries point at the run that stored them through their
+/// `exec_version`.
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+pub struct SyncRun {
+    pub id: i64,
+    pub started_at: NaiveDateTime,
+    /// Unset while the run is in progress
+    pub finished_at: Option<NaiveDateTime>,
+    /// Text normalization applied to the run's entries; unset for runs
+    /// from before it was recorded
+    pub normalization: Option<String>,
+    pub stats: Option<RunStats>,
+}
+
+/// What a finished sync run did
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RunStats {
+    /// Diary files collected
+    pub files: usize,
+    /// New entries stored
+    pub stored: usize,
+    /// Entries left out because a stored version has the same content
+    pub duplicates: usize,
+    /// Files that could not be read
+    pub skipped: usize,
+    pub duration_ms: u64,
+    /// Why the run stopped short, when it failed after storing entries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// A tag kept apart from the entry content, e.g. one applied by `--auto-tag`
+#[derive(Debug, Clone, Serialize, PartialEq, Deserialize)]
+pub struct StoredTag {
+    pub date: NaiveDate,
+    pub tag: String,
+    /// Entry version the tag was chosen for
+    pub exec_version: i64,
+    /// Where the tag came from, such as `auto`
+    pub source: String,
+    pub confidence: Option<f64>,
+}
+
+/// Whether a habit's checklist item was ticked in a day's entry
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct HabitCheck {
+    pub date: NaiveDate,
+    pub habit: String,
+    pub done: bool,
+}
+
+/// A passage marked for resurfacing with `==highlight==` or a `> !review`
+/// quote, shown again on a spaced schedule: a week after its day, then a
+/// month and a year after each review
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct Highlight {
+    pub id: i64,
+    pub date: NaiveDate,
+    pub text: String,
+    /// Reviews done so far
+    pub stage: u32,
+    /// Day the highlight resurfaces; none once the schedule is done
+    pub due: Option<NaiveDate>,
+    pub last_reviewed: Option<NaiveDate>,
+}
+
+impl Highlight {
+    /// When a highlight at `stage` resurfaces, counting from `from`: its
+    /// day for a new highlight, else the day of the last review. None once
+    /// it has been reviewed a year on.
+    pub fn due_after(stage: u32, from: NaiveDate) -> Option<NaiveDate> {
+        match stage {
+            0 => from.checked_add_days(Days::new(7)),
+            1 => from.checked_add_months(Months::new(1)),
+            2 => from.checked_add_months(Months::new(12)),
+            _ => None,
+        }
+    }
+}
+
+/// Rows of a table derived from the stored entries, before and after
+/// `StorageManager::reindex` rebuilt it
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct TableCount {
+    pub table: String,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl TableCount {
+    /// Whether the table had drifted from the entries it is derived from
+    pub fn drifted(&self) -> bool {
+        self.before != self.after
+    }
+}
+
+/// What `db reindex` rebuilt
+#[derive(Debug, Clone, Serialize, PartialEq, Eq, Deserialize)]
+pub struct ReindexReport {
+    /// Versions stored in the main database
+    pub entries: usize,
+    pub tables: Vec<TableCount>,
+}
+
+/// How the words of a search's text must appear in an entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MatchMode {
+    /// Each text as a substring, or every word of it with `SearchSettings`
+    #[default]
+    Default,
+    /// The words next to each other, in order
+    Phrase,
+    /// At least one of the words
+    AnyTerm,
+}
+
+/// BM25 weights ordering search hits by relevance. A word in the title
+/// (the first line) or among the tags counts that many times a word in
+/// the body.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Ranking {
+    pub title_weight: f64,
+    pub tags_weight: f64,
+    pub body_weight: f64,
+    /// How much newer entries gain: a score is multiplied by up to
+    /// `1 + recency_boost`, halving with every `RECENCY_HALF_LIFE_DAYS` an
+    /// entry is older than the newest hit. Zero ignores dates.
+    pub recency_boost: f64,
+}
+
+impl Ranking {
+    /// Days after which the recency boost of an entry halves
+    pub const RECENCY_HALF_LIFE_DAYS: f64 = 30.0;
+}
+
+impl Default for Ranking {
+    fn default() -> Self {
+        Self { title_weight: 3.0, tags_weight: 2.0, body_weight: 1.0, recency_boost: 0.0 }
+    }
+}
+
+/// Filters selecting stored entries, e.g. for themed journals.
+/// An empty query matches everything.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntryQuery {
+    pub start_date: Option<NaiveDate>,
+    pub end_date: Option<NaiveDate>,
+    /// Hashtags (without `#`, case-insensitive) that must all appear in the content
+    pub tags: Vec<String>,
+    /// Lowercase text that must all appear in the content
+    pub text: Vec<String>,
+    /// Hashtags none of which may appear in the content
+    pub exclude_tags: Vec<String>,
+    /// Lowercase text none of which may appear in the content
+    pub exclude_text: Vec<String>,
+    pub min_words: Option<usize>,
+    pub max_words: Option<usize>,
+    /// Only versions stored by this sync run
+    pub exec_version: Option<i64>,
+    /// Only entries by this author, case-insensitively
+    pub author: Option<String>,
+    /// Only starred days
+    pub starred: bool,
+    /// Custom metadata fields (key, value) the day must all have set
+    pub meta: Vec<(String, String)>,
+    /// How search matches the text
+    pub mode: MatchMode,
+    /// Order search hits by relevance rather than newest first
+    pub ranking: Option<Ranking>,
+}
+
+impl EntryQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Matches entries between the dates, both included
+    pub fn between(start_date: NaiveDate, end_date: NaiveDate) -> Self {
+        Self { start_date: Some(start_date), end_date: Some(end_date), ..Self::default() }
+    }
+
+    /// Matches entries in the range, e.g. `DateRange::iso_week(2024, 12)`
+    pub fn within(range: DateRange) -> Self {
+        Self::between(range.start, range.end)
+    }
+
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.trim_start_matches('#').to_lowercase());
+        self
+    }
+
+    /// Requires the text, case-insensitively
+    pub fn with_text(mut self, text: &str) -> Self {
+        self.text.push(text.to_lowercase());
+        self
+    }
+
+    /// Leaves out entries with the hashtag
+    pub fn without_tag(mut self, tag: &str) -> Self {
+        self.exclude_tags.push(tag.trim_start_matches('#').to_lowercase());
+        self
+    }
+
+    /// Leaves out entries containing the text, case-insensitively
+    pub fn without_text(mut self, text: &str) -> Self {
+        self.exclude_text.push(text.to_lowercase());
+        self
+    }
+
+    pub fn with_min_words(mut self, words: usize) -> Self {
+        self.min_words = Some(words);
+        self
+    }
+
+    pub fn with_max_words(mut self, words: usize) -> Self {
+        self.max_words = Some(words);
+        self
+    }
+
+    pub fn with_exec_version(mut self, exec_version: i64) -> Self {
+        self.exec_version = Some(exec_version);
+        self
+    }
+
+    pub fn with_author(mut self, author: &str) -> Self {
+        self.author = Some(author.to_lowercase());
+        self
+    }
+
+    /// Requires the day's custom metadata field `key` to be `value`
+    pub fn with_meta(mut self, key: &str, value: &str) -> Self {
+        self.meta.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_starred(mut self) -> Self {
+        self.starred = true;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_ranking(mut self, ranking: Ranking) -> Self {
+        self.ranking = Some(ranking);
+        self
+    }
+
+    /// Date range to load before filtering; missing bounds are open-ended
+    pub fn bounds(&self) -> (NaiveDate, NaiveDate) {
+        (self.start_date.unwrap_or(NaiveDate::MIN), self.end_date.unwrap_or(NaiveDate::MAX))
+    }
+
+    /// Whether the entry passes the filters on its content and dates.
+    /// Stars and custom metadata are stored apart from the content, so
+    /// they are left to `StorageManager::query`.
+    pub fn matches(&self, entry: &DiaryEntry) -> bool {
+        let (start, end) = self.bounds();
+        if entry.date < start || entry.date > end {
+            return false;
+        }
+        if self.exec_version.is_some_and(|version| version != entry.exec_version) {
+            return false;
+        }
+        if self.min_words.is_some_and(|words| entry.word_count() < words) {
+            return false;
+        }
+        if self.max_words.is_some_and(|words| entry.word_count() > words) {
+            return false;
+        }
+        if self.author.as_ref().is_some_and(|author| entry.author.as_ref().is_none_or(|name| name.to_lowercase() != *author)) {
+            return false;
+        }
+        if !self.text.is_empty() || !self.exclude_text.is_empty() {
+            let content = entry.content.to_lowercase();
+            if !self.text.iter().all(|text| content.contains(text.as_str())) {
+                return false;
+            }
+            if self.exclude_text.iter().any(|text| content.contains(text.as_str())) {
+                return false;
+            }
+        }
+        if self.tags.is_empty() && self.exclude_tags.is_empty() {
+            return true;
+        }
+
+        let tags = entry.tags();
+        self.tags.iter().all(|tag| tags.contains(tag)) && !self.exclude_tags.iter().any(|tag| tags.contains(tag))
+    }
+}
+
+/// Hex SHA-256 of entry content
+pub fn content_hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
 impl DiaryEntry {
-    pub fn new(exec_version: i64, date: NaiveDate, content: String) -> Self {
-        let now = chrono::Local::now().naive_local();
+    /// An entry created, and last updated, at `created_at`
+    pub fn new(exec_version: i64, date: NaiveDate, content: String, created_at: NaiveDateTime) -> Self {
         let metadata_pattern = Regex::new(
             r"(?s)\A---\s*(?:.*\n)*?tags:\s*(?:.*\n)*?(?:date:\s*[^\n]+\n)?(?:.*\n)*?---\s*"
         ).unwrap();
@@ -31,19 +559,32 @@ impl DiaryEntry {
             exec_version,
             date,
             content: stripped_content.lines().collect::<Vec<&str>>().join("\n"),
-            created_at: now,
-            updated_at: Some(now),
+            created_at,
+            updated_at: Some(created_at),
+            author: None,
         }
     }
 
+    /// Attributes the entry to an author
+    pub fn with_author(mut self, author: Option<String>) -> Self {
+        self.author = author;
+        self
+    }
+
+    #[allow(clippy::should_implement_trait)]
     pub fn eq(&self, other: &Self) -> bool {
-        self.date == other.date && self.content == other.content
+        self.date == other.date && self.content == other.content && self.author == other.author
     }
 
     pub fn word_count(&self) -> usize {
         self.content.split_whitespace().count()
     }
 
+    /// Hex SHA-256 of the content, identifying it across versions and dates
+    pub fn content_hash(&self) -> String {
+        content_hash(&self.content)
+    }
+
     pub fn metadata(&self) -> EntryMetadata {
         EntryMetadata {
             date: self.date,
@@ -51,17 +592,287 @@ impl DiaryEntry {
             exec_version: self.exec_version,
         }
     }
+
+    /// Lowercased `#hashtags` in the content, in order of appearance.
+    /// Headings (`# Title`) are not tags.
+    pub fn tags(&self) -> Vec<String> {
+        let pattern = Regex::new(r"(?:^|\s)#([\p{L}\p{N}_-]+)").unwrap();
+        let mut tags: Vec<String> = Vec::new();
+        for capture in pattern.captures_iter(&self.content) {
+            let tag = capture[1].to_lowercase();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
+    /// `[[target]]` links in the content, in order of appearance. Aliases
+    /// (`[[target|text]]`) and headings (`[[target#heading]]`) are dropped,
+    /// as are embedded files such as `![[photo.jpg]]`.
+    pub fn links(&self) -> Vec<EntryLink> {
+        let pattern = Regex::new(r"(!?)\[\[([^\[\]|#]+)(?:[|#][^\[\]]*)?\]\]").unwrap();
+        let mut links: Vec<EntryLink> = Vec::new();
+        for capture in pattern.captures_iter(&self.content) {
+            let target = capture[2].trim();
+            let file = target.rsplit_once('.').is_some_and(|(_, ext)| !ext.eq_ignore_ascii_case("md"));
+            if target.is_empty() || (!capture[1].is_empty() && file) || links.iter().any(|link| link.target == target) {
+                continue;
+            }
+            links.push(EntryLink {
+                source: self.date,
+                target: target.to_string(),
+                target_date: NaiveDate::parse_from_str(target, "%Y-%m-%d").ok(),
+            });
+        }
+        links
+    }
+
+    /// Passages marked with `==highlight==`, or quoted under a `> !review`
+    /// marker, in order of appearance. A quote runs until its first
+    /// unquoted line; text after the marker belongs to it.
+    pub fn highlights(&self) -> Vec<String> {
+        let inline = Regex::new(HIGHLIGHT_PATTERN).unwrap();
+        let mut highlights: Vec<String> = Vec::new();
+        let mut push = |text: String| {
+            if !text.is_empty() && !highlights.contains(&text) {
+                highlights.push(text);
+            }
+        };
+
+        let mut quote: Option<Vec<&str>> = None;
+        for line in self.content.lines() {
+            let quoted = line.trim_start().strip_prefix('>').map(str::trim);
+            match (quoted, quote.as_mut()) {
+                (Some(text), Some(lines)) => lines.push(text),
+                (Some(text), None) => {
+                    let marker = text.strip_prefix("!review").filter(|rest| rest.is_empty() || rest.starts_with(' '));
+                    if let Some(rest) = marker {
+                        quote = Some(vec![rest.trim()]);
+                    }
+                }
+                (None, _) => {
+                    if let Some(lines) = quote.take() {
+                        push(lines.join("\n").trim().to_string());
+                    }
+                }
+            }
+            for capture in inline.captures_iter(line) {
+                push(capture[1].to_string());
+            }
+        }
+        if let Some(lines) = quote {
+            push(lines.join("\n").trim().to_string());
+        }
+        highlights
+    }
+
+    /// Life events marked with `!event: text`, in order of appearance. The
+    /// text runs to the end of the line. A bare `!event:` marks the whole
+    /// entry, named by its first line. Markers in code blocks are skipped.
+    pub fn events(&self) -> Vec<String> {
+        let mut events: Vec<String> = Vec::new();
+        let mut fence: Option<&str> = None;
+        for line in self.content.lines() {
+            let trimmed = line.trim_start();
+            if let Some(open) = ["```", "~~~"].into_iter().find(|open| trimmed.starts_with(open)) {
+                match fence {
+                    Some(current) if current == open => fence = None,
+                    Some(_) => {}
+                    None => fence = Some(open),
+                }
+                continue;
+            }
+            let Some((_, text)) = line.split_once(EVENT_MARKER).filter(|_| fence.is_none()) else { continue };
+            let text = match text.trim() {
+                "" => self
+                    .content
+                    .lines()
+                    .map(|line| line.trim().trim_start_matches('#').trim())
+                    .find(|line| !line.is_empty() && !line.contains(EVENT_MARKER))
+                    .unwrap_or_default(),
+                text => text,
+            };
+            if !text.is_empty() && !events.iter().any(|event| event == text) {
+                events.push(text.to_string());
+            }
+        }
+        events
+    }
+
+    /// People the entry mentions, with how often. `@ana` and `@ana_silva`
+    /// mentions are read as "Ana" and "Ana Silva". Runs of capitalized
+    /// words inside a sentence count as names too, unless they are days,
+    /// months or the like. The word opening a sentence counts only when
+    /// it also starts a name inside one. Code, links, tags and HTML are
+    /// left out.
+    pub fn people(&self) -> Vec<(String, usize)> {
+        let ignored = Regex::new(concat!(
+            r"(?s)```.*?(?:```|$)|`[^`\n]*`",             // code
+            r"|\]\([^)]*\)|\w+://\S+|\S+@\S+\.\w+", // link targets, URLs and email addresses
+            r"|\[\[[^\]]*\]\]",                       // wiki links
+            r"|(?:^|\s)#[\p{L}\p{N}_-]+",              // tags
+            r"|<[^>]+>",                              // HTML
+        ))
+        .unwrap();
+        let content = ignored.replace_all(&self.content, " ");
+        let words = Regex::new(r"@[\p{L}][\p{L}\p{N}_.-]*|[\p{L}][\p{L}'’-]*").unwrap();
+
+        // Each run of capitalized words, and whether it opens a sentence
+        let mut runs: Vec<(Vec<&str>, bool)> = Vec::new();
+        let mut mentions: Vec<String> = Vec::new();
+        let mut current: Option<(Vec<&str>, bool)> = None;
+        let mut previous_end = 0;
+        for word in words.find_iter(&content) {
+            let gap = &content[previous_end..word.start()];
+            let opens = previous_end == 0 || sentence_break(gap);
+            let glued = gap.is_empty() && previous_end > 0;
+            previous_end = word.end();
+            let text = word.as_str().trim_end_matches(['\'', '’', '-', '.']);
+            let text = text.strip_suffix("'s").or_else(|| text.strip_suffix("’s")).unwrap_or(text);
+
+            if gap != " " {
+                runs.extend(current.take());
+            }
+            if let Some(handle) = text.strip_prefix('@') {
+                runs.extend(current.take());
+                if !glued {
+                    mentions.push(handle_name(handle));
+                }
+                continue;
+            }
+            let capitalized = text.chars().count() >= 2
+                && text.chars().next().is_some_and(char::is_uppercase)
+                && text.chars().skip(1).any(char::is_lowercase);
+            if !capitalized || (NOT_NAMES.contains(&text) && !opens) {
+                runs.extend(current.take());
+                continue;
+            }
+            match current.as_mut() {
+                Some((run, _)) => run.push(text),
+                None => current = Some((vec![text], opens)),
+            }
+        }
+        runs.extend(current);
+
+        // First words of the names seen inside a sentence, which count at its start too
+        let inside: Vec<&str> = runs.iter().filter(|(_, opens)| !opens).map(|(run, _)| run[0]).collect();
+        let mut people: Vec<(String, usize)> = Vec::new();
+        let mut count = |name: String| match people.iter_mut().find(|(known, _)| known.to_lowercase() == name.to_lowercase()) {
+            Some((_, mentions)) => *mentions += 1,
+            None => people.push((name, 1)),
+        };
+        for (mut run, opens) in runs {
+            if opens && !inside.contains(&run[0]) {
+                run.remove(0);
+            }
+            if !run.is_empty() {
+                count(run.join(" "));
+            }
+        }
+        for mention in mentions {
+            count(mention);
+        }
+        people
+    }
+
+    /// Case-insensitive substring search over the content lines, with
+    /// `context` words around each match. When searching many entries,
+    /// compile the query once with `query_pattern` and use `search_pattern`.
+    pub fn search(&self, query: &str, context: usize) -> Option<SearchHit> {
+        self.search_pattern(&Self::query_pattern(query)?, context)
+    }
+
+    /// Case-insensitive pattern matching `query` literally, as `search` uses
+    pub fn query_pattern(query: &str) -> Option<Regex> {
+        Regex::new(&format!("(?i){}", regex::escape(query))).ok()
+    }
+
+    /// Lines matching `pattern`, with `context` words around each match
+    pub fn search_pattern(&self, pattern: &Regex, context: usize) -> Option<SearchHit> {
+        let mut matching = self.content.lines().filter(|line| pattern.is_match(line));
+        let line = matching.next()?.trim().to_string();
+        let matches = 1 + matching.count();
+
+        let snippets = self
+            .content
+            .lines()
+            .map(|line| line.as_ptr() as usize - self.content.as_ptr() as usize)
+            .zip(self.content.lines())
+            .flat_map(|(offset, line)| pattern.find_iter(line).map(move |m| (offset + m.start(), offset + m.end())))
+            .filter(|(start, end)| start < end)
+            .map(|(start, end)| Snippet::new(&self.content, start, end, context))
+            .collect();
+
+        Some(SearchHit { date: self.date, exec_version: self.exec_version, matches, line, snippets, score: None })
+    }
+
+    /// Lines with words matching the `terms` of `tokenizer` as `mode`
+    /// asks, with `context` words around each match
+    pub fn search_tokens(
+        &self,
+        tokenizer: &Tokenizer,
+        terms: &[Vec<String>],
+        mode: MatchMode,
+        context: usize,
+    ) -> Option<SearchHit> {
+        let spans = tokenizer.find(&self.content, terms, mode)?;
+        let mut matching = self
+            .content
+            .lines()
+            .map(|line| (line.as_ptr() as usize - self.content.as_ptr() as usize, line))
+            .filter(|(offset, line)| spans.iter().any(|(start, _)| (*offset..offset + line.len()).contains(start)));
+        let line = matching.next()?.1.trim().to_string();
+        let matches = 1 + matching.count();
+        let snippets = spans.into_iter().map(|(start, end)| Snippet::new(&self.content, start, end, context)).collect();
+
+        Some(SearchHit { date: self.date, exec_version: self.exec_version, matches, line, snippets, score: None })
+    }
+
+    pub fn summary(&self) -> EntrySummary {
+        EntrySummary {
+            exec_version: self.exec_version,
+            date: self.date,
+            word_count: self.word_count(),
+            created_at: self.created_at,
+            author: self.author.clone(),
+        }
+    }
+}
+
+/// Whether the text between two words ends a sentence or block: end
+/// punctuation, a blank line, or a new line opening with a list, heading
+/// or quote marker
+fn sentence_break(gap: &str) -> bool {
+    if gap.contains(['.', '!', '?', ':']) || gap.matches('\n').count() > 1 {
+        return true;
+    }
+    gap.rsplit_once('\n').is_some_and(|(_, indent)| !indent.trim().is_empty())
+}
+
+/// The name an `@handle` stands for: `ana_silva` is "Ana Silva"
+fn handle_name(handle: &str) -> String {
+    handle
+        .split(['_', '.'])
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::{Clock, SystemClock};
 
     #[test]
     fn test_diary_entry_new() {
         let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
         let content = "Some diary content";
-        let entry = DiaryEntry::new(1, date, content.to_string());
+        let entry = DiaryEntry::new(1, date, content.to_string(), SystemClock.now());
 
         assert_eq!(entry.exec_version, 1);
         assert_eq!(entry.date, date);
@@ -73,7 +884,7 @@ mod tests {
     fn test_strip_metadata() {
         let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
         let content = "---\ntags:\n  - reflections\ndate: 2025-06-07\n---\n##Actual content here";
-        let entry = DiaryEntry::new(1, date, content.to_string());
+        let entry = DiaryEntry::new(1, date, content.to_string(), SystemClock.now());
 
         assert_eq!(entry.content, "##Actual content here");
     }
@@ -82,7 +893,7 @@ mod tests {
     fn test_metadata_extraction() {
         let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
         let content = "This is a test with multiple words";
-        let entry = DiaryEntry::new(1, date, content.to_string());
+        let entry = DiaryEntry::new(1, date, content.to_string(), SystemClock.now());
         let metadata = entry.metadata();
 
         assert_eq!(metadata.date, date);
@@ -93,18 +904,143 @@ mod tests {
     #[test]
     fn test_eq_implementation() {
         let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
-        let entry1 = DiaryEntry::new(1, date.clone(), "Content".to_string());
-        let entry2 = DiaryEntry::new(2, date.clone(), "Content".to_string());
-        let entry3 = DiaryEntry::new(1, date, "Different content".to_string());
+        let entry1 = DiaryEntry::new(1, date, "Content".to_string(), SystemClock.now());
+        let entry2 = DiaryEntry::new(2, date, "Content".to_string(), SystemClock.now());
+        let entry3 = DiaryEntry::new(1, date, "Different content".to_string(), SystemClock.now());
 
         assert!(entry1.eq(&entry2)); // Same content and date
         assert!(!entry1.eq(&entry3)); // Different content
     }
 
+    #[test]
+    fn test_search_and_stats() {
+        let now = SystemClock.now();
+        let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
+        let entry = DiaryEntry::new(2, date, "Went hiking\nno rain\n  More HIKING later ".to_string(), now);
+
+        let hit = entry.search("hiking", 1).unwrap();
+        assert_eq!(hit.matches, 2);
+        assert_eq!(hit.line, "Went hiking");
+        assert!(entry.search("snow", 1).is_none());
+
+        let starts: Vec<usize> = hit.snippets.iter().map(|s| s.start).collect();
+        assert_eq!(starts, vec![5, 27]);
+        assert_eq!(&entry.content[27..33], "HIKING");
+        assert_eq!(
+            (hit.snippets[1].before.as_str(), hit.snippets[1].matched.as_str(), hit.snippets[1].after.as_str()),
+            ("More ", "HIKING", " later")
+        );
+        // Context crosses lines, and a word the match starts inside stays attached
+        let hit = entry.search("ain", 2).unwrap();
+        assert_eq!(hit.snippets[0].before, "hiking no r");
+        assert_eq!(hit.snippets[0].after, " More HIKING");
+
+        let markup = DiaryEntry::new(1, date, "a <b> & \"rain\"".to_string(), now);
+        assert_eq!(markup.search("rain", 3).unwrap().snippets[0].html, "a &lt;b&gt; &amp; &quot;<mark>rain</mark>&quot;");
+
+        let older = DiaryEntry::new(1, date, "Draft".to_string(), now);
+        let next_day = DiaryEntry::new(2, date.succ_opt().unwrap(), "One two".to_string(), now);
+        let stats = DiaryStats::from_summaries(&[next_day.summary(), entry.summary(), older.summary()]);
+        assert_eq!(stats.days, 2);
+        assert_eq!(stats.versions, 3);
+        assert_eq!(stats.total_words, 2 + 7);
+        assert_eq!(stats.first_date, Some(date));
+    }
+
+    #[test]
+    fn test_tags_and_query() {
+        let now = SystemClock.now();
+        let date = NaiveDate::from_ymd_opt(2023, 5, 1).unwrap();
+        let entry = DiaryEntry::new(3, date, "# Lisbon\n##Day two #Travel with #food-trip\nissue #12 #travel".to_string(), now);
+        assert_eq!(entry.tags(), vec!["travel", "food-trip", "12"]);
+
+        assert!(EntryQuery::new().matches(&entry));
+        assert!(EntryQuery::between(date, date).with_tag("#TRAVEL").with_tag("food-trip").matches(&entry));
+        assert!(!EntryQuery::new().with_tag("lisbon").matches(&entry));
+        assert!(!EntryQuery::new().with_min_words(20).matches(&entry));
+        assert!(!EntryQuery::new().with_max_words(2).matches(&entry));
+        assert!(!EntryQuery::new().with_exec_version(2).matches(&entry));
+        assert!(!EntryQuery::between(date.succ_opt().unwrap(), NaiveDate::MAX).matches(&entry));
+        assert!(EntryQuery::new().with_text("DAY TWO").with_tag("travel").matches(&entry));
+        assert!(!EntryQuery::new().with_text("day two").with_text("porto").matches(&entry));
+        assert!(!EntryQuery::new().with_tag("travel").without_tag("#Food-Trip").matches(&entry));
+        assert!(!EntryQuery::new().without_text("DAY TWO").matches(&entry));
+        assert!(EntryQuery::new().without_tag("work").without_text("porto").matches(&entry));
+    }
+
+    #[test]
+    fn test_links() {
+        let entry = DiaryEntry::new(
+            1,
+            NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+            "Like [[2024-01-02]] and [[Some Note|the note]], see [[Some Note#Plans]] and [[ ]] ![[photo.jpg|300]]".to_string(),
+            SystemClock.now(),
+        );
+
+        let links = entry.links();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].target_date, NaiveDate::from_ymd_opt(2024, 1, 2));
+        assert_eq!((links[1].target.as_str(), links[1].target_date), ("Some Note", None));
+    }
+
+    #[test]
+    fn test_events() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 3).unwrap();
+        let entry = DiaryEntry::new(
+            1,
+            day,
+            "Big day. !event: Moved to **Lisbon**\n```\n!event: not this\n```\n!event: Moved to **Lisbon**\n- !event:  Got a cat \n"
+                .to_string(),
+            SystemClock.now(),
+        );
+        assert_eq!(entry.events(), vec!["Moved to **Lisbon**", "Got a cat"]);
+
+        let entry = DiaryEntry::new(1, day, "\n## Our wedding\n\n!event:\nIt rained.".to_string(), SystemClock.now());
+        assert_eq!(entry.events(), vec!["Our wedding"]);
+        assert!(DiaryEntry::new(1, day, "!event:".to_string(), SystemClock.now()).events().is_empty());
+    }
+
+    #[test]
+    fn test_people() {
+        let entry = DiaryEntry::new(
+            1,
+            NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+            "# Lunch with Ana\n\nMet Ana and João Silva at the café. Ana's idea was great! Later, @bob_lee called. \
+             Yesterday Mary Jane Watson texted; I told her Monday works.\n`Code Person` and [a link](Somewhere.md) #Tag\n\
+             Bruno came too. Then Marta, and anna@Example.com.\n"
+                .to_string(),
+            SystemClock.now(),
+        );
+        let people = entry.people();
+        let people: Vec<(&str, usize)> = people.iter().map(|(name, count)| (name.as_str(), *count)).collect();
+        assert_eq!(
+            people,
+            vec![("Ana", 3), ("João Silva", 1), ("Mary Jane Watson", 1), ("Bruno", 1), ("Marta", 1), ("Bob Lee", 1)]
+        );
+    }
+
+    #[test]
+    fn test_highlights() {
+        let entry = DiaryEntry::new(
+            1,
+            NaiveDate::from_ymd_opt(2024, 1, 3).unwrap(),
+            "Read that ==slow is smooth== today, a == b == c.\n\n> !review Keep\n> it simple\nAfter\n> !reviewed no\n> !review"
+                .to_string(),
+            SystemClock.now(),
+        );
+        assert_eq!(entry.highlights(), vec!["slow is smooth", "Keep\nit simple"]);
+
+        let day = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        assert_eq!(Highlight::due_after(0, day), NaiveDate::from_ymd_opt(2024, 2, 7));
+        assert_eq!(Highlight::due_after(1, day), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(Highlight::due_after(2, day), NaiveDate::from_ymd_opt(2025, 1, 31));
+        assert_eq!(Highlight::due_after(3, day), None);
+    }
+
     #[test]
     fn test_word_count() {
         let date = NaiveDate::from_ymd_opt(2023, 1, 1).unwrap();
-        let entry = DiaryEntry::new(1, date, "One two three\nfour five".to_string());
+        let entry = DiaryEntry::new(1, date, "One two three\nfour five".to_string(), SystemClock.now());
 
         assert_eq!(entry.word_count(), 5);
     }
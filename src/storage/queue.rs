@@ -0,0 +1,130 @@
+//! A single worker thread that runs every write in arrival order.
+//!
+//! Long-running modes such as the daemon share one storage between many
+//! callers. Reads go straight to the repository's pool of read-only
+//! connections, while writes are submitted here: one worker owns the
+//! writing, so callers queue up in order instead of contending for the
+//! writer and SQLite never sees two writers at once.
+
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::error::{Result, RustyDiaryError};
+
+type Job<S> = Box<dyn FnOnce(&S) + Send>;
+
+/// Runs submitted writes against `S` one at a time, first come first served
+pub struct WriteQueue<S> {
+    sender: Option<Sender<Job<S>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<S: Send + Sync + 'static> WriteQueue<S> {
+    /// Starts the worker. It stops once the queue is dropped and every
+    /// submitted write has run.
+    pub fn spawn(target: Arc<S>) -> Result<Self> {
+        let (sender, jobs) = mpsc::channel::<Job<S>>();
+        let worker = thread::Builder::new()
+            .name("rusty-diary-writer".to_string())
+            .spawn(move || {
+                for job in jobs {
+                    job(&target);
+                }
+            })?;
+
+        Ok(Self { sender: Some(sender), worker: Some(worker) })
+    }
+
+    /// Queues a write and returns a receiver for its result, without waiting
+    pub fn enqueue<T, F>(&self, write: F) -> Result<Receiver<Result<T>>>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> Result<T> + Send + 'static,
+    {
+        let (reply, result) = mpsc::sync_channel(1);
+        let job: Job<S> = Box::new(move |target| {
+            // The caller may have stopped waiting; the write still happened
+            let _ = reply.send(write(target));
+        });
+
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(job).ok())
+            .ok_or(RustyDiaryError::WriteQueueStopped)?;
+        Ok(result)
+    }
+
+    /// Queues a write and waits for it to run
+    pub fn submit<T, F>(&self, write: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&S) -> Result<T> + Send + 'static,
+    {
+        self.enqueue(write)?.recv().map_err(|_| RustyDiaryError::WriteQueueStopped)?
+    }
+}
+
+impl<S> Drop for WriteQueue<S> {
+    fn drop(&mut self) {
+        // Closing the channel lets the worker finish what is queued and exit
+        self.sender.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::storage::{DiaryEntry, StorageManager};
+    use chrono::NaiveDate;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_concurrent_writes_and_reads() -> Result<()> {
+        let now = SystemClock.now();
+        let temp_dir = TempDir::new()?;
+        let storage = Arc::new(StorageManager::new(temp_dir.path().join("test.db"))?);
+        let queue = Arc::new(WriteQueue::spawn(Arc::clone(&storage))?);
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|writer| {
+                let queue = Arc::clone(&queue);
+                let storage = Arc::clone(&storage);
+                thread::spawn(move || -> Result<()> {
+                    for day in 0..10 {
+                        let date = start + chrono::Duration::days(writer * 10 + day);
+                        queue.submit(move |storage: &StorageManager| {
+                            storage.store_entries(vec![DiaryEntry::new(1, date, format!("Day {}", day), now)])
+                        })?;
+                        storage.latest_entries(start, date)?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        assert_eq!(storage.latest_entries(start, start + chrono::Duration::days(100))?.len(), 80);
+        Ok(())
+    }
+
+    #[test]
+    fn test_writes_run_in_order() -> Result<()> {
+        let queue = WriteQueue::spawn(Arc::new(parking_lot::Mutex::new(Vec::new())))?;
+        let pending: Vec<_> = (0..50)
+            .map(|i| queue.enqueue(move |log: &parking_lot::Mutex<Vec<i32>>| { log.lock().push(i); Ok(i) }))
+            .collect::<Result<_>>()?;
+
+        let finished: Vec<i32> = pending.into_iter().map(|result| result.recv().unwrap()).collect::<Result<_>>()?;
+        assert_eq!(finished, (0..50).collect::<Vec<_>>());
+        assert_eq!(queue.submit(|log| Ok(log.lock().clone()))?, finished);
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+//! BM25 relevance of entries to a search, for ordering hits. The title
+//! (first line), tags and body of an entry are scored as separate fields
+//! and weighted by `Ranking` (BM25F), so a word in the title counts for
+//! more than the same word in passing.
+
+use super::models::{DiaryEntry, Ranking};
+use super::tokenize::{self, Tokenizer};
+
+/// Term frequency saturation
+const K1: f64 = 1.2;
+/// Length normalization: 0 ignores field lengths, 1 fully divides by them
+const B: f64 = 0.75;
+
+/// Scores of the entries for the query terms, in order. Word frequencies
+/// across the diary are taken from `entries` themselves, so pass every
+/// candidate rather than only the hits.
+pub fn scores(entries: &[DiaryEntry], tokenizer: &Tokenizer, terms: &[Vec<String>], ranking: &Ranking) -> Vec<f64> {
+    let keys = |text: &str| -> Vec<Vec<String>> { tokenizer.words(text).map(|(_, word)| tokenizer.keys(word)).collect() };
+    let fields: Vec<[Vec<Vec<String>>; 3]> = entries
+        .iter()
+        .map(|entry| [keys(title(&entry.content)), keys(&entry.tags().join(" ")), keys(&entry.content)])
+        .collect();
+    let weights = [ranking.title_weight, ranking.tags_weight, ranking.body_weight];
+    let count = entries.len() as f64;
+    let average: Vec<f64> =
+        (0..3).map(|field| fields.iter().map(|doc| doc[field].len()).sum::<usize>() as f64 / count.max(1.0)).collect();
+
+    // Weighted, length-normalized frequency of each term in each entry
+    let frequencies: Vec<Vec<f64>> = fields
+        .iter()
+        .map(|doc| {
+            terms
+                .iter()
+                .map(|term| {
+                    (0..3)
+                        .map(|field| {
+                            let tf = doc[field].iter().filter(|keys| tokenize::matches(term, keys)).count() as f64;
+                            if tf == 0.0 {
+                                return 0.0;
+                            }
+                            weights[field] * tf / (1.0 - B + B * doc[field].len() as f64 / average[field])
+                        })
+                        .sum()
+                })
+                .collect()
+        })
+        .collect();
+
+    let idf: Vec<f64> = (0..terms.len())
+        .map(|term| {
+            let containing = frequencies.iter().filter(|doc| doc[term] > 0.0).count() as f64;
+            (1.0 + (count - containing + 0.5) / (containing + 0.5)).ln()
+        })
+        .collect();
+
+    let newest = entries.iter().map(|entry| entry.date).max();
+    entries
+        .iter()
+        .zip(&frequencies)
+        .map(|(entry, doc)| {
+            let relevance: f64 = if terms.is_empty() {
+                1.0
+            } else {
+                doc.iter().zip(&idf).map(|(tf, idf)| idf * tf * (K1 + 1.0) / (K1 + tf)).sum()
+            };
+            let age = newest.map_or(0, |newest| (newest - entry.date).num_days()) as f64;
+            relevance * (1.0 + ranking.recency_boost * 0.5f64.powf(age / Ranking::RECENCY_HALF_LIFE_DAYS))
+        })
+        .collect()
+}
+
+/// The first non-empty line, without heading marks
+fn title(content: &str) -> &str {
+    content
+        .lines()
+        .map(|line| line.trim().trim_start_matches('#').trim())
+        .find(|line| !line.is_empty())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, SystemClock};
+    use crate::storage::settings::SearchSettings;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_scores() {
+        let month = |m| NaiveDate::from_ymd_opt(2024, m, 1).unwrap();
+        let entries = vec![
+            DiaryEntry::new(1, month(1), "# Garden\nPlanted beans".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, month(3), "Long day at work, then the garden for a bit".to_string(), SystemClock.now()),
+            DiaryEntry::new(1, month(4), "Nothing much".to_string(), SystemClock.now()),
+        ];
+        let tokenizer = Tokenizer::new(&SearchSettings::default());
+        let terms = tokenizer.terms("garden");
+
+        let ranked = scores(&entries, &tokenizer, &terms, &Ranking::default());
+        assert!(ranked[0] > ranked[1] && ranked[1] > 0.0, "{:?}", ranked);
+        assert_eq!(ranked[2], 0.0);
+
+        let recent = Ranking { title_weight: 1.0, recency_boost: 10.0, ..Ranking::default() };
+        let ranked = scores(&entries, &tokenizer, &terms, &recent);
+        assert!(ranked[1] > ranked[0], "{:?}", ranked);
+    }
+}
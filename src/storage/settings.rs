@@ -0,0 +1,218 @@
+use std::fmt;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+
+use crate::error::RustyDiaryError;
+
+/// SQLite journal modes we allow users to select
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    Wal,
+}
+
+/// SQLite `synchronous` levels, from fastest to most durable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+/// How a batch of entries is written. Every mode stores the whole batch in
+/// a single transaction; `benches/batch_insert.rs` compares them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BatchMode {
+    /// `INSERT OR REPLACE` each entry, then its metadata, author and links
+    #[default]
+    Replace,
+    /// `INSERT ... ON CONFLICT DO UPDATE`: a version stored again is updated
+    /// in place rather than deleted and re-inserted with its metadata
+    Upsert,
+    /// Every entry row first, then metadata, authors and links in a second
+    /// pass over the batch
+    DeferredMetadata,
+}
+
+/// Connection tuning applied through PRAGMAs when the database is opened.
+/// Use one of the named profiles and adjust individual fields as needed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SqliteSettings {
+    pub journal_mode: JournalMode,
+    pub synchronous: Synchronous,
+    /// Page cache size; negative values are KiB, positive values are pages
+    pub cache_size: i64,
+    /// Bytes of the database file to memory-map (0 disables mmap)
+    pub mmap_size: i64,
+}
+
+impl SqliteSettings {
+    /// WAL with relaxed syncing: good durability for local disks
+    pub fn default_profile() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Normal,
+            cache_size: -2000,
+            mmap_size: 0,
+        }
+    }
+
+    /// Rollback journal with full syncing, for network filesystems
+    /// and anything else where WAL's shared memory is unreliable
+    pub fn safe() -> Self {
+        Self {
+            journal_mode: JournalMode::Delete,
+            synchronous: Synchronous::Full,
+            cache_size: -2000,
+            mmap_size: 0,
+        }
+    }
+
+    /// Large cache, memory-mapped I/O and no fsync. The database survives
+    /// the application crashing, but an OS crash or power loss can
+    /// corrupt it
+    pub fn fast() -> Self {
+        Self {
+            journal_mode: JournalMode::Wal,
+            synchronous: Synchronous::Off,
+            cache_size: -64_000,
+            mmap_size: 256 * 1024 * 1024,
+        }
+    }
+
+    /// Names accepted by `profile`
+    pub const PROFILES: &'static [&'static str] = &["default", "safe", "fast"];
+
+    /// Looks up a named profile: "default", "safe" or "fast"
+    pub fn profile(name: &str) -> Option<Self> {
+        match name {
+            "default" => Some(Self::default_profile()),
+            "safe" => Some(Self::safe()),
+            "fast" => Some(Self::fast()),
+            _ => None,
+        }
+    }
+
+    /// PRAGMAs for the read-write connection
+    pub fn writer_pragmas(&self) -> String {
+        format!(
+            "PRAGMA foreign_keys = ON;
+             PRAGMA journal_mode = {};
+             PRAGMA synchronous = {};
+             {}",
+            self.journal_mode,
+            self.synchronous,
+            self.reader_pragmas()
+        )
+    }
+
+    /// Per-connection PRAGMAs that are safe to run on read-only connections
+    pub fn reader_pragmas(&self) -> String {
+        format!(
+            "PRAGMA cache_size = {};
+             PRAGMA mmap_size = {};",
+            self.cache_size, self.mmap_size
+        )
+    }
+}
+
+impl Default for SqliteSettings {
+    fn default() -> Self {
+        Self::default_profile()
+    }
+}
+
+impl FromStr for SqliteSettings {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Self::profile(name).ok_or_else(|| RustyDiaryError::InvalidConfig(format!(
+            "Unknown SQLite profile '{}' (expected default, safe or fast)", name
+        )))
+    }
+}
+
+/// Languages search can stem words in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StemLanguage {
+    English,
+    Portuguese,
+    Spanish,
+    French,
+    German,
+    Italian,
+}
+
+impl StemLanguage {
+    pub const NAMES: &'static [&'static str] = &["english", "portuguese", "spanish", "french", "german", "italian"];
+}
+
+impl FromStr for StemLanguage {
+    type Err = RustyDiaryError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "english" => Ok(StemLanguage::English),
+            "portuguese" => Ok(StemLanguage::Portuguese),
+            "spanish" => Ok(StemLanguage::Spanish),
+            "french" => Ok(StemLanguage::French),
+            "german" => Ok(StemLanguage::German),
+            "italian" => Ok(StemLanguage::Italian),
+            _ => Err(RustyDiaryError::InvalidConfig(format!(
+                "Unknown stemming language '{}' (expected one of {})",
+                name,
+                Self::NAMES.join(", ")
+            ))),
+        }
+    }
+}
+
+/// How search splits text into words and compares them. The default is a
+/// plain case-insensitive substring search; any option switches to matching
+/// whole words, each query word against the entry's words after the same
+/// folding and stemming.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchSettings {
+    /// Stem words in each of these languages, so `walked` finds `walking`;
+    /// a word matches when any language's stems agree
+    pub stemming: Vec<StemLanguage>,
+    /// Compare words without their accents, so `coracao` finds `coração`
+    pub fold_diacritics: bool,
+    /// Query words to ignore, lowercase
+    pub stopwords: Vec<String>,
+}
+
+impl SearchSettings {
+    /// Whether search matches whole words rather than substrings
+    pub fn is_tokenized(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+impl fmt::Display for JournalMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+        })
+    }
+}
+
+impl fmt::Display for Synchronous {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        })
+    }
+}
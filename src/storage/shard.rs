@@ -0,0 +1,162 @@
+use std::collections::BTreeSet;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use chrono::{Datelike, NaiveDate};
+use rusqlite::{Connection, params};
+
+use crate::error::{Result, RustyDiaryError};
+
+/// SQLite's compiled-in limit on attached databases is 10; keep a margin
+/// so a single query never trips it.
+pub const MAX_ATTACHED_SHARDS: usize = 8;
+
+/// Schema created inside a freshly attached shard. `{db}` is replaced
+/// with the schema name the shard is attached under.
+const SHARD_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS {db}.diary_entries (
+        exec_version INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        content TEXT NOT NULL,
+        created_at TEXT NOT NULL,
+        updated_at TEXT,
+        compressed INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (exec_version, date)
+    );
+
+    CREATE INDEX IF NOT EXISTS {db}.idx_diary_entries_date_version
+    ON diary_entries(date DESC, exec_version DESC);
+
+    CREATE TABLE IF NOT EXISTS {db}.entry_metadata (
+        entry_id INTEGER PRIMARY KEY,
+        exec_version INTEGER NOT NULL,
+        date TEXT NOT NULL,
+        word_count INTEGER NOT NULL
+    );
+
+    CREATE INDEX IF NOT EXISTS {db}.idx_entry_metadata_version_date
+    ON entry_metadata(exec_version, date, word_count);
+";
+
+/// Schema name a year shard is attached under
+pub fn alias(year: i32) -> String {
+    format!("shard_{}", year)
+}
+
+/// Location of the shard holding `year`, next to the main database and
+/// named after it: `diary.db` shards into `diary-2023.db`
+pub fn path(db_path: &Path, year: i32) -> PathBuf {
+    let stem = db_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "rusty-diary".to_string());
+
+    db_path.with_file_name(format!("{}-{}.db", stem, year))
+}
+
+/// SQL creating the shard tables under the given schema name
+pub fn schema(db: &str) -> String {
+    SHARD_SCHEMA.replace("{db}", db)
+}
+
+/// Joins one SELECT per source with UNION ALL. `{db}` in the template is
+/// replaced by each source's schema name.
+pub fn union_query(select: &str, sources: &[String]) -> String {
+    sources
+        .iter()
+        .map(|db| select.replace("{db}", db))
+        .collect::<Vec<_>>()
+        .join(" UNION ALL ")
+}
+
+/// A read-only connection together with the year shards attached to it.
+/// Shards are attached lazily, only when a query's range needs them.
+pub struct ReaderConnection {
+    conn: Connection,
+    attached: BTreeSet<i32>,
+}
+
+impl ReaderConnection {
+    pub fn new(conn: Connection) -> Self {
+        Self {
+            conn,
+            attached: BTreeSet::new(),
+        }
+    }
+
+    /// Years archived into shards that overlap the given range
+    pub fn shard_years(&self, start_date: NaiveDate, end_date: NaiveDate) -> Result<Vec<i32>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT year FROM shards WHERE year BETWEEN ?1 AND ?2 ORDER BY year"
+        )?;
+
+        let years = stmt.query_map(
+            params![start_date.year(), end_date.year()],
+            |row| row.get(0)
+        )?;
+
+        years.collect::<rusqlite::Result<Vec<_>>>()
+            .map_err(RustyDiaryError::from)
+    }
+
+    /// Makes sure every year in `years` is attached, detaching others
+    /// first if that would exceed `MAX_ATTACHED_SHARDS`
+    pub fn attach(&mut self, db_path: &Path, years: &[i32]) -> Result<()> {
+        let missing: Vec<i32> = years
+            .iter()
+            .copied()
+            .filter(|year| !self.attached.contains(year))
+            .collect();
+
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let overflow = (self.attached.len() + missing.len()).saturating_sub(MAX_ATTACHED_SHARDS);
+        if overflow > 0 {
+            // Cached statements may still reference the shards being dropped
+            self.conn.flush_prepared_statement_cache();
+            let evict: Vec<i32> = self.attached
+                .iter()
+                .copied()
+                .filter(|year| !years.contains(year))
+                .take(overflow)
+                .collect();
+
+            for year in evict {
+                self.conn.execute_batch(&format!("DETACH DATABASE {}", alias(year)))?;
+                self.attached.remove(&year);
+            }
+        }
+
+        for year in missing {
+            let path = path(db_path, year);
+            if !path.is_file() {
+                return Err(RustyDiaryError::ContentIntegrity(format!(
+                    "Shard for {} is missing: {}", year, path.display()
+                )));
+            }
+
+            self.conn.execute(
+                &format!("ATTACH DATABASE ?1 AS {}", alias(year)),
+                params![path.to_string_lossy()],
+            )?;
+            self.attached.insert(year);
+        }
+
+        Ok(())
+    }
+}
+
+impl Deref for ReaderConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        &self.conn
+    }
+}
+
+impl DerefMut for ReaderConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        &mut self.conn
+    }
+}
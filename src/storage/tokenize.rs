@@ -0,0 +1,145 @@
+//! Word-based matching for search, set up by `SearchSettings`: text is split
+//! into words, lowercased, stemmed in the configured languages and folded
+//! to plain letters, so Portuguese and English journals can be searched
+//! without guessing the exact spelling.
+
+use std::collections::HashSet;
+
+use rust_stemmers::{Algorithm, Stemmer};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+use super::models::MatchMode;
+use super::settings::{SearchSettings, StemLanguage};
+
+/// Splits and normalizes words the same way for entries and queries
+pub struct Tokenizer {
+    stemmers: Vec<Stemmer>,
+    fold_diacritics: bool,
+    stopwords: HashSet<String>,
+}
+
+impl Tokenizer {
+    pub fn new(settings: &SearchSettings) -> Self {
+        let stemmers = settings.stemming.iter().map(|language| Stemmer::create(algorithm(*language))).collect();
+        let mut tokenizer = Self { stemmers, fold_diacritics: settings.fold_diacritics, stopwords: HashSet::new() };
+        tokenizer.stopwords = settings.stopwords.iter().map(|word| tokenizer.fold(&word.to_lowercase())).collect();
+        tokenizer
+    }
+
+    /// The words of `text` with their byte offsets: runs of letters and digits
+    pub fn words<'a>(&self, text: &'a str) -> impl Iterator<Item = (usize, &'a str)> + 'a {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(move |word| (word.as_ptr() as usize - text.as_ptr() as usize, word))
+    }
+
+    /// Forms a word is compared by: its stem in each language, or the word
+    /// itself without stemming, lowercased and folded
+    pub fn keys(&self, word: &str) -> Vec<String> {
+        let word = word.to_lowercase();
+        if self.stemmers.is_empty() {
+            return vec![self.fold(&word)];
+        }
+        self.stemmers.iter().map(|stemmer| self.fold(&stemmer.stem(&word))).collect()
+    }
+
+    /// The keys of each query word that is not a stopword
+    pub fn terms(&self, query: &str) -> Vec<Vec<String>> {
+        self.words(query)
+            .filter(|(_, word)| !self.stopwords.contains(&self.fold(&word.to_lowercase())))
+            .map(|(_, word)| self.keys(word))
+            .collect()
+    }
+
+    /// Byte spans in `text` matching the terms as `mode` asks, or `None`
+    /// when it does not match: by default every term must match some word,
+    /// a phrase needs them in consecutive words, and any term will do
+    /// otherwise. A term matches a word when they stem alike in one of the
+    /// languages.
+    pub fn find(&self, text: &str, terms: &[Vec<String>], mode: MatchMode) -> Option<Vec<(usize, usize)>> {
+        let words: Vec<(usize, usize, Vec<String>)> =
+            self.words(text).map(|(offset, word)| (offset, offset + word.len(), self.keys(word))).collect();
+        if mode == MatchMode::Phrase {
+            let spans: Vec<(usize, usize)> = words
+                .windows(terms.len().max(1))
+                .filter(|window| !terms.is_empty() && window.iter().zip(terms).all(|(word, term)| matches(term, &word.2)))
+                .map(|window| (window[0].0, window[window.len() - 1].1))
+                .collect();
+            return (!spans.is_empty()).then_some(spans);
+        }
+
+        let mut found = vec![false; terms.len()];
+        let mut spans = Vec::new();
+        for (start, end, keys) in &words {
+            let mut matched = false;
+            for (term, found) in terms.iter().zip(found.iter_mut()) {
+                if matches(term, keys) {
+                    *found = true;
+                    matched = true;
+                }
+            }
+            if matched {
+                spans.push((*start, *end));
+            }
+        }
+        let complete = mode == MatchMode::AnyTerm || found.iter().all(|found| *found);
+        (!spans.is_empty() && complete).then_some(spans)
+    }
+
+    fn fold(&self, word: &str) -> String {
+        if !self.fold_diacritics {
+            return word.to_string();
+        }
+        word.nfd().filter(|c| !is_combining_mark(*c)).collect()
+    }
+}
+
+/// Whether a query term matches a word with these keys
+pub fn matches(term: &[String], keys: &[String]) -> bool {
+    term.iter().zip(keys).any(|(a, b)| a == b)
+}
+
+fn algorithm(language: StemLanguage) -> Algorithm {
+    match language {
+        StemLanguage::English => Algorithm::English,
+        StemLanguage::Portuguese => Algorithm::Portuguese,
+        StemLanguage::Spanish => Algorithm::Spanish,
+        StemLanguage::French => Algorithm::French,
+        StemLanguage::German => Algorithm::German,
+        StemLanguage::Italian => Algorithm::Italian,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenizer() {
+        let settings = SearchSettings {
+            stemming: vec![StemLanguage::Portuguese, StemLanguage::English],
+            fold_diacritics: true,
+            stopwords: vec!["De".to_string()],
+        };
+        let tokenizer = Tokenizer::new(&settings);
+
+        let text = "Caminhadas à beira-mar; walking helps the CORAÇÃO";
+        let words: Vec<&str> = tokenizer.words(text).map(|(_, word)| word).collect();
+        assert_eq!(words, vec!["Caminhadas", "à", "beira", "mar", "walking", "helps", "the", "CORAÇÃO"]);
+
+        let terms = tokenizer.terms("de coracao caminhada walked");
+        assert_eq!(terms.len(), 3);
+        let spans = tokenizer.find(text, &terms, MatchMode::Default).unwrap();
+        let matched: Vec<&str> = spans.iter().map(|(start, end)| &text[*start..*end]).collect();
+        assert_eq!(matched, vec!["Caminhadas", "walking", "CORAÇÃO"]);
+        assert!(tokenizer.find(text, &tokenizer.terms("coracao chuva"), MatchMode::Default).is_none());
+        assert_eq!(tokenizer.find(text, &tokenizer.terms("coracao chuva"), MatchMode::AnyTerm).unwrap().len(), 1);
+
+        let plain = Tokenizer::new(&SearchSettings { fold_diacritics: true, ..SearchSettings::default() });
+        assert!(plain.find(text, &plain.terms("coracao"), MatchMode::Default).is_some());
+        assert!(plain.find(text, &plain.terms("walk"), MatchMode::Default).is_none());
+        assert_eq!(plain.find(text, &plain.terms("a beira MAR"), MatchMode::Phrase), Some(vec![(11, 23)]));
+        assert!(plain.find(text, &plain.terms("mar beira"), MatchMode::Phrase).is_none());
+    }
+}